@@ -0,0 +1,12 @@
+fn main() {
+    // The gRPC service is generated from its published definition
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto/anime_search.proto");
+        let descriptors = protox::compile(["proto/anime_search.proto"], ["proto"])
+            .expect("proto/anime_search.proto is invalid");
+        tonic_build::configure()
+            .compile_fds(descriptors)
+            .expect("failed to generate the gRPC service");
+    }
+}
@@ -0,0 +1,184 @@
+//! Search engines that can sit behind the transcript database.
+//!
+//! Every backend indexes transcript lines and answers word queries with a
+//! ranked list of transcript ids; the lines themselves always come from SQLite
+//! (see [`DbHandler::find_hits_by_ids`](crate::db::DbHandler::find_hits_by_ids)).
+//!
+//! Three backends ship with the crate: the built-in word index ([`SqliteBackend`]),
+//! SQLite FTS5 ([`Fts5Backend`]) and tantivy (`TantivyBackend`, behind the
+//! `tantivy` feature). Other engines, such as a Meilisearch client, can be
+//! plugged in by implementing [`SearchBackend`].
+
+mod fts5;
+mod sqlite;
+#[cfg(feature = "tantivy")]
+mod tantivy_index;
+
+use crate::db::{DbHandler, EpisodeId, Transcript, TranscriptId};
+use crate::tokenizer::JapaneseTokenizer;
+use serde::Deserialize;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+
+pub use fts5::Fts5Backend;
+pub use sqlite::SqliteBackend;
+#[cfg(feature = "tantivy")]
+pub use tantivy_index::TantivyBackend;
+
+/// Which [`SearchBackend`] answers word searches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendKind {
+    /// The built-in word index; supports `word:POS` and `NEAR/N`, unranked
+    #[default]
+    Sqlite,
+    /// SQLite FTS5, ranked by bm25
+    Fts5,
+    /// A tantivy index, ranked by BM25 (requires the `tantivy` feature)
+    Tantivy,
+}
+
+impl FromStr for BackendKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "sqlite" => Ok(BackendKind::Sqlite),
+            "fts5" => Ok(BackendKind::Fts5),
+            "tantivy" => Ok(BackendKind::Tantivy),
+            _ => Err(format!(
+                "unknown search backend {:?} (expected sqlite, fts5 or tantivy)",
+                s
+            )),
+        }
+    }
+}
+
+/// The `[search]` config section.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BackendConfig {
+    pub backend: BackendKind,
+    /// Directory of the tantivy index
+    pub tantivy_index_dir: PathBuf,
+}
+
+impl Default for BackendConfig {
+    fn default() -> Self {
+        BackendConfig {
+            backend: BackendKind::default(),
+            tantivy_index_dir: PathBuf::from("data/tantivy"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum BackendError {
+    DbError(rusqlite::Error),
+    #[cfg(feature = "tantivy")]
+    TantivyError(tantivy::TantivyError),
+    /// The backend was requested but the crate was built without its feature
+    NotCompiled(BackendKind),
+    /// Errors from backends outside this crate
+    Other(Box<dyn std::error::Error + Send + Sync>),
+}
+
+impl From<rusqlite::Error> for BackendError {
+    fn from(error: rusqlite::Error) -> Self {
+        BackendError::DbError(error)
+    }
+}
+
+#[cfg(feature = "tantivy")]
+impl From<tantivy::TantivyError> for BackendError {
+    fn from(error: tantivy::TantivyError) -> Self {
+        BackendError::TantivyError(error)
+    }
+}
+
+impl fmt::Display for BackendError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BackendError::DbError(e) => write!(f, "Database error: {}", e),
+            #[cfg(feature = "tantivy")]
+            BackendError::TantivyError(e) => write!(f, "Tantivy error: {}", e),
+            BackendError::NotCompiled(kind) => write!(
+                f,
+                "The {:?} search backend isn't available in this build",
+                kind
+            ),
+            BackendError::Other(e) => write!(f, "Search backend error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for BackendError {}
+
+pub type Result<T> = std::result::Result<T, BackendError>;
+
+/// A search engine over transcript lines.
+pub trait SearchBackend {
+    /// Adds a line to the index. It may only become searchable after [`commit`](Self::commit).
+    fn index_line(&mut self, line: &Transcript) -> Result<()>;
+
+    /// Makes every line indexed so far searchable.
+    fn commit(&mut self) -> Result<()>;
+
+    /// Removes every line of an episode from the index, e.g. before it is re-ingested.
+    /// Depending on the backend this also only takes effect on [`commit`](Self::commit).
+    fn delete_episode(&mut self, episode_id: EpisodeId) -> Result<()>;
+
+    /// Returns the ids of the best `limit` lines matching `query`, best first.
+    fn search(&self, query: &str, limit: usize) -> Result<Vec<TranscriptId>>;
+}
+
+/// Opens the backend selected by `config` over `db`.
+pub fn open_backend<'a>(
+    config: &BackendConfig,
+    db: &'a mut DbHandler,
+    tokenizer: &'a Arc<JapaneseTokenizer>,
+) -> Result<Box<dyn SearchBackend + 'a>> {
+    match config.backend {
+        BackendKind::Sqlite => Ok(Box::new(SqliteBackend::new(db, tokenizer))),
+        BackendKind::Fts5 => Ok(Box::new(Fts5Backend::new(db, tokenizer)?)),
+        #[cfg(feature = "tantivy")]
+        BackendKind::Tantivy => Ok(Box::new(TantivyBackend::open(
+            &config.tantivy_index_dir,
+            Arc::clone(tokenizer),
+        )?)),
+        #[cfg(not(feature = "tantivy"))]
+        BackendKind::Tantivy => Err(BackendError::NotCompiled(BackendKind::Tantivy)),
+    }
+}
+
+/// Adds newly inserted lines to the backend selected by `config`, unless it's
+/// the built-in word index, which [`DbHandler::index_transcripts`] keeps.
+/// With `replace`, the backend first drops what it had indexed for `episode_ids`.
+pub fn update_index(
+    config: &BackendConfig,
+    db: &mut DbHandler,
+    tokenizer: &Arc<JapaneseTokenizer>,
+    transcript_ids: &[TranscriptId],
+    episode_ids: &[EpisodeId],
+    replace: bool,
+) -> Result<()> {
+    if config.backend == BackendKind::Sqlite {
+        return Ok(());
+    }
+    let lines = transcript_ids
+        .iter()
+        .filter_map(|&id| db.get_transcript(id).transpose())
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    let mut backend = open_backend(config, db, tokenizer)?;
+    if replace {
+        for &episode_id in episode_ids {
+            backend.delete_episode(episode_id)?;
+        }
+    }
+    for line in &lines {
+        backend.index_line(line)?;
+    }
+    backend.commit()
+}
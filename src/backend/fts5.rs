@@ -0,0 +1,85 @@
+use super::{Result, SearchBackend};
+use crate::db::{DbHandler, EpisodeId, Transcript, TranscriptId};
+use crate::search::parse_query;
+use crate::tokenizer::JapaneseTokenizer;
+
+/// SQLite's built-in FTS5 full-text index, ranked by bm25.
+///
+/// Like the tantivy backend, lines are stored as the tokenizer's dictionary forms,
+/// and `word:POS` filters and `NEAR/N` operators are reduced to plain words.
+/// Lines are buffered by [`index_line`](SearchBackend::index_line) and written on
+/// [`commit`](SearchBackend::commit).
+pub struct Fts5Backend<'a> {
+    db: &'a mut DbHandler,
+    tokenizer: &'a JapaneseTokenizer,
+    pending: Vec<(TranscriptId, EpisodeId, String)>,
+}
+
+impl<'a> Fts5Backend<'a> {
+    /// Wraps `db`, creating the `transcripts_fts` table if needed.
+    pub fn new(db: &'a mut DbHandler, tokenizer: &'a JapaneseTokenizer) -> Result<Self> {
+        db.create_fts_table()?;
+        Ok(Fts5Backend {
+            db,
+            tokenizer,
+            pending: Vec::new(),
+        })
+    }
+}
+
+impl SearchBackend for Fts5Backend<'_> {
+    fn index_line(&mut self, line: &Transcript) -> Result<()> {
+        let words = self.tokenizer.index_terms(&line.text).join(" ");
+        self.pending.push((line.id, line.episode_id, words));
+        Ok(())
+    }
+
+    fn commit(&mut self) -> Result<()> {
+        self.db.fts_insert_lines(&self.pending)?;
+        self.pending.clear();
+        Ok(())
+    }
+
+    fn delete_episode(&mut self, episode_id: EpisodeId) -> Result<()> {
+        self.pending.retain(|(_, id, _)| *id != episode_id);
+        Ok(self.db.fts_delete_episode(episode_id)?)
+    }
+
+    fn search(&self, query: &str, limit: usize) -> Result<Vec<TranscriptId>> {
+        let words: Vec<String> = parse_query(self.tokenizer, query)
+            .terms
+            .into_iter()
+            .map(|term| term.word)
+            .collect();
+        Ok(self.db.fts_search(&words, limit)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_utils::test_db_with_lines;
+    use crate::tokenizer::test_utils::test_tokenizer;
+
+    #[test]
+    fn test_fts5_backend() {
+        let (mut db, ids) = test_db_with_lines(&["犬が好きです", "猫が猫を見る", "猫が好きです"]);
+        let tokenizer = test_tokenizer();
+        let lines: Vec<Transcript> = ids
+            .iter()
+            .map(|&id| db.get_transcript(id).unwrap().unwrap())
+            .collect();
+
+        let mut backend = Fts5Backend::new(&mut db, &tokenizer).unwrap();
+        for line in &lines {
+            backend.index_line(line).unwrap();
+        }
+        backend.commit().unwrap();
+        assert_eq!(backend.search("猫", 10).unwrap(), [ids[1], ids[2]]);
+        assert_eq!(backend.search("猫が好き", 10).unwrap(), [ids[2]]);
+        assert_eq!(backend.search("\"猫", 10).unwrap(), [ids[1], ids[2]]);
+
+        backend.delete_episode(lines[0].episode_id).unwrap();
+        assert!(backend.search("猫", 10).unwrap().is_empty());
+    }
+}
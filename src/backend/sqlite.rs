@@ -0,0 +1,85 @@
+use super::{Result, SearchBackend};
+use crate::db::{DbHandler, EpisodeId, Transcript, TranscriptId};
+use crate::search::parse_query;
+use crate::tokenizer::JapaneseTokenizer;
+
+/// The word index stored in the SQLite database itself (`words` and `word_occurrences`).
+///
+/// Lines are buffered by [`index_line`](SearchBackend::index_line) and written in
+/// one transaction on [`commit`](SearchBackend::commit). Results are not ranked;
+/// they come back in show and episode order.
+pub struct SqliteBackend<'a> {
+    db: &'a mut DbHandler,
+    tokenizer: &'a JapaneseTokenizer,
+    pending: Vec<TranscriptId>,
+}
+
+impl<'a> SqliteBackend<'a> {
+    pub fn new(db: &'a mut DbHandler, tokenizer: &'a JapaneseTokenizer) -> Self {
+        SqliteBackend {
+            db,
+            tokenizer,
+            pending: Vec::new(),
+        }
+    }
+}
+
+impl SearchBackend for SqliteBackend<'_> {
+    fn index_line(&mut self, line: &Transcript) -> Result<()> {
+        self.pending.push(line.id);
+        Ok(())
+    }
+
+    fn commit(&mut self) -> Result<()> {
+        self.db.index_transcripts(self.tokenizer, &self.pending)?;
+        self.pending.clear();
+        Ok(())
+    }
+
+    fn delete_episode(&mut self, episode_id: EpisodeId) -> Result<()> {
+        Ok(self.db.delete_episode_index(episode_id)?)
+    }
+
+    fn search(&self, query: &str, limit: usize) -> Result<Vec<TranscriptId>> {
+        let query = parse_query(self.tokenizer, query);
+        let hits = self.db.find_lines(&query)?;
+        Ok(hits
+            .into_iter()
+            .take(limit)
+            .map(|hit| hit.transcript_id)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_utils::test_db_with_lines;
+    use crate::tokenizer::test_utils::test_tokenizer;
+
+    #[test]
+    fn test_sqlite_backend() {
+        let (mut db, ids) = test_db_with_lines(&["猫が好き", "犬が好き", "猫が走った"]);
+        let tokenizer = test_tokenizer();
+        let lines: Vec<Transcript> = ids
+            .iter()
+            .map(|&id| db.get_transcript(id).unwrap().unwrap())
+            .collect();
+
+        let mut backend = SqliteBackend::new(&mut db, &tokenizer);
+        for line in &lines {
+            backend.index_line(line).unwrap();
+        }
+        backend.commit().unwrap();
+        assert_eq!(backend.search("猫", 10).unwrap(), [ids[0], ids[2]]);
+        assert_eq!(backend.search("猫", 1).unwrap(), [ids[0]]);
+
+        let hits = db.find_hits_by_ids(&[ids[2], ids[0]]).unwrap();
+        assert_eq!(hits[0].text, "猫が走った");
+        assert_eq!(hits[1].text, "猫が好き");
+
+        let mut backend = SqliteBackend::new(&mut db, &tokenizer);
+        backend.delete_episode(lines[0].episode_id).unwrap();
+        assert!(backend.search("猫", 10).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,200 @@
+use super::{Result, SearchBackend};
+use crate::db::{EpisodeId, Transcript, TranscriptId};
+use crate::search::parse_query;
+use crate::tokenizer::JapaneseTokenizer;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use tantivy::collector::TopDocs;
+use tantivy::query::{BooleanQuery, Occur, Query, TermQuery};
+use tantivy::schema::{
+    Field, IndexRecordOption, Schema, TextFieldIndexing, TextOptions, Value, INDEXED, STORED,
+};
+use tantivy::{Index, IndexReader, IndexWriter, ReloadPolicy, TantivyDocument, Term};
+
+// Memory the index writer may use before flushing a segment to disk
+const WRITER_MEMORY_BUDGET: usize = 50_000_000;
+
+/// A [tantivy](https://docs.rs/tantivy) index over transcript lines, ranked by BM25.
+///
+/// Lines are tokenized with the crate's own [`JapaneseTokenizer`] and stored as
+/// whitespace-separated dictionary forms, so the index matches exactly what the
+/// SQLite word index would. `word:POS` filters and `NEAR/N` operators are not
+/// supported; their words are simply required to appear in the line.
+///
+/// Requires the `tantivy` feature.
+pub struct TantivyBackend {
+    index: Index,
+    reader: IndexReader,
+    // Opened on first write, since it takes a lock on the index directory
+    writer: Option<IndexWriter>,
+    tokenizer: Arc<JapaneseTokenizer>,
+    transcript_id: Field,
+    episode_id: Field,
+    words: Field,
+}
+
+impl TantivyBackend {
+    /// Opens the index in `dir`, creating it if the directory is empty or missing.
+    pub fn open<P: AsRef<Path>>(dir: P, tokenizer: Arc<JapaneseTokenizer>) -> Result<Self> {
+        let dir = dir.as_ref();
+        let index = if dir.join("meta.json").exists() {
+            Index::open_in_dir(dir)?
+        } else {
+            fs::create_dir_all(dir).map_err(tantivy::TantivyError::from)?;
+            Index::create_in_dir(dir, schema())?
+        };
+        Self::from_index(index, tokenizer)
+    }
+
+    /// Creates an index held in memory, mostly useful for tests.
+    pub fn in_memory(tokenizer: Arc<JapaneseTokenizer>) -> Result<Self> {
+        Self::from_index(Index::create_in_ram(schema()), tokenizer)
+    }
+
+    fn from_index(index: Index, tokenizer: Arc<JapaneseTokenizer>) -> Result<Self> {
+        let schema = index.schema();
+        let transcript_id = schema.get_field("transcript_id")?;
+        let episode_id = schema.get_field("episode_id")?;
+        let words = schema.get_field("words")?;
+        let reader = index
+            .reader_builder()
+            .reload_policy(ReloadPolicy::Manual)
+            .try_into()?;
+        Ok(TantivyBackend {
+            index,
+            reader,
+            writer: None,
+            tokenizer,
+            transcript_id,
+            episode_id,
+            words,
+        })
+    }
+
+    fn writer(&mut self) -> Result<&mut IndexWriter> {
+        if self.writer.is_none() {
+            self.writer = Some(self.index.writer(WRITER_MEMORY_BUDGET)?);
+        }
+        Ok(self.writer.as_mut().unwrap())
+    }
+}
+
+fn schema() -> Schema {
+    let mut builder = Schema::builder();
+    builder.add_i64_field("transcript_id", INDEXED | STORED);
+    builder.add_i64_field("episode_id", INDEXED);
+    // Terms are already dictionary forms; only split them apart again
+    let words = TextOptions::default().set_indexing_options(
+        TextFieldIndexing::default()
+            .set_tokenizer("whitespace")
+            .set_index_option(IndexRecordOption::WithFreqsAndPositions),
+    );
+    builder.add_text_field("words", words);
+    builder.build()
+}
+
+impl SearchBackend for TantivyBackend {
+    fn index_line(&mut self, line: &Transcript) -> Result<()> {
+        let mut document = TantivyDocument::default();
+        document.add_i64(self.transcript_id, line.id.0);
+        document.add_i64(self.episode_id, line.episode_id.0);
+        document.add_text(self.words, self.tokenizer.index_terms(&line.text).join(" "));
+
+        // Re-indexing a line replaces it
+        let transcript_id = Term::from_field_i64(self.transcript_id, line.id.0);
+        let writer = self.writer()?;
+        writer.delete_term(transcript_id);
+        writer.add_document(document)?;
+        Ok(())
+    }
+
+    fn commit(&mut self) -> Result<()> {
+        if let Some(writer) = &mut self.writer {
+            writer.commit()?;
+            self.reader.reload()?;
+        }
+        Ok(())
+    }
+
+    fn delete_episode(&mut self, episode_id: EpisodeId) -> Result<()> {
+        let episode_id = Term::from_field_i64(self.episode_id, episode_id.0);
+        self.writer()?.delete_term(episode_id);
+        Ok(())
+    }
+
+    fn search(&self, query: &str, limit: usize) -> Result<Vec<TranscriptId>> {
+        let terms = parse_query(&self.tokenizer, query).terms;
+        if terms.is_empty() || limit == 0 {
+            return Ok(Vec::new());
+        }
+        let clauses: Vec<(Occur, Box<dyn Query>)> = terms
+            .iter()
+            .map(|term| {
+                let term = Term::from_field_text(self.words, &term.word);
+                let query = TermQuery::new(term, IndexRecordOption::WithFreqs);
+                (Occur::Must, Box::new(query) as Box<dyn Query>)
+            })
+            .collect();
+        let query = BooleanQuery::new(clauses);
+
+        let searcher = self.reader.searcher();
+        let top_docs = searcher.search(&query, &TopDocs::with_limit(limit).order_by_score())?;
+        let mut ids = Vec::with_capacity(top_docs.len());
+        for (_score, address) in top_docs {
+            let document: TantivyDocument = searcher.doc(address)?;
+            if let Some(id) = document
+                .get_first(self.transcript_id)
+                .and_then(|value| value.as_i64())
+            {
+                ids.push(TranscriptId(id));
+            }
+        }
+        Ok(ids)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenizer::test_utils::test_tokenizer;
+
+    fn line(id: i64, text: &str) -> Transcript {
+        Transcript {
+            id: TranscriptId(id),
+            episode_id: EpisodeId(1),
+            line_id: id as i32,
+            time_start: "00:00:00,000".to_string(),
+            time_end: "00:00:01,000".to_string(),
+            text: text.to_string(),
+            text_raw: text.to_string(),
+            speaker: None,
+            stable_id: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_tantivy_backend_ranks_hits() {
+        let mut backend = TantivyBackend::in_memory(Arc::new(test_tokenizer())).unwrap();
+        backend.index_line(&line(1, "犬が好きです")).unwrap();
+        backend.index_line(&line(2, "猫が猫を見る")).unwrap();
+        backend.index_line(&line(3, "猫が好きです")).unwrap();
+        assert!(backend.search("猫", 10).unwrap().is_empty());
+
+        backend.commit().unwrap();
+        assert_eq!(
+            backend.search("猫", 10).unwrap(),
+            [TranscriptId(2), TranscriptId(3)]
+        );
+        assert_eq!(backend.search("猫が好き", 10).unwrap(), [TranscriptId(3)]);
+
+        // Re-indexing replaces the old document
+        backend.index_line(&line(3, "犬を見る")).unwrap();
+        backend.commit().unwrap();
+        assert_eq!(backend.search("猫", 10).unwrap(), [TranscriptId(2)]);
+
+        backend.delete_episode(EpisodeId(1)).unwrap();
+        backend.commit().unwrap();
+        assert!(backend.search("猫", 10).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,168 @@
+//! A benchmark of representative queries against the user's own corpus, so
+//! index and backend changes can be judged on real data.
+//!
+//! Each query is run a number of times through the configured backend and its
+//! latency reported as percentiles. With the built-in word index, the query
+//! is also profiled: SQLite's query plan and how many rows it had to scan.
+
+use crate::backend::{open_backend, BackendConfig, BackendKind, Result};
+use crate::db::{DbHandler, QueryProfile};
+use crate::search::parse_query;
+use crate::tokenizer::JapaneseTokenizer;
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// The bundled suite: what each query exercises, and the query.
+pub const BENCH_QUERIES: &[(&str, &str)] = &[
+    ("very common word", "する"),
+    ("common word", "今日"),
+    ("rare word", "憂鬱"),
+    ("kana word, all spellings", "おもしろい"),
+    ("two words", "今日 行く"),
+    ("three words", "俺 お前 言う"),
+    ("part of speech", "見る:動詞"),
+    ("proximity", "気 NEAR/2 する"),
+];
+
+/// How one query performed.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BenchResult {
+    pub label: String,
+    pub query: String,
+    pub hits: usize,
+    pub p50_ms: f64,
+    pub p90_ms: f64,
+    pub p99_ms: f64,
+    pub max_ms: f64,
+    /// How SQLite ran it, for the built-in word index only
+    pub profile: Option<QueryProfile>,
+}
+
+/// Runs each of `queries` (label and query) `runs` times through the backend
+/// `config` selects, at most `limit` hits each.
+pub fn run_bench(
+    config: &BackendConfig,
+    db: &mut DbHandler,
+    tokenizer: &Arc<JapaneseTokenizer>,
+    queries: &[(String, String)],
+    runs: usize,
+    limit: usize,
+) -> Result<Vec<BenchResult>> {
+    let runs = runs.max(1);
+    let mut profiles = Vec::new();
+    for (_, query) in queries {
+        let sql = if config.backend == BackendKind::Sqlite {
+            db.word_query_sql(&parse_query(tokenizer, query))?
+        } else {
+            None
+        };
+        profiles.push(match sql {
+            Some(sql) => Some(db.profile_query(&sql.sql, &sql.params)?),
+            None => None,
+        });
+    }
+
+    let mut timings = Vec::new();
+    if config.backend == BackendKind::Sqlite {
+        for (_, query) in queries {
+            timings.push(time_runs(runs, || {
+                Ok(db.find_lines(&parse_query(tokenizer, query))?.len())
+            })?);
+        }
+    } else {
+        let backend = open_backend(config, db, tokenizer)?;
+        for (_, query) in queries {
+            timings.push(time_runs(runs, || Ok(backend.search(query, limit)?.len()))?);
+        }
+    }
+
+    Ok(queries
+        .iter()
+        .zip(timings)
+        .zip(profiles)
+        .map(|(((label, query), (hits, mut latencies)), profile)| {
+            latencies.sort();
+            let ms = |p: f64| percentile(&latencies, p).as_secs_f64() * 1000.0;
+            BenchResult {
+                label: label.clone(),
+                query: query.clone(),
+                hits,
+                p50_ms: ms(50.0),
+                p90_ms: ms(90.0),
+                p99_ms: ms(99.0),
+                max_ms: ms(100.0),
+                profile,
+            }
+        })
+        .collect())
+}
+
+// The hits of the last run and the latency of every run
+fn time_runs(
+    runs: usize,
+    mut search: impl FnMut() -> Result<usize>,
+) -> Result<(usize, Vec<Duration>)> {
+    let mut hits = 0;
+    let mut latencies = Vec::new();
+    for _ in 0..runs {
+        let start = Instant::now();
+        hits = search()?;
+        latencies.push(start.elapsed());
+    }
+    Ok((hits, latencies))
+}
+
+/// The nearest-rank `p`th percentile (0–100) of sorted latencies; zero if there are none.
+pub fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_utils::test_db_with_lines;
+    use crate::tokenizer::test_utils::test_tokenizer;
+
+    #[test]
+    fn test_percentile() {
+        let latencies: Vec<Duration> = (1..=10).map(Duration::from_millis).collect();
+        assert_eq!(percentile(&latencies, 50.0), Duration::from_millis(5));
+        assert_eq!(percentile(&latencies, 90.0), Duration::from_millis(9));
+        assert_eq!(percentile(&latencies, 99.0), Duration::from_millis(10));
+        assert_eq!(percentile(&latencies, 0.0), Duration::from_millis(1));
+        assert_eq!(percentile(&[], 50.0), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_run_bench() {
+        let (mut db, ids) = test_db_with_lines(&["猫が走った", "犬が好き", "走る"]);
+        let tokenizer = Arc::new(test_tokenizer());
+        db.index_transcripts(&tokenizer, &ids).unwrap();
+        let queries = [
+            ("inflected verb".to_string(), "走る".to_string()),
+            ("two words".to_string(), "猫 走る".to_string()),
+        ];
+
+        let results = run_bench(
+            &BackendConfig::default(),
+            &mut db,
+            &tokenizer,
+            &queries,
+            3,
+            100,
+        )
+        .unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].hits, 2);
+        assert_eq!(results[1].hits, 1);
+        assert!(results[0].p50_ms <= results[0].max_ms);
+        let profile = results[0].profile.as_ref().unwrap();
+        assert_eq!(profile.rows, 2);
+        assert!(!profile.plan.is_empty());
+    }
+}
@@ -0,0 +1,4 @@
+//! Chat bots answering searches over the library API.
+
+#[cfg(feature = "discord")]
+pub mod discord;
@@ -0,0 +1,478 @@
+//! A Discord bot answering `!jsearch <query>` with matching lines and their context.
+//!
+//! The bot connects to the Discord gateway, listens for messages in channels
+//! it can read (the MESSAGE_CONTENT intent must be enabled for the bot in the
+//! developer portal) and replies through the REST API. Requires the `discord`
+//! feature.
+
+use crate::db::{episode_label, DbPool, SearchHit};
+use crate::nonblocking;
+use crate::search::SearchError;
+use crate::tokenizer::JapaneseTokenizer;
+use futures_util::{SinkExt, StreamExt};
+use rand::Rng;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::Message;
+
+const GATEWAY_URL: &str = "wss://gateway.discord.gg/?v=10&encoding=json";
+const API_URL: &str = "https://discord.com/api/v10";
+
+// GUILD_MESSAGES | DIRECT_MESSAGES | MESSAGE_CONTENT
+const INTENTS: u64 = (1 << 9) | (1 << 12) | (1 << 15);
+
+// Discord rejects messages longer than this
+const MAX_MESSAGE_CHARS: usize = 2000;
+
+// How long to wait before reconnecting, doubled for each reconnect in a row up to the maximum
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(60);
+
+// A session that stays up this long resets the backoff
+const STABLE_SESSION: Duration = Duration::from_secs(60);
+
+// Gateway opcodes
+const OP_DISPATCH: u64 = 0;
+const OP_HEARTBEAT: u64 = 1;
+const OP_IDENTIFY: u64 = 2;
+const OP_RECONNECT: u64 = 7;
+const OP_INVALID_SESSION: u64 = 9;
+const OP_HELLO: u64 = 10;
+
+/// How the bot is reached and how much it replies with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BotOptions {
+    /// The bot token from the Discord developer portal
+    pub token: String,
+    /// Messages starting with this are searches
+    pub prefix: String,
+    /// Hits shown per reply
+    pub max_hits: usize,
+    /// Lines of context shown before each hit
+    pub before: u32,
+    /// Lines of context shown after each hit
+    pub after: u32,
+}
+
+impl BotOptions {
+    pub fn new(token: String) -> Self {
+        BotOptions {
+            token,
+            prefix: "!jsearch".to_string(),
+            max_hits: 3,
+            before: 1,
+            after: 1,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum BotError {
+    Gateway(tokio_tungstenite::tungstenite::Error),
+    Http(Box<ureq::Error>),
+    Json(serde_json::Error),
+    Search(SearchError),
+    /// The gateway closed the connection for a reason reconnecting won't fix
+    Rejected(String),
+    /// The gateway sent something other than the expected payload
+    Protocol(String),
+}
+
+impl From<tokio_tungstenite::tungstenite::Error> for BotError {
+    fn from(error: tokio_tungstenite::tungstenite::Error) -> Self {
+        BotError::Gateway(error)
+    }
+}
+
+impl From<ureq::Error> for BotError {
+    fn from(error: ureq::Error) -> Self {
+        BotError::Http(Box::new(error))
+    }
+}
+
+impl From<serde_json::Error> for BotError {
+    fn from(error: serde_json::Error) -> Self {
+        BotError::Json(error)
+    }
+}
+
+impl From<SearchError> for BotError {
+    fn from(error: SearchError) -> Self {
+        BotError::Search(error)
+    }
+}
+
+impl fmt::Display for BotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BotError::Gateway(e) => write!(f, "Gateway error: {}", e),
+            BotError::Http(e) => write!(f, "HTTP error: {}", e),
+            BotError::Json(e) => write!(f, "Invalid JSON: {}", e),
+            BotError::Search(e) => write!(f, "Search error: {}", e),
+            BotError::Rejected(reason) => write!(f, "Gateway rejected the bot: {}", reason),
+            BotError::Protocol(message) => write!(f, "Unexpected gateway payload: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for BotError {}
+
+/// Runs the bot until the gateway rejects it, e.g. for an invalid token.
+///
+/// Dropped connections are reopened after a delay that grows while they keep
+/// dropping.
+pub async fn run(
+    pool: Arc<DbPool>,
+    tokenizer: Arc<JapaneseTokenizer>,
+    options: BotOptions,
+) -> Result<(), BotError> {
+    let options = Arc::new(options);
+    let mut attempt = 0;
+    loop {
+        let started = Instant::now();
+        let end = session(&pool, &tokenizer, &options).await;
+        if started.elapsed() >= STABLE_SESSION {
+            attempt = 0;
+        }
+        let mut delay = reconnect_delay(attempt);
+        match end {
+            Ok(SessionEnd::Reconnect) => {}
+            Ok(SessionEnd::InvalidSession) => {
+                // Discord asks for a random wait of 1 to 5 seconds before identifying again
+                let wait = Duration::from_millis(rand::thread_rng().gen_range(1000..=5000));
+                delay = delay.max(wait);
+            }
+            Ok(SessionEnd::Closed) => eprintln!("Discord closed the connection"),
+            Err(e @ BotError::Rejected(_)) => return Err(e),
+            Err(e) => eprintln!("Discord connection lost: {}", e),
+        }
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+    }
+}
+
+// How long to wait before reconnect number `attempt` of a row, counting from 0
+fn reconnect_delay(attempt: u32) -> Duration {
+    RECONNECT_DELAY
+        .saturating_mul(1 << attempt.min(16))
+        .min(MAX_RECONNECT_DELAY)
+}
+
+// Why a gateway session ended, when it wasn't an error
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SessionEnd {
+    /// The gateway asked the bot to reconnect
+    Reconnect,
+    /// The gateway dropped the session, e.g. after too many identifies
+    InvalidSession,
+    /// The gateway closed the connection, or it ended
+    Closed,
+}
+
+#[derive(Deserialize)]
+struct Payload {
+    op: u64,
+    #[serde(default)]
+    d: Value,
+    s: Option<u64>,
+    t: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct MessageCreate {
+    id: String,
+    channel_id: String,
+    content: String,
+    author: Author,
+}
+
+#[derive(Deserialize)]
+struct Author {
+    #[serde(default)]
+    bot: bool,
+}
+
+// One gateway connection, from identifying until the gateway asks to reconnect
+async fn session(
+    pool: &Arc<DbPool>,
+    tokenizer: &Arc<JapaneseTokenizer>,
+    options: &Arc<BotOptions>,
+) -> Result<SessionEnd, BotError> {
+    let (socket, _) = tokio_tungstenite::connect_async(GATEWAY_URL).await?;
+    let (mut sink, mut stream) = socket.split();
+
+    let hello = match stream.next().await {
+        Some(Ok(Message::Text(text))) => serde_json::from_str::<Payload>(&text)?,
+        _ => return Err(BotError::Protocol("expected Hello".to_string())),
+    };
+    let interval = hello.d["heartbeat_interval"]
+        .as_u64()
+        .filter(|_| hello.op == OP_HELLO)
+        .ok_or_else(|| BotError::Protocol("expected Hello".to_string()))?;
+
+    let identify = json!({
+        "op": OP_IDENTIFY,
+        "d": {
+            "token": options.token,
+            "intents": INTENTS,
+            "properties": {
+                "os": std::env::consts::OS,
+                "browser": env!("CARGO_PKG_NAME"),
+                "device": env!("CARGO_PKG_NAME"),
+            },
+        },
+    });
+    sink.send(Message::Text(identify.to_string())).await?;
+
+    let mut heartbeat = tokio::time::interval(Duration::from_millis(interval));
+    let mut sequence = None;
+    loop {
+        tokio::select! {
+            _ = heartbeat.tick() => {
+                let beat = json!({ "op": OP_HEARTBEAT, "d": sequence });
+                sink.send(Message::Text(beat.to_string())).await?;
+            }
+            message = stream.next() => {
+                let payload = match message.transpose()? {
+                    Some(Message::Text(text)) => serde_json::from_str::<Payload>(&text)?,
+                    Some(Message::Close(frame)) => {
+                        return match frame {
+                            Some(frame) if is_fatal(frame.code) => {
+                                Err(BotError::Rejected(frame.reason.into_owned()))
+                            }
+                            _ => Ok(SessionEnd::Closed),
+                        };
+                    }
+                    Some(_) => continue,
+                    None => return Ok(SessionEnd::Closed),
+                };
+                sequence = payload.s.or(sequence);
+                match payload.op {
+                    OP_DISPATCH if payload.t.as_deref() == Some("MESSAGE_CREATE") => {
+                        let message: MessageCreate = serde_json::from_value(payload.d)?;
+                        handle_message(pool, tokenizer, options, message);
+                    }
+                    OP_HEARTBEAT => {
+                        let beat = json!({ "op": OP_HEARTBEAT, "d": sequence });
+                        sink.send(Message::Text(beat.to_string())).await?;
+                    }
+                    OP_RECONNECT => return Ok(SessionEnd::Reconnect),
+                    OP_INVALID_SESSION => return Ok(SessionEnd::InvalidSession),
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+// Authentication failures and invalid intents; see Discord's gateway close codes
+fn is_fatal(code: CloseCode) -> bool {
+    matches!(u16::from(code), 4004 | 4010..=4014)
+}
+
+// Answers a search on its own task so slow searches don't delay heartbeats
+fn handle_message(
+    pool: &Arc<DbPool>,
+    tokenizer: &Arc<JapaneseTokenizer>,
+    options: &Arc<BotOptions>,
+    message: MessageCreate,
+) {
+    if message.author.bot {
+        return;
+    }
+    let Some(query) = parse_command(&message.content, &options.prefix) else {
+        return;
+    };
+    let query = query.to_string();
+    let (pool, tokenizer, options) = (pool.clone(), tokenizer.clone(), options.clone());
+    tokio::spawn(async move {
+        let reply = match answer(pool, tokenizer, &options, &query).await {
+            Ok(reply) => reply,
+            Err(e) => format!("Search failed: {}", e),
+        };
+        if let Err(e) = send_reply(&options.token, &message, reply).await {
+            eprintln!("Failed to reply on Discord: {}", e);
+        }
+    });
+}
+
+async fn answer(
+    pool: Arc<DbPool>,
+    tokenizer: Arc<JapaneseTokenizer>,
+    options: &BotOptions,
+    query: &str,
+) -> Result<String, BotError> {
+    let hits = nonblocking::search(pool.clone(), tokenizer, query.to_string()).await?;
+    let mut results = Vec::new();
+    for hit in hits.iter().take(options.max_hits) {
+        let context = nonblocking::context(
+            pool.clone(),
+            hit.transcript_id,
+            options.before,
+            options.after,
+        )
+        .await?;
+        results.push((hit.clone(), context));
+    }
+    Ok(format_reply(query, hits.len(), &results))
+}
+
+async fn send_reply(token: &str, message: &MessageCreate, content: String) -> Result<(), BotError> {
+    let url = format!("{}/channels/{}/messages", API_URL, message.channel_id);
+    let authorization = format!("Bot {}", token);
+    let body = json!({
+        "content": content,
+        "message_reference": { "message_id": message.id },
+        "allowed_mentions": { "parse": [] },
+    });
+    // ureq blocks, so the request runs on the blocking thread pool
+    let task = tokio::task::spawn_blocking(move || {
+        ureq::post(&url)
+            .set("Authorization", &authorization)
+            .send_json(body)
+            .map(|_| ())
+            .map_err(Box::new)
+    });
+    match task.await {
+        Ok(result) => result.map_err(BotError::Http),
+        Err(e) => std::panic::resume_unwind(e.into_panic()),
+    }
+}
+
+/// Returns the query of a `prefix query` message, or None for other messages.
+pub fn parse_command<'a>(content: &'a str, prefix: &str) -> Option<&'a str> {
+    let rest = content.trim_start().strip_prefix(prefix)?;
+    // `!jsearchfoo` is a different command
+    if !rest.is_empty() && !rest.starts_with(char::is_whitespace) {
+        return None;
+    }
+    Some(rest.trim()).filter(|query| !query.is_empty())
+}
+
+/// Formats hits and their context as a Discord message.
+///
+/// Each hit's line is bold, with its context quoted around it. Hits that
+/// don't fit in a message are left out and counted at the end.
+pub fn format_reply(query: &str, total: usize, results: &[(SearchHit, Vec<SearchHit>)]) -> String {
+    if results.is_empty() {
+        return format!("No lines found for {}.", escape(query));
+    }
+    let mut reply = format!("{} lines found for {}", total, escape(query));
+    let mut shown = 0;
+    for (hit, context) in results {
+        let mut section = format!(
+            "\n\n**{}** {} `{}`",
+            escape(&hit.show_name),
+            episode_label(hit.season, hit.episode_number),
+            hit.time_start
+        );
+        for line in context {
+            let text = escape(&line.text.replace('\n', " "));
+            if line.transcript_id == hit.transcript_id {
+                section.push_str(&format!("\n> **{}**", text));
+            } else {
+                section.push_str(&format!("\n> {}", text));
+            }
+        }
+        if let Some(translation) = &hit.translation {
+            section.push_str(&format!("\n*{}*", escape(&translation.replace('\n', " "))));
+        }
+        let footer = more_footer(total - shown - 1);
+        if (reply.chars().count() + section.chars().count() + footer.chars().count())
+            > MAX_MESSAGE_CHARS
+        {
+            break;
+        }
+        reply.push_str(&section);
+        shown += 1;
+    }
+    reply.push_str(&more_footer(total - shown));
+    reply
+}
+
+fn more_footer(remaining: usize) -> String {
+    if remaining == 0 {
+        String::new()
+    } else {
+        format!("\n\n…and {} more.", remaining)
+    }
+}
+
+// Escapes Discord markdown so subtitle text shows as written
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '*' | '_' | '~' | '`' | '|' | '>' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::TranscriptId;
+
+    fn hit(id: i64, text: &str) -> SearchHit {
+        SearchHit {
+            transcript_id: TranscriptId(id),
+            show_name: "Show".to_string(),
+            season: 1,
+            episode_number: Some(2),
+            line_id: id as i32,
+            time_start: "00:00:01,000".to_string(),
+            time_end: "00:00:02,000".to_string(),
+            text: text.to_string(),
+            translation: None,
+            stable_id: String::new(),
+            source_url: None,
+            deep_link: None,
+        }
+    }
+
+    #[test]
+    fn test_reconnect_delay() {
+        assert_eq!(reconnect_delay(0), Duration::from_secs(1));
+        assert_eq!(reconnect_delay(3), Duration::from_secs(8));
+        assert_eq!(reconnect_delay(10), MAX_RECONNECT_DELAY);
+        assert_eq!(reconnect_delay(u32::MAX), MAX_RECONNECT_DELAY);
+    }
+
+    #[test]
+    fn test_parse_command() {
+        assert_eq!(
+            parse_command("!jsearch 猫 好き", "!jsearch"),
+            Some("猫 好き")
+        );
+        assert_eq!(parse_command("  !jsearch\n猫", "!jsearch"), Some("猫"));
+        assert_eq!(parse_command("!jsearch", "!jsearch"), None);
+        assert_eq!(parse_command("!jsearchx 猫", "!jsearch"), None);
+        assert_eq!(parse_command("猫 !jsearch", "!jsearch"), None);
+    }
+
+    #[test]
+    fn test_format_reply() {
+        assert_eq!(format_reply("*猫*", 0, &[]), "No lines found for \\*猫\\*.");
+
+        let results = [(
+            hit(2, "猫だ"),
+            vec![hit(1, "あれは"), hit(2, "猫だ"), hit(3, "a_b")],
+        )];
+        assert_eq!(
+            format_reply("猫", 4, &results),
+            "4 lines found for 猫\n\n**Show** S01E02 `00:00:01,000`\n> あれは\n> **猫だ**\n> a\\_b\n\n…and 3 more."
+        );
+
+        let long = "猫".repeat(900);
+        let results = vec![(hit(1, &long), vec![hit(1, &long)]); 3];
+        let reply = format_reply("猫", 3, &results);
+        assert!(reply.chars().count() <= MAX_MESSAGE_CHARS);
+        assert!(reply.ends_with("…and 1 more."));
+    }
+}
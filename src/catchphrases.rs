@@ -0,0 +1,283 @@
+//! What each character of a show says more than the others: their distinctive
+//! token sequences and the lines they repeat, for fans and for building
+//! character decks (see the `speaker:` search filter).
+
+use crate::collocations::is_punctuation;
+use crate::db::{keyness, DbHandler, MIN_KEYNESS};
+use crate::tokenizer::JapaneseTokenizer;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Which sequences and lines [`character_catchphrases`] counts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CatchphraseOptions {
+    /// Shortest sequence, in tokens
+    pub min_length: usize,
+    /// Longest sequence, in tokens
+    pub max_length: usize,
+    /// Times a character must say a sequence or line for it to count
+    pub min_count: usize,
+    /// Characters with fewer lines are left out; there's too little to compare
+    pub min_lines: usize,
+    /// Number of sequences and of lines to list per character
+    pub limit: usize,
+}
+
+impl Default for CatchphraseOptions {
+    fn default() -> Self {
+        CatchphraseOptions {
+            min_length: 2,
+            max_length: 4,
+            min_count: 3,
+            min_lines: 10,
+            limit: 10,
+        }
+    }
+}
+
+/// A token sequence a character says more often than the rest of the show.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Catchphrase {
+    /// The sequence as it appears in the lines, e.g. だってばよ
+    pub text: String,
+    pub occurrences: usize,
+    /// Times the other characters say it, or lines without a speaker have it
+    pub elsewhere: usize,
+    /// The character's rate of the sequence over the rest of the show's (see [`keyness`])
+    pub keyness: f64,
+}
+
+/// A line a character says several times.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FrequentLine {
+    pub text: String,
+    pub occurrences: usize,
+    /// Times it's said elsewhere in the show
+    pub elsewhere: usize,
+}
+
+/// One character's catchphrases and most frequent lines.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CharacterProfile {
+    pub speaker: String,
+    /// Number of lines the character says
+    pub lines: usize,
+    /// The most distinctive sequences first
+    pub catchphrases: Vec<Catchphrase>,
+    /// The most frequent first
+    pub frequent_lines: Vec<FrequentLine>,
+}
+
+#[derive(Debug)]
+pub enum CatchphraseError {
+    UnknownShow(String),
+    DbError(rusqlite::Error),
+}
+
+impl From<rusqlite::Error> for CatchphraseError {
+    fn from(error: rusqlite::Error) -> Self {
+        CatchphraseError::DbError(error)
+    }
+}
+
+impl fmt::Display for CatchphraseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CatchphraseError::UnknownShow(name) => write!(f, "No show named {:?}", name),
+            CatchphraseError::DbError(e) => write!(f, "Database error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for CatchphraseError {}
+
+// What one character, or the whole show, says
+#[derive(Default)]
+struct Counts {
+    lines: usize,
+    tokens: usize,
+    sequences: HashMap<String, usize>,
+    texts: HashMap<String, usize>,
+}
+
+/// Profiles the characters of a show with at least `min_lines` lines, those
+/// with the most lines first.
+///
+/// Lines are tokenized again, stopwords included, so sequences such as
+/// ってばよ can be counted; those running into punctuation are skipped. A
+/// sequence is a catchphrase when the character says it at least
+/// `min_count` times and at least [`MIN_KEYNESS`] times as often as the rest
+/// of the show. Lines are compared without their whitespace.
+pub fn character_catchphrases(
+    db: &DbHandler,
+    tokenizer: &JapaneseTokenizer,
+    show: &str,
+    options: &CatchphraseOptions,
+) -> Result<Vec<CharacterProfile>, CatchphraseError> {
+    let lines = db
+        .speaker_lines(show)?
+        .ok_or_else(|| CatchphraseError::UnknownShow(show.to_string()))?;
+
+    let mut show_counts = Counts::default();
+    let mut speakers: HashMap<String, Counts> = HashMap::new();
+    for (speaker, text) in lines {
+        let tokens = tokenizer.tokenize(&text);
+        let mut sequences: Vec<String> = Vec::new();
+        for length in options.min_length.max(1)..=options.max_length {
+            for window in tokens.windows(length) {
+                if !window.iter().any(is_punctuation) {
+                    sequences.push(window.iter().map(|token| token.surface.as_str()).collect());
+                }
+            }
+        }
+        let text: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+
+        let mut add = |counts: &mut Counts| {
+            counts.lines += 1;
+            counts.tokens += tokens.len();
+            for sequence in &sequences {
+                *counts.sequences.entry(sequence.clone()).or_default() += 1;
+            }
+            *counts.texts.entry(text.clone()).or_default() += 1;
+        };
+        add(&mut show_counts);
+        if let Some(speaker) = speaker {
+            add(speakers.entry(speaker).or_default());
+        }
+    }
+
+    let mut profiles: Vec<CharacterProfile> = speakers
+        .into_iter()
+        .filter(|(_, counts)| counts.lines >= options.min_lines)
+        .map(|(speaker, counts)| profile(speaker, &counts, &show_counts, options))
+        .collect();
+    profiles.sort_by(|a, b| {
+        b.lines
+            .cmp(&a.lines)
+            .then_with(|| a.speaker.cmp(&b.speaker))
+    });
+    Ok(profiles)
+}
+
+// One character's profile, from their counts and the whole show's
+fn profile(
+    speaker: String,
+    counts: &Counts,
+    show: &Counts,
+    options: &CatchphraseOptions,
+) -> CharacterProfile {
+    // The baseline is per token of the rest of the show rather than all of it: a main
+    // character can say half of the lines, which would halve the baseline
+    let rest_tokens = show.tokens - counts.tokens;
+    let mut catchphrases: Vec<Catchphrase> = counts
+        .sequences
+        .iter()
+        .filter(|(_, &occurrences)| occurrences >= options.min_count)
+        .map(|(text, &occurrences)| {
+            let elsewhere = show.sequences[text] - occurrences;
+            Catchphrase {
+                text: text.clone(),
+                occurrences,
+                elsewhere,
+                keyness: keyness(
+                    occurrences as i64,
+                    counts.tokens.max(1) as i64,
+                    elsewhere as i64,
+                    rest_tokens as i64,
+                ),
+            }
+        })
+        .filter(|catchphrase| catchphrase.keyness >= MIN_KEYNESS)
+        .collect();
+    catchphrases.sort_by(|a, b| {
+        b.keyness
+            .total_cmp(&a.keyness)
+            .then_with(|| b.occurrences.cmp(&a.occurrences))
+            .then_with(|| a.text.cmp(&b.text))
+    });
+    catchphrases.truncate(options.limit);
+
+    let mut frequent_lines: Vec<FrequentLine> = counts
+        .texts
+        .iter()
+        .filter(|(_, &occurrences)| occurrences >= options.min_count)
+        .map(|(text, &occurrences)| FrequentLine {
+            text: text.clone(),
+            occurrences,
+            elsewhere: show.texts[text] - occurrences,
+        })
+        .collect();
+    frequent_lines.sort_by(|a, b| {
+        b.occurrences
+            .cmp(&a.occurrences)
+            .then_with(|| a.text.cmp(&b.text))
+    });
+    frequent_lines.truncate(options.limit);
+
+    CharacterProfile {
+        speaker,
+        lines: counts.lines,
+        catchphrases,
+        frequent_lines,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_utils::insert_speaker_lines;
+    use crate::tokenizer::test_utils::test_tokenizer;
+
+    #[test]
+    fn test_character_catchphrases() {
+        let mut db = DbHandler::new(":memory:").unwrap();
+        db.create_tables().unwrap();
+        let mut lines = Vec::new();
+        for _ in 0..3 {
+            lines.extend([
+                (Some("太郎"), "猫が好きだ"),
+                (Some("太郎"), "行くぞ"),
+                (Some("花子"), "犬が走った"),
+                (Some("花子"), "行くぞ"),
+                (None, "犬が好きだ"),
+            ]);
+        }
+        lines.push((Some("次郎"), "猫が好きだ"));
+        insert_speaker_lines(&mut db, "Show Name", &lines);
+
+        let options = CatchphraseOptions {
+            min_lines: 2,
+            ..CatchphraseOptions::default()
+        };
+        let profiles =
+            character_catchphrases(&db, &test_tokenizer(), "Show Name", &options).unwrap();
+        let speakers: Vec<(&str, usize)> = profiles
+            .iter()
+            .map(|profile| (profile.speaker.as_str(), profile.lines))
+            .collect();
+        assert_eq!(speakers, [("太郎", 6), ("花子", 6)]);
+
+        // 猫が is 太郎's, though 次郎 says it once; 行くぞ is shared, and 好きだ
+        // is as common without a speaker
+        let taro = &profiles[0];
+        let texts: Vec<&str> = taro.catchphrases.iter().map(|c| c.text.as_str()).collect();
+        assert!(texts.contains(&"猫が"));
+        assert!(!texts.iter().any(|text| text.contains("行く")));
+        assert!(!texts.contains(&"好きだ"));
+        let neko = taro.catchphrases.iter().find(|c| c.text == "猫が").unwrap();
+        assert_eq!((neko.occurrences, neko.elsewhere), (3, 1));
+
+        let frequent: Vec<(&str, usize, usize)> = taro
+            .frequent_lines
+            .iter()
+            .map(|line| (line.text.as_str(), line.occurrences, line.elsewhere))
+            .collect();
+        assert_eq!(frequent, [("猫が好きだ", 3, 1), ("行くぞ", 3, 3)]);
+
+        assert!(matches!(
+            character_catchphrases(&db, &test_tokenizer(), "Missing", &options),
+            Err(CatchphraseError::UnknownShow(_))
+        ));
+    }
+}
@@ -0,0 +1,142 @@
+//! Frequent token sequences around a word (e.g. 気がする after 気が), so
+//! learners see how it's naturally used rather than isolated hits.
+
+use crate::db::DbHandler;
+use crate::tokenizer::{JapaneseTokenizer, Token};
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Which sequences [`collocations`] counts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CollocationOptions {
+    /// Shortest sequence, in tokens
+    pub min_length: usize,
+    /// Longest sequence, in tokens
+    pub max_length: usize,
+    /// Number of sequences to return
+    pub limit: usize,
+}
+
+impl Default for CollocationOptions {
+    fn default() -> Self {
+        CollocationOptions {
+            min_length: 2,
+            max_length: 4,
+            limit: 20,
+        }
+    }
+}
+
+/// A token sequence containing the target word, with how many times it occurs.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Collocation {
+    /// Surfaces of the tokens, as they appear in the lines
+    pub tokens: Vec<String>,
+    pub frequency: usize,
+}
+
+impl Collocation {
+    /// The sequence as one string, e.g. 気がする.
+    pub fn text(&self) -> String {
+        self.tokens.concat()
+    }
+}
+
+/// The most frequent sequences of `min_length` to `max_length` tokens that
+/// contain `target` and at least one token more, the most frequent first.
+///
+/// Lines are found through the word index by the first indexed token of
+/// `target`, then tokenized again: the stored token positions count
+/// stopwords too, so particles such as が can be part of the sequences even
+/// though they aren't indexed. Sequences running into punctuation are skipped.
+pub fn collocations(
+    db: &DbHandler,
+    tokenizer: &JapaneseTokenizer,
+    target: &str,
+    options: &CollocationOptions,
+) -> rusqlite::Result<Vec<Collocation>> {
+    let target_tokens = tokenizer.tokenize(target);
+    let Some((anchor_offset, anchor)) = tokenizer.index_tokens(target).into_iter().next() else {
+        return Ok(Vec::new());
+    };
+    let shortest = options.min_length.max(target_tokens.len() + 1);
+
+    let mut counts: HashMap<Vec<String>, usize> = HashMap::new();
+    for (_, position, text) in db.word_positions(&anchor.base_form)? {
+        let Some(start) = (position as usize).checked_sub(anchor_offset) else {
+            continue;
+        };
+        let end = start + target_tokens.len();
+        let tokens = tokenizer.tokenize(&text);
+        let matches_target = tokens.get(start..end).is_some_and(|span| {
+            span.iter()
+                .zip(&target_tokens)
+                .all(|(token, target)| token.base_form == target.base_form)
+        });
+        if !matches_target {
+            continue;
+        }
+        for length in shortest..=options.max_length {
+            // Every window of this length that covers start..end
+            for first in end.saturating_sub(length)..=start {
+                let Some(window) = tokens.get(first..first + length) else {
+                    break;
+                };
+                if window.iter().any(is_punctuation) {
+                    continue;
+                }
+                let surfaces = window.iter().map(|token| token.surface.clone()).collect();
+                *counts.entry(surfaces).or_default() += 1;
+            }
+        }
+    }
+
+    let mut collocations: Vec<Collocation> = counts
+        .into_iter()
+        .map(|(tokens, frequency)| Collocation { tokens, frequency })
+        .collect();
+    collocations.sort_by(|a, b| b.frequency.cmp(&a.frequency).then(a.tokens.cmp(&b.tokens)));
+    collocations.truncate(options.limit);
+    Ok(collocations)
+}
+
+// Whether a token is a punctuation mark or other symbol
+pub(crate) fn is_punctuation(token: &Token) -> bool {
+    matches!(
+        token.pos.first().map(String::as_str),
+        Some("記号" | "補助記号")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_utils::test_db_with_lines;
+    use crate::tokenizer::test_utils::test_tokenizer;
+
+    #[test]
+    fn test_collocations_include_particles() {
+        let (mut db, ids) =
+            test_db_with_lines(&["猫が好き", "猫が好きです", "犬が猫を見る", "猫。"]);
+        let tokenizer = test_tokenizer();
+        db.index_transcripts(&tokenizer, &ids).unwrap();
+
+        let options = CollocationOptions {
+            max_length: 3,
+            ..CollocationOptions::default()
+        };
+        let found = collocations(&db, &tokenizer, "猫が", &options).unwrap();
+        let texts: Vec<(String, usize)> = found.iter().map(|c| (c.text(), c.frequency)).collect();
+        assert_eq!(texts, [("猫が好き".to_string(), 2)]);
+
+        let found = collocations(&db, &tokenizer, "猫", &CollocationOptions::default()).unwrap();
+        assert_eq!(found[0].text(), "猫が");
+        assert_eq!(found[0].frequency, 2);
+        assert!(found.iter().all(|c| !c.text().contains('。')));
+        assert!(found.iter().any(|c| c.text() == "が猫を見る"));
+
+        assert!(collocations(&db, &tokenizer, "が", &options)
+            .unwrap()
+            .is_empty());
+    }
+}
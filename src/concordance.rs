@@ -0,0 +1,186 @@
+//! Keyword-in-context (KWIC) lines: each hit aligned on its match, with a
+//! fixed width of context on either side, as corpus tools print concordances.
+//!
+//! Widths are in terminal columns, so kanji and kana count twice.
+
+use crate::db::WordQuery;
+use crate::tokenizer::{normalize, JapaneseTokenizer};
+use regex::Regex;
+use std::fmt;
+use std::ops::Range;
+use unicode_width::UnicodeWidthChar;
+
+/// What a search matched, to find it again within a hit's line.
+pub enum Matcher<'a> {
+    /// Tokens whose dictionary form (or, unless the query is exact_script, reading)
+    /// is one of the query's words; lines are normalized like the tokenizer does
+    Words {
+        tokenizer: &'a JapaneseTokenizer,
+        query: &'a WordQuery,
+    },
+    Regex(&'a Regex),
+    /// A literal string, e.g. a kanji or a grammar pattern's fixed part
+    Text(&'a str),
+}
+
+impl Matcher<'_> {
+    // The line as matched, and the byte range of the first match in it
+    fn find(&self, text: &str) -> Option<(String, Range<usize>)> {
+        match self {
+            Matcher::Words { tokenizer, query } => {
+                let text = normalize(text);
+                let readings: Vec<String> = if query.exact_script {
+                    Vec::new()
+                } else {
+                    query
+                        .terms
+                        .iter()
+                        .filter_map(|term| tokenizer.tokenize(&term.word).first()?.base_reading())
+                        .collect()
+                };
+                let mut offset = 0;
+                let mut by_reading = None;
+                for token in tokenizer.tokenize(&text) {
+                    let start = offset + text[offset..].find(&token.surface)?;
+                    offset = start + token.surface.len();
+                    let span = start..offset;
+                    if query.terms.iter().any(|term| term.word == token.base_form) {
+                        return Some((text, span));
+                    }
+                    if by_reading.is_none()
+                        && token
+                            .base_reading()
+                            .is_some_and(|reading| readings.contains(&reading))
+                    {
+                        by_reading = Some(span);
+                    }
+                }
+                by_reading.map(|span| (text, span))
+            }
+            Matcher::Regex(regex) => {
+                let found = regex.find(text).filter(|m| !m.is_empty())?;
+                Some((text.to_string(), found.range()))
+            }
+            Matcher::Text(literal) => {
+                let start = text.find(literal).filter(|_| !literal.is_empty())?;
+                Some((text.to_string(), start..start + literal.len()))
+            }
+        }
+    }
+}
+
+/// A line split around its match, with the context cut and padded to a fixed width.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KwicLine {
+    /// The context before the match, right-aligned
+    pub left: String,
+    pub keyword: String,
+    /// The context after the match, padded so whatever follows lines up too
+    pub right: String,
+}
+
+impl KwicLine {
+    /// Splits `text` around the byte range `span`, keeping `width` columns of
+    /// context on each side. Newlines are shown as spaces.
+    pub fn new(text: &str, span: Range<usize>, width: usize) -> Self {
+        let text = text.replace('\n', " ");
+        let left = last_columns(&text[..span.start], width);
+        let right = first_columns(&text[span.end..], width);
+        KwicLine {
+            left: format!("{}{}", " ".repeat(width - columns(&left)), left),
+            keyword: text[span].to_string(),
+            right: format!("{}{}", right, " ".repeat(width - columns(&right))),
+        }
+    }
+}
+
+impl fmt::Display for KwicLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.left, self.keyword, self.right)
+    }
+}
+
+/// The KWIC line of `text` around what `matcher` finds in it, or None if it finds nothing.
+pub fn kwic(matcher: &Matcher, text: &str, width: usize) -> Option<KwicLine> {
+    let (text, span) = matcher.find(text)?;
+    Some(KwicLine::new(&text, span, width))
+}
+
+/// Display width of `text` in terminal columns.
+pub fn columns(text: &str) -> usize {
+    text.chars().map(|c| c.width().unwrap_or(0)).sum()
+}
+
+// The longest prefix of `text` no wider than `width` columns
+fn first_columns(text: &str, width: usize) -> String {
+    let mut used = 0;
+    text.chars()
+        .take_while(|c| {
+            used += c.width().unwrap_or(0);
+            used <= width
+        })
+        .collect()
+}
+
+// The longest suffix of `text` no wider than `width` columns
+fn last_columns(text: &str, width: usize) -> String {
+    let mut used = 0;
+    let mut suffix: Vec<char> = text
+        .chars()
+        .rev()
+        .take_while(|c| {
+            used += c.width().unwrap_or(0);
+            used <= width
+        })
+        .collect();
+    suffix.reverse();
+    suffix.into_iter().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::parse_query;
+    use crate::tokenizer::test_utils::test_tokenizer;
+
+    #[test]
+    fn test_kwic_aligns_on_the_match() {
+        let tokenizer = test_tokenizer();
+        let query = parse_query(&tokenizer, "走る");
+        let matcher = Matcher::Words {
+            tokenizer: &tokenizer,
+            query: &query,
+        };
+        let line = kwic(&matcher, "猫が走った", 6).unwrap();
+        assert_eq!(line.left, "  猫が");
+        assert_eq!(line.keyword, "走っ");
+        assert_eq!(line.right, "た    ");
+        assert_eq!(line.to_string(), "  猫が 走っ た    ");
+
+        // Cut on whole characters: a wide one doesn't fit in the last column
+        let line = kwic(&Matcher::Text("走"), "好き好き好き走った", 5).unwrap();
+        assert_eq!(line.left, " 好き");
+        assert_eq!(columns(&line.left), 5);
+
+        assert!(kwic(&Matcher::Text("犬"), "猫が走った", 6).is_none());
+    }
+
+    #[test]
+    fn test_kwic_matches_by_reading() {
+        let tokenizer = test_tokenizer();
+        let query = parse_query(&tokenizer, "おもしろい");
+        let matcher = Matcher::Words {
+            tokenizer: &tokenizer,
+            query: &query,
+        };
+        assert_eq!(kwic(&matcher, "猫は面白い", 4).unwrap().keyword, "面白い");
+
+        let regex = Regex::new("猫+").unwrap();
+        assert_eq!(
+            kwic(&Matcher::Regex(&regex), "犬と猫猫", 4)
+                .unwrap()
+                .keyword,
+            "猫猫"
+        );
+    }
+}
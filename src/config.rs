@@ -0,0 +1,93 @@
+use crate::backend::BackendConfig;
+use crate::context::ContextWindow;
+use crate::db::TimeRangeConfig;
+use crate::fetch::FetchConfig;
+use crate::hooks::ShowHookConfig;
+use crate::ingest::ShowTypeConfig;
+use crate::llm::LlmConfig;
+use crate::player::PlayerConfig;
+use crate::query_log::QueryLogConfig;
+use crate::ranking::RankingConfig;
+use crate::tokenizer::TokenizerConfig;
+use crate::transcribe::TranscribeConfig;
+use crate::translate::TranslationConfig;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Settings loaded from a TOML config file.
+///
+/// Every section is optional; missing values fall back to their defaults.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub tokenizer: TokenizerConfig,
+    pub search: BackendConfig,
+    pub time_range: TimeRangeConfig,
+    pub context: ContextWindow,
+    pub player: PlayerConfig,
+    pub llm: LlmConfig,
+    pub translation: TranslationConfig,
+    pub ranking: RankingConfig,
+    pub query_log: QueryLogConfig,
+    pub fetch: FetchConfig,
+    pub transcribe: TranscribeConfig,
+    pub show_types: ShowTypeConfig,
+    /// Ingestion hooks, by show name
+    pub hooks: HashMap<String, ShowHookConfig>,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    IoError(std::io::Error),
+    ParseError(toml::de::Error),
+}
+
+impl From<std::io::Error> for ConfigError {
+    fn from(error: std::io::Error) -> Self {
+        ConfigError::IoError(error)
+    }
+}
+
+impl From<toml::de::Error> for ConfigError {
+    fn from(error: toml::de::Error) -> Self {
+        ConfigError::ParseError(error)
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::IoError(e) => write!(f, "I/O error: {}", e),
+            ConfigError::ParseError(e) => write!(f, "Invalid config: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl FromStr for Config {
+    type Err = ConfigError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        Ok(toml::from_str(input)?)
+    }
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        Self::from_str(&fs::read_to_string(path)?)
+    }
+
+    /// Loads the config at `path`, or returns the defaults if the file does not exist.
+    pub fn load_or_default(path: &Path) -> Result<Self, ConfigError> {
+        if path.exists() {
+            Self::load(path)
+        } else {
+            Ok(Self::default())
+        }
+    }
+}
@@ -0,0 +1,159 @@
+//! The lines shown around a search hit.
+
+use crate::db::{DbHandler, SearchHit, TranscriptId};
+use serde::Deserialize;
+
+// Characters that end a sentence, and closing brackets/quotes that may follow them
+const SENTENCE_ENDINGS: &[char] = &['。', '！', '？', '!', '?', '…', '♪'];
+const CLOSING_MARKS: &[char] = &['」', '』', '）', ')', '”', '"', '】'];
+
+/// `[context]` section of the config file: how many lines to show around a hit.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ContextWindow {
+    /// Lines before the hit
+    pub before: u32,
+    /// Lines after the hit
+    pub after: u32,
+    /// Extend the window until it starts and ends on a sentence boundary (。！？)
+    pub sentence_boundaries: bool,
+    /// The most lines a sentence boundary may add on each side
+    pub max_extension: u32,
+}
+
+impl Default for ContextWindow {
+    fn default() -> Self {
+        ContextWindow {
+            before: 5,
+            after: 2,
+            sentence_boundaries: false,
+            max_extension: 5,
+        }
+    }
+}
+
+/// Finds the lines around `transcript_id` in its episode, including the line itself.
+///
+/// With `sentence_boundaries`, the fixed window grows (by at most
+/// `max_extension` lines on each side) so that it doesn't start or end in the
+/// middle of a sentence: the line before the first one must end a sentence,
+/// and so must the last line. Subtitles often leave out final punctuation, in
+/// which case the window simply grows by the maximum.
+pub fn context_window(
+    db: &DbHandler,
+    transcript_id: TranscriptId,
+    window: &ContextWindow,
+) -> rusqlite::Result<Vec<SearchHit>> {
+    if !window.sentence_boundaries {
+        return db.find_context(transcript_id, window.before, window.after);
+    }
+    let extension = window.max_extension;
+    let lines = db.find_context(
+        transcript_id,
+        window.before.saturating_add(extension),
+        window.after.saturating_add(extension),
+    )?;
+    let Some(hit) = lines
+        .iter()
+        .position(|line| line.transcript_id == transcript_id)
+    else {
+        return Ok(lines);
+    };
+
+    // The fixed window, clamped to the start and end of the episode
+    let mut start = hit.saturating_sub(window.before as usize);
+    let mut end = hit
+        .saturating_add(window.after as usize)
+        .min(lines.len() - 1);
+
+    let earliest = hit.saturating_sub(window.before.saturating_add(extension) as usize);
+    while start > earliest && !ends_sentence(&lines[start - 1].text) {
+        start -= 1;
+    }
+    let latest = lines.len() - 1;
+    while end < latest
+        && end - hit < window.after.saturating_add(extension) as usize
+        && !ends_sentence(&lines[end].text)
+    {
+        end += 1;
+    }
+    Ok(lines[start..=end].to_vec())
+}
+
+/// Whether a line ends with sentence-ending punctuation, ignoring closing quotes.
+pub fn ends_sentence(text: &str) -> bool {
+    text.trim_end()
+        .trim_end_matches(CLOSING_MARKS)
+        .ends_with(SENTENCE_ENDINGS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_utils::test_db_with_lines;
+
+    #[test]
+    fn test_ends_sentence() {
+        assert!(ends_sentence("行こう。"));
+        assert!(ends_sentence("「本当？」"));
+        assert!(!ends_sentence("だから"));
+    }
+
+    #[test]
+    fn test_context_window_extends_to_sentence_boundaries() {
+        let (db, ids) = test_db_with_lines(&[
+            "おはよう。",
+            "昨日ね",
+            "駅の前で",
+            "猫を見たんだ",
+            "すごく大きくて",
+            "びっくりした！",
+            "へえ。",
+        ]);
+        let texts =
+            |hits: Vec<SearchHit>| -> Vec<String> { hits.into_iter().map(|h| h.text).collect() };
+
+        let fixed = ContextWindow {
+            before: 1,
+            after: 1,
+            ..ContextWindow::default()
+        };
+        assert_eq!(
+            texts(context_window(&db, ids[3], &fixed).unwrap()),
+            ["駅の前で", "猫を見たんだ", "すごく大きくて"]
+        );
+
+        let sentences = ContextWindow {
+            sentence_boundaries: true,
+            ..fixed.clone()
+        };
+        assert_eq!(
+            texts(context_window(&db, ids[3], &sentences).unwrap()),
+            [
+                "昨日ね",
+                "駅の前で",
+                "猫を見たんだ",
+                "すごく大きくて",
+                "びっくりした！"
+            ]
+        );
+
+        let limited = ContextWindow {
+            max_extension: 0,
+            ..sentences
+        };
+        assert_eq!(context_window(&db, ids[3], &limited).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_context_window_with_huge_window() {
+        let (db, ids) = test_db_with_lines(&["昨日ね", "猫を見たんだ", "へえ"]);
+        let window = ContextWindow {
+            before: u32::MAX,
+            after: u32::MAX,
+            sentence_boundaries: true,
+            max_extension: 5,
+        };
+        assert_eq!(context_window(&db, ids[1], &window).unwrap().len(), 3);
+    }
+}
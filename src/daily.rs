@@ -0,0 +1,231 @@
+//! A sentence of the day, picked deterministically from the date.
+
+use crate::context::{context_window, ContextWindow};
+use crate::db::{DbHandler, SearchHit};
+use serde::Serialize;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A JLPT level from N5 (easiest) to N1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JlptLevel(pub u8);
+
+impl FromStr for JlptLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let digits = s.strip_prefix(['N', 'n']).unwrap_or(s);
+        match digits.parse() {
+            Ok(level @ 1..=5) => Ok(JlptLevel(level)),
+            _ => Err(format!("Invalid JLPT level {:?}; expected N1 to N5", s)),
+        }
+    }
+}
+
+impl fmt::Display for JlptLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "N{}", self.0)
+    }
+}
+
+/// A calendar date, used to pick the day's sentence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Date {
+    pub year: i32,
+    pub month: u32,
+    pub day: u32,
+}
+
+impl Date {
+    /// Today's date in UTC.
+    pub fn today() -> Self {
+        let seconds = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        Date::from_days((seconds / 86_400) as i64)
+    }
+
+    /// Days since 1970-01-01.
+    pub fn days(&self) -> i64 {
+        // Howard Hinnant's days_from_civil
+        let year = self.year as i64 - (self.month <= 2) as i64;
+        let era = year.div_euclid(400);
+        let year_of_era = year - era * 400;
+        let month = self.month as i64;
+        let day_of_year =
+            (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + self.day as i64 - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        era * 146_097 + day_of_era - 719_468
+    }
+
+    fn from_days(days: i64) -> Self {
+        // Howard Hinnant's civil_from_days
+        let days = days + 719_468;
+        let era = days.div_euclid(146_097);
+        let day_of_era = days - era * 146_097;
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let shifted_month = (5 * day_of_year + 2) / 153;
+        let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+        let month = if shifted_month < 10 {
+            shifted_month + 3
+        } else {
+            shifted_month - 9
+        } as u32;
+        let year = (year_of_era + era * 400 + (month <= 2) as i64) as i32;
+        Date { year, month, day }
+    }
+}
+
+impl FromStr for Date {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid date {:?}; expected YYYY-MM-DD", s);
+        let mut parts = s.splitn(3, '-');
+        let mut next = || {
+            parts
+                .next()
+                .and_then(|part| part.parse().ok())
+                .ok_or_else(invalid)
+        };
+        let (year, month, day) = (next()? as i32, next()?, next()?);
+        let date = Date { year, month, day };
+        // Round-tripping rejects days that don't exist, like 02-30
+        if !(1..=12).contains(&month) || Date::from_days(date.days()) != date {
+            return Err(invalid());
+        }
+        Ok(date)
+    }
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+/// Which lines may be picked as the sentence of the day.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DailyOptions {
+    /// Only lines whose words are all at this JLPT level or easier, with one at it
+    pub level: Option<JlptLevel>,
+    pub min_chars: u32,
+    pub max_chars: u32,
+    /// Lines need at least this many distinct indexed words to be interesting
+    pub min_words: u32,
+}
+
+impl Default for DailyOptions {
+    fn default() -> Self {
+        DailyOptions {
+            level: None,
+            min_chars: 8,
+            max_chars: 40,
+            min_words: 3,
+        }
+    }
+}
+
+/// The sentence picked for a date, with its context and the word it features.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DailySentence {
+    pub date: String,
+    pub line: SearchHit,
+    /// The line's word at the requested JLPT level, or its rarest word
+    pub word: Option<String>,
+    /// The surrounding lines, including the line itself
+    pub context: Vec<SearchHit>,
+}
+
+/// Picks the sentence of the day for `date`.
+///
+/// The same date always picks the same line as long as the corpus doesn't
+/// change. Returns None if no line meets `options`.
+pub fn daily_sentence(
+    db: &DbHandler,
+    date: Date,
+    options: &DailyOptions,
+    window: &ContextWindow,
+) -> rusqlite::Result<Option<DailySentence>> {
+    let level = options.level.map(|level| level.0);
+    let candidates = db.daily_candidates(
+        level,
+        options.min_chars,
+        options.max_chars,
+        options.min_words,
+    )?;
+    if candidates.is_empty() {
+        return Ok(None);
+    }
+    let id = candidates[(mix(date.days() as u64) % candidates.len() as u64) as usize];
+    let Some(line) = db.find_hits_by_ids(&[id])?.pop() else {
+        return Ok(None);
+    };
+    Ok(Some(DailySentence {
+        date: date.to_string(),
+        word: db.featured_word(id, level)?,
+        context: context_window(db, id, window)?,
+        line,
+    }))
+}
+
+// splitmix64, so consecutive days pick unrelated lines
+fn mix(seed: u64) -> u64 {
+    let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_utils::test_db_with_lines;
+    use crate::tokenizer::test_utils::test_tokenizer;
+
+    #[test]
+    fn test_date_round_trip() {
+        let date: Date = "2024-02-29".parse().unwrap();
+        assert_eq!(date.days(), 19_782);
+        assert_eq!(Date::from_days(date.days()), date);
+        assert_eq!(date.to_string(), "2024-02-29");
+        assert_eq!(Date::from_days(0).to_string(), "1970-01-01");
+        assert!("2023-02-29".parse::<Date>().is_err());
+        assert!("2023-13-01".parse::<Date>().is_err());
+        assert_eq!("n3".parse::<JlptLevel>().unwrap(), JlptLevel(3));
+        assert!("N6".parse::<JlptLevel>().is_err());
+    }
+
+    #[test]
+    fn test_daily_sentence_is_deterministic() {
+        let (mut db, ids) = test_db_with_lines(&["猫が走った", "犬が好きです", "猫が好き", "犬"]);
+        db.index_transcripts(&test_tokenizer(), &ids).unwrap();
+        let options = DailyOptions {
+            min_chars: 2,
+            min_words: 2,
+            ..DailyOptions::default()
+        };
+        let window = ContextWindow::default();
+        let date: Date = "2026-01-01".parse().unwrap();
+
+        let first = daily_sentence(&db, date, &options, &window)
+            .unwrap()
+            .unwrap();
+        let again = daily_sentence(&db, date, &options, &window)
+            .unwrap()
+            .unwrap();
+        assert_eq!(first, again);
+        assert_ne!(first.line.text, "犬");
+        assert!(first.word.is_some());
+        assert!(first.context.contains(&first.line));
+
+        let strict = DailyOptions {
+            min_words: 10,
+            ..options
+        };
+        assert_eq!(daily_sentence(&db, date, &strict, &window).unwrap(), None);
+    }
+}
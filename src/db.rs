@@ -1,12 +1,105 @@
+mod api_keys;
+mod bookmarks;
+mod check;
+mod conflicts;
+mod corpora;
+mod csv_output;
+mod delete;
+mod difficulty;
+mod explanations;
+mod fts;
+mod glossary;
+mod index_meta;
+mod jlpt;
+mod llm_cache;
+mod lyrics;
+mod maintenance;
+mod media_files;
+mod notes;
+mod pitch_accent;
+mod pool;
+mod profile;
+mod progress;
+mod queries;
+mod query_log;
+mod search;
+mod show_types;
+mod source_files;
+mod sources;
+mod stable_ids;
+mod stats;
+mod suggest;
+mod tags;
+#[cfg(test)]
+pub(crate) mod test_utils;
+mod time_ranges;
+mod translations;
+mod types;
+mod vocabulary;
+mod word_index;
+
 // Import necessary items from the rusqlite crate and the standard library
-use rusqlite::{params, Batch, Connection, Error, Result};
-use std::fs::File;
-use std::io::{BufWriter, Write};
+use crate::error::WithPath;
+use crate::srt_parser::Timestamp;
+use crate::tokenizer::normalize;
+use rusqlite::functions::FunctionFlags;
+use rusqlite::{params, Batch, Connection, Error, Result, ToSql};
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
 use std::path::Path;
+use std::str::FromStr;
+
+pub use api_keys::{ApiKey, ApiScope, KeyRejection, RateLimiter};
+pub use bookmarks::{Bookmark, SavedSearch};
+pub use check::{ForeignKeyViolation, IntegrityReport};
+pub use conflicts::{ConflictPolicies, ConflictPolicy};
+pub use corpora::{CorpusSummary, DEFAULT_CORPUS};
+pub use csv_output::{CsvColumn, CsvOutput};
+pub use difficulty::{DifficultyMeasures, COMMON_WORD_COUNT};
+pub use explanations::Explanation;
+pub use glossary::{keyness, GlossaryTerm, MIN_KEYNESS};
+pub use index_meta::INDEX_POLICY_KEY;
+pub use lyrics::{LyricsFilter, LyricsOptions};
+pub use maintenance::OptimizeReport;
+pub use media_files::MediaFile;
+pub use notes::Note;
+pub use pool::{DbPool, PooledReader};
+pub use profile::QueryProfile;
+pub use progress::WatchedEpisode;
+pub use query_log::{LoggedQuery, NewLoggedQuery};
+pub use search::{Proximity, QueryTerm, SearchHit, WordQuery, WordQuerySql};
+pub use show_types::ShowType;
+pub use source_files::SourceFile;
+pub use sources::{deep_link, Source};
+pub use stable_ids::{stable_line_id, LineAnnotations};
+pub use stats::{CorpusStats, EpisodeStats, WordFrequency, TOP_WORDS_CACHED};
+pub use suggest::Suggestion;
+pub use tags::TagCount;
+pub use time_ranges::{TimeRange, TimeRangeConfig};
+pub use types::{
+    episode_label, Episode, EpisodeId, InsertedTranscripts, NewEpisode, NewShow, NewTranscript,
+    NewTranslation, Show, ShowId, Transcript, TranscriptId,
+};
+
+// Number of rows bound into a single multi-row INSERT statement
+// Kept well below SQLite's limit on the number of bound parameters
+const INSERT_CHUNK_SIZE: usize = 500;
+
+// Adds the SQL functions queries rely on to a new connection:
+// nfkc(text) normalizes text like the tokenizer does, for substring searches
+fn register_functions(conn: &Connection) -> Result<()> {
+    conn.create_scalar_function(
+        "nfkc",
+        1,
+        FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+        |ctx| Ok(normalize(&ctx.get::<String>(0)?)),
+    )
+}
 
 // Define a public struct called DbHandler that wraps a SQLite connection
 pub struct DbHandler {
     conn: Connection,
+    conflicts: ConflictPolicies,
 }
 
 impl DbHandler {
@@ -15,17 +108,192 @@ impl DbHandler {
     // Returns a Result containing either a new DbHandler or an error
     pub fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
         let conn = Connection::open(path)?;
-        Ok(DbHandler { conn })
+        // Each batch insert prepares up to two chunk sizes per table, plus the id lookups
+        conn.set_prepared_statement_cache_capacity(32);
+        register_functions(&conn)?;
+        Ok(DbHandler {
+            conn,
+            conflicts: ConflictPolicies::default(),
+        })
+    }
+
+    // Opens the database at `path` with its tables created, and those of
+    // databases from older versions brought up to date
+    // Use this rather than `new` unless the tables are known to exist
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let db = DbHandler::new(path)?;
+        db.create_tables()?;
+        Ok(db)
     }
 
     // Method to create necessary tables in the database
     // Uses the execute method to run SQL statements
     pub fn create_tables(&self) -> Result<()> {
+        // Databases created before spellings were grouped by lemma get the column; their words
+        // have none until `reindex`, so they only match their own spelling
+        let words_lack_lemma: bool = self.conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'words')
+             AND NOT EXISTS(SELECT 1 FROM pragma_table_info('words') WHERE name = 'lemma_id')",
+            [],
+            |row| row.get(0),
+        )?;
+        if words_lack_lemma {
+            self.conn.execute_batch(
+                "ALTER TABLE words ADD COLUMN lemma_id INTEGER REFERENCES lemmas(id)",
+            )?;
+        }
+        // Databases created before word frequencies were tracked get the column and its counts
+        let words_lack_frequency: bool = self.conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'words')
+             AND NOT EXISTS(SELECT 1 FROM pragma_table_info('words') WHERE name = 'frequency')",
+            [],
+            |row| row.get(0),
+        )?;
+        if words_lack_frequency {
+            self.conn.execute_batch(
+                "ALTER TABLE words ADD COLUMN frequency INTEGER NOT NULL DEFAULT 0",
+            )?;
+            word_index::recount_word_frequencies(&self.conn)?;
+        }
+        // Word indexes built before token positions and parts of speech were stored are keyed
+        // by word and line alone; SQLite can't change a primary key in place, so the table is
+        // rebuilt, empty, as the old rows have neither. `reindex` fills it again
+        let occurrences_lack_position: bool = self.conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'word_occurrences')
+             AND (NOT EXISTS(SELECT 1 FROM pragma_table_info('word_occurrences') WHERE name = 'position')
+                  OR NOT EXISTS(SELECT 1 FROM pragma_table_info('word_occurrences') WHERE name = 'pos'))",
+            [],
+            |row| row.get(0),
+        )?;
+        if occurrences_lack_position {
+            self.conn.execute_batch(
+                "SAVEPOINT add_position;
+                 DROP TABLE word_occurrences;
+                 CREATE TABLE word_occurrences (
+                     word_id INTEGER NOT NULL,
+                     transcript_id INTEGER NOT NULL,
+                     position INTEGER NOT NULL,
+                     pos TEXT NOT NULL,
+                     PRIMARY KEY(word_id, transcript_id, position),
+                     FOREIGN KEY(word_id) REFERENCES words(id),
+                     FOREIGN KEY(transcript_id) REFERENCES transcripts(id)
+                 ) WITHOUT ROWID;
+                 RELEASE add_position;",
+            )?;
+            word_index::recount_word_frequencies(&self.conn)?;
+        }
+        // Databases created before corpora existed put every show in the default one
+        let shows_lack_corpus: bool = self.conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'shows')
+             AND NOT EXISTS(SELECT 1 FROM pragma_table_info('shows') WHERE name = 'corpus')",
+            [],
+            |row| row.get(0),
+        )?;
+        if shows_lack_corpus {
+            self.conn.execute_batch(
+                "ALTER TABLE shows ADD COLUMN corpus TEXT NOT NULL DEFAULT 'default'",
+            )?;
+        }
+        // Lines ingested before raw text was kept get their cleaned text as the raw text
+        let transcripts_lack_raw_text: bool = self.conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'transcripts')
+             AND NOT EXISTS(SELECT 1 FROM pragma_table_info('transcripts') WHERE name = 'text_raw')",
+            [],
+            |row| row.get(0),
+        )?;
+        if transcripts_lack_raw_text {
+            self.conn.execute_batch(
+                "ALTER TABLE transcripts ADD COLUMN text_raw TEXT NOT NULL DEFAULT '';
+                 UPDATE transcripts SET text_raw = text;",
+            )?;
+        }
+        // Files ingested before checksums were kept have none, so they can't be told apart
+        let source_files_lack_checksum: bool = self.conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'source_files')
+             AND NOT EXISTS(SELECT 1 FROM pragma_table_info('source_files') WHERE name = 'checksum')",
+            [],
+            |row| row.get(0),
+        )?;
+        if source_files_lack_checksum {
+            self.conn
+                .execute_batch("ALTER TABLE source_files ADD COLUMN checksum TEXT")?;
+        }
+        // Lines ingested before stable ids existed get theirs from their stored values
+        let transcripts_lack_stable_id: bool = self.conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'transcripts')
+             AND NOT EXISTS(SELECT 1 FROM pragma_table_info('transcripts') WHERE name = 'stable_id')",
+            [],
+            |row| row.get(0),
+        )?;
+        if transcripts_lack_stable_id {
+            self.conn.execute_batch(
+                "ALTER TABLE transcripts ADD COLUMN stable_id TEXT NOT NULL DEFAULT ''",
+            )?;
+            stable_ids::backfill_stable_ids(&self.conn)?;
+        }
+        // Lines ingested before simultaneous cues were kept apart get sub-index 0; SQLite
+        // can't change a UNIQUE constraint in place, so the table is rebuilt with the new one
+        let transcripts_lack_sub_index: bool = self.conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'transcripts')
+             AND NOT EXISTS(SELECT 1 FROM pragma_table_info('transcripts') WHERE name = 'sub_index')",
+            [],
+            |row| row.get(0),
+        )?;
+        if transcripts_lack_sub_index {
+            self.conn.execute_batch(
+                "SAVEPOINT add_sub_index;
+                 CREATE TABLE transcripts_rebuilt (
+                     id INTEGER PRIMARY KEY,
+                     episode_id INTEGER,
+                     line_id INTEGER,
+                     time_start TEXT,
+                     time_end TEXT,
+                     sub_index INTEGER NOT NULL DEFAULT 0,
+                     text TEXT NOT NULL,
+                     text_raw TEXT NOT NULL DEFAULT '',
+                     stable_id TEXT NOT NULL DEFAULT '',
+                     UNIQUE(episode_id, time_start, time_end, sub_index),
+                     FOREIGN KEY(episode_id) REFERENCES episodes(id)
+                 );
+                 INSERT INTO transcripts_rebuilt
+                     (id, episode_id, line_id, time_start, time_end, text, text_raw, stable_id)
+                 SELECT id, episode_id, line_id, time_start, time_end, text, text_raw, stable_id
+                 FROM transcripts;
+                 DROP TABLE transcripts;
+                 ALTER TABLE transcripts_rebuilt RENAME TO transcripts;
+                 RELEASE add_sub_index;",
+            )?;
+        }
+        // Lines ingested before lyrics were detected aren't flagged until `detect-lyrics` runs
+        let transcripts_lack_lyrics: bool = self.conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'transcripts')
+             AND NOT EXISTS(SELECT 1 FROM pragma_table_info('transcripts') WHERE name = 'lyrics')",
+            [],
+            |row| row.get(0),
+        )?;
+        if transcripts_lack_lyrics {
+            self.conn.execute_batch(
+                "ALTER TABLE transcripts ADD COLUMN lyrics INTEGER NOT NULL DEFAULT 0",
+            )?;
+        }
+        // Lines ingested before speakers were recorded have none
+        let transcripts_lack_speaker: bool = self.conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'transcripts')
+             AND NOT EXISTS(SELECT 1 FROM pragma_table_info('transcripts') WHERE name = 'speaker')",
+            [],
+            |row| row.get(0),
+        )?;
+        if transcripts_lack_speaker {
+            self.conn
+                .execute_batch("ALTER TABLE transcripts ADD COLUMN speaker TEXT")?;
+        }
+
         let sql = r"
         CREATE TABLE IF NOT EXISTS shows (
             id INTEGER PRIMARY KEY,
             name TEXT NOT NULL UNIQUE,
-            show_type TEXT NOT NULL
+            show_type TEXT NOT NULL,
+            corpus TEXT NOT NULL DEFAULT 'default'
         );
         CREATE TABLE IF NOT EXISTS episodes (
             id INTEGER PRIMARY KEY,
@@ -42,131 +310,1163 @@ impl DbHandler {
             line_id INTEGER,
             time_start TEXT,
             time_end TEXT,
+            sub_index INTEGER NOT NULL DEFAULT 0,
             text TEXT NOT NULL,
-            UNIQUE(episode_id, time_start, time_end),
+            text_raw TEXT NOT NULL DEFAULT '',
+            stable_id TEXT NOT NULL DEFAULT '',
+            lyrics INTEGER NOT NULL DEFAULT 0,
+            speaker TEXT,
+            UNIQUE(episode_id, time_start, time_end, sub_index),
+            FOREIGN KEY(episode_id) REFERENCES episodes(id)
+        );
+        CREATE TABLE IF NOT EXISTS source_files (
+            path TEXT PRIMARY KEY,
+            episode_id INTEGER NOT NULL,
+            size INTEGER,
+            modified INTEGER,
+            ingested_at TEXT NOT NULL,
+            checksum TEXT,
+            FOREIGN KEY(episode_id) REFERENCES episodes(id)
+        );
+        CREATE INDEX IF NOT EXISTS source_files_checksum ON source_files(checksum);
+        CREATE TABLE IF NOT EXISTS sources (
+            episode_id INTEGER PRIMARY KEY,
+            path TEXT,
+            url TEXT,
+            video_id TEXT,
+            FOREIGN KEY(episode_id) REFERENCES episodes(id)
+        );
+        CREATE INDEX IF NOT EXISTS transcripts_stable_id ON transcripts(stable_id);
+        CREATE INDEX IF NOT EXISTS transcripts_speaker ON transcripts(speaker COLLATE NOCASE);
+        -- Movies have no episode number, which the UNIQUE constraint of episodes
+        -- doesn't compare, so this keeps a show to one per season
+        CREATE UNIQUE INDEX IF NOT EXISTS episodes_movie ON episodes(show_id, season)
+            WHERE episode_number IS NULL;
+        CREATE TABLE IF NOT EXISTS media_files (
+            episode_id INTEGER PRIMARY KEY,
+            path TEXT NOT NULL,
+            FOREIGN KEY(episode_id) REFERENCES episodes(id)
+        );
+        CREATE TABLE IF NOT EXISTS translations (
+            transcript_id INTEGER PRIMARY KEY,
+            text TEXT NOT NULL,
+            FOREIGN KEY(transcript_id) REFERENCES transcripts(id)
+        );
+        CREATE TABLE IF NOT EXISTS machine_translations (
+            transcript_id INTEGER NOT NULL,
+            provider TEXT NOT NULL,
+            text TEXT NOT NULL,
+            PRIMARY KEY(transcript_id, provider),
+            FOREIGN KEY(transcript_id) REFERENCES transcripts(id)
+        ) WITHOUT ROWID;
+        CREATE TABLE IF NOT EXISTS line_explanations (
+            transcript_id INTEGER PRIMARY KEY,
+            model TEXT NOT NULL,
+            text TEXT NOT NULL,
+            FOREIGN KEY(transcript_id) REFERENCES transcripts(id)
+        );
+        CREATE TABLE IF NOT EXISTS llm_cache (
+            key TEXT PRIMARY KEY,
+            model TEXT NOT NULL,
+            reply TEXT NOT NULL,
+            created_at INTEGER NOT NULL,
+            used_at INTEGER NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS lemmas (
+            id INTEGER PRIMARY KEY,
+            reading TEXT NOT NULL UNIQUE
+        );
+        CREATE TABLE IF NOT EXISTS words (
+            id INTEGER PRIMARY KEY,
+            word TEXT NOT NULL UNIQUE,
+            lemma_id INTEGER,
+            frequency INTEGER NOT NULL DEFAULT 0,
+            FOREIGN KEY(lemma_id) REFERENCES lemmas(id)
+        );
+        CREATE INDEX IF NOT EXISTS words_prefix ON words(word, frequency);
+        CREATE INDEX IF NOT EXISTS words_lemma ON words(lemma_id);
+        CREATE TABLE IF NOT EXISTS word_occurrences (
+            word_id INTEGER NOT NULL,
+            transcript_id INTEGER NOT NULL,
+            position INTEGER NOT NULL,
+            pos TEXT NOT NULL,
+            PRIMARY KEY(word_id, transcript_id, position),
+            FOREIGN KEY(word_id) REFERENCES words(id),
+            FOREIGN KEY(transcript_id) REFERENCES transcripts(id)
+        ) WITHOUT ROWID;
+        CREATE TABLE IF NOT EXISTS index_meta (
+            key TEXT PRIMARY KEY,
+            value TEXT NOT NULL
+        ) WITHOUT ROWID;
+        CREATE TABLE IF NOT EXISTS kanji_occurrences (
+            kanji TEXT NOT NULL,
+            transcript_id INTEGER NOT NULL,
+            PRIMARY KEY(kanji, transcript_id),
+            FOREIGN KEY(transcript_id) REFERENCES transcripts(id)
+        ) WITHOUT ROWID;
+        CREATE TABLE IF NOT EXISTS pitch_accents (
+            word TEXT NOT NULL,
+            reading TEXT NOT NULL,
+            accent TEXT NOT NULL,
+            PRIMARY KEY(word, reading)
+        ) WITHOUT ROWID;
+        CREATE TABLE IF NOT EXISTS jlpt_levels (
+            word TEXT PRIMARY KEY,
+            level INTEGER NOT NULL
+        ) WITHOUT ROWID;
+        CREATE TABLE IF NOT EXISTS corpus_stats (
+            id INTEGER PRIMARY KEY CHECK (id = 1),
+            shows INTEGER NOT NULL,
+            episodes INTEGER NOT NULL,
+            lines INTEGER NOT NULL,
+            unique_words INTEGER NOT NULL,
+            dialogue_millis INTEGER NOT NULL,
+            refreshed_at TEXT NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS episode_stats (
+            episode_id INTEGER PRIMARY KEY,
+            lines INTEGER NOT NULL,
+            characters INTEGER NOT NULL,
+            dialogue_millis INTEGER NOT NULL,
+            runtime_millis INTEGER NOT NULL,
             FOREIGN KEY(episode_id) REFERENCES episodes(id)
         );
+        CREATE TABLE IF NOT EXISTS episode_difficulty (
+            episode_id INTEGER PRIMARY KEY,
+            average_line_length REAL NOT NULL,
+            kanji_density REAL NOT NULL,
+            average_jlpt_level REAL,
+            rare_word_share REAL NOT NULL,
+            FOREIGN KEY(episode_id) REFERENCES episodes(id)
+        );
+        CREATE TABLE IF NOT EXISTS top_words (
+            rank INTEGER PRIMARY KEY,
+            word TEXT NOT NULL,
+            frequency INTEGER NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS user_progress (
+            user TEXT NOT NULL,
+            episode_id INTEGER NOT NULL,
+            watched_at TEXT NOT NULL,
+            PRIMARY KEY(user, episode_id),
+            FOREIGN KEY(episode_id) REFERENCES episodes(id)
+        ) WITHOUT ROWID;
+        CREATE TABLE IF NOT EXISTS show_glossary (
+            show_id INTEGER NOT NULL,
+            term TEXT NOT NULL,
+            reading TEXT,
+            occurrences INTEGER NOT NULL,
+            keyness REAL NOT NULL,
+            PRIMARY KEY(show_id, term),
+            FOREIGN KEY(show_id) REFERENCES shows(id)
+        ) WITHOUT ROWID;
+        CREATE TABLE IF NOT EXISTS api_keys (
+            id INTEGER PRIMARY KEY,
+            name TEXT NOT NULL UNIQUE,
+            key_hash TEXT NOT NULL UNIQUE,
+            scope TEXT NOT NULL,
+            rate_limit INTEGER NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS bookmarks (
+            id INTEGER PRIMARY KEY,
+            user TEXT NOT NULL,
+            transcript_id INTEGER NOT NULL,
+            note TEXT,
+            tag TEXT,
+            created_at TEXT NOT NULL,
+            UNIQUE(user, transcript_id),
+            FOREIGN KEY(transcript_id) REFERENCES transcripts(id)
+        );
+        CREATE TABLE IF NOT EXISTS saved_searches (
+            user TEXT NOT NULL,
+            name TEXT NOT NULL,
+            query TEXT NOT NULL,
+            kind TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            PRIMARY KEY(user, name)
+        ) WITHOUT ROWID;
+        CREATE TABLE IF NOT EXISTS line_tags (
+            transcript_id INTEGER NOT NULL,
+            tag TEXT NOT NULL,
+            PRIMARY KEY(transcript_id, tag),
+            FOREIGN KEY(transcript_id) REFERENCES transcripts(id)
+        ) WITHOUT ROWID;
+        CREATE INDEX IF NOT EXISTS line_tags_tag ON line_tags(tag);
+        CREATE TABLE IF NOT EXISTS notes (
+            id INTEGER PRIMARY KEY,
+            transcript_id INTEGER,
+            show_id INTEGER,
+            text TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            CHECK((transcript_id IS NULL) <> (show_id IS NULL)),
+            FOREIGN KEY(transcript_id) REFERENCES transcripts(id),
+            FOREIGN KEY(show_id) REFERENCES shows(id)
+        );
+        CREATE INDEX IF NOT EXISTS notes_transcript ON notes(transcript_id);
+        CREATE INDEX IF NOT EXISTS notes_show ON notes(show_id);
+        CREATE VIRTUAL TABLE IF NOT EXISTS notes_fts USING fts5(
+            text,
+            content = 'notes',
+            content_rowid = 'id',
+            tokenize = 'trigram'
+        );
+        CREATE TRIGGER IF NOT EXISTS notes_fts_insert AFTER INSERT ON notes BEGIN
+            INSERT INTO notes_fts (rowid, text) VALUES (new.id, new.text);
+        END;
+        CREATE TRIGGER IF NOT EXISTS notes_fts_delete AFTER DELETE ON notes BEGIN
+            INSERT INTO notes_fts (notes_fts, rowid, text) VALUES ('delete', old.id, old.text);
+        END;
+        CREATE TRIGGER IF NOT EXISTS notes_fts_update AFTER UPDATE ON notes BEGIN
+            INSERT INTO notes_fts (notes_fts, rowid, text) VALUES ('delete', old.id, old.text);
+            INSERT INTO notes_fts (rowid, text) VALUES (new.id, new.text);
+        END;
+        CREATE TABLE IF NOT EXISTS query_log (
+            id INTEGER PRIMARY KEY,
+            query TEXT NOT NULL,
+            kind TEXT NOT NULL,
+            filters TEXT NOT NULL,
+            hits INTEGER NOT NULL,
+            latency_micros INTEGER NOT NULL,
+            logged_at TEXT NOT NULL
+        );
     ";
 
         let mut batch = Batch::new(&self.conn, sql);
         while let Some(mut stmt) = batch.next()? {
             stmt.execute([])?;
         }
+        // The emptied word index is recorded as built by an unknown tokenizer (see
+        // IndexVersion), so searches warn to run `reindex`
+        if occurrences_lack_position {
+            self.set_index_meta("tokenizer", "unknown, without token positions")?;
+        }
         Ok(())
     }
 
+    // Runs `f` as a single unit: if it returns an error, everything it wrote is rolled back
+    // The batch methods use savepoints rather than transactions, so they nest inside `f`
+    // Errors of any type convertible from rusqlite::Error can be used to abort
+    pub fn atomically<T, E: From<Error>>(
+        &mut self,
+        f: impl FnOnce(&mut DbHandler) -> std::result::Result<T, E>,
+    ) -> std::result::Result<T, E> {
+        self.atomically_or(E::from, f)
+    }
+
+    // Like `atomically`, for error types that need more than a rusqlite::Error
+    // `db_error` converts the errors of creating, releasing or rolling back the savepoint
+    pub fn atomically_or<T, E>(
+        &mut self,
+        db_error: impl Fn(Error) -> E,
+        f: impl FnOnce(&mut DbHandler) -> std::result::Result<T, E>,
+    ) -> std::result::Result<T, E> {
+        self.conn
+            .execute_batch("SAVEPOINT atomically")
+            .map_err(&db_error)?;
+        match f(self) {
+            Ok(value) => {
+                self.conn
+                    .execute_batch("RELEASE atomically")
+                    .map_err(&db_error)?;
+                Ok(value)
+            }
+            Err(e) => {
+                self.conn
+                    .execute_batch("ROLLBACK TO atomically; RELEASE atomically")
+                    .map_err(&db_error)?;
+                Err(e)
+            }
+        }
+    }
+
     // Method to insert a new show into the database
-    // Returns the ID of the newly inserted row
+    // Returns the ID of the show, whether it was newly inserted or already existed
     // params! is a macro that helps prevent SQL injection
-    pub fn insert_show(&self, name: &str, show_type: &str) -> Result<i64> {
+    pub fn insert_show(&self, show: &NewShow) -> Result<ShowId> {
         self.conn.execute(
             "INSERT OR IGNORE INTO shows (name, show_type) VALUES (?1, ?2)",
-            params![name, show_type],
+            params![show.name, show.show_type],
         )?;
-        Ok(self.conn.last_insert_rowid())
+        self.conn.query_row(
+            "SELECT id FROM shows WHERE name = ?1",
+            params![show.name],
+            |row| row.get(0),
+        )
     }
 
     // Method to insert a new episode into the database
-    pub fn insert_episode(
-        &self,
-        show_id: i64,
-        name: &str,
-        season: i32,
-        episode_number: i32,
-    ) -> Result<i64> {
+    pub fn insert_episode(&self, episode: &NewEpisode) -> Result<EpisodeId> {
         self.conn.execute(
-        "INSERT OR IGNORE INTO episodes (show_id, name, season, episode_number) VALUES (?1, ?2, ?3, ?4)",
-        params![show_id, name, season, episode_number],
-    )?;
-        Ok(self.conn.last_insert_rowid())
-    }
-
-    // Method to insert a new transcript group into the database
-    pub fn insert_transcript(
-        &self,
-        episode_id: i64,
-        line_id: i32,
-        time_start: &str,
-        time_end: &str,
-        text: &str,
-    ) -> Result<i64> {
-        self.conn.execute(
-        "INSERT OR IGNORE INTO transcripts (episode_id, line_id, time_start, time_end, text) VALUES (?1, ?2, ?3, ?4, ?5)",
-        params![episode_id, line_id, time_start, time_end, text],
-    )?;
-        Ok(self.conn.last_insert_rowid())
+            "INSERT OR IGNORE INTO episodes (show_id, name, season, episode_number) VALUES (?1, ?2, ?3, ?4)",
+            params![episode.show_id, episode.name, episode.season, episode.episode_number],
+        )?;
+        self.conn.query_row(
+            "SELECT id FROM episodes WHERE show_id = ?1 AND season = ?2 AND episode_number IS ?3",
+            params![episode.show_id, episode.season, episode.episode_number],
+            |row| row.get(0),
+        )
+    }
+
+    // Method to insert a new transcript line into the database
+    // Returns None if an identical line already exists
+    pub fn insert_transcript(&self, transcript: &NewTranscript) -> Result<Option<TranscriptId>> {
+        let (show_name, _, season, episode_number) =
+            lookup_episode_info(&self.conn, &mut HashMap::new(), transcript.episode_id)?.clone();
+        let stable_id = stable_line_id(
+            &show_name,
+            season,
+            episode_number,
+            &transcript.time_start,
+            &transcript.text,
+        );
+        let rows_affected = self.conn.execute(
+            "INSERT OR IGNORE INTO transcripts (episode_id, line_id, time_start, time_end, sub_index, text, speaker, stable_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                transcript.episode_id,
+                transcript.line_id,
+                transcript.time_start,
+                transcript.time_end,
+                transcript.sub_index,
+                transcript.text,
+                transcript.speaker,
+                stable_id
+            ],
+        )?;
+        Ok((rows_affected > 0).then(|| TranscriptId(self.conn.last_insert_rowid())))
     }
 
-    pub fn batch_insert_shows(&mut self, shows: &[(String, String)]) -> Result<()> {
+    // Inserts all shows in a single transaction
+    // Rows with the same name are one show; for shows that already exist, the shows
+    // conflict policy decides whether they're reused, updated, renamed or an error
+    // Returns the ID of each show, in the same order as the input
+    pub fn batch_insert_shows(&mut self, shows: &[NewShow]) -> Result<Vec<ShowId>> {
         println!("Inserting shows...");
-        let sql = "INSERT OR IGNORE INTO shows (name, show_type) VALUES (?, ?)";
-        let tx = self.conn.transaction()?;
+        let policy = self.conflicts.shows;
+        let tx = self.conn.savepoint()?;
+        // The name each input name is stored under, and the rows to insert
+        let mut names: HashMap<&str, String> = HashMap::new();
+        let mut unique = Vec::new();
         {
-            let mut stmt = tx.prepare(sql)?;
-            for (name, show_type) in shows {
-                stmt.execute(params![name, show_type])?;
+            let mut exists =
+                tx.prepare_cached("SELECT EXISTS(SELECT 1 FROM shows WHERE name = ?)")?;
+            for show in shows {
+                if names.contains_key(show.name.as_str()) {
+                    continue;
+                }
+                let mut name = show.name.clone();
+                if policy == ConflictPolicy::Rename {
+                    let mut number = 2;
+                    while exists.query_row(params![name], |row| row.get(0))? {
+                        name = format!("{} ({})", show.name, number);
+                        number += 1;
+                    }
+                }
+                names.insert(&show.name, name.clone());
+                unique.push(NewShow {
+                    name,
+                    show_type: show.show_type.clone(),
+                });
             }
         }
-        tx.commit()
+        let columns = ["name", "show_type"];
+        for chunk in unique.chunks(INSERT_CHUNK_SIZE) {
+            let sql = multi_row_insert_sql("shows", &columns, chunk.len(), policy)
+                + &policy.upsert_clause(&["name"], &columns);
+            let mut stmt = tx.prepare_cached(&sql)?;
+            let values: Vec<&dyn ToSql> = chunk
+                .iter()
+                .flat_map(|show| [&show.name as &dyn ToSql, &show.show_type])
+                .collect();
+            stmt.execute(&values[..])?;
+        }
+        let ids = {
+            let mut select = tx.prepare_cached("SELECT id FROM shows WHERE name = ?")?;
+            shows
+                .iter()
+                .map(|show| select.query_row(params![names[show.name.as_str()]], |row| row.get(0)))
+                .collect::<Result<Vec<_>>>()?
+        };
+        tx.commit()?;
+        Ok(ids)
     }
 
-    pub fn batch_insert_episodes(&mut self, episodes: &[(i64, String, i32, i32)]) -> Result<()> {
+    // Inserts all episodes in a single transaction
+    // Rows with the same show, season and number (or movies of the same show and season)
+    // are one episode; for episodes that already exist, the episodes conflict policy
+    // decides what happens, as for shows
+    // Returns the ID of each episode, in the same order as the input
+    pub fn batch_insert_episodes(&mut self, episodes: &[NewEpisode]) -> Result<Vec<EpisodeId>> {
         println!("Inserting episodes...");
-        let sql =
-        "INSERT OR IGNORE INTO episodes (show_id, name, season, episode_number) VALUES (?, ?, ?, ?)";
-        let tx = self.conn.transaction()?;
+        let policy = self.conflicts.episodes;
+        let tx = self.conn.savepoint()?;
+        // The number each input episode is stored under, and the rows to insert
+        let mut numbers: HashMap<(ShowId, i32, Option<i32>), Option<i32>> = HashMap::new();
+        let mut unique = Vec::new();
         {
-            let mut stmt = tx.prepare(sql)?;
-            for (show_id, name, season, episode_number) in episodes {
-                stmt.execute(params![show_id, name, season, episode_number])?;
+            let mut exists = tx.prepare_cached(
+                "SELECT EXISTS(SELECT 1 FROM episodes
+                 WHERE show_id = ? AND season = ? AND episode_number = ?)",
+            )?;
+            let mut last_number = tx.prepare_cached(
+                "SELECT COALESCE(MAX(episode_number), 0) FROM episodes
+                 WHERE show_id = ? AND season = ?",
+            )?;
+            // The last number taken in each season, by stored, incoming or renamed episodes
+            let mut last_numbers: HashMap<(ShowId, i32), i32> = HashMap::new();
+            for episode in episodes {
+                let key = (episode.show_id, episode.season, episode.episode_number);
+                if numbers.contains_key(&key) {
+                    continue;
+                }
+                let mut number = episode.episode_number;
+                // Movies have no number to change, so they're never renamed
+                if policy == ConflictPolicy::Rename
+                    && number.is_some()
+                    && exists.query_row(params![key.0, key.1, key.2], |row| row.get(0))?
+                {
+                    let last = match last_numbers.entry((key.0, key.1)) {
+                        Entry::Occupied(entry) => entry.into_mut(),
+                        Entry::Vacant(entry) => {
+                            let stored: i32 =
+                                last_number.query_row(params![key.0, key.1], |row| row.get(0))?;
+                            let incoming = episodes
+                                .iter()
+                                .filter(|e| (e.show_id, e.season) == (key.0, key.1))
+                                .filter_map(|e| e.episode_number)
+                                .max()
+                                .unwrap_or(0);
+                            entry.insert(stored.max(incoming))
+                        }
+                    };
+                    *last += 1;
+                    number = Some(*last);
+                }
+                numbers.insert(key, number);
+                unique.push(NewEpisode {
+                    episode_number: number,
+                    ..episode.clone()
+                });
             }
         }
-        tx.commit()
+        let columns = ["show_id", "name", "season", "episode_number"];
+        // Movies collide on the partial index of episodes without a number rather than
+        // on the key, so they're upserted against that index
+        let (movies, numbered): (Vec<NewEpisode>, Vec<NewEpisode>) = unique
+            .into_iter()
+            .partition(|episode| episode.episode_number.is_none());
+        let upserts = [
+            (
+                numbered,
+                policy.upsert_clause(&["show_id", "season", "episode_number"], &columns),
+            ),
+            (
+                movies,
+                policy.partial_upsert_clause(
+                    &["show_id", "season"],
+                    "episode_number IS NULL",
+                    &columns,
+                ),
+            ),
+        ];
+        for (rows, upsert) in &upserts {
+            for chunk in rows.chunks(INSERT_CHUNK_SIZE) {
+                let sql = multi_row_insert_sql("episodes", &columns, chunk.len(), policy) + upsert;
+                let mut stmt = tx.prepare_cached(&sql)?;
+                let values: Vec<&dyn ToSql> = chunk
+                    .iter()
+                    .flat_map(|episode| {
+                        [
+                            &episode.show_id as &dyn ToSql,
+                            &episode.name,
+                            &episode.season,
+                            &episode.episode_number,
+                        ]
+                    })
+                    .collect();
+                stmt.execute(&values[..])?;
+            }
+        }
+        let ids = {
+            let mut select = tx.prepare_cached(
+                "SELECT id FROM episodes WHERE show_id = ? AND season = ? AND episode_number IS ?",
+            )?;
+            episodes
+                .iter()
+                .map(|episode| {
+                    let number =
+                        numbers[&(episode.show_id, episode.season, episode.episode_number)];
+                    select.query_row(params![episode.show_id, episode.season, number], |row| {
+                        row.get(0)
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?
+        };
+        tx.commit()?;
+        Ok(ids)
     }
 
+    // Inserts all transcripts in a single transaction
+    // Lines whose episode, start and end time and sub-index are taken follow the lines
+    // conflict policy; replaced lines keep their id, and lose their word index entries
+    // to be indexed again
+    // If `csv_output` is given, every newly inserted or replaced line is also written to that CSV file
+    // Returns the IDs of the lines that were newly inserted or replaced
     pub fn batch_insert_transcripts(
         &mut self,
-        transcripts: &[(i64, i32, String, String, String)],
-        output_csv: bool,
-    ) -> Result<()> {
+        transcripts: &[NewTranscript],
+        csv_output: Option<&CsvOutput>,
+    ) -> crate::error::Result<InsertedTranscripts> {
         println!("Inserting transcripts...");
-        let sql = "INSERT OR IGNORE INTO transcripts (episode_id, line_id, time_start, time_end, text) VALUES (?, ?, ?, ?, ?)";
-        let tx = self.conn.transaction()?;
-
-        let mut csv_writer = if output_csv {
-            Some(BufWriter::new(
-                File::create("transcripts.csv")
-                    .map_err(|e| Error::InvalidParameterName(e.to_string()))?,
-            ))
+        let policy = self.conflicts.lines;
+        let tx = self.conn.savepoint()?;
+        let mut conflicts = 0;
+        let renamed;
+        let transcripts = if policy == ConflictPolicy::Rename {
+            (renamed, conflicts) = rename_colliding_lines(&tx, transcripts)?;
+            &renamed[..]
         } else {
-            None
+            transcripts
         };
+        // Lines with a higher id than this are new; the others were replaced
+        let last_id: i64 =
+            tx.query_row("SELECT COALESCE(MAX(id), 0) FROM transcripts", [], |row| {
+                row.get(0)
+            })?;
 
-        {
-            let mut stmt = tx.prepare(sql)?;
-            for (episode_id, line_id, time_start, time_end, text) in transcripts {
-                match stmt.execute(params![episode_id, line_id, time_start, time_end, text]) {
-                    Ok(rows_affected) if rows_affected > 0 => {
-                        let id = tx.last_insert_rowid();
-                        if let Some(writer) = csv_writer.as_mut() {
-                            for line in text.split('\n') {
-                                writeln!(writer, "{},{}", id, line)
-                                    .map_err(|e| Error::InvalidParameterName(e.to_string()))?;
-                            }
+        let mut csv_writer = match csv_output {
+            Some(output) => {
+                let file = std::fs::OpenOptions::new()
+                    .create(true)
+                    .write(true)
+                    .append(output.append)
+                    .truncate(!output.append)
+                    .open(&output.path)
+                    .with_path(&output.path)?;
+                let is_empty = file.metadata().with_path(&output.path)?.len() == 0;
+                let mut writer = csv::Writer::from_writer(file);
+                if output.include_header && is_empty {
+                    writer
+                        .write_record(output.columns.iter().map(|column| column.header()))
+                        .with_path(&output.path)?;
+                }
+                Some((writer, output))
+            }
+            None => None,
+        };
+        let mut episode_info = HashMap::new();
+        let mut inserted_ids = Vec::new();
+
+        for chunk in transcripts.chunks(INSERT_CHUNK_SIZE) {
+            let stable_ids = chunk
+                .iter()
+                .map(|transcript| {
+                    let (show_name, _, season, episode_number) =
+                        lookup_episode_info(&tx, &mut episode_info, transcript.episode_id)?;
+                    Ok(stable_line_id(
+                        show_name,
+                        *season,
+                        *episode_number,
+                        &transcript.time_start,
+                        &transcript.text,
+                    ))
+                })
+                .collect::<Result<Vec<String>>>()?;
+            // RETURNING only yields rows that were actually inserted or replaced,
+            // so lines that are ignored are skipped in the CSV
+            let columns = [
+                "episode_id",
+                "line_id",
+                "time_start",
+                "time_end",
+                "sub_index",
+                "text",
+                "text_raw",
+                "speaker",
+                "stable_id",
+            ];
+            let key = ["episode_id", "time_start", "time_end", "sub_index"];
+            let sql = multi_row_insert_sql("transcripts", &columns, chunk.len(), policy)
+                + &policy.upsert_clause(&key, &columns)
+                + " RETURNING id, episode_id, line_id, time_start, time_end, sub_index, text, text_raw,
+                       speaker, stable_id";
+            let mut stmt = tx.prepare_cached(&sql)?;
+            let values: Vec<&dyn ToSql> = chunk
+                .iter()
+                .zip(&stable_ids)
+                .flat_map(|(transcript, stable_id)| {
+                    [
+                        &transcript.episode_id as &dyn ToSql,
+                        &transcript.line_id,
+                        &transcript.time_start,
+                        &transcript.time_end,
+                        &transcript.sub_index,
+                        &transcript.text,
+                        &transcript.text_raw,
+                        &transcript.speaker,
+                        stable_id,
+                    ]
+                })
+                .collect();
+            let inserted = stmt
+                .query_map(&values[..], |row| {
+                    Ok((
+                        row.get::<_, TranscriptId>(0)?,
+                        NewTranscript {
+                            episode_id: row.get(1)?,
+                            line_id: row.get(2)?,
+                            time_start: row.get(3)?,
+                            time_end: row.get(4)?,
+                            sub_index: row.get(5)?,
+                            text: row.get(6)?,
+                            text_raw: row.get(7)?,
+                            speaker: row.get(8)?,
+                        },
+                        row.get::<_, String>(9)?,
+                    ))
+                })?
+                .collect::<Result<Vec<_>>>()?;
+            inserted_ids.extend(inserted.iter().map(|(id, _, _)| *id));
+
+            if let Some((writer, output)) = csv_writer.as_mut() {
+                for (id, transcript, stable_id) in &inserted {
+                    let info = if output.columns.iter().any(|c| c.needs_episode_info()) {
+                        Some(lookup_episode_info(
+                            &tx,
+                            &mut episode_info,
+                            transcript.episode_id,
+                        )?)
+                    } else {
+                        None
+                    };
+                    let record = output.columns.iter().map(|column| match (column, info) {
+                        (CsvColumn::TranscriptId, _) => id.to_string(),
+                        (CsvColumn::LineId, _) => transcript.line_id.to_string(),
+                        (CsvColumn::TimeStart, _) => transcript.time_start.clone(),
+                        (CsvColumn::TimeEnd, _) => transcript.time_end.clone(),
+                        (CsvColumn::Text, _) => transcript.text.clone(),
+                        (CsvColumn::TextRaw, _) => transcript.text_raw.clone(),
+                        (CsvColumn::StableId, _) => stable_id.clone(),
+                        (CsvColumn::ShowName, Some(info)) => info.0.clone(),
+                        (CsvColumn::EpisodeName, Some(info)) => info.1.clone(),
+                        (CsvColumn::Season, Some(info)) => info.2.to_string(),
+                        (CsvColumn::EpisodeNumber, Some(info)) => {
+                            info.3.map_or_else(String::new, |number| number.to_string())
                         }
-                    }
-                    Ok(_) => {}              // Row already exists, skip CSV writing
-                    Err(e) => return Err(e), // Propagate other errors
+                        (_, None) => String::new(),
+                    });
+                    writer.write_record(record).with_path(&output.path)?;
+                }
+            }
+        }
+
+        if let Some((mut writer, output)) = csv_writer {
+            writer.flush().with_path(&output.path)?;
+        }
+
+        match policy {
+            ConflictPolicy::Ignore => conflicts = transcripts.len() - inserted_ids.len(),
+            // A line replaced twice in one batch is returned twice
+            ConflictPolicy::Replace => {
+                let mut seen = HashSet::new();
+                inserted_ids.retain(|&id| seen.insert(id));
+                let replaced: Vec<TranscriptId> = inserted_ids
+                    .iter()
+                    .copied()
+                    .filter(|id| id.0 <= last_id)
+                    .collect();
+                word_index::delete_line_index(&tx, &replaced)?;
+                conflicts = transcripts.len() - (inserted_ids.len() - replaced.len());
+            }
+            ConflictPolicy::Error | ConflictPolicy::Rename => {}
+        }
+
+        tx.commit()?;
+        Ok(InsertedTranscripts {
+            ids: inserted_ids,
+            conflicts,
+        })
+    }
+}
+
+// Moves the end time of lines whose episode, start and end time and sub-index are taken,
+// by stored lines or earlier ones in `transcripts`, a millisecond later at a time until
+// it's free
+// Returns the lines, and how many were moved
+fn rename_colliding_lines(
+    conn: &Connection,
+    transcripts: &[NewTranscript],
+) -> Result<(Vec<NewTranscript>, usize)> {
+    let mut select = conn.prepare_cached(
+        "SELECT time_start, time_end, sub_index FROM transcripts WHERE episode_id = ?",
+    )?;
+    let mut taken: HashSet<(EpisodeId, String, String, i32)> = HashSet::new();
+    let mut loaded = HashSet::new();
+    let mut lines = Vec::with_capacity(transcripts.len());
+    let mut renamed = 0;
+    for transcript in transcripts {
+        if loaded.insert(transcript.episode_id) {
+            let stored = select.query_map(params![transcript.episode_id], |row| {
+                Ok((transcript.episode_id, row.get(0)?, row.get(1)?, row.get(2)?))
+            })?;
+            for key in stored {
+                taken.insert(key?);
+            }
+        }
+        let mut line = transcript.clone();
+        let key = |line: &NewTranscript| {
+            (
+                line.episode_id,
+                line.time_start.clone(),
+                line.time_end.clone(),
+                line.sub_index,
+            )
+        };
+        if taken.contains(&key(&line)) {
+            // Lines whose end time can't be read are left to be ignored
+            if let Ok(end) = Timestamp::from_str(&line.time_end) {
+                let mut millis = end.to_millis();
+                while taken.contains(&key(&line)) {
+                    millis += 1;
+                    line.time_end = Timestamp::from_millis(millis).to_string();
                 }
+                renamed += 1;
             }
         }
+        taken.insert(key(&line));
+        lines.push(line);
+    }
+    Ok((lines, renamed))
+}
+
+// Show name, episode name, season, and episode number (none for a movie) of an episode
+type EpisodeInfo = (String, String, i32, Option<i32>);
 
-        if let Some(mut writer) = csv_writer {
-            writer
-                .flush()
-                .map_err(|e| Error::InvalidParameterName(e.to_string()))?;
+// Looks up the show and episode details of an episode, caching the result
+fn lookup_episode_info<'a>(
+    conn: &Connection,
+    cache: &'a mut HashMap<EpisodeId, EpisodeInfo>,
+    episode_id: EpisodeId,
+) -> Result<&'a EpisodeInfo> {
+    match cache.entry(episode_id) {
+        Entry::Occupied(entry) => Ok(entry.into_mut()),
+        Entry::Vacant(entry) => {
+            let info = conn.query_row(
+                "SELECT shows.name, episodes.name, episodes.season, episodes.episode_number
+                 FROM episodes JOIN shows ON shows.id = episodes.show_id
+                 WHERE episodes.id = ?",
+                params![episode_id],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )?;
+            Ok(entry.insert(info))
         }
+    }
+}
+
+// Builds an "INSERT OR IGNORE ... VALUES (?, ?), (?, ?), ..." statement for `rows` rows,
+// with the INSERT verb of the conflict policy
+// Full chunks always produce the same SQL, so the prepared statement cache can reuse them
+fn multi_row_insert_sql(
+    table: &str,
+    columns: &[&str],
+    rows: usize,
+    policy: ConflictPolicy,
+) -> String {
+    let placeholders = format!("({})", vec!["?"; columns.len()].join(", "));
+    format!(
+        "{} INTO {} ({}) VALUES {}",
+        policy.insert_verb(),
+        table,
+        columns.join(", "),
+        vec![placeholders; rows].join(", ")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_batch_insert_shows_returns_existing_ids() {
+        let mut db = DbHandler::new(":memory:").unwrap();
+        db.create_tables().unwrap();
+        let show = NewShow {
+            name: "Show Name".to_string(),
+            show_type: "Anime".to_string(),
+        };
+        let shows = vec![show.clone(), show];
+        let ids = db.batch_insert_shows(&shows).unwrap();
+        assert_eq!(ids.len(), 2);
+        assert_eq!(ids[0], ids[1]);
+    }
+
+    #[test]
+    fn test_create_tables_adds_word_frequencies() {
+        let db = DbHandler::new(":memory:").unwrap();
+        db.conn
+            .execute_batch(
+                "CREATE TABLE words (id INTEGER PRIMARY KEY, word TEXT NOT NULL UNIQUE);
+                 CREATE TABLE word_occurrences (word_id INTEGER NOT NULL, transcript_id INTEGER NOT NULL,
+                     position INTEGER NOT NULL, pos TEXT NOT NULL,
+                     PRIMARY KEY(word_id, transcript_id, position)) WITHOUT ROWID;
+                 INSERT INTO words (id, word) VALUES (1, '猫');
+                 INSERT INTO word_occurrences VALUES (1, 1, 0, ''), (1, 2, 0, '');",
+            )
+            .unwrap();
+        db.create_tables().unwrap();
+        let frequency: i64 = db
+            .conn
+            .query_row("SELECT frequency FROM words WHERE id = 1", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(frequency, 2);
+    }
+
+    #[test]
+    fn test_create_tables_upgrades_pre_lemma_schema() {
+        let mut db = DbHandler::new(":memory:").unwrap();
+        // The tables as created before words had lemmas
+        db.conn
+            .execute_batch(
+                "CREATE TABLE shows (id INTEGER PRIMARY KEY, name TEXT NOT NULL UNIQUE,
+                     show_type TEXT NOT NULL);
+                 CREATE TABLE episodes (id INTEGER PRIMARY KEY, show_id INTEGER, name TEXT NOT NULL,
+                     season INTEGER, episode_number INTEGER,
+                     UNIQUE(show_id, season, episode_number),
+                     FOREIGN KEY(show_id) REFERENCES shows(id));
+                 CREATE TABLE transcripts (id INTEGER PRIMARY KEY, episode_id INTEGER, line_id INTEGER,
+                     time_start TEXT, time_end TEXT, text TEXT NOT NULL,
+                     UNIQUE(episode_id, time_start, time_end),
+                     FOREIGN KEY(episode_id) REFERENCES episodes(id));
+                 CREATE TABLE translations (transcript_id INTEGER PRIMARY KEY, text TEXT NOT NULL,
+                     FOREIGN KEY(transcript_id) REFERENCES transcripts(id));
+                 CREATE TABLE words (id INTEGER PRIMARY KEY, word TEXT NOT NULL UNIQUE);
+                 CREATE TABLE word_occurrences (word_id INTEGER NOT NULL, transcript_id INTEGER NOT NULL,
+                     position INTEGER NOT NULL, pos TEXT NOT NULL,
+                     PRIMARY KEY(word_id, transcript_id, position),
+                     FOREIGN KEY(word_id) REFERENCES words(id),
+                     FOREIGN KEY(transcript_id) REFERENCES transcripts(id)) WITHOUT ROWID;
+                 CREATE TABLE kanji_occurrences (kanji TEXT NOT NULL, transcript_id INTEGER NOT NULL,
+                     PRIMARY KEY(kanji, transcript_id),
+                     FOREIGN KEY(transcript_id) REFERENCES transcripts(id)) WITHOUT ROWID;
+                 CREATE TABLE pitch_accents (word TEXT NOT NULL, reading TEXT NOT NULL,
+                     accent TEXT NOT NULL, PRIMARY KEY(word, reading)) WITHOUT ROWID;
+                 INSERT INTO shows VALUES (1, 'Show Name', 'Anime');
+                 INSERT INTO episodes VALUES (1, 1, 'Episode 1', 1, 1);
+                 INSERT INTO transcripts VALUES
+                     (1, 1, 1, '00:00:01,000', '00:00:02,000', '猫が走った');
+                 INSERT INTO words VALUES (1, '猫');
+                 INSERT INTO word_occurrences VALUES (1, 1, 0, '名詞-一般');",
+            )
+            .unwrap();
+        db.create_tables().unwrap();
+        let lemma: Option<i64> = db
+            .conn
+            .query_row("SELECT lemma_id FROM words WHERE id = 1", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(lemma, None);
+
+        // The upgraded index takes new lines
+        let tokenizer = crate::tokenizer::test_utils::test_tokenizer();
+        db.index_transcripts(&tokenizer, &[TranscriptId(1)])
+            .unwrap();
+        let lemmas: i64 = db
+            .conn
+            .query_row(
+                "SELECT COUNT(*) FROM words WHERE lemma_id IS NOT NULL",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert!(lemmas > 0);
+    }
+
+    #[test]
+    fn test_create_tables_rebuilds_word_occurrences() {
+        let db = DbHandler::new(":memory:").unwrap();
+        db.conn
+            .execute_batch(
+                "CREATE TABLE words (id INTEGER PRIMARY KEY, word TEXT NOT NULL UNIQUE);
+                 CREATE TABLE word_occurrences (word_id INTEGER NOT NULL, transcript_id INTEGER NOT NULL,
+                     PRIMARY KEY(word_id, transcript_id)) WITHOUT ROWID;
+                 INSERT INTO words (id, word) VALUES (1, '猫');
+                 INSERT INTO word_occurrences VALUES (1, 1), (1, 2);",
+            )
+            .unwrap();
+        db.create_tables().unwrap();
+        let key: Vec<String> = db
+            .conn
+            .prepare(
+                "SELECT name FROM pragma_table_info('word_occurrences') WHERE pk > 0 ORDER BY pk",
+            )
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(key, ["word_id", "transcript_id", "position"]);
+        let frequency: i64 = db
+            .conn
+            .query_row("SELECT frequency FROM words WHERE id = 1", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(frequency, 0);
+        assert!(db.index_meta("tokenizer").unwrap().is_some());
+
+        // Tables already keyed by position are left alone
+        db.conn
+            .execute("INSERT INTO word_occurrences VALUES (1, 1, 0, '名詞')", [])
+            .unwrap();
+        db.create_tables().unwrap();
+        let count: i64 = db
+            .conn
+            .query_row("SELECT COUNT(*) FROM word_occurrences", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(count, 1);
+    }
+
+    #[test]
+    fn test_multi_row_insert_sql() {
+        assert_eq!(
+            multi_row_insert_sql("shows", &["name", "show_type"], 2, ConflictPolicy::Ignore),
+            "INSERT OR IGNORE INTO shows (name, show_type) VALUES (?, ?), (?, ?)"
+        );
+        assert_eq!(
+            multi_row_insert_sql("shows", &["name"], 1, ConflictPolicy::Error),
+            "INSERT INTO shows (name) VALUES (?)"
+        );
+    }
+
+    #[test]
+    fn test_batch_insert_transcripts_spans_chunks() {
+        let mut db = DbHandler::new(":memory:").unwrap();
+        db.create_tables().unwrap();
+        let show_ids = db
+            .batch_insert_shows(&[NewShow {
+                name: "Show Name".to_string(),
+                show_type: "Anime".to_string(),
+            }])
+            .unwrap();
+        let episode_ids = db
+            .batch_insert_episodes(&[NewEpisode {
+                show_id: show_ids[0],
+                name: "Episode 1".to_string(),
+                season: 1,
+                episode_number: Some(1),
+            }])
+            .unwrap();
+        let transcripts: Vec<NewTranscript> = (0..INSERT_CHUNK_SIZE as i32 + 3)
+            .map(|i| NewTranscript {
+                episode_id: episode_ids[0],
+                line_id: i + 1,
+                time_start: format!("00:00:{:02},{:03}", i / 1000, i % 1000),
+                time_end: format!("00:01:{:02},{:03}", i / 1000, i % 1000),
+                sub_index: 0,
+                text: format!("line {}", i),
+                text_raw: format!("line {}", i),
+                speaker: None,
+            })
+            .collect();
+        db.batch_insert_transcripts(&transcripts, None).unwrap();
+        let count: i64 = db
+            .conn
+            .query_row("SELECT COUNT(*) FROM transcripts", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, transcripts.len() as i64);
+    }
+
+    #[test]
+    fn test_line_conflict_policies() {
+        let (mut db, ids) = test_utils::test_db_with_lines(&["古い"]);
+        let old = db.get_transcript(ids[0]).unwrap().unwrap();
+        let colliding = NewTranscript {
+            episode_id: old.episode_id,
+            line_id: 9,
+            time_start: old.time_start.clone(),
+            time_end: old.time_end.clone(),
+            sub_index: 0,
+            text: "新しい".to_string(),
+            text_raw: "新しい".to_string(),
+            speaker: None,
+        };
+        let lines = [colliding.clone(), colliding];
+        let texts = |db: &DbHandler| -> Vec<String> {
+            let lines = db.get_episode_lines(old.episode_id).unwrap();
+            lines.into_iter().map(|line| line.text).collect()
+        };
+
+        let inserted = db.batch_insert_transcripts(&lines, None).unwrap();
+        assert_eq!((inserted.ids.len(), inserted.conflicts), (0, 2));
+        assert_eq!(texts(&db), ["古い"]);
+
+        db.set_conflict_policies("error".parse().unwrap());
+        assert!(db.batch_insert_transcripts(&lines, None).is_err());
+
+        db.set_conflict_policies("replace".parse().unwrap());
+        let inserted = db.batch_insert_transcripts(&lines, None).unwrap();
+        assert_eq!((inserted.ids, inserted.conflicts), (vec![ids[0]], 2));
+        assert_eq!(texts(&db), ["新しい"]);
+
+        db.set_conflict_policies("rename".parse().unwrap());
+        let inserted = db.batch_insert_transcripts(&lines, None).unwrap();
+        assert_eq!((inserted.ids.len(), inserted.conflicts), (2, 2));
+        let ends: Vec<String> = db
+            .get_episode_lines(old.episode_id)
+            .unwrap()
+            .into_iter()
+            .map(|line| line.time_end)
+            .collect();
+        assert_eq!(ends.len(), 3);
+        assert!(ends.contains(&"00:00:00,501".to_string()));
+        assert!(ends.contains(&"00:00:00,502".to_string()));
+    }
+
+    #[test]
+    fn test_show_and_episode_conflict_policies() {
+        let (mut db, _) = test_utils::test_db_with_lines(&["猫"]);
+        let show = NewShow {
+            name: "Show Name".to_string(),
+            show_type: "Drama".to_string(),
+        };
+        let existing = db.find_show_id("Show Name").unwrap().unwrap();
+
+        db.set_conflict_policies("shows=rename,episodes=rename".parse().unwrap());
+        let ids = db
+            .batch_insert_shows(&[show.clone(), show.clone()])
+            .unwrap();
+        assert_eq!(ids[0], ids[1]);
+        assert_ne!(ids[0], existing);
+        assert_eq!(db.find_show_id("Show Name (2)").unwrap(), Some(ids[0]));
+        let episode = NewEpisode {
+            show_id: existing,
+            name: "Again".to_string(),
+            season: 1,
+            episode_number: Some(1),
+        };
+        let episode_ids = db.batch_insert_episodes(&[episode.clone()]).unwrap();
+        let episodes = db.list_episodes(existing).unwrap();
+        let renamed = episodes.iter().find(|e| e.id == episode_ids[0]).unwrap();
+        assert_eq!(renamed.episode_number, Some(2));
+
+        db.set_conflict_policies("shows=replace".parse().unwrap());
+        assert_eq!(db.batch_insert_shows(&[show.clone()]).unwrap(), [existing]);
+        let shows = db.list_shows().unwrap();
+        let replaced = shows.iter().find(|s| s.id == existing).unwrap();
+        assert_eq!(replaced.show_type, "Drama");
+
+        db.set_conflict_policies("shows=error".parse().unwrap());
+        assert!(db.batch_insert_shows(&[show]).is_err());
+    }
+
+    #[test]
+    fn test_movie_episodes() {
+        let (mut db, _) = test_utils::test_db_with_lines(&["猫"]);
+        let show_id = db.find_show_id("Show Name").unwrap().unwrap();
+        let movie = NewEpisode {
+            show_id,
+            name: "The Movie".to_string(),
+            season: 1,
+            episode_number: None,
+        };
+        let ids = db
+            .batch_insert_episodes(&[movie.clone(), movie.clone()])
+            .unwrap();
+        assert_eq!(ids[0], ids[1]);
+        assert_eq!(db.insert_episode(&movie).unwrap(), ids[0]);
+
+        db.set_conflict_policies("episodes=rename".parse().unwrap());
+        assert_eq!(db.batch_insert_episodes(&[movie.clone()]).unwrap(), ids);
+        db.set_conflict_policies("episodes=replace".parse().unwrap());
+        let renamed = NewEpisode {
+            name: "The Movie (Director's Cut)".to_string(),
+            ..movie.clone()
+        };
+        assert_eq!(db.batch_insert_episodes(&[renamed]).unwrap(), ids);
+        let episodes = db.list_episodes(show_id).unwrap();
+        assert_eq!(episodes.len(), 2);
+        let stored = episodes.iter().find(|e| e.id == ids[0]).unwrap();
+        assert_eq!(stored.episode_number, None);
+        assert_eq!(stored.name, "The Movie (Director's Cut)");
+
+        db.set_conflict_policies("episodes=error".parse().unwrap());
+        assert!(db.batch_insert_episodes(&[movie]).is_err());
+    }
+
+    #[test]
+    fn test_csv_output_quotes_fields() {
+        let mut db = DbHandler::new(":memory:").unwrap();
+        db.create_tables().unwrap();
+        let show_ids = db
+            .batch_insert_shows(&[NewShow {
+                name: "Show, Name".to_string(),
+                show_type: "Anime".to_string(),
+            }])
+            .unwrap();
+        let episode_ids = db
+            .batch_insert_episodes(&[NewEpisode {
+                show_id: show_ids[0],
+                name: "Episode 1".to_string(),
+                season: 1,
+                episode_number: Some(1),
+            }])
+            .unwrap();
+        let path = std::env::temp_dir().join("anime_search_test_csv_output.csv");
+        let output = CsvOutput::new(&path).with_columns(vec![
+            CsvColumn::ShowName,
+            CsvColumn::EpisodeNumber,
+            CsvColumn::Text,
+            CsvColumn::TextRaw,
+        ]);
+        db.batch_insert_transcripts(
+            &[NewTranscript {
+                episode_id: episode_ids[0],
+                line_id: 1,
+                time_start: "00:00:01,000".to_string(),
+                time_end: "00:00:02,000".to_string(),
+                sub_index: 0,
+                text: "え、\"本当\"？\nうん".to_string(),
+                text_raw: "え、\"本当\"？\nうん".to_string(),
+                speaker: None,
+            }],
+            Some(&output),
+        )
+        .unwrap();
+        // Appending adds rows without repeating the header
+        db.batch_insert_transcripts(
+            &[NewTranscript {
+                episode_id: episode_ids[0],
+                line_id: 2,
+                time_start: "00:00:03,000".to_string(),
+                time_end: "00:00:04,000".to_string(),
+                sub_index: 0,
+                text: "次".to_string(),
+                text_raw: "（太郎）次".to_string(),
+                speaker: None,
+            }],
+            Some(&output.with_append(true)),
+        )
+        .unwrap();
+        let written = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            written,
+            "show_name,episode_number,text,text_raw\n\
+             \"Show, Name\",1,\"え、\"\"本当\"\"？\nうん\",\"え、\"\"本当\"\"？\nうん\"\n\
+             \"Show, Name\",1,次,（太郎）次\n"
+        );
+    }
 
-        tx.commit()
+    #[test]
+    fn test_find_context() {
+        let (db, ids) = test_utils::test_db_with_lines(&["一", "二", "三", "四"]);
+        let context = db.find_context(ids[1], 1, 5).unwrap();
+        let texts: Vec<&str> = context.iter().map(|hit| hit.text.as_str()).collect();
+        assert_eq!(texts, ["一", "二", "三", "四"]);
+        assert_eq!(db.find_context(ids[0], 0, 0).unwrap().len(), 1);
     }
 }
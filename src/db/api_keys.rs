@@ -0,0 +1,296 @@
+use super::DbHandler;
+use rand::Rng;
+use rusqlite::{params, OptionalExtension, Result, Row};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// What an API key may do. Keys that may ingest may also read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ApiScope {
+    /// Search and browse
+    Read,
+    /// Also add subtitles to the corpus
+    Ingest,
+}
+
+impl ApiScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ApiScope::Read => "read",
+            ApiScope::Ingest => "ingest",
+        }
+    }
+
+    /// Whether a key with this scope may do what `required` covers.
+    pub fn allows(&self, required: ApiScope) -> bool {
+        *self >= required
+    }
+}
+
+impl fmt::Display for ApiScope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ApiScope {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "read" => Ok(ApiScope::Read),
+            "ingest" => Ok(ApiScope::Ingest),
+            _ => Err(format!("Unknown scope {:?} (expected read or ingest)", s)),
+        }
+    }
+}
+
+/// An API key for the server, as stored in the `api_keys` table.
+/// The secret itself is only stored as a hash.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiKey {
+    pub id: i64,
+    /// Who the key was issued to
+    pub name: String,
+    pub scope: ApiScope,
+    /// Requests allowed per minute
+    pub rate_limit: u32,
+}
+
+impl ApiKey {
+    fn from_row(row: &Row) -> Result<Self> {
+        let scope: String = row.get(2)?;
+        Ok(ApiKey {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            scope: scope.parse().map_err(|e: String| {
+                rusqlite::Error::FromSqlConversionFailure(2, rusqlite::types::Type::Text, e.into())
+            })?,
+            rate_limit: row.get(3)?,
+        })
+    }
+}
+
+/// Why a request with a known key was refused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyRejection {
+    /// The request needs more than the key's scope allows
+    Scope { scope: ApiScope, required: ApiScope },
+    /// The key has no requests left; another is available after this long
+    RateLimited(Duration),
+}
+
+impl fmt::Display for KeyRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyRejection::Scope { scope, required } => {
+                write!(
+                    f,
+                    "This key's scope is {}, but {} is required",
+                    scope, required
+                )
+            }
+            KeyRejection::RateLimited(_) => f.write_str("Rate limit exceeded"),
+        }
+    }
+}
+
+/// Tracks how many requests each key has left.
+///
+/// Each key gets a bucket of `rate_limit` requests that refills continuously
+/// over a minute, so short bursts are allowed but the average rate is capped.
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    buckets: Mutex<HashMap<i64, Bucket>>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl RateLimiter {
+    pub fn new() -> Self {
+        RateLimiter::default()
+    }
+
+    /// Takes one request from the key's bucket, or returns how long until one is available.
+    pub fn acquire(&self, key_id: i64, per_minute: u32, now: Instant) -> Result<(), Duration> {
+        let capacity = per_minute as f64;
+        let per_second = capacity / 60.0;
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        let bucket = buckets.entry(key_id).or_insert(Bucket {
+            tokens: capacity,
+            updated: now,
+        });
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * per_second).min(capacity);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else if per_second == 0.0 {
+            Err(Duration::MAX)
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / per_second))
+        }
+    }
+
+    /// Lets a request with `key` through if the key's scope allows `required`,
+    /// taking one request from its bucket.
+    pub fn admit(
+        &self,
+        key: &ApiKey,
+        required: ApiScope,
+        now: Instant,
+    ) -> std::result::Result<(), KeyRejection> {
+        if !key.scope.allows(required) {
+            return Err(KeyRejection::Scope {
+                scope: key.scope,
+                required,
+            });
+        }
+        self.acquire(key.id, key.rate_limit, now)
+            .map_err(KeyRejection::RateLimited)
+    }
+}
+
+impl DbHandler {
+    // Issues a new key, returning it with its secret; only the hash of the secret is stored
+    pub fn create_api_key(
+        &self,
+        name: &str,
+        scope: ApiScope,
+        rate_limit: u32,
+    ) -> Result<(ApiKey, String)> {
+        let secret = hex(&rand::thread_rng().gen::<[u8; 24]>());
+        self.conn.execute(
+            "INSERT INTO api_keys (name, key_hash, scope, rate_limit) VALUES (?, ?, ?, ?)",
+            params![name, hash_secret(&secret), scope.as_str(), rate_limit],
+        )?;
+        let key = ApiKey {
+            id: self.conn.last_insert_rowid(),
+            name: name.to_string(),
+            scope,
+            rate_limit,
+        };
+        Ok((key, secret))
+    }
+
+    // The key with this secret, if it exists
+    pub fn find_api_key(&self, secret: &str) -> Result<Option<ApiKey>> {
+        self.conn
+            .prepare_cached("SELECT id, name, scope, rate_limit FROM api_keys WHERE key_hash = ?")?
+            .query_row(params![hash_secret(secret)], ApiKey::from_row)
+            .optional()
+    }
+
+    // Every key, by name
+    pub fn api_keys(&self) -> Result<Vec<ApiKey>> {
+        let mut stmt = self
+            .conn
+            .prepare_cached("SELECT id, name, scope, rate_limit FROM api_keys ORDER BY name")?;
+        let keys = stmt.query_map([], ApiKey::from_row)?.collect();
+        keys
+    }
+
+    // Deletes the key issued to `name`; returns whether there was one
+    pub fn revoke_api_key(&self, name: &str) -> Result<bool> {
+        let deleted = self
+            .conn
+            .execute("DELETE FROM api_keys WHERE name = ?", params![name])?;
+        Ok(deleted > 0)
+    }
+}
+
+fn hash_secret(secret: &str) -> String {
+    hex(&Sha256::digest(secret.as_bytes()))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_utils::test_db_with_lines;
+    use super::*;
+
+    #[test]
+    fn test_api_keys() {
+        let (db, _) = test_db_with_lines(&[]);
+        let (key, secret) = db
+            .create_api_key("study group", ApiScope::Read, 30)
+            .unwrap();
+        assert_eq!(db.find_api_key(&secret).unwrap(), Some(key.clone()));
+        assert_eq!(db.find_api_key("not a key").unwrap(), None);
+        assert_eq!(db.api_keys().unwrap(), [key]);
+
+        // Names are unique
+        assert!(db
+            .create_api_key("study group", ApiScope::Ingest, 30)
+            .is_err());
+
+        assert!(db.revoke_api_key("study group").unwrap());
+        assert!(!db.revoke_api_key("study group").unwrap());
+        assert_eq!(db.find_api_key(&secret).unwrap(), None);
+    }
+
+    #[test]
+    fn test_scopes() {
+        assert!(ApiScope::Ingest.allows(ApiScope::Read));
+        assert!(!ApiScope::Read.allows(ApiScope::Ingest));
+        assert_eq!("ingest".parse(), Ok(ApiScope::Ingest));
+        assert!("admin".parse::<ApiScope>().is_err());
+    }
+
+    #[test]
+    fn test_rate_limiter_refills() {
+        let limiter = RateLimiter::new();
+        let start = Instant::now();
+        assert!(limiter.acquire(1, 2, start).is_ok());
+        assert!(limiter.acquire(1, 2, start).is_ok());
+        let wait = limiter.acquire(1, 2, start).unwrap_err();
+        assert_eq!(wait.as_secs(), 30);
+
+        // Other keys have their own bucket
+        assert!(limiter.acquire(2, 2, start).is_ok());
+
+        assert!(limiter
+            .acquire(1, 2, start + Duration::from_secs(30))
+            .is_ok());
+        assert!(limiter
+            .acquire(1, 2, start + Duration::from_secs(30))
+            .is_err());
+    }
+
+    #[test]
+    fn test_admit_checks_scope() {
+        let limiter = RateLimiter::new();
+        let key = ApiKey {
+            id: 1,
+            name: "reader".to_string(),
+            scope: ApiScope::Read,
+            rate_limit: 1,
+        };
+        let now = Instant::now();
+        assert_eq!(
+            limiter.admit(&key, ApiScope::Ingest, now),
+            Err(KeyRejection::Scope {
+                scope: ApiScope::Read,
+                required: ApiScope::Ingest
+            })
+        );
+        // A refused request doesn't use up the key's bucket
+        assert_eq!(limiter.admit(&key, ApiScope::Read, now), Ok(()));
+        assert!(matches!(
+            limiter.admit(&key, ApiScope::Read, now),
+            Err(KeyRejection::RateLimited(_))
+        ));
+    }
+}
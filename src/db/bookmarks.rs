@@ -0,0 +1,208 @@
+use super::{DbHandler, SearchHit, TranscriptId};
+use rusqlite::{params, OptionalExtension, Result, Row};
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// A line a user bookmarked, as recorded in the `bookmarks` table.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Bookmark {
+    pub hit: SearchHit,
+    pub note: Option<String>,
+    pub tag: Option<String>,
+    pub created_at: String,
+}
+
+/// A query a user saved under a name, as recorded in the `saved_searches` table.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SavedSearch {
+    pub name: String,
+    pub query: String,
+    /// How the query is run: `words`, `regex`, `kanji` or `grammar`
+    pub kind: String,
+    pub created_at: String,
+}
+
+impl SavedSearch {
+    fn from_row(row: &Row) -> Result<Self> {
+        Ok(SavedSearch {
+            name: row.get(0)?,
+            query: row.get(1)?,
+            kind: row.get(2)?,
+            created_at: row.get(3)?,
+        })
+    }
+}
+
+impl DbHandler {
+    // Bookmarks a line for `user`, replacing the note and tag if it already was
+    // Returns whether the line wasn't bookmarked before
+    pub fn add_bookmark(
+        &self,
+        user: &str,
+        transcript_id: TranscriptId,
+        note: Option<&str>,
+        tag: Option<&str>,
+    ) -> Result<bool> {
+        let existed: bool = self
+            .conn
+            .prepare_cached(
+                "SELECT EXISTS(SELECT 1 FROM bookmarks WHERE user = ? AND transcript_id = ?)",
+            )?
+            .query_row(params![user, transcript_id], |row| row.get(0))?;
+        self.conn
+            .prepare_cached(
+                "INSERT INTO bookmarks (user, transcript_id, note, tag, created_at)
+                 VALUES (?, ?, ?, ?, CURRENT_TIMESTAMP)
+                 ON CONFLICT(user, transcript_id) DO UPDATE SET
+                     note = excluded.note, tag = excluded.tag",
+            )?
+            .execute(params![user, transcript_id, note, tag])?;
+        Ok(!existed)
+    }
+
+    // Removes a bookmark; returns whether the line was bookmarked
+    pub fn remove_bookmark(&self, user: &str, transcript_id: TranscriptId) -> Result<bool> {
+        let removed = self.conn.execute(
+            "DELETE FROM bookmarks WHERE user = ? AND transcript_id = ?",
+            params![user, transcript_id],
+        )?;
+        Ok(removed > 0)
+    }
+
+    // The lines `user` bookmarked, optionally only those with `tag`, oldest first
+    pub fn bookmarks(&self, user: &str, tag: Option<&str>) -> Result<Vec<Bookmark>> {
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT transcript_id, note, tag, created_at FROM bookmarks
+             WHERE user = ?1 AND (?2 IS NULL OR tag = ?2)
+             ORDER BY id",
+        )?;
+        let rows = stmt
+            .query_map(params![user, tag], |row| {
+                Ok((
+                    row.get::<_, TranscriptId>(0)?,
+                    row.get::<_, Option<String>>(1)?,
+                    row.get::<_, Option<String>>(2)?,
+                    row.get::<_, String>(3)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>>>()?;
+        let ids: Vec<TranscriptId> = rows.iter().map(|(id, ..)| *id).collect();
+        let mut hits: HashMap<TranscriptId, SearchHit> = self
+            .find_hits_by_ids(&ids)?
+            .into_iter()
+            .map(|hit| (hit.transcript_id, hit))
+            .collect();
+        Ok(rows
+            .into_iter()
+            .filter_map(|(id, note, tag, created_at)| {
+                Some(Bookmark {
+                    hit: hits.remove(&id)?,
+                    note,
+                    tag,
+                    created_at,
+                })
+            })
+            .collect())
+    }
+
+    // Saves a query under `name` for `user`, replacing any saved under the same name
+    pub fn save_search(&self, user: &str, name: &str, query: &str, kind: &str) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO saved_searches (user, name, query, kind, created_at)
+             VALUES (?, ?, ?, ?, CURRENT_TIMESTAMP)
+             ON CONFLICT(user, name) DO UPDATE SET
+                 query = excluded.query, kind = excluded.kind, created_at = excluded.created_at",
+            params![user, name, query, kind],
+        )?;
+        Ok(())
+    }
+
+    // The query `user` saved under `name`, if any
+    pub fn saved_search(&self, user: &str, name: &str) -> Result<Option<SavedSearch>> {
+        self.conn
+            .prepare_cached(
+                "SELECT name, query, kind, created_at FROM saved_searches
+                 WHERE user = ? AND name = ?",
+            )?
+            .query_row(params![user, name], SavedSearch::from_row)
+            .optional()
+    }
+
+    // The queries `user` saved, by name
+    pub fn saved_searches(&self, user: &str) -> Result<Vec<SavedSearch>> {
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT name, query, kind, created_at FROM saved_searches
+             WHERE user = ? ORDER BY name",
+        )?;
+        let searches = stmt
+            .query_map(params![user], SavedSearch::from_row)?
+            .collect();
+        searches
+    }
+
+    // Deletes a saved query; returns whether there was one
+    pub fn delete_saved_search(&self, user: &str, name: &str) -> Result<bool> {
+        let deleted = self.conn.execute(
+            "DELETE FROM saved_searches WHERE user = ? AND name = ?",
+            params![user, name],
+        )?;
+        Ok(deleted > 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_utils::test_db_with_lines;
+
+    #[test]
+    fn test_bookmarks() {
+        let (db, ids) = test_db_with_lines(&["猫が好き", "犬が好き", "走る"]);
+        assert!(db.add_bookmark("a", ids[1], Some("nice"), None).unwrap());
+        assert!(db.add_bookmark("a", ids[0], None, Some("cats")).unwrap());
+        assert!(db.add_bookmark("b", ids[2], None, None).unwrap());
+        // Bookmarking again updates the note and tag
+        assert!(!db
+            .add_bookmark("a", ids[1], Some("great"), Some("dogs"))
+            .unwrap());
+
+        let bookmarks = db.bookmarks("a", None).unwrap();
+        let texts: Vec<&str> = bookmarks.iter().map(|b| b.hit.text.as_str()).collect();
+        assert_eq!(texts, ["犬が好き", "猫が好き"]);
+        assert_eq!(bookmarks[0].note.as_deref(), Some("great"));
+        assert_eq!(bookmarks[0].tag.as_deref(), Some("dogs"));
+
+        let cats = db.bookmarks("a", Some("cats")).unwrap();
+        assert_eq!(cats.len(), 1);
+        assert_eq!(cats[0].hit.transcript_id, ids[0]);
+
+        assert!(db.remove_bookmark("a", ids[0]).unwrap());
+        assert!(!db.remove_bookmark("a", ids[0]).unwrap());
+        assert_eq!(db.bookmarks("a", None).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_saved_searches() {
+        let (db, _) = test_db_with_lines(&[]);
+        db.save_search("a", "cats", "猫", "words").unwrap();
+        db.save_search("a", "te-mo", "て(も|は)いい", "regex")
+            .unwrap();
+        db.save_search("a", "cats", "猫 好き", "words").unwrap();
+        db.save_search("b", "dogs", "犬", "words").unwrap();
+
+        let names: Vec<String> = db
+            .saved_searches("a")
+            .unwrap()
+            .into_iter()
+            .map(|search| search.name)
+            .collect();
+        assert_eq!(names, ["cats", "te-mo"]);
+        assert_eq!(
+            db.saved_search("a", "cats").unwrap().unwrap().query,
+            "猫 好き"
+        );
+        assert_eq!(db.saved_search("b", "cats").unwrap(), None);
+
+        assert!(db.delete_saved_search("a", "cats").unwrap());
+        assert!(!db.delete_saved_search("a", "cats").unwrap());
+    }
+}
@@ -0,0 +1,144 @@
+use super::{DbHandler, EpisodeId, TranscriptId};
+use rusqlite::{Result, Row};
+
+/// A row whose foreign key points at a missing parent row.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ForeignKeyViolation {
+    pub table: String,
+    /// None for WITHOUT ROWID tables
+    pub rowid: Option<i64>,
+    pub parent: String,
+}
+
+/// Problems found by [`DbHandler::check_integrity`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IntegrityReport {
+    pub foreign_key_violations: Vec<ForeignKeyViolation>,
+    pub empty_episodes: Vec<EpisodeId>,
+    pub empty_lines: Vec<TranscriptId>,
+    /// Episodes with the same line_id more than once, with the duplicated line_id
+    pub duplicate_line_ids: Vec<(EpisodeId, i32)>,
+    /// Lines whose end timestamp comes before their start
+    pub reversed_timestamps: Vec<TranscriptId>,
+    /// Word or kanji index entries pointing at a missing line or word
+    pub orphaned_index_entries: usize,
+}
+
+impl IntegrityReport {
+    pub fn is_clean(&self) -> bool {
+        *self == IntegrityReport::default()
+    }
+}
+
+impl DbHandler {
+    // Looks for inconsistent data: broken foreign keys, episodes without lines,
+    // empty lines, duplicated line numbers, reversed timestamps and orphaned index entries
+    pub fn check_integrity(&self) -> Result<IntegrityReport> {
+        Ok(IntegrityReport {
+            foreign_key_violations: self.collect("PRAGMA foreign_key_check", |row| {
+                Ok(ForeignKeyViolation {
+                    table: row.get(0)?,
+                    rowid: row.get(1)?,
+                    parent: row.get(2)?,
+                })
+            })?,
+            empty_episodes: self.collect(
+                "SELECT id FROM episodes WHERE NOT EXISTS
+                 (SELECT 1 FROM transcripts WHERE transcripts.episode_id = episodes.id)
+                 ORDER BY id",
+                |row| row.get(0),
+            )?,
+            empty_lines: self.collect(
+                "SELECT id FROM transcripts WHERE trim(text) = '' ORDER BY id",
+                |row| row.get(0),
+            )?,
+            duplicate_line_ids: self.collect(
+                "SELECT episode_id, line_id FROM transcripts
+                 GROUP BY episode_id, line_id HAVING COUNT(*) > 1
+                 ORDER BY episode_id, line_id",
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )?,
+            // Timestamps are fixed-width HH:MM:SS,mmm strings, so they compare as text
+            reversed_timestamps: self.collect(
+                "SELECT id FROM transcripts WHERE time_end < time_start ORDER BY id",
+                |row| row.get(0),
+            )?,
+            orphaned_index_entries: self.conn.query_row(
+                "SELECT
+                   (SELECT COUNT(*) FROM word_occurrences
+                    WHERE transcript_id NOT IN (SELECT id FROM transcripts)
+                       OR word_id NOT IN (SELECT id FROM words))
+                 + (SELECT COUNT(*) FROM kanji_occurrences
+                    WHERE transcript_id NOT IN (SELECT id FROM transcripts))",
+                [],
+                |row| row.get(0),
+            )?,
+        })
+    }
+
+    // Deletes word and kanji index entries that point at missing lines or words
+    pub fn delete_orphaned_index_entries(&mut self) -> Result<usize> {
+        let tx = self.conn.savepoint()?;
+        let deleted = tx.execute(
+            "DELETE FROM word_occurrences
+             WHERE transcript_id NOT IN (SELECT id FROM transcripts)
+                OR word_id NOT IN (SELECT id FROM words)",
+            [],
+        )? + tx.execute(
+            "DELETE FROM kanji_occurrences
+             WHERE transcript_id NOT IN (SELECT id FROM transcripts)",
+            [],
+        )?;
+        super::word_index::recount_word_frequencies(&tx)?;
+        tx.commit()?;
+        Ok(deleted)
+    }
+
+    fn collect<T>(&self, sql: &str, f: impl FnMut(&Row) -> Result<T>) -> Result<Vec<T>> {
+        let mut stmt = self.conn.prepare(sql)?;
+        let rows = stmt.query_map([], f)?.collect();
+        rows
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_utils::test_db_with_lines;
+    use super::*;
+
+    #[test]
+    fn test_check_integrity() {
+        let (mut db, ids) = test_db_with_lines(&["猫", " ", "犬"]);
+        assert_eq!(db.check_integrity().unwrap().empty_lines, [ids[1]]);
+
+        db.conn
+            .execute_batch(
+                // Broken references can only come from databases written without enforcement
+                "PRAGMA foreign_keys = OFF;
+                 INSERT INTO episodes (show_id, name, season, episode_number)
+                 VALUES (1, 'Empty', 1, 2);
+                 INSERT INTO episodes (show_id, name, season, episode_number)
+                 VALUES (99, 'Orphan', 1, 1);
+                 INSERT INTO transcripts (episode_id, line_id, time_start, time_end, text)
+                 VALUES (1, 1, '00:01:00,000', '00:00:59,000', 'duplicate');
+                 INSERT INTO kanji_occurrences (kanji, transcript_id) VALUES ('猫', 999);",
+            )
+            .unwrap();
+        let report = db.check_integrity().unwrap();
+        assert!(!report.is_clean());
+        let mut tables: Vec<&str> = report
+            .foreign_key_violations
+            .iter()
+            .map(|violation| violation.table.as_str())
+            .collect();
+        tables.sort();
+        assert_eq!(tables, ["episodes", "kanji_occurrences"]);
+        assert_eq!(report.empty_episodes, [EpisodeId(2), EpisodeId(3)]);
+        assert_eq!(report.duplicate_line_ids, [(EpisodeId(1), 1)]);
+        assert_eq!(report.reversed_timestamps.len(), 1);
+        assert_eq!(report.orphaned_index_entries, 1);
+
+        assert_eq!(db.delete_orphaned_index_entries().unwrap(), 1);
+        assert_eq!(db.check_integrity().unwrap().orphaned_index_entries, 0);
+    }
+}
@@ -0,0 +1,191 @@
+use super::DbHandler;
+use std::fmt;
+use std::str::FromStr;
+
+/// What inserting a row does when its unique key is already taken.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    /// Keep the existing row and drop the new one
+    #[default]
+    Ignore,
+    /// Give the existing row the new one's values, keeping its id
+    Replace,
+    /// Fail the insert, and with it the ingestion run
+    Error,
+    /// Keep both, changing the new row's key until it's free: shows get a
+    /// numbered name, episodes the next free number in their season, and lines
+    /// an end time a millisecond later. Movies have no number to change, and
+    /// are ignored instead
+    Rename,
+}
+
+impl ConflictPolicy {
+    // The start of an INSERT statement under this policy; Replace adds an upsert clause
+    // (see `upsert_clause`), and Rename picks free keys before inserting
+    pub(super) fn insert_verb(self) -> &'static str {
+        match self {
+            ConflictPolicy::Ignore | ConflictPolicy::Rename => "INSERT OR IGNORE",
+            ConflictPolicy::Replace | ConflictPolicy::Error => "INSERT",
+        }
+    }
+
+    // The upsert clause giving rows that collide on `key` the new `columns` values
+    pub(super) fn upsert_clause(self, key: &[&str], columns: &[&str]) -> String {
+        self.upsert(&format!("({})", key.join(", ")), key, columns)
+    }
+
+    // Like `upsert_clause`, for rows that collide on `key` in a partial unique index,
+    // which only covers the rows where `condition` holds
+    pub(super) fn partial_upsert_clause(
+        self,
+        key: &[&str],
+        condition: &str,
+        columns: &[&str],
+    ) -> String {
+        self.upsert(
+            &format!("({}) WHERE {}", key.join(", "), condition),
+            key,
+            columns,
+        )
+    }
+
+    fn upsert(self, target: &str, key: &[&str], columns: &[&str]) -> String {
+        if self != ConflictPolicy::Replace {
+            return String::new();
+        }
+        let updates: Vec<String> = columns
+            .iter()
+            .filter(|column| !key.contains(column))
+            .map(|column| format!("{0} = excluded.{0}", column))
+            .collect();
+        format!(
+            " ON CONFLICT{} DO UPDATE SET {}",
+            target,
+            updates.join(", ")
+        )
+    }
+}
+
+impl fmt::Display for ConflictPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ConflictPolicy::Ignore => "ignore",
+            ConflictPolicy::Replace => "replace",
+            ConflictPolicy::Error => "error",
+            ConflictPolicy::Rename => "rename",
+        })
+    }
+}
+
+impl FromStr for ConflictPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ignore" => Ok(ConflictPolicy::Ignore),
+            "replace" => Ok(ConflictPolicy::Replace),
+            "error" => Ok(ConflictPolicy::Error),
+            "rename" => Ok(ConflictPolicy::Rename),
+            _ => Err(format!(
+                "unknown conflict policy {:?} (expected ignore, replace, error or rename)",
+                s
+            )),
+        }
+    }
+}
+
+/// The [`ConflictPolicy`] of each table rows are ingested into.
+///
+/// Shows collide by name, episodes by show, season and number, and lines by
+/// episode, start and end time and sub-index. Rows for the same show or episode within
+/// one batch always refer to one show or episode; their policies only decide
+/// about those that already existed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ConflictPolicies {
+    pub shows: ConflictPolicy,
+    pub episodes: ConflictPolicy,
+    pub lines: ConflictPolicy,
+}
+
+// Comma-separated `table=policy` pairs, e.g. `lines=rename,shows=error`;
+// a bare policy is for lines
+impl FromStr for ConflictPolicies {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut policies = ConflictPolicies::default();
+        for part in s.split(',').map(str::trim).filter(|part| !part.is_empty()) {
+            let (table, policy) = part.split_once('=').unwrap_or(("lines", part));
+            let policy = policy.trim().parse()?;
+            match table.trim() {
+                "shows" => policies.shows = policy,
+                "episodes" => policies.episodes = policy,
+                "lines" => policies.lines = policy,
+                table => {
+                    return Err(format!(
+                        "unknown table {:?} (expected shows, episodes or lines)",
+                        table
+                    ))
+                }
+            }
+        }
+        Ok(policies)
+    }
+}
+
+impl DbHandler {
+    // The conflict policies the batch inserts follow
+    pub fn conflict_policies(&self) -> ConflictPolicies {
+        self.conflicts
+    }
+
+    pub fn set_conflict_policies(&mut self, policies: ConflictPolicies) {
+        self.conflicts = policies;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_policies() {
+        assert_eq!(
+            "rename".parse::<ConflictPolicies>(),
+            Ok(ConflictPolicies {
+                lines: ConflictPolicy::Rename,
+                ..ConflictPolicies::default()
+            })
+        );
+        assert_eq!(
+            "shows=error, episodes=replace".parse::<ConflictPolicies>(),
+            Ok(ConflictPolicies {
+                shows: ConflictPolicy::Error,
+                episodes: ConflictPolicy::Replace,
+                lines: ConflictPolicy::Ignore,
+            })
+        );
+        assert!("words=ignore".parse::<ConflictPolicies>().is_err());
+        assert!("lines=merge".parse::<ConflictPolicies>().is_err());
+    }
+
+    #[test]
+    fn test_upsert_clause() {
+        assert_eq!(
+            ConflictPolicy::Replace.upsert_clause(&["name"], &["name", "show_type"]),
+            " ON CONFLICT(name) DO UPDATE SET show_type = excluded.show_type"
+        );
+        assert_eq!(
+            ConflictPolicy::Ignore.upsert_clause(&["name"], &["name", "show_type"]),
+            ""
+        );
+        assert_eq!(
+            ConflictPolicy::Replace.partial_upsert_clause(
+                &["show_id", "season"],
+                "episode_number IS NULL",
+                &["show_id", "name", "season"]
+            ),
+            " ON CONFLICT(show_id, season) WHERE episode_number IS NULL DO UPDATE SET name = excluded.name"
+        );
+    }
+}
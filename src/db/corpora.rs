@@ -0,0 +1,125 @@
+use super::{DbHandler, SearchHit, ShowId, TranscriptId};
+use rusqlite::{params, Result};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+// The corpus of shows ingested without one
+pub const DEFAULT_CORPUS: &str = "default";
+
+/// A corpus, e.g. `anime`, `drama` or `podcasts`, and how many shows are in it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CorpusSummary {
+    pub corpus: String,
+    pub shows: i64,
+}
+
+impl DbHandler {
+    // Moves shows into `corpus`; returns how many weren't in it already
+    pub fn set_show_corpus(&mut self, corpus: &str, show_ids: &[ShowId]) -> Result<usize> {
+        let tx = self.conn.savepoint()?;
+        let mut moved = 0;
+        {
+            let mut stmt =
+                tx.prepare_cached("UPDATE shows SET corpus = ?1 WHERE id = ?2 AND corpus <> ?1")?;
+            for show_id in show_ids {
+                moved += stmt.execute(params![corpus, show_id])?;
+            }
+        }
+        tx.commit()?;
+        Ok(moved)
+    }
+
+    // Every corpus with its number of shows, by name
+    pub fn corpora(&self) -> Result<Vec<CorpusSummary>> {
+        let mut stmt = self
+            .conn
+            .prepare_cached("SELECT corpus, COUNT(*) FROM shows GROUP BY corpus ORDER BY corpus")?;
+        let corpora = stmt
+            .query_map([], |row| {
+                Ok(CorpusSummary {
+                    corpus: row.get(0)?,
+                    shows: row.get(1)?,
+                })
+            })?
+            .collect();
+        corpora
+    }
+
+    // The given lines grouped by the corpus of their show, each group in input order
+    pub fn lines_by_corpus(
+        &self,
+        transcript_ids: &[TranscriptId],
+    ) -> Result<HashMap<String, Vec<TranscriptId>>> {
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT shows.corpus FROM transcripts
+             JOIN episodes ON episodes.id = transcripts.episode_id
+             JOIN shows ON shows.id = episodes.show_id
+             WHERE transcripts.id = ?",
+        )?;
+        let mut lines: HashMap<String, Vec<TranscriptId>> = HashMap::new();
+        for &transcript_id in transcript_ids {
+            let corpus: String = stmt.query_row(params![transcript_id], |row| row.get(0))?;
+            lines.entry(corpus).or_default().push(transcript_id);
+        }
+        Ok(lines)
+    }
+
+    // Drops the hits from shows outside `corpus`
+    pub fn retain_corpus(&self, corpus: &str, hits: &mut Vec<SearchHit>) -> Result<()> {
+        let in_corpus: HashSet<TranscriptId> = self
+            .conn
+            .prepare_cached(
+                "SELECT transcripts.id FROM transcripts
+                 JOIN episodes ON episodes.id = transcripts.episode_id
+                 JOIN shows ON shows.id = episodes.show_id
+                 WHERE shows.corpus = ?",
+            )?
+            .query_map(params![corpus], |row| row.get(0))?
+            .collect::<Result<_>>()?;
+        hits.retain(|hit| in_corpus.contains(&hit.transcript_id));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_utils::{insert_show_lines, test_db_with_lines};
+    use super::*;
+
+    #[test]
+    fn test_corpora() {
+        let (mut db, anime) = test_db_with_lines(&["猫が好き"]);
+        let drama = insert_show_lines(&mut db, "Drama Name", &["犬が好き"]);
+        let drama_show = db.find_show_id("Drama Name").unwrap().unwrap();
+        assert_eq!(db.set_show_corpus("drama", &[drama_show]).unwrap(), 1);
+        assert_eq!(db.set_show_corpus("drama", &[drama_show]).unwrap(), 0);
+
+        assert_eq!(
+            db.corpora().unwrap(),
+            [
+                CorpusSummary {
+                    corpus: DEFAULT_CORPUS.to_string(),
+                    shows: 1
+                },
+                CorpusSummary {
+                    corpus: "drama".to_string(),
+                    shows: 1
+                },
+            ]
+        );
+        let shows = db.list_shows().unwrap();
+        assert_eq!(shows[0].corpus, "drama");
+
+        let mut hits = db.find_lines_containing("好き").unwrap();
+        db.retain_corpus("drama", &mut hits).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].transcript_id, drama[0]);
+        let mut hits = db.find_lines_containing("好き").unwrap();
+        db.retain_corpus(DEFAULT_CORPUS, &mut hits).unwrap();
+        assert_eq!(hits[0].transcript_id, anime[0]);
+
+        let lines = db.lines_by_corpus(&[drama[0], anime[0]]).unwrap();
+        assert_eq!(lines["drama"], drama);
+        assert_eq!(lines[DEFAULT_CORPUS], anime);
+    }
+}
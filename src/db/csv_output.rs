@@ -0,0 +1,89 @@
+use std::path::PathBuf;
+
+/// A column that can be written to the transcript CSV side-output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CsvColumn {
+    TranscriptId,
+    ShowName,
+    EpisodeName,
+    Season,
+    EpisodeNumber,
+    LineId,
+    TimeStart,
+    TimeEnd,
+    Text,
+    /// The line as it appeared in the subtitle file
+    TextRaw,
+    /// The line's content-derived id (see [`super::stable_line_id`])
+    StableId,
+}
+
+impl CsvColumn {
+    pub fn header(&self) -> &'static str {
+        match self {
+            CsvColumn::TranscriptId => "id",
+            CsvColumn::ShowName => "show_name",
+            CsvColumn::EpisodeName => "episode_name",
+            CsvColumn::Season => "season",
+            CsvColumn::EpisodeNumber => "episode_number",
+            CsvColumn::LineId => "line_id",
+            CsvColumn::TimeStart => "time_start",
+            CsvColumn::TimeEnd => "time_end",
+            CsvColumn::Text => "text",
+            CsvColumn::TextRaw => "text_raw",
+            CsvColumn::StableId => "stable_id",
+        }
+    }
+
+    /// Whether this column requires looking up the episode and show of a line.
+    pub(crate) fn needs_episode_info(&self) -> bool {
+        matches!(
+            self,
+            CsvColumn::ShowName
+                | CsvColumn::EpisodeName
+                | CsvColumn::Season
+                | CsvColumn::EpisodeNumber
+        )
+    }
+}
+
+/// Where and what to write when exporting newly inserted transcripts as CSV.
+///
+/// Fields are quoted according to RFC 4180, so text containing commas,
+/// quotes, or newlines round-trips correctly.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CsvOutput {
+    pub path: PathBuf,
+    pub columns: Vec<CsvColumn>,
+    pub include_header: bool,
+    /// Add to an existing file instead of overwriting it (the header is only
+    /// written if the file is new or empty)
+    pub append: bool,
+}
+
+impl CsvOutput {
+    /// Creates an output writing the transcript id and text to `path`.
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        CsvOutput {
+            path: path.into(),
+            columns: vec![CsvColumn::TranscriptId, CsvColumn::Text],
+            include_header: true,
+            append: false,
+        }
+    }
+
+    pub fn with_columns(mut self, columns: Vec<CsvColumn>) -> Self {
+        self.columns = columns;
+        self
+    }
+
+    pub fn with_header(mut self, include_header: bool) -> Self {
+        self.include_header = include_header;
+        self
+    }
+
+    pub fn with_append(mut self, append: bool) -> Self {
+        self.append = append;
+        self
+    }
+}
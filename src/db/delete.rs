@@ -0,0 +1,158 @@
+use super::{DbHandler, EpisodeId, ShowId};
+use rusqlite::{params, OptionalExtension, Result};
+
+impl DbHandler {
+    // Looks up an episode by show name, season and episode number (None for a movie)
+    pub fn find_episode_id(
+        &self,
+        show_name: &str,
+        season: i32,
+        episode_number: Option<i32>,
+    ) -> Result<Option<EpisodeId>> {
+        self.conn
+            .prepare_cached(
+                "SELECT episodes.id FROM episodes JOIN shows ON shows.id = episodes.show_id
+                 WHERE shows.name = ? AND episodes.season = ? AND episodes.episode_number IS ?",
+            )?
+            .query_row(params![show_name, season, episode_number], |row| row.get(0))
+            .optional()
+    }
+
+    // Looks up a show by name
+    pub fn find_show_id(&self, name: &str) -> Result<Option<ShowId>> {
+        self.conn
+            .prepare_cached("SELECT id FROM shows WHERE name = ?")?
+            .query_row(params![name], |row| row.get(0))
+            .optional()
+    }
+
+    // Deletes an episode with all of its lines and their index entries
+    // Returns the id of the deleted episode, or None if there was no such episode
+    // External search backends (tantivy) have to be told separately
+    pub fn delete_episode(
+        &mut self,
+        show_name: &str,
+        season: i32,
+        episode_number: Option<i32>,
+    ) -> Result<Option<EpisodeId>> {
+        let Some(episode_id) = self.find_episode_id(show_name, season, episode_number)? else {
+            return Ok(None);
+        };
+        self.atomically(|db| db.delete_episode_by_id(episode_id))?;
+        Ok(Some(episode_id))
+    }
+
+    // Deletes a show with all of its episodes, lines and index entries
+    // Returns the ids of the deleted episodes, or None if there was no such show
+    pub fn delete_show(&mut self, name: &str) -> Result<Option<Vec<EpisodeId>>> {
+        let Some(show_id) = self.find_show_id(name)? else {
+            return Ok(None);
+        };
+        let episode_ids: Vec<EpisodeId> = self
+            .list_episodes(show_id)?
+            .into_iter()
+            .map(|episode| episode.id)
+            .collect();
+        self.atomically(|db| -> Result<()> {
+            for &episode_id in &episode_ids {
+                db.delete_episode_by_id(episode_id)?;
+            }
+            for table in ["show_glossary", "notes"] {
+                db.conn.execute(
+                    &format!("DELETE FROM {} WHERE show_id = ?", table),
+                    params![show_id],
+                )?;
+            }
+            db.conn
+                .execute("DELETE FROM shows WHERE id = ?", params![show_id])?;
+            Ok(())
+        })?;
+        Ok(Some(episode_ids))
+    }
+
+    fn delete_episode_by_id(&mut self, episode_id: EpisodeId) -> Result<()> {
+        if self.has_fts_table()? {
+            self.fts_delete_episode(episode_id)?;
+        }
+        self.delete_episode_lines(episode_id)?;
+        for table in [
+            "source_files",
+            "sources",
+            "media_files",
+            "episode_stats",
+            "episode_difficulty",
+            "user_progress",
+        ] {
+            self.conn.execute(
+                &format!("DELETE FROM {} WHERE episode_id = ?", table),
+                params![episode_id],
+            )?;
+        }
+        self.conn
+            .execute("DELETE FROM episodes WHERE id = ?", params![episode_id])?;
+        Ok(())
+    }
+
+    pub(crate) fn has_fts_table(&self) -> Result<bool> {
+        self.conn
+            .prepare_cached(
+                "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE name = 'transcripts_fts')",
+            )?
+            .query_row([], |row| row.get(0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_utils::test_db_with_lines;
+    use super::*;
+    use crate::tokenizer::test_utils::test_tokenizer;
+
+    fn count(db: &DbHandler, table: &str) -> i64 {
+        db.conn
+            .query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| {
+                row.get(0)
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn test_delete_episode_cascades() {
+        let (mut db, ids) = test_db_with_lines(&["猫が好き", "犬が好き"]);
+        db.index_transcripts(&test_tokenizer(), &ids).unwrap();
+        assert!(count(&db, "word_occurrences") > 0);
+
+        assert_eq!(db.delete_episode("Show Name", 1, Some(2)).unwrap(), None);
+        assert!(db
+            .delete_episode("Show Name", 1, Some(1))
+            .unwrap()
+            .is_some());
+        assert_eq!(count(&db, "episodes"), 0);
+        assert_eq!(count(&db, "transcripts"), 0);
+        assert_eq!(count(&db, "word_occurrences"), 0);
+        assert_eq!(count(&db, "shows"), 1);
+    }
+
+    #[test]
+    fn test_delete_show() {
+        let (mut db, _) = test_db_with_lines(&["猫が好き"]);
+        assert_eq!(db.delete_show("Other Show").unwrap(), None);
+        assert_eq!(db.delete_show("Show Name").unwrap().unwrap().len(), 1);
+        assert_eq!(count(&db, "shows"), 0);
+        assert_eq!(count(&db, "transcripts"), 0);
+    }
+
+    #[test]
+    fn test_atomically_rolls_back() {
+        let (mut db, _) = test_db_with_lines(&["猫が好き"]);
+        let result: Result<()> = db.atomically(|db| {
+            db.delete_show("Show Name")?;
+            db.conn
+                .execute("INSERT INTO missing_table VALUES (1)", [])?;
+            Ok(())
+        });
+        assert!(result.is_err());
+        assert_eq!(count(&db, "shows"), 1);
+        assert_eq!(count(&db, "transcripts"), 1);
+    }
+}
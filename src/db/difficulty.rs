@@ -0,0 +1,172 @@
+use super::{DbHandler, EpisodeId};
+use crate::tokenizer::is_kanji;
+use rusqlite::{params, Connection, Result};
+use std::collections::HashMap;
+
+// Words outside this many of the most frequent count as rare
+pub const COMMON_WORD_COUNT: usize = 5000;
+
+/// What makes an episode's dialogue hard, as cached by [`DbHandler::refresh_corpus_stats`].
+/// See [`crate::difficulty`] for how they are combined into a score.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DifficultyMeasures {
+    pub episode_id: EpisodeId,
+    /// Characters per line, not counting whitespace
+    pub average_line_length: f64,
+    /// Share of characters that are kanji, from 0 to 1
+    pub kanji_density: f64,
+    /// Average JLPT level of the words that have one (5 = N5 to 1 = N1),
+    /// or `None` if no JLPT list was imported or no word is on it
+    pub average_jlpt_level: Option<f64>,
+    /// Share of word occurrences outside the corpus's most frequent words, from 0 to 1
+    pub rare_word_share: f64,
+}
+
+impl DbHandler {
+    // Recomputes the cached difficulty measures of every episode
+    pub fn refresh_difficulty(&mut self) -> Result<()> {
+        let tx = self.conn.savepoint()?;
+        tx.execute("DELETE FROM episode_difficulty", [])?;
+        {
+            let mut stmt = tx.prepare_cached(
+                "INSERT INTO episode_difficulty
+                 (episode_id, average_line_length, kanji_density, average_jlpt_level, rare_word_share)
+                 VALUES (?, ?, ?, ?, ?)",
+            )?;
+            for measures in compute_difficulty(&tx)? {
+                stmt.execute(params![
+                    measures.episode_id,
+                    measures.average_line_length,
+                    measures.kanji_density,
+                    measures.average_jlpt_level,
+                    measures.rare_word_share
+                ])?;
+            }
+        }
+        tx.commit()
+    }
+
+    // The difficulty measures of every episode with lines, keyed by episode
+    // Without cached measures they are computed on the spot
+    pub fn difficulty_measures(&self) -> Result<HashMap<EpisodeId, DifficultyMeasures>> {
+        let cached: bool = self.conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM episode_difficulty)",
+            [],
+            |row| row.get(0),
+        )?;
+        let measures = if cached {
+            let mut stmt = self.conn.prepare_cached(
+                "SELECT episode_id, average_line_length, kanji_density, average_jlpt_level,
+                        rare_word_share
+                 FROM episode_difficulty",
+            )?;
+            let measures = stmt
+                .query_map([], |row| {
+                    Ok(DifficultyMeasures {
+                        episode_id: row.get(0)?,
+                        average_line_length: row.get(1)?,
+                        kanji_density: row.get(2)?,
+                        average_jlpt_level: row.get(3)?,
+                        rare_word_share: row.get(4)?,
+                    })
+                })?
+                .collect::<Result<Vec<_>>>()?;
+            measures
+        } else {
+            compute_difficulty(&self.conn)?
+        };
+        Ok(measures
+            .into_iter()
+            .map(|measures| (measures.episode_id, measures))
+            .collect())
+    }
+}
+
+fn compute_difficulty(conn: &Connection) -> Result<Vec<DifficultyMeasures>> {
+    // Lines, characters and kanji of each episode
+    let mut text_counts: HashMap<EpisodeId, (usize, usize, usize)> = HashMap::new();
+    let mut stmt = conn.prepare_cached("SELECT episode_id, text FROM transcripts")?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        let text: String = row.get(1)?;
+        let counts = text_counts.entry(row.get(0)?).or_default();
+        counts.0 += 1;
+        for c in text.chars().filter(|c| !c.is_whitespace()) {
+            counts.1 += 1;
+            counts.2 += is_kanji(c) as usize;
+        }
+    }
+
+    let mut stmt = conn.prepare_cached(
+        "SELECT transcripts.episode_id, AVG(jlpt_levels.level)
+         FROM word_occurrences
+         JOIN transcripts ON transcripts.id = word_occurrences.transcript_id
+         JOIN words ON words.id = word_occurrences.word_id
+         JOIN jlpt_levels ON jlpt_levels.word = words.word
+         GROUP BY transcripts.episode_id",
+    )?;
+    let jlpt_levels: HashMap<EpisodeId, f64> = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<_>>()?;
+
+    let mut stmt = conn.prepare_cached(
+        "WITH common AS (SELECT id FROM words ORDER BY frequency DESC LIMIT ?)
+         SELECT transcripts.episode_id, COUNT(*),
+                SUM(word_occurrences.word_id NOT IN (SELECT id FROM common))
+         FROM word_occurrences
+         JOIN transcripts ON transcripts.id = word_occurrences.transcript_id
+         GROUP BY transcripts.episode_id",
+    )?;
+    let rare_shares: HashMap<EpisodeId, f64> = stmt
+        .query_map(params![COMMON_WORD_COUNT as i64], |row| {
+            let total: i64 = row.get(1)?;
+            let rare: i64 = row.get(2)?;
+            Ok((row.get(0)?, rare as f64 / total as f64))
+        })?
+        .collect::<Result<_>>()?;
+
+    let mut measures: Vec<DifficultyMeasures> = text_counts
+        .into_iter()
+        .map(
+            |(episode_id, (lines, characters, kanji))| DifficultyMeasures {
+                episode_id,
+                average_line_length: characters as f64 / lines as f64,
+                kanji_density: if characters == 0 {
+                    0.0
+                } else {
+                    kanji as f64 / characters as f64
+                },
+                average_jlpt_level: jlpt_levels.get(&episode_id).copied(),
+                rare_word_share: rare_shares.get(&episode_id).copied().unwrap_or(0.0),
+            },
+        )
+        .collect();
+    measures.sort_by_key(|measures| measures.episode_id);
+    Ok(measures)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_utils::test_db_with_lines;
+    use crate::tokenizer::test_utils::test_tokenizer;
+
+    #[test]
+    fn test_difficulty_measures() {
+        let (mut db, ids) = test_db_with_lines(&["猫が好き", "ねこだ"]);
+        db.index_transcripts(&test_tokenizer(), &ids).unwrap();
+        db.import_jlpt_levels("猫\tN5\n好き\tN4\n".as_bytes())
+            .unwrap();
+
+        let live = db.difficulty_measures().unwrap();
+        db.refresh_difficulty().unwrap();
+        let cached = db.difficulty_measures().unwrap();
+        assert_eq!(live, cached);
+
+        let measures = cached.values().next().unwrap();
+        assert_eq!(measures.average_line_length, 3.5);
+        assert_eq!(measures.kanji_density, 2.0 / 7.0);
+        assert_eq!(measures.average_jlpt_level, Some(4.5));
+        // Every word of a tiny corpus is among the most frequent
+        assert_eq!(measures.rare_word_share, 0.0);
+    }
+}
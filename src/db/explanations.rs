@@ -0,0 +1,83 @@
+use super::{DbHandler, TranscriptId};
+use rusqlite::{params, OptionalExtension, Result};
+use std::collections::HashMap;
+
+/// A model's notes on the grammar and word senses of one line (see `explain`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Explanation {
+    pub transcript_id: TranscriptId,
+    /// The provider and model that wrote it
+    pub model: String,
+    pub text: String,
+}
+
+impl DbHandler {
+    // The stored explanation of a line, if it has one
+    pub fn explanation(&self, transcript_id: TranscriptId) -> Result<Option<Explanation>> {
+        self.conn
+            .prepare_cached(
+                "SELECT transcript_id, model, text FROM line_explanations WHERE transcript_id = ?",
+            )?
+            .query_row(params![transcript_id], |row| {
+                Ok(Explanation {
+                    transcript_id: row.get(0)?,
+                    model: row.get(1)?,
+                    text: row.get(2)?,
+                })
+            })
+            .optional()
+    }
+
+    // The stored explanations of the given lines, keyed by line; lines without one are left out
+    pub fn explanations(
+        &self,
+        transcript_ids: &[TranscriptId],
+    ) -> Result<HashMap<TranscriptId, String>> {
+        let mut explanations = HashMap::new();
+        for &id in transcript_ids {
+            if let Some(explanation) = self.explanation(id)? {
+                explanations.insert(id, explanation.text);
+            }
+        }
+        Ok(explanations)
+    }
+
+    // Stores a line's explanation, replacing any earlier one
+    pub fn save_explanation(&self, explanation: &Explanation) -> Result<()> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO line_explanations (transcript_id, model, text) VALUES (?, ?, ?)",
+            params![explanation.transcript_id, explanation.model, explanation.text],
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_utils::test_db_with_lines;
+
+    #[test]
+    fn test_explanations_are_replaced_and_deleted_with_their_line() {
+        let (mut db, ids) = test_db_with_lines(&["猫が走った", "犬"]);
+        assert_eq!(db.explanation(ids[0]).unwrap(), None);
+        for text in ["が marks the subject", "走った: past of 走る"] {
+            db.save_explanation(&Explanation {
+                transcript_id: ids[0],
+                model: "fake:model".to_string(),
+                text: text.to_string(),
+            })
+            .unwrap();
+        }
+        assert_eq!(
+            db.explanation(ids[0]).unwrap().unwrap().text,
+            "走った: past of 走る"
+        );
+        let explanations = db.explanations(&ids).unwrap();
+        assert_eq!(explanations.len(), 1);
+
+        let episode_id = db.get_transcript(ids[0]).unwrap().unwrap().episode_id;
+        db.delete_episode_lines(episode_id).unwrap();
+        assert_eq!(db.explanation(ids[0]).unwrap(), None);
+    }
+}
@@ -0,0 +1,64 @@
+use super::{DbHandler, EpisodeId, TranscriptId};
+use rusqlite::{params, Result};
+
+impl DbHandler {
+    // Creates the FTS5 table used by the FTS5 search backend
+    // Each row holds a line's dictionary forms separated by spaces, keyed by transcript id
+    pub fn create_fts_table(&self) -> Result<()> {
+        self.conn.execute(
+            "CREATE VIRTUAL TABLE IF NOT EXISTS transcripts_fts USING fts5(
+                words,
+                episode_id UNINDEXED,
+                tokenize = 'unicode61'
+            )",
+            [],
+        )?;
+        Ok(())
+    }
+
+    // Writes (or replaces) FTS rows in a single transaction
+    pub fn fts_insert_lines(&mut self, lines: &[(TranscriptId, EpisodeId, String)]) -> Result<()> {
+        let tx = self.conn.savepoint()?;
+        {
+            let mut delete = tx.prepare_cached("DELETE FROM transcripts_fts WHERE rowid = ?")?;
+            let mut insert = tx.prepare_cached(
+                "INSERT INTO transcripts_fts (rowid, words, episode_id) VALUES (?, ?, ?)",
+            )?;
+            for (transcript_id, episode_id, words) in lines {
+                delete.execute(params![transcript_id])?;
+                insert.execute(params![transcript_id, words, episode_id])?;
+            }
+        }
+        tx.commit()
+    }
+
+    // Finds the lines containing every given word, best bm25 rank first
+    pub fn fts_search(&self, words: &[String], limit: usize) -> Result<Vec<TranscriptId>> {
+        if words.is_empty() {
+            return Ok(Vec::new());
+        }
+        // Quote each word so FTS5 query syntax in the input is taken literally
+        let query = words
+            .iter()
+            .map(|word| format!("\"{}\"", word.replace('"', "\"\"")))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT rowid FROM transcripts_fts WHERE transcripts_fts MATCH ?
+             ORDER BY rank LIMIT ?",
+        )?;
+        let ids = stmt
+            .query_map(params![query, limit as i64], |row| row.get(0))?
+            .collect();
+        ids
+    }
+
+    // Removes an episode's lines from the FTS table
+    pub fn fts_delete_episode(&self, episode_id: EpisodeId) -> Result<()> {
+        self.conn.execute(
+            "DELETE FROM transcripts_fts WHERE episode_id = ?",
+            params![episode_id],
+        )?;
+        Ok(())
+    }
+}
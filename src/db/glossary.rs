@@ -0,0 +1,221 @@
+use super::{DbHandler, ShowId, TranscriptId};
+use rusqlite::{params, Result};
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// How many times more often a proper noun has to occur in a show than in the
+/// rest of the corpus to make the show's glossary.
+pub const MIN_KEYNESS: f64 = 2.0;
+
+/// A proper noun (character, place or organization name) characteristic of a
+/// show, as stored by [`DbHandler::refresh_glossary`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GlossaryTerm {
+    pub show_name: String,
+    pub term: String,
+    /// Hiragana reading, if the dictionary had one for every part of the term
+    pub reading: Option<String>,
+    pub occurrences: i64,
+    /// The term's rate in the show divided by its (smoothed) rate in the rest of the corpus
+    pub keyness: f64,
+}
+
+// A run of adjacent proper noun tokens, such as 魔王 followed by 城
+#[derive(Default)]
+struct TermCount {
+    reading: Option<String>,
+    occurrences: i64,
+}
+
+impl DbHandler {
+    // Rebuilds every show's glossary from the word index
+    // Adjacent tokens tagged 名詞-固有名詞 are joined into one term, since the
+    // dictionary tends to split names it doesn't know
+    // A term is kept when it occurs at least `min_count` times in a show and
+    // at least MIN_KEYNESS times as often there as in the other shows
+    // Returns the number of terms stored
+    pub fn refresh_glossary(&mut self, min_count: i64) -> Result<usize> {
+        let show_tokens = self.show_token_counts()?;
+        let total_tokens: i64 = show_tokens.values().sum();
+        let terms = self.proper_noun_runs()?;
+        let mut corpus_counts: HashMap<&str, i64> = HashMap::new();
+        for ((_, term), count) in &terms {
+            *corpus_counts.entry(term).or_default() += count.occurrences;
+        }
+
+        let tx = self.conn.savepoint()?;
+        let mut stored = 0;
+        {
+            tx.execute("DELETE FROM show_glossary", [])?;
+            let mut insert = tx.prepare_cached(
+                "INSERT INTO show_glossary (show_id, term, reading, occurrences, keyness)
+                 VALUES (?, ?, ?, ?, ?)",
+            )?;
+            for ((show_id, term), count) in &terms {
+                if count.occurrences < min_count {
+                    continue;
+                }
+                let tokens = show_tokens.get(show_id).copied().unwrap_or(0).max(1);
+                let elsewhere = corpus_counts[term.as_str()] - count.occurrences;
+                let keyness = keyness(count.occurrences, tokens, elsewhere, total_tokens);
+                if keyness < MIN_KEYNESS {
+                    continue;
+                }
+                insert.execute(params![
+                    show_id,
+                    term,
+                    count.reading,
+                    count.occurrences,
+                    keyness
+                ])?;
+                stored += 1;
+            }
+        }
+        tx.commit()?;
+        Ok(stored)
+    }
+
+    // The stored glossary of one show, or of every show, the most characteristic terms first
+    pub fn glossary(&self, show_name: Option<&str>) -> Result<Vec<GlossaryTerm>> {
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT shows.name, show_glossary.term, show_glossary.reading,
+                    show_glossary.occurrences, show_glossary.keyness
+             FROM show_glossary
+             JOIN shows ON shows.id = show_glossary.show_id
+             WHERE ?1 IS NULL OR shows.name = ?1
+             ORDER BY shows.name, show_glossary.keyness DESC, show_glossary.term",
+        )?;
+        let terms = stmt
+            .query_map(params![show_name], |row| {
+                Ok(GlossaryTerm {
+                    show_name: row.get(0)?,
+                    term: row.get(1)?,
+                    reading: row.get(2)?,
+                    occurrences: row.get(3)?,
+                    keyness: row.get(4)?,
+                })
+            })?
+            .collect();
+        terms
+    }
+
+    // Number of indexed tokens per show
+    fn show_token_counts(&self) -> Result<HashMap<ShowId, i64>> {
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT episodes.show_id, COUNT(*)
+             FROM word_occurrences
+             JOIN transcripts ON transcripts.id = word_occurrences.transcript_id
+             JOIN episodes ON episodes.id = transcripts.episode_id
+             GROUP BY episodes.show_id",
+        )?;
+        let counts = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect();
+        counts
+    }
+
+    // Counts the runs of adjacent proper noun tokens per show
+    fn proper_noun_runs(&self) -> Result<HashMap<(ShowId, String), TermCount>> {
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT episodes.show_id, word_occurrences.transcript_id, word_occurrences.position,
+                    words.word, lemmas.reading
+             FROM word_occurrences
+             JOIN words ON words.id = word_occurrences.word_id
+             LEFT JOIN lemmas ON lemmas.id = words.lemma_id
+             JOIN transcripts ON transcripts.id = word_occurrences.transcript_id
+             JOIN episodes ON episodes.id = transcripts.episode_id
+             WHERE word_occurrences.pos LIKE '名詞-固有名詞%'
+             ORDER BY word_occurrences.transcript_id, word_occurrences.position",
+        )?;
+        let mut rows = stmt.query([])?;
+        let mut terms: HashMap<(ShowId, String), TermCount> = HashMap::new();
+        // The run being built: its show, line, last position, text and reading
+        let mut run: Option<(ShowId, TranscriptId, i64, String, Option<String>)> = None;
+        let mut finish = |run: Option<(ShowId, TranscriptId, i64, String, Option<String>)>| {
+            if let Some((show_id, _, _, term, reading)) = run {
+                let count = terms.entry((show_id, term)).or_default();
+                count.occurrences += 1;
+                count.reading = count.reading.take().or(reading);
+            }
+        };
+        while let Some(row) = rows.next()? {
+            let show_id: ShowId = row.get(0)?;
+            let transcript_id: TranscriptId = row.get(1)?;
+            let position: i64 = row.get(2)?;
+            let word: String = row.get(3)?;
+            let reading: Option<String> = row.get(4)?;
+            match &mut run {
+                Some((_, run_line, run_end, term, run_reading))
+                    if *run_line == transcript_id && *run_end + 1 == position =>
+                {
+                    *run_end = position;
+                    term.push_str(&word);
+                    *run_reading = run_reading.take().zip(reading).map(|(a, b)| a + &b);
+                }
+                _ => finish(run.replace((show_id, transcript_id, position, word, reading))),
+            }
+        }
+        finish(run);
+        Ok(terms)
+    }
+}
+
+/// How characteristic a term is of part of a corpus: its rate there over its
+/// add-one smoothed rate elsewhere, per token of `total_tokens`. Glossaries
+/// count those in the whole corpus, so that a show without company still
+/// gets a score.
+pub fn keyness(occurrences: i64, show_tokens: i64, elsewhere: i64, total_tokens: i64) -> f64 {
+    let rate = occurrences as f64 / show_tokens as f64;
+    let baseline = (elsewhere + 1) as f64 / total_tokens.max(1) as f64;
+    rate / baseline
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_utils::{insert_show_lines, test_db_with_lines};
+    use super::*;
+    use crate::tokenizer::test_utils::test_dictionary;
+    use crate::tokenizer::{DictionaryKind, JapaneseTokenizer};
+
+    fn tokenizer_with_names() -> JapaneseTokenizer {
+        let user_lexicon = "\
+魔王,0,0,100,名詞,固有名詞,人名,*,*,*,魔王,マオウ,マオウ
+城,0,0,100,名詞,固有名詞,地域,*,*,*,城,シロ,シロ
+東京,0,0,100,名詞,固有名詞,地域,*,*,*,東京,トウキョウ,トウキョウ
+";
+        let dictionary = test_dictionary()
+            .reset_user_lexicon_from_reader(Some(user_lexicon.as_bytes()))
+            .unwrap();
+        JapaneseTokenizer::new(dictionary, DictionaryKind::Ipadic)
+    }
+
+    #[test]
+    fn test_glossary_joins_names_and_skips_shared_ones() {
+        let (mut db, mut ids) =
+            test_db_with_lines(&["魔王城が好き", "魔王城だ", "魔王城", "東京", "猫"]);
+        ids.extend(insert_show_lines(
+            &mut db,
+            "Other Show",
+            &["東京が好き", "猫", "犬", "走った"],
+        ));
+        db.index_transcripts(&tokenizer_with_names(), &ids).unwrap();
+
+        assert_eq!(db.refresh_glossary(2).unwrap(), 1);
+        let glossary = db.glossary(None).unwrap();
+        assert_eq!(glossary.len(), 1);
+        assert_eq!(glossary[0].show_name, "Show Name");
+        assert_eq!(glossary[0].term, "魔王城");
+        assert_eq!(glossary[0].reading.as_deref(), Some("まおうしろ"));
+        assert_eq!(glossary[0].occurrences, 3);
+        assert!(glossary[0].keyness >= MIN_KEYNESS);
+        assert!(db.glossary(Some("Other Show")).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_keyness() {
+        // Only in this show: well above the threshold, even as the only show
+        assert!(keyness(10, 1000, 0, 1000) >= MIN_KEYNESS);
+        // Equally common in two shows of the same size
+        assert!(keyness(10, 1000, 10, 2000) < MIN_KEYNESS);
+    }
+}
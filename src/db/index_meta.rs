@@ -0,0 +1,42 @@
+use super::DbHandler;
+use rusqlite::{params, OptionalExtension, Result};
+
+// The fingerprint of the policy the word index was built with (see IndexPolicy::fingerprint)
+pub const INDEX_POLICY_KEY: &str = "index_policy";
+
+impl DbHandler {
+    // A value recorded about how the word index was built, if any
+    pub fn index_meta(&self, key: &str) -> Result<Option<String>> {
+        self.conn
+            .prepare_cached("SELECT value FROM index_meta WHERE key = ?")?
+            .query_row(params![key], |row| row.get(0))
+            .optional()
+    }
+
+    // Records a value about how the word index was built, replacing any earlier one
+    pub fn set_index_meta(&self, key: &str, value: &str) -> Result<()> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO index_meta (key, value) VALUES (?, ?)",
+            params![key, value],
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_utils::test_db_with_lines;
+    use super::*;
+
+    #[test]
+    fn test_index_meta() {
+        let (db, _) = test_db_with_lines(&[]);
+        assert_eq!(db.index_meta(INDEX_POLICY_KEY).unwrap(), None);
+        db.set_index_meta(INDEX_POLICY_KEY, "min_length:1").unwrap();
+        db.set_index_meta(INDEX_POLICY_KEY, "min_length:2").unwrap();
+        assert_eq!(
+            db.index_meta(INDEX_POLICY_KEY).unwrap().as_deref(),
+            Some("min_length:2")
+        );
+    }
+}
@@ -0,0 +1,155 @@
+use super::{DbHandler, TranscriptId};
+use rusqlite::{params, OptionalExtension, Result, ToSql};
+use std::io::BufRead;
+
+impl DbHandler {
+    // Loads a JLPT word list of tab-separated `word, level` lines (e.g. 猫	N5)
+    // Levels may be written as N5 or 5; unparseable lines are skipped
+    // Returns the number of entries read
+    pub fn import_jlpt_levels<R: BufRead>(&mut self, reader: R) -> crate::error::Result<usize> {
+        let tx = self.conn.savepoint()?;
+        let mut count = 0;
+        {
+            let mut insert = tx
+                .prepare_cached("INSERT OR REPLACE INTO jlpt_levels (word, level) VALUES (?, ?)")?;
+            for line in reader.lines() {
+                let line = line?;
+                if line.trim().is_empty() || line.starts_with('#') {
+                    continue;
+                }
+                let mut fields = line.split('\t');
+                let (Some(word), Some(level)) = (fields.next(), fields.next()) else {
+                    continue;
+                };
+                let level = level.trim();
+                let Ok(level) = level
+                    .strip_prefix(['N', 'n'])
+                    .unwrap_or(level)
+                    .parse::<u8>()
+                else {
+                    continue;
+                };
+                insert.execute(params![word.trim(), level])?;
+                count += 1;
+            }
+        }
+        tx.commit()?;
+        Ok(count)
+    }
+
+    // Looks up the JLPT level of a dictionary form (5 = N5, the easiest, to 1 = N1)
+    pub fn jlpt_level(&self, word: &str) -> Result<Option<u8>> {
+        self.conn
+            .prepare_cached("SELECT level FROM jlpt_levels WHERE word = ?")?
+            .query_row(params![word], |row| row.get(0))
+            .optional()
+    }
+
+    // Finds lines suitable as a sentence of the day, in id order:
+    // between `min_chars` and `max_chars` long with at least `min_words` distinct indexed words
+    // With `level`, no word may be harder than that JLPT level and one must be at it
+    pub fn daily_candidates(
+        &self,
+        level: Option<u8>,
+        min_chars: u32,
+        max_chars: u32,
+        min_words: u32,
+    ) -> Result<Vec<TranscriptId>> {
+        let level_condition = if level.is_some() {
+            "AND NOT EXISTS (
+                SELECT 1 FROM word_occurrences
+                JOIN words ON words.id = word_occurrences.word_id
+                JOIN jlpt_levels ON jlpt_levels.word = words.word
+                WHERE word_occurrences.transcript_id = transcripts.id AND jlpt_levels.level < ?
+             )
+             AND EXISTS (
+                SELECT 1 FROM word_occurrences
+                JOIN words ON words.id = word_occurrences.word_id
+                JOIN jlpt_levels ON jlpt_levels.word = words.word
+                WHERE word_occurrences.transcript_id = transcripts.id AND jlpt_levels.level = ?
+             )"
+        } else {
+            ""
+        };
+        let sql = format!(
+            "SELECT id FROM transcripts
+             WHERE length(trim(text)) BETWEEN ? AND ?
+               AND (SELECT COUNT(DISTINCT word_id) FROM word_occurrences
+                    WHERE word_occurrences.transcript_id = transcripts.id) >= ?
+               {}
+             ORDER BY id",
+            level_condition
+        );
+        let mut values: Vec<&dyn ToSql> = vec![&min_chars, &max_chars, &min_words];
+        if let Some(level) = &level {
+            values.extend([level as &dyn ToSql, level]);
+        }
+        let mut stmt = self.conn.prepare_cached(&sql)?;
+        let ids = stmt.query_map(&values[..], |row| row.get(0))?.collect();
+        ids
+    }
+
+    // The word of a line worth highlighting: its first word at the given JLPT level,
+    // or without a level, the word that is rarest across the corpus
+    pub fn featured_word(
+        &self,
+        transcript_id: TranscriptId,
+        level: Option<u8>,
+    ) -> Result<Option<String>> {
+        let sql = if level.is_some() {
+            "SELECT words.word FROM word_occurrences
+             JOIN words ON words.id = word_occurrences.word_id
+             JOIN jlpt_levels ON jlpt_levels.word = words.word
+             WHERE word_occurrences.transcript_id = ?1 AND jlpt_levels.level = ?2
+             ORDER BY word_occurrences.position LIMIT 1"
+        } else {
+            "SELECT words.word FROM word_occurrences
+             JOIN words ON words.id = word_occurrences.word_id
+             WHERE word_occurrences.transcript_id = ?1 AND ?2 IS NULL
+             ORDER BY (SELECT COUNT(*) FROM word_occurrences AS other
+                       WHERE other.word_id = word_occurrences.word_id),
+                      word_occurrences.position
+             LIMIT 1"
+        };
+        self.conn
+            .prepare_cached(sql)?
+            .query_row(params![transcript_id, level], |row| row.get(0))
+            .optional()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_utils::test_db_with_lines;
+    use crate::tokenizer::test_utils::test_tokenizer;
+
+    #[test]
+    fn test_daily_candidates_by_level() {
+        let (mut db, ids) = test_db_with_lines(&["猫が走った", "犬が好きです", "猫", "面白い犬"]);
+        db.index_transcripts(&test_tokenizer(), &ids).unwrap();
+        let levels = "猫\tN5\n犬\t5\n走る\tN4\n好き\tN5\n面白い\tN4\nbroken\tN\n";
+        assert_eq!(db.import_jlpt_levels(levels.as_bytes()).unwrap(), 5);
+        assert_eq!(db.jlpt_level("走る").unwrap(), Some(4));
+
+        assert_eq!(
+            db.daily_candidates(None, 2, 20, 2).unwrap(),
+            [ids[0], ids[1], ids[3]]
+        );
+        // N5 excludes the lines with N4 words
+        assert_eq!(db.daily_candidates(Some(5), 2, 20, 2).unwrap(), [ids[1]]);
+        assert_eq!(
+            db.daily_candidates(Some(4), 2, 20, 2).unwrap(),
+            [ids[0], ids[3]]
+        );
+
+        assert_eq!(
+            db.featured_word(ids[0], Some(4)).unwrap().as_deref(),
+            Some("走る")
+        );
+        // 走る occurs once in the corpus, 猫 twice
+        assert_eq!(
+            db.featured_word(ids[0], None).unwrap().as_deref(),
+            Some("走る")
+        );
+    }
+}
@@ -0,0 +1,84 @@
+use super::DbHandler;
+use rusqlite::{params, OptionalExtension, Result};
+
+impl DbHandler {
+    // The cached reply stored under `key` at or after `created_after` (Unix seconds)
+    // A hit marks the entry as used at `now`, so pruning by size keeps it longer
+    pub fn cached_llm_reply(
+        &self,
+        key: &str,
+        created_after: i64,
+        now: i64,
+    ) -> Result<Option<String>> {
+        let reply = self
+            .conn
+            .prepare_cached("SELECT reply FROM llm_cache WHERE key = ? AND created_at >= ?")?
+            .query_row(params![key, created_after], |row| row.get(0))
+            .optional()?;
+        if reply.is_some() {
+            self.conn.execute(
+                "UPDATE llm_cache SET used_at = ? WHERE key = ?",
+                params![now, key],
+            )?;
+        }
+        Ok(reply)
+    }
+
+    // Stores a reply under `key`, replacing any earlier one
+    pub fn cache_llm_reply(&self, key: &str, model: &str, reply: &str, now: i64) -> Result<()> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO llm_cache (key, model, reply, created_at, used_at)
+             VALUES (?, ?, ?, ?, ?)",
+            params![key, model, reply, now, now],
+        )?;
+        Ok(())
+    }
+
+    // Deletes replies created before `created_after`, then the least recently
+    // used ones beyond `max_entries`, the earliest stored first among those used
+    // in the same second; returns the number deleted
+    pub fn prune_llm_cache(&self, created_after: i64, max_entries: usize) -> Result<usize> {
+        let expired = self.conn.execute(
+            "DELETE FROM llm_cache WHERE created_at < ?",
+            params![created_after],
+        )?;
+        let evicted = self.conn.execute(
+            "DELETE FROM llm_cache WHERE key IN
+             (SELECT key FROM llm_cache ORDER BY used_at DESC, rowid DESC LIMIT -1 OFFSET ?)",
+            params![max_entries as i64],
+        )?;
+        Ok(expired + evicted)
+    }
+
+    // Deletes every cached reply; returns the number deleted
+    pub fn clear_llm_cache(&self) -> Result<usize> {
+        self.conn.execute("DELETE FROM llm_cache", [])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_utils::test_db_with_lines;
+
+    #[test]
+    fn test_llm_cache_expires_and_evicts() {
+        let (db, _) = test_db_with_lines(&[]);
+        db.cache_llm_reply("a", "fake:model", "A", 100).unwrap();
+        db.cache_llm_reply("b", "fake:model", "B", 200).unwrap();
+        db.cache_llm_reply("c", "fake:model", "C", 300).unwrap();
+
+        assert_eq!(
+            db.cached_llm_reply("a", 0, 400).unwrap().as_deref(),
+            Some("A")
+        );
+        assert_eq!(db.cached_llm_reply("a", 150, 400).unwrap(), None);
+        assert_eq!(db.cached_llm_reply("missing", 0, 400).unwrap(), None);
+
+        // b is the least recently used, as a was just read
+        assert_eq!(db.prune_llm_cache(0, 2).unwrap(), 1);
+        assert_eq!(db.cached_llm_reply("b", 0, 500).unwrap(), None);
+        assert_eq!(db.prune_llm_cache(150, 2).unwrap(), 1);
+        assert_eq!(db.cached_llm_reply("a", 0, 500).unwrap(), None);
+        assert_eq!(db.clear_llm_cache().unwrap(), 1);
+    }
+}
@@ -0,0 +1,222 @@
+use super::{DbHandler, SearchHit, ShowId, TranscriptId};
+use crate::srt_parser::Timestamp;
+use rusqlite::{params, Result};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
+
+/// How [`DbHandler::detect_lyrics`] recognizes the lyrics of opening and ending songs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LyricsOptions {
+    /// Episodes a line must recur in at nearly the same time
+    pub min_episodes: usize,
+    /// Seconds the line's start may differ by between those episodes
+    pub max_drift: u32,
+    /// Shorter lines, such as はい or えっ, are never flagged; they recur by chance
+    pub min_length: usize,
+}
+
+impl Default for LyricsOptions {
+    fn default() -> Self {
+        LyricsOptions {
+            min_episodes: 3,
+            max_drift: 60,
+            min_length: 4,
+        }
+    }
+}
+
+/// Which lines flagged as lyrics (`transcripts.lyrics`) a search keeps.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LyricsFilter {
+    /// Lyrics and dialogue alike
+    #[default]
+    Include,
+    Exclude,
+    /// Only lyrics
+    Only,
+}
+
+impl fmt::Display for LyricsFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            LyricsFilter::Include => "include",
+            LyricsFilter::Exclude => "exclude",
+            LyricsFilter::Only => "only",
+        })
+    }
+}
+
+impl FromStr for LyricsFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "include" => Ok(LyricsFilter::Include),
+            "exclude" => Ok(LyricsFilter::Exclude),
+            "only" => Ok(LyricsFilter::Only),
+            _ => Err(format!(
+                "unknown lyrics filter {:?} (expected include, exclude or only)",
+                s
+            )),
+        }
+    }
+}
+
+impl DbHandler {
+    // Flags the lines of a show that recur at nearly the same time in several of its episodes,
+    // as the lyrics of its opening and ending do; returns how many lines are flagged
+    // The show's earlier flags are cleared first, so running it again after ingesting
+    // more episodes brings them up to date
+    pub fn detect_lyrics(&mut self, show_id: ShowId, options: &LyricsOptions) -> Result<usize> {
+        // The lines of each text, with their episode and start in milliseconds
+        let mut texts: HashMap<String, Vec<(TranscriptId, i64, u64)>> = HashMap::new();
+        {
+            let mut stmt = self.conn.prepare_cached(
+                "SELECT transcripts.id, transcripts.episode_id, transcripts.time_start,
+                        transcripts.text
+                 FROM transcripts JOIN episodes ON episodes.id = transcripts.episode_id
+                 WHERE episodes.show_id = ?",
+            )?;
+            let mut rows = stmt.query(params![show_id])?;
+            while let Some(row) = rows.next()? {
+                let text: String = row.get(3)?;
+                let text: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+                let time_start: String = row.get(2)?;
+                let Ok(start) = time_start.parse::<Timestamp>() else {
+                    continue;
+                };
+                if text.chars().count() >= options.min_length {
+                    texts.entry(text).or_default().push((
+                        row.get(0)?,
+                        row.get(1)?,
+                        start.to_millis(),
+                    ));
+                }
+            }
+        }
+
+        let drift = u64::from(options.max_drift) * 1000;
+        let mut lyrics = Vec::new();
+        for lines in texts.values() {
+            for &(id, _, start) in lines {
+                let episodes: HashSet<i64> = lines
+                    .iter()
+                    .filter(|(_, _, other)| start.abs_diff(*other) <= drift)
+                    .map(|&(_, episode, _)| episode)
+                    .collect();
+                if episodes.len() >= options.min_episodes {
+                    lyrics.push(id);
+                }
+            }
+        }
+
+        let tx = self.conn.savepoint()?;
+        tx.execute(
+            "UPDATE transcripts SET lyrics = 0
+             WHERE lyrics = 1
+               AND episode_id IN (SELECT id FROM episodes WHERE show_id = ?)",
+            params![show_id],
+        )?;
+        {
+            let mut stmt = tx.prepare_cached("UPDATE transcripts SET lyrics = 1 WHERE id = ?")?;
+            for id in &lyrics {
+                stmt.execute(params![id])?;
+            }
+        }
+        tx.commit()?;
+        Ok(lyrics.len())
+    }
+
+    // Drops the hits `filter` doesn't keep
+    pub fn retain_lyrics(&self, filter: LyricsFilter, hits: &mut Vec<SearchHit>) -> Result<()> {
+        let only = match filter {
+            LyricsFilter::Include => return Ok(()),
+            LyricsFilter::Exclude => false,
+            LyricsFilter::Only => true,
+        };
+        let lyrics: HashSet<TranscriptId> = self
+            .conn
+            .prepare_cached("SELECT id FROM transcripts WHERE lyrics = 1")?
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<_>>()?;
+        hits.retain(|hit| lyrics.contains(&hit.transcript_id) == only);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{NewEpisode, NewShow, NewTranscript};
+    use super::*;
+
+    #[test]
+    fn test_detect_lyrics() {
+        let mut db = DbHandler::new(":memory:").unwrap();
+        db.create_tables().unwrap();
+        let show_ids = db
+            .batch_insert_shows(&[NewShow {
+                name: "Show Name".to_string(),
+                show_type: "Anime".to_string(),
+            }])
+            .unwrap();
+        let episodes: Vec<NewEpisode> = (1..=3)
+            .map(|number| NewEpisode {
+                show_id: show_ids[0],
+                name: format!("Episode {}", number),
+                season: 1,
+                episode_number: Some(number),
+            })
+            .collect();
+        let episode_ids = db.batch_insert_episodes(&episodes).unwrap();
+        // The opening starts a little later each episode; the catchphrase is at a
+        // different time in each, and はい is too short to count
+        let mut lines = Vec::new();
+        for (i, &episode_id) in episode_ids.iter().enumerate() {
+            for (line_id, (time, text)) in [
+                (format!("00:01:{:02},000", 10 * i), "夢の彼方へ飛んでいけ"),
+                (
+                    format!("00:{:02}:00,000", 2 + 5 * i),
+                    "諦めたらそこで終わりだ",
+                ),
+                ("00:03:00,000".to_string(), "はい"),
+            ]
+            .into_iter()
+            .enumerate()
+            {
+                lines.push(NewTranscript {
+                    episode_id,
+                    line_id: line_id as i32 + 1,
+                    time_start: time.clone(),
+                    time_end: time,
+                    sub_index: 0,
+                    text: text.to_string(),
+                    text_raw: text.to_string(),
+                    speaker: None,
+                });
+            }
+        }
+        db.batch_insert_transcripts(&lines, None).unwrap();
+
+        assert_eq!(
+            db.detect_lyrics(show_ids[0], &LyricsOptions::default())
+                .unwrap(),
+            3
+        );
+        let mut hits = db.find_lines_containing_any(&[]).unwrap();
+        db.retain_lyrics(LyricsFilter::Only, &mut hits).unwrap();
+        assert_eq!(hits.len(), 3);
+        assert!(hits.iter().all(|hit| hit.text == "夢の彼方へ飛んでいけ"));
+        let mut hits = db.find_lines_containing_any(&[]).unwrap();
+        db.retain_lyrics(LyricsFilter::Exclude, &mut hits).unwrap();
+        assert_eq!(hits.len(), 6);
+
+        // Running again with a stricter drift clears the flags
+        let options = LyricsOptions {
+            max_drift: 5,
+            ..LyricsOptions::default()
+        };
+        assert_eq!(db.detect_lyrics(show_ids[0], &options).unwrap(), 0);
+        assert_eq!("exclude".parse(), Ok(LyricsFilter::Exclude));
+    }
+}
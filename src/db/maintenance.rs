@@ -0,0 +1,86 @@
+use super::DbHandler;
+use rusqlite::Result;
+
+/// What [`DbHandler::optimize`] did.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OptimizeReport {
+    /// Words removed from the `words` table because no line uses them anymore
+    pub pruned_words: usize,
+    pub fts_rebuilt: bool,
+    pub vacuumed: bool,
+    pub size_before: u64,
+    pub size_after: u64,
+}
+
+impl DbHandler {
+    // Tidies the database after bulk ingestion or deletes:
+    // prunes unused words and lemmas, rebuilds the FTS and b-tree indexes, refreshes the
+    // query planner statistics, and with `vacuum` also reclaims free pages
+    // VACUUM rewrites the whole file, so it can take a while on large databases
+    pub fn optimize(&mut self, vacuum: bool) -> Result<OptimizeReport> {
+        let size_before = self.database_size()?;
+        let pruned_words = self.conn.execute(
+            "DELETE FROM words WHERE NOT EXISTS
+             (SELECT 1 FROM word_occurrences WHERE word_occurrences.word_id = words.id)",
+            [],
+        )?;
+        self.conn.execute(
+            "DELETE FROM lemmas WHERE NOT EXISTS
+             (SELECT 1 FROM words WHERE words.lemma_id = lemmas.id)",
+            [],
+        )?;
+
+        let fts_rebuilt = self.has_fts_table()?;
+        if fts_rebuilt {
+            self.conn.execute_batch(
+                "INSERT INTO transcripts_fts (transcripts_fts) VALUES ('rebuild');
+                 INSERT INTO transcripts_fts (transcripts_fts) VALUES ('optimize');",
+            )?;
+        }
+
+        self.conn
+            .execute_batch("REINDEX; ANALYZE; PRAGMA optimize;")?;
+        if vacuum {
+            self.conn.execute_batch("VACUUM")?;
+        }
+
+        Ok(OptimizeReport {
+            pruned_words,
+            fts_rebuilt,
+            vacuumed: vacuum,
+            size_before,
+            size_after: self.database_size()?,
+        })
+    }
+
+    // Size of the database file in bytes, from the page count
+    pub fn database_size(&self) -> Result<u64> {
+        let page_count: u64 = self
+            .conn
+            .query_row("PRAGMA page_count", [], |row| row.get(0))?;
+        let page_size: u64 = self
+            .conn
+            .query_row("PRAGMA page_size", [], |row| row.get(0))?;
+        Ok(page_count * page_size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_utils::test_db_with_lines;
+    use crate::tokenizer::test_utils::test_tokenizer;
+
+    #[test]
+    fn test_optimize_prunes_unused_words() {
+        let (mut db, ids) = test_db_with_lines(&["猫が好き", "犬が好き"]);
+        db.index_transcripts(&test_tokenizer(), &ids).unwrap();
+        db.create_fts_table().unwrap();
+        db.delete_episode_index(crate::db::EpisodeId(1)).unwrap();
+
+        let report = db.optimize(true).unwrap();
+        assert_eq!(report.pruned_words, 3);
+        assert!(report.fts_rebuilt);
+        assert!(report.vacuumed);
+        assert_eq!(db.optimize(false).unwrap().pruned_words, 0);
+    }
+}
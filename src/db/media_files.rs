@@ -0,0 +1,89 @@
+use super::{DbHandler, EpisodeId, TranscriptId};
+use rusqlite::{params, OptionalExtension, Result};
+use std::collections::HashMap;
+
+/// The video or audio file an episode's subtitles belong to, as recorded in the `media_files` table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MediaFile {
+    pub episode_id: EpisodeId,
+    pub path: String,
+}
+
+impl DbHandler {
+    // Maps episodes to media files, replacing earlier mappings of the same episodes
+    pub fn record_media_files(&mut self, files: &[MediaFile]) -> Result<()> {
+        let tx = self.conn.savepoint()?;
+        {
+            let mut stmt = tx.prepare_cached(
+                "INSERT OR REPLACE INTO media_files (episode_id, path) VALUES (?, ?)",
+            )?;
+            for file in files {
+                stmt.execute(params![file.episode_id, file.path])?;
+            }
+        }
+        tx.commit()
+    }
+
+    // The media file mapped to an episode, if any
+    pub fn media_file(&self, episode_id: EpisodeId) -> Result<Option<String>> {
+        self.conn
+            .prepare_cached("SELECT path FROM media_files WHERE episode_id = ?")?
+            .query_row(params![episode_id], |row| row.get(0))
+            .optional()
+    }
+
+    // The media file of a line's episode, if any
+    pub fn line_media_file(&self, transcript_id: TranscriptId) -> Result<Option<String>> {
+        self.conn
+            .prepare_cached(
+                "SELECT media_files.path FROM transcripts
+                 JOIN media_files ON media_files.episode_id = transcripts.episode_id
+                 WHERE transcripts.id = ?",
+            )?
+            .query_row(params![transcript_id], |row| row.get(0))
+            .optional()
+    }
+
+    // Every mapped media file, keyed by episode
+    pub fn media_files(&self) -> Result<HashMap<EpisodeId, String>> {
+        let mut stmt = self
+            .conn
+            .prepare_cached("SELECT episode_id, path FROM media_files")?;
+        let files = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect();
+        files
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_utils::test_db_with_lines;
+    use super::*;
+
+    #[test]
+    fn test_record_media_files() {
+        let (mut db, ids) = test_db_with_lines(&["猫"]);
+        let episode_id = db.get_transcript(ids[0]).unwrap().unwrap().episode_id;
+        for path in ["/videos/old.mkv", "/videos/Show 01.mkv"] {
+            db.record_media_files(&[MediaFile {
+                episode_id,
+                path: path.to_string(),
+            }])
+            .unwrap();
+        }
+        assert_eq!(
+            db.media_file(episode_id).unwrap().as_deref(),
+            Some("/videos/Show 01.mkv")
+        );
+        assert_eq!(db.media_files().unwrap().len(), 1);
+        assert_eq!(
+            db.line_media_file(ids[0]).unwrap().as_deref(),
+            Some("/videos/Show 01.mkv")
+        );
+
+        // Deleting the episode forgets its media file
+        db.delete_episode("Show Name", 1, Some(1)).unwrap();
+        assert_eq!(db.media_file(episode_id).unwrap(), None);
+    }
+}
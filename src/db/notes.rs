@@ -0,0 +1,183 @@
+use super::{DbHandler, SearchHit, ShowId, TranscriptId};
+use rusqlite::{params, Result, Row};
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// A free-text note on a line or a whole show, as stored in the `notes` table.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Note {
+    pub id: i64,
+    /// The line the note is on, for line notes
+    pub transcript_id: Option<TranscriptId>,
+    /// The show the note is on, for show notes
+    pub show_id: Option<ShowId>,
+    pub text: String,
+    pub created_at: String,
+}
+
+impl Note {
+    fn from_row(row: &Row) -> Result<Self> {
+        Ok(Note {
+            id: row.get(0)?,
+            transcript_id: row.get(1)?,
+            show_id: row.get(2)?,
+            text: row.get(3)?,
+            created_at: row.get(4)?,
+        })
+    }
+}
+
+const NOTE_SELECT: &str =
+    "SELECT notes.id, notes.transcript_id, notes.show_id, notes.text, notes.created_at FROM notes";
+
+// The trigram tokenizer can't match anything shorter than this
+const MIN_FTS_QUERY_CHARS: usize = 3;
+
+impl DbHandler {
+    // Attaches a note to a line; returns the note's id
+    pub fn add_line_note(&self, transcript_id: TranscriptId, text: &str) -> Result<i64> {
+        self.conn.execute(
+            "INSERT INTO notes (transcript_id, text, created_at) VALUES (?, ?, CURRENT_TIMESTAMP)",
+            params![transcript_id, text],
+        )?;
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    // Attaches a note to a show; returns the note's id
+    pub fn add_show_note(&self, show_id: ShowId, text: &str) -> Result<i64> {
+        self.conn.execute(
+            "INSERT INTO notes (show_id, text, created_at) VALUES (?, ?, CURRENT_TIMESTAMP)",
+            params![show_id, text],
+        )?;
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    // Deletes a note; returns whether there was one with that id
+    pub fn delete_note(&self, id: i64) -> Result<bool> {
+        let deleted = self
+            .conn
+            .execute("DELETE FROM notes WHERE id = ?", params![id])?;
+        Ok(deleted > 0)
+    }
+
+    // The notes on each of the given lines that has any, oldest first
+    pub fn line_notes(
+        &self,
+        transcript_ids: &[TranscriptId],
+    ) -> Result<HashMap<TranscriptId, Vec<Note>>> {
+        let sql = format!(
+            "{} WHERE notes.transcript_id = ? ORDER BY notes.id",
+            NOTE_SELECT
+        );
+        let mut stmt = self.conn.prepare_cached(&sql)?;
+        let mut notes = HashMap::new();
+        for &transcript_id in transcript_ids {
+            let line_notes = stmt
+                .query_map(params![transcript_id], Note::from_row)?
+                .collect::<Result<Vec<_>>>()?;
+            if !line_notes.is_empty() {
+                notes.insert(transcript_id, line_notes);
+            }
+        }
+        Ok(notes)
+    }
+
+    // The notes on a show, oldest first
+    pub fn show_notes(&self, show_id: ShowId) -> Result<Vec<Note>> {
+        let sql = format!("{} WHERE notes.show_id = ? ORDER BY notes.id", NOTE_SELECT);
+        let mut stmt = self.conn.prepare_cached(&sql)?;
+        let notes = stmt.query_map(params![show_id], Note::from_row)?.collect();
+        notes
+    }
+
+    // The notes containing `text`, on lines and shows alike, best match first
+    // Uses the full-text index, except for text too short for it, which scans every note
+    pub fn search_notes(&self, text: &str) -> Result<Vec<Note>> {
+        let text = text.trim();
+        if text.is_empty() {
+            return Ok(Vec::new());
+        }
+        if text.chars().count() < MIN_FTS_QUERY_CHARS {
+            let sql = format!(
+                "{} WHERE instr(notes.text, ?) > 0 ORDER BY notes.id",
+                NOTE_SELECT
+            );
+            let mut stmt = self.conn.prepare_cached(&sql)?;
+            let notes = stmt.query_map(params![text], Note::from_row)?.collect();
+            return notes;
+        }
+        // Quoted, so FTS5 query syntax in the text is taken literally
+        let query = format!("\"{}\"", text.replace('"', "\"\""));
+        let sql = format!(
+            "{} JOIN notes_fts ON notes_fts.rowid = notes.id
+             WHERE notes_fts MATCH ? ORDER BY notes_fts.rank",
+            NOTE_SELECT
+        );
+        let mut stmt = self.conn.prepare_cached(&sql)?;
+        let notes = stmt.query_map(params![query], Note::from_row)?.collect();
+        notes
+    }
+
+    // The lines with a note containing `text`, in corpus order
+    pub fn find_lines_with_notes(&self, text: &str) -> Result<Vec<SearchHit>> {
+        let mut ids: Vec<TranscriptId> = self
+            .search_notes(text)?
+            .into_iter()
+            .filter_map(|note| note.transcript_id)
+            .collect();
+        ids.sort();
+        ids.dedup();
+        let mut hits = self.find_hits_by_ids(&ids)?;
+        hits.sort_by(|a, b| {
+            (&a.show_name, a.season, a.episode_number, a.line_id).cmp(&(
+                &b.show_name,
+                b.season,
+                b.episode_number,
+                b.line_id,
+            ))
+        });
+        Ok(hits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_utils::test_db_with_lines;
+
+    #[test]
+    fn test_notes() {
+        let (db, ids) = test_db_with_lines(&["猫が好き", "犬が好き"]);
+        let show_id = db.find_show_id("Show Name").unwrap().unwrap();
+        let first = db
+            .add_line_note(ids[1], "Casual way to say you like something")
+            .unwrap();
+        db.add_line_note(ids[0], "好き is a na-adjective").unwrap();
+        db.add_show_note(show_id, "Slow, clear speech; good for beginners")
+            .unwrap();
+
+        let notes = db.line_notes(&ids).unwrap();
+        assert_eq!(notes[&ids[1]][0].id, first);
+        assert_eq!(notes[&ids[0]][0].text, "好き is a na-adjective");
+        assert_eq!(db.show_notes(show_id).unwrap().len(), 1);
+
+        // Through the full-text index, on lines and shows alike
+        let found = db.search_notes("like").unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].transcript_id, Some(ids[1]));
+        assert_eq!(
+            db.search_notes("beginner").unwrap()[0].show_id,
+            Some(show_id)
+        );
+        assert_eq!(db.search_notes("adjective").unwrap().len(), 1);
+        // Too short for the index
+        assert_eq!(db.search_notes("好き").unwrap().len(), 1);
+
+        let hits = db.find_lines_with_notes("a").unwrap();
+        let texts: Vec<&str> = hits.iter().map(|hit| hit.text.as_str()).collect();
+        assert_eq!(texts, ["猫が好き", "犬が好き"]);
+
+        assert!(db.delete_note(first).unwrap());
+        assert!(!db.delete_note(first).unwrap());
+        assert!(db.search_notes("like").unwrap().is_empty());
+    }
+}
@@ -0,0 +1,73 @@
+use super::DbHandler;
+use crate::tokenizer::katakana_to_hiragana;
+use rusqlite::{params, OptionalExtension, Result};
+use std::io::BufRead;
+
+impl DbHandler {
+    // Loads a pitch-accent dictionary of tab-separated `word, reading, accent` lines,
+    // the layout used by the Kanjium/NHK accent lists (e.g. 日本	にほん	2)
+    // The accent column is stored as written, since a word may list several patterns ("1,0")
+    // Readings are stored in hiragana so katakana readings from the tokenizer match them
+    // Returns the number of entries read
+    pub fn import_pitch_accents<R: BufRead>(&mut self, reader: R) -> crate::error::Result<usize> {
+        let tx = self.conn.savepoint()?;
+        let mut count = 0;
+        {
+            let mut insert = tx.prepare_cached(
+                "INSERT OR REPLACE INTO pitch_accents (word, reading, accent) VALUES (?, ?, ?)",
+            )?;
+            for line in reader.lines() {
+                let line = line?;
+                if line.trim().is_empty() || line.starts_with('#') {
+                    continue;
+                }
+                let mut fields = line.split('\t');
+                let (Some(word), Some(reading), Some(accent)) =
+                    (fields.next(), fields.next(), fields.next())
+                else {
+                    continue;
+                };
+                insert.execute(params![
+                    word.trim(),
+                    katakana_to_hiragana(reading.trim()),
+                    accent.trim()
+                ])?;
+                count += 1;
+            }
+        }
+        tx.commit()?;
+        Ok(count)
+    }
+
+    // Looks up the accent pattern of a dictionary form
+    // When the word has several readings, the one matching `reading` is preferred
+    pub fn pitch_accent(&self, word: &str, reading: Option<&str>) -> Result<Option<String>> {
+        let reading = reading.map(katakana_to_hiragana).unwrap_or_default();
+        self.conn
+            .prepare_cached(
+                "SELECT accent FROM pitch_accents WHERE word = ?
+                 ORDER BY reading = ? DESC, reading LIMIT 1",
+            )?
+            .query_row(params![word, reading], |row| row.get(0))
+            .optional()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_utils::test_db_with_lines;
+
+    #[test]
+    fn test_import_pitch_accents() {
+        let (mut db, _) = test_db_with_lines(&[]);
+        let data = "# comment\n箸\tはし\t1\n橋\tはし\t2\n端\tはし\t0\n端\tはた\t0\nbroken line\n";
+        assert_eq!(db.import_pitch_accents(data.as_bytes()).unwrap(), 4);
+
+        assert_eq!(db.pitch_accent("橋", None).unwrap().as_deref(), Some("2"));
+        assert_eq!(
+            db.pitch_accent("端", Some("ハタ")).unwrap().as_deref(),
+            Some("0")
+        );
+        assert_eq!(db.pitch_accent("猫", None).unwrap(), None);
+    }
+}
@@ -0,0 +1,209 @@
+use super::{register_functions, ConflictPolicies, DbHandler};
+use rusqlite::{Connection, OpenFlags, Result};
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::time::Duration;
+
+// How long a connection waits on a lock held by another connection before giving up
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Shares one database file between threads: a pool of read-only connections
+/// for searches, plus a single writer connection behind a mutex.
+///
+/// The database is switched to WAL mode, so searches keep running while the
+/// writer is ingesting. The path must point to a file; `:memory:` databases
+/// can't be shared between connections.
+pub struct DbPool {
+    path: PathBuf,
+    max_readers: usize,
+    readers: Mutex<Readers>,
+    reader_returned: Condvar,
+    writer: Mutex<DbHandler>,
+}
+
+struct Readers {
+    idle: Vec<DbHandler>,
+    open: usize,
+}
+
+impl DbPool {
+    /// Opens (creating if needed) the database at `path`, allowing up to
+    /// `max_readers` read-only connections at once. Readers are opened lazily.
+    pub fn open<P: AsRef<Path>>(path: P, max_readers: usize) -> Result<Self> {
+        let writer = DbHandler::new(&path)?;
+        writer.conn.busy_timeout(BUSY_TIMEOUT)?;
+        writer
+            .conn
+            .query_row("PRAGMA journal_mode = WAL", [], |row| {
+                row.get::<_, String>(0)
+            })?;
+        writer.create_tables()?;
+        Ok(DbPool {
+            path: path.as_ref().to_path_buf(),
+            max_readers: max_readers.max(1),
+            readers: Mutex::new(Readers {
+                idle: Vec::new(),
+                open: 0,
+            }),
+            reader_returned: Condvar::new(),
+            writer: Mutex::new(writer),
+        })
+    }
+
+    /// Takes a read-only handle from the pool, waiting for one to be returned
+    /// if `max_readers` are already in use.
+    pub fn reader(&self) -> Result<PooledReader<'_>> {
+        let mut readers = lock(&self.readers);
+        loop {
+            if let Some(handler) = readers.idle.pop() {
+                return Ok(PooledReader {
+                    pool: self,
+                    handler: Some(handler),
+                });
+            }
+            if readers.open < self.max_readers {
+                readers.open += 1;
+                drop(readers);
+                return match DbHandler::open_read_only(&self.path) {
+                    Ok(handler) => Ok(PooledReader {
+                        pool: self,
+                        handler: Some(handler),
+                    }),
+                    Err(e) => {
+                        lock(&self.readers).open -= 1;
+                        self.reader_returned.notify_one();
+                        Err(e)
+                    }
+                };
+            }
+            readers = self
+                .reader_returned
+                .wait(readers)
+                .unwrap_or_else(|e| e.into_inner());
+        }
+    }
+
+    /// Locks the writer connection. Only one writer exists, so concurrent
+    /// ingests queue up here instead of failing with SQLITE_BUSY.
+    pub fn writer(&self) -> MutexGuard<'_, DbHandler> {
+        lock(&self.writer)
+    }
+}
+
+// A panic while holding a handle doesn't leave the connection in a broken state,
+// so poisoned locks are recovered rather than propagated
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// A read-only handle borrowed from a [`DbPool`]; returned to the pool when dropped.
+pub struct PooledReader<'a> {
+    pool: &'a DbPool,
+    handler: Option<DbHandler>,
+}
+
+impl Deref for PooledReader<'_> {
+    type Target = DbHandler;
+
+    fn deref(&self) -> &DbHandler {
+        self.handler.as_ref().unwrap()
+    }
+}
+
+impl Drop for PooledReader<'_> {
+    fn drop(&mut self) {
+        if let Some(handler) = self.handler.take() {
+            lock(&self.pool.readers).idle.push(handler);
+            self.pool.reader_returned.notify_one();
+        }
+    }
+}
+
+impl DbHandler {
+    // Opens an existing database for reading only
+    // Any attempt to write through the handle fails with SQLITE_READONLY
+    pub fn open_read_only<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let conn = Connection::open_with_flags(
+            path,
+            OpenFlags::SQLITE_OPEN_READ_ONLY
+                | OpenFlags::SQLITE_OPEN_NO_MUTEX
+                | OpenFlags::SQLITE_OPEN_URI,
+        )?;
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        conn.set_prepared_statement_cache_capacity(32);
+        register_functions(&conn)?;
+        Ok(DbHandler {
+            conn,
+            conflicts: ConflictPolicies::default(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{NewShow, ShowId};
+    use std::thread;
+
+    // A database file in the temp directory, deleted along with its WAL files on drop
+    struct TempDbPath(PathBuf);
+
+    impl TempDbPath {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("{}-{}.db", name, std::process::id()));
+            TempDbPath(path)
+        }
+    }
+
+    impl Drop for TempDbPath {
+        fn drop(&mut self) {
+            for suffix in ["", "-wal", "-shm"] {
+                let mut path = self.0.clone().into_os_string();
+                path.push(suffix);
+                let _ = std::fs::remove_file(path);
+            }
+        }
+    }
+
+    fn count_shows(db: &DbHandler) -> i64 {
+        db.conn
+            .query_row("SELECT COUNT(*) FROM shows", [], |row| row.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_pool_concurrent_readers() {
+        let path = TempDbPath::new("pool-readers");
+        let pool = DbPool::open(&path.0, 2).unwrap();
+        let id = pool
+            .writer()
+            .insert_show(&NewShow {
+                name: "Show Name".to_string(),
+                show_type: "Anime".to_string(),
+            })
+            .unwrap();
+        assert_eq!(id, ShowId(1));
+
+        thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| assert_eq!(count_shows(&pool.reader().unwrap()), 1));
+            }
+        });
+        assert!(lock(&pool.readers).open <= 2);
+    }
+
+    #[test]
+    fn test_reader_is_read_only() {
+        let path = TempDbPath::new("pool-read-only");
+        let pool = DbPool::open(&path.0, 1).unwrap();
+        let reader = pool.reader().unwrap();
+        assert!(reader
+            .insert_show(&NewShow {
+                name: "Show Name".to_string(),
+                show_type: "Anime".to_string(),
+            })
+            .is_err());
+        assert_eq!(count_shows(&reader), 0);
+    }
+}
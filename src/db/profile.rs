@@ -0,0 +1,85 @@
+use super::DbHandler;
+use rusqlite::types::Value;
+use rusqlite::{params_from_iter, Result, StatementStatus};
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// How SQLite ran a query, as reported by `bench` and `search --explain`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct QueryProfile {
+    /// The steps of EXPLAIN QUERY PLAN, indented two spaces per level of nesting
+    pub plan: Vec<String>,
+    /// Rows the query returned
+    pub rows: usize,
+    /// Rows stepped through in full table scans, which an index would have avoided
+    pub rows_scanned: i64,
+    /// Virtual machine steps, a rough measure of the total work done
+    pub vm_steps: i64,
+}
+
+impl DbHandler {
+    // The query plan SQLite picks for `sql`, one step per line, nested steps indented
+    pub fn query_plan(&self, sql: &str, params: &[Value]) -> Result<Vec<String>> {
+        let mut stmt = self.conn.prepare(&format!("EXPLAIN QUERY PLAN {}", sql))?;
+        let mut depths: HashMap<i64, usize> = HashMap::new();
+        let mut plan = Vec::new();
+        let mut rows = stmt.query(params_from_iter(params))?;
+        while let Some(row) = rows.next()? {
+            let id: i64 = row.get(0)?;
+            let parent: i64 = row.get(1)?;
+            let detail: String = row.get(3)?;
+            let depth = depths.get(&parent).map_or(0, |depth| depth + 1);
+            depths.insert(id, depth);
+            plan.push(format!("{}{}", "  ".repeat(depth), detail));
+        }
+        Ok(plan)
+    }
+
+    // Runs `sql` to completion, counting the rows it returns and the work it took
+    pub fn profile_query(&self, sql: &str, params: &[Value]) -> Result<QueryProfile> {
+        let plan = self.query_plan(sql, params)?;
+        let mut stmt = self.conn.prepare(sql)?;
+        let mut rows = 0;
+        {
+            let mut results = stmt.query(params_from_iter(params))?;
+            while results.next()?.is_some() {
+                rows += 1;
+            }
+        }
+        Ok(QueryProfile {
+            plan,
+            rows,
+            rows_scanned: stmt.get_status(StatementStatus::FullscanStep).into(),
+            vm_steps: stmt.get_status(StatementStatus::VmStep).into(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_utils::test_db_with_lines;
+    use super::*;
+
+    #[test]
+    fn test_profile_query() {
+        let (db, _) = test_db_with_lines(&["猫", "犬", "猫が走った"]);
+        let profile = db
+            .profile_query(
+                "SELECT id FROM transcripts WHERE text LIKE ?",
+                &[Value::Text("猫%".to_string())],
+            )
+            .unwrap();
+        assert_eq!(profile.rows, 2);
+        assert_eq!(profile.rows_scanned, 2);
+        assert!(profile.plan[0].starts_with("SCAN transcripts"));
+
+        let plan = db
+            .query_plan(
+                "SELECT * FROM transcripts WHERE id IN (SELECT transcript_id FROM translations
+                 GROUP BY transcript_id HAVING COUNT(*) > 1)",
+                &[],
+            )
+            .unwrap();
+        assert!(plan.contains(&"  SCAN translations".to_string()));
+    }
+}
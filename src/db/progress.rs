@@ -0,0 +1,152 @@
+use super::{DbHandler, EpisodeId, SearchHit, WordFrequency};
+use rusqlite::{params, Result};
+use serde::Serialize;
+use std::collections::HashSet;
+
+/// An episode a user marked as watched, as recorded in the `user_progress` table.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct WatchedEpisode {
+    pub episode_id: EpisodeId,
+    pub show_name: String,
+    pub season: i32,
+    pub episode_number: Option<i32>,
+    pub watched_at: String,
+}
+
+impl DbHandler {
+    // Marks episodes as watched by `user`; returns how many weren't already
+    pub fn mark_watched(&mut self, user: &str, episode_ids: &[EpisodeId]) -> Result<usize> {
+        let tx = self.conn.savepoint()?;
+        let mut marked = 0;
+        {
+            let mut stmt = tx.prepare_cached(
+                "INSERT OR IGNORE INTO user_progress (user, episode_id, watched_at)
+                 VALUES (?, ?, CURRENT_TIMESTAMP)",
+            )?;
+            for episode_id in episode_ids {
+                marked += stmt.execute(params![user, episode_id])?;
+            }
+        }
+        tx.commit()?;
+        Ok(marked)
+    }
+
+    // Removes episodes from `user`'s watch history; returns how many were in it
+    pub fn mark_unwatched(&mut self, user: &str, episode_ids: &[EpisodeId]) -> Result<usize> {
+        let tx = self.conn.savepoint()?;
+        let mut unmarked = 0;
+        {
+            let mut stmt =
+                tx.prepare_cached("DELETE FROM user_progress WHERE user = ? AND episode_id = ?")?;
+            for episode_id in episode_ids {
+                unmarked += stmt.execute(params![user, episode_id])?;
+            }
+        }
+        tx.commit()?;
+        Ok(unmarked)
+    }
+
+    // The episodes `user` has watched, in show and episode order
+    pub fn watched_episodes(&self, user: &str) -> Result<Vec<WatchedEpisode>> {
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT episodes.id, shows.name, episodes.season, episodes.episode_number,
+                    user_progress.watched_at
+             FROM user_progress
+             JOIN episodes ON episodes.id = user_progress.episode_id
+             JOIN shows ON shows.id = episodes.show_id
+             WHERE user_progress.user = ?
+             ORDER BY shows.name, episodes.season, episodes.episode_number",
+        )?;
+        let episodes = stmt
+            .query_map(params![user], |row| {
+                Ok(WatchedEpisode {
+                    episode_id: row.get(0)?,
+                    show_name: row.get(1)?,
+                    season: row.get(2)?,
+                    episode_number: row.get(3)?,
+                    watched_at: row.get(4)?,
+                })
+            })?
+            .collect();
+        episodes
+    }
+
+    // Drops the hits from episodes `user` hasn't watched, so results hold no spoilers
+    pub fn retain_watched(&self, user: &str, hits: &mut Vec<SearchHit>) -> Result<()> {
+        let watched: HashSet<(String, i32, Option<i32>)> = self
+            .watched_episodes(user)?
+            .into_iter()
+            .map(|episode| (episode.show_name, episode.season, episode.episode_number))
+            .collect();
+        hits.retain(|hit| {
+            watched.contains(&(hit.show_name.clone(), hit.season, hit.episode_number))
+        });
+        Ok(())
+    }
+
+    // The indexed words occurring at least `min_count` times in the episodes `user` has watched,
+    // the most frequent first
+    pub fn encountered_words(&self, user: &str, min_count: i64) -> Result<Vec<WordFrequency>> {
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT words.word, COUNT(*) AS encounters
+             FROM user_progress
+             JOIN transcripts ON transcripts.episode_id = user_progress.episode_id
+             JOIN word_occurrences ON word_occurrences.transcript_id = transcripts.id
+             JOIN words ON words.id = word_occurrences.word_id
+             WHERE user_progress.user = ?
+             GROUP BY words.word
+             HAVING encounters >= ?
+             ORDER BY encounters DESC, words.word",
+        )?;
+        let words = stmt
+            .query_map(params![user, min_count], |row| {
+                Ok(WordFrequency {
+                    word: row.get(0)?,
+                    frequency: row.get(1)?,
+                })
+            })?
+            .collect();
+        words
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_utils::{insert_show_lines, test_db_with_lines};
+    use crate::tokenizer::test_utils::test_tokenizer;
+
+    #[test]
+    fn test_watch_history() {
+        let (mut db, mut ids) = test_db_with_lines(&["猫が好き", "猫だ"]);
+        ids.extend(insert_show_lines(&mut db, "Other Show", &["猫と犬"]));
+        db.index_transcripts(&test_tokenizer(), &ids).unwrap();
+        let watched = db
+            .find_episode_id("Show Name", 1, Some(1))
+            .unwrap()
+            .unwrap();
+        let unwatched = db
+            .find_episode_id("Other Show", 1, Some(1))
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(db.mark_watched("alice", &[watched]).unwrap(), 1);
+        assert_eq!(db.mark_watched("alice", &[watched]).unwrap(), 0);
+        assert_eq!(db.watched_episodes("alice").unwrap()[0].episode_id, watched);
+        assert!(db.watched_episodes("bob").unwrap().is_empty());
+
+        let words = db.encountered_words("alice", 2).unwrap();
+        assert_eq!(words.len(), 1);
+        assert_eq!((words[0].word.as_str(), words[0].frequency), ("猫", 2));
+
+        let mut hits = db.find_lines_with_words(&["猫".to_string()]).unwrap();
+        assert_eq!(hits.len(), 3);
+        db.retain_watched("alice", &mut hits).unwrap();
+        assert!(hits.iter().all(|hit| hit.show_name == "Show Name"));
+
+        assert_eq!(
+            db.mark_unwatched("alice", &[watched, unwatched]).unwrap(),
+            1
+        );
+        assert!(db.watched_episodes("alice").unwrap().is_empty());
+    }
+}
@@ -0,0 +1,135 @@
+use super::{DbHandler, Episode, EpisodeId, Show, ShowId, Transcript, TranscriptId};
+use rusqlite::{params, OptionalExtension, Result, Row};
+
+impl Show {
+    fn from_row(row: &Row) -> Result<Self> {
+        Ok(Show {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            show_type: row.get(2)?,
+            corpus: row.get(3)?,
+        })
+    }
+}
+
+impl Episode {
+    fn from_row(row: &Row) -> Result<Self> {
+        Ok(Episode {
+            id: row.get(0)?,
+            show_id: row.get(1)?,
+            name: row.get(2)?,
+            season: row.get(3)?,
+            episode_number: row.get(4)?,
+        })
+    }
+}
+
+impl Transcript {
+    fn from_row(row: &Row) -> Result<Self> {
+        Ok(Transcript {
+            id: row.get(0)?,
+            episode_id: row.get(1)?,
+            line_id: row.get(2)?,
+            time_start: row.get(3)?,
+            time_end: row.get(4)?,
+            text: row.get(5)?,
+            text_raw: row.get(6)?,
+            speaker: row.get(7)?,
+            stable_id: row.get(8)?,
+        })
+    }
+}
+
+const TRANSCRIPT_COLUMNS: &str =
+    "id, episode_id, line_id, time_start, time_end, text, text_raw, speaker, stable_id";
+
+impl DbHandler {
+    // Lists every show, ordered by name
+    pub fn list_shows(&self) -> Result<Vec<Show>> {
+        let mut stmt = self
+            .conn
+            .prepare_cached("SELECT id, name, show_type, corpus FROM shows ORDER BY name")?;
+        let shows = stmt.query_map([], Show::from_row)?.collect();
+        shows
+    }
+
+    // Lists the episodes of a show, ordered by season and episode number
+    pub fn list_episodes(&self, show_id: ShowId) -> Result<Vec<Episode>> {
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT id, show_id, name, season, episode_number FROM episodes
+             WHERE show_id = ? ORDER BY season, episode_number",
+        )?;
+        let episodes = stmt
+            .query_map(params![show_id], Episode::from_row)?
+            .collect();
+        episodes
+    }
+
+    // Returns every line of an episode in subtitle order
+    pub fn get_episode_lines(&self, episode_id: EpisodeId) -> Result<Vec<Transcript>> {
+        let mut stmt = self.conn.prepare_cached(&format!(
+            "SELECT {} FROM transcripts WHERE episode_id = ? ORDER BY line_id",
+            TRANSCRIPT_COLUMNS
+        ))?;
+        let lines = stmt
+            .query_map(params![episode_id], Transcript::from_row)?
+            .collect();
+        lines
+    }
+
+    // Returns a single line by id, or None if it doesn't exist
+    pub fn get_transcript(&self, id: TranscriptId) -> Result<Option<Transcript>> {
+        self.conn
+            .prepare_cached(&format!(
+                "SELECT {} FROM transcripts WHERE id = ?",
+                TRANSCRIPT_COLUMNS
+            ))?
+            .query_row(params![id], Transcript::from_row)
+            .optional()
+    }
+
+    // Every episode's id, in insertion order
+    pub fn all_episode_ids(&self) -> Result<Vec<EpisodeId>> {
+        let mut stmt = self
+            .conn
+            .prepare_cached("SELECT id FROM episodes ORDER BY id")?;
+        let ids = stmt.query_map([], |row| row.get(0))?.collect();
+        ids
+    }
+
+    // Every line's id, in insertion order
+    pub fn all_transcript_ids(&self) -> Result<Vec<TranscriptId>> {
+        let mut stmt = self
+            .conn
+            .prepare_cached("SELECT id FROM transcripts ORDER BY id")?;
+        let ids = stmt.query_map([], |row| row.get(0))?.collect();
+        ids
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_utils::test_db_with_lines;
+    use super::*;
+
+    #[test]
+    fn test_read_api() {
+        let (db, ids) = test_db_with_lines(&["一行目", "二行目"]);
+
+        let shows = db.list_shows().unwrap();
+        assert_eq!(shows.len(), 1);
+        assert_eq!(shows[0].name, "Show Name");
+
+        let episodes = db.list_episodes(shows[0].id).unwrap();
+        assert_eq!(episodes.len(), 1);
+        assert_eq!(episodes[0].episode_number, Some(1));
+
+        let lines = db.get_episode_lines(episodes[0].id).unwrap();
+        let texts: Vec<&str> = lines.iter().map(|line| line.text.as_str()).collect();
+        assert_eq!(texts, ["一行目", "二行目"]);
+
+        let line = db.get_transcript(ids[1]).unwrap().unwrap();
+        assert_eq!(line, lines[1]);
+        assert_eq!(db.get_transcript(TranscriptId(999)).unwrap(), None);
+    }
+}
@@ -0,0 +1,132 @@
+use super::DbHandler;
+use rusqlite::{params, Result, Row};
+use serde::Serialize;
+
+/// A search to record in the `query_log` table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NewLoggedQuery {
+    pub query: String,
+    /// How the query was interpreted: `words`, `regex`, `kanji` or `grammar`
+    pub kind: String,
+    /// The options that narrowed or changed the search, e.g. `show=Show Name watched`
+    pub filters: String,
+    pub hits: usize,
+    pub latency_micros: u64,
+}
+
+/// A search recorded in the `query_log` table.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LoggedQuery {
+    pub id: i64,
+    pub query: String,
+    pub kind: String,
+    pub filters: String,
+    pub hits: usize,
+    pub latency_micros: u64,
+    pub logged_at: String,
+}
+
+impl LoggedQuery {
+    fn from_row(row: &Row) -> Result<Self> {
+        Ok(LoggedQuery {
+            id: row.get(0)?,
+            query: row.get(1)?,
+            kind: row.get(2)?,
+            filters: row.get(3)?,
+            hits: row.get(4)?,
+            latency_micros: row.get(5)?,
+            logged_at: row.get(6)?,
+        })
+    }
+}
+
+const LOGGED_QUERY_SELECT: &str =
+    "SELECT id, query, kind, filters, hits, latency_micros, logged_at FROM query_log";
+
+impl DbHandler {
+    // Records a search, timestamped now
+    pub fn log_query(&self, query: &NewLoggedQuery) -> Result<()> {
+        self.conn
+            .prepare_cached(
+                "INSERT INTO query_log (query, kind, filters, hits, latency_micros, logged_at)
+                 VALUES (?, ?, ?, ?, ?, CURRENT_TIMESTAMP)",
+            )?
+            .execute(params![
+                query.query,
+                query.kind,
+                query.filters,
+                query.hits,
+                query.latency_micros
+            ])?;
+        Ok(())
+    }
+
+    // The `limit` most recent searches, newest first
+    pub fn recent_queries(&self, limit: usize) -> Result<Vec<LoggedQuery>> {
+        let sql = format!("{} ORDER BY id DESC LIMIT ?", LOGGED_QUERY_SELECT);
+        let mut stmt = self.conn.prepare_cached(&sql)?;
+        let queries = stmt
+            .query_map(params![limit as i64], LoggedQuery::from_row)?
+            .collect();
+        queries
+    }
+
+    // The `limit` slowest searches that took at least `min_micros`, slowest first
+    pub fn slow_queries(&self, min_micros: u64, limit: usize) -> Result<Vec<LoggedQuery>> {
+        let sql = format!(
+            "{} WHERE latency_micros >= ? ORDER BY latency_micros DESC, id DESC LIMIT ?",
+            LOGGED_QUERY_SELECT
+        );
+        let mut stmt = self.conn.prepare_cached(&sql)?;
+        let queries = stmt
+            .query_map(params![min_micros, limit as i64], LoggedQuery::from_row)?
+            .collect();
+        queries
+    }
+
+    // Deletes every recorded search; returns the number deleted
+    pub fn clear_query_log(&self) -> Result<usize> {
+        self.conn.execute("DELETE FROM query_log", [])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_utils::test_db_with_lines;
+    use super::*;
+
+    fn logged(query: &str, latency_micros: u64) -> NewLoggedQuery {
+        NewLoggedQuery {
+            query: query.to_string(),
+            kind: "words".to_string(),
+            filters: String::new(),
+            hits: 1,
+            latency_micros,
+        }
+    }
+
+    #[test]
+    fn test_query_log() {
+        let (db, _) = test_db_with_lines(&[]);
+        db.log_query(&logged("猫", 300)).unwrap();
+        db.log_query(&logged("犬", 5_000)).unwrap();
+        db.log_query(&logged("走る", 1_000)).unwrap();
+
+        let recent: Vec<String> = db
+            .recent_queries(2)
+            .unwrap()
+            .into_iter()
+            .map(|query| query.query)
+            .collect();
+        assert_eq!(recent, ["走る", "犬"]);
+
+        let slow = db.slow_queries(1_000, 10).unwrap();
+        assert_eq!(slow.len(), 2);
+        assert_eq!(slow[0].query, "犬");
+        assert_eq!(slow[0].latency_micros, 5_000);
+        assert!(!slow[0].logged_at.is_empty());
+
+        assert_eq!(db.clear_query_log().unwrap(), 3);
+        assert!(db.recent_queries(10).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,482 @@
+use super::{deep_link, DbHandler, TimeRangeConfig, TranscriptId, INSERT_CHUNK_SIZE};
+use crate::tokenizer::{is_kana, is_kanji, katakana_to_hiragana, normalize};
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, OptionalExtension, Result, Row, ToSql};
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::ops::ControlFlow;
+
+/// A transcript line matching a search, with the show and episode it belongs to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SearchHit {
+    pub transcript_id: TranscriptId,
+    pub show_name: String,
+    pub season: i32,
+    /// `None` for a movie
+    pub episode_number: Option<i32>,
+    pub line_id: i32,
+    pub time_start: String,
+    pub time_end: String,
+    pub text: String,
+    /// English translation from a paired subtitle file, if the line has one
+    pub translation: Option<String>,
+    /// The line's content-derived id (see [`super::stable_line_id`])
+    pub stable_id: String,
+    /// Where the line's episode can be found online, if that's known (see [`super::Source`])
+    pub source_url: Option<String>,
+    /// A link to the line's moment in the episode's video online, if its
+    /// source can be linked to at a time (see [`super::deep_link`])
+    pub deep_link: Option<String>,
+}
+
+// Selects every SearchHit column; callers append their own WHERE clause
+pub(crate) const SEARCH_HIT_SELECT: &str = "
+    SELECT transcripts.id, shows.name, episodes.season, episodes.episode_number,
+           transcripts.line_id, transcripts.time_start, transcripts.time_end, transcripts.text,
+           translations.text, transcripts.stable_id, sources.url
+    FROM transcripts
+    LEFT JOIN translations ON translations.transcript_id = transcripts.id
+    JOIN episodes ON episodes.id = transcripts.episode_id
+    LEFT JOIN sources ON sources.episode_id = episodes.id
+    JOIN shows ON shows.id = episodes.show_id";
+
+pub(crate) const SEARCH_HIT_ORDER: &str =
+    "ORDER BY shows.name, episodes.season, episodes.episode_number, transcripts.line_id";
+
+// Keeps occurrences tagged with a part of speech or one of its subcategories
+// (動詞 also matches 動詞-自立); the POS is bound to each of the three parameters
+// It's compared by prefix rather than with LIKE, so % and _ in it are literal
+const POS_CONDITION: &str = "AND (word_occurrences.pos = ?
+     OR substr(word_occurrences.pos, 1, length(?) + 1) = ? || '-')";
+
+impl SearchHit {
+    pub(crate) fn from_row(row: &Row) -> Result<Self> {
+        let time_start: String = row.get(5)?;
+        let source_url: Option<String> = row.get(10)?;
+        let link = source_url
+            .as_deref()
+            .and_then(|url| deep_link(url, &time_start));
+        Ok(SearchHit {
+            transcript_id: row.get(0)?,
+            show_name: row.get(1)?,
+            season: row.get(2)?,
+            episode_number: row.get(3)?,
+            line_id: row.get(4)?,
+            time_start,
+            time_end: row.get(6)?,
+            text: row.get(7)?,
+            translation: row.get(8)?,
+            stable_id: row.get(9)?,
+            source_url,
+            deep_link: link,
+        })
+    }
+}
+
+/// A query against the word index.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WordQuery {
+    /// Words that must all occur in a line
+    pub terms: Vec<QueryTerm>,
+    /// Pairs of words that must occur close to each other
+    pub proximity: Vec<Proximity>,
+    /// Match words only as written, so おもしろい doesn't also find 面白い
+    pub exact_script: bool,
+    /// The start and end of episodes to leave out, e.g. their opening and ending songs
+    pub time_range: TimeRangeConfig,
+    /// Only lines said by this speaker (`transcripts.speaker`), compared case-insensitively
+    pub speaker: Option<String>,
+}
+
+/// The SQL statement a [`WordQuery`] runs as, with its parameters.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WordQuerySql {
+    pub sql: String,
+    pub params: Vec<Value>,
+}
+
+/// A dictionary form, optionally restricted to a part-of-speech.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct QueryTerm {
+    pub word: String,
+    /// Part-of-speech tag the occurrence must start with, e.g. `動詞` or `名詞-一般`
+    pub pos: Option<String>,
+}
+
+impl QueryTerm {
+    pub fn word(word: impl Into<String>) -> Self {
+        QueryTerm {
+            word: word.into(),
+            pos: None,
+        }
+    }
+}
+
+/// Requires `left` and `right` to occur within `max_distance` tokens of each other.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Proximity {
+    pub left: String,
+    pub right: String,
+    pub max_distance: u32,
+}
+
+impl DbHandler {
+    // Finds every line whose word index contains all of the given words
+    pub fn find_lines_with_words(&self, words: &[String]) -> Result<Vec<SearchHit>> {
+        self.find_lines(&WordQuery {
+            terms: words.iter().map(QueryTerm::word).collect(),
+            ..WordQuery::default()
+        })
+    }
+
+    // Finds every line matching a word query
+    // Unless the query asks for the exact script, each word also matches its
+    // other spellings (see spelling_variants)
+    pub fn find_lines(&self, query: &WordQuery) -> Result<Vec<SearchHit>> {
+        let mut hits = Vec::new();
+        self.for_each_line(query, |hit| {
+            hits.push(hit);
+            ControlFlow::Continue(())
+        })?;
+        Ok(hits)
+    }
+
+    // Like find_lines, but hands each line to `f` as soon as it is read,
+    // stopping early when `f` breaks
+    pub fn for_each_line(
+        &self,
+        query: &WordQuery,
+        mut f: impl FnMut(SearchHit) -> ControlFlow<()>,
+    ) -> Result<()> {
+        let Some(query) = self.word_query_sql(query)? else {
+            return Ok(());
+        };
+        let mut stmt = self.conn.prepare_cached(&query.sql)?;
+        for hit in stmt.query_map(params_from_iter(&query.params), SearchHit::from_row)? {
+            if f(hit?).is_break() {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    // The SQL find_lines runs for a word query, or None if the query has neither words
+    // nor a speaker
+    pub fn word_query_sql(&self, query: &WordQuery) -> Result<Option<WordQuerySql>> {
+        if query.terms.is_empty() && query.speaker.is_none() {
+            return Ok(None);
+        }
+        let variants = |word: &String| -> Result<Vec<String>> {
+            if query.exact_script {
+                Ok(vec![word.clone()])
+            } else {
+                self.spelling_variants(word)
+            }
+        };
+        let term_variants = query
+            .terms
+            .iter()
+            .map(|term| variants(&term.word))
+            .collect::<Result<Vec<_>>>()?;
+        let proximity_variants = query
+            .proximity
+            .iter()
+            .map(|proximity| Ok((variants(&proximity.left)?, variants(&proximity.right)?)))
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut conditions = Vec::new();
+        let mut params: Vec<Value> = Vec::new();
+        let placeholders = |words: &[String]| vec!["?"; words.len()].join(", ");
+        let text = |word: &String| Value::Text(word.clone());
+
+        // Plain words with a single spelling are matched together in one grouped lookup
+        let is_grouped = |(term, spellings): &(&QueryTerm, &Vec<String>)| {
+            term.pos.is_none() && spellings.len() == 1
+        };
+        let terms: Vec<(&QueryTerm, &Vec<String>)> =
+            query.terms.iter().zip(&term_variants).collect();
+        let words: Vec<&String> = terms
+            .iter()
+            .filter(|term| is_grouped(term))
+            .map(|(term, _)| &term.word)
+            .collect();
+        if !words.is_empty() {
+            conditions.push(format!(
+                "transcripts.id IN (
+                    SELECT word_occurrences.transcript_id
+                    FROM word_occurrences JOIN words ON words.id = word_occurrences.word_id
+                    WHERE words.word IN ({})
+                    GROUP BY word_occurrences.transcript_id
+                    HAVING COUNT(DISTINCT words.word) = {}
+                )",
+                vec!["?"; words.len()].join(", "),
+                words.len()
+            ));
+            params.extend(words.iter().map(|w| text(w)));
+        }
+
+        // Words with several spellings or a part-of-speech filter each need their own lookup
+        for (term, spellings) in terms.iter().filter(|term| !is_grouped(term)) {
+            let pos_condition = if term.pos.is_some() {
+                POS_CONDITION
+            } else {
+                ""
+            };
+            conditions.push(format!(
+                "transcripts.id IN (
+                    SELECT word_occurrences.transcript_id
+                    FROM word_occurrences JOIN words ON words.id = word_occurrences.word_id
+                    WHERE words.word IN ({}) {}
+                )",
+                placeholders(spellings),
+                pos_condition
+            ));
+            params.extend(spellings.iter().map(text));
+            if let Some(pos) = &term.pos {
+                params.extend([text(pos), text(pos), text(pos)]);
+            }
+        }
+
+        for (proximity, (left, right)) in query.proximity.iter().zip(&proximity_variants) {
+            conditions.push(format!(
+                "transcripts.id IN (
+                    SELECT a.transcript_id
+                    FROM word_occurrences a
+                    JOIN words word_a ON word_a.id = a.word_id
+                    JOIN word_occurrences b ON b.transcript_id = a.transcript_id
+                    JOIN words word_b ON word_b.id = b.word_id
+                    WHERE word_a.word IN ({}) AND word_b.word IN ({})
+                      AND abs(a.position - b.position) <= ?
+                      AND a.position <> b.position
+                )",
+                placeholders(left),
+                placeholders(right)
+            ));
+            params.extend(left.iter().map(text));
+            params.extend(right.iter().map(text));
+            params.push(Value::Integer(proximity.max_distance.into()));
+        }
+
+        if let Some((condition, values)) = query.time_range.sql_condition() {
+            conditions.push(condition);
+            params.extend(values);
+        }
+
+        if let Some(speaker) = &query.speaker {
+            conditions.push("transcripts.speaker = ? COLLATE NOCASE".to_string());
+            params.push(text(speaker));
+        }
+
+        let sql = format!(
+            "{} WHERE {} {}",
+            SEARCH_HIT_SELECT,
+            conditions.join(" AND "),
+            SEARCH_HIT_ORDER
+        );
+        Ok(Some(WordQuerySql { sql, params }))
+    }
+
+    // Number of lines a word query matches, without reading them
+    pub fn count_lines(&self, query: &WordQuery) -> Result<usize> {
+        let Some(query) = self.word_query_sql(query)? else {
+            return Ok(0);
+        };
+        self.conn
+            .prepare(&format!("SELECT COUNT(*) FROM ({})", query.sql))?
+            .query_row(params_from_iter(&query.params), |row| row.get(0))
+    }
+
+    // Number of occurrences of any of `spellings` in the word index, and of
+    // distinct lines they occur in, optionally only where tagged with `pos`
+    pub fn word_postings(&self, spellings: &[String], pos: Option<&str>) -> Result<(usize, usize)> {
+        if spellings.is_empty() {
+            return Ok((0, 0));
+        }
+        let pos_condition = if pos.is_some() { POS_CONDITION } else { "" };
+        let sql = format!(
+            "SELECT COUNT(*), COUNT(DISTINCT word_occurrences.transcript_id)
+             FROM word_occurrences JOIN words ON words.id = word_occurrences.word_id
+             WHERE words.word IN ({}) {}",
+            vec!["?"; spellings.len()].join(", "),
+            pos_condition
+        );
+        let mut values: Vec<&dyn ToSql> = spellings.iter().map(|s| s as &dyn ToSql).collect();
+        if let Some(pos) = &pos {
+            values.extend([pos as &dyn ToSql, pos as &dyn ToSql, pos as &dyn ToSql]);
+        }
+        self.conn
+            .prepare_cached(&sql)?
+            .query_row(&values[..], |row| Ok((row.get(0)?, row.get(1)?)))
+    }
+
+    // The spellings of `word` in the word index that a search for it should match
+    // A word in kana matches every spelling with that reading (おもしろい finds 面白い);
+    // a word with kanji matches itself and the kana spellings of its reading,
+    // but not other kanji with the same reading (橋 doesn't find 箸)
+    pub fn spelling_variants(&self, word: &str) -> Result<Vec<String>> {
+        let is_kana_word = |w: &str| w.chars().all(is_kana);
+        let reading: Option<String> = self
+            .conn
+            .prepare_cached(
+                "SELECT lemmas.reading FROM words JOIN lemmas ON lemmas.id = words.lemma_id
+                 WHERE words.word = ?",
+            )?
+            .query_row(params![word], |row| row.get(0))
+            .optional()?;
+        let reading = match reading {
+            Some(reading) => reading,
+            None if is_kana_word(word) => katakana_to_hiragana(word),
+            None => return Ok(vec![word.to_string()]),
+        };
+
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT words.word FROM words JOIN lemmas ON lemmas.id = words.lemma_id
+             WHERE lemmas.reading = ?",
+        )?;
+        let mut variants = vec![word.to_string()];
+        for spelling in stmt.query_map(params![reading], |row| row.get::<_, String>(0))? {
+            let spelling = spelling?;
+            if is_kana_word(word) || is_kana_word(&spelling) {
+                variants.push(spelling);
+            }
+        }
+        variants.sort();
+        variants.dedup();
+        Ok(variants)
+    }
+
+    // Finds every line whose text contains `text` as a substring
+    // Both are normalized first (see `normalize`), so ２０１０ finds 2010
+    // Kanji in `text` narrow the candidates through the kanji index first;
+    // text without kanji falls back to scanning every line
+    pub fn find_lines_containing(&self, text: &str) -> Result<Vec<SearchHit>> {
+        if text.is_empty() {
+            return Ok(Vec::new());
+        }
+        let text = normalize(text);
+        let kanji: Vec<String> = text
+            .chars()
+            .filter(|&c| is_kanji(c))
+            .collect::<BTreeSet<char>>()
+            .into_iter()
+            .map(String::from)
+            .collect();
+        let candidates = if kanji.is_empty() {
+            String::new()
+        } else {
+            format!(
+                "transcripts.id IN (
+                    SELECT transcript_id FROM kanji_occurrences
+                    WHERE kanji IN ({})
+                    GROUP BY transcript_id
+                    HAVING COUNT(*) = {}
+                ) AND",
+                vec!["?"; kanji.len()].join(", "),
+                kanji.len()
+            )
+        };
+        let sql = format!(
+            "{} WHERE {} instr(nfkc(transcripts.text), ?) > 0 {}",
+            SEARCH_HIT_SELECT, candidates, SEARCH_HIT_ORDER
+        );
+        let mut values: Vec<&dyn ToSql> = kanji.iter().map(|k| k as &dyn ToSql).collect();
+        values.push(&text);
+        let mut stmt = self.conn.prepare_cached(&sql)?;
+        let hits = stmt.query_map(&values[..], SearchHit::from_row)?.collect();
+        hits
+    }
+
+    // Finds every line containing at least one of the given substrings,
+    // normalized like in `find_lines_containing`
+    // With no substrings, every line is returned
+    pub fn find_lines_containing_any(&self, texts: &[String]) -> Result<Vec<SearchHit>> {
+        let texts: Vec<String> = texts.iter().map(|text| normalize(text)).collect();
+        let condition = if texts.is_empty() {
+            String::new()
+        } else {
+            format!(
+                "WHERE {}",
+                vec!["instr(nfkc(transcripts.text), ?) > 0"; texts.len()].join(" OR ")
+            )
+        };
+        let sql = format!("{} {} {}", SEARCH_HIT_SELECT, condition, SEARCH_HIT_ORDER);
+        let mut stmt = self.conn.prepare_cached(&sql)?;
+        let hits = stmt
+            .query_map(params_from_iter(&texts), SearchHit::from_row)?
+            .collect();
+        hits
+    }
+
+    // Finds the lines surrounding a transcript line in the same episode,
+    // `before` lines before it and `after` lines after it, including the line itself
+    pub fn find_context(
+        &self,
+        transcript_id: TranscriptId,
+        before: u32,
+        after: u32,
+    ) -> Result<Vec<SearchHit>> {
+        let sql = format!(
+            "{} JOIN transcripts AS target ON target.id = ?
+             WHERE transcripts.episode_id = target.episode_id
+               AND transcripts.line_id BETWEEN target.line_id - ? AND target.line_id + ?
+             {}",
+            SEARCH_HIT_SELECT, SEARCH_HIT_ORDER
+        );
+        let mut stmt = self.conn.prepare_cached(&sql)?;
+        let hits = stmt
+            .query_map(params![transcript_id, before, after], SearchHit::from_row)?
+            .collect();
+        hits
+    }
+
+    // Looks up the given lines, returning them in the same order as `ids`
+    // Ids that don't exist are skipped
+    pub fn find_hits_by_ids(&self, ids: &[TranscriptId]) -> Result<Vec<SearchHit>> {
+        let mut hits = HashMap::new();
+        for chunk in ids.chunks(INSERT_CHUNK_SIZE) {
+            let sql = format!(
+                "{} WHERE transcripts.id IN ({})",
+                SEARCH_HIT_SELECT,
+                vec!["?"; chunk.len()].join(", ")
+            );
+            let mut stmt = self.conn.prepare_cached(&sql)?;
+            for hit in stmt.query_map(params_from_iter(chunk), SearchHit::from_row)? {
+                let hit = hit?;
+                hits.insert(hit.transcript_id, hit);
+            }
+        }
+        Ok(ids.iter().filter_map(|id| hits.remove(id)).collect())
+    }
+
+    // The ids of the hits whose line matches `condition`, a condition over the
+    // transcripts, episodes and shows tables with `params` for its placeholders
+    // Only the hits are looked up, so filtering a few hits stays cheap in a large corpus
+    pub(super) fn hits_matching(
+        &self,
+        hits: &[SearchHit],
+        condition: &str,
+        params: &[Value],
+    ) -> Result<HashSet<TranscriptId>> {
+        let mut matching = HashSet::new();
+        for chunk in hits.chunks(INSERT_CHUNK_SIZE) {
+            let sql = format!(
+                "SELECT transcripts.id FROM transcripts
+                 JOIN episodes ON episodes.id = transcripts.episode_id
+                 JOIN shows ON shows.id = episodes.show_id
+                 WHERE ({}) AND transcripts.id IN ({})",
+                condition,
+                vec!["?"; chunk.len()].join(", ")
+            );
+            let ids = chunk.iter().map(|hit| Value::Integer(hit.transcript_id.0));
+            let mut stmt = self.conn.prepare_cached(&sql)?;
+            for id in stmt
+                .query_map(params_from_iter(params.iter().cloned().chain(ids)), |row| {
+                    row.get(0)
+                })?
+            {
+                matching.insert(id?);
+            }
+        }
+        Ok(matching)
+    }
+}
@@ -0,0 +1,107 @@
+use super::{DbHandler, SearchHit, ShowId};
+use rusqlite::types::Value;
+use rusqlite::{params, Result};
+use serde::Deserialize;
+use std::fmt;
+use std::str::FromStr;
+
+/// What kind of content a show is, as stored in `shows.show_type`.
+///
+/// Stored capitalized (`Anime`), and read case-insensitively, as in the
+/// config, where types are written lowercase.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ShowType {
+    #[default]
+    Anime,
+    Drama,
+    Movie,
+    Variety,
+    Podcast,
+}
+
+impl fmt::Display for ShowType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ShowType::Anime => "Anime",
+            ShowType::Drama => "Drama",
+            ShowType::Movie => "Movie",
+            ShowType::Variety => "Variety",
+            ShowType::Podcast => "Podcast",
+        })
+    }
+}
+
+impl FromStr for ShowType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "anime" => Ok(ShowType::Anime),
+            "drama" => Ok(ShowType::Drama),
+            "movie" => Ok(ShowType::Movie),
+            "variety" => Ok(ShowType::Variety),
+            "podcast" => Ok(ShowType::Podcast),
+            _ => Err(format!(
+                "unknown show type {:?} (expected anime, drama, movie, variety or podcast)",
+                s
+            )),
+        }
+    }
+}
+
+impl DbHandler {
+    // Gives shows a type; returns how many didn't have it already
+    pub fn set_show_type(&mut self, show_type: ShowType, show_ids: &[ShowId]) -> Result<usize> {
+        let tx = self.conn.savepoint()?;
+        let mut changed = 0;
+        {
+            let mut stmt = tx.prepare_cached(
+                "UPDATE shows SET show_type = ?1 WHERE id = ?2 AND show_type <> ?1",
+            )?;
+            for show_id in show_ids {
+                changed += stmt.execute(params![show_type.to_string(), show_id])?;
+            }
+        }
+        tx.commit()?;
+        Ok(changed)
+    }
+
+    // Drops the hits from shows of another type
+    pub fn retain_show_type(&self, show_type: ShowType, hits: &mut Vec<SearchHit>) -> Result<()> {
+        let of_type = self.hits_matching(
+            hits,
+            "shows.show_type = ? COLLATE NOCASE",
+            &[Value::Text(show_type.to_string())],
+        )?;
+        hits.retain(|hit| of_type.contains(&hit.transcript_id));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_utils::{insert_show_lines, test_db_with_lines};
+    use super::*;
+
+    #[test]
+    fn test_show_types() {
+        assert_eq!("Drama".parse(), Ok(ShowType::Drama));
+        assert!("sitcom".parse::<ShowType>().is_err());
+
+        let (mut db, anime) = test_db_with_lines(&["猫が好き"]);
+        let movie = insert_show_lines(&mut db, "Movie Name", &["犬が好き"]);
+        let movie_show = db.find_show_id("Movie Name").unwrap().unwrap();
+        assert_eq!(db.set_show_type(ShowType::Movie, &[movie_show]).unwrap(), 1);
+        assert_eq!(db.set_show_type(ShowType::Movie, &[movie_show]).unwrap(), 0);
+        assert_eq!(db.list_shows().unwrap()[0].show_type, "Movie");
+
+        let mut hits = db.find_lines_containing("好き").unwrap();
+        db.retain_show_type(ShowType::Movie, &mut hits).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].transcript_id, movie[0]);
+        let mut hits = db.find_lines_containing("好き").unwrap();
+        db.retain_show_type(ShowType::Anime, &mut hits).unwrap();
+        assert_eq!(hits[0].transcript_id, anime[0]);
+    }
+}
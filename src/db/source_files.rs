@@ -0,0 +1,121 @@
+use super::{DbHandler, EpisodeId};
+use rusqlite::{params, Result, Row};
+use std::collections::HashMap;
+
+/// A subtitle file that has been ingested, as recorded in the `source_files` table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceFile {
+    /// Absolute path of the file when it was ingested
+    pub path: String,
+    pub episode_id: EpisodeId,
+    /// File size in bytes, if it could be read
+    pub size: Option<i64>,
+    /// Modification time in seconds since the Unix epoch, if it could be read
+    pub modified: Option<i64>,
+    /// Hash of the subtitles parsed from the file (see
+    /// [`content_checksum`](crate::ingest::content_checksum)), if it was recorded
+    pub checksum: Option<String>,
+}
+
+impl SourceFile {
+    /// Whether `other` describes the same file, unchanged since this record was made.
+    pub fn is_unchanged(&self, other: &SourceFile) -> bool {
+        self.path == other.path
+            && self.size.is_some()
+            && self.size == other.size
+            && self.modified == other.modified
+    }
+
+    fn from_row(row: &Row) -> Result<Self> {
+        Ok(SourceFile {
+            path: row.get(0)?,
+            episode_id: row.get(1)?,
+            size: row.get(2)?,
+            modified: row.get(3)?,
+            checksum: row.get(4)?,
+        })
+    }
+}
+
+impl DbHandler {
+    // Records that files were ingested, replacing earlier records of the same paths
+    pub fn record_source_files(&mut self, files: &[SourceFile]) -> Result<()> {
+        let tx = self.conn.savepoint()?;
+        {
+            let mut stmt = tx.prepare_cached(
+                "INSERT OR REPLACE INTO source_files
+                     (path, episode_id, size, modified, ingested_at, checksum)
+                 VALUES (?, ?, ?, ?, CURRENT_TIMESTAMP, ?)",
+            )?;
+            for file in files {
+                stmt.execute(params![
+                    file.path,
+                    file.episode_id,
+                    file.size,
+                    file.modified,
+                    file.checksum
+                ])?;
+            }
+        }
+        tx.commit()
+    }
+
+    // Every recorded source file, keyed by path
+    pub fn source_files(&self) -> Result<HashMap<String, SourceFile>> {
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT path, episode_id, size, modified, checksum FROM source_files",
+        )?;
+        let files = stmt
+            .query_map([], SourceFile::from_row)?
+            .map(|file| file.map(|file| (file.path.clone(), file)))
+            .collect();
+        files
+    }
+
+    // The recorded source files with this checksum, by path
+    pub fn source_files_with_checksum(&self, checksum: &str) -> Result<Vec<SourceFile>> {
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT path, episode_id, size, modified, checksum FROM source_files
+             WHERE checksum = ? ORDER BY path",
+        )?;
+        let files = stmt.query_map([checksum], SourceFile::from_row)?.collect();
+        files
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_utils::test_db_with_lines;
+    use super::*;
+
+    #[test]
+    fn test_record_source_files() {
+        let (mut db, ids) = test_db_with_lines(&["猫"]);
+        let episode_id = db.get_transcript(ids[0]).unwrap().unwrap().episode_id;
+        let file = SourceFile {
+            path: "/subs/Show/01.srt".to_string(),
+            episode_id,
+            size: Some(10),
+            modified: Some(1000),
+            checksum: Some("abc".to_string()),
+        };
+        db.record_source_files(std::slice::from_ref(&file)).unwrap();
+        let changed = SourceFile {
+            size: Some(12),
+            ..file.clone()
+        };
+        db.record_source_files(std::slice::from_ref(&changed))
+            .unwrap();
+
+        let files = db.source_files().unwrap();
+        assert_eq!(files.len(), 1);
+        assert!(files[&file.path].is_unchanged(&changed));
+        assert!(!files[&file.path].is_unchanged(&file));
+        assert_eq!(db.source_files_with_checksum("abc").unwrap(), [changed]);
+        assert!(db.source_files_with_checksum("def").unwrap().is_empty());
+
+        // Deleting the episode forgets its files
+        db.delete_episode("Show Name", 1, Some(1)).unwrap();
+        assert!(db.source_files().unwrap().is_empty());
+    }
+}
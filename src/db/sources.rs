@@ -0,0 +1,175 @@
+use super::{DbHandler, EpisodeId};
+use crate::media::is_media_file;
+use crate::srt_parser::Timestamp;
+use rusqlite::{params, OptionalExtension, Result};
+use std::path::Path;
+
+// Hosts whose video pages take the start time as a `t` parameter, in seconds
+const YOUTUBE_HOSTS: &[&str] = &[
+    "youtube.com",
+    "www.youtube.com",
+    "m.youtube.com",
+    "youtu.be",
+];
+
+/// Where an episode came from, as recorded in the `sources` table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Source {
+    pub episode_id: EpisodeId,
+    /// The subtitle file the episode was last ingested from
+    pub path: Option<String>,
+    /// Where the episode can be found online, e.g. the YouTube video or the
+    /// page its subtitles were downloaded from
+    pub url: Option<String>,
+    /// The YouTube video id, for captions downloaded from YouTube
+    pub video_id: Option<String>,
+}
+
+impl Source {
+    /// A source of `episode_id` with nothing known yet, for filling in.
+    pub fn new(episode_id: EpisodeId) -> Self {
+        Source {
+            episode_id,
+            path: None,
+            url: None,
+            video_id: None,
+        }
+    }
+}
+
+/// A link to the moment `time_start` (an SRT timestamp) in the video at
+/// `url`: YouTube links get a `t` parameter, and links to video or audio
+/// files a media fragment (`#t=`). `None` for other pages, which can't be
+/// started at a time.
+pub fn deep_link(url: &str, time_start: &str) -> Option<String> {
+    let seconds = time_start.parse::<Timestamp>().ok()?.to_millis() / 1000;
+    let url = url.split('#').next().unwrap_or(url);
+    let (address, query) = match url.split_once('?') {
+        Some((address, query)) => (address, Some(query)),
+        None => (url, None),
+    };
+    let host = address
+        .split_once("://")
+        .map_or(address, |(_, rest)| rest)
+        .split('/')
+        .next()
+        .unwrap_or_default();
+
+    if YOUTUBE_HOSTS.contains(&host.to_ascii_lowercase().as_str()) {
+        // An earlier start time in the link is replaced
+        let mut params: Vec<&str> = query
+            .into_iter()
+            .flat_map(|query| query.split('&'))
+            .filter(|param| !param.is_empty() && !param.starts_with("t="))
+            .collect();
+        let time = format!("t={}", seconds);
+        params.push(&time);
+        Some(format!("{}?{}", address, params.join("&")))
+    } else if is_media_file(Path::new(address)) {
+        Some(format!("{}#t={}", url, seconds))
+    } else {
+        None
+    }
+}
+
+impl DbHandler {
+    // Records where episodes came from; values left empty keep what was recorded before,
+    // so re-ingesting a file doesn't forget the URL it was downloaded from
+    pub fn record_sources(&mut self, sources: &[Source]) -> Result<()> {
+        let tx = self.conn.savepoint()?;
+        {
+            let mut stmt = tx.prepare_cached(
+                "INSERT INTO sources (episode_id, path, url, video_id) VALUES (?, ?, ?, ?)
+                 ON CONFLICT(episode_id) DO UPDATE SET
+                     path = COALESCE(excluded.path, path),
+                     url = COALESCE(excluded.url, url),
+                     video_id = COALESCE(excluded.video_id, video_id)",
+            )?;
+            for source in sources {
+                stmt.execute(params![
+                    source.episode_id,
+                    source.path,
+                    source.url,
+                    source.video_id
+                ])?;
+            }
+        }
+        tx.commit()
+    }
+
+    // Where an episode came from, if that was recorded
+    pub fn source(&self, episode_id: EpisodeId) -> Result<Option<Source>> {
+        self.conn
+            .prepare_cached("SELECT path, url, video_id FROM sources WHERE episode_id = ?")?
+            .query_row(params![episode_id], |row| {
+                Ok(Source {
+                    episode_id,
+                    path: row.get(0)?,
+                    url: row.get(1)?,
+                    video_id: row.get(2)?,
+                })
+            })
+            .optional()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_utils::test_db_with_lines;
+    use super::*;
+
+    #[test]
+    fn test_record_sources() {
+        let (mut db, ids) = test_db_with_lines(&["猫"]);
+        let episode_id = db.get_transcript(ids[0]).unwrap().unwrap().episode_id;
+        assert_eq!(db.source(episode_id).unwrap(), None);
+
+        db.record_sources(&[Source {
+            url: Some("https://example.com/01.srt".to_string()),
+            ..Source::new(episode_id)
+        }])
+        .unwrap();
+        db.record_sources(&[Source {
+            path: Some("/subs/Show/01.srt".to_string()),
+            ..Source::new(episode_id)
+        }])
+        .unwrap();
+        let source = db.source(episode_id).unwrap().unwrap();
+        assert_eq!(source.path.as_deref(), Some("/subs/Show/01.srt"));
+        assert_eq!(source.url.as_deref(), Some("https://example.com/01.srt"));
+
+        let hits = db.find_lines_containing("猫").unwrap();
+        assert_eq!(
+            hits[0].source_url.as_deref(),
+            Some("https://example.com/01.srt")
+        );
+
+        db.delete_episode("Show Name", 1, Some(1)).unwrap();
+        assert_eq!(db.source(episode_id).unwrap(), None);
+    }
+
+    #[test]
+    fn test_deep_link() {
+        assert_eq!(
+            deep_link(
+                "https://www.youtube.com/watch?v=dQw4w9WgXcQ",
+                "00:02:03,500"
+            )
+            .as_deref(),
+            Some("https://www.youtube.com/watch?v=dQw4w9WgXcQ&t=123")
+        );
+        assert_eq!(
+            deep_link("https://youtu.be/dQw4w9WgXcQ?t=5", "00:00:10,000").as_deref(),
+            Some("https://youtu.be/dQw4w9WgXcQ?t=10")
+        );
+        assert_eq!(
+            deep_link("https://example.com/podcast/01.mp3", "01:00:00,000").as_deref(),
+            Some("https://example.com/podcast/01.mp3#t=3600")
+        );
+        assert_eq!(
+            deep_link("https://jimaku.cc/entry/1/01.srt", "00:00:10,000"),
+            None
+        );
+        assert_eq!(deep_link("https://youtu.be/dQw4w9WgXcQ", "soon"), None);
+    }
+}
@@ -0,0 +1,226 @@
+use super::{DbHandler, EpisodeId, TranscriptId};
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use sha2::{Digest, Sha256};
+use unicode_normalization::UnicodeNormalization;
+
+// Hex digits kept of a stable id's hash; 64 bits leave collisions between the
+// lines of even a large corpus unlikely
+const STABLE_ID_LENGTH: usize = 16;
+
+/// An id for a line that is the same whenever the line is ingested: a hash of
+/// its show, season, episode number (empty for a movie), start time and text.
+///
+/// Names and text are NFKC-normalized and trimmed first, so full-width and
+/// half-width spellings, or stray spaces, don't change it. Unlike transcript
+/// ids, stable ids survive deleting and reingesting a show, so bookmarks, Anki
+/// cards and exports can refer to lines by them.
+pub fn stable_line_id(
+    show_name: &str,
+    season: i32,
+    episode_number: Option<i32>,
+    time_start: &str,
+    text: &str,
+) -> String {
+    let mut hasher = Sha256::new();
+    for part in [
+        show_name.trim().nfkc().collect::<String>(),
+        season.to_string(),
+        episode_number.map_or_else(String::new, |number| number.to_string()),
+        time_start.trim().to_string(),
+        text.trim().nfkc().collect::<String>(),
+    ] {
+        hasher.update(part.as_bytes());
+        // Separates the parts, so moving text between them changes the id
+        hasher.update([0]);
+    }
+    hasher
+        .finalize()
+        .iter()
+        .take(STABLE_ID_LENGTH / 2)
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Bookmarks, tags and notes on an episode's lines, by the lines' stable ids,
+/// so they can be put back after the lines are replaced.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LineAnnotations {
+    // Stable id, user, note, tag and creation time
+    bookmarks: Vec<(String, String, Option<String>, Option<String>, String)>,
+    // Stable id and tag
+    tags: Vec<(String, String)>,
+    // Stable id, text and creation time
+    notes: Vec<(String, String, String)>,
+}
+
+impl LineAnnotations {
+    pub fn is_empty(&self) -> bool {
+        self.bookmarks.is_empty() && self.tags.is_empty() && self.notes.is_empty()
+    }
+}
+
+impl DbHandler {
+    // Looks up a line by its stable id
+    pub fn find_line_by_stable_id(&self, stable_id: &str) -> Result<Option<TranscriptId>> {
+        line_with_stable_id(&self.conn, stable_id)
+    }
+
+    // The bookmarks, tags and notes on an episode's lines, to restore once they're replaced
+    pub fn line_annotations(&self, episode_id: EpisodeId) -> Result<LineAnnotations> {
+        let mut bookmarks = self.conn.prepare_cached(
+            "SELECT transcripts.stable_id, bookmarks.user, bookmarks.note, bookmarks.tag,
+                    bookmarks.created_at
+             FROM bookmarks JOIN transcripts ON transcripts.id = bookmarks.transcript_id
+             WHERE transcripts.episode_id = ?",
+        )?;
+        let mut tags = self.conn.prepare_cached(
+            "SELECT transcripts.stable_id, line_tags.tag
+             FROM line_tags JOIN transcripts ON transcripts.id = line_tags.transcript_id
+             WHERE transcripts.episode_id = ?",
+        )?;
+        let mut notes = self.conn.prepare_cached(
+            "SELECT transcripts.stable_id, notes.text, notes.created_at
+             FROM notes JOIN transcripts ON transcripts.id = notes.transcript_id
+             WHERE transcripts.episode_id = ?",
+        )?;
+        Ok(LineAnnotations {
+            bookmarks: bookmarks
+                .query_map(params![episode_id], |row| {
+                    Ok((
+                        row.get(0)?,
+                        row.get(1)?,
+                        row.get(2)?,
+                        row.get(3)?,
+                        row.get(4)?,
+                    ))
+                })?
+                .collect::<Result<_>>()?,
+            tags: tags
+                .query_map(params![episode_id], |row| Ok((row.get(0)?, row.get(1)?)))?
+                .collect::<Result<_>>()?,
+            notes: notes
+                .query_map(params![episode_id], |row| {
+                    Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+                })?
+                .collect::<Result<_>>()?,
+        })
+    }
+
+    // Puts annotations back on the lines that now have their stable ids
+    // Annotations on lines that are gone, or changed, are dropped; returns how many were restored
+    pub fn restore_line_annotations(&mut self, annotations: &LineAnnotations) -> Result<usize> {
+        let tx = self.conn.savepoint()?;
+        let mut restored = 0;
+        {
+            let mut insert_bookmark = tx.prepare_cached(
+                "INSERT OR IGNORE INTO bookmarks (user, transcript_id, note, tag, created_at)
+                 VALUES (?, ?, ?, ?, ?)",
+            )?;
+            for (stable_id, user, note, tag, created_at) in &annotations.bookmarks {
+                if let Some(id) = line_with_stable_id(&tx, stable_id)? {
+                    restored +=
+                        insert_bookmark.execute(params![user, id, note, tag, created_at])?;
+                }
+            }
+            let mut insert_tag = tx.prepare_cached(
+                "INSERT OR IGNORE INTO line_tags (transcript_id, tag) VALUES (?, ?)",
+            )?;
+            for (stable_id, tag) in &annotations.tags {
+                if let Some(id) = line_with_stable_id(&tx, stable_id)? {
+                    restored += insert_tag.execute(params![id, tag])?;
+                }
+            }
+            let mut insert_note = tx.prepare_cached(
+                "INSERT INTO notes (transcript_id, text, created_at) VALUES (?, ?, ?)",
+            )?;
+            for (stable_id, text, created_at) in &annotations.notes {
+                if let Some(id) = line_with_stable_id(&tx, stable_id)? {
+                    restored += insert_note.execute(params![id, text, created_at])?;
+                }
+            }
+        }
+        tx.commit()?;
+        Ok(restored)
+    }
+}
+
+fn line_with_stable_id(conn: &Connection, stable_id: &str) -> Result<Option<TranscriptId>> {
+    conn.prepare_cached("SELECT id FROM transcripts WHERE stable_id = ? ORDER BY id LIMIT 1")?
+        .query_row(params![stable_id], |row| row.get(0))
+        .optional()
+}
+
+// Gives every line its stable id, for databases created before lines had them
+pub(super) fn backfill_stable_ids(conn: &Connection) -> Result<()> {
+    let lines: Vec<(TranscriptId, String)> = conn
+        .prepare(
+            "SELECT transcripts.id, shows.name, episodes.season, episodes.episode_number,
+                    transcripts.time_start, transcripts.text
+             FROM transcripts
+             JOIN episodes ON episodes.id = transcripts.episode_id
+             JOIN shows ON shows.id = episodes.show_id",
+        )?
+        .query_map([], |row| {
+            let stable_id = stable_line_id(
+                &row.get::<_, String>(1)?,
+                row.get(2)?,
+                row.get(3)?,
+                &row.get::<_, String>(4)?,
+                &row.get::<_, String>(5)?,
+            );
+            Ok((row.get(0)?, stable_id))
+        })?
+        .collect::<Result<_>>()?;
+    let mut update = conn.prepare("UPDATE transcripts SET stable_id = ? WHERE id = ?")?;
+    for (id, stable_id) in lines {
+        update.execute(params![stable_id, id])?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_utils::{insert_test_lines, test_db_with_lines};
+    use super::*;
+
+    #[test]
+    fn test_stable_line_id() {
+        let id = stable_line_id("Show Name", 1, Some(2), "00:00:01,000", "ＡＢＣです");
+        assert_eq!(id.len(), STABLE_ID_LENGTH);
+        assert_eq!(
+            id,
+            stable_line_id(" Show Name", 1, Some(2), "00:00:01,000", "ABCです ")
+        );
+        assert_ne!(
+            id,
+            stable_line_id("Show Name", 1, Some(3), "00:00:01,000", "ABCです")
+        );
+        assert_ne!(
+            id,
+            stable_line_id("Show Name", 1, None, "00:00:01,000", "ABCです")
+        );
+    }
+
+    #[test]
+    fn test_annotations_survive_reingest() {
+        let (mut db, ids) = test_db_with_lines(&["猫", "犬"]);
+        let line = db.get_transcript(ids[1]).unwrap().unwrap();
+        assert_eq!(
+            db.find_line_by_stable_id(&line.stable_id).unwrap(),
+            Some(ids[1])
+        );
+        db.add_bookmark("me", ids[1], Some("かわいい"), None)
+            .unwrap();
+        db.tag_lines("animals", &[ids[1]]).unwrap();
+
+        let annotations = db.line_annotations(line.episode_id).unwrap();
+        db.delete_episode_lines(line.episode_id).unwrap();
+        assert!(db.bookmarks("me", None).unwrap().is_empty());
+        let new_ids = insert_test_lines(&mut db, &["猫", "犬"]);
+
+        assert_eq!(db.restore_line_annotations(&annotations).unwrap(), 2);
+        let bookmarks = db.bookmarks("me", None).unwrap();
+        assert_eq!(bookmarks[0].hit.transcript_id, new_ids[1]);
+        assert_eq!(bookmarks[0].note.as_deref(), Some("かわいい"));
+    }
+}
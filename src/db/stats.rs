@@ -0,0 +1,476 @@
+use super::{DbHandler, EpisodeId, ShowType};
+use crate::srt_parser::Timestamp;
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+// Words kept in the top_words cache; /stats can ask for at most this many
+pub const TOP_WORDS_CACHED: usize = 100;
+
+/// Corpus-wide numbers, as cached by [`DbHandler::refresh_corpus_stats`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CorpusStats {
+    pub shows: i64,
+    pub episodes: i64,
+    pub lines: i64,
+    /// Distinct indexed words
+    pub unique_words: i64,
+    /// Time covered by subtitles, estimated from cue timestamps; overlapping cues count once
+    pub dialogue_hours: f64,
+    /// The most frequent indexed words, most frequent first
+    pub top_words: Vec<WordFrequency>,
+    /// When the numbers were computed, or `None` if they were computed for this request
+    pub refreshed_at: Option<String>,
+}
+
+/// An indexed word and how many times it occurs.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct WordFrequency {
+    pub word: String,
+    pub frequency: i64,
+}
+
+/// How much dialogue an episode has, as cached by [`DbHandler::refresh_corpus_stats`].
+///
+/// The runtime is estimated as the end of the last cue, so credits without
+/// subtitles aren't counted.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EpisodeStats {
+    pub episode_id: EpisodeId,
+    pub show_name: String,
+    pub season: i32,
+    /// `None` for a movie
+    pub episode_number: Option<i32>,
+    pub lines: i64,
+    pub characters: i64,
+    /// Time covered by at least one cue
+    pub dialogue_minutes: f64,
+    pub runtime_minutes: f64,
+    /// Characters of dialogue per minute of runtime
+    pub characters_per_minute: f64,
+    /// Percentage of the runtime covered by cues
+    pub dialogue_coverage: f64,
+}
+
+impl EpisodeStats {
+    fn new(
+        (episode_id, show_name, season, episode_number): (EpisodeId, String, i32, Option<i32>),
+        lines: i64,
+        characters: i64,
+        timing: EpisodeTiming,
+    ) -> Self {
+        let minutes = |millis: u64| millis as f64 / 60_000.0;
+        let runtime_minutes = minutes(timing.runtime_millis);
+        let (characters_per_minute, dialogue_coverage) = if timing.runtime_millis == 0 {
+            (0.0, 0.0)
+        } else {
+            (
+                characters as f64 / runtime_minutes,
+                timing.dialogue_millis as f64 * 100.0 / timing.runtime_millis as f64,
+            )
+        };
+        EpisodeStats {
+            episode_id,
+            show_name,
+            season,
+            episode_number,
+            lines,
+            characters,
+            dialogue_minutes: minutes(timing.dialogue_millis),
+            runtime_minutes,
+            characters_per_minute,
+            dialogue_coverage,
+        }
+    }
+}
+
+// Cue timing of one episode, in milliseconds
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct EpisodeTiming {
+    dialogue_millis: u64,
+    runtime_millis: u64,
+}
+
+impl DbHandler {
+    // Recomputes the cached corpus statistics, per-episode metrics and difficulty measures
+    // Call after ingesting or deleting, or importing a JLPT list
+    pub fn refresh_corpus_stats(&mut self) -> Result<()> {
+        self.refresh_difficulty()?;
+        let tx = self.conn.savepoint()?;
+        tx.execute("DELETE FROM episode_stats", [])?;
+        {
+            let mut stmt = tx.prepare_cached(
+                "INSERT INTO episode_stats
+                 (episode_id, lines, characters, dialogue_millis, runtime_millis)
+                 VALUES (?, ?, ?, ?, ?)",
+            )?;
+            for (episode_id, (lines, characters, timing)) in episode_counts(&tx)? {
+                stmt.execute(params![
+                    episode_id,
+                    lines,
+                    characters,
+                    timing.dialogue_millis as i64,
+                    timing.runtime_millis as i64
+                ])?;
+            }
+        }
+        let stats = compute_corpus_stats(&tx, TOP_WORDS_CACHED)?;
+        let dialogue_millis = (stats.dialogue_hours * 3_600_000.0).round() as i64;
+        tx.execute(
+            "INSERT OR REPLACE INTO corpus_stats
+             (id, shows, episodes, lines, unique_words, dialogue_millis, refreshed_at)
+             VALUES (1, ?, ?, ?, ?, ?, CURRENT_TIMESTAMP)",
+            params![
+                stats.shows,
+                stats.episodes,
+                stats.lines,
+                stats.unique_words,
+                dialogue_millis
+            ],
+        )?;
+        tx.execute("DELETE FROM top_words", [])?;
+        {
+            let mut stmt = tx
+                .prepare_cached("INSERT INTO top_words (rank, word, frequency) VALUES (?, ?, ?)")?;
+            for (rank, word) in stats.top_words.iter().enumerate() {
+                stmt.execute(params![rank as i64, word.word, word.frequency])?;
+            }
+        }
+        tx.commit()
+    }
+
+    // The cached corpus statistics with up to `top` top words (at most TOP_WORDS_CACHED)
+    // A database that was never refreshed gets them computed on the spot, without caching
+    pub fn corpus_stats(&self, top: usize) -> Result<CorpusStats> {
+        let top = top.min(TOP_WORDS_CACHED);
+        let cached = self
+            .conn
+            .prepare_cached(
+                "SELECT shows, episodes, lines, unique_words, dialogue_millis, refreshed_at
+                 FROM corpus_stats WHERE id = 1",
+            )?
+            .query_row([], |row| {
+                Ok(CorpusStats {
+                    shows: row.get(0)?,
+                    episodes: row.get(1)?,
+                    lines: row.get(2)?,
+                    unique_words: row.get(3)?,
+                    dialogue_hours: row.get::<_, i64>(4)? as f64 / 3_600_000.0,
+                    top_words: Vec::new(),
+                    refreshed_at: row.get(5)?,
+                })
+            })
+            .optional()?;
+        let Some(mut stats) = cached else {
+            return compute_corpus_stats(&self.conn, top);
+        };
+        let mut stmt = self
+            .conn
+            .prepare_cached("SELECT word, frequency FROM top_words ORDER BY rank LIMIT ?")?;
+        stats.top_words = stmt
+            .query_map(params![top as i64], |row| {
+                Ok(WordFrequency {
+                    word: row.get(0)?,
+                    frequency: row.get(1)?,
+                })
+            })?
+            .collect::<Result<_>>()?;
+        Ok(stats)
+    }
+
+    // Statistics of the shows in one corpus, with up to `top` top words
+    // Word frequencies count only that corpus's lines; these are always computed on the spot
+    pub fn corpus_stats_in(&self, corpus: &str, top: usize) -> Result<CorpusStats> {
+        self.stats_of_shows("shows.corpus = ?", corpus, top)
+    }
+
+    // Statistics of the shows of one type, computed like those of a corpus
+    pub fn show_type_stats(&self, show_type: ShowType, top: usize) -> Result<CorpusStats> {
+        self.stats_of_shows(
+            "shows.show_type = ? COLLATE NOCASE",
+            &show_type.to_string(),
+            top,
+        )
+    }
+
+    // Statistics of the shows matching `condition`, a condition on `shows` with one parameter
+    fn stats_of_shows(&self, condition: &str, value: &str, top: usize) -> Result<CorpusStats> {
+        let sql = |template: &str| template.replace("{condition}", condition);
+        let count = |template: &str| {
+            self.conn
+                .query_row(&sql(template), params![value], |row| row.get::<_, i64>(0))
+        };
+        let episode_ids: HashSet<EpisodeId> = self
+            .conn
+            .prepare_cached(&sql(
+                "SELECT episodes.id FROM episodes JOIN shows ON shows.id = episodes.show_id
+                 WHERE {condition}",
+            ))?
+            .query_map(params![value], |row| row.get(0))?
+            .collect::<Result<_>>()?;
+        let dialogue_millis: u64 = episode_timings(&self.conn)?
+            .into_iter()
+            .filter(|(episode_id, _)| episode_ids.contains(episode_id))
+            .map(|(_, timing)| timing.dialogue_millis)
+            .sum();
+        let mut stmt = self.conn.prepare_cached(&sql(
+            "SELECT words.word, COUNT(*) AS frequency FROM word_occurrences
+             JOIN words ON words.id = word_occurrences.word_id
+             JOIN transcripts ON transcripts.id = word_occurrences.transcript_id
+             JOIN episodes ON episodes.id = transcripts.episode_id
+             JOIN shows ON shows.id = episodes.show_id
+             WHERE {condition}
+             GROUP BY words.id ORDER BY frequency DESC, words.word LIMIT ?",
+        ))?;
+        let top_words = stmt
+            .query_map(params![value, top as i64], |row| {
+                Ok(WordFrequency {
+                    word: row.get(0)?,
+                    frequency: row.get(1)?,
+                })
+            })?
+            .collect::<Result<_>>()?;
+        Ok(CorpusStats {
+            shows: count("SELECT COUNT(*) FROM shows WHERE {condition}")?,
+            episodes: episode_ids.len() as i64,
+            lines: count(
+                "SELECT COUNT(*) FROM transcripts
+                 JOIN episodes ON episodes.id = transcripts.episode_id
+                 JOIN shows ON shows.id = episodes.show_id
+                 WHERE {condition}",
+            )?,
+            unique_words: count(
+                "SELECT COUNT(DISTINCT word_occurrences.word_id) FROM word_occurrences
+                 JOIN transcripts ON transcripts.id = word_occurrences.transcript_id
+                 JOIN episodes ON episodes.id = transcripts.episode_id
+                 JOIN shows ON shows.id = episodes.show_id
+                 WHERE {condition}",
+            )?,
+            dialogue_hours: dialogue_millis as f64 / 3_600_000.0,
+            top_words,
+            refreshed_at: None,
+        })
+    }
+
+    // Dialogue metrics of every episode, optionally only those of one show,
+    // in show and episode order
+    // Without cached metrics (never refreshed, or refreshed before they were added)
+    // they are computed on the spot
+    pub fn episode_stats(&self, show: Option<&str>) -> Result<Vec<EpisodeStats>> {
+        let refreshed: bool =
+            self.conn
+                .query_row("SELECT EXISTS(SELECT 1 FROM episode_stats)", [], |row| {
+                    row.get(0)
+                })?;
+        let mut counts = if refreshed {
+            let mut stmt = self.conn.prepare_cached(
+                "SELECT episode_id, lines, characters, dialogue_millis, runtime_millis
+                 FROM episode_stats",
+            )?;
+            let counts = stmt
+                .query_map([], |row| {
+                    let timing = EpisodeTiming {
+                        dialogue_millis: row.get::<_, i64>(3)? as u64,
+                        runtime_millis: row.get::<_, i64>(4)? as u64,
+                    };
+                    Ok((row.get(0)?, (row.get(1)?, row.get(2)?, timing)))
+                })?
+                .collect::<Result<_>>()?;
+            counts
+        } else {
+            episode_counts(&self.conn)?
+        };
+
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT episodes.id, shows.name, episodes.season, episodes.episode_number
+             FROM episodes JOIN shows ON shows.id = episodes.show_id
+             WHERE ?1 IS NULL OR shows.name = ?1
+             ORDER BY shows.name, episodes.season, episodes.episode_number",
+        )?;
+        let episodes = stmt.query_map(params![show], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+        })?;
+        let mut stats = Vec::new();
+        for episode in episodes {
+            let episode = episode?;
+            let (lines, characters, timing) = counts.remove(&episode.0).unwrap_or_default();
+            stats.push(EpisodeStats::new(episode, lines, characters, timing));
+        }
+        Ok(stats)
+    }
+}
+
+// Lines, characters and cue timing of each episode with lines
+fn episode_counts(conn: &Connection) -> Result<HashMap<EpisodeId, (i64, i64, EpisodeTiming)>> {
+    let timings = episode_timings(conn)?;
+    let mut stmt = conn.prepare_cached(
+        "SELECT episode_id, COUNT(*), SUM(length(text)) FROM transcripts GROUP BY episode_id",
+    )?;
+    let counts = stmt
+        .query_map([], |row| {
+            let episode_id = row.get(0)?;
+            let timing = timings.get(&episode_id).copied().unwrap_or_default();
+            Ok((episode_id, (row.get(1)?, row.get(2)?, timing)))
+        })?
+        .collect();
+    counts
+}
+
+fn compute_corpus_stats(conn: &Connection, top: usize) -> Result<CorpusStats> {
+    let count = |sql: &str| conn.query_row(sql, [], |row| row.get::<_, i64>(0));
+    let dialogue_millis: u64 = episode_timings(conn)?
+        .values()
+        .map(|timing| timing.dialogue_millis)
+        .sum();
+    let mut stmt = conn.prepare_cached(
+        "SELECT word, frequency FROM words WHERE frequency > 0
+         ORDER BY frequency DESC, word LIMIT ?",
+    )?;
+    let top_words = stmt
+        .query_map(params![top as i64], |row| {
+            Ok(WordFrequency {
+                word: row.get(0)?,
+                frequency: row.get(1)?,
+            })
+        })?
+        .collect::<Result<_>>()?;
+    Ok(CorpusStats {
+        shows: count("SELECT COUNT(*) FROM shows")?,
+        episodes: count("SELECT COUNT(*) FROM episodes")?,
+        lines: count("SELECT COUNT(*) FROM transcripts")?,
+        unique_words: count("SELECT COUNT(*) FROM words WHERE frequency > 0")?,
+        dialogue_hours: dialogue_millis as f64 / 3_600_000.0,
+        top_words,
+        refreshed_at: None,
+    })
+}
+
+// How much of each episode is covered by at least one cue, and when its last cue ends
+// Lines with timestamps that don't parse are left out
+fn episode_timings(conn: &Connection) -> Result<HashMap<EpisodeId, EpisodeTiming>> {
+    let mut cues: HashMap<EpisodeId, Vec<(u64, u64)>> = HashMap::new();
+    let mut stmt =
+        conn.prepare_cached("SELECT episode_id, time_start, time_end FROM transcripts")?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        let start: String = row.get(1)?;
+        let end: String = row.get(2)?;
+        if let (Ok(start), Ok(end)) = (start.parse::<Timestamp>(), end.parse::<Timestamp>()) {
+            cues.entry(row.get(0)?)
+                .or_default()
+                .push((start.to_millis(), end.to_millis()));
+        }
+    }
+    Ok(cues
+        .into_iter()
+        .map(|(episode_id, intervals)| {
+            let runtime_millis = intervals.iter().map(|&(_, end)| end).max().unwrap_or(0);
+            let timing = EpisodeTiming {
+                dialogue_millis: covered_millis(intervals),
+                runtime_millis,
+            };
+            (episode_id, timing)
+        })
+        .collect())
+}
+
+// Total length of the union of the intervals
+fn covered_millis(mut intervals: Vec<(u64, u64)>) -> u64 {
+    intervals.sort_unstable();
+    let mut total = 0;
+    let mut covered_until = 0;
+    for (start, end) in intervals {
+        let start = start.max(covered_until);
+        if end > start {
+            total += end - start;
+            covered_until = end;
+        }
+    }
+    total
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_utils::{insert_show_lines, test_db_with_lines};
+    use super::*;
+    use crate::tokenizer::test_utils::test_tokenizer;
+
+    #[test]
+    fn test_covered_millis_merges_overlaps() {
+        assert_eq!(
+            covered_millis(vec![(0, 1000), (500, 1500), (3000, 4000)]),
+            2500
+        );
+        assert_eq!(covered_millis(vec![(0, 5000), (1000, 2000)]), 5000);
+        assert_eq!(covered_millis(vec![(1000, 500)]), 0);
+    }
+
+    #[test]
+    fn test_corpus_stats() {
+        let (mut db, ids) = test_db_with_lines(&["猫が好き", "猫だ"]);
+        db.index_transcripts(&test_tokenizer(), &ids).unwrap();
+
+        let live = db.corpus_stats(1).unwrap();
+        assert_eq!((live.shows, live.episodes, live.lines), (1, 1, 2));
+        assert_eq!(live.top_words[0].word, "猫");
+        assert_eq!(live.top_words[0].frequency, 2);
+        assert_eq!(live.refreshed_at, None);
+
+        db.refresh_corpus_stats().unwrap();
+        let cached = db.corpus_stats(1).unwrap();
+        assert!(cached.refreshed_at.is_some());
+        assert_eq!(cached.top_words, live.top_words);
+        assert_eq!(
+            CorpusStats {
+                refreshed_at: None,
+                ..cached
+            },
+            live
+        );
+    }
+
+    #[test]
+    fn test_corpus_stats_in() {
+        let (mut db, mut ids) = test_db_with_lines(&["猫が好き", "猫だ"]);
+        ids.extend(insert_show_lines(&mut db, "Drama Name", &["犬だ"]));
+        db.index_transcripts(&test_tokenizer(), &ids).unwrap();
+        let drama = db.find_show_id("Drama Name").unwrap().unwrap();
+        db.set_show_corpus("drama", &[drama]).unwrap();
+
+        let stats = db.corpus_stats_in("drama", 10).unwrap();
+        assert_eq!((stats.shows, stats.episodes, stats.lines), (1, 1, 1));
+        let words: Vec<&str> = stats.top_words.iter().map(|w| w.word.as_str()).collect();
+        assert!(words.contains(&"犬"));
+        assert!(!words.contains(&"猫"));
+        assert_eq!(stats.unique_words, stats.top_words.len() as i64);
+        assert_eq!(stats.dialogue_hours, 0.5 / 3600.0);
+
+        let anime = db.corpus_stats_in(crate::db::DEFAULT_CORPUS, 1).unwrap();
+        assert_eq!(anime.lines, 2);
+        assert_eq!(anime.top_words[0].word, "猫");
+        assert_eq!(anime.top_words[0].frequency, 2);
+        assert_eq!(db.corpus_stats_in("podcasts", 1).unwrap().lines, 0);
+
+        db.set_show_type(ShowType::Drama, &[drama]).unwrap();
+        let dramas = db.show_type_stats(ShowType::Drama, 10).unwrap();
+        assert_eq!((dramas.shows, dramas.lines), (1, 1));
+        assert_eq!(db.show_type_stats(ShowType::Anime, 1).unwrap().lines, 2);
+    }
+
+    #[test]
+    fn test_episode_stats() {
+        // The test lines are half-second cues starting on each second
+        let (mut db, _) = test_db_with_lines(&["猫が好き", "猫だ"]);
+        let live = db.episode_stats(None).unwrap();
+        db.refresh_corpus_stats().unwrap();
+        let cached = db.episode_stats(Some("Show Name")).unwrap();
+        assert_eq!(live, cached);
+
+        let stats = &cached[0];
+        assert_eq!((stats.lines, stats.characters), (2, 6));
+        assert_eq!(stats.runtime_minutes, 1.5 / 60.0);
+        assert!((stats.dialogue_coverage - 100.0 / 1.5).abs() < 1e-9);
+        assert!((stats.characters_per_minute - 240.0).abs() < 1e-9);
+        assert!(db.episode_stats(Some("Other Show")).unwrap().is_empty());
+    }
+}
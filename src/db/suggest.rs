@@ -0,0 +1,104 @@
+use super::{DbHandler, INSERT_CHUNK_SIZE};
+use crate::tokenizer::katakana_to_hiragana;
+use rusqlite::{params, params_from_iter, Result};
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// An indexed word offered as a completion.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Suggestion {
+    pub word: String,
+    /// The dictionary form's reading in hiragana, if the tokenizer knew it
+    pub reading: Option<String>,
+    /// How many times the word occurs in indexed lines
+    pub frequency: i64,
+}
+
+impl DbHandler {
+    // Indexed words whose spelling or reading starts with `prefix`, the most frequent first
+    // A katakana prefix matches readings too, so ハシ suggests 走る
+    pub fn suggest(&self, prefix: &str, limit: usize) -> Result<Vec<Suggestion>> {
+        if prefix.is_empty() {
+            return Ok(Vec::new());
+        }
+        // Ranges on the words_prefix and UNIQUE(reading) indexes instead of LIKE,
+        // which can't use an index for non-ASCII text
+        let reading = katakana_to_hiragana(prefix);
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT words.word, lemmas.reading, words.frequency FROM words
+             LEFT JOIN lemmas ON lemmas.id = words.lemma_id
+             WHERE words.id IN (
+                 SELECT id FROM words WHERE word >= ?1 AND word < ?1 || char(1114111)
+                 UNION
+                 SELECT words.id FROM lemmas JOIN words ON words.lemma_id = lemmas.id
+                 WHERE lemmas.reading >= ?2 AND lemmas.reading < ?2 || char(1114111)
+             )
+             AND words.frequency > 0
+             ORDER BY words.frequency DESC, words.word
+             LIMIT ?3",
+        )?;
+        let suggestions = stmt
+            .query_map(params![prefix, reading, limit as i64], |row| {
+                Ok(Suggestion {
+                    word: row.get(0)?,
+                    reading: row.get(1)?,
+                    frequency: row.get(2)?,
+                })
+            })?
+            .collect();
+        suggestions
+    }
+
+    // How often words with each of the given hiragana readings occur, summed over spellings
+    // Readings no indexed word has are left out
+    pub fn reading_frequencies(&self, readings: &[String]) -> Result<HashMap<String, i64>> {
+        let mut frequencies = HashMap::new();
+        for chunk in readings.chunks(INSERT_CHUNK_SIZE) {
+            let sql = format!(
+                "SELECT lemmas.reading, SUM(words.frequency) FROM lemmas
+                 JOIN words ON words.lemma_id = lemmas.id
+                 WHERE lemmas.reading IN ({})
+                 GROUP BY lemmas.reading HAVING SUM(words.frequency) > 0",
+                vec!["?"; chunk.len()].join(", ")
+            );
+            let mut stmt = self.conn.prepare_cached(&sql)?;
+            for row in stmt.query_map(params_from_iter(chunk), |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })? {
+                let (reading, frequency) = row?;
+                frequencies.insert(reading, frequency);
+            }
+        }
+        Ok(frequencies)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_utils::test_db_with_lines;
+    use crate::tokenizer::test_utils::test_tokenizer;
+
+    #[test]
+    fn test_suggest() {
+        let (mut db, ids) = test_db_with_lines(&["走った", "走る", "面白い", "猫が走った"]);
+        db.index_transcripts(&test_tokenizer(), &ids).unwrap();
+        let words = |prefix: &str| -> Vec<String> {
+            db.suggest(prefix, 5)
+                .unwrap()
+                .into_iter()
+                .map(|suggestion| suggestion.word)
+                .collect()
+        };
+
+        let suggestions = db.suggest("走", 5).unwrap();
+        assert_eq!(suggestions.len(), 1);
+        assert_eq!(suggestions[0].word, "走る");
+        assert_eq!(suggestions[0].reading.as_deref(), Some("はしる"));
+        assert_eq!(suggestions[0].frequency, 3);
+        assert_eq!(words("はし"), ["走る"]);
+        assert_eq!(words("ハシ"), ["走る"]);
+        assert_eq!(words("面"), ["面白い"]);
+        assert!(words("犬").is_empty());
+        assert!(words("").is_empty());
+    }
+}
@@ -0,0 +1,156 @@
+use super::search::{SEARCH_HIT_ORDER, SEARCH_HIT_SELECT};
+use super::{DbHandler, SearchHit, TranscriptId};
+use rusqlite::{params, Result};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+/// A tag and how many lines carry it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TagCount {
+    pub tag: String,
+    pub lines: i64,
+}
+
+impl DbHandler {
+    // Tags lines with `tag`, e.g. `funny` or `grammar:〜わけだ`; returns how many weren't already
+    pub fn tag_lines(&mut self, tag: &str, transcript_ids: &[TranscriptId]) -> Result<usize> {
+        let tx = self.conn.savepoint()?;
+        let mut tagged = 0;
+        {
+            let mut stmt = tx.prepare_cached(
+                "INSERT OR IGNORE INTO line_tags (transcript_id, tag) VALUES (?, ?)",
+            )?;
+            for transcript_id in transcript_ids {
+                tagged += stmt.execute(params![transcript_id, tag])?;
+            }
+        }
+        tx.commit()?;
+        Ok(tagged)
+    }
+
+    // Removes `tag` from lines; returns how many had it
+    pub fn untag_lines(&mut self, tag: &str, transcript_ids: &[TranscriptId]) -> Result<usize> {
+        let tx = self.conn.savepoint()?;
+        let mut untagged = 0;
+        {
+            let mut stmt =
+                tx.prepare_cached("DELETE FROM line_tags WHERE transcript_id = ? AND tag = ?")?;
+            for transcript_id in transcript_ids {
+                untagged += stmt.execute(params![transcript_id, tag])?;
+            }
+        }
+        tx.commit()?;
+        Ok(untagged)
+    }
+
+    // Every tag in use with its number of lines, by name
+    pub fn tags(&self) -> Result<Vec<TagCount>> {
+        let mut stmt = self
+            .conn
+            .prepare_cached("SELECT tag, COUNT(*) FROM line_tags GROUP BY tag ORDER BY tag")?;
+        let tags = stmt
+            .query_map([], |row| {
+                Ok(TagCount {
+                    tag: row.get(0)?,
+                    lines: row.get(1)?,
+                })
+            })?
+            .collect();
+        tags
+    }
+
+    // The tags of each of the given lines that has any, by name
+    pub fn line_tags(
+        &self,
+        transcript_ids: &[TranscriptId],
+    ) -> Result<HashMap<TranscriptId, Vec<String>>> {
+        let mut stmt = self
+            .conn
+            .prepare_cached("SELECT tag FROM line_tags WHERE transcript_id = ? ORDER BY tag")?;
+        let mut tags = HashMap::new();
+        for &transcript_id in transcript_ids {
+            let line_tags = stmt
+                .query_map(params![transcript_id], |row| row.get(0))?
+                .collect::<Result<Vec<String>>>()?;
+            if !line_tags.is_empty() {
+                tags.insert(transcript_id, line_tags);
+            }
+        }
+        Ok(tags)
+    }
+
+    // Every line tagged with `tag`, in corpus order
+    pub fn find_tagged_lines(&self, tag: &str) -> Result<Vec<SearchHit>> {
+        let sql = format!(
+            "{} WHERE transcripts.id IN (SELECT transcript_id FROM line_tags WHERE tag = ?) {}",
+            SEARCH_HIT_SELECT, SEARCH_HIT_ORDER
+        );
+        let mut stmt = self.conn.prepare_cached(&sql)?;
+        let hits = stmt.query_map(params![tag], SearchHit::from_row)?.collect();
+        hits
+    }
+
+    // Drops the hits that don't carry every one of `tags`
+    pub fn retain_tagged(&self, tags: &[String], hits: &mut Vec<SearchHit>) -> Result<()> {
+        for tag in tags {
+            let tagged: HashSet<TranscriptId> = self
+                .conn
+                .prepare_cached("SELECT transcript_id FROM line_tags WHERE tag = ?")?
+                .query_map(params![tag], |row| row.get(0))?
+                .collect::<Result<_>>()?;
+            hits.retain(|hit| tagged.contains(&hit.transcript_id));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_utils::test_db_with_lines;
+    use super::*;
+
+    #[test]
+    fn test_line_tags() {
+        let (mut db, ids) = test_db_with_lines(&["猫が好き", "犬が好き", "走る"]);
+        assert_eq!(db.tag_lines("funny", &ids[..2]).unwrap(), 2);
+        assert_eq!(db.tag_lines("funny", &ids[..1]).unwrap(), 0);
+        assert_eq!(db.tag_lines("grammar:〜わけだ", &ids[1..]).unwrap(), 2);
+
+        assert_eq!(
+            db.tags().unwrap(),
+            [
+                TagCount {
+                    tag: "funny".to_string(),
+                    lines: 2
+                },
+                TagCount {
+                    tag: "grammar:〜わけだ".to_string(),
+                    lines: 2
+                },
+            ]
+        );
+        let tags = db.line_tags(&ids).unwrap();
+        assert_eq!(tags[&ids[1]], ["funny", "grammar:〜わけだ"]);
+        assert_eq!(tags[&ids[0]], ["funny"]);
+
+        let texts: Vec<String> = db
+            .find_tagged_lines("funny")
+            .unwrap()
+            .into_iter()
+            .map(|hit| hit.text)
+            .collect();
+        assert_eq!(texts, ["猫が好き", "犬が好き"]);
+
+        let mut hits = db.find_lines_containing("好き").unwrap();
+        db.retain_tagged(
+            &["funny".to_string(), "grammar:〜わけだ".to_string()],
+            &mut hits,
+        )
+        .unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].transcript_id, ids[1]);
+
+        assert_eq!(db.untag_lines("funny", &ids).unwrap(), 2);
+        assert!(db.find_tagged_lines("funny").unwrap().is_empty());
+    }
+}
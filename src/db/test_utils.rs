@@ -0,0 +1,81 @@
+use super::{DbHandler, NewEpisode, NewShow, NewTranscript, TranscriptId};
+use std::path::PathBuf;
+
+// Creates an in-memory database holding one episode of one show with the given lines
+pub(crate) fn test_db_with_lines(lines: &[&str]) -> (DbHandler, Vec<TranscriptId>) {
+    let mut db = DbHandler::new(":memory:").unwrap();
+    db.create_tables().unwrap();
+    let ids = insert_test_lines(&mut db, lines);
+    (db, ids)
+}
+
+// Adds one episode of one show with the given lines to an empty database
+pub(crate) fn insert_test_lines(db: &mut DbHandler, lines: &[&str]) -> Vec<TranscriptId> {
+    insert_show_lines(db, "Show Name", lines)
+}
+
+// Adds episode 1 of the named show with the given lines
+pub(crate) fn insert_show_lines(
+    db: &mut DbHandler,
+    show_name: &str,
+    lines: &[&str],
+) -> Vec<TranscriptId> {
+    let lines: Vec<(Option<&str>, &str)> = lines.iter().map(|&text| (None, text)).collect();
+    insert_speaker_lines(db, show_name, &lines)
+}
+
+// Adds episode 1 of the named show with the given lines, each with its speaker
+pub(crate) fn insert_speaker_lines(
+    db: &mut DbHandler,
+    show_name: &str,
+    lines: &[(Option<&str>, &str)],
+) -> Vec<TranscriptId> {
+    let show_ids = db
+        .batch_insert_shows(&[NewShow {
+            name: show_name.to_string(),
+            show_type: "Anime".to_string(),
+        }])
+        .unwrap();
+    let episode_ids = db
+        .batch_insert_episodes(&[NewEpisode {
+            show_id: show_ids[0],
+            name: "Episode 1".to_string(),
+            season: 1,
+            episode_number: Some(1),
+        }])
+        .unwrap();
+    let transcripts: Vec<NewTranscript> = lines
+        .iter()
+        .enumerate()
+        .map(|(i, (speaker, text))| NewTranscript {
+            episode_id: episode_ids[0],
+            line_id: i as i32 + 1,
+            time_start: format!("00:00:{:02},000", i),
+            time_end: format!("00:00:{:02},500", i),
+            sub_index: 0,
+            text: text.to_string(),
+            text_raw: text.to_string(),
+            speaker: speaker.map(str::to_string),
+        })
+        .collect();
+    db.batch_insert_transcripts(&transcripts, None).unwrap().ids
+}
+
+// A database file in the temp directory, deleted with its WAL files on drop
+pub(crate) struct TempDb(pub PathBuf);
+
+impl TempDb {
+    pub fn new(name: &str) -> Self {
+        TempDb(std::env::temp_dir().join(format!("{}-{}.db", name, std::process::id())))
+    }
+}
+
+impl Drop for TempDb {
+    fn drop(&mut self) {
+        for suffix in ["", "-wal", "-shm"] {
+            let mut path = self.0.clone().into_os_string();
+            path.push(suffix);
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
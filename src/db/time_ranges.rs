@@ -0,0 +1,168 @@
+use super::{DbHandler, SearchHit};
+use rusqlite::types::Value;
+use rusqlite::Result;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+/// How much of the start and end of each episode to leave out of searches,
+/// in seconds, e.g. to skip the lyrics of its opening and ending songs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TimeRange {
+    /// Lines starting this early in the episode are left out
+    pub skip_start: u32,
+    /// Lines ending this close to the end of the episode (the end of its last line)
+    /// are left out
+    pub skip_end: u32,
+}
+
+impl TimeRange {
+    pub fn is_empty(&self) -> bool {
+        self.skip_start == 0 && self.skip_end == 0
+    }
+
+    // The condition keeping a line of the range, with its parameters
+    fn condition(&self, params: &mut Vec<Value>) -> String {
+        let mut conditions = Vec::new();
+        if self.skip_start > 0 {
+            conditions.push(format!("{} >= ?", millis("transcripts.time_start")));
+            params.push(Value::Integer(i64::from(self.skip_start) * 1000));
+        }
+        if self.skip_end > 0 {
+            // A line late enough in the episode must end long enough before another does
+            conditions.push(format!(
+                "EXISTS (SELECT 1 FROM transcripts AS later
+                         WHERE later.episode_id = transcripts.episode_id
+                           AND {} >= {} + ?)",
+                millis("later.time_end"),
+                millis("transcripts.time_end")
+            ));
+            params.push(Value::Integer(i64::from(self.skip_end) * 1000));
+        }
+        if conditions.is_empty() {
+            "1".to_string()
+        } else {
+            conditions.join(" AND ")
+        }
+    }
+}
+
+/// The `[time_range]` config section: the start and end of episodes that
+/// searches skip in every show, and in particular shows.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TimeRangeConfig {
+    pub skip_start: u32,
+    pub skip_end: u32,
+    /// Ranges of shows whose opening or ending differs, by show name
+    pub shows: BTreeMap<String, TimeRange>,
+}
+
+impl TimeRangeConfig {
+    /// The range skipped in every show without its own.
+    pub fn default_range(&self) -> TimeRange {
+        TimeRange {
+            skip_start: self.skip_start,
+            skip_end: self.skip_end,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.default_range().is_empty() && self.shows.values().all(TimeRange::is_empty)
+    }
+
+    // The condition keeping the lines outside the skipped ranges, for a query selecting
+    // from transcripts joined with shows; None if nothing is skipped
+    pub(super) fn sql_condition(&self) -> Option<(String, Vec<Value>)> {
+        if self.is_empty() {
+            return None;
+        }
+        let mut params = Vec::new();
+        let mut cases = String::new();
+        for (show, range) in &self.shows {
+            params.push(Value::Text(show.clone()));
+            cases.push_str(&format!(" WHEN ? THEN {}", range.condition(&mut params)));
+        }
+        let default = self.default_range().condition(&mut params);
+        let condition = if cases.is_empty() {
+            default
+        } else {
+            format!("CASE shows.name{} ELSE {} END", cases, default)
+        };
+        Some((format!("({})", condition), params))
+    }
+}
+
+// The milliseconds of an SRT timestamp column, e.g. 62500 for 00:01:02,500
+fn millis(column: &str) -> String {
+    format!(
+        "(CAST(substr({0}, 1, 2) AS INTEGER) * 3600000 + CAST(substr({0}, 4, 2) AS INTEGER) * 60000
+          + CAST(substr({0}, 7, 2) AS INTEGER) * 1000 + CAST(substr({0}, 10, 3) AS INTEGER))",
+        column
+    )
+}
+
+impl DbHandler {
+    // Drops the hits from the start and end of episodes `config` skips, for searches
+    // that don't go through the word index; word queries skip them in their SQL
+    pub fn retain_time_range(
+        &self,
+        config: &TimeRangeConfig,
+        hits: &mut Vec<SearchHit>,
+    ) -> Result<()> {
+        let Some((condition, params)) = config.sql_condition() else {
+            return Ok(());
+        };
+        let kept = self.hits_matching(hits, &condition, &params)?;
+        hits.retain(|hit| kept.contains(&hit.transcript_id));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_utils::insert_show_lines;
+    use super::super::WordQuery;
+    use super::*;
+    use crate::search::parse_query;
+    use crate::tokenizer::test_utils::test_tokenizer;
+
+    #[test]
+    fn test_time_ranges() {
+        // insert_show_lines starts line i at second i and ends it half a second later
+        let mut db = DbHandler::new(":memory:").unwrap();
+        db.create_tables().unwrap();
+        let lines = ["猫がいる", "猫が走る", "猫が寝る", "猫が鳴く"];
+        let mut ids = insert_show_lines(&mut db, "Show Name", &lines);
+        ids.extend(insert_show_lines(&mut db, "Other Show", &lines));
+        db.index_transcripts(&test_tokenizer(), &ids).unwrap();
+
+        let config: TimeRangeConfig =
+            toml::from_str("skip_start = 2\n[shows.\"Other Show\"]\nskip_start = 0\nskip_end = 2")
+                .unwrap();
+        let query = WordQuery {
+            time_range: config.clone(),
+            ..parse_query(&test_tokenizer(), "猫")
+        };
+        let texts = |hits: Vec<SearchHit>| -> Vec<(String, String)> {
+            hits.into_iter()
+                .map(|hit| (hit.show_name, hit.text))
+                .collect()
+        };
+        let expected = [
+            ("Other Show", "猫がいる"),
+            ("Other Show", "猫が走る"),
+            ("Show Name", "猫が寝る"),
+            ("Show Name", "猫が鳴く"),
+        ]
+        .map(|(show, text)| (show.to_string(), text.to_string()));
+        assert_eq!(texts(db.find_lines(&query).unwrap()), expected);
+
+        let mut hits = db.find_lines_containing("猫").unwrap();
+        db.retain_time_range(&config, &mut hits).unwrap();
+        assert_eq!(texts(hits), expected);
+
+        assert!(TimeRangeConfig::default().is_empty());
+        assert_eq!(TimeRangeConfig::default().sql_condition(), None);
+    }
+}
@@ -0,0 +1,130 @@
+use super::{DbHandler, NewTranslation, TranscriptId};
+use rusqlite::{params, OptionalExtension, Result};
+use std::collections::HashMap;
+
+impl DbHandler {
+    // Stores English translations for existing lines, replacing any they already had
+    // Translations whose line doesn't exist are skipped, and those matching several lines
+    // are stored for each; returns the number stored
+    pub fn batch_insert_translations(&mut self, translations: &[NewTranslation]) -> Result<usize> {
+        let tx = self.conn.savepoint()?;
+        let mut inserted = 0;
+        {
+            let mut stmt = tx.prepare_cached(
+                "INSERT OR REPLACE INTO translations (transcript_id, text)
+                 SELECT id, ? FROM transcripts
+                 WHERE episode_id = ? AND time_start = ? AND time_end = ?",
+            )?;
+            for translation in translations {
+                inserted += stmt.execute(params![
+                    translation.text,
+                    translation.episode_id,
+                    translation.time_start,
+                    translation.time_end
+                ])?;
+            }
+        }
+        tx.commit()?;
+        Ok(inserted)
+    }
+
+    // The cached machine translations by `provider` of the given lines, keyed by line
+    // Kept apart from the subtitle translations, which come with the lines' hits
+    pub fn machine_translations(
+        &self,
+        provider: &str,
+        transcript_ids: &[TranscriptId],
+    ) -> Result<HashMap<TranscriptId, String>> {
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT text FROM machine_translations WHERE transcript_id = ? AND provider = ?",
+        )?;
+        let mut translations = HashMap::new();
+        for &id in transcript_ids {
+            if let Some(text) = stmt
+                .query_row(params![id, provider], |row| row.get(0))
+                .optional()?
+            {
+                translations.insert(id, text);
+            }
+        }
+        Ok(translations)
+    }
+
+    // Caches machine translations by `provider`, replacing earlier ones by the same provider
+    pub fn insert_machine_translations(
+        &mut self,
+        provider: &str,
+        translations: &[(TranscriptId, String)],
+    ) -> Result<()> {
+        let tx = self.conn.savepoint()?;
+        {
+            let mut stmt = tx.prepare_cached(
+                "INSERT OR REPLACE INTO machine_translations (transcript_id, provider, text)
+                 VALUES (?, ?, ?)",
+            )?;
+            for (id, text) in translations {
+                stmt.execute(params![id, provider, text])?;
+            }
+        }
+        tx.commit()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_utils::test_db_with_lines;
+    use super::*;
+
+    #[test]
+    fn test_translations_are_returned_with_hits() {
+        let (mut db, ids) = test_db_with_lines(&["行こう", "うん"]);
+        let episode_id = db.get_transcript(ids[0]).unwrap().unwrap().episode_id;
+        let inserted = db
+            .batch_insert_translations(&[
+                NewTranslation {
+                    episode_id,
+                    time_start: "00:00:00,000".to_string(),
+                    time_end: "00:00:00,500".to_string(),
+                    text: "Let's go.".to_string(),
+                },
+                NewTranslation {
+                    episode_id,
+                    time_start: "00:09:00,000".to_string(),
+                    time_end: "00:09:00,500".to_string(),
+                    text: "No such line.".to_string(),
+                },
+            ])
+            .unwrap();
+        assert_eq!(inserted, 1);
+
+        let hits = db.find_hits_by_ids(&ids).unwrap();
+        assert_eq!(hits[0].translation.as_deref(), Some("Let's go."));
+        assert_eq!(hits[1].translation, None);
+
+        db.delete_episode_lines(episode_id).unwrap();
+        let remaining: i64 = db
+            .conn
+            .query_row("SELECT COUNT(*) FROM translations", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(remaining, 0);
+    }
+
+    #[test]
+    fn test_machine_translations_are_cached_per_provider() {
+        let (mut db, ids) = test_db_with_lines(&["行こう", "うん"]);
+        db.insert_machine_translations("deepl", &[(ids[0], "Let's go.".to_string())])
+            .unwrap();
+        db.insert_machine_translations("ollama:model", &[(ids[0], "Let us go.".to_string())])
+            .unwrap();
+
+        let cached = db.machine_translations("deepl", &ids).unwrap();
+        assert_eq!(cached.len(), 1);
+        assert_eq!(cached[&ids[0]], "Let's go.");
+        // Machine translations aren't subtitle translations
+        assert_eq!(db.find_hits_by_ids(&ids).unwrap()[0].translation, None);
+
+        let episode_id = db.get_transcript(ids[0]).unwrap().unwrap().episode_id;
+        db.delete_episode_lines(episode_id).unwrap();
+        assert!(db.machine_translations("deepl", &ids).unwrap().is_empty());
+    }
+}
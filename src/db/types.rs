@@ -0,0 +1,139 @@
+use rusqlite::types::{FromSql, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
+use serde::Serialize;
+use std::fmt;
+
+macro_rules! id_newtype {
+    ($name:ident) => {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+        #[serde(transparent)]
+        pub struct $name(pub i64);
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "{}", self.0)
+            }
+        }
+
+        impl ToSql for $name {
+            fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+                self.0.to_sql()
+            }
+        }
+
+        impl FromSql for $name {
+            fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+                i64::column_result(value).map($name)
+            }
+        }
+    };
+}
+
+id_newtype!(ShowId);
+id_newtype!(EpisodeId);
+id_newtype!(TranscriptId);
+
+/// How an episode is referred to in listings: `S01E02`, or `Movie` for a movie.
+pub fn episode_label(season: i32, episode_number: Option<i32>) -> String {
+    match episode_number {
+        Some(number) => format!("S{:02}E{:02}", season, number),
+        None => "Movie".to_string(),
+    }
+}
+
+/// An English translation of a line, to be inserted into the `translations` table.
+///
+/// The line is identified by its episode and timestamps. These aren't unique:
+/// every line sharing them, such as two speakers' lines in one cue, gets the text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NewTranslation {
+    pub episode_id: EpisodeId,
+    pub time_start: String,
+    pub time_end: String,
+    pub text: String,
+}
+
+/// A show to be inserted into the `shows` table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NewShow {
+    pub name: String,
+    pub show_type: String,
+}
+
+/// An episode to be inserted into the `episodes` table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NewEpisode {
+    pub show_id: ShowId,
+    pub name: String,
+    pub season: i32,
+    /// `None` for a movie, which a show has at most one of per season
+    pub episode_number: Option<i32>,
+}
+
+/// A single subtitle line to be inserted into the `transcripts` table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NewTranscript {
+    pub episode_id: EpisodeId,
+    pub line_id: i32,
+    pub time_start: String,
+    pub time_end: String,
+    /// Tells apart cues shown at the same time, such as top and bottom text:
+    /// 0 for the first, 1 for the next, and so on
+    pub sub_index: i32,
+    /// The cleaned text, which is indexed and displayed
+    pub text: String,
+    /// The line as it appeared in the subtitle file (markup, speaker names and all),
+    /// for exporting back to subtitle files
+    pub text_raw: String,
+    /// Who says the line, from the actor field of ASS events or a bracketed
+    /// name before the text, e.g. （太郎）
+    pub speaker: Option<String>,
+}
+
+/// What inserting a batch of lines did.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InsertedTranscripts {
+    /// Lines that were inserted, or replaced, and so need indexing
+    pub ids: Vec<TranscriptId>,
+    /// Lines whose episode and start and end time were already taken, handled by
+    /// the lines [`ConflictPolicy`](super::ConflictPolicy)
+    pub conflicts: usize,
+}
+
+/// A row of the `shows` table.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Show {
+    pub id: ShowId,
+    pub name: String,
+    pub show_type: String,
+    /// The corpus the show belongs to, e.g. `anime` or `drama`
+    pub corpus: String,
+}
+
+/// A row of the `episodes` table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Episode {
+    pub id: EpisodeId,
+    pub show_id: ShowId,
+    pub name: String,
+    pub season: i32,
+    /// `None` for a movie
+    pub episode_number: Option<i32>,
+}
+
+/// A row of the `transcripts` table: one subtitle line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Transcript {
+    pub id: TranscriptId,
+    pub episode_id: EpisodeId,
+    pub line_id: i32,
+    pub time_start: String,
+    pub time_end: String,
+    pub text: String,
+    /// The line as it appeared in the subtitle file, before cleaning
+    pub text_raw: String,
+    /// Who says the line, if the subtitle file named them
+    pub speaker: Option<String>,
+    /// An id derived from the line's show, episode, start time and text, that
+    /// stays the same when the line is reingested (see [`super::stable_line_id`])
+    pub stable_id: String,
+}
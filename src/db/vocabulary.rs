@@ -0,0 +1,165 @@
+use super::{DbHandler, TranscriptId};
+use rusqlite::{params, OptionalExtension, Result};
+use std::collections::{HashMap, HashSet};
+
+impl DbHandler {
+    // How many times each indexed word occurs in a show, or None if there is no such show
+    pub fn show_word_counts(&self, show_name: &str) -> Result<Option<HashMap<String, i64>>> {
+        let exists = self
+            .conn
+            .prepare_cached("SELECT 1 FROM shows WHERE name = ?")?
+            .query_row(params![show_name], |_| Ok(()))
+            .optional()?;
+        if exists.is_none() {
+            return Ok(None);
+        }
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT words.word, COUNT(*)
+             FROM word_occurrences
+             JOIN words ON words.id = word_occurrences.word_id
+             JOIN transcripts ON transcripts.id = word_occurrences.transcript_id
+             JOIN episodes ON episodes.id = transcripts.episode_id
+             JOIN shows ON shows.id = episodes.show_id
+             WHERE shows.name = ?
+             GROUP BY words.word",
+        )?;
+        let counts = stmt
+            .query_map(params![show_name], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<_>>()?;
+        Ok(Some(counts))
+    }
+
+    // Every line of a show, with who says it if that's known, in episode order; None if
+    // there is no such show
+    pub fn speaker_lines(&self, show_name: &str) -> Result<Option<Vec<(Option<String>, String)>>> {
+        let exists = self
+            .conn
+            .prepare_cached("SELECT 1 FROM shows WHERE name = ?")?
+            .query_row(params![show_name], |_| Ok(()))
+            .optional()?;
+        if exists.is_none() {
+            return Ok(None);
+        }
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT transcripts.speaker, transcripts.text
+             FROM transcripts
+             JOIN episodes ON episodes.id = transcripts.episode_id
+             JOIN shows ON shows.id = episodes.show_id
+             WHERE shows.name = ?
+             ORDER BY episodes.season, episodes.episode_number, transcripts.time_start,
+                      transcripts.sub_index",
+        )?;
+        let lines = stmt
+            .query_map(params![show_name], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<_>>()?;
+        Ok(Some(lines))
+    }
+
+    // Every occurrence of an indexed word: the line, the token position within it, and its text
+    pub fn word_positions(&self, word: &str) -> Result<Vec<(TranscriptId, i64, String)>> {
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT transcripts.id, word_occurrences.position, transcripts.text
+             FROM words
+             JOIN word_occurrences ON word_occurrences.word_id = words.id
+             JOIN transcripts ON transcripts.id = word_occurrences.transcript_id
+             WHERE words.word = ?
+             ORDER BY transcripts.id, word_occurrences.position",
+        )?;
+        let positions = stmt
+            .query_map(params![word], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+            })?
+            .collect();
+        positions
+    }
+
+    // How many indexed words of each line fall outside the `common` most frequent words
+    // Lines without any are left out
+    pub fn rare_word_counts(
+        &self,
+        transcript_ids: &[TranscriptId],
+        common: usize,
+    ) -> Result<HashMap<TranscriptId, usize>> {
+        let mut stmt = self.conn.prepare_cached(
+            "WITH common AS (SELECT id FROM words ORDER BY frequency DESC LIMIT ?)
+             SELECT COUNT(*) FROM word_occurrences
+             WHERE transcript_id = ? AND word_id NOT IN (SELECT id FROM common)",
+        )?;
+        let mut counts = HashMap::new();
+        for &id in transcript_ids {
+            let count: i64 = stmt.query_row(params![common as i64, id], |row| row.get(0))?;
+            if count > 0 {
+                counts.insert(id, count as usize);
+            }
+        }
+        Ok(counts)
+    }
+
+    // The indexed words of each line, in token order; lines without any are left out
+    pub fn line_words(
+        &self,
+        transcript_ids: &[TranscriptId],
+    ) -> Result<HashMap<TranscriptId, Vec<String>>> {
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT words.word FROM word_occurrences
+             JOIN words ON words.id = word_occurrences.word_id
+             WHERE word_occurrences.transcript_id = ?
+             ORDER BY word_occurrences.position",
+        )?;
+        let mut words = HashMap::new();
+        for &id in transcript_ids {
+            let line: Vec<String> = stmt
+                .query_map(params![id], |row| row.get(0))?
+                .collect::<Result<_>>()?;
+            if !line.is_empty() {
+                words.insert(id, line);
+            }
+        }
+        Ok(words)
+    }
+
+    // The `limit` most frequent indexed words
+    pub fn common_words(&self, limit: usize) -> Result<HashSet<String>> {
+        let mut stmt = self
+            .conn
+            .prepare_cached("SELECT word FROM words ORDER BY frequency DESC, word LIMIT ?")?;
+        let words = stmt
+            .query_map(params![limit as i64], |row| row.get(0))?
+            .collect();
+        words
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_utils::{insert_show_lines, test_db_with_lines};
+    use crate::tokenizer::test_utils::test_tokenizer;
+
+    #[test]
+    fn test_show_word_counts() {
+        let (mut db, mut ids) = test_db_with_lines(&["猫が好き", "猫だ"]);
+        ids.extend(insert_show_lines(&mut db, "Other Show", &["犬"]));
+        db.index_transcripts(&test_tokenizer(), &ids).unwrap();
+
+        let counts = db.show_word_counts("Show Name").unwrap().unwrap();
+        assert_eq!(counts["猫"], 2);
+        assert!(!counts.contains_key("犬"));
+        assert_eq!(db.show_word_counts("Missing").unwrap(), None);
+
+        // Only 猫 is among the single most frequent words
+        let rare = db.rare_word_counts(&ids, 1).unwrap();
+        assert_eq!(rare.get(&ids[0]), Some(&1));
+        assert_eq!(rare.get(&ids[2]), Some(&1));
+        assert!(db.rare_word_counts(&ids, 100).unwrap().is_empty());
+
+        let positions = db.word_positions("猫").unwrap();
+        assert_eq!(positions.len(), 2);
+        assert_eq!(positions[0].1, 0);
+        assert_eq!(positions[0].2, "猫が好き");
+
+        let words = db.line_words(&ids).unwrap();
+        assert_eq!(words[&ids[0]], ["猫", "好き"]);
+        assert_eq!(words[&ids[2]], ["犬"]);
+        assert!(db.common_words(1).unwrap().contains("猫"));
+    }
+}
@@ -0,0 +1,278 @@
+use super::{DbHandler, EpisodeId, TranscriptId};
+use crate::tokenizer::{is_kanji, normalize, Tokenizer};
+use rusqlite::{params, Connection, Result};
+use std::collections::{HashMap, HashSet};
+
+impl DbHandler {
+    // Tokenizes the given transcript lines and records which words occur in which lines
+    // Words are stored by their dictionary form, so 走った and 走る share an entry
+    // Each occurrence is stored with its token position and part-of-speech
+    // (joined with '-', e.g. 動詞-自立), for proximity and POS-filtered queries
+    // Each word is linked to a lemma keyed by its hiragana reading, so that
+    // spellings in kana and kanji (おもしろい, 面白い) can be searched together
+    // Each word's frequency (its number of occurrences) is kept up to date
+    // Stopwords are skipped according to the tokenizer's StopwordStrategy
+    // Every kanji character is also indexed on its own, regardless of tokenization
+    pub fn index_transcripts(
+        &mut self,
+        tokenizer: &dyn Tokenizer,
+        transcript_ids: &[TranscriptId],
+    ) -> Result<()> {
+        println!("Indexing words...");
+        let tx = self.conn.savepoint()?;
+        {
+            let mut select_text = tx.prepare_cached("SELECT text FROM transcripts WHERE id = ?")?;
+            let mut insert_lemma =
+                tx.prepare_cached("INSERT OR IGNORE INTO lemmas (reading) VALUES (?)")?;
+            let mut select_lemma = tx.prepare_cached("SELECT id FROM lemmas WHERE reading = ?")?;
+            let mut insert_word =
+                tx.prepare_cached("INSERT OR IGNORE INTO words (word, lemma_id) VALUES (?, ?)")?;
+            // Words indexed before they had a lemma get one the next time they're seen
+            let mut set_lemma = tx.prepare_cached(
+                "UPDATE words SET lemma_id = ? WHERE word = ? AND lemma_id IS NULL",
+            )?;
+            let mut select_word = tx.prepare_cached("SELECT id FROM words WHERE word = ?")?;
+            let mut insert_occurrence = tx.prepare_cached(
+                "INSERT OR IGNORE INTO word_occurrences (word_id, transcript_id, position, pos)
+                 VALUES (?, ?, ?, ?)",
+            )?;
+            let mut insert_kanji = tx.prepare_cached(
+                "INSERT OR IGNORE INTO kanji_occurrences (kanji, transcript_id) VALUES (?, ?)",
+            )?;
+            let mut word_ids: HashMap<String, i64> = HashMap::new();
+
+            for &transcript_id in transcript_ids {
+                let text: String =
+                    select_text.query_row(params![transcript_id], |row| row.get(0))?;
+                for (position, token) in tokenizer.index_tokens(&text) {
+                    let pos = token.pos.join("-");
+                    let word_id = match word_ids.get(&token.base_form) {
+                        Some(&id) => id,
+                        None => {
+                            let lemma_id: Option<i64> = match token.base_reading() {
+                                Some(reading) => {
+                                    insert_lemma.execute(params![reading])?;
+                                    Some(
+                                        select_lemma
+                                            .query_row(params![reading], |row| row.get(0))?,
+                                    )
+                                }
+                                None => None,
+                            };
+                            let word = token.base_form;
+                            insert_word.execute(params![word, lemma_id])?;
+                            if lemma_id.is_some() {
+                                set_lemma.execute(params![lemma_id, word])?;
+                            }
+                            let id = select_word.query_row(params![word], |row| row.get(0))?;
+                            word_ids.insert(word, id);
+                            id
+                        }
+                    };
+                    insert_occurrence.execute(params![
+                        word_id,
+                        transcript_id,
+                        position as i64,
+                        pos
+                    ])?;
+                }
+
+                // From the normalized text, as find_lines_containing looks kanji up by it
+                let kanji: HashSet<char> =
+                    normalize(&text).chars().filter(|&c| is_kanji(c)).collect();
+                for c in kanji {
+                    insert_kanji.execute(params![c.to_string(), transcript_id])?;
+                }
+            }
+            update_word_frequencies(&tx, word_ids.values().copied())?;
+        }
+        tx.commit()
+    }
+
+    // Removes an episode's lines from the word and kanji indexes
+    // The words themselves are kept, since other lines may still use them
+    pub fn delete_episode_index(&mut self, episode_id: EpisodeId) -> Result<()> {
+        let tx = self.conn.savepoint()?;
+        delete_episode_index(&tx, episode_id)?;
+        tx.commit()
+    }
+
+    // Deletes an episode's lines along with their translations and word and kanji index entries
+    // The episode itself is kept; returns the number of lines deleted
+    pub fn delete_episode_lines(&mut self, episode_id: EpisodeId) -> Result<usize> {
+        let tx = self.conn.savepoint()?;
+        delete_episode_index(&tx, episode_id)?;
+        for table in [
+            "translations",
+            "machine_translations",
+            "line_explanations",
+            "bookmarks",
+            "line_tags",
+            "notes",
+        ] {
+            tx.execute(
+                &format!(
+                    "DELETE FROM {} WHERE transcript_id IN
+                     (SELECT id FROM transcripts WHERE episode_id = ?)",
+                    table
+                ),
+                params![episode_id],
+            )?;
+        }
+        let deleted = tx.execute(
+            "DELETE FROM transcripts WHERE episode_id = ?",
+            params![episode_id],
+        )?;
+        tx.commit()?;
+        Ok(deleted)
+    }
+
+    // Empties the word and kanji indexes along with the words, lemmas and index metadata,
+    // so every line can be indexed afresh; the lines themselves are kept
+    pub fn clear_word_index(&mut self) -> Result<()> {
+        let tx = self.conn.savepoint()?;
+        for table in [
+            "word_occurrences",
+            "kanji_occurrences",
+            "words",
+            "lemmas",
+            "index_meta",
+        ] {
+            tx.execute(&format!("DELETE FROM {}", table), [])?;
+        }
+        tx.commit()
+    }
+}
+
+fn delete_episode_index(tx: &Connection, episode_id: EpisodeId) -> Result<()> {
+    let word_ids: Vec<i64> = tx
+        .prepare(
+            "SELECT DISTINCT word_id FROM word_occurrences WHERE transcript_id IN
+             (SELECT id FROM transcripts WHERE episode_id = ?)",
+        )?
+        .query_map(params![episode_id], |row| row.get(0))?
+        .collect::<Result<_>>()?;
+    for table in ["word_occurrences", "kanji_occurrences"] {
+        tx.execute(
+            &format!(
+                "DELETE FROM {} WHERE transcript_id IN
+                 (SELECT id FROM transcripts WHERE episode_id = ?)",
+                table
+            ),
+            params![episode_id],
+        )?;
+    }
+    update_word_frequencies(tx, word_ids)
+}
+
+// Removes lines from the word and kanji indexes, e.g. before indexing their new text
+pub(super) fn delete_line_index(tx: &Connection, transcript_ids: &[TranscriptId]) -> Result<()> {
+    let mut word_ids = HashSet::new();
+    {
+        let mut select = tx.prepare_cached(
+            "SELECT DISTINCT word_id FROM word_occurrences WHERE transcript_id = ?",
+        )?;
+        for &transcript_id in transcript_ids {
+            for word_id in select.query_map(params![transcript_id], |row| row.get(0))? {
+                word_ids.insert(word_id?);
+            }
+        }
+    }
+    for table in ["word_occurrences", "kanji_occurrences"] {
+        let mut delete =
+            tx.prepare_cached(&format!("DELETE FROM {} WHERE transcript_id = ?", table))?;
+        for &transcript_id in transcript_ids {
+            delete.execute(params![transcript_id])?;
+        }
+    }
+    update_word_frequencies(tx, word_ids)
+}
+
+// Recounts the occurrences of the given words
+fn update_word_frequencies(tx: &Connection, word_ids: impl IntoIterator<Item = i64>) -> Result<()> {
+    let mut update = tx.prepare_cached(
+        "UPDATE words SET frequency =
+         (SELECT COUNT(*) FROM word_occurrences WHERE word_occurrences.word_id = words.id)
+         WHERE id = ?",
+    )?;
+    for word_id in word_ids {
+        update.execute(params![word_id])?;
+    }
+    Ok(())
+}
+
+// Recounts the occurrences of every word, after index entries were deleted wholesale
+pub(super) fn recount_word_frequencies(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "UPDATE words SET frequency =
+         (SELECT COUNT(*) FROM word_occurrences WHERE word_occurrences.word_id = words.id)",
+        [],
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_utils::test_db_with_lines;
+    use crate::tokenizer::test_utils::test_tokenizer;
+
+    #[test]
+    fn test_index_transcripts_stores_base_forms() {
+        let (mut db, ids) = test_db_with_lines(&["猫が走った"]);
+        db.index_transcripts(&test_tokenizer(), &ids).unwrap();
+
+        let transcript_id: TranscriptId = db
+            .conn
+            .query_row(
+                "SELECT transcript_id FROM word_occurrences
+                 JOIN words ON words.id = word_occurrences.word_id
+                 WHERE words.word = '走る'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(transcript_id, ids[0]);
+    }
+
+    #[test]
+    fn test_word_frequencies_follow_the_index() {
+        let (mut db, ids) = test_db_with_lines(&["走った", "走る"]);
+        db.index_transcripts(&test_tokenizer(), &ids).unwrap();
+        let frequency = |db: &DbHandler| -> i64 {
+            db.conn
+                .query_row(
+                    "SELECT frequency FROM words WHERE word = '走る'",
+                    [],
+                    |row| row.get(0),
+                )
+                .unwrap()
+        };
+        assert_eq!(frequency(&db), 2);
+        // Reindexing the same lines doesn't count them twice
+        db.index_transcripts(&test_tokenizer(), &ids).unwrap();
+        assert_eq!(frequency(&db), 2);
+        db.delete_episode_index(EpisodeId(1)).unwrap();
+        assert_eq!(frequency(&db), 0);
+    }
+
+    #[test]
+    fn test_clear_word_index() {
+        let (mut db, ids) = test_db_with_lines(&["猫が走った"]);
+        db.index_transcripts(&test_tokenizer(), &ids).unwrap();
+        db.set_index_meta("tokenizer", "japanese/ipadic v2")
+            .unwrap();
+        db.clear_word_index().unwrap();
+        assert_eq!(db.index_meta("tokenizer").unwrap(), None);
+        for table in ["word_occurrences", "kanji_occurrences", "words", "lemmas"] {
+            let rows: i64 = db
+                .conn
+                .query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| {
+                    row.get(0)
+                })
+                .unwrap();
+            assert_eq!(rows, 0, "{}", table);
+        }
+        assert_eq!(db.all_transcript_ids().unwrap(), ids);
+    }
+}
@@ -0,0 +1,205 @@
+//! Readability scores for episodes and shows, so learners can pick what to watch next.
+//!
+//! An episode's score runs from 0 (easiest) to 100 and averages four measures,
+//! each scaled to 0–1 (see [`DifficultyMeasures`]):
+//! - how long its lines are, up to [`LONG_LINE`] characters
+//! - how much of its text is kanji, up to [`DENSE_KANJI`]
+//! - the average JLPT level of its words, from N5 to N1 (left out if unknown)
+//! - how many of its words are rare in the corpus, up to [`RARE_WORDS`]
+
+use crate::db::{DbHandler, DifficultyMeasures, EpisodeId};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Average line length, in characters, at which lines count as fully hard.
+pub const LONG_LINE: f64 = 30.0;
+
+/// Kanji density at which text counts as fully hard.
+pub const DENSE_KANJI: f64 = 0.4;
+
+/// Share of rare words at which vocabulary counts as fully hard.
+pub const RARE_WORDS: f64 = 0.2;
+
+/// How hard an episode is, with what the score is made of.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EpisodeDifficulty {
+    pub episode_id: EpisodeId,
+    pub show_name: String,
+    pub season: i32,
+    /// `None` for a movie
+    pub episode_number: Option<i32>,
+    pub average_line_length: f64,
+    pub kanji_density: f64,
+    pub average_jlpt_level: Option<f64>,
+    pub rare_word_share: f64,
+    /// From 0 (easiest) to 100
+    pub score: f64,
+}
+
+/// How hard a show is: the average score of its episodes.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ShowDifficulty {
+    pub show_name: String,
+    pub episodes: usize,
+    pub score: f64,
+}
+
+#[derive(Debug)]
+pub enum DifficultyError {
+    /// There is no show with this name, or it has no lines
+    UnknownShow(String),
+    DbError(rusqlite::Error),
+}
+
+impl From<rusqlite::Error> for DifficultyError {
+    fn from(error: rusqlite::Error) -> Self {
+        DifficultyError::DbError(error)
+    }
+}
+
+impl fmt::Display for DifficultyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DifficultyError::UnknownShow(name) => write!(f, "No show named {:?} has lines", name),
+            DifficultyError::DbError(e) => write!(f, "Database error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for DifficultyError {}
+
+/// Combines the measures into a score from 0 (easiest) to 100.
+pub fn score(measures: &DifficultyMeasures) -> f64 {
+    let mut parts = vec![
+        measures.average_line_length / LONG_LINE,
+        measures.kanji_density / DENSE_KANJI,
+        measures.rare_word_share / RARE_WORDS,
+    ];
+    if let Some(level) = measures.average_jlpt_level {
+        parts.push((5.0 - level) / 4.0);
+    }
+    let total: f64 = parts.iter().map(|part| part.clamp(0.0, 1.0)).sum();
+    total / parts.len() as f64 * 100.0
+}
+
+/// Scores every episode with lines, optionally only those of one show, in show and episode order.
+pub fn episode_difficulty(
+    db: &DbHandler,
+    show: Option<&str>,
+) -> Result<Vec<EpisodeDifficulty>, DifficultyError> {
+    let mut measures = db.difficulty_measures()?;
+    let mut episodes = Vec::new();
+    for stats in db.episode_stats(show)? {
+        let Some(measures) = measures.remove(&stats.episode_id) else {
+            continue;
+        };
+        episodes.push(EpisodeDifficulty {
+            episode_id: stats.episode_id,
+            show_name: stats.show_name,
+            season: stats.season,
+            episode_number: stats.episode_number,
+            average_line_length: measures.average_line_length,
+            kanji_density: measures.kanji_density,
+            average_jlpt_level: measures.average_jlpt_level,
+            rare_word_share: measures.rare_word_share,
+            score: score(&measures),
+        });
+    }
+    Ok(episodes)
+}
+
+/// Scores every show with lines, easiest first.
+pub fn show_difficulty(db: &DbHandler) -> Result<Vec<ShowDifficulty>, DifficultyError> {
+    let mut scores: BTreeMap<String, Vec<f64>> = BTreeMap::new();
+    for episode in episode_difficulty(db, None)? {
+        scores
+            .entry(episode.show_name)
+            .or_default()
+            .push(episode.score);
+    }
+    let mut shows: Vec<ShowDifficulty> = scores
+        .into_iter()
+        .map(|(show_name, scores)| ShowDifficulty {
+            show_name,
+            episodes: scores.len(),
+            score: scores.iter().sum::<f64>() / scores.len() as f64,
+        })
+        .collect();
+    shows.sort_by(|a, b| a.score.total_cmp(&b.score));
+    Ok(shows)
+}
+
+/// The shows easier than `show_name`, easiest first.
+pub fn shows_easier_than(
+    db: &DbHandler,
+    show_name: &str,
+) -> Result<Vec<ShowDifficulty>, DifficultyError> {
+    shows_relative_to(db, show_name, |score, reference| score < reference)
+}
+
+/// The shows harder than `show_name`, easiest first.
+pub fn shows_harder_than(
+    db: &DbHandler,
+    show_name: &str,
+) -> Result<Vec<ShowDifficulty>, DifficultyError> {
+    shows_relative_to(db, show_name, |score, reference| score > reference)
+}
+
+// The shows whose score passes `keep` given the score of `show_name`
+fn shows_relative_to(
+    db: &DbHandler,
+    show_name: &str,
+    keep: impl Fn(f64, f64) -> bool,
+) -> Result<Vec<ShowDifficulty>, DifficultyError> {
+    let shows = show_difficulty(db)?;
+    let reference = shows
+        .iter()
+        .find(|show| show.show_name == show_name)
+        .ok_or_else(|| DifficultyError::UnknownShow(show_name.to_string()))?
+        .score;
+    Ok(shows
+        .into_iter()
+        .filter(|show| keep(show.score, reference))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_utils::test_db_with_lines;
+
+    fn measures(length: f64, kanji: f64, jlpt: Option<f64>, rare: f64) -> DifficultyMeasures {
+        DifficultyMeasures {
+            episode_id: EpisodeId(1),
+            average_line_length: length,
+            kanji_density: kanji,
+            average_jlpt_level: jlpt,
+            rare_word_share: rare,
+        }
+    }
+
+    #[test]
+    fn test_score() {
+        assert_eq!(score(&measures(0.0, 0.0, Some(5.0), 0.0)), 0.0);
+        assert_eq!(score(&measures(60.0, 0.9, Some(1.0), 0.5)), 100.0);
+        // Without JLPT data the other three count a third each
+        assert_eq!(score(&measures(15.0, 0.2, None, 0.1)), 50.0);
+        assert!(
+            score(&measures(10.0, 0.1, Some(4.0), 0.0))
+                < score(&measures(20.0, 0.3, Some(2.0), 0.1))
+        );
+    }
+
+    #[test]
+    fn test_shows_easier_than() {
+        let (db, _) = test_db_with_lines(&["ねこ"]);
+        let shows = show_difficulty(&db).unwrap();
+        assert_eq!(shows.len(), 1);
+        assert!(shows_easier_than(&db, "Show Name").unwrap().is_empty());
+        assert!(matches!(
+            shows_easier_than(&db, "Shirokuma Café"),
+            Err(DifficultyError::UnknownShow(_))
+        ));
+    }
+}
@@ -0,0 +1,120 @@
+//! A search handle that can be shared between threads.
+//!
+//! [`DbHandler`] wraps a single SQLite connection, so it can't be used from
+//! several threads at once. A [`SearchEngine`] is `Send + Sync` and cheap to
+//! clone: each search borrows its own read-only connection from a [`DbPool`],
+//! so searches on different threads run in parallel instead of queueing on a
+//! mutex.
+
+use crate::db::{DbHandler, DbPool, SearchHit, TranscriptId};
+use crate::grammar::GrammarPattern;
+use crate::search::{self, Result};
+use crate::tokenizer::JapaneseTokenizer;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Read-only access to the corpus from any number of threads.
+#[derive(Clone)]
+pub struct SearchEngine {
+    pool: Arc<DbPool>,
+    tokenizer: Arc<JapaneseTokenizer>,
+}
+
+impl SearchEngine {
+    /// Searches through an existing pool, e.g. one shared with a server that also ingests.
+    pub fn new(pool: Arc<DbPool>, tokenizer: Arc<JapaneseTokenizer>) -> Self {
+        SearchEngine { pool, tokenizer }
+    }
+
+    /// Opens the database at `path` with up to `max_readers` connections, one
+    /// per thread searching at the same time; further threads wait for one.
+    pub fn open<P: AsRef<Path>>(
+        path: P,
+        tokenizer: JapaneseTokenizer,
+        max_readers: usize,
+    ) -> Result<Self> {
+        Ok(SearchEngine::new(
+            Arc::new(DbPool::open(path, max_readers)?),
+            Arc::new(tokenizer),
+        ))
+    }
+
+    pub fn pool(&self) -> &Arc<DbPool> {
+        &self.pool
+    }
+
+    pub fn tokenizer(&self) -> &Arc<JapaneseTokenizer> {
+        &self.tokenizer
+    }
+
+    /// Runs `f` with a read-only connection of its own, for queries without a method here.
+    pub fn with_reader<T>(&self, f: impl FnOnce(&DbHandler) -> Result<T>) -> Result<T> {
+        let reader = self.pool.reader()?;
+        f(&reader)
+    }
+
+    /// [`search::search`]
+    pub fn search(&self, query: &str) -> Result<Vec<SearchHit>> {
+        self.with_reader(|db| search::search(db, &self.tokenizer, query))
+    }
+
+    /// [`search::search_regex`]
+    pub fn search_regex(&self, pattern: &str) -> Result<Vec<SearchHit>> {
+        self.with_reader(|db| search::search_regex(db, pattern))
+    }
+
+    /// [`search::search_kanji`]
+    pub fn search_kanji(&self, text: &str, within_token: bool) -> Result<Vec<SearchHit>> {
+        self.with_reader(|db| search::search_kanji(db, &self.tokenizer, text, within_token))
+    }
+
+    /// [`search::search_grammar`]
+    pub fn search_grammar(&self, pattern: &GrammarPattern) -> Result<Vec<SearchHit>> {
+        self.with_reader(|db| search::search_grammar(db, &self.tokenizer, pattern))
+    }
+
+    /// The lines around a line, in the same episode (see [`DbHandler::find_context`]).
+    pub fn context(
+        &self,
+        transcript_id: TranscriptId,
+        before: u32,
+        after: u32,
+    ) -> Result<Vec<SearchHit>> {
+        self.with_reader(|db| Ok(db.find_context(transcript_id, before, after)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_utils::{insert_test_lines, TempDb};
+    use crate::tokenizer::test_utils::test_tokenizer;
+    use std::thread;
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn test_search_from_many_threads() {
+        assert_send_sync::<SearchEngine>();
+        let path = TempDb::new("engine-threads");
+        let engine = SearchEngine::open(&path.0, test_tokenizer(), 2).unwrap();
+        let ids = {
+            let mut db = engine.pool().writer();
+            let ids = insert_test_lines(&mut db, &["猫が走った", "犬が好き", "走る"]);
+            db.index_transcripts(engine.tokenizer(), &ids).unwrap();
+            ids
+        };
+
+        thread::scope(|scope| {
+            for _ in 0..6 {
+                let engine = engine.clone();
+                scope.spawn(move || {
+                    assert_eq!(engine.search("走る").unwrap().len(), 2);
+                    assert_eq!(engine.search_regex("犬|猫").unwrap().len(), 2);
+                });
+            }
+        });
+        let context = engine.context(ids[1], 1, 0).unwrap();
+        assert_eq!(context[0].text, "猫が走った");
+    }
+}
@@ -0,0 +1,130 @@
+//! An error type spanning the crate's layers, for code that reads files,
+//! parses subtitles, queries the database and calls the tokenizer or an LLM
+//! in one go.
+//!
+//! The errors of each layer keep their own types; this wraps them, with the
+//! file or query they were about where one is known, so a failure says what
+//! it was doing rather than only what went wrong.
+
+use crate::backend::BackendError;
+use crate::llm::LlmError;
+use crate::search::SearchError;
+use crate::srt_parser::ParsingError;
+use crate::tokenizer::TokenizerError;
+use std::io;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    /// A subtitle file couldn't be parsed
+    #[error("Couldn't parse {}: {source}", path.display())]
+    Parse {
+        path: PathBuf,
+        #[source]
+        source: ParsingError,
+    },
+    /// A file couldn't be opened, read or written
+    #[error("Couldn't read or write {}: {source}", path.display())]
+    File {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    /// A CSV file couldn't be written
+    #[error("Couldn't write CSV to {}: {source}", path.display())]
+    Csv {
+        path: PathBuf,
+        #[source]
+        source: csv::Error,
+    },
+    /// Reading or writing a stream that isn't a named file failed
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    #[error("Database error: {0}")]
+    Db(#[from] rusqlite::Error),
+    /// The search backend couldn't be opened or updated
+    #[error(transparent)]
+    Backend(#[from] BackendError),
+    /// A search failed
+    #[error("Search for {query:?} failed: {source}")]
+    Search {
+        query: String,
+        #[source]
+        source: SearchError,
+    },
+    #[error("Tokenizer error: {0}")]
+    Tokenizer(#[from] TokenizerError),
+    #[error("LLM error: {0}")]
+    Llm(#[from] LlmError),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Attaches the file an I/O or parsing error was about.
+pub trait WithPath<T> {
+    fn with_path(self, path: impl AsRef<Path>) -> Result<T>;
+}
+
+impl<T> WithPath<T> for std::result::Result<T, io::Error> {
+    fn with_path(self, path: impl AsRef<Path>) -> Result<T> {
+        self.map_err(|source| Error::File {
+            path: path.as_ref().to_path_buf(),
+            source,
+        })
+    }
+}
+
+impl<T> WithPath<T> for std::result::Result<T, ParsingError> {
+    fn with_path(self, path: impl AsRef<Path>) -> Result<T> {
+        self.map_err(|source| Error::Parse {
+            path: path.as_ref().to_path_buf(),
+            source,
+        })
+    }
+}
+
+impl<T> WithPath<T> for std::result::Result<T, csv::Error> {
+    fn with_path(self, path: impl AsRef<Path>) -> Result<T> {
+        self.map_err(|source| Error::Csv {
+            path: path.as_ref().to_path_buf(),
+            source,
+        })
+    }
+}
+
+/// Attaches the query a search error was about.
+pub trait WithQuery<T> {
+    fn with_query(self, query: &str) -> Result<T>;
+}
+
+impl<T> WithQuery<T> for std::result::Result<T, SearchError> {
+    fn with_query(self, query: &str) -> Result<T> {
+        self.map_err(|source| Error::Search {
+            query: query.to_string(),
+            source,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_context_in_messages() {
+        let missing =
+            std::fs::File::open("/nonexistent/subs.srt").with_path("/nonexistent/subs.srt");
+        let message = missing.unwrap_err().to_string();
+        assert!(message.starts_with("Couldn't read or write /nonexistent/subs.srt: "));
+
+        let parsed: Result<()> = Err(ParsingError::MalformedSubtitle).with_path("Show/01.srt");
+        assert_eq!(
+            parsed.unwrap_err().to_string(),
+            "Couldn't parse Show/01.srt: Malformed subtitle"
+        );
+
+        let error = Error::from(rusqlite::Error::QueryReturnedNoRows);
+        assert!(std::error::Error::source(&error).is_some());
+    }
+}
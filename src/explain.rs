@@ -0,0 +1,189 @@
+//! A breakdown of how a word query is understood and run, for `search --explain`.
+//!
+//! Shows what the tokenizer made of the query and which words it dropped,
+//! how many lines each remaining term has in the word index, how the
+//! candidates shrink as the terms are intersected, and the final SQL with
+//! SQLite's query plan. Enough to tell why a query is slow or finds nothing.
+
+use crate::db::{DbHandler, QueryProfile, QueryTerm, WordQuery, WordQuerySql};
+use crate::search::{parse_query, Result};
+use crate::tokenizer::JapaneseTokenizer;
+use regex::Regex;
+use std::collections::HashSet;
+
+/// How a word query was understood and run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryExplanation {
+    /// Every token of the free-text parts of the query
+    pub tokens: Vec<ExplainedToken>,
+    /// The words lines must contain, rarest first, the order they narrow the candidates in
+    pub terms: Vec<ExplainedTerm>,
+    /// The statement the search runs, or None if no words were left to search for
+    pub sql: Option<WordQuerySql>,
+    /// How SQLite ran the statement
+    pub profile: Option<QueryProfile>,
+}
+
+/// A token of the query and whether it became a search term.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExplainedToken {
+    pub surface: String,
+    pub base_form: String,
+    /// Part-of-speech hierarchy joined with `-`, e.g. `動詞-自立`
+    pub pos: String,
+    /// False for stopwords, which aren't in the word index
+    pub indexed: bool,
+}
+
+/// A search term with its posting list in the word index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExplainedTerm {
+    pub term: QueryTerm,
+    /// The spellings looked up, each with the number of lines it occurs in
+    pub spellings: Vec<(String, usize)>,
+    /// Occurrences of the term across all spellings
+    pub occurrences: usize,
+    /// Lines containing the term
+    pub lines: usize,
+    /// Lines containing this term and every term before it
+    pub remaining: usize,
+}
+
+/// Explains `query` as [`search`](crate::search::search) would run it on the word index.
+pub fn explain_query(
+    db: &DbHandler,
+    tokenizer: &JapaneseTokenizer,
+    query: &str,
+    exact_script: bool,
+) -> Result<QueryExplanation> {
+    let mut word_query = parse_query(tokenizer, query);
+    word_query.exact_script = exact_script;
+
+    let mut terms = Vec::new();
+    for term in &word_query.terms {
+        let spellings = if exact_script {
+            vec![term.word.clone()]
+        } else {
+            db.spelling_variants(&term.word)?
+        };
+        let pos = term.pos.as_deref();
+        let (occurrences, lines) = db.word_postings(&spellings, pos)?;
+        let spellings = spellings
+            .into_iter()
+            .map(|spelling| {
+                let (_, lines) = db.word_postings(std::slice::from_ref(&spelling), pos)?;
+                Ok((spelling, lines))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        terms.push(ExplainedTerm {
+            term: term.clone(),
+            spellings,
+            occurrences,
+            lines,
+            remaining: 0,
+        });
+    }
+    terms.sort_by_key(|term| term.lines);
+    let remaining = (1..=terms.len())
+        .map(|n| {
+            db.count_lines(&WordQuery {
+                terms: terms[..n].iter().map(|term| term.term.clone()).collect(),
+                exact_script,
+                ..WordQuery::default()
+            })
+        })
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    for (term, remaining) in terms.iter_mut().zip(remaining) {
+        term.remaining = remaining;
+    }
+
+    let sql = db.word_query_sql(&word_query)?;
+    let profile = match &sql {
+        Some(sql) => Some(db.profile_query(&sql.sql, &sql.params)?),
+        None => None,
+    };
+    Ok(QueryExplanation {
+        tokens: query_tokens(tokenizer, query),
+        terms,
+        sql,
+        profile,
+    })
+}
+
+// The tokens of the parts of `query` that get tokenized, leaving out
+// NEAR operators and `word:POS` terms, which are used as written
+fn query_tokens(tokenizer: &JapaneseTokenizer, query: &str) -> Vec<ExplainedToken> {
+    let near = Regex::new(r"\s+NEAR/\d+\s+").unwrap();
+    let mut tokens = Vec::new();
+    for chunk in near.split(query).flat_map(str::split_whitespace) {
+        let is_pos_term = chunk
+            .split_once([':', '：'])
+            .is_some_and(|(word, pos)| !word.is_empty() && !pos.is_empty());
+        if is_pos_term {
+            continue;
+        }
+        let indexed: HashSet<usize> = tokenizer
+            .index_tokens(chunk)
+            .into_iter()
+            .map(|(position, _)| position)
+            .collect();
+        tokens.extend(tokenizer.tokenize(chunk).into_iter().enumerate().map(
+            |(position, token)| ExplainedToken {
+                surface: token.surface,
+                base_form: token.base_form,
+                pos: token.pos.join("-"),
+                indexed: indexed.contains(&position),
+            },
+        ));
+    }
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_utils::test_db_with_lines;
+    use crate::tokenizer::test_utils::test_tokenizer;
+
+    #[test]
+    fn test_explain_query() {
+        let (mut db, ids) = test_db_with_lines(&["猫が走った", "猫が好き", "犬が走る", "猫"]);
+        let tokenizer = test_tokenizer();
+        db.index_transcripts(&tokenizer, &ids).unwrap();
+
+        let explanation = explain_query(&db, &tokenizer, "猫が走る", false).unwrap();
+        let surfaces: Vec<(&str, bool)> = explanation
+            .tokens
+            .iter()
+            .map(|token| (token.surface.as_str(), token.indexed))
+            .collect();
+        assert_eq!(surfaces, [("猫", true), ("が", false), ("走る", true)]);
+
+        // 走る is rarer, so it comes first
+        let terms: Vec<(&str, usize, usize)> = explanation
+            .terms
+            .iter()
+            .map(|term| (term.term.word.as_str(), term.lines, term.remaining))
+            .collect();
+        assert_eq!(terms, [("走る", 2, 2), ("猫", 3, 1)]);
+        assert_eq!(explanation.profile.unwrap().rows, 1);
+        assert!(explanation.sql.is_some());
+    }
+
+    #[test]
+    fn test_explain_query_with_unknown_word() {
+        let (mut db, ids) = test_db_with_lines(&["猫が走った"]);
+        let tokenizer = test_tokenizer();
+        db.index_transcripts(&tokenizer, &ids).unwrap();
+
+        let explanation = explain_query(&db, &tokenizer, "猫 犬", true).unwrap();
+        assert_eq!(explanation.terms[0].term.word, "犬");
+        assert_eq!(explanation.terms[0].spellings, [("犬".to_string(), 0)]);
+        assert_eq!(explanation.terms[0].remaining, 0);
+        assert_eq!(explanation.terms[1].remaining, 0);
+
+        let explanation = explain_query(&db, &tokenizer, "が", false).unwrap();
+        assert!(explanation.terms.is_empty());
+        assert!(explanation.sql.is_none());
+    }
+}
@@ -0,0 +1,254 @@
+//! Example sentences exported as tab-separated sentence pairs, for sharing
+//! curated decks with sentence-bank tools.
+//!
+//! Each row follows Tatoeba's sentence pair downloads (id, sentence,
+//! translation id, translation), with a fifth column saying where the line
+//! is from. Lines have no separate translation ids, so the line's id is used
+//! for both; the translation columns are empty for untranslated lines.
+//! Notes stored by `explain` can be added as a sixth column, for Anki cards.
+//!
+//! Bookmarked lines are exported for Anki directly, with the bookmark's note
+//! as a field and its tag as the note's tag. Notes are identified by the
+//! line's stable id, so exporting again after a reingest updates the cards
+//! instead of duplicating them.
+
+use crate::db::{episode_label, Bookmark, DbHandler, SearchHit, TranscriptId};
+use crate::search::{self, Result};
+use crate::tokenizer::JapaneseTokenizer;
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, BufRead, Write};
+
+/// Which lines [`filter_sentences`] keeps.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SentenceFilter {
+    /// Fewest characters a line may have, not counting whitespace
+    pub min_length: Option<usize>,
+    /// Most characters a line may have, not counting whitespace
+    pub max_length: Option<usize>,
+    /// Only lines with an English translation
+    pub translated_only: bool,
+}
+
+/// The lines containing at least one of `words`, each searched like a query, in corpus order.
+pub fn lines_with_any_word(
+    db: &DbHandler,
+    tokenizer: &JapaneseTokenizer,
+    words: &[String],
+) -> Result<Vec<SearchHit>> {
+    let mut hits = BTreeMap::new();
+    for word in words {
+        for hit in search::search(db, tokenizer, word)? {
+            hits.entry((
+                hit.show_name.clone(),
+                hit.season,
+                hit.episode_number,
+                hit.line_id,
+            ))
+            .or_insert(hit);
+        }
+    }
+    Ok(hits.into_values().collect())
+}
+
+/// Drops the lines `filter` doesn't allow.
+pub fn filter_sentences(hits: &mut Vec<SearchHit>, filter: &SentenceFilter) {
+    hits.retain(|hit| {
+        let length = hit.text.chars().filter(|c| !c.is_whitespace()).count();
+        filter.min_length.is_none_or(|min| length >= min)
+            && filter.max_length.is_none_or(|max| length <= max)
+            && (!filter.translated_only || hit.translation.is_some())
+    });
+}
+
+/// Where a line is from, e.g. `Show Name S01E02 00:01:02,000`, or
+/// `Movie Name Movie 00:01:02,000` for a movie.
+pub fn attribution(hit: &SearchHit) -> String {
+    format!(
+        "{} {} {}",
+        hit.show_name,
+        episode_label(hit.season, hit.episode_number),
+        hit.time_start
+    )
+}
+
+/// Writes one sentence pair row per hit. Tabs and line breaks inside the
+/// text become spaces, so every row stays one line with five columns.
+/// With `explanations`, each row gets a sixth column with the line's
+/// explanation (empty if it has none), its line breaks written as `<br>` as
+/// Anki expects in imported fields.
+pub fn write_sentence_pairs<W: Write>(
+    hits: &[SearchHit],
+    explanations: Option<&HashMap<TranscriptId, String>>,
+    mut writer: W,
+) -> io::Result<()> {
+    for hit in hits {
+        let (translation_id, translation) = match &hit.translation {
+            Some(text) => (hit.transcript_id.to_string(), field(text)),
+            None => (String::new(), String::new()),
+        };
+        write!(
+            writer,
+            "{}\t{}\t{}\t{}\t{}\t{}",
+            hit.transcript_id,
+            field(&hit.text),
+            translation_id,
+            translation,
+            field(&attribution(hit))
+        )?;
+        if let Some(explanations) = explanations {
+            let explanation = explanations
+                .get(&hit.transcript_id)
+                .map_or(String::new(), |text| field(&text.replace('\n', "<br>")));
+            write!(writer, "\t{}", explanation)?;
+        }
+        writeln!(writer)?;
+    }
+    writer.flush()
+}
+
+/// Writes bookmarks as an Anki import file: the line, its translation, where
+/// it's from and the bookmark's note, with its tag in a fifth column and the
+/// line's stable id in a sixth, which the file's header tells Anki to use as
+/// the note's tags and GUID.
+pub fn write_anki_bookmarks<W: Write>(bookmarks: &[Bookmark], mut writer: W) -> io::Result<()> {
+    writeln!(writer, "#separator:tab")?;
+    writeln!(writer, "#tags column:5")?;
+    writeln!(writer, "#guid column:6")?;
+    for bookmark in bookmarks {
+        let hit = &bookmark.hit;
+        // Anki separates tags with spaces
+        let tag = bookmark
+            .tag
+            .as_deref()
+            .unwrap_or_default()
+            .replace(' ', "_");
+        writeln!(
+            writer,
+            "{}\t{}\t{}\t{}\t{}\t{}",
+            field(&hit.text),
+            field(hit.translation.as_deref().unwrap_or_default()),
+            field(&attribution(hit)),
+            field(
+                &bookmark
+                    .note
+                    .as_deref()
+                    .unwrap_or_default()
+                    .replace('\n', "<br>")
+            ),
+            field(&tag),
+            field(&hit.stable_id)
+        )?;
+    }
+    writer.flush()
+}
+
+/// Reads a word list: one word per line, or the first column of a
+/// tab-separated file such as a JLPT list. Blank lines and `#` comments are skipped.
+pub fn read_word_list<R: BufRead>(reader: R) -> io::Result<Vec<String>> {
+    let mut words = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        let word = line.split('\t').next().unwrap_or_default().trim();
+        if !word.is_empty() && !word.starts_with('#') {
+            words.push(word.to_string());
+        }
+    }
+    Ok(words)
+}
+
+fn field(text: &str) -> String {
+    text.replace(['\t', '\r', '\n'], " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_utils::test_db_with_lines;
+    use crate::tokenizer::test_utils::test_tokenizer;
+
+    #[test]
+    fn test_export_sentence_pairs() {
+        let (mut db, ids) = test_db_with_lines(&["猫が好き", "犬が\n走った", "好きです", "猫"]);
+        let tokenizer = test_tokenizer();
+        db.index_transcripts(&tokenizer, &ids).unwrap();
+
+        let words = read_word_list("猫\tN5\n\n# animals\n犬\n".as_bytes()).unwrap();
+        assert_eq!(words, ["猫", "犬"]);
+        let mut hits = lines_with_any_word(&db, &tokenizer, &words).unwrap();
+        assert_eq!(hits.len(), 3);
+        filter_sentences(
+            &mut hits,
+            &SentenceFilter {
+                min_length: Some(3),
+                ..SentenceFilter::default()
+            },
+        );
+        assert_eq!(hits.len(), 2);
+        hits[0].translation = Some("I like cats".to_string());
+
+        let mut output = Vec::new();
+        write_sentence_pairs(&hits, None, &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let rows: Vec<&str> = output.lines().collect();
+        assert_eq!(
+            rows[0],
+            format!(
+                "{0}\t猫が好き\t{0}\tI like cats\tShow Name S01E01 00:00:00,000",
+                ids[0]
+            )
+        );
+        assert_eq!(
+            rows[1],
+            format!("{}\t犬が 走った\t\t\tShow Name S01E01 00:00:01,000", ids[1])
+        );
+
+        let explanations = HashMap::from([(ids[1], "犬: dog\nが: subject".to_string())]);
+        let mut output = Vec::new();
+        write_sentence_pairs(&hits, Some(&explanations), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let rows: Vec<&str> = output.lines().collect();
+        assert!(rows[0].ends_with("00:00:00,000\t"));
+        assert!(rows[1].ends_with("00:00:01,000\t犬: dog<br>が: subject"));
+
+        filter_sentences(
+            &mut hits,
+            &SentenceFilter {
+                translated_only: true,
+                ..SentenceFilter::default()
+            },
+        );
+        assert_eq!(hits.len(), 1);
+    }
+
+    #[test]
+    fn test_write_anki_bookmarks() {
+        let (db, ids) = test_db_with_lines(&["猫が好き", "犬が\n走った"]);
+        db.add_bookmark("a", ids[0], Some("like\nthis"), Some("cats and dogs"))
+            .unwrap();
+        db.add_bookmark("a", ids[1], None, None).unwrap();
+
+        let mut output = Vec::new();
+        write_anki_bookmarks(&db.bookmarks("a", None).unwrap(), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let rows: Vec<&str> = output.lines().collect();
+        assert_eq!(
+            rows[..3],
+            ["#separator:tab", "#tags column:5", "#guid column:6"]
+        );
+        let stable_id = |id| db.get_transcript(id).unwrap().unwrap().stable_id;
+        assert_eq!(
+            rows[3],
+            format!(
+                "猫が好き\t\tShow Name S01E01 00:00:00,000\tlike<br>this\tcats_and_dogs\t{}",
+                stable_id(ids[0])
+            )
+        );
+        assert_eq!(
+            rows[4],
+            format!(
+                "犬が 走った\t\tShow Name S01E01 00:00:01,000\t\t\t{}",
+                stable_id(ids[1])
+            )
+        );
+    }
+}
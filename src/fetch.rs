@@ -0,0 +1,332 @@
+//! Downloading a show's subtitles into the subtitle directory, from jimaku.cc
+//! or a list of URLs, so they can be ingested.
+//!
+//! Downloads need the `fetch` feature, which brings the HTTP client. Jimaku's
+//! API takes a key (free with an account there), read from the environment
+//! variable named in the `[fetch]` config section.
+
+use crate::srt_parser::{is_archive_file, is_subtitle_file};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The `[fetch]` config section.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FetchConfig {
+    /// The environment variable holding the jimaku.cc API key
+    pub api_key_env: String,
+    /// Where jimaku.cc's API is
+    pub jimaku_url: String,
+}
+
+impl Default for FetchConfig {
+    fn default() -> Self {
+        FetchConfig {
+            api_key_env: "JIMAKU_API_KEY".to_string(),
+            jimaku_url: "https://jimaku.cc/api".to_string(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum FetchError {
+    /// The crate was built without the `fetch` feature
+    NotCompiled,
+    /// jimaku.cc needs an API key, expected in this environment variable
+    MissingApiKey(String),
+    /// The request didn't reach the server, or its reply couldn't be read
+    #[cfg(feature = "fetch")]
+    Http(Box<ureq::Error>),
+    /// The server answered with an error status
+    Api {
+        status: u16,
+        message: String,
+    },
+    /// The reply didn't have the expected shape
+    InvalidResponse(String),
+    /// jimaku.cc has no entry matching this show name
+    NoMatch(String),
+    /// None of the files were subtitle files or archives
+    NoFiles,
+    IoError(std::io::Error),
+}
+
+#[cfg(feature = "fetch")]
+impl From<ureq::Error> for FetchError {
+    fn from(error: ureq::Error) -> Self {
+        match error {
+            ureq::Error::Status(status, response) => FetchError::Api {
+                status,
+                message: response.into_string().unwrap_or_default(),
+            },
+            error => FetchError::Http(Box::new(error)),
+        }
+    }
+}
+
+impl From<std::io::Error> for FetchError {
+    fn from(error: std::io::Error) -> Self {
+        FetchError::IoError(error)
+    }
+}
+
+impl fmt::Display for FetchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FetchError::NotCompiled => write!(f, "Fetching isn't available in this build"),
+            FetchError::MissingApiKey(name) => write!(f, "Set {} to the jimaku.cc API key", name),
+            #[cfg(feature = "fetch")]
+            FetchError::Http(e) => write!(f, "HTTP error: {}", e),
+            FetchError::Api { status, message } => {
+                write!(f, "The server answered {}: {}", status, message)
+            }
+            FetchError::InvalidResponse(message) => {
+                write!(f, "Unexpected reply from jimaku.cc: {}", message)
+            }
+            FetchError::NoMatch(show) => write!(f, "jimaku.cc has no entry for {:?}", show),
+            FetchError::NoFiles => write!(f, "No subtitle files or archives to download"),
+            FetchError::IoError(e) => write!(f, "I/O error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for FetchError {}
+
+pub type Result<T> = std::result::Result<T, FetchError>;
+
+/// Where [`fetch_show`] downloads a show's subtitles from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FetchSource {
+    /// The files of jimaku.cc's best match for the show's name
+    Jimaku,
+    /// These URLs, e.g. a show's files on kitsunekko
+    Urls(Vec<String>),
+}
+
+/// What [`fetch_show`] downloaded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FetchedShow {
+    /// The show's folder in the subtitle directory
+    pub dir: PathBuf,
+    /// The downloaded files, in that folder
+    pub files: Vec<PathBuf>,
+    /// Names of the files left out for not being subtitle files or archives
+    /// that can be ingested, such as `.sup` image subtitles
+    pub skipped: Vec<String>,
+    /// The URL each file was downloaded from, by path
+    pub urls: HashMap<PathBuf, String>,
+}
+
+// A file to download, as jimaku.cc lists an entry's files
+#[derive(Debug, Deserialize)]
+struct RemoteFile {
+    url: String,
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct JimakuEntry {
+    id: u64,
+    name: String,
+}
+
+/// Downloads the subtitle files and archives for `show` from `source` into a
+/// folder named after it under `root_dir`, replacing files of the same name.
+pub fn fetch_show(
+    config: &FetchConfig,
+    show: &str,
+    source: &FetchSource,
+    root_dir: &Path,
+) -> Result<FetchedShow> {
+    if !cfg!(feature = "fetch") {
+        return Err(FetchError::NotCompiled);
+    }
+    let files = match source {
+        FetchSource::Jimaku => jimaku_files(config, show)?,
+        FetchSource::Urls(urls) => urls
+            .iter()
+            .map(|url| RemoteFile {
+                url: url.clone(),
+                name: file_name_from_url(url),
+            })
+            .collect(),
+    };
+    let (files, skipped): (Vec<RemoteFile>, Vec<RemoteFile>) =
+        files.into_iter().partition(|file| {
+            let name = Path::new(&file.name);
+            is_subtitle_file(name) || is_archive_file(name)
+        });
+    if files.is_empty() {
+        return Err(FetchError::NoFiles);
+    }
+
+    let dir = root_dir.join(sanitize_file_name(show));
+    fs::create_dir_all(&dir)?;
+    let mut downloaded = Vec::new();
+    let mut urls = HashMap::new();
+    for file in files {
+        println!("Downloading {}...", file.name);
+        let path = dir.join(sanitize_file_name(&file.name));
+        download(&file.url, &path)?;
+        urls.insert(path.clone(), file.url);
+        downloaded.push(path);
+    }
+    Ok(FetchedShow {
+        dir,
+        files: downloaded,
+        skipped: skipped.into_iter().map(|file| file.name).collect(),
+        urls,
+    })
+}
+
+/// The URLs in a list file: one per line, skipping blank lines and `#` comments.
+pub fn parse_url_list(text: &str) -> Vec<String> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect()
+}
+
+// The files of jimaku.cc's first search result for `show`
+fn jimaku_files(config: &FetchConfig, show: &str) -> Result<Vec<RemoteFile>> {
+    let api_key = std::env::var(&config.api_key_env)
+        .ok()
+        .filter(|key| !key.is_empty())
+        .ok_or_else(|| FetchError::MissingApiKey(config.api_key_env.clone()))?;
+    let base_url = config.jimaku_url.trim_end_matches('/');
+    let entries: Vec<JimakuEntry> = get_json(
+        &format!("{}/entries/search", base_url),
+        &[("anime", "true"), ("query", show)],
+        &api_key,
+    )?;
+    let entry = entries
+        .into_iter()
+        .next()
+        .ok_or_else(|| FetchError::NoMatch(show.to_string()))?;
+    println!("Found {} on jimaku.cc.", entry.name);
+    get_json(
+        &format!("{}/entries/{}/files", base_url, entry.id),
+        &[],
+        &api_key,
+    )
+}
+
+#[cfg(feature = "fetch")]
+fn get_json<T: DeserializeOwned>(url: &str, query: &[(&str, &str)], api_key: &str) -> Result<T> {
+    let mut request = ureq::get(url).set("Authorization", api_key);
+    for (name, value) in query {
+        request = request.query(name, value);
+    }
+    request
+        .call()?
+        .into_json()
+        .map_err(|e| FetchError::InvalidResponse(e.to_string()))
+}
+
+#[cfg(not(feature = "fetch"))]
+fn get_json<T: DeserializeOwned>(_url: &str, _query: &[(&str, &str)], _api_key: &str) -> Result<T> {
+    Err(FetchError::NotCompiled)
+}
+
+// Writes to a `.part` file first, so an interrupted download isn't taken for a subtitle file
+#[cfg(feature = "fetch")]
+fn download(url: &str, path: &Path) -> Result<()> {
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".part");
+    let mut reader = ureq::get(url).call()?.into_reader();
+    let mut file = fs::File::create(&partial)?;
+    std::io::copy(&mut reader, &mut file)?;
+    fs::rename(&partial, path)?;
+    Ok(())
+}
+
+#[cfg(not(feature = "fetch"))]
+fn download(_url: &str, _path: &Path) -> Result<()> {
+    Err(FetchError::NotCompiled)
+}
+
+// The last segment of the URL's path, percent-decoded
+fn file_name_from_url(url: &str) -> String {
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    let name = path
+        .trim_end_matches('/')
+        .rsplit('/')
+        .next()
+        .unwrap_or(path);
+    percent_decode(name)
+}
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) if bytes[i] == b'%' => {
+                decoded.push(byte);
+                i += 3;
+            }
+            _ => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+// `name` with the characters file systems don't allow replaced, and no path separators,
+// so a name from a server can't point outside the show's folder
+pub(crate) fn sanitize_file_name(name: &str) -> String {
+    let sanitized: String = name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    let sanitized = sanitized.trim_matches(|c: char| c == '.' || c.is_whitespace());
+    if sanitized.is_empty() {
+        "_".to_string()
+    } else {
+        sanitized.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_names() {
+        assert_eq!(
+            file_name_from_url("https://jimaku.cc/entry/1/download/%E7%8C%AB%2001.srt?x=1"),
+            "猫 01.srt"
+        );
+        assert_eq!(
+            file_name_from_url("https://example.com/subs/Show.zip"),
+            "Show.zip"
+        );
+        assert_eq!(sanitize_file_name("../Re:Zero?.srt"), "_Re_Zero_.srt");
+        assert_eq!(sanitize_file_name(" .. "), "_");
+    }
+
+    #[test]
+    fn test_parse_url_list() {
+        let list = "# Season 1\nhttps://example.com/01.srt\n\n  https://example.com/02.srt  \n";
+        assert_eq!(
+            parse_url_list(list),
+            ["https://example.com/01.srt", "https://example.com/02.srt"]
+        );
+    }
+}
@@ -0,0 +1,146 @@
+//! Ruby (furigana) markup for transcript lines, using the tokenizer's readings.
+
+use crate::tokenizer::{is_kanji, katakana_to_hiragana, JapaneseTokenizer};
+use std::fmt;
+use std::str::FromStr;
+
+/// How readings are written next to the kanji they belong to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FuriganaFormat {
+    /// Anki-style `漢字[かんじ]`, with a space separating it from preceding text
+    #[default]
+    Brackets,
+    /// HTML `<ruby>漢字<rt>かんじ</rt></ruby>`; the rest of the text is escaped
+    Html,
+}
+
+impl FromStr for FuriganaFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "brackets" => Ok(FuriganaFormat::Brackets),
+            "html" => Ok(FuriganaFormat::Html),
+            _ => Err(format!(
+                "unknown furigana format {:?} (expected brackets or html)",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for FuriganaFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FuriganaFormat::Brackets => write!(f, "brackets"),
+            FuriganaFormat::Html => write!(f, "html"),
+        }
+    }
+}
+
+/// Adds readings to every token of `text` that contains kanji.
+///
+/// Kana shared by the surface and its reading (okurigana such as the っ in 走っ)
+/// are kept outside the annotation, giving `走[はし]っ` rather than `走っ[はしっ]`.
+/// The text is kept as written, though it is tokenized normalized.
+pub fn add_furigana(tokenizer: &JapaneseTokenizer, text: &str, format: FuriganaFormat) -> String {
+    let mut output = String::new();
+    for token in tokenizer.tokenize_original(text) {
+        let reading = match &token.reading {
+            Some(reading) if token.surface.chars().any(is_kanji) => katakana_to_hiragana(reading),
+            _ => {
+                push_text(&mut output, &token.surface, format);
+                continue;
+            }
+        };
+        let (prefix, base, ruby, suffix) = split_okurigana(&token.surface, &reading);
+        push_text(&mut output, prefix, format);
+        match format {
+            FuriganaFormat::Brackets => {
+                if !output.is_empty() {
+                    output.push(' ');
+                }
+                output.push_str(&format!("{}[{}]", base, ruby));
+            }
+            FuriganaFormat::Html => output.push_str(&format!(
+                "<ruby>{}<rt>{}</rt></ruby>",
+                escape_html(base),
+                escape_html(ruby)
+            )),
+        }
+        push_text(&mut output, suffix, format);
+    }
+    output
+}
+
+// Splits a surface and its hiragana reading into (kana prefix, kanji part, its reading, kana suffix)
+fn split_okurigana<'a>(surface: &'a str, reading: &'a str) -> (&'a str, &'a str, &'a str, &'a str) {
+    // Kana are three bytes in both hiragana and katakana, so byte offsets carry over
+    let common_prefix = common_len(surface.chars(), reading.chars());
+    let common_suffix = common_len(surface.chars().rev(), reading.chars().rev());
+
+    let (prefix, rest) = surface.split_at(common_prefix);
+    let rest_reading = &reading[common_prefix..];
+    let suffix_len = common_suffix.min(rest.len()).min(rest_reading.len());
+    let (base, suffix) = rest.split_at(rest.len() - suffix_len);
+    let ruby = &rest_reading[..rest_reading.len() - suffix_len];
+    if base.is_empty() || ruby.is_empty() {
+        // The reading doesn't fit the surface; annotate the whole token
+        return ("", surface, reading, "");
+    }
+    (prefix, base, ruby, suffix)
+}
+
+// Byte length of the shared run of (hiragana-normalized) kana at the start of both iterators
+fn common_len(surface: impl Iterator<Item = char>, reading: impl Iterator<Item = char>) -> usize {
+    surface
+        .zip(reading)
+        .take_while(|&(s, r)| !is_kanji(s) && katakana_to_hiragana(&s.to_string()) == r.to_string())
+        .map(|(s, _)| s.len_utf8())
+        .sum()
+}
+
+fn push_text(output: &mut String, text: &str, format: FuriganaFormat) {
+    match format {
+        FuriganaFormat::Brackets => output.push_str(text),
+        FuriganaFormat::Html => output.push_str(&escape_html(text)),
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenizer::test_utils::test_tokenizer;
+
+    #[test]
+    fn test_add_furigana_brackets() {
+        let text = add_furigana(&test_tokenizer(), "猫が走った", FuriganaFormat::Brackets);
+        assert_eq!(text, "猫[ねこ]が 走[はし]った");
+    }
+
+    #[test]
+    fn test_add_furigana_html() {
+        let text = add_furigana(&test_tokenizer(), "<猫>", FuriganaFormat::Html);
+        assert_eq!(text, "&lt;<ruby>猫<rt>ねこ</rt></ruby>&gt;");
+    }
+
+    #[test]
+    fn test_add_furigana_keeps_the_text() {
+        let text = add_furigana(&test_tokenizer(), "ﾈｺが走った！", FuriganaFormat::Brackets);
+        assert_eq!(text, "ﾈｺが 走[はし]った！");
+    }
+
+    #[test]
+    fn test_split_okurigana() {
+        assert_eq!(split_okurigana("走っ", "はしっ"), ("", "走", "はし", "っ"));
+        assert_eq!(split_okurigana("お茶", "おちゃ"), ("お", "茶", "ちゃ", ""));
+        assert_eq!(split_okurigana("猫", "ねこ"), ("", "猫", "ねこ", ""));
+    }
+}
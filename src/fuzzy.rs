@@ -0,0 +1,139 @@
+//! Typo tolerance for kana queries: when a query finds nothing, look for a
+//! close reading that does, and offer it as "did you mean".
+
+use crate::db::DbHandler;
+use crate::tokenizer::{is_kana, katakana_to_hiragana};
+
+// Spellings commonly mixed up when typing kana, tried in both directions
+const CONFUSIONS: &[(&str, &str)] = &[
+    ("づ", "ず"),
+    ("ぢ", "じ"),
+    ("を", "お"),
+    ("は", "わ"),
+    ("へ", "え"),
+    ("おう", "おお"),
+    ("えい", "ええ"),
+    ("っ", "つ"),
+    ("ゃ", "や"),
+    ("ゅ", "ゆ"),
+    ("ょ", "よ"),
+];
+
+/// Replaces each kana word of `query` that no indexed word reads as with
+/// the most frequent reading one typo away, or None if nothing changed.
+///
+/// Common confusions (づ/ず, を/お, おう/おお, ...) are preferred over other
+/// single-character insertions, deletions and substitutions. Words with
+/// kanji, `word:POS` terms and `NEAR/N` operators are kept as typed.
+pub fn correct_query(db: &DbHandler, query: &str) -> rusqlite::Result<Option<String>> {
+    let mut changed = false;
+    let mut words = Vec::new();
+    for word in query.split_whitespace() {
+        let corrected = if word.chars().all(is_kana) {
+            correct_reading(db, &katakana_to_hiragana(word))?
+        } else {
+            None
+        };
+        changed |= corrected.is_some();
+        words.push(corrected.unwrap_or_else(|| word.to_string()));
+    }
+    Ok(changed.then(|| words.join(" ")))
+}
+
+// The best known reading close to `reading`, or None if `reading` is known or nothing is close
+fn correct_reading(db: &DbHandler, reading: &str) -> rusqlite::Result<Option<String>> {
+    if !db.reading_frequencies(&[reading.to_string()])?.is_empty() {
+        return Ok(None);
+    }
+    for candidates in [confusion_variants(reading), edit_variants(reading)] {
+        let frequencies = db.reading_frequencies(&candidates)?;
+        let best = frequencies
+            .into_iter()
+            .max_by(|a, b| a.1.cmp(&b.1).then_with(|| b.0.cmp(&a.0)));
+        if let Some((reading, _)) = best {
+            return Ok(Some(reading));
+        }
+    }
+    Ok(None)
+}
+
+// Readings with one commonly confused spelling swapped
+fn confusion_variants(reading: &str) -> Vec<String> {
+    let mut variants = Vec::new();
+    for &(a, b) in CONFUSIONS {
+        for (from, to) in [(a, b), (b, a)] {
+            for (index, _) in reading.match_indices(from) {
+                variants.push(format!(
+                    "{}{}{}",
+                    &reading[..index],
+                    to,
+                    &reading[index + from.len()..]
+                ));
+            }
+        }
+    }
+    variants
+}
+
+// Readings one hiragana insertion, deletion or substitution away
+fn edit_variants(reading: &str) -> Vec<String> {
+    let chars: Vec<char> = reading.chars().collect();
+    let alphabet: Vec<char> = ('ぁ'..='ゖ').chain(['ー']).collect();
+    let mut variants = Vec::new();
+    for i in 0..=chars.len() {
+        let (head, tail) = chars.split_at(i);
+        let head: String = head.iter().collect();
+        if let Some((_, rest)) = tail.split_first() {
+            let rest: String = rest.iter().collect();
+            variants.push(format!("{}{}", head, rest));
+            for &c in alphabet.iter().filter(|&&c| c != tail[0]) {
+                variants.push(format!("{}{}{}", head, c, rest));
+            }
+        }
+        let tail: String = tail.iter().collect();
+        for &c in &alphabet {
+            variants.push(format!("{}{}{}", head, c, tail));
+        }
+    }
+    variants.sort();
+    variants.dedup();
+    variants
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_utils::test_db_with_lines;
+    use crate::tokenizer::test_utils::test_tokenizer;
+
+    #[test]
+    fn test_variants() {
+        assert!(confusion_variants("つづく").contains(&"つずく".to_string()));
+        assert!(confusion_variants("おおきい").contains(&"おうきい".to_string()));
+        let edits = edit_variants("ねこ");
+        for variant in ["ね", "こ", "ねご", "ねここ", "ねこー"] {
+            assert!(edits.contains(&variant.to_string()), "{}", variant);
+        }
+        assert!(!edits.contains(&"ねこ".to_string()));
+    }
+
+    #[test]
+    fn test_correct_query_suggests_a_close_reading() {
+        let tokenizer = test_tokenizer();
+        let (mut db, ids) = test_db_with_lines(&["面白い", "走った"]);
+        db.index_transcripts(&tokenizer, &ids).unwrap();
+
+        assert_eq!(correct_query(&db, "おもしろい").unwrap(), None);
+        assert_eq!(
+            correct_query(&db, "おもしらい").unwrap().as_deref(),
+            Some("おもしろい")
+        );
+
+        assert_eq!(correct_query(&db, "ハシル").unwrap(), None);
+        assert_eq!(
+            correct_query(&db, "はしう 猫").unwrap().as_deref(),
+            Some("はしる 猫")
+        );
+        assert_eq!(correct_query(&db, "ぜんぜん").unwrap(), None);
+    }
+}
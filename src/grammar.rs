@@ -0,0 +1,273 @@
+use crate::tokenizer::Token;
+
+/// Matches a single token of a grammar pattern.
+///
+/// Every field that is set must match; an element with no fields set matches any token.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TokenPattern {
+    /// The token's surface must be one of these
+    pub surfaces: &'static [&'static str],
+    /// The token's dictionary form
+    pub base_form: Option<&'static str>,
+    /// The token's part-of-speech must start with this tag (`-` separates sub-categories)
+    pub pos: Option<&'static str>,
+}
+
+impl TokenPattern {
+    const fn surface(surfaces: &'static [&'static str]) -> Self {
+        TokenPattern {
+            surfaces,
+            base_form: None,
+            pos: None,
+        }
+    }
+
+    const fn base(base_form: &'static str) -> Self {
+        TokenPattern {
+            surfaces: &[],
+            base_form: Some(base_form),
+            pos: None,
+        }
+    }
+
+    const fn pos(pos: &'static str) -> Self {
+        TokenPattern {
+            surfaces: &[],
+            base_form: None,
+            pos: Some(pos),
+        }
+    }
+
+    pub fn matches(&self, token: &Token) -> bool {
+        (self.surfaces.is_empty() || self.surfaces.contains(&token.surface.as_str()))
+            && self.base_form.is_none_or(|base| token.base_form == base)
+            && self.pos.is_none_or(|pos| pos_matches(pos, &token.pos))
+    }
+}
+
+/// Whether a part-of-speech hierarchy starts with `tag`, e.g. `名詞-数` matches ["名詞", "数", ...].
+pub fn pos_matches(tag: &str, pos: &[String]) -> bool {
+    let tags: Vec<&str> = tag.split('-').collect();
+    pos.len() >= tags.len() && pos.iter().zip(&tags).all(|(a, b)| a == b)
+}
+
+/// A grammar point expressed as a sequence of consecutive tokens.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GrammarPattern {
+    /// JLPT level, e.g. "N3"
+    pub level: &'static str,
+    pub name: &'static str,
+    pub meaning: &'static str,
+    /// A string every matching line must contain, used to pre-filter candidates
+    pub literal: &'static str,
+    pub tokens: &'static [TokenPattern],
+}
+
+impl GrammarPattern {
+    /// The id used on the command line, e.g. "N3:ばかりに".
+    pub fn id(&self) -> String {
+        format!("{}:{}", self.level, self.name)
+    }
+
+    /// Whether the pattern occurs somewhere in a tokenized line.
+    pub fn matches(&self, tokens: &[Token]) -> bool {
+        tokens.windows(self.tokens.len()).any(|window| {
+            window
+                .iter()
+                .zip(self.tokens)
+                .all(|(token, pattern)| pattern.matches(token))
+        })
+    }
+}
+
+const TE: &[&str] = &["て", "で"];
+
+/// The built-in grammar patterns, written for IPADIC part-of-speech tags.
+pub const GRAMMAR_PATTERNS: &[GrammarPattern] = &[
+    GrammarPattern {
+        level: "N5",
+        name: "ましょう",
+        meaning: "let's ~",
+        literal: "ましょう",
+        tokens: &[
+            TokenPattern::surface(&["ましょ"]),
+            TokenPattern::surface(&["う"]),
+        ],
+    },
+    GrammarPattern {
+        level: "N5",
+        name: "なければならない",
+        meaning: "must ~",
+        literal: "なければなら",
+        tokens: &[
+            TokenPattern::surface(&["なけれ"]),
+            TokenPattern::surface(&["ば"]),
+            TokenPattern::base("なる"),
+            TokenPattern::base("ない"),
+        ],
+    },
+    GrammarPattern {
+        level: "N4",
+        name: "てもいい",
+        meaning: "it's okay to ~",
+        literal: "もいい",
+        tokens: &[
+            TokenPattern::surface(TE),
+            TokenPattern::surface(&["も"]),
+            TokenPattern::base("いい"),
+        ],
+    },
+    GrammarPattern {
+        level: "N4",
+        name: "たことがある",
+        meaning: "have done ~ before",
+        literal: "ことがあ",
+        tokens: &[
+            TokenPattern::base("た"),
+            TokenPattern::surface(&["こと"]),
+            TokenPattern::surface(&["が"]),
+            TokenPattern::base("ある"),
+        ],
+    },
+    GrammarPattern {
+        level: "N4",
+        name: "ながら",
+        meaning: "while ~",
+        literal: "ながら",
+        tokens: &[
+            TokenPattern::pos("動詞"),
+            TokenPattern {
+                surfaces: &["ながら"],
+                base_form: None,
+                pos: Some("助詞"),
+            },
+        ],
+    },
+    GrammarPattern {
+        level: "N3",
+        name: "てしまう",
+        meaning: "end up ~ / completely ~",
+        literal: "しま",
+        tokens: &[TokenPattern::surface(TE), TokenPattern::base("しまう")],
+    },
+    GrammarPattern {
+        level: "N3",
+        name: "ばかりに",
+        meaning: "simply because ~ (with a bad result)",
+        literal: "ばかりに",
+        tokens: &[
+            TokenPattern::surface(&["ばかり"]),
+            TokenPattern::surface(&["に"]),
+        ],
+    },
+    GrammarPattern {
+        level: "N3",
+        name: "ようにする",
+        meaning: "make sure to ~",
+        literal: "ように",
+        tokens: &[
+            TokenPattern::surface(&["よう"]),
+            TokenPattern::surface(&["に"]),
+            TokenPattern::base("する"),
+        ],
+    },
+    GrammarPattern {
+        level: "N3",
+        name: "わけにはいかない",
+        meaning: "cannot afford to ~",
+        literal: "わけにはい",
+        tokens: &[
+            TokenPattern::surface(&["わけ", "訳"]),
+            TokenPattern::surface(&["に"]),
+            TokenPattern::surface(&["は"]),
+            TokenPattern::base("いく"),
+            TokenPattern::base("ない"),
+        ],
+    },
+    GrammarPattern {
+        level: "N2",
+        name: "に違いない",
+        meaning: "must be ~ / no doubt ~",
+        literal: "に違いな",
+        tokens: &[
+            TokenPattern::surface(&["に"]),
+            TokenPattern::base("違い"),
+            TokenPattern::base("ない"),
+        ],
+    },
+    GrammarPattern {
+        level: "N2",
+        name: "ばかりか",
+        meaning: "not only ~ but also",
+        literal: "ばかりか",
+        tokens: &[
+            TokenPattern::surface(&["ばかり"]),
+            TokenPattern::surface(&["か"]),
+        ],
+    },
+    GrammarPattern {
+        level: "N1",
+        name: "ざるを得ない",
+        meaning: "cannot help but ~",
+        literal: "ざるを得な",
+        tokens: &[
+            TokenPattern::surface(&["ざる"]),
+            TokenPattern::surface(&["を"]),
+            TokenPattern::base("得る"),
+            TokenPattern::base("ない"),
+        ],
+    },
+    GrammarPattern {
+        level: "N1",
+        name: "ずにはいられない",
+        meaning: "cannot help ~",
+        literal: "ずにはいられな",
+        tokens: &[
+            TokenPattern::surface(&["ず"]),
+            TokenPattern::surface(&["に"]),
+            TokenPattern::surface(&["は"]),
+            TokenPattern::base("いる"),
+            TokenPattern::base("れる"),
+            TokenPattern::base("ない"),
+        ],
+    },
+];
+
+/// Looks up a built-in pattern by id ("N3:ばかりに") or by name alone ("ばかりに").
+pub fn find_grammar_pattern(id: &str) -> Option<&'static GrammarPattern> {
+    GRAMMAR_PATTERNS
+        .iter()
+        .find(|pattern| pattern.id() == id || pattern.name == id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenizer::test_utils::test_tokenizer;
+
+    #[test]
+    fn test_find_grammar_pattern() {
+        assert_eq!(
+            find_grammar_pattern("N3:ばかりに").unwrap().name,
+            "ばかりに"
+        );
+        assert_eq!(find_grammar_pattern("ばかりに").unwrap().level, "N3");
+        assert!(find_grammar_pattern("N1:ばかりに").is_none());
+    }
+
+    #[test]
+    fn test_pattern_matches_token_sequence() {
+        let pattern = find_grammar_pattern("N3:ばかりに").unwrap();
+        let tokenizer = test_tokenizer();
+        assert!(pattern.matches(&tokenizer.tokenize("犬を見たばかりに")));
+        assert!(!pattern.matches(&tokenizer.tokenize("犬ばかり")));
+    }
+
+    #[test]
+    fn test_pattern_ids_are_unique() {
+        for (i, pattern) in GRAMMAR_PATTERNS.iter().enumerate() {
+            assert!(!pattern.tokens.is_empty(), "{} has no tokens", pattern.id());
+            assert!(GRAMMAR_PATTERNS[..i].iter().all(|p| p.id() != pattern.id()));
+        }
+    }
+}
@@ -0,0 +1,354 @@
+//! gRPC access to search, context and ingestion, for non-Rust backends.
+//!
+//! The service is defined in `proto/anime_search.proto`; generate a client
+//! from it in any language. Requires the `grpc` feature.
+//!
+//! `Ingest` needs an API key with the ingest scope, sent as `authorization:
+//! Bearer <key>` or `x-api-key: <key>` metadata, and only reads directories
+//! under the service's ingest root.
+
+use crate::backend::BackendConfig;
+use crate::db::{ApiScope, DbPool, KeyRejection, RateLimiter, SearchHit, TranscriptId};
+use crate::ingest::{self, ShowTypeConfig};
+use crate::nonblocking;
+use crate::search::SearchError;
+use crate::tokenizer::JapaneseTokenizer;
+use futures_util::stream::{self, BoxStream};
+use futures_util::StreamExt;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Instant;
+use tonic::metadata::MetadataMap;
+use tonic::{Request, Response, Status};
+
+/// Types and client generated from `proto/anime_search.proto`.
+#[allow(clippy::all)]
+pub mod proto {
+    tonic::include_proto!("anime_search.v1");
+}
+
+use proto::subtitle_search_server::{SubtitleSearch, SubtitleSearchServer};
+use proto::{
+    ContextRequest, ContextResponse, IngestRequest, IngestResponse, Line, SearchMode,
+    SearchRequest, SearchResponse,
+};
+
+/// Implements the `SubtitleSearch` service over a database pool.
+pub struct SearchService {
+    pool: Arc<DbPool>,
+    tokenizer: Arc<JapaneseTokenizer>,
+    api_keys: RateLimiter,
+    ingest_root: Option<PathBuf>,
+    search_backend: BackendConfig,
+    show_types: ShowTypeConfig,
+}
+
+impl SearchService {
+    /// A service refusing `Ingest` until it is given a root with [`SearchService::ingest_root`].
+    pub fn new(pool: Arc<DbPool>, tokenizer: Arc<JapaneseTokenizer>) -> Self {
+        SearchService {
+            pool,
+            tokenizer,
+            api_keys: RateLimiter::new(),
+            ingest_root: None,
+            search_backend: BackendConfig::default(),
+            show_types: ShowTypeConfig::default(),
+        }
+    }
+
+    /// Makes `Ingest` add lines to this search backend besides the word index.
+    pub fn search_backend(mut self, config: BackendConfig) -> Self {
+        self.search_backend = config;
+        self
+    }
+
+    /// Makes `Ingest` give the shows it adds the types `config` gives them.
+    pub fn show_types(mut self, config: ShowTypeConfig) -> Self {
+        self.show_types = config;
+        self
+    }
+
+    /// Lets `Ingest` read subtitle files from `root`, itself or below.
+    pub fn ingest_root(mut self, root: PathBuf) -> Self {
+        self.ingest_root = Some(root);
+        self
+    }
+
+    // Checks the request's API key allows `required`, counting it against the key's rate limit
+    async fn authorize(&self, metadata: &MetadataMap, required: ApiScope) -> Result<(), Status> {
+        let Some(secret) = request_key(metadata) else {
+            return Err(Status::unauthenticated("An API key is required"));
+        };
+        let key = nonblocking::find_api_key(self.pool.clone(), secret.to_string())
+            .await
+            .map_err(search_status)?
+            .ok_or_else(|| Status::unauthenticated("Unknown API key"))?;
+        self.api_keys
+            .admit(&key, required, Instant::now())
+            .map_err(|rejection| match rejection {
+                KeyRejection::Scope { .. } => Status::permission_denied(rejection.to_string()),
+                KeyRejection::RateLimited(_) => Status::resource_exhausted(rejection.to_string()),
+            })
+    }
+
+    async fn find(&self, request: SearchRequest) -> Result<Vec<Line>, Status> {
+        let limit = result_limit(&request);
+        let (pool, tokenizer) = (self.pool.clone(), self.tokenizer.clone());
+        let hits = match request.mode() {
+            SearchMode::Words => nonblocking::search(pool, tokenizer, request.query).await,
+            SearchMode::Regex => nonblocking::search_regex(pool, request.query).await,
+            SearchMode::Kanji => {
+                nonblocking::search_kanji(pool, tokenizer, request.query, false).await
+            }
+        }
+        .map_err(search_status)?;
+        Ok(hits.into_iter().take(limit).map(Line::from).collect())
+    }
+}
+
+/// Serves the service on `addr` until the process exits.
+pub async fn serve(
+    addr: SocketAddr,
+    service: SearchService,
+) -> Result<(), tonic::transport::Error> {
+    tonic::transport::Server::builder()
+        .add_service(SubtitleSearchServer::new(service))
+        .serve(addr)
+        .await
+}
+
+#[tonic::async_trait]
+impl SubtitleSearch for SearchService {
+    async fn search(
+        &self,
+        request: Request<SearchRequest>,
+    ) -> Result<Response<SearchResponse>, Status> {
+        let lines = self.find(request.into_inner()).await?;
+        Ok(Response::new(SearchResponse { lines }))
+    }
+
+    type StreamSearchStream = BoxStream<'static, Result<Line, Status>>;
+
+    async fn stream_search(
+        &self,
+        request: Request<SearchRequest>,
+    ) -> Result<Response<Self::StreamSearchStream>, Status> {
+        let request = request.into_inner();
+        if request.mode() != SearchMode::Words {
+            let lines = self.find(request).await?;
+            return Ok(Response::new(Box::pin(stream::iter(
+                lines.into_iter().map(Ok),
+            ))));
+        }
+        // Word searches send each line as soon as it is read
+        let limit = result_limit(&request);
+        let receiver =
+            nonblocking::search_streaming(self.pool.clone(), self.tokenizer.clone(), request.query);
+        let lines = stream::unfold(receiver, |mut receiver| async move {
+            let hit = receiver.recv().await?;
+            Some((hit.map(Line::from).map_err(search_status), receiver))
+        });
+        Ok(Response::new(Box::pin(lines.take(limit))))
+    }
+
+    async fn context(
+        &self,
+        request: Request<ContextRequest>,
+    ) -> Result<Response<ContextResponse>, Status> {
+        let request = request.into_inner();
+        let lines = nonblocking::context(
+            self.pool.clone(),
+            TranscriptId(request.transcript_id),
+            request.before,
+            request.after,
+        )
+        .await
+        .map_err(search_status)?;
+        Ok(Response::new(ContextResponse {
+            lines: lines.into_iter().map(Line::from).collect(),
+        }))
+    }
+
+    async fn ingest(
+        &self,
+        request: Request<IngestRequest>,
+    ) -> Result<Response<IngestResponse>, Status> {
+        self.authorize(request.metadata(), ApiScope::Ingest).await?;
+        let Some(ingest_root) = self.ingest_root.as_deref() else {
+            return Err(Status::permission_denied(
+                "Ingestion is disabled; start the server with --ingest-root",
+            ));
+        };
+        let requested = PathBuf::from(request.into_inner().root_dir);
+        let Some(root_dir) = ingest::directory_within(ingest_root, &requested) else {
+            return Err(Status::not_found(format!(
+                "{} is not a directory under {}",
+                requested.display(),
+                ingest_root.display()
+            )));
+        };
+        let ingested = nonblocking::ingest_directory(
+            self.pool.clone(),
+            self.tokenizer.clone(),
+            self.search_backend.clone(),
+            self.show_types.clone(),
+            root_dir,
+        )
+        .await
+        .map_err(|e| Status::internal(e.to_string()))?;
+        Ok(Response::new(IngestResponse {
+            files: ingested.episode_ids.len() as u32,
+            lines: ingested.transcript_ids.len() as u32,
+        }))
+    }
+}
+
+impl From<SearchHit> for Line {
+    fn from(hit: SearchHit) -> Self {
+        Line {
+            transcript_id: hit.transcript_id.0,
+            show_name: hit.show_name,
+            season: hit.season,
+            episode_number: hit.episode_number,
+            line_id: hit.line_id,
+            time_start: hit.time_start,
+            time_end: hit.time_end,
+            text: hit.text,
+            translation: hit.translation,
+            source_url: hit.source_url,
+            deep_link: hit.deep_link,
+        }
+    }
+}
+
+fn request_key(metadata: &MetadataMap) -> Option<&str> {
+    let bearer = metadata
+        .get("authorization")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    let api_key = || {
+        metadata
+            .get("x-api-key")
+            .and_then(|value| value.to_str().ok())
+    };
+    bearer.or_else(api_key).map(str::trim)
+}
+
+// A limit of 0 means no limit
+fn result_limit(request: &SearchRequest) -> usize {
+    match request.limit {
+        0 => usize::MAX,
+        limit => limit as usize,
+    }
+}
+
+fn search_status(error: SearchError) -> Status {
+    match error {
+        SearchError::InvalidRegex(e) => Status::invalid_argument(e.to_string()),
+        SearchError::DbError(e) => Status::internal(e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_utils::{insert_test_lines, TempDb};
+    use crate::db::ApiScope;
+    use crate::tokenizer::test_utils::test_tokenizer;
+
+    #[tokio::test]
+    async fn test_search_and_context() {
+        let path = TempDb::new("grpc");
+        let pool = Arc::new(DbPool::open(&path.0, 2).unwrap());
+        let tokenizer = Arc::new(test_tokenizer());
+        {
+            let mut db = pool.writer();
+            let ids = insert_test_lines(&mut db, &["走った", "猫が好き", "走る"]);
+            db.index_transcripts(&tokenizer, &ids).unwrap();
+        }
+        let service = SearchService::new(pool, tokenizer);
+
+        let request = |query: &str, mode: SearchMode, limit: u32| {
+            Request::new(SearchRequest {
+                query: query.to_string(),
+                mode: mode as i32,
+                limit,
+            })
+        };
+        let response = service
+            .search(request("走る", SearchMode::Words, 0))
+            .await
+            .unwrap();
+        assert_eq!(response.get_ref().lines.len(), 2);
+
+        let stream = service
+            .stream_search(request("走", SearchMode::Regex, 1))
+            .await
+            .unwrap()
+            .into_inner();
+        let lines: Vec<_> = stream.collect().await;
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].as_ref().unwrap().text, "走った");
+
+        let stream = service
+            .stream_search(request("走る", SearchMode::Words, 0))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(stream.count().await, 2);
+
+        let status = service
+            .search(request("(", SearchMode::Regex, 0))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+
+        let response = service
+            .context(Request::new(ContextRequest {
+                transcript_id: 2,
+                before: 1,
+                after: 1,
+            }))
+            .await
+            .unwrap();
+        assert_eq!(response.get_ref().lines.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_ingest_needs_an_ingest_key() {
+        let path = TempDb::new("grpc_auth");
+        let pool = Arc::new(DbPool::open(&path.0, 2).unwrap());
+        let (read_key, ingest_key) = {
+            let db = pool.writer();
+            let (_, read_key) = db.create_api_key("reader", ApiScope::Read, 60).unwrap();
+            let (_, ingest_key) = db.create_api_key("admin", ApiScope::Ingest, 60).unwrap();
+            (read_key, ingest_key)
+        };
+        let service =
+            SearchService::new(pool, Arc::new(test_tokenizer())).ingest_root(std::env::temp_dir());
+        let ingest = |key: Option<&str>| {
+            let mut request = Request::new(IngestRequest {
+                root_dir: "/nonexistent".to_string(),
+            });
+            if let Some(key) = key {
+                let value = format!("Bearer {}", key).parse().unwrap();
+                request.metadata_mut().insert("authorization", value);
+            }
+            service.ingest(request)
+        };
+
+        let code = |result: Result<Response<IngestResponse>, Status>| result.unwrap_err().code();
+        assert_eq!(code(ingest(None).await), tonic::Code::Unauthenticated);
+        assert_eq!(
+            code(ingest(Some("wrong")).await),
+            tonic::Code::Unauthenticated
+        );
+        assert_eq!(
+            code(ingest(Some(&read_key)).await),
+            tonic::Code::PermissionDenied
+        );
+        // Only directories under the ingest root can be ingested
+        assert_eq!(code(ingest(Some(&ingest_key)).await), tonic::Code::NotFound);
+    }
+}
@@ -0,0 +1,365 @@
+//! Per-show hooks run on parsed subtitles before they're ingested, for
+//! cleaning the parser doesn't know a show needs, such as dropping a fansub
+//! group's watermark lines.
+//!
+//! A hook implements [`IngestHook`] and is called once per parsed file and
+//! once per cue. [`IngestHooks`] holds the hooks of each show: the built-in
+//! ones set up in the `[hooks."Show Name"]` sections of the config, and any a
+//! program using the crate adds itself.
+//!
+//! ```toml
+//! [hooks."Show Name"]
+//! # Drop cues matching any of these
+//! drop = ["訳:", "(?i)fansub"]
+//! # Remove the matches of these from cue texts, dropping cues left empty
+//! strip = ["【[^】]*】"]
+//! # Pass each file's cue texts through a script (see `ScriptHook`)
+//! command = ["python3", "clean.py"]
+//! ```
+
+use crate::srt_parser::{SrtEntry, Subtitle};
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// A `[hooks."Show Name"]` section of the config.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ShowHookConfig {
+    /// Regular expressions; cues matching any of them are dropped
+    pub drop: Vec<String>,
+    /// Regular expressions whose matches are removed from cue texts
+    pub strip: Vec<String>,
+    /// A program and its arguments to pass each file's cue texts through
+    pub command: Vec<String>,
+}
+
+#[derive(Debug)]
+pub enum HookError {
+    /// A `drop` or `strip` pattern isn't a valid regular expression
+    InvalidPattern { show: String, error: regex::Error },
+    /// A script hook couldn't be run, failed, or answered with something
+    /// other than one text or `null` per cue
+    Script { command: String, message: String },
+    /// A hook of a program using the crate failed
+    Custom(String),
+}
+
+impl fmt::Display for HookError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HookError::InvalidPattern { show, error } => {
+                write!(f, "Invalid hook pattern for {:?}: {}", show, error)
+            }
+            HookError::Script { command, message } => {
+                write!(f, "Hook script {:?} failed: {}", command, message)
+            }
+            HookError::Custom(message) => write!(f, "Hook failed: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for HookError {}
+
+/// Custom cleaning for the subtitles of a show, run before they're ingested.
+///
+/// Both methods do nothing by default, so a hook only implements the one it needs.
+pub trait IngestHook {
+    /// Called with each parsed file, before its cues are passed to [`on_cue`](Self::on_cue).
+    fn on_file(&self, _entry: &mut SrtEntry) -> Result<(), HookError> {
+        Ok(())
+    }
+
+    /// Called with each cue of a file; returning false drops the cue.
+    fn on_cue(&self, _cue: &mut Subtitle) -> bool {
+        true
+    }
+}
+
+/// Drops cues matching one pattern, and removes the matches of another from
+/// the rest; the hook a config section's `drop` and `strip` set up.
+#[derive(Debug, Clone)]
+pub struct PatternHook {
+    drop: Vec<Regex>,
+    strip: Vec<Regex>,
+}
+
+impl IngestHook for PatternHook {
+    fn on_cue(&self, cue: &mut Subtitle) -> bool {
+        if self.drop.iter().any(|pattern| pattern.is_match(&cue.text)) {
+            return false;
+        }
+        for pattern in &self.strip {
+            if let std::borrow::Cow::Owned(stripped) = pattern.replace_all(&cue.text, "") {
+                cue.text = stripped.trim().to_string();
+            }
+        }
+        !cue.text.is_empty()
+    }
+}
+
+/// Passes each file's cue texts through an external program.
+///
+/// The program gets a JSON array of the texts on its standard input, and
+/// answers with an array of the same length on its standard output: each
+/// cue's new text, or `null` to drop the cue.
+#[derive(Debug, Clone)]
+pub struct ScriptHook {
+    command: Vec<String>,
+}
+
+impl ScriptHook {
+    pub fn new(command: Vec<String>) -> Self {
+        ScriptHook { command }
+    }
+
+    fn error(&self, message: impl ToString) -> HookError {
+        HookError::Script {
+            command: self.command.join(" "),
+            message: message.to_string(),
+        }
+    }
+}
+
+impl IngestHook for ScriptHook {
+    fn on_file(&self, entry: &mut SrtEntry) -> Result<(), HookError> {
+        let Some((program, args)) = self.command.split_first() else {
+            return Err(self.error("no program given"));
+        };
+        let texts: Vec<&str> = entry
+            .content
+            .0
+            .iter()
+            .map(|cue| cue.text.as_str())
+            .collect();
+        let input = serde_json::to_vec(&texts).map_err(|e| self.error(e))?;
+
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| self.error(e))?;
+        // Written from a thread, so a script answering before it has read all
+        // its input can't leave both sides waiting on full pipes
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        let writer = std::thread::spawn(move || stdin.write_all(&input));
+        let output = child.wait_with_output().map_err(|e| self.error(e))?;
+        writer
+            .join()
+            .expect("writing to the hook script panicked")
+            .map_err(|e| self.error(e))?;
+        if !output.status.success() {
+            return Err(self.error(format!("exited with {}", output.status)));
+        }
+
+        let answers: Vec<Option<String>> =
+            serde_json::from_slice(&output.stdout).map_err(|e| self.error(e))?;
+        if answers.len() != texts.len() {
+            return Err(self.error(format!(
+                "answered with {} texts for {} cues",
+                answers.len(),
+                texts.len()
+            )));
+        }
+        let cues = std::mem::take(&mut entry.content.0);
+        entry.content.0 = cues
+            .into_iter()
+            .zip(answers)
+            .filter_map(|(cue, text)| Some(Subtitle { text: text?, ..cue }))
+            .collect();
+        Ok(())
+    }
+}
+
+/// What running the hooks changed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HookStats {
+    /// Files of shows with hooks
+    pub files: usize,
+    pub cues_changed: usize,
+    pub cues_dropped: usize,
+}
+
+/// The hooks of each show, by show name.
+#[derive(Default)]
+pub struct IngestHooks {
+    shows: HashMap<String, Vec<Box<dyn IngestHook>>>,
+}
+
+impl IngestHooks {
+    /// The built-in hooks set up in the `[hooks]` config sections.
+    pub fn from_config(config: &HashMap<String, ShowHookConfig>) -> Result<Self, HookError> {
+        let mut hooks = IngestHooks::default();
+        for (show, section) in config {
+            let compile = |patterns: &[String]| {
+                patterns
+                    .iter()
+                    .map(|pattern| Regex::new(pattern))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|error| HookError::InvalidPattern {
+                        show: show.clone(),
+                        error,
+                    })
+            };
+            // The script sees the file as it was parsed, before the patterns apply
+            if !section.command.is_empty() {
+                hooks.add(show, ScriptHook::new(section.command.clone()));
+            }
+            if !section.drop.is_empty() || !section.strip.is_empty() {
+                hooks.add(
+                    show,
+                    PatternHook {
+                        drop: compile(&section.drop)?,
+                        strip: compile(&section.strip)?,
+                    },
+                );
+            }
+        }
+        Ok(hooks)
+    }
+
+    /// Adds a hook for `show`, run after the ones added before it.
+    pub fn add(&mut self, show: &str, hook: impl IngestHook + 'static) {
+        self.shows
+            .entry(show.to_string())
+            .or_default()
+            .push(Box::new(hook));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shows.is_empty()
+    }
+
+    /// Runs the hooks of each entry's show on it. Only the (Japanese) cues go
+    /// through the hooks; translations are left as they are.
+    pub fn apply(&self, entries: &mut [SrtEntry]) -> Result<HookStats, HookError> {
+        let mut stats = HookStats::default();
+        for entry in entries {
+            let Some(hooks) = self.shows.get(&entry.show_name) else {
+                continue;
+            };
+            stats.files += 1;
+            let before: HashMap<usize, String> = entry
+                .content
+                .0
+                .iter()
+                .map(|cue| (cue.number, cue.text.clone()))
+                .collect();
+            let cues = entry.content.0.len();
+            for hook in hooks {
+                hook.on_file(entry)?;
+                entry.content.0.retain_mut(|cue| hook.on_cue(cue));
+            }
+            stats.cues_dropped += cues.saturating_sub(entry.content.0.len());
+            stats.cues_changed += entry
+                .content
+                .0
+                .iter()
+                .filter(|cue| {
+                    before
+                        .get(&cue.number)
+                        .is_some_and(|text| *text != cue.text)
+                })
+                .count();
+        }
+        Ok(stats)
+    }
+}
+
+impl fmt::Debug for IngestHooks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries(self.shows.iter().map(|(show, hooks)| (show, hooks.len())))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::srt_parser::Subtitles;
+    use std::path::PathBuf;
+
+    fn entry(show_name: &str, srt: &str) -> SrtEntry {
+        let (content, stats) = Subtitles::parse_from_str_with_stats(srt).unwrap();
+        SrtEntry {
+            path: PathBuf::from(format!("{}/01.srt", show_name)),
+            show_name: show_name.to_string(),
+            episode_name: "Episode 1".to_string(),
+            episode_number: Some(1),
+            content,
+            translation: None,
+            stats,
+            language: None,
+        }
+    }
+
+    #[test]
+    fn test_config_hooks() {
+        let config: Config = r#"
+            [hooks."Show Name"]
+            drop = ["字幕:"]
+            strip = ["【[^】]*】"]
+        "#
+        .parse()
+        .unwrap();
+        let hooks = IngestHooks::from_config(&config.hooks).unwrap();
+
+        let srt = "1\n00:00:01,000 --> 00:00:02,000\n字幕: 某グループ\n\n\
+                   2\n00:00:03,000 --> 00:00:04,000\n【太郎】 おはよう\n\n\
+                   3\n00:00:05,000 --> 00:00:06,000\n【効果音】\n\n\
+                   4\n00:00:07,000 --> 00:00:08,000\nまたね\n";
+        let mut entries = vec![entry("Show Name", srt), entry("Other Show", srt)];
+        let stats = hooks.apply(&mut entries).unwrap();
+
+        assert_eq!(
+            stats,
+            HookStats {
+                files: 1,
+                cues_changed: 1,
+                cues_dropped: 2,
+            }
+        );
+        let texts: Vec<&str> = entries[0]
+            .content
+            .0
+            .iter()
+            .map(|c| c.text.as_str())
+            .collect();
+        assert_eq!(texts, ["おはよう", "またね"]);
+        assert_eq!(entries[1].content.0.len(), 4);
+    }
+
+    #[test]
+    fn test_custom_hook() {
+        struct Shout;
+        impl IngestHook for Shout {
+            fn on_cue(&self, cue: &mut Subtitle) -> bool {
+                cue.text.push('！');
+                true
+            }
+        }
+        let mut hooks = IngestHooks::default();
+        hooks.add("Show Name", Shout);
+        let mut entries = vec![entry(
+            "Show Name",
+            "1\n00:00:01,000 --> 00:00:02,000\nおはよう\n",
+        )];
+        hooks.apply(&mut entries).unwrap();
+        assert_eq!(entries[0].content.0[0].text, "おはよう！");
+    }
+
+    #[test]
+    fn test_invalid_pattern() {
+        let config: Config = "[hooks.Show]\ndrop = [\"(\"]".parse().unwrap();
+        assert!(matches!(
+            IngestHooks::from_config(&config.hooks),
+            Err(HookError::InvalidPattern { .. })
+        ));
+    }
+}
@@ -0,0 +1,193 @@
+//! What the word index was built with, recorded in the `index_meta` table.
+//!
+//! Lines indexed under one tokenizer, dictionary, normalization or index
+//! policy can't reliably be found by queries tokenized under another. So
+//! ingestion records these next to the index, `search` warns when the
+//! current configuration differs, and `reindex` rebuilds the index from the
+//! stored line text.
+
+use crate::db::{DbHandler, INDEX_POLICY_KEY};
+use crate::tokenizer::{Tokenizer, TokenizerConfig};
+use std::path::Path;
+
+/// Bumped whenever a change to tokenization changes which terms get indexed.
+///
+/// 2: Latin letters are lowercased
+pub const TOKENIZER_VERSION: u32 = 2;
+
+/// How text is normalized before it's tokenized (see [`crate::tokenizer::normalize`]).
+pub const NORMALIZATION: &str = "nfkc, lowercase";
+
+// Keys of the index_meta table, besides the policy's
+const TOKENIZER_KEY: &str = "tokenizer";
+const DICTIONARY_KEY: &str = "dictionary";
+const NORMALIZATION_KEY: &str = "normalization";
+
+// Stands in for settings of indexes built before they were recorded
+const UNKNOWN: &str = "unknown";
+
+/// The settings the Japanese word index is built with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexVersion {
+    /// The tokenizer's name and [`TOKENIZER_VERSION`], e.g. `japanese/ipadic v2`
+    pub tokenizer: String,
+    /// The dictionary files, by name and size
+    pub dictionary: String,
+    pub normalization: String,
+    /// See [`crate::tokenizer::IndexPolicy::fingerprint`]
+    pub policy: String,
+}
+
+/// A setting the index was built with that differs from the current one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionChange {
+    pub setting: &'static str,
+    pub indexed: String,
+    pub current: String,
+}
+
+impl IndexVersion {
+    /// The settings lines would be indexed with now.
+    pub fn current(config: &TokenizerConfig, tokenizer: &dyn Tokenizer) -> Self {
+        IndexVersion {
+            tokenizer: format!("{} v{}", tokenizer.name(), TOKENIZER_VERSION),
+            dictionary: dictionary_files(config),
+            normalization: NORMALIZATION.to_string(),
+            policy: tokenizer.index_fingerprint(),
+        }
+    }
+
+    /// The settings recorded for the index, or `None` if nothing was ever indexed.
+    /// Settings of indexes built before they were recorded are `unknown`.
+    pub fn recorded(db: &DbHandler) -> rusqlite::Result<Option<Self>> {
+        let tokenizer = db.index_meta(TOKENIZER_KEY)?;
+        let dictionary = db.index_meta(DICTIONARY_KEY)?;
+        let normalization = db.index_meta(NORMALIZATION_KEY)?;
+        let policy = db.index_meta(INDEX_POLICY_KEY)?;
+        if tokenizer.is_none()
+            && dictionary.is_none()
+            && normalization.is_none()
+            && policy.is_none()
+        {
+            return Ok(None);
+        }
+        let or_unknown = |value: Option<String>| value.unwrap_or_else(|| UNKNOWN.to_string());
+        Ok(Some(IndexVersion {
+            tokenizer: or_unknown(tokenizer),
+            dictionary: or_unknown(dictionary),
+            normalization: or_unknown(normalization),
+            policy: or_unknown(policy),
+        }))
+    }
+
+    /// Records these as the settings the index is built with.
+    pub fn record(&self, db: &DbHandler) -> rusqlite::Result<()> {
+        db.set_index_meta(TOKENIZER_KEY, &self.tokenizer)?;
+        db.set_index_meta(DICTIONARY_KEY, &self.dictionary)?;
+        db.set_index_meta(NORMALIZATION_KEY, &self.normalization)?;
+        db.set_index_meta(INDEX_POLICY_KEY, &self.policy)
+    }
+
+    /// The settings in which `indexed` differs from these.
+    pub fn changes_from(&self, indexed: &IndexVersion) -> Vec<VersionChange> {
+        [
+            ("tokenizer", &indexed.tokenizer, &self.tokenizer),
+            ("dictionary", &indexed.dictionary, &self.dictionary),
+            ("normalization", &indexed.normalization, &self.normalization),
+            ("policy", &indexed.policy, &self.policy),
+        ]
+        .into_iter()
+        .filter(|(_, indexed, current)| indexed != current)
+        .map(|(setting, indexed, current)| VersionChange {
+            setting,
+            indexed: indexed.clone(),
+            current: current.clone(),
+        })
+        .collect()
+    }
+}
+
+/// How the current settings differ from those the index was built with; empty if
+/// they don't, or if nothing was indexed yet.
+pub fn index_changes(
+    db: &DbHandler,
+    config: &TokenizerConfig,
+    tokenizer: &dyn Tokenizer,
+) -> rusqlite::Result<Vec<VersionChange>> {
+    let current = IndexVersion::current(config, tokenizer);
+    Ok(IndexVersion::recorded(db)?
+        .map(|indexed| current.changes_from(&indexed))
+        .unwrap_or_default())
+}
+
+// The dictionary files the tokenizer loads, by name and size, so a replaced one is noticed
+fn dictionary_files(config: &TokenizerConfig) -> String {
+    let describe = |path: &Path| {
+        let name = path
+            .file_name()
+            .unwrap_or(path.as_os_str())
+            .to_string_lossy();
+        match std::fs::metadata(path) {
+            Ok(metadata) => format!("{} ({} bytes)", name, metadata.len()),
+            Err(_) => format!("{} (missing)", name),
+        }
+    };
+    let files: Vec<String> = [
+        &config.dictionary_path,
+        &config.user_dictionary_path,
+        &config.glossary_dictionary_path,
+    ]
+    .into_iter()
+    .flatten()
+    .map(|path| describe(path))
+    .collect();
+    if files.is_empty() {
+        "none".to_string()
+    } else {
+        files.join(", ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_utils::test_db_with_lines;
+    use crate::tokenizer::test_utils::test_tokenizer;
+    use crate::tokenizer::IndexPolicy;
+
+    #[test]
+    fn test_index_changes() {
+        let (db, _) = test_db_with_lines(&[]);
+        let config = TokenizerConfig::default();
+        let tokenizer = test_tokenizer();
+        // Nothing indexed yet
+        assert!(index_changes(&db, &config, &tokenizer).unwrap().is_empty());
+
+        let version = IndexVersion::current(&config, &tokenizer);
+        assert_eq!(version.tokenizer, "japanese/ipadic v2");
+        assert_eq!(version.dictionary, "none");
+        version.record(&db).unwrap();
+        assert_eq!(IndexVersion::recorded(&db).unwrap(), Some(version));
+        assert!(index_changes(&db, &config, &tokenizer).unwrap().is_empty());
+
+        let tokenizer = tokenizer.with_policy(IndexPolicy {
+            min_length: 2,
+            ..IndexPolicy::default()
+        });
+        let changes = index_changes(&db, &config, &tokenizer).unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].setting, "policy");
+    }
+
+    #[test]
+    fn test_unrecorded_settings_are_unknown() {
+        let (db, _) = test_db_with_lines(&[]);
+        let tokenizer = test_tokenizer();
+        db.set_index_meta(INDEX_POLICY_KEY, &tokenizer.index_fingerprint())
+            .unwrap();
+        let changes = index_changes(&db, &TokenizerConfig::default(), &tokenizer).unwrap();
+        let settings: Vec<&str> = changes.iter().map(|change| change.setting).collect();
+        assert_eq!(settings, ["tokenizer", "dictionary", "normalization"]);
+        assert_eq!(changes[0].indexed, "unknown");
+    }
+}
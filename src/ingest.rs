@@ -0,0 +1,1171 @@
+//! Inserting parsed subtitle files into the database.
+
+use crate::backend::{update_index, BackendConfig};
+use crate::db::{
+    episode_label, CsvOutput, DbHandler, EpisodeId, NewEpisode, NewShow, NewTranscript,
+    NewTranslation, ShowId, ShowType, Source, SourceFile, TranscriptId,
+};
+use crate::srt_parser::{
+    align_translations, is_archive_file, process_srt_directory, youtube_video, EpisodeNameMethod,
+    EpisodeNumberMethod, ParseStats, SrtEntry, Subtitles, Timestamp,
+};
+use crate::tokenizer::JapaneseTokenizer;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+
+/// What [`insert_entries`] added to the database.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IngestedLines {
+    /// Episodes the entries were stored under, in entry order
+    pub episode_ids: Vec<EpisodeId>,
+    /// Newly inserted lines, ready to be indexed
+    pub transcript_ids: Vec<TranscriptId>,
+    /// Lines whose episode, start and end time and sub-index were already taken
+    /// by a line ingested before, and were handled by the database's lines
+    /// [`ConflictPolicy`](crate::db::ConflictPolicy)
+    pub line_conflicts: usize,
+}
+
+/// The `[show_types]` config section: which [`ShowType`] ingested shows get.
+///
+/// ```toml
+/// [show_types]
+/// default = "anime"
+/// directories = { "subs/dramas" = "drama" }
+/// shows = { "Show Name" = "movie" }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ShowTypeConfig {
+    /// The type of shows nothing below matches
+    pub default: ShowType,
+    /// Types by the directory given to `ingest`, which also cover the
+    /// directories inside it
+    pub directories: HashMap<PathBuf, ShowType>,
+    /// Types by show name, taking precedence over those by directory
+    pub shows: HashMap<String, ShowType>,
+}
+
+impl ShowTypeConfig {
+    /// The type of `show_name`, ingested from `root_dir`. Of several matching
+    /// directories, the innermost one counts.
+    pub fn show_type(&self, root_dir: &Path, show_name: &str) -> ShowType {
+        if let Some(&show_type) = self.shows.get(show_name) {
+            return show_type;
+        }
+        self.directories
+            .iter()
+            .filter(|(dir, _)| root_dir.starts_with(dir))
+            .max_by_key(|(dir, _)| dir.components().count())
+            .map_or(self.default, |(_, &show_type)| show_type)
+    }
+}
+
+/// Makes movies of the entries of shows `show_types` types as
+/// [`ShowType::Movie`], ingested from `root_dir`: they lose their episode
+/// number and are named after their show. Returns how many entries became movies.
+///
+/// A movie is one file per show (and language); a show typed as a movie whose
+/// entries have several episode numbers, like a film series in one folder,
+/// keeps them. The numbered episodes `db` already stores for the show count
+/// too, so one file of a film series ingested again on its own replaces its
+/// numbered episode rather than becoming a movie.
+pub fn mark_movies(
+    db: &DbHandler,
+    entries: &mut [SrtEntry],
+    show_types: &ShowTypeConfig,
+    root_dir: &Path,
+) -> rusqlite::Result<usize> {
+    let mut numbers: HashMap<&str, Vec<Option<i32>>> = HashMap::new();
+    for entry in entries.iter() {
+        let numbers = numbers.entry(&entry.show_name).or_default();
+        if !numbers.contains(&entry.episode_number) {
+            numbers.push(entry.episode_number);
+        }
+    }
+    let mut movies: Vec<String> = Vec::new();
+    for (show, mut numbers) in numbers {
+        if show_types.show_type(root_dir, show) != ShowType::Movie {
+            continue;
+        }
+        if let Some(show_id) = db.find_show_id(show)? {
+            for episode in db.list_episodes(show_id)? {
+                if episode.episode_number.is_some() && !numbers.contains(&episode.episode_number) {
+                    numbers.push(episode.episode_number);
+                }
+            }
+        }
+        if numbers.len() == 1 {
+            movies.push(show.to_string());
+        }
+    }
+    let mut marked = 0;
+    for entry in entries {
+        if movies.contains(&entry.show_name) && entry.episode_number.is_some() {
+            entry.episode_number = None;
+            entry.episode_name = entry.show_name.clone();
+            marked += 1;
+        }
+    }
+    Ok(marked)
+}
+
+/// Gives the stored shows among `show_names` the type `show_types` gives them
+/// when ingested from `root_dir`, returning their ids.
+pub fn set_show_types<'a>(
+    db: &mut DbHandler,
+    show_types: &ShowTypeConfig,
+    root_dir: &Path,
+    show_names: impl IntoIterator<Item = &'a str>,
+) -> rusqlite::Result<Vec<ShowId>> {
+    let mut show_ids = Vec::new();
+    for show_name in show_names {
+        if let Some(show_id) = db.find_show_id(show_name)? {
+            db.set_show_type(show_types.show_type(root_dir, show_name), &[show_id])?;
+            show_ids.push(show_id);
+        }
+    }
+    Ok(show_ids)
+}
+
+/// Parse statistics of the files in an ingestion run, per file and in total.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct IngestSummary {
+    pub files: Vec<FileReport>,
+    pub total: ParseStats,
+}
+
+/// Parse statistics of one ingested file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FileReport {
+    pub path: PathBuf,
+    pub show_name: String,
+    /// `None` for a movie
+    pub episode_number: Option<i32>,
+    pub stats: ParseStats,
+}
+
+impl IngestSummary {
+    /// Collects the statistics of parsed entries, in show and episode order.
+    pub fn from_entries(entries: &[SrtEntry]) -> Self {
+        let mut summary = IngestSummary::default();
+        for entry in entries {
+            summary.total.merge(&entry.stats);
+            summary.files.push(FileReport {
+                path: entry.path.clone(),
+                show_name: entry.show_name.clone(),
+                episode_number: entry.episode_number,
+                stats: entry.stats.clone(),
+            });
+        }
+        summary.files.sort_by(|a, b| {
+            (&a.show_name, a.episode_number).cmp(&(&b.show_name, b.episode_number))
+        });
+        summary
+    }
+}
+
+// One line per file, with where its cues were skipped, then the totals
+impl fmt::Display for IngestSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn stats_line(stats: &ParseStats) -> String {
+            let time =
+                |t: &Option<Timestamp>| t.as_ref().map_or("-".to_string(), |t| t.to_string());
+            format!(
+                "{} cues ({} skipped, {} cleanups), {} to {}, {} characters",
+                stats.cues_parsed,
+                stats.cues_skipped,
+                stats.cleaning.total(),
+                time(&stats.first_start),
+                time(&stats.last_end),
+                stats.characters
+            )
+        }
+        for file in &self.files {
+            writeln!(
+                f,
+                "{} {}: {}",
+                file.show_name,
+                episode_label(1, file.episode_number),
+                stats_line(&file.stats)
+            )?;
+            for diagnostic in &file.stats.diagnostics {
+                writeln!(f, "  {}: {}", file.path.display(), diagnostic)?;
+            }
+        }
+        write!(
+            f,
+            "Total, {} files: {}",
+            self.files.len(),
+            stats_line(&self.total)
+        )
+    }
+}
+
+/// The step of an ingestion run that failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IngestStage {
+    Shows,
+    Episodes,
+    ReplacingLines,
+    Transcripts,
+    Translations,
+    RestoringAnnotations,
+    SourceFiles,
+    Indexing,
+    /// Starting, committing or rolling back the run's transaction
+    Transaction,
+}
+
+/// An ingestion run failed and was rolled back; the database is unchanged.
+#[derive(Debug)]
+pub struct IngestError {
+    pub stage: IngestStage,
+    pub error: crate::error::Error,
+}
+
+impl fmt::Display for IngestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let stage = match self.stage {
+            IngestStage::Shows => "inserting shows",
+            IngestStage::Episodes => "inserting episodes",
+            IngestStage::ReplacingLines => "deleting the lines being replaced",
+            IngestStage::Transcripts => "inserting transcripts",
+            IngestStage::Translations => "inserting translations",
+            IngestStage::RestoringAnnotations => "restoring bookmarks, tags and notes",
+            IngestStage::SourceFiles => "recording the ingested files",
+            IngestStage::Indexing => "indexing the lines",
+            IngestStage::Transaction => "starting or ending the transaction",
+        };
+        write!(
+            f,
+            "Ingestion failed while {} ({}); no changes were saved",
+            stage, self.error
+        )
+    }
+}
+
+impl std::error::Error for IngestError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+// Runs `f` as one unit, like DbHandler::atomically; errors of the savepoint itself
+// are the Transaction stage
+fn atomically<T>(
+    db: &mut DbHandler,
+    f: impl FnOnce(&mut DbHandler) -> Result<T, IngestError>,
+) -> Result<T, IngestError> {
+    db.atomically_or(
+        |error| IngestError {
+            stage: IngestStage::Transaction,
+            error: error.into(),
+        },
+        f,
+    )
+}
+
+trait AtStage<T> {
+    fn at(self, stage: IngestStage) -> Result<T, IngestError>;
+}
+
+impl<T> AtStage<T> for rusqlite::Result<T> {
+    fn at(self, stage: IngestStage) -> Result<T, IngestError> {
+        self.map_err(|error| IngestError {
+            stage,
+            error: error.into(),
+        })
+    }
+}
+
+impl<T> AtStage<T> for crate::error::Result<T> {
+    fn at(self, stage: IngestStage) -> Result<T, IngestError> {
+        self.map_err(|error| IngestError { stage, error })
+    }
+}
+
+impl<T> AtStage<T> for crate::backend::Result<T> {
+    fn at(self, stage: IngestStage) -> Result<T, IngestError> {
+        self.map_err(|error| IngestError {
+            stage,
+            error: error.into(),
+        })
+    }
+}
+
+/// Inserts the shows, episodes and lines of parsed subtitle files.
+///
+/// Shows and episodes that already exist are reused. With `replace`, the
+/// existing lines of those episodes are deleted first, so a re-parsed file
+/// replaces its old version instead of being merged into it. Bookmarks, tags
+/// and notes on the old lines are moved to the new lines with the same stable
+/// id (see [`stable_line_id`](crate::db::stable_line_id)), and dropped for
+/// lines that changed.
+///
+/// Entries with English subtitles get each line's translation stored, aligned
+/// by timestamp overlap (see [`align_translations`]). Each entry's file is
+/// recorded in `source_files` (see [`source_file`]), so an interrupted run can
+/// be resumed.
+///
+/// Everything happens in one transaction: if any step fails, nothing is kept
+/// (including, with `replace`, the deletion of the old lines). Call this inside
+/// [`DbHandler::atomically`] to make further steps, like indexing, part of it.
+/// Rows already written to `csv_output` are not removed on failure.
+pub fn insert_entries(
+    db: &mut DbHandler,
+    entries: Vec<SrtEntry>,
+    replace: bool,
+    csv_output: Option<&CsvOutput>,
+) -> Result<IngestedLines, IngestError> {
+    atomically(db, |db| insert(db, entries, replace, csv_output))
+}
+
+/// Parses the subtitle files under `root_dir` and inserts and indexes them in
+/// one transaction, replacing the lines of episodes that were ingested before.
+///
+/// Episodes are numbered by file order, and shows get the type `show_types`
+/// gives them for `root_dir` (see [`mark_movies`]). Lines are added to the word
+/// index and to the search backend `backend` selects (see [`update_index`]).
+/// The corpus statistics are refreshed.
+pub fn ingest_directory(
+    db: &mut DbHandler,
+    tokenizer: &Arc<JapaneseTokenizer>,
+    backend: &BackendConfig,
+    show_types: &ShowTypeConfig,
+    root_dir: &Path,
+) -> Result<IngestedLines, IngestError> {
+    let mut entries: Vec<SrtEntry> = process_srt_directory(
+        root_dir,
+        &EpisodeNumberMethod::FromFileOrder,
+        &EpisodeNameMethod::FromEpisodeNumber,
+    )
+    .into_values()
+    .flatten()
+    .collect();
+    mark_movies(db, &mut entries, show_types, root_dir).at(IngestStage::Episodes)?;
+    let mut show_names: Vec<String> = entries
+        .iter()
+        .map(|entry| entry.show_name.clone())
+        .collect();
+    show_names.sort();
+    show_names.dedup();
+    atomically(db, |db| {
+        let ingested = insert_entries(db, entries, true, None)?;
+        let show_names = show_names.iter().map(String::as_str);
+        set_show_types(db, show_types, root_dir, show_names).at(IngestStage::Shows)?;
+        db.index_transcripts(tokenizer, &ingested.transcript_ids)
+            .at(IngestStage::Indexing)?;
+        update_index(
+            backend,
+            db,
+            tokenizer,
+            &ingested.transcript_ids,
+            &ingested.episode_ids,
+            true,
+        )
+        .at(IngestStage::Indexing)?;
+        db.refresh_corpus_stats().at(IngestStage::Indexing)?;
+        Ok(ingested)
+    })
+}
+
+/// The directory a remote client asked to ingest, if it is `allowed_root` or
+/// a directory under it.
+///
+/// Relative paths are taken from `allowed_root`. Both paths are canonicalized
+/// first, so `..` and symlinks can't lead out of the root.
+pub fn directory_within(allowed_root: &Path, requested: &Path) -> Option<PathBuf> {
+    let root = allowed_root.canonicalize().ok()?;
+    let dir = root.join(requested).canonicalize().ok()?;
+    (dir.starts_with(&root) && dir.is_dir()).then_some(dir)
+}
+
+fn insert(
+    db: &mut DbHandler,
+    entries: Vec<SrtEntry>,
+    replace: bool,
+    csv_output: Option<&CsvOutput>,
+) -> Result<IngestedLines, IngestError> {
+    // Insert shows first so the database can assign their ids
+    let shows: Vec<NewShow> = entries
+        .iter()
+        .map(|entry| NewShow {
+            name: entry.show_name.clone(),
+            show_type: ShowType::default().to_string(),
+        })
+        .collect();
+    let show_ids = db.batch_insert_shows(&shows).at(IngestStage::Shows)?;
+
+    // Then insert episodes using the returned show ids
+    let mut episodes = Vec::new();
+    let mut episode_contents = Vec::new();
+    let mut paths = Vec::new();
+    let mut checksums = Vec::new();
+    for (show_id, entry) in show_ids.into_iter().zip(entries) {
+        episodes.push(NewEpisode {
+            show_id,
+            name: entry.episode_name,
+            season: 1, // Assuming all episodes are in season 1
+            episode_number: entry.episode_number,
+        });
+        checksums.push(content_checksum(&entry.content));
+        episode_contents.push((entry.content, entry.translation));
+        paths.push(entry.path);
+    }
+    let episode_ids = db
+        .batch_insert_episodes(&episodes)
+        .at(IngestStage::Episodes)?;
+
+    let mut annotations = Vec::new();
+    if replace {
+        for &episode_id in &episode_ids {
+            annotations.push(
+                db.line_annotations(episode_id)
+                    .at(IngestStage::ReplacingLines)?,
+            );
+            db.delete_episode_lines(episode_id)
+                .at(IngestStage::ReplacingLines)?;
+        }
+    }
+
+    // Finally insert transcripts using the returned episode ids
+    let mut transcripts = Vec::new();
+    let mut translations = Vec::new();
+    for (&episode_id, (content, translation)) in episode_ids.iter().zip(episode_contents) {
+        if let Some(translation) = translation {
+            let aligned = align_translations(&content, &translation);
+            for (subtitle, text) in content.iter().zip(aligned) {
+                if let Some(text) = text {
+                    translations.push(NewTranslation {
+                        episode_id,
+                        time_start: subtitle.start_time.to_string(),
+                        time_end: subtitle.end_time.to_string(),
+                        text,
+                    });
+                }
+            }
+        }
+        // Cues shown at the same time are numbered in file order
+        let mut simultaneous: HashMap<(String, String), i32> = HashMap::new();
+        for subtitle in content {
+            let time_start = subtitle.start_time.to_string();
+            let time_end = subtitle.end_time.to_string();
+            let count = simultaneous
+                .entry((time_start.clone(), time_end.clone()))
+                .or_default();
+            let speaker = subtitle
+                .speaker
+                .clone()
+                .or_else(|| subtitle.bracketed_speaker());
+            transcripts.push(NewTranscript {
+                episode_id,
+                line_id: subtitle.number as i32,
+                time_start,
+                time_end,
+                sub_index: *count,
+                text: subtitle.text,
+                text_raw: subtitle.raw_text,
+                speaker,
+            });
+            *count += 1;
+        }
+    }
+    let inserted = db
+        .batch_insert_transcripts(&transcripts, csv_output)
+        .at(IngestStage::Transcripts)?;
+    db.batch_insert_translations(&translations)
+        .at(IngestStage::Translations)?;
+    for annotations in annotations.iter().filter(|a| !a.is_empty()) {
+        db.restore_line_annotations(annotations)
+            .at(IngestStage::RestoringAnnotations)?;
+    }
+
+    let files: Vec<SourceFile> = paths
+        .iter()
+        .zip(&episode_ids)
+        .zip(checksums)
+        .map(|((path, &episode_id), checksum)| SourceFile {
+            checksum: Some(checksum),
+            ..source_file(path, episode_id)
+        })
+        .collect();
+    db.record_source_files(&files)
+        .at(IngestStage::SourceFiles)?;
+    // YouTube captions also record their video
+    let sources: Vec<Source> = files
+        .iter()
+        .zip(&paths)
+        .map(|(file, path)| {
+            let video = youtube_video(path);
+            Source {
+                path: Some(file.path.clone()),
+                url: video.as_ref().map(|video| video.url()),
+                video_id: video.map(|video| video.id),
+                ..Source::new(file.episode_id)
+            }
+        })
+        .collect();
+    db.record_sources(&sources).at(IngestStage::SourceFiles)?;
+
+    Ok(IngestedLines {
+        episode_ids,
+        transcript_ids: inserted.ids,
+        line_conflicts: inserted.conflicts,
+    })
+}
+
+/// Describes a file as it is now, for recording or comparing with a recorded one.
+///
+/// The path is made absolute so the same file matches however it was reached;
+/// size and modification time are left empty if the file can't be read. The
+/// checksum is left empty, as it's taken from the parsed subtitles.
+///
+/// Files read from an archive (whose paths are the archive's joined with the
+/// path inside it, see [`process_archive`](crate::srt_parser::process_archive))
+/// get the archive's size and modification time.
+pub fn source_file(path: &Path, episode_id: EpisodeId) -> SourceFile {
+    let archive = path
+        .ancestors()
+        .skip(1)
+        .find(|ancestor| is_archive_file(ancestor) && ancestor.is_file());
+    let file = archive.unwrap_or(path);
+    let canonical = std::fs::canonicalize(file).unwrap_or_else(|_| file.to_path_buf());
+    let metadata = std::fs::metadata(&canonical).ok();
+    let path = match archive.and_then(|archive| path.strip_prefix(archive).ok()) {
+        Some(inner) => canonical.join(inner),
+        None => canonical,
+    };
+    SourceFile {
+        path: path.to_string_lossy().into_owned(),
+        episode_id,
+        size: metadata.as_ref().map(|m| m.len() as i64),
+        modified: metadata
+            .and_then(|m| m.modified().ok())
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map(|duration| duration.as_secs() as i64),
+        checksum: None,
+    }
+}
+
+/// The files earlier runs ingested, for resuming an interrupted run: files
+/// unchanged since are skipped, and those that changed replace the lines
+/// they were ingested as rather than being merged into them.
+#[derive(Debug, Clone, Default)]
+pub struct ResumeState {
+    recorded: HashMap<String, SourceFile>,
+}
+
+impl ResumeState {
+    /// Reads the files recorded in `source_files`.
+    pub fn load(db: &DbHandler) -> rusqlite::Result<Self> {
+        Ok(ResumeState {
+            recorded: db.source_files()?,
+        })
+    }
+
+    /// Whether the file at `path` was ingested as it is now. An archive is
+    /// when files were ingested from it, all with its current size and
+    /// modification time.
+    pub fn is_unchanged(&self, path: &Path) -> bool {
+        let current = source_file(path, EpisodeId(0));
+        if !is_archive_file(path) {
+            return self
+                .recorded
+                .get(&current.path)
+                .is_some_and(|recorded| recorded.is_unchanged(&current));
+        }
+        let archive = Path::new(&current.path);
+        let mut inner = self
+            .recorded
+            .values()
+            .filter(|file| file.path != current.path && Path::new(&file.path).starts_with(archive))
+            .peekable();
+        inner.peek().is_some()
+            && inner.all(|file| {
+                file.size.is_some()
+                    && file.size == current.size
+                    && file.modified == current.modified
+            })
+    }
+
+    /// Whether the file at `path` was ingested before, so that ingesting it
+    /// again must replace its lines.
+    pub fn was_ingested(&self, path: &Path) -> bool {
+        self.recorded
+            .contains_key(&source_file(path, EpisodeId(0)).path)
+    }
+
+    /// Splits entries into those whose file was ingested before, which are
+    /// ingested again because it changed, and new ones.
+    pub fn split_changed(&self, entries: Vec<SrtEntry>) -> (Vec<SrtEntry>, Vec<SrtEntry>) {
+        entries
+            .into_iter()
+            .partition(|entry| self.was_ingested(&entry.path))
+    }
+}
+
+/// A hash of subtitles' timings and raw text, the same for identical files
+/// whatever they're named and however their line endings are encoded.
+pub fn content_checksum(subtitles: &Subtitles) -> String {
+    let mut hasher = Sha256::new();
+    for subtitle in subtitles.iter() {
+        hasher.update(
+            format!(
+                "{} --> {}\n{}\n\n",
+                subtitle.start_time, subtitle.end_time, subtitle.raw_text
+            )
+            .as_bytes(),
+        );
+    }
+    hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// What to do with files whose subtitles were already ingested from another path.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DuplicatePolicy {
+    /// Leave them out, so their lines don't count twice in the statistics
+    #[default]
+    Skip,
+    /// Ingest them anyway, only warning about them
+    Warn,
+}
+
+impl FromStr for DuplicatePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "skip" => Ok(DuplicatePolicy::Skip),
+            "warn" => Ok(DuplicatePolicy::Warn),
+            _ => Err(format!(
+                "unknown duplicate policy {:?} (expected skip or warn)",
+                s
+            )),
+        }
+    }
+}
+
+/// What to do with cues shown at the same time, as some rips split top and
+/// bottom text into.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SimultaneousCues {
+    /// Store each as its own line, told apart by their sub-index
+    #[default]
+    Keep,
+    /// Merge them into one line (see [`Subtitles::merge_simultaneous`])
+    Merge,
+}
+
+impl FromStr for SimultaneousCues {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "keep" => Ok(SimultaneousCues::Keep),
+            "merge" => Ok(SimultaneousCues::Merge),
+            _ => Err(format!(
+                "unknown handling of simultaneous cues {:?} (expected keep or merge)",
+                s
+            )),
+        }
+    }
+}
+
+/// Merges the simultaneous cues of each entry and its translation with
+/// [`SimultaneousCues::Merge`]. Returns how many cues were merged away.
+pub fn apply_simultaneous_cues(entries: &mut [SrtEntry], handling: SimultaneousCues) -> usize {
+    if handling == SimultaneousCues::Keep {
+        return 0;
+    }
+    let mut merged = 0;
+    for entry in entries {
+        merged += entry.content.merge_simultaneous();
+        if let Some(translation) = &mut entry.translation {
+            translation.merge_simultaneous();
+        }
+    }
+    merged
+}
+
+/// A parsed file with the same subtitles as another.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateFile {
+    pub path: PathBuf,
+    pub show_name: String,
+    /// The path of the file it duplicates: one ingested before, or an earlier entry
+    pub original: String,
+}
+
+/// Finds the entries whose subtitles (by [`content_checksum`]) match a file
+/// ingested before from another path, or an earlier entry, as happens when
+/// the same download ends up in two show folders. With
+/// [`DuplicatePolicy::Skip`] they're removed from `entries`.
+///
+/// Files ingested before checksums were recorded aren't matched.
+pub fn find_duplicates(
+    db: &DbHandler,
+    entries: &mut Vec<SrtEntry>,
+    policy: DuplicatePolicy,
+) -> rusqlite::Result<Vec<DuplicateFile>> {
+    // First path seen for each checksum in this run
+    let mut seen: HashMap<String, String> = HashMap::new();
+    let mut duplicates = Vec::new();
+    let mut is_duplicate = Vec::with_capacity(entries.len());
+    for entry in entries.iter() {
+        let checksum = content_checksum(&entry.content);
+        let path = source_file(&entry.path, EpisodeId(0)).path;
+        let original = match seen.get(&checksum) {
+            Some(original) => Some(original.clone()),
+            // A file ingested again from the same path replaces itself
+            None => db
+                .source_files_with_checksum(&checksum)?
+                .into_iter()
+                .map(|file| file.path)
+                .find(|original| *original != path),
+        };
+        if let Some(original) = &original {
+            duplicates.push(DuplicateFile {
+                path: entry.path.clone(),
+                show_name: entry.show_name.clone(),
+                original: original.clone(),
+            });
+        } else {
+            seen.insert(checksum, path);
+        }
+        is_duplicate.push(original.is_some());
+    }
+    if policy == DuplicatePolicy::Skip {
+        let mut is_duplicate = is_duplicate.into_iter();
+        entries.retain(|_| !is_duplicate.next().unwrap_or(false));
+    }
+    Ok(duplicates)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::srt_parser::{process_srt_directory_filtered, Subtitles};
+    use crate::tokenizer::test_utils::test_tokenizer;
+
+    fn entry(episode_number: i32, srt: &str) -> SrtEntry {
+        let (content, stats) = Subtitles::parse_from_str_with_stats(srt).unwrap();
+        SrtEntry {
+            path: PathBuf::from(format!("Show Name/{}.srt", episode_number)),
+            show_name: "Show Name".to_string(),
+            episode_name: format!("Episode {}", episode_number),
+            episode_number: Some(episode_number),
+            content,
+            translation: None,
+            stats,
+            language: None,
+        }
+    }
+
+    #[test]
+    fn test_insert_entries_replace() {
+        let mut db = DbHandler::new(":memory:").unwrap();
+        db.create_tables().unwrap();
+        let old = "1\n00:00:01,000 --> 00:00:02,000\n古い行\n\n2\n00:00:03,000 --> 00:00:04,000\n二行目\n";
+        let new = "1\n00:00:01,500 --> 00:00:02,000\n 新しい行\n";
+
+        let first = insert_entries(&mut db, vec![entry(1, old)], false, None).unwrap();
+        assert_eq!(first.transcript_ids.len(), 2);
+
+        // The same lines again collide with the stored ones, and are ignored by default
+        let again = insert_entries(&mut db, vec![entry(1, old)], false, None).unwrap();
+        assert_eq!((again.transcript_ids.len(), again.line_conflicts), (0, 2));
+
+        // Without replacing, the new line is merged into the old ones
+        let merged = insert_entries(&mut db, vec![entry(1, new)], false, None).unwrap();
+        assert_eq!(merged.episode_ids, first.episode_ids);
+        let lines = db.get_episode_lines(first.episode_ids[0]).unwrap();
+        assert_eq!(lines.len(), 3);
+        for line in &lines {
+            db.add_bookmark("me", line.id, None, None).unwrap();
+        }
+
+        // Only the bookmark on the line that's ingested again is kept
+        let replaced = insert_entries(&mut db, vec![entry(1, new)], true, None).unwrap();
+        let lines = db.get_episode_lines(replaced.episode_ids[0]).unwrap();
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].text, "新しい行");
+        assert_eq!(lines[0].text_raw, " 新しい行");
+        let bookmarks = db.bookmarks("me", None).unwrap();
+        assert_eq!(bookmarks.len(), 1);
+        assert_eq!(bookmarks[0].hit.transcript_id, lines[0].id);
+    }
+
+    #[test]
+    fn test_resume_replaces_changed_files() {
+        let dir = std::env::temp_dir().join(format!("anime_search_resume-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("Show Name")).unwrap();
+        let file = dir.join("Show Name").join("01.srt");
+        std::fs::write(&file, "1\n00:00:01,000 --> 00:00:02,000\n古い行\n").unwrap();
+        let mut db = DbHandler::new(":memory:").unwrap();
+        db.create_tables().unwrap();
+
+        // Ingests what changed since the last run, returning how many files were new and changed
+        let resume = |db: &mut DbHandler| {
+            let state = ResumeState::load(db).unwrap();
+            let entries: Vec<SrtEntry> = process_srt_directory_filtered(
+                &dir,
+                &EpisodeNumberMethod::FromFileOrder,
+                &EpisodeNameMethod::FromEpisodeNumber,
+                |path| !state.is_unchanged(path),
+            )
+            .into_values()
+            .flatten()
+            .collect();
+            let (changed, new) = state.split_changed(entries);
+            let counts = (new.len(), changed.len());
+            insert_entries(db, new, false, None).unwrap();
+            insert_entries(db, changed, true, None).unwrap();
+            counts
+        };
+        assert_eq!(resume(&mut db), (1, 0));
+        assert_eq!(resume(&mut db), (0, 0));
+
+        std::fs::write(&file, "1\n00:00:01,500 --> 00:00:02,000\n新しい行です\n").unwrap();
+        assert_eq!(resume(&mut db), (0, 1));
+        let episode_id = db
+            .source_files()
+            .unwrap()
+            .into_values()
+            .next()
+            .unwrap()
+            .episode_id;
+        let lines = db.get_episode_lines(episode_id).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].text, "新しい行です");
+    }
+
+    #[test]
+    fn test_resume_skips_unchanged_archives() {
+        use std::io::Write;
+
+        let dir =
+            std::env::temp_dir().join(format!("anime_search_resume_zip-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let archive = dir.join("Show Name.zip");
+        {
+            let mut writer = zip::ZipWriter::new(std::fs::File::create(&archive).unwrap());
+            writer
+                .start_file("01.srt", zip::write::SimpleFileOptions::default())
+                .unwrap();
+            writer
+                .write_all("1\n00:00:01,000 --> 00:00:02,000\n猫だ\n".as_bytes())
+                .unwrap();
+            writer.finish().unwrap();
+        }
+        let mut db = DbHandler::new(":memory:").unwrap();
+        db.create_tables().unwrap();
+        let state = ResumeState::load(&db).unwrap();
+        assert!(!state.is_unchanged(&archive));
+
+        let entries: Vec<SrtEntry> = process_srt_directory(
+            &dir,
+            &EpisodeNumberMethod::FromFileOrder,
+            &EpisodeNameMethod::FromEpisodeNumber,
+        )
+        .into_values()
+        .flatten()
+        .collect();
+        insert_entries(&mut db, entries, false, None).unwrap();
+        let recorded = db.source_files().unwrap().into_values().next().unwrap();
+        let state = ResumeState::load(&db).unwrap();
+        let unchanged = state.is_unchanged(&archive);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(recorded.path.ends_with("Show Name.zip/01.srt"));
+        assert!(recorded.size.is_some());
+        assert!(unchanged);
+    }
+
+    #[test]
+    fn test_ingest_directory_applies_config() {
+        use crate::backend::{open_backend, BackendKind};
+
+        let dir = std::env::temp_dir().join(format!("anime_search_backend-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("Show Name")).unwrap();
+        std::fs::write(
+            dir.join("Show Name").join("01.srt"),
+            "1\n00:00:01,000 --> 00:00:02,000\n猫が好き\n",
+        )
+        .unwrap();
+        let mut db = DbHandler::new(":memory:").unwrap();
+        db.create_tables().unwrap();
+        let tokenizer = Arc::new(test_tokenizer());
+        let config = BackendConfig {
+            backend: BackendKind::Fts5,
+            ..BackendConfig::default()
+        };
+
+        let show_types = ShowTypeConfig {
+            default: ShowType::Drama,
+            ..ShowTypeConfig::default()
+        };
+
+        let first = ingest_directory(&mut db, &tokenizer, &config, &show_types, &dir);
+        // Ingesting again replaces the episode's lines in the backend too
+        let second = ingest_directory(&mut db, &tokenizer, &config, &show_types, &dir);
+        std::fs::remove_dir_all(&dir).unwrap();
+        first.unwrap();
+        let ingested = second.unwrap();
+        assert_eq!(db.list_shows().unwrap()[0].show_type, "Drama");
+
+        let backend = open_backend(&config, &mut db, &tokenizer).unwrap();
+        assert_eq!(backend.search("猫", 10).unwrap(), ingested.transcript_ids);
+    }
+
+    #[test]
+    fn test_directory_within() {
+        let dir = std::env::temp_dir().join(format!("anime_search_within-{}", std::process::id()));
+        let root = dir.join("subtitles");
+        std::fs::create_dir_all(root.join("Show Name")).unwrap();
+        std::fs::write(root.join("notes.txt"), "").unwrap();
+
+        let show = directory_within(&root, Path::new("Show Name"));
+        let absolute = directory_within(&root, &root.join("Show Name"));
+        let itself = directory_within(&root, &root);
+        let outside = directory_within(&root, &dir);
+        let escaping = directory_within(&root, Path::new("Show Name/../.."));
+        let file = directory_within(&root, Path::new("notes.txt"));
+        let missing = directory_within(&root, Path::new("Other Show"));
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(show.is_some_and(|show| show.ends_with("subtitles/Show Name")));
+        assert!(absolute.is_some());
+        assert!(itself.is_some());
+        assert_eq!(outside, None);
+        assert_eq!(escaping, None);
+        assert_eq!(file, None);
+        assert_eq!(missing, None);
+    }
+
+    #[test]
+    fn test_insert_simultaneous_cues() {
+        let mut db = DbHandler::new(":memory:").unwrap();
+        db.create_tables().unwrap();
+        let content =
+            "1\n00:00:01,000 --> 00:00:02,000\n上\n\n2\n00:00:01,000 --> 00:00:02,000\n下\n";
+
+        let kept = insert_entries(&mut db, vec![entry(1, content)], false, None).unwrap();
+        assert_eq!((kept.transcript_ids.len(), kept.line_conflicts), (2, 0));
+
+        let mut entries = vec![entry(2, content)];
+        assert_eq!(
+            apply_simultaneous_cues(&mut entries, SimultaneousCues::Merge),
+            1
+        );
+        let merged = insert_entries(&mut db, entries, false, None).unwrap();
+        let lines = db.get_episode_lines(merged.episode_ids[0]).unwrap();
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].text, "上\n下");
+    }
+
+    #[test]
+    fn test_insert_speakers() {
+        let mut db = DbHandler::new(":memory:").unwrap();
+        db.create_tables().unwrap();
+        let content = "1\n00:00:01,000 --> 00:00:02,000\n（太郎）おはよう\n\n\
+                       2\n00:00:03,000 --> 00:00:04,000\nうん\n";
+        let inserted = insert_entries(&mut db, vec![entry(1, content)], false, None).unwrap();
+        let lines = db.get_episode_lines(inserted.episode_ids[0]).unwrap();
+        assert_eq!(lines[0].speaker.as_deref(), Some("太郎"));
+        assert_eq!(lines[0].text, "（太郎）おはよう");
+        assert_eq!(lines[1].speaker, None);
+
+        let tokenizer = crate::tokenizer::test_utils::test_tokenizer();
+        db.index_transcripts(&tokenizer, &inserted.transcript_ids)
+            .unwrap();
+        let hits = crate::search::search(&db, &tokenizer, "speaker:太郎").unwrap();
+        assert_eq!(hits.len(), 1);
+        assert!(
+            crate::search::search(&db, &tokenizer, "speaker:花子 おはよう")
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_show_type_config() {
+        let config: crate::config::Config = r#"
+            [show_types]
+            default = "drama"
+            directories = { "subs" = "anime", "subs/movies" = "movie" }
+            shows = { "Podcast Name" = "podcast" }
+        "#
+        .parse()
+        .unwrap();
+        let types = &config.show_types;
+        assert_eq!(
+            types.show_type(Path::new("subs"), "Show Name"),
+            ShowType::Anime
+        );
+        assert_eq!(
+            types.show_type(Path::new("subs/movies/ghibli"), "Show Name"),
+            ShowType::Movie
+        );
+        assert_eq!(
+            types.show_type(Path::new("subs"), "Podcast Name"),
+            ShowType::Podcast
+        );
+        assert_eq!(
+            types.show_type(Path::new("other"), "Show Name"),
+            ShowType::Drama
+        );
+    }
+
+    #[test]
+    fn test_mark_movies() {
+        let srt = "1\n00:00:01,000 --> 00:00:02,000\n行こう\n";
+        let movie = |name: &str| SrtEntry {
+            show_name: name.to_string(),
+            ..entry(1, srt)
+        };
+        let mut entries = vec![movie("Movie Name"), movie("Movie Name"), entry(1, srt)];
+        entries.push(SrtEntry {
+            show_name: "Film Series".to_string(),
+            ..entry(2, srt)
+        });
+        entries.push(movie("Film Series"));
+        let config: crate::config::Config = r#"
+            [show_types]
+            shows = { "Movie Name" = "movie", "Film Series" = "movie" }
+        "#
+        .parse()
+        .unwrap();
+        let mut db = DbHandler::new(":memory:").unwrap();
+        db.create_tables().unwrap();
+        assert_eq!(
+            mark_movies(&db, &mut entries, &config.show_types, Path::new("")).unwrap(),
+            2
+        );
+        assert_eq!(entries[0].episode_number, None);
+        assert_eq!(entries[0].episode_name, "Movie Name");
+        assert_eq!(entries[2].episode_number, Some(1));
+        assert_eq!(entries[3].episode_number, Some(2));
+
+        let ingested = insert_entries(&mut db, entries.drain(..1).collect(), false, None).unwrap();
+        let hits = db.find_lines_containing("行こう").unwrap();
+        assert_eq!(hits[0].episode_number, None);
+        assert_eq!(ingested.episode_ids.len(), 1);
+
+        // Once the film series is stored, one of its files on its own keeps its number
+        let series: Vec<SrtEntry> = entries.drain(2..).collect();
+        insert_entries(&mut db, series, false, None).unwrap();
+        let mut single = vec![SrtEntry {
+            show_name: "Film Series".to_string(),
+            ..entry(2, srt)
+        }];
+        assert_eq!(
+            mark_movies(&db, &mut single, &config.show_types, Path::new("")).unwrap(),
+            0
+        );
+        assert_eq!(single[0].episode_number, Some(2));
+    }
+
+    #[test]
+    fn test_insert_entries_rolls_back() {
+        let mut db = DbHandler::new(":memory:").unwrap();
+        db.create_tables().unwrap();
+        let old = "1\n00:00:01,000 --> 00:00:02,000\n古い行\n";
+        insert_entries(&mut db, vec![entry(1, old)], false, None).unwrap();
+
+        // Writing the CSV fails after the old lines were deleted and new ones inserted
+        let csv_output = CsvOutput::new("/nonexistent/dir/out.csv");
+        let new = "1\n00:00:05,000 --> 00:00:06,000\n新しい行\n";
+        let error = insert_entries(
+            &mut db,
+            vec![entry(2, new), entry(1, new)],
+            true,
+            Some(&csv_output),
+        )
+        .unwrap_err();
+        assert_eq!(error.stage, IngestStage::Transcripts);
+        // The failure names the file it couldn't write
+        assert!(matches!(
+            &error.error,
+            crate::error::Error::File { path, .. } if path.ends_with("out.csv")
+        ));
+
+        let show_id = db.list_shows().unwrap()[0].id;
+        let episodes = db.list_episodes(show_id).unwrap();
+        assert_eq!(episodes.len(), 1);
+        let lines = db.get_episode_lines(episodes[0].id).unwrap();
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].text, "古い行");
+    }
+
+    #[test]
+    fn test_insert_entries_with_translation() {
+        let mut db = DbHandler::new(":memory:").unwrap();
+        db.create_tables().unwrap();
+        let mut bilingual = entry(1, "1\n00:00:01,000 --> 00:00:02,000\n行こう\n");
+        bilingual.translation = Some(
+            Subtitles::parse_from_str("1\n00:00:01,100 --> 00:00:02,100\nLet's go.\n").unwrap(),
+        );
+        let ingested = insert_entries(&mut db, vec![bilingual], false, None).unwrap();
+
+        let hits = db.find_hits_by_ids(&ingested.transcript_ids).unwrap();
+        assert_eq!(hits[0].translation.as_deref(), Some("Let's go."));
+    }
+
+    #[test]
+    fn test_find_duplicates() {
+        let mut db = DbHandler::new(":memory:").unwrap();
+        db.create_tables().unwrap();
+        let srt = "1\n00:00:01,000 --> 00:00:02,000\n同じ行\n";
+        insert_entries(&mut db, vec![entry(1, srt)], false, None).unwrap();
+
+        // The same file in another show folder, and a different file
+        let mut copy = entry(1, srt);
+        copy.path = PathBuf::from("Show Copy/1.srt");
+        copy.show_name = "Show Copy".to_string();
+        let other = entry(2, "1\n00:00:01,000 --> 00:00:02,000\n違う行\n");
+        let mut entries = vec![copy, other, entry(1, srt)];
+
+        let duplicates = find_duplicates(&db, &mut entries, DuplicatePolicy::Warn).unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(duplicates.len(), 1);
+        assert_eq!(duplicates[0].show_name, "Show Copy");
+        assert!(duplicates[0].original.ends_with("1.srt"));
+
+        find_duplicates(&db, &mut entries, DuplicatePolicy::Skip).unwrap();
+        let paths: Vec<&Path> = entries.iter().map(|entry| entry.path.as_path()).collect();
+        assert_eq!(
+            paths,
+            [Path::new("Show Name/2.srt"), Path::new("Show Name/1.srt")]
+        );
+    }
+
+    #[test]
+    fn test_ingest_summary() {
+        let entries = vec![
+            entry(2, "1\n00:00:05,000 --> 00:00:06,000\n二話\n"),
+            entry(1, "1\n00:00:01,000 --> 00:00:02,000\n一話\n\nbroken\n"),
+        ];
+        let summary = IngestSummary::from_entries(&entries);
+        assert_eq!(summary.files[0].episode_number, Some(1));
+        assert_eq!(summary.total.cues_parsed, 2);
+        assert_eq!(summary.total.cues_skipped, 1);
+        assert_eq!(summary.total.characters, 4);
+        let report = summary.to_string();
+        assert!(report
+            .contains("  Show Name/1.srt: line 5, column 1 (cue 2): Invalid subtitle number\n"));
+        assert!(report.ends_with(
+            "2 cues (1 skipped, 0 cleanups), 00:00:01,000 to 00:00:06,000, 4 characters"
+        ));
+    }
+}
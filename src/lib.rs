@@ -0,0 +1,43 @@
+pub mod backend;
+pub mod bench;
+pub mod bots;
+pub mod catchphrases;
+pub mod collocations;
+pub mod concordance;
+pub mod config;
+pub mod context;
+pub mod daily;
+pub mod db;
+pub mod difficulty;
+pub mod engine;
+pub mod error;
+pub mod explain;
+pub mod export;
+pub mod fetch;
+pub mod furigana;
+pub mod fuzzy;
+pub mod grammar;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod hooks;
+pub mod index_version;
+pub mod ingest;
+pub mod llm;
+pub mod mcp;
+pub mod media;
+#[cfg(feature = "async")]
+pub mod nonblocking;
+pub mod overlap;
+pub mod pitch_accent;
+pub mod player;
+pub mod query_log;
+pub mod ranking;
+pub mod sample;
+pub mod search;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod srt_parser;
+pub mod tokenizer;
+pub mod transcribe;
+pub mod translate;
+pub mod watch;
@@ -0,0 +1,613 @@
+//! Large language models, for the steps of the pipeline that need judgement,
+//! such as picking the most memorable lines out of a set of hits.
+//!
+//! Every model is reached through an [`LlmProvider`]. Three ship with the crate,
+//! behind the `llm` feature: OpenAI-compatible chat completion APIs (which also
+//! covers local servers such as llama.cpp or LM Studio), Anthropic, and Ollama.
+//! Which one is used, and with what model, is set in the `[llm]` config section.
+
+#[cfg(feature = "llm")]
+mod anthropic;
+mod cache;
+#[cfg(feature = "llm")]
+mod ollama;
+#[cfg(feature = "llm")]
+mod openai;
+
+use crate::db::{DbHandler, Explanation, SearchHit, TranscriptId};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt;
+use std::path::Path;
+
+#[cfg(feature = "llm")]
+pub use anthropic::AnthropicProvider;
+pub use cache::CachedProvider;
+#[cfg(feature = "llm")]
+pub use ollama::OllamaProvider;
+#[cfg(feature = "llm")]
+pub use openai::OpenAiProvider;
+
+/// Which [`LlmProvider`] the `[llm]` section configures.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProviderKind {
+    /// `POST {base_url}/chat/completions`, as offered by OpenAI and many local servers
+    OpenAi,
+    /// Anthropic's Messages API
+    Anthropic,
+    /// A local Ollama server
+    Ollama,
+}
+
+/// The `[llm]` config section.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LlmConfig {
+    /// No provider means no LLM is configured
+    pub provider: Option<ProviderKind>,
+    /// The model to ask, in the provider's naming
+    pub model: Option<String>,
+    /// Where the API is, if not the provider's usual address
+    pub base_url: Option<String>,
+    /// The environment variable holding the API key, if not the provider's usual one
+    pub api_key_env: Option<String>,
+    /// Most tokens in a reply
+    pub max_tokens: u32,
+    /// Most tokens in a prompt; candidates that don't fit are ranked in batches
+    pub prompt_tokens: usize,
+    pub temperature: Option<f32>,
+    /// Keep replies in the database and reuse them for the same prompt and model
+    pub cache: bool,
+    /// Days a cached reply stays usable; 0 keeps them until evicted
+    pub cache_ttl_days: u64,
+    /// Most replies kept; the least recently used are dropped first
+    pub cache_max_entries: usize,
+}
+
+impl Default for LlmConfig {
+    fn default() -> Self {
+        LlmConfig {
+            provider: None,
+            model: None,
+            base_url: None,
+            api_key_env: None,
+            max_tokens: 1024,
+            prompt_tokens: 8000,
+            temperature: None,
+            cache: true,
+            cache_ttl_days: 30,
+            cache_max_entries: 10_000,
+        }
+    }
+}
+
+impl LlmConfig {
+    // The API key from api_key_env, or else from the provider's usual variable
+    #[cfg(feature = "llm")]
+    fn api_key(&self, default_env: &str) -> Option<String> {
+        let name = self.api_key_env.as_deref().unwrap_or(default_env);
+        std::env::var(name).ok().filter(|key| !key.is_empty())
+    }
+
+    #[cfg(feature = "llm")]
+    fn model(&self) -> Result<String> {
+        self.model.clone().ok_or(LlmError::MissingModel)
+    }
+}
+
+#[derive(Debug)]
+pub enum LlmError {
+    /// The config has no `[llm]` provider
+    NotConfigured,
+    /// The crate was built without the `llm` feature
+    NotCompiled,
+    MissingModel,
+    /// The provider needs an API key, expected in this environment variable
+    MissingApiKey(String),
+    /// The request didn't reach the API, or its reply couldn't be read
+    #[cfg(feature = "llm")]
+    Http(Box<ureq::Error>),
+    /// The API answered with an error status
+    Api {
+        status: u16,
+        message: String,
+    },
+    /// The reply didn't have the expected shape
+    InvalidResponse(String),
+    DbError(rusqlite::Error),
+    /// Errors from providers outside this crate
+    Other(Box<dyn std::error::Error + Send + Sync>),
+}
+
+#[cfg(feature = "llm")]
+impl From<ureq::Error> for LlmError {
+    fn from(error: ureq::Error) -> Self {
+        match error {
+            ureq::Error::Status(status, response) => LlmError::Api {
+                status,
+                message: response.into_string().unwrap_or_default(),
+            },
+            error => LlmError::Http(Box::new(error)),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for LlmError {
+    fn from(error: rusqlite::Error) -> Self {
+        LlmError::DbError(error)
+    }
+}
+
+impl fmt::Display for LlmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LlmError::NotConfigured => write!(f, "No LLM provider set in the [llm] config section"),
+            LlmError::NotCompiled => write!(f, "LLM providers aren't available in this build"),
+            LlmError::MissingModel => write!(f, "No model set in the [llm] config section"),
+            LlmError::MissingApiKey(name) => write!(f, "Set {} to the API key", name),
+            #[cfg(feature = "llm")]
+            LlmError::Http(e) => write!(f, "HTTP error: {}", e),
+            LlmError::Api { status, message } => {
+                write!(f, "The LLM API answered {}: {}", status, message)
+            }
+            LlmError::InvalidResponse(message) => {
+                write!(f, "Unexpected reply from the LLM API: {}", message)
+            }
+            LlmError::DbError(e) => write!(f, "Database error: {}", e),
+            LlmError::Other(e) => write!(f, "LLM error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for LlmError {}
+
+pub type Result<T> = std::result::Result<T, LlmError>;
+
+/// What to ask a model: an optional system prompt and one user message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Completion {
+    pub system: Option<String>,
+    pub prompt: String,
+}
+
+/// A large language model that answers prompts.
+pub trait LlmProvider {
+    /// Identifies the provider and model, e.g. `openai:gpt-4o-mini`.
+    fn name(&self) -> String;
+
+    /// The model's reply to `completion`.
+    fn complete(&self, completion: &Completion) -> Result<String>;
+
+    /// How many tokens `text` takes in a prompt to this model. Providers
+    /// without a tokenizer of their own use [`estimate_tokens`].
+    fn count_tokens(&self, text: &str) -> usize {
+        estimate_tokens(text)
+    }
+}
+
+/// A rough token count that errs on the high side: about four ASCII
+/// characters per token, and a token for every other character, as kana and
+/// kanji take one or more each in common tokenizers.
+pub fn estimate_tokens(text: &str) -> usize {
+    let ascii = text.chars().filter(char::is_ascii).count();
+    ascii.div_ceil(4) + (text.chars().count() - ascii)
+}
+
+impl<P: LlmProvider + ?Sized> LlmProvider for &P {
+    fn name(&self) -> String {
+        (**self).name()
+    }
+
+    fn complete(&self, completion: &Completion) -> Result<String> {
+        (**self).complete(completion)
+    }
+    fn count_tokens(&self, text: &str) -> usize {
+        (**self).count_tokens(text)
+    }
+}
+
+impl<P: LlmProvider + ?Sized> LlmProvider for Box<P> {
+    fn name(&self) -> String {
+        (**self).name()
+    }
+
+    fn complete(&self, completion: &Completion) -> Result<String> {
+        (**self).complete(completion)
+    }
+    fn count_tokens(&self, text: &str) -> usize {
+        (**self).count_tokens(text)
+    }
+}
+
+/// Opens the provider selected by `config`.
+pub fn open_provider(config: &LlmConfig) -> Result<Box<dyn LlmProvider>> {
+    let kind = config.provider.ok_or(LlmError::NotConfigured)?;
+    #[cfg(feature = "llm")]
+    {
+        Ok(match kind {
+            ProviderKind::OpenAi => Box::new(OpenAiProvider::new(config)?),
+            ProviderKind::Anthropic => Box::new(AnthropicProvider::new(config)?),
+            ProviderKind::Ollama => Box::new(OllamaProvider::new(config)?),
+        })
+    }
+    #[cfg(not(feature = "llm"))]
+    {
+        let _ = kind;
+        Err(LlmError::NotCompiled)
+    }
+}
+
+/// Opens the provider selected by `config`, behind a reply cache in the
+/// database at `db_path` unless the config turns caching off.
+pub fn open_cached_provider(config: &LlmConfig, db_path: &Path) -> Result<Box<dyn LlmProvider>> {
+    let provider = open_provider(config)?;
+    if !config.cache {
+        return Ok(provider);
+    }
+    let db = DbHandler::open(db_path)?;
+    Ok(Box::new(CachedProvider::new(
+        provider,
+        db,
+        config.cache_ttl_days * 86_400,
+        config.cache_max_entries,
+    )))
+}
+
+// Lines shown to the model around each candidate, as in the original pipeline
+const RANKING_BEFORE: u32 = 5;
+const RANKING_AFTER: u32 = 2;
+
+const RANKING_SYSTEM: &str =
+    "You pick memorable lines from Japanese anime subtitles for language learners.";
+
+// One line of a candidate's context, as shown to the model
+#[derive(Serialize)]
+struct ContextLine<'a> {
+    id: TranscriptId,
+    /// Position relative to the candidate, which is at 0
+    ts_num: i64,
+    text: &'a str,
+}
+
+/// Asks the model for the `count` most interesting or memorable of `hits`,
+/// most memorable first. Each hit is shown with the lines around it, so the
+/// model can judge it in context.
+///
+/// Prompts are kept within `prompt_tokens`, as counted by the provider: if the
+/// candidates don't fit in one, they are split into batches, the best of each
+/// batch are picked, and a final round ranks those picks against each other.
+pub fn rank_memorable(
+    provider: &dyn LlmProvider,
+    db: &DbHandler,
+    hits: &[SearchHit],
+    count: usize,
+    prompt_tokens: usize,
+) -> Result<Vec<SearchHit>> {
+    if hits.is_empty() || count == 0 {
+        return Ok(Vec::new());
+    }
+    let mut candidates = Vec::new();
+    for hit in hits {
+        let context = db.find_context(hit.transcript_id, RANKING_BEFORE, RANKING_AFTER)?;
+        let position = context
+            .iter()
+            .position(|line| line.transcript_id == hit.transcript_id)
+            .unwrap_or(0) as i64;
+        let lines: Vec<ContextLine> = context
+            .iter()
+            .enumerate()
+            .map(|(i, line)| ContextLine {
+                id: line.transcript_id,
+                ts_num: i as i64 - position,
+                text: &line.text,
+            })
+            .collect();
+        let item = serde_json::to_string(&lines).expect("context lines serialize");
+        let tokens = provider.count_tokens(&item) + 1;
+        candidates.push(Candidate {
+            hit: hit.clone(),
+            item,
+            tokens,
+        });
+    }
+    rank_candidates(provider, candidates, count, prompt_tokens)
+}
+
+// A hit with its context as shown to the model, and what that costs in tokens
+struct Candidate {
+    hit: SearchHit,
+    /// Its context lines as a JSON array, one item of the prompt
+    item: String,
+    tokens: usize,
+}
+
+fn rank_candidates(
+    provider: &dyn LlmProvider,
+    candidates: Vec<Candidate>,
+    count: usize,
+    prompt_tokens: usize,
+) -> Result<Vec<SearchHit>> {
+    let overhead = provider.count_tokens(&ranking_prompt(count, &[]));
+    let batches = token_batches(candidates, prompt_tokens.saturating_sub(overhead));
+    if let [_] = batches.as_slice() {
+        let batch = batches.into_iter().next().expect("one batch");
+        let picks = rank_batch(provider, batch, count)?;
+        return Ok(picks.into_iter().map(|candidate| candidate.hit).collect());
+    }
+    let total: usize = batches.iter().map(Vec::len).sum();
+    let mut finalists = Vec::new();
+    for batch in batches {
+        let mut picks = rank_batch(provider, batch, count)?;
+        finalists.append(&mut picks);
+    }
+    // Each batch was too small to narrow down, so no round could narrow them further
+    if finalists.len() == total {
+        finalists.truncate(count);
+        return Ok(finalists
+            .into_iter()
+            .map(|candidate| candidate.hit)
+            .collect());
+    }
+    rank_candidates(provider, finalists, count, prompt_tokens)
+}
+
+// Splits candidates, in order, into batches of at most `budget` tokens; a
+// candidate too large for the budget on its own still gets a batch
+fn token_batches(candidates: Vec<Candidate>, budget: usize) -> Vec<Vec<Candidate>> {
+    let mut batches: Vec<Vec<Candidate>> = Vec::new();
+    let mut used = 0;
+    for candidate in candidates {
+        match batches.last_mut() {
+            Some(batch) if used + candidate.tokens <= budget => {
+                used += candidate.tokens;
+                batch.push(candidate);
+            }
+            _ => {
+                used = candidate.tokens;
+                batches.push(vec![candidate]);
+            }
+        }
+    }
+    batches
+}
+
+// The model's picks from one batch, best first
+fn rank_batch(
+    provider: &dyn LlmProvider,
+    mut batch: Vec<Candidate>,
+    count: usize,
+) -> Result<Vec<Candidate>> {
+    let items: Vec<&str> = batch
+        .iter()
+        .map(|candidate| candidate.item.as_str())
+        .collect();
+    let reply = provider.complete(&Completion {
+        system: Some(RANKING_SYSTEM.to_string()),
+        prompt: ranking_prompt(count.min(batch.len()), &items),
+    })?;
+    let hits: Vec<&SearchHit> = batch.iter().map(|candidate| &candidate.hit).collect();
+    let ids = reply_ids(&reply, &hits, count);
+    if ids.is_empty() {
+        return Err(LlmError::InvalidResponse(format!(
+            "no line ids in {:?}",
+            reply
+        )));
+    }
+    let mut picks = Vec::new();
+    for id in ids {
+        if let Some(i) = batch
+            .iter()
+            .position(|candidate| candidate.hit.transcript_id == id)
+        {
+            picks.push(batch.swap_remove(i));
+        }
+    }
+    Ok(picks)
+}
+
+// The ranking instructions followed by the items, one per line
+fn ranking_prompt(count: usize, items: &[&str]) -> String {
+    format!(
+        "Each item below is a subtitle line (ts_num 0) with the lines around it.\n\
+         Using the surrounding lines as context, choose the {} most interesting or memorable \
+         ts_num 0 lines, in descending order of interest. Reply with just their ids, \
+         separated by commas.\n\n{}",
+        count,
+        items.join("\n")
+    )
+}
+
+// Lines shown to the model around a line it explains
+const EXPLAIN_BEFORE: u32 = 3;
+const EXPLAIN_AFTER: u32 = 2;
+
+const EXPLAIN_SYSTEM: &str =
+    "You are a Japanese teacher explaining anime subtitle lines to English-speaking learners.";
+
+/// Asks the model to break a line down into its grammar points and the
+/// senses its words are used in, with the lines around it as context.
+/// None if there is no line with this id.
+pub fn explain_line(
+    provider: &dyn LlmProvider,
+    db: &DbHandler,
+    transcript_id: TranscriptId,
+) -> Result<Option<Explanation>> {
+    let context = db.find_context(transcript_id, EXPLAIN_BEFORE, EXPLAIN_AFTER)?;
+    let Some(line) = context
+        .iter()
+        .find(|line| line.transcript_id == transcript_id)
+    else {
+        return Ok(None);
+    };
+    let dialogue: Vec<String> = context
+        .iter()
+        .map(|other| {
+            let marker = if other.transcript_id == transcript_id {
+                ">"
+            } else {
+                " "
+            };
+            format!("{} {}", marker, other.text.replace('\n', " "))
+        })
+        .collect();
+    let prompt = format!(
+        "Explain the subtitle line marked > below, using the lines around it as context.\n\
+         List the grammar points it uses, each with a short explanation, then each word \
+         with its reading and the sense it has here. Be concise, and use plain text without \
+         Markdown.\n\n{}{}",
+        dialogue.join("\n"),
+        line.translation
+            .as_ref()
+            .map_or(String::new(), |translation| format!(
+                "\n\nThe subtitle translates the line as: {}",
+                translation
+            ))
+    );
+    let text = provider.complete(&Completion {
+        system: Some(EXPLAIN_SYSTEM.to_string()),
+        prompt,
+    })?;
+    Ok(Some(Explanation {
+        transcript_id,
+        model: provider.name(),
+        text: text.trim().to_string(),
+    }))
+}
+
+// The candidates' ids in the order the reply mentions them, ignoring anything else
+fn reply_ids(reply: &str, hits: &[&SearchHit], count: usize) -> Vec<TranscriptId> {
+    let candidates: HashSet<TranscriptId> = hits.iter().map(|hit| hit.transcript_id).collect();
+    let mut seen = HashSet::new();
+    reply
+        .split(|c: char| !c.is_ascii_digit())
+        .filter_map(|number| number.parse().ok().map(TranscriptId))
+        .filter(|id| candidates.contains(id) && seen.insert(*id))
+        .take(count)
+        .collect()
+}
+
+#[cfg(test)]
+pub(crate) mod test_utils {
+    use super::*;
+    use std::cell::RefCell;
+
+    // A provider that replies with canned text and remembers the prompts it got
+    pub(crate) struct FakeProvider {
+        pub reply: String,
+        pub prompts: RefCell<Vec<String>>,
+    }
+
+    impl FakeProvider {
+        pub fn new(reply: &str) -> Self {
+            FakeProvider {
+                reply: reply.to_string(),
+                prompts: RefCell::new(Vec::new()),
+            }
+        }
+    }
+
+    impl LlmProvider for FakeProvider {
+        fn name(&self) -> String {
+            "fake:model".to_string()
+        }
+
+        fn complete(&self, completion: &Completion) -> Result<String> {
+            self.prompts.borrow_mut().push(completion.prompt.clone());
+            Ok(self.reply.clone())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_utils::FakeProvider;
+    use super::*;
+    use crate::db::test_utils::test_db_with_lines;
+
+    #[test]
+    fn test_rank_memorable() {
+        let (db, ids) = test_db_with_lines(&["猫", "犬", "走った", "走る"]);
+        let hits = db.find_hits_by_ids(&[ids[1], ids[3]]).unwrap();
+        let provider = FakeProvider::new(&format!("{}, 99, {}, {}", ids[3], ids[1], ids[3]));
+
+        let ranked = rank_memorable(&provider, &db, &hits, 10, 8000).unwrap();
+        let ranked: Vec<TranscriptId> = ranked.iter().map(|hit| hit.transcript_id).collect();
+        assert_eq!(ranked, [ids[3], ids[1]]);
+        let prompt = provider.prompts.borrow()[0].clone();
+        assert!(prompt.contains("\"ts_num\":-1"));
+        assert!(prompt.contains("走った"));
+
+        let ranked = rank_memorable(&provider, &db, &hits, 1, 8000).unwrap();
+        assert_eq!(ranked.len(), 1);
+
+        let provider = FakeProvider::new("I can't decide.");
+        assert!(matches!(
+            rank_memorable(&provider, &db, &hits, 1, 8000),
+            Err(LlmError::InvalidResponse(_))
+        ));
+    }
+
+    #[test]
+    fn test_rank_memorable_in_batches() {
+        let (db, ids) = test_db_with_lines(&["猫", "犬", "走った", "走る"]);
+        let hits = db.find_hits_by_ids(&ids).unwrap();
+        let reply: Vec<String> = ids.iter().rev().map(ToString::to_string).collect();
+        let provider = FakeProvider::new(&reply.join(","));
+
+        // Room for the instructions and about two candidates per prompt
+        let overhead = estimate_tokens(&ranking_prompt(1, &[]));
+        let ranked = rank_memorable(&provider, &db, &hits, 1, overhead + 130).unwrap();
+        let prompts = provider.prompts.borrow().clone();
+        // Two batches, then a final round between their picks
+        assert_eq!(prompts.len(), 3);
+        assert!(prompts
+            .iter()
+            .all(|prompt| estimate_tokens(prompt) <= overhead + 130));
+        assert_eq!(ranked.len(), 1);
+        assert_eq!(ranked[0].transcript_id, ids[3]);
+
+        // Batches of one can't be narrowed down, so their picks are kept in order
+        let provider = FakeProvider::new(&reply.join(","));
+        let ranked = rank_memorable(&provider, &db, &hits, 2, 0).unwrap();
+        assert_eq!(provider.prompts.borrow().len(), 4);
+        assert_eq!(ranked.len(), 2);
+        assert_eq!(ranked[0].transcript_id, ids[0]);
+    }
+
+    #[test]
+    fn test_estimate_tokens() {
+        assert_eq!(estimate_tokens(""), 0);
+        assert_eq!(estimate_tokens("hello"), 2);
+        assert_eq!(estimate_tokens("猫が走った!"), 6);
+    }
+
+    #[test]
+    fn test_explain_line() {
+        let (db, ids) = test_db_with_lines(&["猫", "犬が好き", "走った"]);
+        let provider = FakeProvider::new("  が: subject marker\n好き: liked  \n");
+
+        let explanation = explain_line(&provider, &db, ids[1]).unwrap().unwrap();
+        assert_eq!(explanation.text, "が: subject marker\n好き: liked");
+        assert_eq!(explanation.model, "fake:model");
+        let prompt = provider.prompts.borrow()[0].clone();
+        assert!(prompt.contains("  猫\n> 犬が好き\n  走った"));
+
+        assert_eq!(
+            explain_line(&provider, &db, TranscriptId(999)).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_config() {
+        let config: LlmConfig =
+            toml::from_str("provider = \"anthropic\"\nmodel = \"some-model\"").unwrap();
+        assert_eq!(config.provider, Some(ProviderKind::Anthropic));
+        assert_eq!(config.max_tokens, 1024);
+        assert!(matches!(
+            open_provider(&LlmConfig::default()),
+            Err(LlmError::NotConfigured)
+        ));
+    }
+}
@@ -0,0 +1,114 @@
+// Anthropic's Messages API
+
+use super::{Completion, LlmConfig, LlmError, LlmProvider, Result};
+use serde_json::{json, Value};
+
+const DEFAULT_BASE_URL: &str = "https://api.anthropic.com/v1";
+const DEFAULT_API_KEY_ENV: &str = "ANTHROPIC_API_KEY";
+const API_VERSION: &str = "2023-06-01";
+
+/// A model behind Anthropic's `POST {base_url}/messages`.
+pub struct AnthropicProvider {
+    url: String,
+    model: String,
+    api_key: String,
+    max_tokens: u32,
+    temperature: Option<f32>,
+}
+
+impl AnthropicProvider {
+    pub fn new(config: &LlmConfig) -> Result<Self> {
+        let base_url = config.base_url.as_deref().unwrap_or(DEFAULT_BASE_URL);
+        let api_key = config.api_key(DEFAULT_API_KEY_ENV).ok_or_else(|| {
+            let name = config.api_key_env.as_deref().unwrap_or(DEFAULT_API_KEY_ENV);
+            LlmError::MissingApiKey(name.to_string())
+        })?;
+        Ok(AnthropicProvider {
+            url: format!("{}/messages", base_url.trim_end_matches('/')),
+            model: config.model()?,
+            api_key,
+            max_tokens: config.max_tokens,
+            temperature: config.temperature,
+        })
+    }
+
+    fn request_body(&self, completion: &Completion) -> Value {
+        let mut body = json!({
+            "model": self.model,
+            "max_tokens": self.max_tokens,
+            "messages": [{ "role": "user", "content": completion.prompt }],
+        });
+        if let Some(system) = &completion.system {
+            body["system"] = json!(system);
+        }
+        if let Some(temperature) = self.temperature {
+            body["temperature"] = json!(temperature);
+        }
+        body
+    }
+}
+
+// The reply's text blocks, joined
+fn parse_reply(reply: &Value) -> Result<String> {
+    let blocks = reply["content"]
+        .as_array()
+        .ok_or_else(|| LlmError::InvalidResponse(reply.to_string()))?;
+    Ok(blocks
+        .iter()
+        .filter(|block| block["type"] == "text")
+        .filter_map(|block| block["text"].as_str())
+        .collect())
+}
+
+impl LlmProvider for AnthropicProvider {
+    fn name(&self) -> String {
+        format!("anthropic:{}", self.model)
+    }
+
+    fn complete(&self, completion: &Completion) -> Result<String> {
+        let reply: Value = ureq::post(&self.url)
+            .set("x-api-key", &self.api_key)
+            .set("anthropic-version", API_VERSION)
+            .send_json(self.request_body(completion))?
+            .into_json()
+            .map_err(|e| LlmError::InvalidResponse(e.to_string()))?;
+        parse_reply(&reply)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_and_reply() {
+        let config = LlmConfig {
+            model: Some("some-model".to_string()),
+            api_key_env: Some("ANIME_SEARCH_TEST_MISSING_KEY".to_string()),
+            ..LlmConfig::default()
+        };
+        assert!(matches!(
+            AnthropicProvider::new(&config),
+            Err(LlmError::MissingApiKey(name)) if name == "ANIME_SEARCH_TEST_MISSING_KEY"
+        ));
+
+        let provider = AnthropicProvider {
+            url: format!("{}/messages", DEFAULT_BASE_URL),
+            model: "some-model".to_string(),
+            api_key: "key".to_string(),
+            max_tokens: 100,
+            temperature: None,
+        };
+        let body = provider.request_body(&Completion {
+            system: Some("Be brief.".to_string()),
+            prompt: "猫".to_string(),
+        });
+        assert_eq!(body["system"], "Be brief.");
+        assert_eq!(body["messages"][0]["content"], "猫");
+        assert_eq!(body["max_tokens"], 100);
+        assert!(body.get("temperature").is_none());
+
+        let reply = json!({ "content": [{ "type": "text", "text": "1, " }, { "type": "text", "text": "2" }] });
+        assert_eq!(parse_reply(&reply).unwrap(), "1, 2");
+    }
+}
@@ -0,0 +1,130 @@
+// A cache of replies in front of another provider, kept in the SQLite database
+
+use super::{Completion, LlmProvider, Result};
+use crate::db::DbHandler;
+use sha2::{Digest, Sha256};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Answers prompts seen before from the `llm_cache` table instead of asking
+/// the model again. Replies are keyed by a hash of the model and prompt,
+/// expire after a time to live, and the least recently used ones are
+/// dropped beyond a number of entries.
+pub struct CachedProvider<P> {
+    provider: P,
+    db: DbHandler,
+    /// Seconds a reply stays usable; 0 keeps them until evicted
+    ttl: u64,
+    max_entries: usize,
+}
+
+impl<P: LlmProvider> CachedProvider<P> {
+    pub fn new(provider: P, db: DbHandler, ttl: u64, max_entries: usize) -> Self {
+        CachedProvider {
+            provider,
+            db,
+            ttl,
+            max_entries,
+        }
+    }
+
+    // Unix seconds before which replies have expired
+    fn expired_before(&self, now: i64) -> i64 {
+        if self.ttl == 0 {
+            i64::MIN
+        } else {
+            now - self.ttl as i64
+        }
+    }
+}
+
+// The cache key of a completion by `model`
+fn cache_key(model: &str, completion: &Completion) -> String {
+    let mut hasher = Sha256::new();
+    for part in [
+        model,
+        completion.system.as_deref().unwrap_or(""),
+        &completion.prompt,
+    ] {
+        hasher.update(part.as_bytes());
+        // Separates the parts, so moving text between them changes the key
+        hasher.update([0]);
+    }
+    hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64)
+}
+
+impl<P: LlmProvider> LlmProvider for CachedProvider<P> {
+    fn name(&self) -> String {
+        self.provider.name()
+    }
+
+    fn complete(&self, completion: &Completion) -> Result<String> {
+        let model = self.provider.name();
+        let key = cache_key(&model, completion);
+        let now = now();
+        let expired_before = self.expired_before(now);
+        if let Some(reply) = self.db.cached_llm_reply(&key, expired_before, now)? {
+            return Ok(reply);
+        }
+        let reply = self.provider.complete(completion)?;
+        self.db.cache_llm_reply(&key, &model, &reply, now)?;
+        self.db.prune_llm_cache(expired_before, self.max_entries)?;
+        Ok(reply)
+    }
+
+    fn count_tokens(&self, text: &str) -> usize {
+        self.provider.count_tokens(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_utils::FakeProvider;
+    use super::*;
+    use crate::db::test_utils::test_db_with_lines;
+
+    fn completion(prompt: &str) -> Completion {
+        Completion {
+            system: None,
+            prompt: prompt.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_replies_are_cached() {
+        let (db, _) = test_db_with_lines(&[]);
+        let fake = FakeProvider::new("reply");
+        let provider = CachedProvider::new(&fake, db, 0, 1);
+
+        assert_eq!(provider.complete(&completion("a")).unwrap(), "reply");
+        assert_eq!(provider.complete(&completion("a")).unwrap(), "reply");
+        assert_eq!(fake.prompts.borrow().len(), 1);
+
+        // Only one entry is kept, so b evicts a
+        provider.complete(&completion("b")).unwrap();
+        provider.complete(&completion("a")).unwrap();
+        assert_eq!(fake.prompts.borrow().len(), 3);
+
+        let with_system = Completion {
+            system: Some("a".to_string()),
+            prompt: String::new(),
+        };
+        assert_ne!(
+            cache_key("m", &with_system),
+            cache_key("m", &completion("a"))
+        );
+        assert_ne!(
+            cache_key("m", &completion("a")),
+            cache_key("n", &completion("a"))
+        );
+    }
+}
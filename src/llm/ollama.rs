@@ -0,0 +1,94 @@
+// A local Ollama server's chat API
+
+use super::{Completion, LlmConfig, LlmError, LlmProvider, Result};
+use serde_json::{json, Value};
+
+const DEFAULT_BASE_URL: &str = "http://localhost:11434";
+
+/// A model served by Ollama, through `POST {base_url}/api/chat`.
+pub struct OllamaProvider {
+    url: String,
+    model: String,
+    max_tokens: u32,
+    temperature: Option<f32>,
+}
+
+impl OllamaProvider {
+    pub fn new(config: &LlmConfig) -> Result<Self> {
+        let base_url = config.base_url.as_deref().unwrap_or(DEFAULT_BASE_URL);
+        Ok(OllamaProvider {
+            url: format!("{}/api/chat", base_url.trim_end_matches('/')),
+            model: config.model()?,
+            max_tokens: config.max_tokens,
+            temperature: config.temperature,
+        })
+    }
+
+    fn request_body(&self, completion: &Completion) -> Value {
+        let mut messages = Vec::new();
+        if let Some(system) = &completion.system {
+            messages.push(json!({ "role": "system", "content": system }));
+        }
+        messages.push(json!({ "role": "user", "content": completion.prompt }));
+        let mut options = json!({ "num_predict": self.max_tokens });
+        if let Some(temperature) = self.temperature {
+            options["temperature"] = json!(temperature);
+        }
+        json!({
+            "model": self.model,
+            "messages": messages,
+            "stream": false,
+            "options": options,
+        })
+    }
+}
+
+fn parse_reply(reply: &Value) -> Result<String> {
+    reply["message"]["content"]
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| LlmError::InvalidResponse(reply.to_string()))
+}
+
+impl LlmProvider for OllamaProvider {
+    fn name(&self) -> String {
+        format!("ollama:{}", self.model)
+    }
+
+    fn complete(&self, completion: &Completion) -> Result<String> {
+        let reply: Value = ureq::post(&self.url)
+            .send_json(self.request_body(completion))?
+            .into_json()
+            .map_err(|e| LlmError::InvalidResponse(e.to_string()))?;
+        parse_reply(&reply)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_and_reply() {
+        assert!(matches!(
+            OllamaProvider::new(&LlmConfig::default()),
+            Err(LlmError::MissingModel)
+        ));
+        let config = LlmConfig {
+            model: Some("local-model".to_string()),
+            ..LlmConfig::default()
+        };
+        let provider = OllamaProvider::new(&config).unwrap();
+        assert_eq!(provider.url, "http://localhost:11434/api/chat");
+        let body = provider.request_body(&Completion {
+            system: None,
+            prompt: "猫".to_string(),
+        });
+        assert_eq!(body["stream"], false);
+        assert_eq!(body["messages"][0]["role"], "user");
+        assert_eq!(body["options"]["num_predict"], 1024);
+
+        let reply = json!({ "message": { "role": "assistant", "content": "1, 2" } });
+        assert_eq!(parse_reply(&reply).unwrap(), "1, 2");
+    }
+}
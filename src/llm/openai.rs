@@ -0,0 +1,102 @@
+// OpenAI-compatible chat completion APIs
+
+use super::{Completion, LlmConfig, LlmError, LlmProvider, Result};
+use serde_json::{json, Value};
+
+const DEFAULT_BASE_URL: &str = "https://api.openai.com/v1";
+const DEFAULT_API_KEY_ENV: &str = "OPENAI_API_KEY";
+
+/// A model behind `POST {base_url}/chat/completions`.
+///
+/// The API key is optional, since local OpenAI-compatible servers often don't need one.
+pub struct OpenAiProvider {
+    url: String,
+    model: String,
+    api_key: Option<String>,
+    max_tokens: u32,
+    temperature: Option<f32>,
+}
+
+impl OpenAiProvider {
+    pub fn new(config: &LlmConfig) -> Result<Self> {
+        let base_url = config.base_url.as_deref().unwrap_or(DEFAULT_BASE_URL);
+        Ok(OpenAiProvider {
+            url: format!("{}/chat/completions", base_url.trim_end_matches('/')),
+            model: config.model()?,
+            api_key: config.api_key(DEFAULT_API_KEY_ENV),
+            max_tokens: config.max_tokens,
+            temperature: config.temperature,
+        })
+    }
+
+    fn request_body(&self, completion: &Completion) -> Value {
+        let mut messages = Vec::new();
+        if let Some(system) = &completion.system {
+            messages.push(json!({ "role": "system", "content": system }));
+        }
+        messages.push(json!({ "role": "user", "content": completion.prompt }));
+        let mut body = json!({
+            "model": self.model,
+            "messages": messages,
+            "max_tokens": self.max_tokens,
+        });
+        if let Some(temperature) = self.temperature {
+            body["temperature"] = json!(temperature);
+        }
+        body
+    }
+}
+
+fn parse_reply(reply: &Value) -> Result<String> {
+    reply["choices"][0]["message"]["content"]
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| LlmError::InvalidResponse(reply.to_string()))
+}
+
+impl LlmProvider for OpenAiProvider {
+    fn name(&self) -> String {
+        format!("openai:{}", self.model)
+    }
+
+    fn complete(&self, completion: &Completion) -> Result<String> {
+        let mut request = ureq::post(&self.url);
+        if let Some(key) = &self.api_key {
+            request = request.set("Authorization", &format!("Bearer {}", key));
+        }
+        let reply: Value = request
+            .send_json(self.request_body(completion))?
+            .into_json()
+            .map_err(|e| LlmError::InvalidResponse(e.to_string()))?;
+        parse_reply(&reply)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_and_reply() {
+        let config = LlmConfig {
+            model: Some("local-model".to_string()),
+            base_url: Some("http://localhost:8081/v1/".to_string()),
+            temperature: Some(0.5),
+            ..LlmConfig::default()
+        };
+        let provider = OpenAiProvider::new(&config).unwrap();
+        assert_eq!(provider.url, "http://localhost:8081/v1/chat/completions");
+        let body = provider.request_body(&Completion {
+            system: Some("Be brief.".to_string()),
+            prompt: "猫".to_string(),
+        });
+        assert_eq!(body["messages"][0]["role"], "system");
+        assert_eq!(body["messages"][1]["content"], "猫");
+        assert_eq!(body["temperature"], 0.5);
+
+        let reply =
+            json!({ "choices": [{ "message": { "role": "assistant", "content": "1, 2" } }] });
+        assert_eq!(parse_reply(&reply).unwrap(), "1, 2");
+        assert!(parse_reply(&json!({})).is_err());
+    }
+}
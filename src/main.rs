@@ -42,66 +42,2567 @@ determening that. Return just the ids in your output.
 6. Search the transcripts table for those 10 ids and return the full text of the matching lines.
 */
 
-mod db;
-mod srt_parser;
-use db::DbHandler;
-use rusqlite::Result;
-use srt_parser::{process_srt_directory, EpisodeNameMethod, EpisodeNumberMethod};
-use std::path::Path;
-use std::time::Instant;
-
-fn main() -> Result<()> {
+use anime_search::backend::{open_backend, update_index, BackendConfig, BackendKind};
+use anime_search::bench::{run_bench, BENCH_QUERIES};
+use anime_search::catchphrases::{character_catchphrases, CatchphraseOptions};
+use anime_search::collocations::{collocations, Collocation, CollocationOptions};
+use anime_search::concordance::{columns, kwic, KwicLine, Matcher};
+use anime_search::config::Config;
+use anime_search::context::context_window;
+use anime_search::daily::{daily_sentence, DailyOptions, Date, JlptLevel};
+use anime_search::db::{
+    episode_label, ApiScope, ConflictPolicies, CsvOutput, DbHandler, IntegrityReport, LyricsFilter,
+    LyricsOptions, MediaFile, NewLoggedQuery, SearchHit, ShowId, ShowType, Source, TimeRangeConfig,
+    TranscriptId, WordQuery,
+};
+use anime_search::difficulty;
+use anime_search::error::WithPath;
+use anime_search::explain::{explain_query, QueryExplanation};
+use anime_search::export::{
+    filter_sentences, lines_with_any_word, read_word_list, write_anki_bookmarks,
+    write_sentence_pairs, SentenceFilter,
+};
+use anime_search::fetch::{fetch_show, parse_url_list, FetchSource};
+use anime_search::furigana::{add_furigana, FuriganaFormat};
+use anime_search::fuzzy::correct_query;
+use anime_search::grammar::{find_grammar_pattern, GRAMMAR_PATTERNS};
+use anime_search::hooks::IngestHooks;
+use anime_search::index_version::{index_changes, IndexVersion, VersionChange};
+use anime_search::ingest::{
+    apply_simultaneous_cues, find_duplicates, insert_entries, mark_movies, set_show_types,
+    DuplicatePolicy, IngestSummary, IngestedLines, ResumeState, SimultaneousCues,
+};
+use anime_search::llm::{explain_line, open_cached_provider, open_provider};
+use anime_search::mcp::McpServer;
+use anime_search::media::{episodes_to_match, find_media_files, match_media, read_media_csv};
+use anime_search::overlap::{compare_vocabulary, OverlapOptions};
+use anime_search::pitch_accent::annotate_pitch_accent;
+use anime_search::player::{play, Playback};
+use anime_search::query_log::{latency_micros, log_search};
+use anime_search::ranking::{open_ranker, LearnabilityScorer, RankerKind, SortOrder};
+use anime_search::sample::{sample, SampleOptions};
+use anime_search::search::{
+    filter_complexity, group_hits, parse_query, search, search_grammar, search_kanji, search_regex,
+    ComplexityFilter,
+};
+use anime_search::srt_parser::{
+    find_original_file, is_translation_file, process_srt_directory_filtered, process_srt_file,
+    EpisodeNameMethod, EpisodeNumberMethod, Language, LineEnding, SrtEntry, Subtitles,
+};
+use anime_search::tokenizer::{
+    hiragana_to_katakana, open_tokenizer, DictionaryKind, JapaneseTokenizer, TokenizerKind,
+};
+use anime_search::transcribe::transcribe_show;
+use anime_search::translate::{open_translator, translate_lines};
+use anime_search::watch::watch_srt_files;
+use clap::{Parser, Subcommand};
+use regex::Regex;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// How long the watcher waits for file events to stop before ingesting a batch
+const WATCH_QUIET_PERIOD: Duration = Duration::from_secs(2);
+
+// Number of files `ingest` saves at a time, each batch in its own transaction
+const INGEST_BATCH_SIZE: usize = 200;
+
+// Whose watch history is used when no --user is given
+const DEFAULT_USER: &str = "default";
+
+#[derive(Parser)]
+#[command(about = "Index and search Japanese subtitle transcripts")]
+struct Cli {
+    /// Path to the TOML config file
+    #[arg(long, default_value = "config.toml")]
+    config: PathBuf,
+    /// Path to the SQLite database
+    #[arg(long, default_value = "transcripts.db")]
+    db: PathBuf,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Parse every subtitle file under a directory and add it to the database
+    Ingest {
+        /// A directory of show folders, which may also hold one archive (.zip, or .7z
+        /// and .rar with the 7z and unrar commands) per show, or a single archive;
+        /// archives inside a show folder count as that show's
+        #[arg(default_value = "data/transcripts_raw")]
+        root_dir: PathBuf,
+        /// Also write the per-file parse statistics to this JSON file
+        #[arg(long, value_name = "PATH")]
+        report: Option<PathBuf>,
+        /// Skip files already ingested by an earlier (possibly interrupted) run,
+        /// unless they changed since; those replace the lines they were ingested as
+        #[arg(long)]
+        resume: bool,
+        /// Put the ingested shows in this corpus, e.g. `drama` or `podcasts`,
+        /// so their words don't count towards the statistics of other content
+        #[arg(long)]
+        corpus: Option<String>,
+        /// Only ingest files in this language (ja, en, zh or ko), told by their text and
+        /// file name, e.g. to leave out the Chinese variants of episodes
+        #[arg(long, value_name = "LANG")]
+        lang: Option<Language>,
+        /// What to do with files whose subtitles were already ingested from another
+        /// path, e.g. the same download in two show folders: skip or warn
+        #[arg(long, default_value = "skip")]
+        duplicates: DuplicatePolicy,
+        /// What to do with rows whose key is taken, per table: e.g. `lines=rename` keeps
+        /// lines with the same episode and timestamps as another (ignore, replace, error
+        /// or rename; tables are shows, episodes and lines, and all default to ignore)
+        #[arg(long, value_name = "POLICIES")]
+        on_conflict: Option<ConflictPolicies>,
+        /// What to do with cues shown at the same time, e.g. two speakers' lines as
+        /// separate cues: keep them as separate lines, or merge them into one
+        #[arg(long, default_value = "keep")]
+        simultaneous: SimultaneousCues,
+    },
+    /// Search transcript lines by words (supports `word NEAR/N word` and `speaker:NAME`)
+    Search {
+        query: String,
+        /// Treat the query as a regular expression over the line text
+        #[arg(long, conflicts_with_all = ["kanji", "grammar"])]
+        regex: bool,
+        /// Treat the query as a grammar pattern id, e.g. N3:ばかりに
+        #[arg(long, conflicts_with = "kanji")]
+        grammar: bool,
+        /// Match the query as a character string, e.g. a single kanji
+        #[arg(long)]
+        kanji: bool,
+        /// With --kanji, require the match to lie inside a single token
+        #[arg(long, requires = "kanji")]
+        within_token: bool,
+        /// Annotate each hit with pitch accents (see `import-accents`)
+        #[arg(long)]
+        accent: bool,
+        /// Search engine for word queries: sqlite, fts5 or tantivy (default from config)
+        #[arg(long, conflicts_with_all = ["regex", "grammar", "kanji"])]
+        backend: Option<BackendKind>,
+        /// Maximum number of hits from the ranked fts5 and tantivy backends
+        #[arg(long, default_value_t = 100)]
+        limit: usize,
+        /// Print hits with readings added, as `brackets` (漢字[かんじ]) or `html` (<ruby>)
+        #[arg(long, value_name = "FORMAT")]
+        furigana: Option<FuriganaFormat>,
+        /// Match words only as written (おもしろい won't find 面白い); sqlite backend only
+        #[arg(long, conflicts_with_all = ["regex", "grammar", "kanji"])]
+        exact_script: bool,
+        /// Instead of searching, show how the word index runs the query: its tokens,
+        /// each word's posting list, the intersection order, and the final SQL and plan
+        #[arg(long, conflicts_with_all = ["regex", "grammar", "kanji", "fuzzy"])]
+        explain: bool,
+        /// Also print the surrounding lines of each hit (window set in `[context]`)
+        #[arg(long)]
+        context: bool,
+        /// With --context, extend the window to whole sentences
+        #[arg(long, requires = "context")]
+        sentences: bool,
+        /// Group hits by show and episode, with a hit count for each
+        #[arg(long)]
+        group: bool,
+        /// Print hits as a concordance, aligned on the match with this many
+        /// columns of context on each side (a kanji takes two)
+        #[arg(
+            long,
+            value_name = "WIDTH",
+            num_args = 0..=1,
+            default_missing_value = "30",
+            conflicts_with_all = ["group", "furigana", "accent"]
+        )]
+        kwic: Option<usize>,
+        /// If nothing matches, retry with kana words corrected for typos (づ/ず, を/お, ...)
+        #[arg(long, conflicts_with_all = ["regex", "grammar", "kanji"])]
+        fuzzy: bool,
+        /// Only hits from episodes the user marked as watched, to avoid spoilers
+        #[arg(long)]
+        watched: bool,
+        /// Whose watch history --watched and --sort learnable use
+        #[arg(long, default_value = DEFAULT_USER)]
+        user: String,
+        /// Order hits as found (relevance), or easiest to learn from first (learnable):
+        /// short lines of known and common words using the query's usual collocations.
+        /// Known words are those met often enough in the user's watched episodes
+        #[arg(long, default_value = "relevance")]
+        sort: SortOrder,
+        /// Skip lines starting in the first SECONDS of each episode, e.g. its opening
+        /// song, instead of what `[time_range]` sets for each show
+        #[arg(long, value_name = "SECONDS")]
+        skip_start: Option<u32>,
+        /// Skip lines ending in the last SECONDS of each episode, e.g. its ending song,
+        /// instead of what `[time_range]` sets for each show
+        #[arg(long, value_name = "SECONDS")]
+        skip_end: Option<u32>,
+        /// Keep (include), leave out (exclude) or only show (only) the lines flagged as
+        /// opening and ending lyrics by `detect-lyrics`
+        #[arg(long, default_value = "include")]
+        lyrics: LyricsFilter,
+        /// Only lines of at most this many tokens, punctuation not counted
+        #[arg(long, value_name = "N")]
+        max_tokens: Option<usize>,
+        /// Only lines of at most this many characters
+        #[arg(long, value_name = "N")]
+        max_chars: Option<usize>,
+        /// Only lines with at most this many unknown words: words not met often enough
+        /// in the user's watched episodes (see --sort) nor listed in --known
+        #[arg(long, value_name = "N")]
+        max_unknown: Option<usize>,
+        /// With --sort learnable or --max-unknown, also count the words in this file
+        /// (one per line) as known
+        #[arg(long, value_name = "FILE")]
+        known: Option<PathBuf>,
+        /// Also find lines whose notes (see `note`) contain the query
+        #[arg(long)]
+        notes: bool,
+        /// Only hits tagged with this tag (see `tag`); repeat to require several
+        #[arg(long = "tag", value_name = "TAG")]
+        tags: Vec<String>,
+        /// Only hits from shows in this corpus (see `corpus`)
+        #[arg(long)]
+        corpus: Option<String>,
+        /// Only hits from shows of this type (anime, drama, movie, variety or podcast;
+        /// see `[show_types]` in the config)
+        #[arg(long, value_name = "TYPE")]
+        show_type: Option<ShowType>,
+        /// Machine-translate hits without a subtitle translation, and any --context
+        /// lines, with the translator set in `[translation]`; translations are cached
+        #[arg(long)]
+        translate: bool,
+    },
+    /// Print random lines containing a word, for varied example sentences
+    Sample {
+        query: String,
+        /// Number of lines to print
+        #[arg(long, short = 'n', default_value_t = 10)]
+        count: usize,
+        /// Favor shorter lines
+        #[arg(long)]
+        short: bool,
+        /// Favor lines from this show (can be repeated)
+        #[arg(long = "prefer-show", value_name = "SHOW")]
+        preferred_shows: Vec<String>,
+    },
+    /// Ask the LLM set in `[llm]` to explain a line's grammar and word senses, and
+    /// keep the notes for `export-sentences --explanations`
+    Explain {
+        transcript_id: i64,
+        /// Ask again even if the line was already explained
+        #[arg(long)]
+        refresh: bool,
+    },
+    /// Pick the most memorable lines containing a word, by asking the LLM set in
+    /// `[llm]` or, without one, by heuristics
+    Memorable {
+        query: String,
+        /// Number of lines to pick
+        #[arg(short = 'n', long, default_value_t = 10)]
+        count: usize,
+        /// Hits to rank, the first ones found
+        #[arg(long, default_value_t = 50)]
+        candidates: usize,
+        /// auto, llm or heuristic (default from `[ranking]`); heuristic gives
+        /// the same lines every run
+        #[arg(long)]
+        ranker: Option<RankerKind>,
+    },
+    /// Print the sentence of the day with its context, the same all day
+    Daily {
+        /// Pick for this date (YYYY-MM-DD) instead of today (UTC)
+        #[arg(long)]
+        date: Option<Date>,
+        /// Only lines at this JLPT level or easier (see `import-jlpt`), e.g. N4
+        #[arg(long)]
+        level: Option<JlptLevel>,
+        /// Print JSON, e.g. for a webhook
+        #[arg(long)]
+        json: bool,
+    },
+    /// Export lines as tab-separated sentence pairs (Tatoeba layout) with where each is from
+    ExportSentences {
+        /// Only lines with at least one of the words in this file (one per line)
+        #[arg(long, value_name = "FILE")]
+        words: Option<PathBuf>,
+        /// Fewest characters per line
+        #[arg(long)]
+        min_length: Option<usize>,
+        /// Most characters per line
+        #[arg(long)]
+        max_length: Option<usize>,
+        /// Only lines with an English translation
+        #[arg(long)]
+        translated_only: bool,
+        /// Add a column with each line's notes from `explain`, for Anki cards
+        #[arg(long)]
+        explanations: bool,
+        /// Write to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Load a tab-separated JLPT word list (word and level, e.g. N5, per line)
+    ImportJlpt { path: PathBuf },
+    /// Load a tab-separated pitch-accent dictionary (word, reading, accent per line)
+    ImportAccents { path: PathBuf },
+    /// Download a show's subtitles from jimaku.cc (or a list of URLs) into its
+    /// folder in the subtitle directory, and ingest them
+    Fetch {
+        /// The show to look up on jimaku.cc, which also names its folder
+        show: String,
+        /// Download the URLs listed in this file, one per line, instead
+        #[arg(long, value_name = "PATH")]
+        urls: Option<PathBuf>,
+        #[arg(long, default_value = "data/transcripts_raw")]
+        root_dir: PathBuf,
+        /// Put the show in this corpus (see `ingest --corpus`)
+        #[arg(long)]
+        corpus: Option<String>,
+        /// Only ingest files in this language (ja, en, zh or ko)
+        #[arg(long, value_name = "LANG")]
+        lang: Option<Language>,
+    },
+    /// Transcribe video or audio files without subtitles with Whisper (see the
+    /// [transcribe] config section) into a show's folder in the subtitle directory,
+    /// and ingest them
+    Transcribe {
+        /// The show the files are of, which also names its folder
+        show: String,
+        /// Video or audio files, or directories of them
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+        #[arg(long, default_value = "data/transcripts_raw")]
+        root_dir: PathBuf,
+        /// Also transcribe files with subtitles next to them, or transcribed before
+        #[arg(long)]
+        force: bool,
+        /// Put the show in this corpus (see `ingest --corpus`)
+        #[arg(long)]
+        corpus: Option<String>,
+    },
+    /// Keep watching a directory and ingest subtitle files as they appear or change
+    Watch {
+        #[arg(default_value = "data/transcripts_raw")]
+        root_dir: PathBuf,
+    },
+    /// Parse one subtitle file again and replace the episode's lines with it
+    Reingest {
+        file: PathBuf,
+        /// Directory the file's show folder lives in, used to number episodes
+        #[arg(long, default_value = "data/transcripts_raw")]
+        root_dir: PathBuf,
+    },
+    /// Rebuild the word and search indexes from the stored lines, e.g. after changing
+    /// the dictionary or [tokenizer] settings
+    Reindex,
+    /// Delete a show, or one of its episodes, with all of its lines
+    Delete {
+        show: String,
+        /// Delete only this episode instead of the whole show
+        #[arg(long)]
+        episode: Option<i32>,
+        #[arg(long, default_value_t = 1, requires = "episode")]
+        season: i32,
+    },
+    /// Database maintenance
+    Db {
+        #[command(subcommand)]
+        command: DbCommand,
+    },
+    /// Bookmark lines worth keeping, with a note and tag, and export them to Anki
+    Bookmark {
+        #[arg(long, default_value = DEFAULT_USER)]
+        user: String,
+        #[command(subcommand)]
+        command: BookmarkCommand,
+    },
+    /// Tag lines, e.g. `funny` or `grammar:〜わけだ`, to find them again with `search --tag`
+    Tag {
+        #[command(subcommand)]
+        command: TagCommand,
+    },
+    /// Attach free-text notes to lines or shows, and search them
+    Note {
+        #[command(subcommand)]
+        command: NoteCommand,
+    },
+    /// Group shows into corpora, e.g. `anime`, `drama` or `podcasts`, searched and counted apart
+    Corpus {
+        #[command(subcommand)]
+        command: CorpusCommand,
+    },
+    /// Save queries under a name to run them again later
+    SavedSearch {
+        #[arg(long, default_value = DEFAULT_USER)]
+        user: String,
+        #[command(subcommand)]
+        command: SavedSearchCommand,
+    },
+    /// Manage the API keys `serve --require-api-key` accepts
+    ApiKey {
+        #[command(subcommand)]
+        command: ApiKeyCommand,
+    },
+    /// Link episodes to their video or audio files, by file name or from a CSV
+    MapMedia {
+        /// Directory to search for media files named like the episodes
+        #[arg(required_unless_present = "csv", conflicts_with = "csv")]
+        media_dir: Option<PathBuf>,
+        /// Read explicit mappings with the header show,season,episode,path instead
+        /// (episode left empty for a movie)
+        #[arg(long)]
+        csv: Option<PathBuf>,
+        /// Also look again for episodes that already have a media file
+        #[arg(long)]
+        remap: bool,
+        /// Print the mappings without saving them
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Play a line in mpv, from a little before it starts (see `map-media` and `[player]`)
+    Play {
+        transcript_id: i64,
+        /// Seconds to start before the line, instead of the configured lead_in
+        #[arg(long)]
+        lead_in: Option<f64>,
+    },
+    /// Rewrite a subtitle file (SRT, ASS, LRC, TTML or YouTube json3/srv3) as cleaned, renumbered
+    /// SRT or WebVTT
+    Convert {
+        input: PathBuf,
+        /// Written as WebVTT if it ends in .vtt, otherwise as SRT
+        output: PathBuf,
+        /// Use Windows (CRLF) line endings
+        #[arg(long)]
+        crlf: bool,
+    },
+    /// Print corpus-wide numbers: shows, episodes, lines, words and hours of dialogue
+    Stats {
+        /// Most frequent words to list
+        #[arg(long, default_value_t = 10)]
+        top: usize,
+        /// Recompute the numbers instead of using those cached at the last ingestion
+        #[arg(long)]
+        refresh: bool,
+        /// List dialogue density per episode instead
+        #[arg(long)]
+        episodes: bool,
+        /// With --episodes, only the episodes of this show
+        #[arg(long, requires = "episodes")]
+        show: Option<String>,
+        /// With --episodes, list the most dialogue-heavy episodes first
+        #[arg(long, requires = "episodes")]
+        by_density: bool,
+        /// Only the shows in this corpus, with word frequencies counted over them alone
+        #[arg(long, conflicts_with_all = ["episodes", "refresh"])]
+        corpus: Option<String>,
+        /// Only the shows of this type, counted like those of a corpus
+        #[arg(long, value_name = "TYPE", conflicts_with_all = ["episodes", "refresh", "corpus"])]
+        show_type: Option<ShowType>,
+        #[arg(long)]
+        json: bool,
+    },
+    /// Rank shows by difficulty, easiest first, or list the difficulty of episodes
+    Difficulty {
+        /// Only shows easier than this one
+        #[arg(long, conflicts_with = "harder_than")]
+        easier_than: Option<String>,
+        /// Only shows harder than this one
+        #[arg(long)]
+        harder_than: Option<String>,
+        /// List episodes instead, optionally only those of one show
+        #[arg(long, conflicts_with_all = ["easier_than", "harder_than"])]
+        episodes: bool,
+        #[arg(long, requires = "episodes")]
+        show: Option<String>,
+        #[arg(long)]
+        json: bool,
+    },
+    /// Flag the lines recurring at nearly the same time across a show's episodes, such
+    /// as its opening and ending lyrics, so `search --lyrics` can leave them out
+    DetectLyrics {
+        /// Only this show instead of every show
+        show: Option<String>,
+        /// Episodes a line must recur in
+        #[arg(long, default_value_t = 3)]
+        min_episodes: usize,
+        /// Seconds its start may differ by between episodes
+        #[arg(long, value_name = "SECONDS", default_value_t = 60)]
+        max_drift: u32,
+        /// Shortest line to flag, in characters; short replies recur by chance
+        #[arg(long, default_value_t = 4)]
+        min_length: usize,
+    },
+    /// Mark a show, or one of its episodes, as watched
+    Watched {
+        show: String,
+        /// Only this episode instead of the whole show
+        #[arg(long)]
+        episode: Option<i32>,
+        #[arg(long, default_value_t = 1, requires = "episode")]
+        season: i32,
+        /// Remove from the watch history instead
+        #[arg(long)]
+        unmark: bool,
+        #[arg(long, default_value = DEFAULT_USER)]
+        user: String,
+    },
+    /// Show the watched episodes and how many words were encountered often enough
+    Progress {
+        #[arg(long, default_value = DEFAULT_USER)]
+        user: String,
+        /// Encounters for a word to count
+        #[arg(long, default_value_t = 5)]
+        min_count: i64,
+        /// Also list those words
+        #[arg(long)]
+        words: bool,
+    },
+    /// Compare the vocabulary of two shows: what they share and what B adds after A
+    Overlap {
+        show_a: String,
+        show_b: String,
+        /// Occurrences in show A for a word to count as known
+        #[arg(long, default_value_t = 1)]
+        min_count: i64,
+        /// Number of show B's new words to list
+        #[arg(long, default_value_t = 20)]
+        top: usize,
+        #[arg(long)]
+        json: bool,
+    },
+    /// List the most frequent token sequences containing a word, e.g. what follows 気が
+    Collocations {
+        word: String,
+        #[arg(long, default_value_t = 2)]
+        min_length: usize,
+        #[arg(long, default_value_t = 4)]
+        max_length: usize,
+        #[arg(short = 'n', long, default_value_t = 20)]
+        limit: usize,
+        #[arg(long)]
+        json: bool,
+    },
+    /// List each character's catchphrases and most frequent lines, compared to the
+    /// rest of the show; needs lines with speakers (ASS actor fields or names in brackets)
+    Catchphrases {
+        show: String,
+        /// Only this character
+        #[arg(long)]
+        speaker: Option<String>,
+        #[arg(long, default_value_t = 2)]
+        min_length: usize,
+        #[arg(long, default_value_t = 4)]
+        max_length: usize,
+        /// Times a character must say a sequence or line for it to be listed
+        #[arg(long, default_value_t = 3)]
+        min_count: usize,
+        /// Characters with fewer lines are left out
+        #[arg(long, default_value_t = 10)]
+        min_lines: usize,
+        /// Number of sequences and of lines per character
+        #[arg(short = 'n', long, default_value_t = 10)]
+        limit: usize,
+        #[arg(long)]
+        json: bool,
+    },
+    /// List the proper nouns (names, places) characteristic of each show
+    Glossary {
+        /// Only this show's glossary
+        show: Option<String>,
+        /// Find them again in the word index first, and rewrite the tokenizer's
+        /// glossary_dictionary_path so the next ingest keeps them together
+        #[arg(long)]
+        extract: bool,
+        /// Occurrences in a show for a term to count
+        #[arg(long, default_value_t = 3, requires = "extract")]
+        min_count: i64,
+        #[arg(long)]
+        json: bool,
+    },
+    /// List the most frequent indexed words starting with a prefix (spelling or reading)
+    Suggest {
+        prefix: String,
+        #[arg(short = 'n', long, default_value_t = 10)]
+        limit: usize,
+    },
+    /// Time a suite of representative queries on this database, with latency
+    /// percentiles and, for the sqlite backend, query plans and rows scanned
+    Bench {
+        /// Runs per query
+        #[arg(long, default_value_t = 20)]
+        runs: usize,
+        /// Benchmark these queries instead of the bundled suite
+        #[arg(long = "query", value_name = "QUERY")]
+        queries: Vec<String>,
+        /// sqlite, fts5 or tantivy (default from config)
+        #[arg(long)]
+        backend: Option<BackendKind>,
+        /// Maximum number of hits from the ranked fts5 and tantivy backends
+        #[arg(long, default_value_t = 100)]
+        limit: usize,
+        /// Also print each query's plan
+        #[arg(long)]
+        plan: bool,
+        #[arg(long)]
+        json: bool,
+    },
+    /// List the searches recorded while `[query_log]` is enabled
+    QueryLog {
+        /// Only searches that took at least this many milliseconds, slowest first
+        #[arg(long, value_name = "MILLIS")]
+        slow: Option<u64>,
+        /// Number of searches to list
+        #[arg(long, short = 'n', default_value_t = 20)]
+        limit: usize,
+        /// Delete every recorded search instead
+        #[arg(long, conflicts_with_all = ["slow", "json"])]
+        clear: bool,
+        #[arg(long)]
+        json: bool,
+    },
+    /// List the built-in grammar patterns usable with `search --grammar`
+    Grammar,
+    /// Serve `search_corpus` and `get_context` tools to an LLM assistant over MCP (stdio)
+    Mcp,
+    /// Serve search over HTTP, with an OpenSearch description for browsers
+    #[cfg(feature = "server")]
+    Serve {
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        addr: std::net::SocketAddr,
+        /// The URL browsers reach the server at, if not the Host they send
+        #[arg(long)]
+        public_url: Option<String>,
+        /// Open the search page in the default browser
+        #[arg(long)]
+        open: bool,
+        /// Only answer requests with a key from `api-key create`, rate limited per key.
+        /// Ingestion always needs one
+        #[arg(long)]
+        require_api_key: bool,
+        /// Let `POST /ingest` read subtitle files under this directory
+        #[arg(long)]
+        ingest_root: Option<PathBuf>,
+    },
+    /// Run a Discord bot answering `!jsearch <query>`, with its token in DISCORD_TOKEN
+    #[cfg(feature = "discord")]
+    Discord {
+        /// Messages starting with this are searches
+        #[arg(long, default_value = "!jsearch")]
+        prefix: String,
+        /// Hits shown per reply
+        #[arg(long, default_value_t = 3)]
+        max_hits: usize,
+    },
+    /// Serve search, context and ingestion over gRPC (see proto/anime_search.proto)
+    #[cfg(feature = "grpc")]
+    Grpc {
+        #[arg(long, default_value = "127.0.0.1:50051")]
+        addr: std::net::SocketAddr,
+        /// Let `Ingest` read subtitle files under this directory
+        #[arg(long)]
+        ingest_root: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum DbCommand {
+    /// Prune unused words, rebuild indexes and refresh query planner statistics
+    Optimize {
+        /// Also VACUUM to shrink the file (rewrites the whole database)
+        #[arg(long)]
+        vacuum: bool,
+    },
+    /// Look for inconsistent data and print what to do about it
+    Check {
+        /// Delete orphaned word index entries instead of only reporting them
+        #[arg(long)]
+        fix: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum ApiKeyCommand {
+    /// Issue a key and print its secret, which can't be shown again
+    Create {
+        /// Who the key is for
+        name: String,
+        /// read, or ingest to also allow POST /ingest
+        #[arg(long, default_value = "read")]
+        scope: ApiScope,
+        /// Requests allowed per minute
+        #[arg(long, default_value_t = 60)]
+        rate_limit: u32,
+    },
+    /// List the issued keys
+    List,
+    /// Delete a key
+    Revoke { name: String },
+}
+
+#[derive(Subcommand)]
+enum BookmarkCommand {
+    /// Bookmark a line by its id (shown in brackets in search results)
+    Add {
+        transcript_id: i64,
+        #[arg(long)]
+        note: Option<String>,
+        #[arg(long)]
+        tag: Option<String>,
+    },
+    /// Remove a bookmark
+    Remove { transcript_id: i64 },
+    /// List the bookmarked lines
+    List {
+        /// Only bookmarks with this tag
+        #[arg(long)]
+        tag: Option<String>,
+        #[arg(long)]
+        json: bool,
+    },
+    /// Write the bookmarked lines as an Anki import file (tab-separated, tags in column 5)
+    Export {
+        /// Only bookmarks with this tag
+        #[arg(long)]
+        tag: Option<String>,
+        /// Write to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum TagCommand {
+    /// Tag lines by their ids (shown in brackets in search results)
+    Add {
+        tag: String,
+        #[arg(required = true)]
+        transcript_ids: Vec<i64>,
+    },
+    /// Remove a tag from lines
+    Remove {
+        tag: String,
+        #[arg(required = true)]
+        transcript_ids: Vec<i64>,
+    },
+    /// List the tags in use, with their number of lines
+    List,
+    /// Print the lines with a tag
+    Show { tag: String },
+}
+
+#[derive(Subcommand)]
+enum NoteCommand {
+    /// Add a note to a line, by its id, or to a show
+    Add {
+        text: String,
+        #[arg(
+            long,
+            value_name = "TRANSCRIPT_ID",
+            required_unless_present = "show",
+            conflicts_with = "show"
+        )]
+        line: Option<i64>,
+        #[arg(long)]
+        show: Option<String>,
+    },
+    /// Delete a note by its id
+    Delete { id: i64 },
+    /// List the notes on a line or a show
+    List {
+        #[arg(
+            long,
+            value_name = "TRANSCRIPT_ID",
+            required_unless_present = "show",
+            conflicts_with = "show"
+        )]
+        line: Option<i64>,
+        #[arg(long)]
+        show: Option<String>,
+    },
+    /// Print the notes containing some text, on lines and shows alike
+    Search { text: String },
+}
+
+#[derive(Subcommand)]
+enum CorpusCommand {
+    /// Move shows into a corpus
+    Set {
+        corpus: String,
+        #[arg(required = true)]
+        shows: Vec<String>,
+    },
+    /// List the corpora with their number of shows
+    List,
+    /// List the shows of a corpus
+    Shows { corpus: String },
+}
+
+#[derive(Subcommand)]
+enum SavedSearchCommand {
+    /// Save a query, replacing any saved under the same name
+    Save {
+        name: String,
+        query: String,
+        /// Run the query as a regular expression
+        #[arg(long, conflicts_with_all = ["kanji", "grammar"])]
+        regex: bool,
+        /// Run the query as a character string
+        #[arg(long, conflicts_with = "grammar")]
+        kanji: bool,
+        /// Run the query as a grammar pattern id
+        #[arg(long)]
+        grammar: bool,
+    },
+    /// List the saved queries
+    List,
+    /// Run a saved query and print its hits
+    Run { name: String },
+    /// Delete a saved query
+    Delete { name: String },
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let cli = Cli::parse();
+    let config = Config::load_or_default(&cli.config)?;
+
+    match cli.command {
+        Command::Ingest {
+            root_dir,
+            report,
+            resume,
+            corpus,
+            lang,
+            duplicates,
+            on_conflict,
+            simultaneous,
+        } => ingest(
+            &config,
+            &cli.db,
+            &root_dir,
+            &IngestOptions {
+                report_path: report.as_deref(),
+                resume,
+                corpus: corpus.as_deref(),
+                lang,
+                duplicates,
+                conflicts: on_conflict.unwrap_or_default(),
+                simultaneous,
+            },
+        ),
+        Command::Fetch {
+            show,
+            urls,
+            root_dir,
+            corpus,
+            lang,
+        } => {
+            let source = match urls {
+                Some(path) => FetchSource::Urls(parse_url_list(&std::fs::read_to_string(path)?)),
+                None => FetchSource::Jimaku,
+            };
+            let fetched = fetch_show(&config.fetch, &show, &source, &root_dir)?;
+            for name in &fetched.skipped {
+                println!(
+                    "Skipped {}: not a subtitle file or archive that can be ingested.",
+                    name
+                );
+            }
+            println!(
+                "Downloaded {} files to {:?}.",
+                fetched.files.len(),
+                fetched.dir
+            );
+            ingest_show_dir(
+                &config,
+                &cli.db,
+                &root_dir,
+                &fetched.dir,
+                lang,
+                corpus.as_deref(),
+                &fetched.urls,
+            )
+        }
+        Command::Transcribe {
+            show,
+            files,
+            root_dir,
+            force,
+            corpus,
+        } => {
+            let transcribed = transcribe_show(&config.transcribe, &show, &files, &root_dir, force)?;
+            for path in &transcribed.skipped {
+                println!("Skipped {:?}: it has subtitles already.", path);
+            }
+            println!(
+                "Transcribed {} files to {:?}.",
+                transcribed.files.len(),
+                transcribed.dir
+            );
+            ingest_show_dir(
+                &config,
+                &cli.db,
+                &root_dir,
+                &transcribed.dir,
+                None,
+                corpus.as_deref(),
+                &HashMap::new(),
+            )
+        }
+        Command::Watch { root_dir } => watch(&config, &cli.db, &root_dir),
+        Command::Reingest { file, root_dir } => {
+            let mut db = DbHandler::open(&cli.db)?;
+            let mut entry = process_srt_file(
+                &file,
+                &root_dir,
+                &EpisodeNumberMethod::FromFileOrder,
+                &EpisodeNameMethod::FromEpisodeNumber,
+            )?;
+            report_movies(&config, &db, &root_dir, std::slice::from_mut(&mut entry))?;
+            println!(
+                "{}",
+                IngestSummary::from_entries(std::slice::from_ref(&entry))
+            );
+            let ingested =
+                ingest_entries(&config, &mut db, &root_dir, vec![entry], true, None, None)?;
+            db.refresh_corpus_stats()?;
+            println!(
+                "Replaced episode with {} lines.",
+                ingested.transcript_ids.len()
+            );
+            Ok(())
+        }
+        Command::Reindex => {
+            let mut db = DbHandler::open(&cli.db)?;
+            let lines = IngestedLines {
+                episode_ids: db.all_episode_ids()?,
+                transcript_ids: db.all_transcript_ids()?,
+                ..IngestedLines::default()
+            };
+            db.atomically(|db| {
+                db.clear_word_index()?;
+                index_lines(&config, db, &lines, true)
+            })?;
+            db.refresh_corpus_stats()?;
+            println!(
+                "Reindexed {} lines from {} episodes.",
+                lines.transcript_ids.len(),
+                lines.episode_ids.len()
+            );
+            Ok(())
+        }
+        Command::Delete {
+            show,
+            episode,
+            season,
+        } => {
+            let mut db = DbHandler::open(&cli.db)?;
+            let deleted = match episode {
+                Some(episode) => db
+                    .delete_episode(&show, season, Some(episode))?
+                    .map(|id| vec![id]),
+                None => db.delete_show(&show)?,
+            };
+            let Some(episode_ids) = deleted else {
+                return Err(format!("Nothing to delete for {:?}", show).into());
+            };
+            // The tantivy index lives outside the database
+            if config.search.backend == BackendKind::Tantivy {
+                let tokenizer = Arc::new(JapaneseTokenizer::from_config(&config.tokenizer)?);
+                let mut backend = open_backend(&config.search, &mut db, &tokenizer)?;
+                for &episode_id in &episode_ids {
+                    backend.delete_episode(episode_id)?;
+                }
+                backend.commit()?;
+            }
+            db.refresh_corpus_stats()?;
+            println!("Deleted {} episodes.", episode_ids.len());
+            Ok(())
+        }
+        Command::Search {
+            query,
+            regex,
+            grammar,
+            kanji,
+            within_token,
+            accent,
+            furigana,
+            backend,
+            limit,
+            exact_script,
+            explain,
+            context,
+            sentences,
+            group,
+            kwic,
+            fuzzy,
+            watched,
+            user,
+            sort,
+            skip_start,
+            skip_end,
+            lyrics,
+            max_tokens,
+            max_chars,
+            max_unknown,
+            known,
+            notes,
+            tags,
+            corpus,
+            show_type,
+            translate,
+        } => {
+            let mut db = DbHandler::open(&cli.db)?;
+            if explain {
+                let tokenizer = JapaneseTokenizer::from_config(&config.tokenizer)?;
+                print_explanation(&explain_query(&db, &tokenizer, &query, exact_script)?);
+                return Ok(());
+            }
+            // The query the hits are for, after any --fuzzy correction
+            let mut matched_query = query.clone();
+            // Word queries on a corpus with its own tokenizer are split the way its lines were
+            let corpus_tokenizer = match corpus.as_deref() {
+                Some(corpus) if !regex && !grammar && !kanji => {
+                    match config.tokenizer.kind_for(corpus) {
+                        TokenizerKind::Japanese => None,
+                        kind => Some(open_tokenizer(&config.tokenizer, kind)?),
+                    }
+                }
+                _ => None,
+            };
+            let mut time_range = config.time_range.clone();
+            if skip_start.is_some() || skip_end.is_some() {
+                time_range = TimeRangeConfig {
+                    skip_start: skip_start.unwrap_or(time_range.skip_start),
+                    skip_end: skip_end.unwrap_or(time_range.skip_end),
+                    shows: BTreeMap::new(),
+                };
+            }
+            let complexity = ComplexityFilter {
+                max_tokens,
+                max_chars,
+                max_unknown,
+            };
+            let tokenizer = if (!regex && corpus_tokenizer.is_none())
+                || accent
+                || furigana.is_some()
+                || !complexity.is_empty()
+            {
+                Some(Arc::new(JapaneseTokenizer::from_config(&config.tokenizer)?))
+            } else {
+                None
+            };
+            if let Some(tokenizer) = tokenizer
+                .as_ref()
+                .filter(|_| !regex && !grammar && !kanji && corpus_tokenizer.is_none())
+            {
+                warn_on_index_changes(
+                    &index_changes(&db, &config.tokenizer, tokenizer)?,
+                    "The word index was built with different [tokenizer] settings; \
+                     some words may not be found",
+                );
+            }
+            let known_words = if sort == SortOrder::Learnable || max_unknown.is_some() {
+                load_known_words(&db, &config, &user, known.as_deref())?
+            } else {
+                HashSet::new()
+            };
+            // The filters besides the query and time range, run once on each
+            // source of hits; the ranked backends refill through them so --limit
+            // counts what's left
+            let retain_filters =
+                |db: &DbHandler, hits: &mut Vec<SearchHit>| -> Result<(), Box<dyn Error>> {
+                    if watched {
+                        db.retain_watched(&user, hits)?;
+                    }
+                    if !tags.is_empty() {
+                        db.retain_tagged(&tags, hits)?;
+                    }
+                    if let Some(corpus) = &corpus {
+                        db.retain_corpus(corpus, hits)?;
+                    }
+                    if let Some(show_type) = show_type {
+                        db.retain_show_type(show_type, hits)?;
+                    }
+                    if lyrics != LyricsFilter::Include {
+                        db.retain_lyrics(lyrics, hits)?;
+                    }
+                    if !complexity.is_empty() {
+                        let tokenizer = tokenizer
+                            .as_ref()
+                            .expect("the tokenizer is opened for the complexity filters");
+                        filter_complexity(db, tokenizer, hits, &complexity, &known_words)?;
+                    }
+                    Ok(())
+                };
+            let started = Instant::now();
+            let mut hits = match (&corpus_tokenizer, &tokenizer) {
+                // Only the sqlite word index has lines tokenized other than as Japanese
+                (Some(corpus_tokenizer), _) => {
+                    let mut word_query = parse_query(&**corpus_tokenizer, &query);
+                    word_query.exact_script = exact_script;
+                    word_query.time_range = time_range.clone();
+                    let mut hits = db.find_lines(&word_query)?;
+                    retain_filters(&db, &mut hits)?;
+                    hits
+                }
+                (None, Some(tokenizer)) if !regex => {
+                    if grammar {
+                        let pattern = find_grammar_pattern(&query).ok_or_else(|| {
+                            format!(
+                                "Unknown grammar pattern {:?}; run `grammar` to list them",
+                                query
+                            )
+                        })?;
+                        let mut hits = search_grammar(&db, tokenizer, pattern)?;
+                        db.retain_time_range(&time_range, &mut hits)?;
+                        retain_filters(&db, &mut hits)?;
+                        hits
+                    } else if kanji {
+                        let mut hits = search_kanji(&db, tokenizer, &query, within_token)?;
+                        db.retain_time_range(&time_range, &mut hits)?;
+                        retain_filters(&db, &mut hits)?;
+                        hits
+                    } else {
+                        let mut search_config = config.search.clone();
+                        search_config.backend = backend.unwrap_or(search_config.backend);
+                        let mut hits = search_words(
+                            &search_config,
+                            &db,
+                            &cli.db,
+                            tokenizer,
+                            &query,
+                            limit,
+                            exact_script,
+                            &time_range,
+                            &retain_filters,
+                        )?;
+                        let corrected = if fuzzy && hits.is_empty() {
+                            correct_query(&db, &query)?
+                        } else {
+                            None
+                        };
+                        if let Some(corrected) = corrected {
+                            hits = search_words(
+                                &search_config,
+                                &db,
+                                &cli.db,
+                                tokenizer,
+                                &corrected,
+                                limit,
+                                exact_script,
+                                &time_range,
+                                &retain_filters,
+                            )?;
+                            if !hits.is_empty() {
+                                println!("Did you mean: {}", corrected);
+                            }
+                            matched_query = corrected;
+                        }
+                        hits
+                    }
+                }
+                _ => {
+                    let mut hits = search_regex(&db, &query)?;
+                    db.retain_time_range(&time_range, &mut hits)?;
+                    retain_filters(&db, &mut hits)?;
+                    hits
+                }
+            };
+            if notes {
+                let found: HashSet<TranscriptId> =
+                    hits.iter().map(|hit| hit.transcript_id).collect();
+                let mut noted = db.find_lines_with_notes(&query)?;
+                noted.retain(|hit| !found.contains(&hit.transcript_id));
+                retain_filters(&db, &mut noted)?;
+                hits.extend(noted);
+            }
+            if sort == SortOrder::Learnable {
+                let weights = config.ranking.learnability.clone();
+                let usual = match &tokenizer {
+                    Some(tokenizer)
+                        if !regex && !grammar && !kanji && corpus_tokenizer.is_none() =>
+                    {
+                        let options = CollocationOptions {
+                            limit: weights.collocations,
+                            ..CollocationOptions::default()
+                        };
+                        collocations(&db, tokenizer, &matched_query, &options)?
+                            .iter()
+                            .map(Collocation::text)
+                            .collect()
+                    }
+                    _ => Vec::new(),
+                };
+                LearnabilityScorer::new(weights, known_words, usual).sort(&db, &mut hits)?;
+            }
+            if config.query_log.enabled {
+                let kind = if regex {
+                    "regex"
+                } else if grammar {
+                    "grammar"
+                } else if kanji {
+                    "kanji"
+                } else {
+                    "words"
+                };
+                let mut filters = Vec::new();
+                if let Some(backend) = backend {
+                    filters.push(format!("backend={:?}", backend).to_lowercase());
+                }
+                if within_token {
+                    filters.push("within_token".to_string());
+                }
+                if exact_script {
+                    filters.push("exact_script".to_string());
+                }
+                if matched_query != query {
+                    filters.push(format!("fuzzy={}", matched_query));
+                }
+                if notes {
+                    filters.push("notes".to_string());
+                }
+                if watched {
+                    filters.push(format!("watched={}", user));
+                }
+                for tag in &tags {
+                    filters.push(format!("tag={}", tag));
+                }
+                if let Some(corpus) = &corpus {
+                    filters.push(format!("corpus={}", corpus));
+                }
+                if let Some(show_type) = show_type {
+                    filters.push(format!("show_type={}", show_type).to_lowercase());
+                }
+                if sort != SortOrder::Relevance {
+                    filters.push(format!("sort={}", sort));
+                }
+                if lyrics != LyricsFilter::Include {
+                    filters.push(format!("lyrics={}", lyrics));
+                }
+                if let Some(seconds) = skip_start {
+                    filters.push(format!("skip_start={}", seconds));
+                }
+                if let Some(seconds) = skip_end {
+                    filters.push(format!("skip_end={}", seconds));
+                }
+                if let Some(max) = max_tokens {
+                    filters.push(format!("max_tokens={}", max));
+                }
+                if let Some(max) = max_chars {
+                    filters.push(format!("max_chars={}", max));
+                }
+                if let Some(max) = max_unknown {
+                    filters.push(format!("max_unknown={}", max));
+                }
+                log_search(
+                    &db,
+                    &config.query_log,
+                    &NewLoggedQuery {
+                        query: query.clone(),
+                        kind: kind.to_string(),
+                        filters: filters.join(" "),
+                        hits: hits.len(),
+                        latency_micros: latency_micros(started.elapsed()),
+                    },
+                )?;
+            }
+            // Annotate accents from the original text, before furigana is added
+            let mut accent_lines = Vec::new();
+            if let Some(tokenizer) = tokenizer.as_ref().filter(|_| accent) {
+                for hit in &hits {
+                    let line = annotate_pitch_accent(&db, tokenizer, &hit.text)?;
+                    accent_lines.push(format!(
+                        "[{}] {}",
+                        hit.transcript_id,
+                        line.to_string().replace('\n', " ")
+                    ));
+                }
+            }
+            if let (Some(tokenizer), Some(format)) = (&tokenizer, furigana) {
+                for hit in &mut hits {
+                    hit.text = add_furigana(tokenizer, &hit.text, format);
+                }
+            }
+            let contexts = if context {
+                let mut window = config.context.clone();
+                window.sentence_boundaries |= sentences;
+                hits.iter()
+                    .map(|hit| context_window(&db, hit.transcript_id, &window))
+                    .collect::<Result<Vec<_>, _>>()?
+            } else {
+                Vec::new()
+            };
+            let mut translations = HashMap::new();
+            if translate {
+                let translator = open_translator(&config.translation, &config.llm, &cli.db)?;
+                let ids: Vec<TranscriptId> = hits
+                    .iter()
+                    .filter(|hit| hit.translation.is_none())
+                    .chain(contexts.iter().flatten())
+                    .map(|hit| hit.transcript_id)
+                    .collect();
+                translations = translate_lines(&mut db, translator.as_ref(), &ids)?;
+                for hit in &mut hits {
+                    if hit.translation.is_none() {
+                        hit.translation = translations.get(&hit.transcript_id).cloned();
+                    }
+                }
+            }
+            if let Some(width) = kwic {
+                let word_query;
+                let literal;
+                let pattern;
+                let matcher = if regex {
+                    pattern = Regex::new(&query)?;
+                    Matcher::Regex(&pattern)
+                } else if kanji {
+                    Matcher::Text(&query)
+                } else if grammar {
+                    literal = find_grammar_pattern(&query).map_or("", |p| p.literal);
+                    Matcher::Text(literal)
+                } else {
+                    let tokenizer = tokenizer.as_ref().ok_or("word searches need a tokenizer")?;
+                    word_query = WordQuery {
+                        exact_script,
+                        ..parse_query(tokenizer, &matched_query)
+                    };
+                    Matcher::Words {
+                        tokenizer,
+                        query: &word_query,
+                    }
+                };
+                print_kwic(&hits, &matcher, width);
+            } else if group {
+                print_grouped_hits(&hits);
+            } else {
+                print_hits(&hits);
+            }
+            for (hit, lines) in hits.iter().zip(&contexts) {
+                println!();
+                for line in lines {
+                    let marker = if line.transcript_id == hit.transcript_id {
+                        ">"
+                    } else {
+                        " "
+                    };
+                    println!(
+                        "{} [{}] {}",
+                        marker,
+                        line.time_start,
+                        line.text.replace('\n', " ")
+                    );
+                    if let Some(translation) = translations.get(&line.transcript_id) {
+                        println!("      {}", translation);
+                    }
+                }
+            }
+            if !accent_lines.is_empty() {
+                println!();
+                for line in accent_lines {
+                    println!("{}", line);
+                }
+            }
+            Ok(())
+        }
+        Command::Sample {
+            query,
+            count,
+            short,
+            preferred_shows,
+        } => {
+            let db = DbHandler::open(&cli.db)?;
+            let tokenizer = JapaneseTokenizer::from_config(&config.tokenizer)?;
+            let options = SampleOptions {
+                count,
+                prefer_short: short,
+                preferred_shows,
+            };
+            let hits = sample(&db, &tokenizer, &query, &options, &mut rand::thread_rng())?;
+            print_hits(&hits);
+            Ok(())
+        }
+        Command::Memorable {
+            query,
+            count,
+            candidates,
+            ranker,
+        } => {
+            let db = DbHandler::open(&cli.db)?;
+            let tokenizer = JapaneseTokenizer::from_config(&config.tokenizer)?;
+            let mut ranking = config.ranking.clone();
+            ranking.ranker = ranker.unwrap_or(ranking.ranker);
+            let ranker = open_ranker(&ranking, &config.llm, &cli.db)?;
+            let mut hits = search(&db, &tokenizer, &query)?;
+            hits.truncate(candidates);
+            eprintln!("Ranking {} lines by {}...", hits.len(), ranker.name());
+            print_hits(&ranker.rank(&db, &hits, count)?);
+            Ok(())
+        }
+        Command::Explain {
+            transcript_id,
+            refresh,
+        } => {
+            let db = DbHandler::open(&cli.db)?;
+            let transcript_id = TranscriptId(transcript_id);
+            let explanation = match db.explanation(transcript_id)? {
+                Some(explanation) if !refresh => explanation,
+                _ => {
+                    // A refresh asks the model again rather than reusing a cached reply
+                    let provider = if refresh {
+                        open_provider(&config.llm)?
+                    } else {
+                        open_cached_provider(&config.llm, &cli.db)?
+                    };
+                    let explanation = explain_line(provider.as_ref(), &db, transcript_id)?
+                        .ok_or_else(|| format!("No line with id {}", transcript_id))?;
+                    db.save_explanation(&explanation)?;
+                    explanation
+                }
+            };
+            println!("{}", explanation.text);
+            Ok(())
+        }
+        Command::Daily { date, level, json } => {
+            let db = DbHandler::open(&cli.db)?;
+            let options = DailyOptions {
+                level,
+                ..DailyOptions::default()
+            };
+            let date = date.unwrap_or_else(Date::today);
+            let Some(daily) = daily_sentence(&db, date, &options, &config.context)? else {
+                return Err("No line matches the daily sentence criteria".into());
+            };
+            if json {
+                println!("{}", serde_json::to_string_pretty(&daily)?);
+                return Ok(());
+            }
+            println!(
+                "{} — {} {} {}",
+                daily.date,
+                daily.line.show_name,
+                episode_label(daily.line.season, daily.line.episode_number),
+                daily.line.time_start
+            );
+            if let Some(word) = &daily.word {
+                println!("Word: {}", word);
+            }
+            println!();
+            for line in &daily.context {
+                let marker = if line == &daily.line { ">" } else { " " };
+                println!("{} {}", marker, line.text.replace('\n', " "));
+            }
+            if let Some(translation) = &daily.line.translation {
+                println!("\n{}", translation);
+            }
+            Ok(())
+        }
+        Command::ExportSentences {
+            words,
+            min_length,
+            max_length,
+            translated_only,
+            explanations,
+            output,
+        } => {
+            let db = DbHandler::open(&cli.db)?;
+            let mut hits = match words {
+                Some(path) => {
+                    let tokenizer = JapaneseTokenizer::from_config(&config.tokenizer)?;
+                    let words = read_word_list(BufReader::new(File::open(path)?))?;
+                    lines_with_any_word(&db, &tokenizer, &words)?
+                }
+                None => db.find_lines_containing_any(&[])?,
+            };
+            let filter = SentenceFilter {
+                min_length,
+                max_length,
+                translated_only,
+            };
+            filter_sentences(&mut hits, &filter);
+            let explanations = if explanations {
+                let ids: Vec<TranscriptId> = hits.iter().map(|hit| hit.transcript_id).collect();
+                Some(db.explanations(&ids)?)
+            } else {
+                None
+            };
+            let explanations = explanations.as_ref();
+            match output {
+                Some(path) => {
+                    write_sentence_pairs(&hits, explanations, BufWriter::new(File::create(path)?))?
+                }
+                None => write_sentence_pairs(&hits, explanations, std::io::stdout().lock())?,
+            }
+            eprintln!("Exported {} sentences.", hits.len());
+            Ok(())
+        }
+        Command::ImportJlpt { path } => {
+            let mut db = DbHandler::open(&cli.db)?;
+            let count =
+                db.import_jlpt_levels(BufReader::new(File::open(&path).with_path(&path)?))?;
+            // Difficulty scores take JLPT levels into account
+            db.refresh_difficulty()?;
+            println!("Imported {} JLPT levels.", count);
+            Ok(())
+        }
+        Command::ImportAccents { path } => {
+            let mut db = DbHandler::open(&cli.db)?;
+            let count =
+                db.import_pitch_accents(BufReader::new(File::open(&path).with_path(&path)?))?;
+            println!("Imported {} pitch accents.", count);
+            Ok(())
+        }
+        Command::Db {
+            command: DbCommand::Optimize { vacuum },
+        } => {
+            let mut db = DbHandler::open(&cli.db)?;
+            let report = db.optimize(vacuum)?;
+            println!("Pruned {} unused words.", report.pruned_words);
+            if report.fts_rebuilt {
+                println!("Rebuilt the FTS index.");
+            }
+            println!(
+                "Database size: {} KiB -> {} KiB{}",
+                report.size_before / 1024,
+                report.size_after / 1024,
+                if report.vacuumed {
+                    ""
+                } else {
+                    " (run with --vacuum to reclaim free pages)"
+                }
+            );
+            Ok(())
+        }
+        Command::Db {
+            command: DbCommand::Check { fix },
+        } => {
+            let mut db = DbHandler::open(&cli.db)?;
+            let report = db.check_integrity()?;
+            print_integrity_report(&report);
+            if fix && report.orphaned_index_entries > 0 {
+                let deleted = db.delete_orphaned_index_entries()?;
+                println!("Deleted {} orphaned index entries.", deleted);
+            }
+            Ok(())
+        }
+        Command::Bookmark { user, command } => {
+            let db = DbHandler::open(&cli.db)?;
+            match command {
+                BookmarkCommand::Add {
+                    transcript_id,
+                    note,
+                    tag,
+                } => {
+                    let transcript_id = TranscriptId(transcript_id);
+                    if db.find_hits_by_ids(&[transcript_id])?.is_empty() {
+                        return Err(format!("No line with id {}", transcript_id).into());
+                    }
+                    if !db.add_bookmark(&user, transcript_id, note.as_deref(), tag.as_deref())? {
+                        println!("Updated the bookmark of line {}.", transcript_id);
+                    }
+                }
+                BookmarkCommand::Remove { transcript_id } => {
+                    if !db.remove_bookmark(&user, TranscriptId(transcript_id))? {
+                        return Err(format!("Line {} isn't bookmarked", transcript_id).into());
+                    }
+                }
+                BookmarkCommand::List { tag, json } => {
+                    let bookmarks = db.bookmarks(&user, tag.as_deref())?;
+                    if json {
+                        println!("{}", serde_json::to_string_pretty(&bookmarks)?);
+                        return Ok(());
+                    }
+                    for bookmark in &bookmarks {
+                        let hit = &bookmark.hit;
+                        println!(
+                            "[{}] {} {} {} {}{}",
+                            hit.transcript_id,
+                            hit.show_name,
+                            episode_label(hit.season, hit.episode_number),
+                            hit.time_start,
+                            hit.text.replace('\n', " "),
+                            bookmark
+                                .tag
+                                .as_ref()
+                                .map_or(String::new(), |tag| format!("  #{}", tag))
+                        );
+                        if let Some(note) = &bookmark.note {
+                            println!("    {}", note.replace('\n', "\n    "));
+                        }
+                    }
+                }
+                BookmarkCommand::Export { tag, output } => {
+                    let bookmarks = db.bookmarks(&user, tag.as_deref())?;
+                    match output {
+                        Some(path) => {
+                            write_anki_bookmarks(&bookmarks, BufWriter::new(File::create(path)?))?
+                        }
+                        None => write_anki_bookmarks(&bookmarks, std::io::stdout().lock())?,
+                    }
+                    eprintln!("Exported {} bookmarks.", bookmarks.len());
+                }
+            }
+            Ok(())
+        }
+        Command::Tag { command } => {
+            let mut db = DbHandler::open(&cli.db)?;
+            match command {
+                TagCommand::Add {
+                    tag,
+                    transcript_ids,
+                } => {
+                    let ids: Vec<TranscriptId> =
+                        transcript_ids.into_iter().map(TranscriptId).collect();
+                    let found = db.find_hits_by_ids(&ids)?;
+                    if let Some(missing) = ids
+                        .iter()
+                        .find(|id| !found.iter().any(|hit| hit.transcript_id == **id))
+                    {
+                        return Err(format!("No line with id {}", missing).into());
+                    }
+                    let tagged = db.tag_lines(&tag, &ids)?;
+                    println!("Tagged {} lines with {:?}.", tagged, tag);
+                }
+                TagCommand::Remove {
+                    tag,
+                    transcript_ids,
+                } => {
+                    let ids: Vec<TranscriptId> =
+                        transcript_ids.into_iter().map(TranscriptId).collect();
+                    let untagged = db.untag_lines(&tag, &ids)?;
+                    println!("Removed {:?} from {} lines.", tag, untagged);
+                }
+                TagCommand::List => {
+                    for tag in db.tags()? {
+                        println!("{:>6}  {}", tag.lines, tag.tag);
+                    }
+                }
+                TagCommand::Show { tag } => print_hits(&db.find_tagged_lines(&tag)?),
+            }
+            Ok(())
+        }
+        Command::Corpus { command } => {
+            let mut db = DbHandler::open(&cli.db)?;
+            match command {
+                CorpusCommand::Set { corpus, shows } => {
+                    let mut show_ids = Vec::new();
+                    for show in &shows {
+                        let show_id = db
+                            .find_show_id(show)?
+                            .ok_or_else(|| format!("No show named {:?}", show))?;
+                        show_ids.push(show_id);
+                    }
+                    let moved = db.set_show_corpus(&corpus, &show_ids)?;
+                    println!("Moved {} shows into {:?}.", moved, corpus);
+                }
+                CorpusCommand::List => {
+                    for corpus in db.corpora()? {
+                        println!("{:>6}  {}", corpus.shows, corpus.corpus);
+                    }
+                }
+                CorpusCommand::Shows { corpus } => {
+                    for show in db.list_shows()? {
+                        if show.corpus == corpus {
+                            println!("{}", show.name);
+                        }
+                    }
+                }
+            }
+            Ok(())
+        }
+        Command::Note { command } => {
+            let db = DbHandler::open(&cli.db)?;
+            let show_id = |name: &str| -> Result<ShowId, Box<dyn Error>> {
+                db.find_show_id(name)?
+                    .ok_or_else(|| format!("No show named {:?}", name).into())
+            };
+            let line_id = |transcript_id: i64| -> Result<TranscriptId, Box<dyn Error>> {
+                let transcript_id = TranscriptId(transcript_id);
+                if db.find_hits_by_ids(&[transcript_id])?.is_empty() {
+                    return Err(format!("No line with id {}", transcript_id).into());
+                }
+                Ok(transcript_id)
+            };
+            match command {
+                NoteCommand::Add { text, line, show } => {
+                    let id = match (line, show) {
+                        (Some(transcript_id), _) => {
+                            db.add_line_note(line_id(transcript_id)?, &text)?
+                        }
+                        (None, Some(show)) => db.add_show_note(show_id(&show)?, &text)?,
+                        (None, None) => unreachable!("clap requires --line or --show"),
+                    };
+                    println!("Added note {}.", id);
+                }
+                NoteCommand::Delete { id } => {
+                    if !db.delete_note(id)? {
+                        return Err(format!("No note with id {}", id).into());
+                    }
+                }
+                NoteCommand::List { line, show } => {
+                    let notes = match (line, show) {
+                        (Some(transcript_id), _) => {
+                            let transcript_id = line_id(transcript_id)?;
+                            db.line_notes(&[transcript_id])?
+                                .remove(&transcript_id)
+                                .unwrap_or_default()
+                        }
+                        (None, Some(show)) => db.show_notes(show_id(&show)?)?,
+                        (None, None) => unreachable!("clap requires --line or --show"),
+                    };
+                    for note in notes {
+                        println!("[{}] {}", note.id, note.text);
+                    }
+                }
+                NoteCommand::Search { text } => {
+                    let notes = db.search_notes(&text)?;
+                    let ids: Vec<TranscriptId> =
+                        notes.iter().filter_map(|note| note.transcript_id).collect();
+                    let lines: HashMap<TranscriptId, SearchHit> = db
+                        .find_hits_by_ids(&ids)?
+                        .into_iter()
+                        .map(|hit| (hit.transcript_id, hit))
+                        .collect();
+                    let shows: HashMap<ShowId, String> = db
+                        .list_shows()?
+                        .into_iter()
+                        .map(|show| (show.id, show.name))
+                        .collect();
+                    for note in &notes {
+                        let on = match (note.transcript_id, note.show_id) {
+                            (Some(transcript_id), _) => lines.get(&transcript_id).map_or(
+                                format!("line {}", transcript_id),
+                                |hit| {
+                                    format!("[{}] {}", transcript_id, hit.text.replace('\n', " "))
+                                },
+                            ),
+                            (None, Some(show_id)) => shows
+                                .get(&show_id)
+                                .cloned()
+                                .unwrap_or_else(|| format!("show {}", show_id)),
+                            (None, None) => String::new(),
+                        };
+                        println!("[{}] {}", note.id, on);
+                        println!("    {}", note.text.replace('\n', "\n    "));
+                    }
+                    println!("{} notes.", notes.len());
+                }
+            }
+            Ok(())
+        }
+        Command::SavedSearch { user, command } => {
+            let db = DbHandler::open(&cli.db)?;
+            match command {
+                SavedSearchCommand::Save {
+                    name,
+                    query,
+                    regex,
+                    kanji,
+                    grammar,
+                } => {
+                    let kind = if regex {
+                        "regex"
+                    } else if kanji {
+                        "kanji"
+                    } else if grammar {
+                        if find_grammar_pattern(&query).is_none() {
+                            return Err(format!("Unknown grammar pattern {:?}", query).into());
+                        }
+                        "grammar"
+                    } else {
+                        "words"
+                    };
+                    db.save_search(&user, &name, &query, kind)?;
+                }
+                SavedSearchCommand::List => {
+                    for saved in db.saved_searches(&user)? {
+                        println!("{:<20} [{}] {}", saved.name, saved.kind, saved.query);
+                    }
+                }
+                SavedSearchCommand::Run { name } => {
+                    let saved = db
+                        .saved_search(&user, &name)?
+                        .ok_or_else(|| format!("No search is saved as {:?}", name))?;
+                    let hits = if saved.kind == "regex" {
+                        search_regex(&db, &saved.query)?
+                    } else {
+                        let tokenizer = JapaneseTokenizer::from_config(&config.tokenizer)?;
+                        match saved.kind.as_str() {
+                            "kanji" => search_kanji(&db, &tokenizer, &saved.query, false)?,
+                            "grammar" => {
+                                let pattern =
+                                    find_grammar_pattern(&saved.query).ok_or_else(|| {
+                                        format!("Unknown grammar pattern {:?}", saved.query)
+                                    })?;
+                                search_grammar(&db, &tokenizer, pattern)?
+                            }
+                            _ => search(&db, &tokenizer, &saved.query)?,
+                        }
+                    };
+                    print_hits(&hits);
+                }
+                SavedSearchCommand::Delete { name } => {
+                    if !db.delete_saved_search(&user, &name)? {
+                        return Err(format!("No search is saved as {:?}", name).into());
+                    }
+                }
+            }
+            Ok(())
+        }
+        Command::ApiKey { command } => {
+            let db = DbHandler::open(&cli.db)?;
+            match command {
+                ApiKeyCommand::Create {
+                    name,
+                    scope,
+                    rate_limit,
+                } => {
+                    let (_, secret) = db.create_api_key(&name, scope, rate_limit)?;
+                    println!("{}", secret);
+                }
+                ApiKeyCommand::List => {
+                    for key in db.api_keys()? {
+                        println!("{:<20} {:<7} {}/min", key.name, key.scope, key.rate_limit);
+                    }
+                }
+                ApiKeyCommand::Revoke { name } => {
+                    if !db.revoke_api_key(&name)? {
+                        return Err(format!("No API key is issued to {:?}", name).into());
+                    }
+                }
+            }
+            Ok(())
+        }
+        Command::MapMedia {
+            media_dir,
+            csv,
+            remap,
+            dry_run,
+        } => {
+            let mut db = DbHandler::open(&cli.db)?;
+            let matches = match (csv, media_dir) {
+                (Some(csv), _) => read_media_csv(&db, File::open(csv)?)?,
+                (None, Some(media_dir)) => {
+                    let mapped = db.media_files()?;
+                    let episodes: Vec<_> = episodes_to_match(&db)?
+                        .into_iter()
+                        .filter(|episode| remap || !mapped.contains_key(&episode.episode_id))
+                        .collect();
+                    // Absolute paths, so playing media works from any directory
+                    let media_dir = std::fs::canonicalize(media_dir)?;
+                    match_media(&episodes, &find_media_files(&media_dir))
+                }
+                (None, None) => unreachable!("clap requires a media directory or --csv"),
+            };
+            for media_match in &matches {
+                println!(
+                    "[{}] {} ({:.2})",
+                    media_match.episode_id,
+                    media_match.path.display(),
+                    media_match.score
+                );
+            }
+            if !dry_run {
+                let files: Vec<MediaFile> = matches.iter().map(|m| m.to_media_file()).collect();
+                db.record_media_files(&files)?;
+            }
+            println!("Mapped {} episodes.", matches.len());
+            Ok(())
+        }
+        Command::Play {
+            transcript_id,
+            lead_in,
+        } => {
+            let db = DbHandler::open(&cli.db)?;
+            let mut player = config.player.clone();
+            player.lead_in = lead_in.unwrap_or(player.lead_in);
+            match play(&db, TranscriptId(transcript_id), &player)? {
+                Playback::Started => println!("Started mpv."),
+                Playback::Reused => println!("Sent to the running mpv."),
+            }
+            Ok(())
+        }
+        Command::Convert {
+            input,
+            output,
+            crlf,
+        } => {
+            let subtitles = Subtitles::parse_from_file(&input).with_path(&input)?;
+            let line_ending = if crlf {
+                LineEnding::CrLf
+            } else {
+                LineEnding::Lf
+            };
+            let is_vtt = output
+                .extension()
+                .is_some_and(|extension| extension.eq_ignore_ascii_case("vtt"));
+            if is_vtt {
+                subtitles.write_vtt(&output, line_ending)?;
+            } else {
+                subtitles.write_srt(&output, line_ending)?;
+            }
+            println!("Wrote {} cues to {}.", subtitles.len(), output.display());
+            Ok(())
+        }
+        Command::Stats {
+            top,
+            refresh,
+            episodes,
+            show,
+            by_density,
+            corpus,
+            show_type,
+            json,
+        } => {
+            let mut db = DbHandler::open(&cli.db)?;
+            if refresh {
+                db.refresh_corpus_stats()?;
+            }
+            if episodes {
+                let mut stats = db.episode_stats(show.as_deref())?;
+                if by_density {
+                    stats.sort_by(|a, b| {
+                        b.characters_per_minute.total_cmp(&a.characters_per_minute)
+                    });
+                }
+                if json {
+                    println!("{}", serde_json::to_string_pretty(&stats)?);
+                    return Ok(());
+                }
+                println!(
+                    "{:>8} {:>9} {:>6} {:>9}  Episode",
+                    "Lines", "Chars/min", "Cover", "Minutes"
+                );
+                for episode in stats {
+                    println!(
+                        "{:>8} {:>9.0} {:>5.0}% {:>9.1}  {} {}",
+                        episode.lines,
+                        episode.characters_per_minute,
+                        episode.dialogue_coverage,
+                        episode.runtime_minutes,
+                        episode.show_name,
+                        episode_label(episode.season, episode.episode_number)
+                    );
+                }
+                return Ok(());
+            }
+            let stats = match (&corpus, show_type) {
+                (Some(corpus), _) => db.corpus_stats_in(corpus, top)?,
+                (None, Some(show_type)) => db.show_type_stats(show_type, top)?,
+                (None, None) => db.corpus_stats(top)?,
+            };
+            if json {
+                println!("{}", serde_json::to_string_pretty(&stats)?);
+                return Ok(());
+            }
+            println!("Shows:        {}", stats.shows);
+            println!("Episodes:     {}", stats.episodes);
+            println!("Lines:        {}", stats.lines);
+            println!("Unique words: {}", stats.unique_words);
+            println!("Dialogue:     {:.1} hours", stats.dialogue_hours);
+            if let Some(refreshed_at) = &stats.refreshed_at {
+                println!("As of {} (UTC)", refreshed_at);
+            }
+            for (rank, word) in stats.top_words.iter().enumerate() {
+                println!("{:>4}. {}\t{}", rank + 1, word.word, word.frequency);
+            }
+            Ok(())
+        }
+        Command::Difficulty {
+            easier_than,
+            harder_than,
+            episodes,
+            show,
+            json,
+        } => {
+            let db = DbHandler::open(&cli.db)?;
+            if episodes {
+                let episodes = difficulty::episode_difficulty(&db, show.as_deref())?;
+                if json {
+                    println!("{}", serde_json::to_string_pretty(&episodes)?);
+                    return Ok(());
+                }
+                println!(
+                    "{:>5} {:>8} {:>6} {:>5} {:>5}  Episode",
+                    "Score", "Line len", "Kanji", "JLPT", "Rare"
+                );
+                for episode in episodes {
+                    println!(
+                        "{:>5.0} {:>8.1} {:>5.0}% {:>5} {:>4.0}%  {} {}",
+                        episode.score,
+                        episode.average_line_length,
+                        episode.kanji_density * 100.0,
+                        episode
+                            .average_jlpt_level
+                            .map_or("-".to_string(), |level| format!("N{:.1}", level)),
+                        episode.rare_word_share * 100.0,
+                        episode.show_name,
+                        episode_label(episode.season, episode.episode_number)
+                    );
+                }
+                return Ok(());
+            }
+            let shows = match (easier_than, harder_than) {
+                (Some(show), _) => difficulty::shows_easier_than(&db, &show)?,
+                (_, Some(show)) => difficulty::shows_harder_than(&db, &show)?,
+                _ => difficulty::show_difficulty(&db)?,
+            };
+            if json {
+                println!("{}", serde_json::to_string_pretty(&shows)?);
+                return Ok(());
+            }
+            for show in shows {
+                println!(
+                    "{:>5.0}  {} ({} episodes)",
+                    show.score, show.show_name, show.episodes
+                );
+            }
+            Ok(())
+        }
+        Command::Watched {
+            show,
+            episode,
+            season,
+            unmark,
+            user,
+        } => {
+            let mut db = DbHandler::open(&cli.db)?;
+            let episode_ids = match episode {
+                Some(episode) => db
+                    .find_episode_id(&show, season, Some(episode))?
+                    .map(|id| vec![id]),
+                None => match db.find_show_id(&show)? {
+                    Some(show_id) => Some(
+                        db.list_episodes(show_id)?
+                            .into_iter()
+                            .map(|episode| episode.id)
+                            .collect(),
+                    ),
+                    None => None,
+                },
+            };
+            let episode_ids =
+                episode_ids.ok_or_else(|| format!("No episodes found for {:?}", show))?;
+            if unmark {
+                let count = db.mark_unwatched(&user, &episode_ids)?;
+                println!("Removed {} episodes from {}'s watch history.", count, user);
+            } else {
+                let count = db.mark_watched(&user, &episode_ids)?;
+                println!("Marked {} episodes as watched by {}.", count, user);
+            }
+            Ok(())
+        }
+        Command::DetectLyrics {
+            show,
+            min_episodes,
+            max_drift,
+            min_length,
+        } => {
+            let mut db = DbHandler::open(&cli.db)?;
+            let shows = match show {
+                Some(name) => {
+                    let show_id = db
+                        .find_show_id(&name)?
+                        .ok_or_else(|| format!("No show named {:?}", name))?;
+                    vec![(show_id, name)]
+                }
+                None => db
+                    .list_shows()?
+                    .into_iter()
+                    .map(|show| (show.id, show.name))
+                    .collect(),
+            };
+            let options = LyricsOptions {
+                min_episodes,
+                max_drift,
+                min_length,
+            };
+            for (show_id, name) in shows {
+                let flagged = db.detect_lyrics(show_id, &options)?;
+                println!("{}: {} lines flagged as lyrics.", name, flagged);
+            }
+            Ok(())
+        }
+        Command::Progress {
+            user,
+            min_count,
+            words,
+        } => {
+            let db = DbHandler::open(&cli.db)?;
+            let episodes = db.watched_episodes(&user)?;
+            let mut shows: Vec<&str> = episodes.iter().map(|e| e.show_name.as_str()).collect();
+            shows.dedup();
+            println!(
+                "{} has watched {} episodes of {} shows.",
+                user,
+                episodes.len(),
+                shows.len()
+            );
+            let encountered = db.encountered_words(&user, min_count)?;
+            println!(
+                "Words encountered {}+ times: {}",
+                min_count,
+                encountered.len()
+            );
+            if words {
+                for word in encountered {
+                    println!("  {}\t{}", word.word, word.frequency);
+                }
+            }
+            Ok(())
+        }
+        Command::Overlap {
+            show_a,
+            show_b,
+            min_count,
+            top,
+            json,
+        } => {
+            let db = DbHandler::open(&cli.db)?;
+            let options = OverlapOptions {
+                min_count,
+                top_new_words: top,
+            };
+            let overlap = compare_vocabulary(&db, &show_a, &show_b, &options)?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&overlap)?);
+                return Ok(());
+            }
+            println!("{}: {} words", overlap.show_a, overlap.words_a);
+            println!("{}: {} words", overlap.show_b, overlap.words_b);
+            println!("Shared: {}", overlap.shared);
+            println!("Only in {}: {}", overlap.show_a, overlap.only_a);
+            println!(
+                "New in {} after {}: {} words; {:.1}% of its word occurrences are already known",
+                overlap.show_b, overlap.show_a, overlap.new_in_b, overlap.coverage_of_b
+            );
+            for word in &overlap.top_new_words {
+                println!("  {}\t{}", word.word, word.frequency);
+            }
+            Ok(())
+        }
+        Command::Collocations {
+            word,
+            min_length,
+            max_length,
+            limit,
+            json,
+        } => {
+            let db = DbHandler::open(&cli.db)?;
+            let tokenizer = JapaneseTokenizer::from_config(&config.tokenizer)?;
+            let options = CollocationOptions {
+                min_length,
+                max_length,
+                limit,
+            };
+            let found = collocations(&db, &tokenizer, &word, &options)?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&found)?);
+                return Ok(());
+            }
+            for collocation in found {
+                println!(
+                    "{}\t{}",
+                    collocation.tokens.join(" "),
+                    collocation.frequency
+                );
+            }
+            Ok(())
+        }
+        Command::Catchphrases {
+            show,
+            speaker,
+            min_length,
+            max_length,
+            min_count,
+            min_lines,
+            limit,
+            json,
+        } => {
+            let mut db = DbHandler::open(&cli.db)?;
+            let tokenizer = JapaneseTokenizer::from_config(&config.tokenizer)?;
+            let options = CatchphraseOptions {
+                min_length,
+                max_length,
+                min_count,
+                min_lines,
+                limit,
+            };
+            let mut profiles = character_catchphrases(&db, &tokenizer, &show, &options)?;
+            if let Some(speaker) = &speaker {
+                profiles.retain(|profile| profile.speaker.eq_ignore_ascii_case(speaker));
+            }
+            if json {
+                println!("{}", serde_json::to_string_pretty(&profiles)?);
+                return Ok(());
+            }
+            if profiles.is_empty() {
+                eprintln!(
+                    "No characters of {} with {} lines or more; speakers come from ASS \
+                     actor fields and names in brackets before lines, when ingesting",
+                    show, min_lines
+                );
+            }
+            for profile in &profiles {
+                println!("{} ({} lines)", profile.speaker, profile.lines);
+                for catchphrase in &profile.catchphrases {
+                    println!(
+                        "  {}\t{}\t{}\t{:.1}",
+                        catchphrase.text,
+                        catchphrase.occurrences,
+                        catchphrase.elsewhere,
+                        catchphrase.keyness
+                    );
+                }
+                for line in &profile.frequent_lines {
+                    println!(
+                        "  「{}」\t{}\t{}",
+                        line.text, line.occurrences, line.elsewhere
+                    );
+                }
+            }
+            Ok(())
+        }
+        Command::Glossary {
+            show,
+            extract,
+            min_count,
+            json,
+        } => {
+            let mut db = DbHandler::open(&cli.db)?;
+            if extract {
+                let stored = db.refresh_glossary(min_count)?;
+                eprintln!("Found {} terms.", stored);
+                if let Some(path) = &config.tokenizer.glossary_dictionary_path {
+                    write_glossary_dictionary(&db, config.tokenizer.dictionary, path)?;
+                    eprintln!("Wrote {}; reingest to tokenize with it.", path.display());
+                }
+            }
+            let glossary = db.glossary(show.as_deref())?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&glossary)?);
+                return Ok(());
+            }
+            for term in glossary {
+                println!(
+                    "{}\t{}\t{}\t{}\t{:.1}",
+                    term.show_name,
+                    term.term,
+                    term.reading.unwrap_or_default(),
+                    term.occurrences,
+                    term.keyness
+                );
+            }
+            Ok(())
+        }
+        Command::Suggest { prefix, limit } => {
+            let db = DbHandler::open(&cli.db)?;
+            for suggestion in db.suggest(&prefix, limit)? {
+                println!(
+                    "{}\t{}\t{}",
+                    suggestion.word,
+                    suggestion.reading.unwrap_or_default(),
+                    suggestion.frequency
+                );
+            }
+            Ok(())
+        }
+        Command::Bench {
+            runs,
+            queries,
+            backend,
+            limit,
+            plan,
+            json,
+        } => {
+            let mut db = DbHandler::open(&cli.db)?;
+            let tokenizer = Arc::new(JapaneseTokenizer::from_config(&config.tokenizer)?);
+            let mut search_config = config.search.clone();
+            search_config.backend = backend.unwrap_or(search_config.backend);
+            let queries: Vec<(String, String)> = if queries.is_empty() {
+                BENCH_QUERIES
+                    .iter()
+                    .map(|(label, query)| (label.to_string(), query.to_string()))
+                    .collect()
+            } else {
+                queries
+                    .into_iter()
+                    .map(|query| (String::new(), query))
+                    .collect()
+            };
+            let results = run_bench(&search_config, &mut db, &tokenizer, &queries, runs, limit)?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&results)?);
+                return Ok(());
+            }
+            println!(
+                "{:<24} {:>7} {:>9} {:>9} {:>9} {:>9} {:>10}",
+                "query", "hits", "p50 ms", "p90 ms", "p99 ms", "max ms", "scanned"
+            );
+            for result in &results {
+                let query = if result.label.is_empty() {
+                    result.query.clone()
+                } else {
+                    format!("{} ({})", result.query, result.label)
+                };
+                let scanned = result
+                    .profile
+                    .as_ref()
+                    .map_or("-".to_string(), |profile| profile.rows_scanned.to_string());
+                println!(
+                    "{} {:>7} {:>9.2} {:>9.2} {:>9.2} {:>9.2} {:>10}",
+                    pad_columns(&query, 24),
+                    result.hits,
+                    result.p50_ms,
+                    result.p90_ms,
+                    result.p99_ms,
+                    result.max_ms,
+                    scanned
+                );
+                if let Some(profile) = result.profile.as_ref().filter(|_| plan) {
+                    for step in &profile.plan {
+                        println!("    {}", step);
+                    }
+                }
+            }
+            Ok(())
+        }
+        Command::QueryLog {
+            slow,
+            limit,
+            clear,
+            json,
+        } => {
+            let db = DbHandler::open(&cli.db)?;
+            if clear {
+                println!("Deleted {} logged searches.", db.clear_query_log()?);
+                return Ok(());
+            }
+            let queries = match slow {
+                Some(millis) => db.slow_queries(millis.saturating_mul(1000), limit)?,
+                None => db.recent_queries(limit)?,
+            };
+            if json {
+                println!("{}", serde_json::to_string_pretty(&queries)?);
+                return Ok(());
+            }
+            for query in &queries {
+                println!(
+                    "{} {:>9.2} ms {:>6} hits  [{}] {}{}",
+                    query.logged_at,
+                    query.latency_micros as f64 / 1000.0,
+                    query.hits,
+                    query.kind,
+                    query.query,
+                    if query.filters.is_empty() {
+                        String::new()
+                    } else {
+                        format!("  ({})", query.filters)
+                    }
+                );
+            }
+            Ok(())
+        }
+        Command::Grammar => {
+            for pattern in GRAMMAR_PATTERNS {
+                println!("{:<20} {}", pattern.id(), pattern.meaning);
+            }
+            Ok(())
+        }
+        Command::Mcp => {
+            let db = DbHandler::open(&cli.db)?;
+            let tokenizer = JapaneseTokenizer::from_config(&config.tokenizer)?;
+            eprintln!("MCP server ready on stdio");
+            McpServer::new(&db, &tokenizer)
+                .serve(std::io::stdin().lock(), std::io::stdout().lock())?;
+            Ok(())
+        }
+        #[cfg(feature = "server")]
+        Command::Serve {
+            addr,
+            public_url,
+            open,
+            require_api_key,
+            ingest_root,
+        } => {
+            use anime_search::db::DbPool;
+            use anime_search::server::{open_browser, serve, AppState, RateLimiter};
+
+            let state = AppState {
+                pool: Arc::new(DbPool::open(&cli.db, 4)?),
+                tokenizer: Arc::new(JapaneseTokenizer::from_config(&config.tokenizer)?),
+                public_url,
+                api_keys: Arc::new(RateLimiter::new()),
+                require_api_key,
+                ingest_root,
+                query_log: config.query_log.clone(),
+                search_backend: config.search.clone(),
+                show_types: config.show_types.clone(),
+            };
+            let runtime = tokio::runtime::Runtime::new()?;
+            runtime.block_on(async {
+                let listener = tokio::net::TcpListener::bind(addr).await?;
+                let url = format!("http://{}", addr);
+                println!("Listening on {}", url);
+                if open {
+                    if let Err(e) = open_browser(&url) {
+                        eprintln!("Failed to open a browser: {}", e);
+                    }
+                }
+                serve(listener, state).await
+            })?;
+            Ok(())
+        }
+        #[cfg(feature = "discord")]
+        Command::Discord { prefix, max_hits } => {
+            use anime_search::bots::discord::{run, BotOptions};
+            use anime_search::db::DbPool;
+
+            let token =
+                std::env::var("DISCORD_TOKEN").map_err(|_| "Set DISCORD_TOKEN to the bot token")?;
+            let options = BotOptions {
+                prefix,
+                max_hits,
+                ..BotOptions::new(token)
+            };
+            let pool = Arc::new(DbPool::open(&cli.db, 4)?);
+            let tokenizer = Arc::new(JapaneseTokenizer::from_config(&config.tokenizer)?);
+            let runtime = tokio::runtime::Runtime::new()?;
+            runtime.block_on(run(pool, tokenizer, options))?;
+            Ok(())
+        }
+        #[cfg(feature = "grpc")]
+        Command::Grpc { addr, ingest_root } => {
+            use anime_search::db::DbPool;
+            use anime_search::grpc::{serve, SearchService};
+
+            let mut service = SearchService::new(
+                Arc::new(DbPool::open(&cli.db, 4)?),
+                Arc::new(JapaneseTokenizer::from_config(&config.tokenizer)?),
+            )
+            .search_backend(config.search.clone())
+            .show_types(config.show_types.clone());
+            if let Some(root) = ingest_root {
+                service = service.ingest_root(root);
+            }
+            let runtime = tokio::runtime::Runtime::new()?;
+            println!("Listening on {}", addr);
+            runtime.block_on(serve(addr, service))?;
+            Ok(())
+        }
+    }
+}
+
+// How `ingest` picks and reports the files it ingests
+struct IngestOptions<'a> {
+    report_path: Option<&'a Path>,
+    resume: bool,
+    corpus: Option<&'a str>,
+    lang: Option<Language>,
+    duplicates: DuplicatePolicy,
+    conflicts: ConflictPolicies,
+    simultaneous: SimultaneousCues,
+}
+
+fn ingest(
+    config: &Config,
+    db_path: &Path,
+    root_dir: &Path,
+    options: &IngestOptions,
+) -> Result<(), Box<dyn Error>> {
     let start_time = Instant::now();
+    let resume = options.resume;
 
-    let mut db = DbHandler::new("transcripts.db")?;
-    db.create_tables()?;
+    let mut db = DbHandler::open(db_path)?;
+    db.set_conflict_policies(options.conflicts);
 
-    let root_dir = Path::new("data/transcripts_raw");
     let number_method = EpisodeNumberMethod::FromFileOrder;
     let name_method = EpisodeNameMethod::FromEpisodeNumber;
 
-    let show_entries = process_srt_directory(root_dir, &number_method, &name_method);
+    let resume_state = if resume {
+        ResumeState::load(&db)?
+    } else {
+        ResumeState::default()
+    };
+    let mut skipped = 0;
+    let show_entries =
+        process_srt_directory_filtered(root_dir, &number_method, &name_method, |path| {
+            let done = resume_state.is_unchanged(path);
+            skipped += done as usize;
+            !done
+        });
     println!(
         "Processed {} entries.",
         show_entries.values().flatten().count()
     );
+    if resume {
+        println!("Skipped {} files ingested by an earlier run.", skipped);
+    }
 
-    // Prepare data for batch insertion
-    let mut shows = Vec::new();
-    let mut episodes = Vec::new();
-    let mut transcripts = Vec::new();
-
-    for (show_name, show_episodes) in show_entries {
-        let show_id = (shows.len() + 1) as i64;
-        shows.push((show_name.clone(), "Anime".to_string()));
-
-        for episode in show_episodes {
-            let episode_id = (episodes.len() + 1) as i64;
-            episodes.push((
-                show_id,
-                episode.episode_name.clone(),
-                1, // Assuming all episodes are in season 1
-                episode.episode_number as i32,
-            ));
+    let mut entries: Vec<SrtEntry> = show_entries.into_values().flatten().collect();
+    entries.sort_by(|a, b| (&a.show_name, a.episode_number).cmp(&(&b.show_name, b.episode_number)));
+    if let Some(lang) = options.lang {
+        retain_language(&mut entries, lang);
+    }
+    report_duplicates(&db, &mut entries, options.duplicates)?;
+    report_movies(config, &db, root_dir, &mut entries)?;
+    run_hooks(&IngestHooks::from_config(&config.hooks)?, &mut entries)?;
+    let merged = apply_simultaneous_cues(&mut entries, options.simultaneous);
+    if merged > 0 {
+        println!("Merged {} cues shown at the same time as another.", merged);
+    }
+    let summary = IngestSummary::from_entries(&entries);
+    println!("{}", summary);
+    if let Some(path) = options.report_path {
+        serde_json::to_writer_pretty(File::create(path)?, &summary)?;
+        println!("Wrote ingestion report to {:?}.", path);
+    }
 
-            for subtitle in episode.content.0.iter() {
-                transcripts.push((
-                    episode_id,
-                    subtitle.number as i32,
-                    subtitle.start_time.to_string(),
-                    subtitle.end_time.to_string(),
-                    subtitle.text.clone(),
-                ));
+    // Each batch is saved as it completes, so a crash only loses the current batch
+    // and `--resume` picks up after the last saved one
+    let mut csv_output = CsvOutput::new("transcripts.csv").with_append(resume);
+    let total_files = entries.len();
+    let mut done_files = 0;
+    let mut inserted_lines = 0;
+    let mut line_conflicts = 0;
+    let mut entries = entries.into_iter().peekable();
+    while entries.peek().is_some() {
+        let batch: Vec<SrtEntry> = entries.by_ref().take(INGEST_BATCH_SIZE).collect();
+        done_files += batch.len();
+        // Files that changed since an earlier run replace the lines they were ingested as
+        let (changed, new) = resume_state.split_changed(batch);
+        for (batch, replace) in [(new, false), (changed, true)] {
+            if batch.is_empty() {
+                continue;
             }
+            let ingested = ingest_entries(
+                config,
+                &mut db,
+                root_dir,
+                batch,
+                replace,
+                Some(&csv_output),
+                options.corpus,
+            )?;
+            inserted_lines += ingested.transcript_ids.len();
+            line_conflicts += ingested.line_conflicts;
+            csv_output.append = true;
         }
+        println!("Saved {}/{} files.", done_files, total_files);
     }
-
-    // Perform batch insertions
-    db.batch_insert_shows(&shows)?;
-    db.batch_insert_episodes(&episodes)?;
-
-    let output_csv = true; // hard-coded for now
-    db.batch_insert_transcripts(&transcripts, output_csv)?;
+    println!("Inserted {} lines.", inserted_lines);
+    if line_conflicts > 0 {
+        println!(
+            "{} lines had the same episode and timestamps as another line (policy: {}).",
+            line_conflicts, options.conflicts.lines
+        );
+    }
+    db.refresh_corpus_stats()?;
 
     let duration = start_time.elapsed();
     println!("All data has been inserted into the database.");
@@ -109,3 +2610,533 @@ fn main() -> Result<()> {
 
     Ok(())
 }
+
+// Warns about entries with the same subtitles as another file, dropping them unless
+// the policy is to only warn
+fn report_duplicates(
+    db: &DbHandler,
+    entries: &mut Vec<SrtEntry>,
+    policy: DuplicatePolicy,
+) -> Result<(), Box<dyn Error>> {
+    for duplicate in find_duplicates(db, entries, policy)? {
+        let action = match policy {
+            DuplicatePolicy::Skip => "Skipping",
+            DuplicatePolicy::Warn => "Warning",
+        };
+        println!(
+            "{} {:?} ({}): same subtitles as {}.",
+            action, duplicate.path, duplicate.show_name, duplicate.original
+        );
+    }
+    Ok(())
+}
+
+// Makes movies of the entries of shows typed as movies in `[show_types]`, saying how many
+// Parses and ingests the subtitle files of one show folder under `root_dir`, replacing
+// the lines of episodes ingested from it before. The folder's other files are parsed
+// again too, so episodes keep their numbers. Episodes from the files (or archives) in
+// `urls` get the URL they were downloaded from as their source
+fn ingest_show_dir(
+    config: &Config,
+    db_path: &Path,
+    root_dir: &Path,
+    show_dir: &Path,
+    lang: Option<Language>,
+    corpus: Option<&str>,
+    urls: &HashMap<PathBuf, String>,
+) -> Result<(), Box<dyn Error>> {
+    let mut db = DbHandler::open(db_path)?;
+    let mut entries: Vec<SrtEntry> = process_srt_directory_filtered(
+        root_dir,
+        &EpisodeNumberMethod::FromFileOrder,
+        &EpisodeNameMethod::FromEpisodeNumber,
+        |path| path.starts_with(show_dir),
+    )
+    .into_values()
+    .flatten()
+    .collect();
+    if let Some(lang) = lang {
+        retain_language(&mut entries, lang);
+    }
+    report_duplicates(&db, &mut entries, DuplicatePolicy::Skip)?;
+    report_movies(config, &db, root_dir, &mut entries)?;
+    run_hooks(&IngestHooks::from_config(&config.hooks)?, &mut entries)?;
+    println!("{}", IngestSummary::from_entries(&entries));
+    let paths: Vec<PathBuf> = entries.iter().map(|entry| entry.path.clone()).collect();
+    let ingested = ingest_entries(config, &mut db, root_dir, entries, true, None, corpus)?;
+    let sources: Vec<Source> = paths
+        .iter()
+        .zip(&ingested.episode_ids)
+        .filter_map(|(path, &episode_id)| {
+            let (_, url) = urls.iter().find(|(file, _)| path.starts_with(file))?;
+            Some(Source {
+                url: Some(url.clone()),
+                ..Source::new(episode_id)
+            })
+        })
+        .collect();
+    db.record_sources(&sources)?;
+    db.refresh_corpus_stats()?;
+    println!(
+        "Ingested {} lines from {} episodes.",
+        ingested.transcript_ids.len(),
+        ingested.episode_ids.len()
+    );
+    Ok(())
+}
+
+fn report_movies(
+    config: &Config,
+    db: &DbHandler,
+    root_dir: &Path,
+    entries: &mut [SrtEntry],
+) -> Result<(), Box<dyn Error>> {
+    let movies = mark_movies(db, entries, &config.show_types, root_dir)?;
+    if movies > 0 {
+        println!("Ingesting {} files as movies.", movies);
+    }
+    Ok(())
+}
+
+// Runs the configured per-show hooks on the entries, reporting what they changed
+fn run_hooks(hooks: &IngestHooks, entries: &mut [SrtEntry]) -> Result<(), Box<dyn Error>> {
+    if hooks.is_empty() {
+        return Ok(());
+    }
+    let stats = hooks.apply(entries)?;
+    println!(
+        "Hooks ran on {} files: changed {} cues and dropped {}.",
+        stats.files, stats.cues_changed, stats.cues_dropped
+    );
+    Ok(())
+}
+
+// Drops the entries whose files aren't in `lang`, saying which
+fn retain_language(entries: &mut Vec<SrtEntry>, lang: Language) {
+    entries.retain(|entry| {
+        let keep = entry.language == Some(lang);
+        if !keep {
+            let language = entry
+                .language
+                .map_or("unknown".to_string(), |l| l.to_string());
+            println!("Skipping {:?}: language is {}.", entry.path, language);
+        }
+        keep
+    });
+}
+
+// Runs a word search through the configured backend
+// Only the sqlite backend matches words across kana and kanji spellings
+// The words `user` knows: those met often enough in their watched episodes (see
+// `[ranking.learnability]`), and those listed in the `known` file
+fn load_known_words(
+    db: &DbHandler,
+    config: &Config,
+    user: &str,
+    known: Option<&Path>,
+) -> Result<HashSet<String>, Box<dyn Error>> {
+    let mut words: HashSet<String> = db
+        .encountered_words(user, config.ranking.learnability.known_encounters)?
+        .into_iter()
+        .map(|word| word.word)
+        .collect();
+    if let Some(path) = known {
+        words.extend(read_word_list(BufReader::new(File::open(path)?))?);
+    }
+    Ok(words)
+}
+
+// Finds the lines matching a word query with the configured backend
+// The ranked backends return up to `limit` hits left after the time range and `retain`
+fn search_words(
+    config: &BackendConfig,
+    db: &DbHandler,
+    db_path: &Path,
+    tokenizer: &Arc<JapaneseTokenizer>,
+    query: &str,
+    limit: usize,
+    exact_script: bool,
+    time_range: &TimeRangeConfig,
+    retain: &dyn Fn(&DbHandler, &mut Vec<SearchHit>) -> Result<(), Box<dyn Error>>,
+) -> Result<Vec<SearchHit>, Box<dyn Error>> {
+    let mut word_query = parse_query(tokenizer, query);
+    // The search backends don't know who says each line
+    if config.backend == BackendKind::Sqlite || word_query.speaker.is_some() {
+        word_query.exact_script = exact_script;
+        word_query.time_range = time_range.clone();
+        let mut hits = db.find_lines(&word_query)?;
+        retain(db, &mut hits)?;
+        return Ok(hits);
+    }
+    // The backend gets its own connection, so `db` is free for the filters between searches
+    let mut backend_db = DbHandler::new(db_path)?;
+    let backend = open_backend(config, &mut backend_db, tokenizer)?;
+    // The backends rank before the filters run, so fetch more until `limit` hits are left
+    let mut fetch = limit;
+    loop {
+        let ids = backend.search(query, fetch)?;
+        let mut hits = db.find_hits_by_ids(&ids)?;
+        db.retain_time_range(time_range, &mut hits)?;
+        retain(db, &mut hits)?;
+        if hits.len() >= limit || ids.len() < fetch {
+            hits.truncate(limit);
+            return Ok(hits);
+        }
+        fetch = fetch.saturating_mul(2);
+    }
+}
+
+// Inserts and indexes parsed files in a single transaction
+// With `corpus`, their shows are moved into it before indexing, so the corpus's tokenizer is used
+// Shows get the type `[show_types]` gives them for `root_dir`, the directory the files were found in
+// If any step fails the database is left as it was, and the error says which step
+fn ingest_entries(
+    config: &Config,
+    db: &mut DbHandler,
+    root_dir: &Path,
+    entries: Vec<SrtEntry>,
+    replace: bool,
+    csv_output: Option<&CsvOutput>,
+    corpus: Option<&str>,
+) -> Result<IngestedLines, Box<dyn Error>> {
+    let show_names: HashSet<String> = entries
+        .iter()
+        .map(|entry| entry.show_name.clone())
+        .collect();
+    db.atomically(|db| {
+        let ingested = insert_entries(db, entries, replace, csv_output)?;
+        let show_names = show_names.iter().map(String::as_str);
+        let show_ids = set_show_types(db, &config.show_types, root_dir, show_names)?;
+        if let Some(corpus) = corpus {
+            db.set_show_corpus(corpus, &show_ids)?;
+        }
+        index_lines(config, db, &ingested, replace)
+            .map_err(|e| format!("Indexing failed ({}); no changes were saved", e))?;
+        Ok(ingested)
+    })
+}
+
+// Adds newly inserted lines to the word index and the configured backend
+// Each line is tokenized as configured for its show's corpus; only Japanese
+// lines go to the fts5 and tantivy backends, which tokenize them themselves
+// With `replace`, the backend first drops what it had indexed for those episodes
+fn index_lines(
+    config: &Config,
+    db: &mut DbHandler,
+    ingested: &IngestedLines,
+    replace: bool,
+) -> Result<(), Box<dyn Error>> {
+    let mut japanese_ids = Vec::new();
+    for (corpus, ids) in db.lines_by_corpus(&ingested.transcript_ids)? {
+        match config.tokenizer.kind_for(&corpus) {
+            TokenizerKind::Japanese => japanese_ids.extend(ids),
+            kind => db.index_transcripts(&*open_tokenizer(&config.tokenizer, kind)?, &ids)?,
+        }
+    }
+    if japanese_ids.is_empty() {
+        return Ok(());
+    }
+    japanese_ids.sort();
+    let tokenizer = match JapaneseTokenizer::from_config(&config.tokenizer) {
+        Ok(tokenizer) => Arc::new(tokenizer),
+        Err(e) => {
+            eprintln!("Warning: Skipping word index: {}", e);
+            return Ok(());
+        }
+    };
+    db.index_transcripts(&tokenizer, &japanese_ids)?;
+    let version = IndexVersion::current(&config.tokenizer, &tokenizer);
+    match IndexVersion::recorded(db)? {
+        None => version.record(db)?,
+        Some(indexed) => warn_on_index_changes(
+            &version.changes_from(&indexed),
+            "Indexed these lines with different [tokenizer] settings than the rest",
+        ),
+    }
+    if config.search.backend == BackendKind::Sqlite {
+        return Ok(());
+    }
+
+    println!("Updating {:?} index...", config.search.backend);
+    update_index(
+        &config.search,
+        db,
+        &tokenizer,
+        &japanese_ids,
+        &ingested.episode_ids,
+        replace,
+    )?;
+    Ok(())
+}
+
+// Warns when lines are tokenized with other settings than the word index was built with,
+// since words indexed under one may not be found under the other
+fn warn_on_index_changes(changes: &[VersionChange], warning: &str) {
+    if changes.is_empty() {
+        return;
+    }
+    eprintln!("Warning: {}; run `reindex` to rebuild the index", warning);
+    for change in changes {
+        eprintln!(
+            "  {}:\n    index:  {}\n    config: {}",
+            change.setting, change.indexed, change.current
+        );
+    }
+}
+
+// Ingests subtitle files as they are added or changed under `root_dir`
+// A changed file replaces the lines previously ingested from it
+fn watch(config: &Config, db_path: &Path, root_dir: &Path) -> Result<(), Box<dyn Error>> {
+    let mut db = DbHandler::open(db_path)?;
+
+    let number_method = EpisodeNumberMethod::FromFileOrder;
+    let name_method = EpisodeNameMethod::FromEpisodeNumber;
+    let hooks = IngestHooks::from_config(&config.hooks)?;
+
+    println!("Watching {:?} for subtitle files...", root_dir);
+    watch_srt_files(root_dir, WATCH_QUIET_PERIOD, |paths| {
+        let mut entries = Vec::new();
+        // A changed English file re-ingests the Japanese file it belongs to
+        let mut paths: Vec<PathBuf> = paths
+            .into_iter()
+            .filter_map(|path| {
+                if is_translation_file(&path) {
+                    find_original_file(&path)
+                } else {
+                    Some(path)
+                }
+            })
+            .collect();
+        paths.sort();
+        paths.dedup();
+        for path in paths {
+            println!("Processing {:?}...", path.file_name().unwrap_or_default());
+            match process_srt_file(&path, root_dir, &number_method, &name_method) {
+                Ok(entry) => entries.push(entry),
+                Err(e) => eprintln!("Error processing file {:?}: {}", path, e),
+            }
+        }
+        if let Err(e) = report_movies(config, &db, root_dir, &mut entries) {
+            eprintln!("Error checking for movies: {}", e);
+            return ControlFlow::Continue(());
+        }
+        if let Err(e) = run_hooks(&hooks, &mut entries) {
+            eprintln!("Error running ingestion hooks: {}", e);
+            return ControlFlow::Continue(());
+        }
+        match ingest_entries(config, &mut db, root_dir, entries, true, None, None) {
+            Ok(ingested) => println!(
+                "Ingested {} lines from {} episodes.",
+                ingested.transcript_ids.len(),
+                ingested.episode_ids.len()
+            ),
+            Err(e) => eprintln!("Error ingesting files: {}", e),
+        }
+        if let Err(e) = db.refresh_corpus_stats() {
+            eprintln!("Error refreshing corpus statistics: {}", e);
+        }
+        ControlFlow::Continue(())
+    })?;
+    Ok(())
+}
+
+// Writes every show's glossary as a user dictionary CSV, one entry per term
+fn write_glossary_dictionary(
+    db: &DbHandler,
+    kind: DictionaryKind,
+    path: &Path,
+) -> Result<(), Box<dyn Error>> {
+    let mut entries = BTreeMap::new();
+    for term in db.glossary(None)? {
+        let reading = term.reading.as_deref().map(hiragana_to_katakana);
+        entries.entry(term.term).or_insert(reading);
+    }
+    let mut csv = String::new();
+    for (term, reading) in entries {
+        csv.push_str(&kind.proper_noun_entry(&term, reading.as_deref().unwrap_or("*")));
+        csv.push('\n');
+    }
+    std::fs::write(path, csv)?;
+    Ok(())
+}
+
+fn print_integrity_report(report: &IntegrityReport) {
+    if report.is_clean() {
+        println!("No problems found.");
+        return;
+    }
+    for violation in &report.foreign_key_violations {
+        println!(
+            "Broken reference: {} row {} points at a missing {} row",
+            violation.table,
+            violation
+                .rowid
+                .map_or_else(|| "?".to_string(), |id| id.to_string()),
+            violation.parent
+        );
+    }
+    if !report.foreign_key_violations.is_empty() {
+        println!("  Fix: delete the affected show and ingest it again");
+    }
+    for episode_id in &report.empty_episodes {
+        println!("Episode {} has no lines", episode_id);
+    }
+    if !report.empty_episodes.is_empty() {
+        println!("  Fix: `reingest` its subtitle file, or `delete` the episode");
+    }
+    for transcript_id in &report.empty_lines {
+        println!("Line {} has no text", transcript_id);
+    }
+    for (episode_id, line_id) in &report.duplicate_line_ids {
+        println!(
+            "Episode {} has line number {} more than once",
+            episode_id, line_id
+        );
+    }
+    for transcript_id in &report.reversed_timestamps {
+        println!("Line {} ends before it starts", transcript_id);
+    }
+    if !report.empty_lines.is_empty()
+        || !report.duplicate_line_ids.is_empty()
+        || !report.reversed_timestamps.is_empty()
+    {
+        println!("  Fix: correct the subtitle file and `reingest` it");
+    }
+    if report.orphaned_index_entries > 0 {
+        println!(
+            "{} word index entries point at deleted lines or words",
+            report.orphaned_index_entries
+        );
+        println!("  Fix: run `db check --fix`");
+    }
+}
+
+fn print_explanation(explanation: &QueryExplanation) {
+    println!("Tokens:");
+    for token in &explanation.tokens {
+        println!(
+            "  {} ({}) {}{}",
+            token.surface,
+            token.base_form,
+            token.pos,
+            if token.indexed {
+                ""
+            } else {
+                " [stopword, ignored]"
+            }
+        );
+    }
+    println!("Terms, in intersection order:");
+    if explanation.terms.is_empty() {
+        println!("  none; the query matches nothing");
+    }
+    for term in &explanation.terms {
+        let pos = term
+            .term
+            .pos
+            .as_ref()
+            .map_or(String::new(), |pos| format!(":{}", pos));
+        println!(
+            "  {}{}: {} occurrences in {} lines, {} lines left",
+            term.term.word, pos, term.occurrences, term.lines, term.remaining
+        );
+        for (spelling, lines) in &term.spellings {
+            let note = if *lines == 0 {
+                " (not in the word index)"
+            } else {
+                ""
+            };
+            println!("    {} {} lines{}", spelling, lines, note);
+        }
+    }
+    if let Some(sql) = &explanation.sql {
+        println!("SQL:");
+        println!(
+            "  {}",
+            sql.sql.split_whitespace().collect::<Vec<_>>().join(" ")
+        );
+        println!("  parameters: {:?}", sql.params);
+    }
+    if let Some(profile) = &explanation.profile {
+        println!("Plan:");
+        for step in &profile.plan {
+            println!("  {}", step);
+        }
+        println!(
+            "{} rows, {} scanned, {} VM steps",
+            profile.rows, profile.rows_scanned, profile.vm_steps
+        );
+    }
+}
+
+fn print_grouped_hits(hits: &[SearchHit]) {
+    let groups = group_hits(hits.to_vec());
+    for show in &groups {
+        println!("{} ({} hits)", show.show_name, show.hit_count());
+        for episode in &show.episodes {
+            println!(
+                "  {} ({} hits){}",
+                episode_label(episode.season, episode.episode_number),
+                episode.hits.len(),
+                episode.hits[0]
+                    .source_url
+                    .as_ref()
+                    .map_or_else(String::new, |url| format!(" {}", url))
+            );
+            for hit in &episode.hits {
+                println!(
+                    "    [{}] {} {}",
+                    hit.transcript_id,
+                    hit.time_start,
+                    hit.text.replace('\n', " ")
+                );
+                if let Some(translation) = &hit.translation {
+                    println!("        {}", translation);
+                }
+            }
+        }
+    }
+    println!("{} hits in {} shows.", hits.len(), groups.len());
+}
+
+// `text` padded with spaces to `width` terminal columns, for tables with Japanese in them
+fn pad_columns(text: &str, width: usize) -> String {
+    format!(
+        "{}{}",
+        text,
+        " ".repeat(width.saturating_sub(columns(text)))
+    )
+}
+
+fn print_kwic(hits: &[SearchHit], matcher: &Matcher, width: usize) {
+    for hit in hits {
+        // Lines where the match can't be located again are shown from the start
+        let line = kwic(matcher, &hit.text, width)
+            .unwrap_or_else(|| KwicLine::new(&hit.text, 0..0, width));
+        println!(
+            "{}  [{}] {} {}",
+            line,
+            hit.transcript_id,
+            hit.show_name,
+            episode_label(hit.season, hit.episode_number)
+        );
+    }
+    println!("{} hits.", hits.len());
+}
+
+fn print_hits(hits: &[SearchHit]) {
+    for hit in hits {
+        println!(
+            "[{}] {} {} {} {}",
+            hit.transcript_id,
+            hit.show_name,
+            episode_label(hit.season, hit.episode_number),
+            hit.time_start,
+            hit.text.replace('\n', " ")
+        );
+        if let Some(translation) = &hit.translation {
+            println!("    {}", translation);
+        }
+    }
+    println!("{} hits.", hits.len());
+}
@@ -0,0 +1,231 @@
+//! A Model Context Protocol server, so local LLM assistants can search the
+//! corpus themselves through the `search_corpus` and `get_context` tools.
+//!
+//! Speaks JSON-RPC 2.0 over stdio, one message per line, as MCP clients such
+//! as Claude Desktop expect from a local server. Only the requests needed
+//! for tools are handled: `initialize`, `ping`, `tools/list` and `tools/call`.
+
+use crate::db::{DbHandler, SearchHit, TranscriptId};
+use crate::search::{self, SearchError};
+use crate::tokenizer::JapaneseTokenizer;
+use serde_json::{json, Value};
+use std::io::{self, BufRead, Write};
+
+/// The protocol revision this server implements.
+pub const PROTOCOL_VERSION: &str = "2024-11-05";
+
+// Hits returned by search_corpus when the call doesn't set a limit
+const DEFAULT_LIMIT: usize = 20;
+
+// JSON-RPC error codes
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+/// Answers MCP requests from one database.
+pub struct McpServer<'a> {
+    db: &'a DbHandler,
+    tokenizer: &'a JapaneseTokenizer,
+}
+
+impl<'a> McpServer<'a> {
+    pub fn new(db: &'a DbHandler, tokenizer: &'a JapaneseTokenizer) -> Self {
+        McpServer { db, tokenizer }
+    }
+
+    /// Reads requests from `input` until it ends, writing each response to `output`.
+    pub fn serve<R: BufRead, W: Write>(&self, input: R, mut output: W) -> io::Result<()> {
+        for line in input.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            if let Some(response) = self.handle(&line) {
+                writeln!(output, "{}", response)?;
+                output.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The response to one JSON-RPC message, or None for a notification.
+    pub fn handle(&self, message: &str) -> Option<String> {
+        let request: Value = match serde_json::from_str(message) {
+            Ok(request) => request,
+            Err(e) => return Some(error_response(Value::Null, PARSE_ERROR, &e.to_string())),
+        };
+        // Notifications, such as notifications/initialized, have no id and get no response
+        let id = request.get("id")?.clone();
+        let method = request["method"].as_str().unwrap_or_default();
+        let result = match method {
+            "initialize" => Ok(json!({
+                "protocolVersion": PROTOCOL_VERSION,
+                "capabilities": { "tools": {} },
+                "serverInfo": {
+                    "name": env!("CARGO_PKG_NAME"),
+                    "version": env!("CARGO_PKG_VERSION"),
+                },
+            })),
+            "ping" => Ok(json!({})),
+            "tools/list" => Ok(json!({ "tools": tools() })),
+            "tools/call" => self.call_tool(&request["params"]),
+            _ => Err((METHOD_NOT_FOUND, format!("Unknown method {:?}", method))),
+        };
+        Some(match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }).to_string(),
+            Err((code, message)) => error_response(id, code, &message),
+        })
+    }
+
+    // Runs a tool; failures of the tool itself are reported in the result, as MCP asks
+    fn call_tool(&self, params: &Value) -> Result<Value, (i64, String)> {
+        let arguments = &params["arguments"];
+        let output = match params["name"].as_str().unwrap_or_default() {
+            "search_corpus" => {
+                let query = arguments["query"]
+                    .as_str()
+                    .ok_or((INVALID_PARAMS, "search_corpus needs a query".to_string()))?;
+                let limit = arguments["limit"]
+                    .as_u64()
+                    .map_or(DEFAULT_LIMIT, |n| n as usize);
+                let regex = arguments["regex"].as_bool().unwrap_or(false);
+                self.search(query, regex, limit)
+            }
+            "get_context" => {
+                let id = arguments["id"]
+                    .as_i64()
+                    .ok_or((INVALID_PARAMS, "get_context needs a line id".to_string()))?;
+                let before = arguments["before"].as_u64().unwrap_or(5) as u32;
+                let after = arguments["after"].as_u64().unwrap_or(2) as u32;
+                self.db
+                    .find_context(TranscriptId(id), before, after)
+                    .map_err(SearchError::from)
+            }
+            name => return Err((INVALID_PARAMS, format!("Unknown tool {:?}", name))),
+        };
+        Ok(match output {
+            Ok(hits) => json!({
+                "content": [{ "type": "text", "text": serde_json::to_string(&hits).expect("hits serialize") }],
+            }),
+            Err(e) => json!({
+                "content": [{ "type": "text", "text": e.to_string() }],
+                "isError": true,
+            }),
+        })
+    }
+
+    fn search(&self, query: &str, regex: bool, limit: usize) -> search::Result<Vec<SearchHit>> {
+        let mut hits = if regex {
+            search::search_regex(self.db, query)?
+        } else {
+            search::search(self.db, self.tokenizer, query)?
+        };
+        hits.truncate(limit);
+        Ok(hits)
+    }
+}
+
+// The tools and their JSON Schema arguments, as listed by tools/list
+fn tools() -> Value {
+    json!([
+        {
+            "name": "search_corpus",
+            "description": "Search Japanese anime subtitle lines. Words match in any inflection \
+                (走る finds 走った); `word NEAR/N word` requires two words within N tokens. \
+                Returns the matching lines as JSON, each with an id for get_context.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "query": { "type": "string", "description": "Japanese words to find" },
+                    "limit": { "type": "integer", "minimum": 1, "description": "Most lines to return (default 20)" },
+                    "regex": { "type": "boolean", "description": "Treat the query as a regular expression over the line text" },
+                },
+                "required": ["query"],
+            },
+        },
+        {
+            "name": "get_context",
+            "description": "Get the subtitle lines around a line from search_corpus, in the same episode.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "id": { "type": "integer", "description": "The id of a line" },
+                    "before": { "type": "integer", "minimum": 0, "description": "Lines before it (default 5)" },
+                    "after": { "type": "integer", "minimum": 0, "description": "Lines after it (default 2)" },
+                },
+                "required": ["id"],
+            },
+        },
+    ])
+}
+
+fn error_response(id: Value, code: i64, message: &str) -> String {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } }).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_utils::test_db_with_lines;
+    use crate::tokenizer::test_utils::test_tokenizer;
+
+    fn call(server: &McpServer, request: Value) -> Value {
+        serde_json::from_str(&server.handle(&request.to_string()).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_mcp_tools() {
+        let (mut db, ids) = test_db_with_lines(&["犬が好き", "猫が走った", "走る"]);
+        let tokenizer = test_tokenizer();
+        db.index_transcripts(&tokenizer, &ids).unwrap();
+        let server = McpServer::new(&db, &tokenizer);
+
+        let response = call(
+            &server,
+            json!({"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {}}),
+        );
+        assert_eq!(response["result"]["protocolVersion"], PROTOCOL_VERSION);
+        let notification = json!({"jsonrpc": "2.0", "method": "notifications/initialized"});
+        assert_eq!(server.handle(&notification.to_string()), None);
+
+        let response = call(
+            &server,
+            json!({"jsonrpc": "2.0", "id": 2, "method": "tools/list"}),
+        );
+        assert_eq!(response["result"]["tools"][0]["name"], "search_corpus");
+
+        let response = call(
+            &server,
+            json!({"jsonrpc": "2.0", "id": 3, "method": "tools/call",
+                   "params": {"name": "search_corpus", "arguments": {"query": "走る", "limit": 1}}}),
+        );
+        let text = response["result"]["content"][0]["text"].as_str().unwrap();
+        let hits: Value = serde_json::from_str(text).unwrap();
+        assert_eq!(hits.as_array().unwrap().len(), 1);
+        assert_eq!(hits[0]["text"], "猫が走った");
+
+        let response = call(
+            &server,
+            json!({"jsonrpc": "2.0", "id": 4, "method": "tools/call",
+                   "params": {"name": "get_context", "arguments": {"id": ids[1].0, "before": 1, "after": 0}}}),
+        );
+        let text = response["result"]["content"][0]["text"].as_str().unwrap();
+        let lines: Value = serde_json::from_str(text).unwrap();
+        assert_eq!(lines[0]["text"], "犬が好き");
+
+        let response = call(
+            &server,
+            json!({"jsonrpc": "2.0", "id": 5, "method": "tools/call",
+                   "params": {"name": "search_corpus", "arguments": {"query": "(", "regex": true}}}),
+        );
+        assert_eq!(response["result"]["isError"], true);
+
+        let response = call(
+            &server,
+            json!({"jsonrpc": "2.0", "id": 6, "method": "resources/list"}),
+        );
+        assert_eq!(response["error"]["code"], METHOD_NOT_FOUND);
+        let response: Value = serde_json::from_str(&server.handle("{").unwrap()).unwrap();
+        assert_eq!(response["error"]["code"], PARSE_ERROR);
+    }
+}
@@ -0,0 +1,410 @@
+//! Links episodes to the video or audio files their subtitles belong to,
+//! for playing hits and exporting clips.
+
+use crate::db::{episode_label, DbHandler, EpisodeId, MediaFile};
+use crate::srt_parser::{is_translation_file, Timestamp};
+use crate::tokenizer::normalize;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use walkdir::WalkDir;
+
+const MEDIA_EXTENSIONS: &[&str] = &[
+    "mkv", "mp4", "avi", "webm", "mov", "m4v", "mp3", "m4a", "flac", "ogg", "opus", "wav",
+];
+
+// How much of the show name a file name must contain to be matched
+const MIN_SCORE: f64 = 0.6;
+
+// Audio kept before and after a line in clips, so its first and last sounds aren't cut off
+const CLIP_PADDING_MILLIS: u64 = 250;
+
+#[derive(Debug)]
+pub enum MediaError {
+    CsvError(csv::Error),
+    DbError(rusqlite::Error),
+    UnknownEpisode {
+        show: String,
+        season: i32,
+        episode: Option<i32>,
+    },
+}
+
+impl From<csv::Error> for MediaError {
+    fn from(error: csv::Error) -> Self {
+        MediaError::CsvError(error)
+    }
+}
+
+impl From<rusqlite::Error> for MediaError {
+    fn from(error: rusqlite::Error) -> Self {
+        MediaError::DbError(error)
+    }
+}
+
+impl fmt::Display for MediaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MediaError::CsvError(e) => write!(f, "Invalid CSV: {}", e),
+            MediaError::DbError(e) => write!(f, "Database error: {}", e),
+            MediaError::UnknownEpisode {
+                show,
+                season,
+                episode,
+            } => write!(
+                f,
+                "No episode {} {}",
+                show,
+                episode_label(*season, *episode)
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MediaError {}
+
+/// An episode to find a media file for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EpisodeToMatch {
+    pub episode_id: EpisodeId,
+    pub show_name: String,
+    pub season: i32,
+    /// `None` for a movie
+    pub episode_number: Option<i32>,
+    /// The Japanese subtitle file the episode was ingested from, if recorded
+    pub subtitle: Option<PathBuf>,
+}
+
+/// A media file proposed for an episode, with how sure the match is (0 to 1).
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaMatch {
+    pub episode_id: EpisodeId,
+    pub path: PathBuf,
+    pub score: f64,
+}
+
+impl MediaMatch {
+    pub fn to_media_file(&self) -> MediaFile {
+        MediaFile {
+            episode_id: self.episode_id,
+            path: self.path.to_string_lossy().into_owned(),
+        }
+    }
+}
+
+/// Whether `path` has the extension of a video or audio file.
+pub fn is_media_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| {
+            MEDIA_EXTENSIONS.contains(&extension.to_ascii_lowercase().as_str())
+        })
+}
+
+/// Every video or audio file under `root_dir`.
+pub fn find_media_files(root_dir: &Path) -> Vec<PathBuf> {
+    WalkDir::new(root_dir)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_file() && is_media_file(entry.path()))
+        .map(|entry| entry.into_path())
+        .collect()
+}
+
+/// Every episode in the database, with the subtitle file it came from.
+pub fn episodes_to_match(db: &DbHandler) -> rusqlite::Result<Vec<EpisodeToMatch>> {
+    let mut subtitles: HashMap<EpisodeId, PathBuf> = HashMap::new();
+    for (path, file) in db.source_files()? {
+        let path = PathBuf::from(path);
+        if !is_translation_file(&path) {
+            subtitles.insert(file.episode_id, path);
+        }
+    }
+    let mut episodes = Vec::new();
+    for show in db.list_shows()? {
+        for episode in db.list_episodes(show.id)? {
+            episodes.push(EpisodeToMatch {
+                episode_id: episode.id,
+                show_name: show.name.clone(),
+                season: episode.season,
+                episode_number: episode.episode_number,
+                subtitle: subtitles.remove(&episode.id),
+            });
+        }
+    }
+    Ok(episodes)
+}
+
+/// Proposes a media file for each episode that has a convincing one.
+///
+/// A media file named like the episode's subtitle file (`Ep 01.mkv` for
+/// `Ep 01.ja.srt`) is a sure match. Otherwise the file's name and folder must
+/// contain the episode number (for an episode, not a movie) and most of the
+/// show name. Each file is given to at most one episode, the best matches first.
+pub fn match_media(episodes: &[EpisodeToMatch], media: &[PathBuf]) -> Vec<MediaMatch> {
+    let mut candidates = Vec::new();
+    for episode in episodes {
+        let show = bigrams(&simplify(&episode.show_name));
+        let subtitle_stem = episode.subtitle.as_deref().map(subtitle_stem);
+        for path in media {
+            let stem = simplify(&file_stem(path));
+            let score = if subtitle_stem.as_deref() == Some(stem.as_str()) {
+                1.0
+            } else if episode
+                .episode_number
+                .is_none_or(|number| numbers(&file_stem(path)).contains(&number))
+            {
+                let folder = path
+                    .parent()
+                    .and_then(Path::file_name)
+                    .map(|name| simplify(&name.to_string_lossy()))
+                    .unwrap_or_default();
+                // Scaled below 1 so an exact name match always wins
+                0.9 * containment(&show, &bigrams(&format!("{} {}", folder, stem)))
+            } else {
+                0.0
+            };
+            if score >= MIN_SCORE * 0.9 {
+                candidates.push(MediaMatch {
+                    episode_id: episode.episode_id,
+                    path: path.clone(),
+                    score,
+                });
+            }
+        }
+    }
+    candidates.sort_by(|a, b| {
+        b.score
+            .total_cmp(&a.score)
+            .then_with(|| a.path.cmp(&b.path))
+    });
+
+    let mut matched_episodes = HashSet::new();
+    let mut matched_paths = HashSet::new();
+    candidates
+        .into_iter()
+        .filter(|candidate| {
+            // Claims the episode and the file once both are still free
+            !matched_episodes.contains(&candidate.episode_id)
+                && !matched_paths.contains(&candidate.path)
+                && matched_episodes.insert(candidate.episode_id)
+                && matched_paths.insert(candidate.path.clone())
+        })
+        .collect()
+}
+
+#[derive(Deserialize)]
+struct MediaRow {
+    show: String,
+    season: i32,
+    episode: Option<i32>,
+    path: PathBuf,
+}
+
+/// Reads explicit mappings from CSV with the header `show,season,episode,path`,
+/// the episode left empty for a movie.
+pub fn read_media_csv<R: Read>(db: &DbHandler, reader: R) -> Result<Vec<MediaMatch>, MediaError> {
+    let mut ids = HashMap::new();
+    for show in db.list_shows()? {
+        for episode in db.list_episodes(show.id)? {
+            ids.insert(
+                (show.name.clone(), episode.season, episode.episode_number),
+                episode.id,
+            );
+        }
+    }
+    let mut matches = Vec::new();
+    for row in csv::Reader::from_reader(reader).deserialize() {
+        let row: MediaRow = row?;
+        let episode_id = *ids
+            .get(&(row.show.clone(), row.season, row.episode))
+            .ok_or(MediaError::UnknownEpisode {
+                show: row.show,
+                season: row.season,
+                episode: row.episode,
+            })?;
+        matches.push(MediaMatch {
+            episode_id,
+            path: row.path,
+            score: 1.0,
+        });
+    }
+    Ok(matches)
+}
+
+/// The ffmpeg command writing the audio of `media` between two subtitle
+/// timestamps (padded a little on both sides) to stdout as MP3.
+pub fn audio_clip_command(media: &Path, time_start: &str, time_end: &str) -> io::Result<Command> {
+    let millis = |timestamp: &str| {
+        timestamp
+            .parse::<Timestamp>()
+            .map(|timestamp| timestamp.to_millis())
+            .map_err(|_| {
+                let message = format!("Invalid timestamp {:?}", timestamp);
+                io::Error::new(io::ErrorKind::InvalidInput, message)
+            })
+    };
+    let start = millis(time_start)?.saturating_sub(CLIP_PADDING_MILLIS);
+    let end = millis(time_end)?.max(start) + CLIP_PADDING_MILLIS;
+    let mut command = Command::new("ffmpeg");
+    command
+        .args(["-v", "error", "-ss"])
+        .arg(format!("{:.3}", start as f64 / 1000.0))
+        .arg("-i")
+        .arg(media)
+        .arg("-t")
+        .arg(format!("{:.3}", (end - start) as f64 / 1000.0))
+        .args([
+            "-vn",
+            "-ac",
+            "1",
+            "-c:a",
+            "libmp3lame",
+            "-b:a",
+            "96k",
+            "-f",
+            "mp3",
+            "pipe:1",
+        ]);
+    Ok(command)
+}
+
+/// Runs [`audio_clip_command`] and returns the MP3 data. Needs ffmpeg on the PATH.
+pub fn extract_audio_clip(media: &Path, time_start: &str, time_end: &str) -> io::Result<Vec<u8>> {
+    let output = audio_clip_command(media, time_start, time_end)?.output()?;
+    if !output.status.success() {
+        let message = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        return Err(io::Error::other(format!("ffmpeg failed: {}", message)));
+    }
+    Ok(output.stdout)
+}
+
+fn file_stem(path: &Path) -> String {
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+// The subtitle's file name without extension or language suffix, simplified
+fn subtitle_stem(path: &Path) -> String {
+    let stem = file_stem(path);
+    let stem = match stem.rsplit_once('.') {
+        Some((name, language)) if (2..=3).contains(&language.len()) => name.to_string(),
+        _ => stem,
+    };
+    simplify(&stem)
+}
+
+// Lowercased letters and digits, with runs of anything else collapsed to one space
+fn simplify(text: &str) -> String {
+    normalize(text)
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn numbers(text: &str) -> Vec<i32> {
+    normalize(text)
+        .split(|c: char| !c.is_ascii_digit())
+        .filter_map(|digits| digits.parse().ok())
+        .collect()
+}
+
+fn bigrams(text: &str) -> HashSet<(char, char)> {
+    let chars: Vec<char> = text.chars().filter(|c| !c.is_whitespace()).collect();
+    if chars.len() == 1 {
+        return HashSet::from([(chars[0], chars[0])]);
+    }
+    chars.windows(2).map(|pair| (pair[0], pair[1])).collect()
+}
+
+// The share of `needle`'s bigrams that also occur in `haystack`
+fn containment(needle: &HashSet<(char, char)>, haystack: &HashSet<(char, char)>) -> f64 {
+    if needle.is_empty() {
+        return 0.0;
+    }
+    needle.intersection(haystack).count() as f64 / needle.len() as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn episode(id: i64, show_name: &str, number: i32, subtitle: Option<&str>) -> EpisodeToMatch {
+        EpisodeToMatch {
+            episode_id: EpisodeId(id),
+            show_name: show_name.to_string(),
+            season: 1,
+            episode_number: Some(number),
+            subtitle: subtitle.map(PathBuf::from),
+        }
+    }
+
+    #[test]
+    fn test_audio_clip_command() {
+        let command =
+            audio_clip_command(Path::new("ep 1.mkv"), "00:01:02,100", "00:01:03,000").unwrap();
+        let args: Vec<&std::ffi::OsStr> = command.get_args().collect();
+        assert_eq!(args[3], "61.850");
+        assert_eq!(args[5], "ep 1.mkv");
+        assert_eq!(args[7], "1.400");
+        assert!(audio_clip_command(Path::new("ep 1.mkv"), "soon", "later").is_err());
+    }
+
+    #[test]
+    fn test_match_media() {
+        let episodes = [
+            episode(
+                1,
+                "Shirokuma Cafe",
+                1,
+                Some("/subs/Shirokuma/[Sub] 01.ja.srt"),
+            ),
+            episode(2, "Shirokuma Cafe", 2, None),
+            episode(3, "Yuru Camp", 2, None),
+            episode(4, "Unrelated", 3, None),
+        ];
+        let media: Vec<PathBuf> = [
+            "/videos/whatever/[Sub] 01.mkv",
+            "/videos/Shirokuma Cafe/Shirokuma Cafe - 02 [1080p].mkv",
+            "/videos/ゆるキャン/Yuru.Camp.S01E02.mp4",
+            "/videos/Other Show/Other Show - 03.mkv",
+        ]
+        .iter()
+        .map(PathBuf::from)
+        .collect();
+
+        let matches = match_media(&episodes, &media);
+        let pairs: HashMap<i64, &Path> = matches
+            .iter()
+            .map(|m| (m.episode_id.0, m.path.as_path()))
+            .collect();
+        assert_eq!(pairs.len(), 3);
+        assert_eq!(pairs[&1], media[0]);
+        assert_eq!(pairs[&2], media[1]);
+        assert_eq!(pairs[&3], media[2]);
+        assert_eq!(matches[0].score, 1.0);
+    }
+
+    #[test]
+    fn test_read_media_csv() {
+        let (db, _) = crate::db::test_utils::test_db_with_lines(&["猫"]);
+        let csv = "show,season,episode,path\nShow Name,1,1,/videos/a.mkv\n";
+        let matches = read_media_csv(&db, csv.as_bytes()).unwrap();
+        assert_eq!(matches[0].path, PathBuf::from("/videos/a.mkv"));
+
+        let csv = "show,season,episode,path\nShow Name,1,2,/videos/b.mkv\n";
+        assert!(matches!(
+            read_media_csv(&db, csv.as_bytes()),
+            Err(MediaError::UnknownEpisode {
+                episode: Some(2),
+                ..
+            })
+        ));
+    }
+}
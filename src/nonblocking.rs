@@ -0,0 +1,235 @@
+//! Async variants of the search functions, for embedding in async servers.
+//!
+//! SQLite calls block, so each function borrows a reader from a [`DbPool`] and
+//! runs the search on tokio's blocking thread pool via `spawn_blocking`.
+//! Requires the `async` feature.
+
+use crate::backend::BackendConfig;
+use crate::db::{
+    ApiKey, CorpusStats, DbHandler, DbPool, EpisodeStats, NewLoggedQuery, SearchHit, Show,
+    Suggestion, TranscriptId,
+};
+use crate::grammar::GrammarPattern;
+use crate::ingest::{self, IngestError, IngestedLines, ShowTypeConfig};
+use crate::query_log::{self, QueryLogConfig};
+use crate::sample::{self, SampleOptions};
+use crate::search::{self, Result, SearchError};
+use crate::tokenizer::JapaneseTokenizer;
+use std::ops::ControlFlow;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+// Lines read ahead of a slow consumer of search_streaming
+const STREAM_BUFFER: usize = 64;
+
+/// Async [`search::search`].
+pub async fn search(
+    pool: Arc<DbPool>,
+    tokenizer: Arc<JapaneseTokenizer>,
+    query: String,
+) -> Result<Vec<SearchHit>> {
+    with_reader(pool, move |db| search::search(db, &tokenizer, &query)).await
+}
+
+/// Streaming [`search::search`]: the matching lines arrive on the returned
+/// channel as they are read, so the first can be shown before the search ends.
+///
+/// The search stops early when the receiver is dropped. An error ends the
+/// stream; a panic inside the search closes the channel without one.
+pub fn search_streaming(
+    pool: Arc<DbPool>,
+    tokenizer: Arc<JapaneseTokenizer>,
+    query: String,
+) -> mpsc::Receiver<Result<SearchHit>> {
+    let (sender, receiver) = mpsc::channel(STREAM_BUFFER);
+    tokio::task::spawn_blocking(move || {
+        let result = pool.reader().map_err(SearchError::from).and_then(|db| {
+            search::search_each(&db, &tokenizer, &query, |hit| {
+                match sender.blocking_send(Ok(hit)) {
+                    Ok(()) => ControlFlow::Continue(()),
+                    Err(_) => ControlFlow::Break(()),
+                }
+            })
+        });
+        if let Err(e) = result {
+            let _ = sender.blocking_send(Err(e));
+        }
+    });
+    receiver
+}
+
+/// Async [`search::search_kanji`].
+pub async fn search_kanji(
+    pool: Arc<DbPool>,
+    tokenizer: Arc<JapaneseTokenizer>,
+    text: String,
+    within_token: bool,
+) -> Result<Vec<SearchHit>> {
+    with_reader(pool, move |db| {
+        search::search_kanji(db, &tokenizer, &text, within_token)
+    })
+    .await
+}
+
+/// Async [`search::search_regex`].
+pub async fn search_regex(pool: Arc<DbPool>, pattern: String) -> Result<Vec<SearchHit>> {
+    with_reader(pool, move |db| search::search_regex(db, &pattern)).await
+}
+
+/// Async [`search::search_grammar`].
+pub async fn search_grammar(
+    pool: Arc<DbPool>,
+    tokenizer: Arc<JapaneseTokenizer>,
+    pattern: &'static GrammarPattern,
+) -> Result<Vec<SearchHit>> {
+    with_reader(pool, move |db| {
+        search::search_grammar(db, &tokenizer, pattern)
+    })
+    .await
+}
+
+/// Async [`sample::sample`], using the thread-local random number generator.
+pub async fn sample(
+    pool: Arc<DbPool>,
+    tokenizer: Arc<JapaneseTokenizer>,
+    query: String,
+    options: SampleOptions,
+) -> Result<Vec<SearchHit>> {
+    with_reader(pool, move |db| {
+        sample::sample(db, &tokenizer, &query, &options, &mut rand::thread_rng())
+    })
+    .await
+}
+
+/// Async [`DbHandler::find_context`]: the lines around a hit in the same episode.
+pub async fn context(
+    pool: Arc<DbPool>,
+    transcript_id: TranscriptId,
+    before: u32,
+    after: u32,
+) -> Result<Vec<SearchHit>> {
+    with_reader(pool, move |db| {
+        Ok(db.find_context(transcript_id, before, after)?)
+    })
+    .await
+}
+
+/// Async [`DbHandler::list_shows`].
+pub async fn list_shows(pool: Arc<DbPool>) -> Result<Vec<Show>> {
+    with_reader(pool, |db| Ok(db.list_shows()?)).await
+}
+
+/// Async [`DbHandler::suggest`]: the most frequent indexed words starting with `prefix`.
+pub async fn suggest(pool: Arc<DbPool>, prefix: String, limit: usize) -> Result<Vec<Suggestion>> {
+    with_reader(pool, move |db| Ok(db.suggest(&prefix, limit)?)).await
+}
+
+/// Async [`DbHandler::corpus_stats`].
+pub async fn corpus_stats(pool: Arc<DbPool>, top: usize) -> Result<CorpusStats> {
+    with_reader(pool, move |db| Ok(db.corpus_stats(top)?)).await
+}
+
+/// Async [`DbHandler::episode_stats`].
+pub async fn episode_stats(pool: Arc<DbPool>, show: Option<String>) -> Result<Vec<EpisodeStats>> {
+    with_reader(pool, move |db| Ok(db.episode_stats(show.as_deref())?)).await
+}
+
+/// Async [`DbHandler::line_media_file`].
+pub async fn line_media_file(
+    pool: Arc<DbPool>,
+    transcript_id: TranscriptId,
+) -> Result<Option<String>> {
+    with_reader(pool, move |db| Ok(db.line_media_file(transcript_id)?)).await
+}
+
+/// Async [`DbHandler::find_api_key`].
+pub async fn find_api_key(pool: Arc<DbPool>, secret: String) -> Result<Option<ApiKey>> {
+    with_reader(pool, move |db| Ok(db.find_api_key(&secret)?)).await
+}
+
+/// Async [`ingest::ingest_directory`], using the pool's writer.
+pub async fn ingest_directory(
+    pool: Arc<DbPool>,
+    tokenizer: Arc<JapaneseTokenizer>,
+    backend: BackendConfig,
+    show_types: ShowTypeConfig,
+    root_dir: PathBuf,
+) -> std::result::Result<IngestedLines, IngestError> {
+    let task = tokio::task::spawn_blocking(move || {
+        let mut db = pool.writer();
+        ingest::ingest_directory(&mut db, &tokenizer, &backend, &show_types, &root_dir)
+    });
+    match task.await {
+        Ok(result) => result,
+        Err(e) => std::panic::resume_unwind(e.into_panic()),
+    }
+}
+
+/// Async [`query_log::log_search`], using the pool's writer.
+pub async fn log_search(
+    pool: Arc<DbPool>,
+    config: QueryLogConfig,
+    query: NewLoggedQuery,
+) -> Result<()> {
+    let task =
+        tokio::task::spawn_blocking(move || query_log::log_search(&pool.writer(), &config, &query));
+    match task.await {
+        Ok(result) => Ok(result?),
+        Err(e) => std::panic::resume_unwind(e.into_panic()),
+    }
+}
+
+// Runs `f` with a pooled reader on the blocking thread pool
+// A panic inside `f` is resumed on the calling task, as if `f` had run inline
+async fn with_reader<T, F>(pool: Arc<DbPool>, f: F) -> Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce(&DbHandler) -> Result<T> + Send + 'static,
+{
+    let task = tokio::task::spawn_blocking(move || f(&*pool.reader()?));
+    match task.await {
+        Ok(result) => result,
+        Err(e) => std::panic::resume_unwind(e.into_panic()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_utils::{insert_test_lines, TempDb};
+    use crate::tokenizer::test_utils::test_tokenizer;
+
+    #[tokio::test]
+    async fn test_async_search_and_context() {
+        let path = TempDb::new("nonblocking");
+        let pool = Arc::new(DbPool::open(&path.0, 2).unwrap());
+        let tokenizer = Arc::new(test_tokenizer());
+        {
+            let mut db = pool.writer();
+            let ids = insert_test_lines(&mut db, &["犬が好き", "猫が好き", "走った"]);
+            db.index_transcripts(&tokenizer, &ids).unwrap();
+        }
+
+        let hits = search(pool.clone(), tokenizer.clone(), "猫".to_string())
+            .await
+            .unwrap();
+        assert_eq!(hits.len(), 1);
+
+        let context = context(pool.clone(), hits[0].transcript_id, 1, 1)
+            .await
+            .unwrap();
+        let texts: Vec<&str> = context.iter().map(|hit| hit.text.as_str()).collect();
+        assert_eq!(texts, ["犬が好き", "猫が好き", "走った"]);
+
+        let hits = search_regex(pool.clone(), "走".to_string()).await.unwrap();
+        assert_eq!(hits.len(), 1);
+
+        let mut stream = search_streaming(pool, tokenizer, "好き".to_string());
+        let mut texts = Vec::new();
+        while let Some(hit) = stream.recv().await {
+            texts.push(hit.unwrap().text);
+        }
+        assert_eq!(texts, ["犬が好き", "猫が好き"]);
+    }
+}
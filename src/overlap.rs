@@ -0,0 +1,180 @@
+//! Vocabulary shared between shows, to help learners decide what to watch next.
+
+use crate::db::{DbHandler, WordFrequency};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+
+/// How [`compare_vocabulary`] counts words.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OverlapOptions {
+    /// Occurrences in the first show for a word to count as known from it
+    pub min_count: i64,
+    /// Number of new words of the second show to list
+    pub top_new_words: usize,
+}
+
+impl Default for OverlapOptions {
+    fn default() -> Self {
+        OverlapOptions {
+            min_count: 1,
+            top_new_words: 20,
+        }
+    }
+}
+
+/// The vocabulary of show B compared to show A.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct VocabularyOverlap {
+    pub show_a: String,
+    pub show_b: String,
+    /// Distinct words of show A
+    pub words_a: usize,
+    /// Distinct words of show B
+    pub words_b: usize,
+    /// Words in both shows
+    pub shared: usize,
+    /// Words only in show A
+    pub only_a: usize,
+    /// Words of show B not known from show A: what watching B after A adds
+    pub new_in_b: usize,
+    /// Percentage of show B's word occurrences known from show A
+    pub coverage_of_b: f64,
+    /// Show B's most frequent new words, with their number of occurrences in B
+    pub top_new_words: Vec<WordFrequency>,
+}
+
+#[derive(Debug)]
+pub enum OverlapError {
+    UnknownShow(String),
+    DbError(rusqlite::Error),
+}
+
+impl From<rusqlite::Error> for OverlapError {
+    fn from(error: rusqlite::Error) -> Self {
+        OverlapError::DbError(error)
+    }
+}
+
+impl fmt::Display for OverlapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OverlapError::UnknownShow(name) => write!(f, "No show named {:?}", name),
+            OverlapError::DbError(e) => write!(f, "Database error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for OverlapError {}
+
+/// Compares the indexed vocabulary of two shows.
+pub fn compare_vocabulary(
+    db: &DbHandler,
+    show_a: &str,
+    show_b: &str,
+    options: &OverlapOptions,
+) -> Result<VocabularyOverlap, OverlapError> {
+    let counts = |show: &str| {
+        db.show_word_counts(show)?
+            .ok_or_else(|| OverlapError::UnknownShow(show.to_string()))
+    };
+    Ok(overlap(
+        show_a,
+        &counts(show_a)?,
+        show_b,
+        &counts(show_b)?,
+        options,
+    ))
+}
+
+// The set operations behind compare_vocabulary
+fn overlap(
+    show_a: &str,
+    counts_a: &HashMap<String, i64>,
+    show_b: &str,
+    counts_b: &HashMap<String, i64>,
+    options: &OverlapOptions,
+) -> VocabularyOverlap {
+    let known = |word: &String| {
+        counts_a
+            .get(word)
+            .is_some_and(|&count| count >= options.min_count)
+    };
+    let shared = counts_b
+        .keys()
+        .filter(|word| counts_a.contains_key(*word))
+        .count();
+
+    let mut new_words: Vec<WordFrequency> = counts_b
+        .iter()
+        .filter(|(word, _)| !known(word))
+        .map(|(word, &frequency)| WordFrequency {
+            word: word.clone(),
+            frequency,
+        })
+        .collect();
+    let occurrences_b: i64 = counts_b.values().sum();
+    let new_occurrences: i64 = new_words.iter().map(|word| word.frequency).sum();
+    let coverage_of_b = if occurrences_b == 0 {
+        0.0
+    } else {
+        (occurrences_b - new_occurrences) as f64 * 100.0 / occurrences_b as f64
+    };
+    let new_in_b = new_words.len();
+    new_words.sort_by(|a, b| {
+        b.frequency
+            .cmp(&a.frequency)
+            .then_with(|| a.word.cmp(&b.word))
+    });
+    new_words.truncate(options.top_new_words);
+
+    VocabularyOverlap {
+        show_a: show_a.to_string(),
+        show_b: show_b.to_string(),
+        words_a: counts_a.len(),
+        words_b: counts_b.len(),
+        shared,
+        only_a: counts_a.len() - shared,
+        new_in_b,
+        coverage_of_b,
+        top_new_words: new_words,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn counts(words: &[(&str, i64)]) -> HashMap<String, i64> {
+        words
+            .iter()
+            .map(|&(word, count)| (word.to_string(), count))
+            .collect()
+    }
+
+    #[test]
+    fn test_overlap() {
+        let a = counts(&[("猫", 5), ("犬", 1), ("走る", 2)]);
+        let b = counts(&[("猫", 3), ("犬", 2), ("魚", 4), ("鳥", 1)]);
+
+        let result = overlap("A", &a, "B", &b, &OverlapOptions::default());
+        assert_eq!((result.words_a, result.words_b), (3, 4));
+        assert_eq!((result.shared, result.only_a, result.new_in_b), (2, 1, 2));
+        assert_eq!(result.coverage_of_b, 50.0);
+        let top: Vec<&str> = result
+            .top_new_words
+            .iter()
+            .map(|w| w.word.as_str())
+            .collect();
+        assert_eq!(top, ["魚", "鳥"]);
+
+        // A word seen once in A isn't known yet with a higher threshold
+        let options = OverlapOptions {
+            min_count: 2,
+            top_new_words: 1,
+        };
+        let result = overlap("A", &a, "B", &b, &options);
+        assert_eq!(result.new_in_b, 3);
+        assert_eq!(result.top_new_words.len(), 1);
+    }
+}
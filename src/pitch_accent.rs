@@ -0,0 +1,81 @@
+//! Pitch-accent annotation of transcript lines, backed by the `pitch_accents` table.
+
+use crate::db::DbHandler;
+use crate::tokenizer::{JapaneseTokenizer, Token};
+use rusqlite::Result;
+use std::fmt;
+
+/// A token together with the accent pattern of its dictionary form, if known.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnnotatedToken {
+    pub token: Token,
+    /// Accent as listed in the dictionary: the downstep mora, 0 for heiban, comma-separated when several
+    pub accent: Option<String>,
+}
+
+/// A line of text split into annotated tokens. Displays as `猫[1]が走った[2]`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnnotatedLine(pub Vec<AnnotatedToken>);
+
+impl fmt::Display for AnnotatedLine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for annotated in &self.0 {
+            write!(f, "{}", annotated.token.surface)?;
+            if let Some(accent) = &annotated.accent {
+                write!(f, "[{}]", accent)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Tokenizes `text` and looks up the accent pattern of every token.
+///
+/// Conjugated words are annotated with the accent of their dictionary form.
+pub fn annotate_pitch_accent(
+    db: &DbHandler,
+    tokenizer: &JapaneseTokenizer,
+    text: &str,
+) -> Result<AnnotatedLine> {
+    let tokens = tokenizer
+        .tokenize(text)
+        .into_iter()
+        .map(|token| {
+            let accent = db.pitch_accent(&token.base_form, token.base_reading().as_deref())?;
+            Ok(AnnotatedToken { token, accent })
+        })
+        .collect::<Result<_>>()?;
+    Ok(AnnotatedLine(tokens))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_utils::test_db_with_lines;
+    use crate::tokenizer::test_utils::test_tokenizer;
+
+    #[test]
+    fn test_annotate_pitch_accent() {
+        let (mut db, _) = test_db_with_lines(&[]);
+        db.import_pitch_accents("猫\tねこ\t1\n走る\tはしる\t2\n".as_bytes())
+            .unwrap();
+
+        let line = annotate_pitch_accent(&db, &test_tokenizer(), "猫が走った").unwrap();
+        assert_eq!(line.0[0].accent.as_deref(), Some("1"));
+        assert_eq!(line.0[1].accent, None);
+        assert_eq!(line.to_string(), "猫[1]が走っ[2]た");
+    }
+
+    #[test]
+    fn test_inflected_words_use_the_base_reading() {
+        let (mut db, _) = test_db_with_lines(&[]);
+        // Without a matching reading, そうる would be picked, as it sorts first
+        db.import_pitch_accents("走る\tそうる\t0\n走る\tはしる\t2\n".as_bytes())
+            .unwrap();
+
+        // 走っ reads ハシッ; its dictionary form 走る reads はしる
+        let line = annotate_pitch_accent(&db, &test_tokenizer(), "走った").unwrap();
+        assert_eq!(line.0[0].token.surface, "走っ");
+        assert_eq!(line.0[0].accent.as_deref(), Some("2"));
+    }
+}
@@ -0,0 +1,253 @@
+//! Plays a line's episode in mpv from just before the line, for reviewing hits.
+//!
+//! With `ipc_socket` set, an mpv already listening on that socket is told to
+//! load the file and seek (Unix only); otherwise a new mpv is started, itself
+//! listening on the socket so later hits reuse its window.
+
+use crate::db::{DbHandler, TranscriptId};
+use crate::srt_parser::Timestamp;
+use serde::Deserialize;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// `[player]` section of the config file.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PlayerConfig {
+    /// The mpv executable
+    pub mpv_path: PathBuf,
+    /// Seconds to start before the line, to hear it in context
+    pub lead_in: f64,
+    /// mpv's JSON IPC socket, for reusing a running player
+    pub ipc_socket: Option<PathBuf>,
+    /// Extra command-line arguments for a newly started mpv
+    pub extra_args: Vec<String>,
+}
+
+impl Default for PlayerConfig {
+    fn default() -> Self {
+        PlayerConfig {
+            mpv_path: PathBuf::from("mpv"),
+            lead_in: 2.0,
+            ipc_socket: None,
+            extra_args: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum PlayerError {
+    /// No line has this id
+    UnknownLine(TranscriptId),
+    /// The line's episode has no media file; see `map-media`
+    NoMediaFile(TranscriptId),
+    InvalidTimestamp(String),
+    DbError(rusqlite::Error),
+    IoError(std::io::Error),
+}
+
+impl From<rusqlite::Error> for PlayerError {
+    fn from(error: rusqlite::Error) -> Self {
+        PlayerError::DbError(error)
+    }
+}
+
+impl From<std::io::Error> for PlayerError {
+    fn from(error: std::io::Error) -> Self {
+        PlayerError::IoError(error)
+    }
+}
+
+impl fmt::Display for PlayerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlayerError::UnknownLine(id) => write!(f, "No line with id {}", id),
+            PlayerError::NoMediaFile(id) => write!(
+                f,
+                "The episode of line {} has no media file; run `map-media` first",
+                id
+            ),
+            PlayerError::InvalidTimestamp(timestamp) => {
+                write!(f, "Invalid timestamp {:?}", timestamp)
+            }
+            PlayerError::DbError(e) => write!(f, "Database error: {}", e),
+            PlayerError::IoError(e) => write!(f, "Failed to run mpv: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for PlayerError {}
+
+/// How [`play`] reached mpv.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Playback {
+    /// A new mpv process was started
+    Started,
+    /// A running mpv was told to play the line
+    Reused,
+}
+
+/// Plays the episode of a line from `lead_in` seconds before the line starts.
+pub fn play(
+    db: &DbHandler,
+    transcript_id: TranscriptId,
+    config: &PlayerConfig,
+) -> Result<Playback, PlayerError> {
+    let line = db
+        .get_transcript(transcript_id)?
+        .ok_or(PlayerError::UnknownLine(transcript_id))?;
+    let media = db
+        .media_file(line.episode_id)?
+        .ok_or(PlayerError::NoMediaFile(transcript_id))?;
+    let start = start_seconds(&line.time_start, config.lead_in)?;
+    play_media(Path::new(&media), start, config)
+}
+
+/// Plays `media` from `start` seconds, reusing a running mpv if possible.
+pub fn play_media(
+    media: &Path,
+    start: f64,
+    config: &PlayerConfig,
+) -> Result<Playback, PlayerError> {
+    #[cfg(unix)]
+    if let Some(socket) = &config.ipc_socket {
+        // A stale socket file from an mpv that has exited refuses connections
+        if let Ok(stream) = std::os::unix::net::UnixStream::connect(socket) {
+            ipc::play(stream, media, start)?;
+            return Ok(Playback::Reused);
+        }
+    }
+    let mut command = Command::new(&config.mpv_path);
+    command.arg(format!("--start={:.3}", start));
+    if let Some(socket) = &config.ipc_socket {
+        command.arg(format!("--input-ipc-server={}", socket.display()));
+    }
+    command.args(&config.extra_args).arg("--").arg(media);
+    command.spawn()?;
+    Ok(Playback::Started)
+}
+
+/// Seconds into the media at which to start playing a line starting at `time_start`.
+pub fn start_seconds(time_start: &str, lead_in: f64) -> Result<f64, PlayerError> {
+    let timestamp: Timestamp = time_start
+        .parse()
+        .map_err(|_| PlayerError::InvalidTimestamp(time_start.to_string()))?;
+    Ok((timestamp.to_millis() as f64 / 1000.0 - lead_in).max(0.0))
+}
+
+#[cfg(unix)]
+mod ipc {
+    use serde_json::{json, Value};
+    use std::io::{self, BufRead, BufReader, Write};
+    use std::os::unix::net::UnixStream;
+    use std::path::Path;
+
+    // Seeks if mpv is already playing `media`, and otherwise loads it from `start`
+    pub(super) fn play(stream: UnixStream, media: &Path, start: f64) -> io::Result<()> {
+        let mut client = Client {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+            next_id: 1,
+        };
+        let media = media.to_string_lossy();
+        let playing = client.request(json!(["get_property", "path"]))?;
+        if playing.as_str() == Some(media.as_ref()) {
+            client.request(json!(["seek", start, "absolute"]))?;
+        } else {
+            // `start` applies to the next file loaded
+            client.request(json!(["set_property", "start", format!("{:.3}", start)]))?;
+            client.request(json!(["loadfile", media, "replace"]))?;
+        }
+        client.request(json!(["set_property", "pause", false]))?;
+        Ok(())
+    }
+
+    struct Client {
+        reader: BufReader<UnixStream>,
+        writer: UnixStream,
+        next_id: u64,
+    }
+
+    impl Client {
+        // Sends a command and returns its `data`, or Null if the command failed
+        fn request(&mut self, command: Value) -> io::Result<Value> {
+            let request_id = self.next_id;
+            self.next_id += 1;
+            let message = json!({ "command": command, "request_id": request_id });
+            writeln!(self.writer, "{}", message)?;
+            // Events are interleaved with replies, so skip lines until ours arrives
+            let mut line = String::new();
+            loop {
+                line.clear();
+                if self.reader.read_line(&mut line)? == 0 {
+                    return Err(io::ErrorKind::UnexpectedEof.into());
+                }
+                let Ok(reply) = serde_json::from_str::<Value>(&line) else {
+                    continue;
+                };
+                if reply["request_id"] == request_id {
+                    return Ok(reply.get("data").cloned().unwrap_or(Value::Null));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_start_seconds() {
+        assert_eq!(start_seconds("00:01:02,500", 2.0).unwrap(), 60.5);
+        assert_eq!(start_seconds("00:00:01,000", 2.0).unwrap(), 0.0);
+        assert!(start_seconds("soon", 2.0).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_play_reuses_running_mpv() {
+        use serde_json::Value;
+        use std::io::{BufRead, BufReader, Write};
+        use std::os::unix::net::UnixListener;
+
+        let socket = std::env::temp_dir().join(format!("player-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&socket);
+        let listener = UnixListener::bind(&socket).unwrap();
+        // A fake mpv that is playing /videos/a.mkv and records the commands it gets
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut writer = stream.try_clone().unwrap();
+            let mut commands = Vec::new();
+            for line in BufReader::new(stream).lines() {
+                let request: Value = serde_json::from_str(&line.unwrap()).unwrap();
+                let data = if request["command"][0] == "get_property" {
+                    Value::from("/videos/a.mkv")
+                } else {
+                    Value::Null
+                };
+                writeln!(writer, r#"{{"event":"playback-restart"}}"#).unwrap();
+                let reply = serde_json::json!({
+                    "request_id": request["request_id"],
+                    "data": data,
+                    "error": "success",
+                });
+                writeln!(writer, "{}", reply).unwrap();
+                commands.push(request["command"].clone());
+            }
+            commands
+        });
+
+        let config = PlayerConfig {
+            ipc_socket: Some(socket.clone()),
+            ..PlayerConfig::default()
+        };
+        let playback = play_media(Path::new("/videos/a.mkv"), 60.5, &config).unwrap();
+        assert_eq!(playback, Playback::Reused);
+        let commands = server.join().unwrap();
+        std::fs::remove_file(&socket).unwrap();
+        assert_eq!(commands[1], serde_json::json!(["seek", 60.5, "absolute"]));
+        assert_eq!(commands.len(), 3);
+    }
+}
@@ -0,0 +1,77 @@
+//! Optional instrumentation recording searches in the `query_log` table.
+//!
+//! Each `search` on the command line, and each JSON `/search` the server
+//! answers, can be logged with its filters, hit count and latency, for
+//! finding slow queries (`query-log --slow`) and listing recent searches.
+//! Off unless `[query_log]` enables it.
+
+use crate::db::{DbHandler, NewLoggedQuery};
+use serde::Deserialize;
+use std::time::Duration;
+
+/// The `[query_log]` config section.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QueryLogConfig {
+    /// Record searches at all
+    pub enabled: bool,
+    /// Only record searches that took at least this long, to keep just the slow ones
+    pub min_millis: u64,
+}
+
+impl QueryLogConfig {
+    /// Whether a search that took `latency` should be recorded.
+    pub fn should_log(&self, latency: Duration) -> bool {
+        self.enabled && latency >= Duration::from_millis(self.min_millis)
+    }
+}
+
+/// Records a search in `db` if `config` asks for it.
+pub fn log_search(
+    db: &DbHandler,
+    config: &QueryLogConfig,
+    query: &NewLoggedQuery,
+) -> rusqlite::Result<()> {
+    if config.should_log(Duration::from_micros(query.latency_micros)) {
+        db.log_query(query)?;
+    }
+    Ok(())
+}
+
+/// A latency in whole microseconds, as stored in the query log.
+pub fn latency_micros(latency: Duration) -> u64 {
+    latency.as_micros().try_into().unwrap_or(u64::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_utils::test_db_with_lines;
+
+    #[test]
+    fn test_log_search_only_when_enabled_and_slow() {
+        let (db, _) = test_db_with_lines(&[]);
+        let config = QueryLogConfig {
+            enabled: true,
+            min_millis: 10,
+        };
+        let log = |config: &QueryLogConfig, millis| {
+            let query = NewLoggedQuery {
+                query: "猫".to_string(),
+                kind: "words".to_string(),
+                filters: String::new(),
+                hits: 3,
+                latency_micros: latency_micros(Duration::from_millis(millis)),
+            };
+            log_search(&db, config, &query).unwrap()
+        };
+        log(&QueryLogConfig::default(), 50);
+        log(&config, 5);
+        log(&config, 20);
+
+        let logged = db.recent_queries(10).unwrap();
+        assert_eq!(logged.len(), 1);
+        assert_eq!(logged[0].latency_micros, 20_000);
+        assert_eq!(logged[0].hits, 3);
+    }
+}
@@ -0,0 +1,503 @@
+//! Picking the most interesting or memorable lines out of a set of hits.
+//!
+//! A [`Ranker`] does the picking: the LLM set in `[llm]`, or a heuristic
+//! scorer that needs no network and always gives the same order. Unless
+//! `[ranking]` picks one, the heuristics are used when no LLM is configured,
+//! so `memorable` works offline too.
+//!
+//! [`LearnabilityScorer`] orders hits another way, for `search --sort
+//! learnable`: by how easy a line is to learn from for a given user.
+
+use crate::db::{DbHandler, SearchHit, TranscriptId, COMMON_WORD_COUNT};
+use crate::llm::{open_cached_provider, rank_memorable, LlmConfig, LlmError, LlmProvider, Result};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Orders hits by how interesting they are.
+pub trait Ranker {
+    /// Identifies the ranker, e.g. `heuristic` or the LLM's provider and model.
+    fn name(&self) -> String;
+
+    /// The `count` most interesting of `hits`, most interesting first.
+    fn rank(&self, db: &DbHandler, hits: &[SearchHit], count: usize) -> Result<Vec<SearchHit>>;
+}
+
+/// Opens the ranker `config` selects. `auto` opens the LLM ranker if `[llm]`
+/// sets a provider, or the heuristic ranker if it doesn't (or the crate was
+/// built without LLM providers). LLM replies are cached in the database at `db_path`.
+pub fn open_ranker(
+    config: &RankingConfig,
+    llm: &LlmConfig,
+    db_path: &Path,
+) -> Result<Box<dyn Ranker>> {
+    let heuristic = || Box::new(HeuristicRanker::new(config.heuristic.clone()));
+    if config.ranker == RankerKind::Heuristic {
+        return Ok(heuristic());
+    }
+    match open_cached_provider(llm, db_path) {
+        Ok(provider) => Ok(Box::new(LlmRanker::new(provider, llm.prompt_tokens))),
+        Err(LlmError::NotConfigured | LlmError::NotCompiled)
+            if config.ranker == RankerKind::Auto =>
+        {
+            Ok(heuristic())
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Asks an LLM, with each hit's context (see [`rank_memorable`]).
+pub struct LlmRanker<P> {
+    provider: P,
+    prompt_tokens: usize,
+}
+
+impl<P: LlmProvider> LlmRanker<P> {
+    pub fn new(provider: P, prompt_tokens: usize) -> Self {
+        LlmRanker {
+            provider,
+            prompt_tokens,
+        }
+    }
+}
+
+impl<P: LlmProvider> Ranker for LlmRanker<P> {
+    fn name(&self) -> String {
+        self.provider.name()
+    }
+
+    fn rank(&self, db: &DbHandler, hits: &[SearchHit], count: usize) -> Result<Vec<SearchHit>> {
+        rank_memorable(&self.provider, db, hits, count, self.prompt_tokens)
+    }
+}
+
+/// Which [`Ranker`] to use.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RankerKind {
+    /// The LLM if `[llm]` sets a provider, otherwise the heuristics
+    #[default]
+    Auto,
+    Llm,
+    /// The heuristic scorer, for deterministic, reproducible output
+    Heuristic,
+}
+
+impl FromStr for RankerKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(RankerKind::Auto),
+            "llm" => Ok(RankerKind::Llm),
+            "heuristic" => Ok(RankerKind::Heuristic),
+            _ => Err(format!(
+                "unknown ranker {:?} (expected auto, llm or heuristic)",
+                s
+            )),
+        }
+    }
+}
+
+/// The `[ranking]` config section.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RankingConfig {
+    pub ranker: RankerKind,
+    pub heuristic: HeuristicWeights,
+    pub learnability: LearnabilityWeights,
+}
+
+/// What [`HeuristicRanker`] scores lines by, set in `[ranking.heuristic]`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HeuristicWeights {
+    /// Lines from this many characters up to long_line score the full point for length;
+    /// shorter and longer ones score less
+    pub short_line: usize,
+    pub long_line: usize,
+    /// Points per rare word, for up to max_rare_words of them
+    pub rare_word: f64,
+    pub max_rare_words: usize,
+    /// Words outside this many of the corpus's most frequent count as rare
+    pub common_words: usize,
+    /// Points per emotional punctuation mark (！？…～), for up to max_punctuation of them
+    pub punctuation: f64,
+    pub max_punctuation: usize,
+    /// Points for ending in an exclamation
+    pub exclamation: f64,
+    /// Points for a complete sentence rather than a fragment ending in て, けど, 、...
+    pub complete_sentence: f64,
+    /// Points taken from lines containing one of boilerplate_phrases
+    pub boilerplate: f64,
+    /// Text marking lines that aren't dialogue, such as next-episode previews
+    pub boilerplate_phrases: Vec<String>,
+}
+
+impl Default for HeuristicWeights {
+    fn default() -> Self {
+        HeuristicWeights {
+            short_line: 8,
+            long_line: 30,
+            rare_word: 0.5,
+            max_rare_words: 3,
+            common_words: COMMON_WORD_COUNT,
+            punctuation: 0.25,
+            max_punctuation: 3,
+            exclamation: 0.5,
+            complete_sentence: 0.5,
+            boilerplate: 3.0,
+            boilerplate_phrases: [
+                "次回予告",
+                "次回、",
+                "この番組は",
+                "ご覧のスポンサー",
+                "提供",
+            ]
+            .map(str::to_string)
+            .to_vec(),
+        }
+    }
+}
+
+// Endings of fragments that continue in the next line
+const FRAGMENT_ENDINGS: &[&str] = &[
+    "、",
+    ",",
+    "て",
+    "で",
+    "が",
+    "けど",
+    "けれど",
+    "から",
+    "ので",
+    "のに",
+    "し",
+    "と",
+    "たら",
+    "ば",
+];
+
+/// Whether a line reads as a whole sentence: it ends in sentence-final
+/// punctuation, or at least doesn't end like a fragment (in a comma or a
+/// conjunctive form such as 〜て or 〜けど).
+pub fn is_complete_sentence(text: &str) -> bool {
+    let text = text.trim_end();
+    if text.ends_with(['。', '！', '？', '!', '?', '…', '」', '』']) {
+        return true;
+    }
+    !text.is_empty() && !FRAGMENT_ENDINGS.iter().any(|ending| text.ends_with(ending))
+}
+
+/// Scores lines without a model, by the sum of what [`HeuristicWeights`]
+/// gives points for. Ties keep the hits' order, so the output only changes
+/// when the corpus or the weights do.
+#[derive(Debug, Clone, Default)]
+pub struct HeuristicRanker {
+    weights: HeuristicWeights,
+}
+
+impl HeuristicRanker {
+    pub fn new(weights: HeuristicWeights) -> Self {
+        HeuristicRanker { weights }
+    }
+
+    /// The score of a line with `rare_words` rare words; higher is more interesting.
+    pub fn score(&self, text: &str, rare_words: usize) -> f64 {
+        let weights = &self.weights;
+        let length = text.chars().filter(|c| !c.is_whitespace()).count();
+        let length_score = if length < weights.short_line {
+            length as f64 / weights.short_line as f64
+        } else if length > weights.long_line {
+            weights.long_line as f64 / length as f64
+        } else {
+            1.0
+        };
+        let punctuation = text
+            .chars()
+            .filter(|c| matches!(c, '！' | '？' | '!' | '?' | '…' | '～' | '〜'))
+            .count()
+            .min(weights.max_punctuation);
+        let mut score = length_score
+            + weights.rare_word * rare_words.min(weights.max_rare_words) as f64
+            + weights.punctuation * punctuation as f64;
+        if text.trim_end().ends_with(['！', '!']) {
+            score += weights.exclamation;
+        }
+        if is_complete_sentence(text) {
+            score += weights.complete_sentence;
+        }
+        if weights
+            .boilerplate_phrases
+            .iter()
+            .any(|phrase| text.contains(phrase.as_str()))
+        {
+            score -= weights.boilerplate;
+        }
+        score
+    }
+}
+
+impl Ranker for HeuristicRanker {
+    fn name(&self) -> String {
+        "heuristic".to_string()
+    }
+
+    fn rank(&self, db: &DbHandler, hits: &[SearchHit], count: usize) -> Result<Vec<SearchHit>> {
+        let ids: Vec<TranscriptId> = hits.iter().map(|hit| hit.transcript_id).collect();
+        let rare_words: HashMap<TranscriptId, usize> =
+            db.rare_word_counts(&ids, self.weights.common_words)?;
+        let mut scored: Vec<(f64, &SearchHit)> = hits
+            .iter()
+            .map(|hit| {
+                let rare = rare_words.get(&hit.transcript_id).copied().unwrap_or(0);
+                (self.score(&hit.text, rare), hit)
+            })
+            .collect();
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+        Ok(scored
+            .into_iter()
+            .take(count)
+            .map(|(_, hit)| hit.clone())
+            .collect())
+    }
+}
+
+/// How search hits are ordered.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SortOrder {
+    /// As the search found them
+    #[default]
+    Relevance,
+    /// Easiest to learn from first (see [`LearnabilityScorer`])
+    Learnable,
+}
+
+impl fmt::Display for SortOrder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SortOrder::Relevance => "relevance",
+            SortOrder::Learnable => "learnable",
+        })
+    }
+}
+
+impl FromStr for SortOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "relevance" => Ok(SortOrder::Relevance),
+            "learnable" => Ok(SortOrder::Learnable),
+            _ => Err(format!(
+                "unknown sort order {:?} (expected relevance or learnable)",
+                s
+            )),
+        }
+    }
+}
+
+/// What [`LearnabilityScorer`] scores lines by, set in `[ranking.learnability]`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LearnabilityWeights {
+    /// Lines up to this many characters score the full point for length; longer
+    /// ones score less the longer they are
+    pub short_line: usize,
+    /// Points for a line whose words are all known, less for fewer
+    pub known_word: f64,
+    /// Points for a line whose words are all among common_words most frequent, less
+    /// for fewer
+    pub common_word: f64,
+    pub common_words: usize,
+    /// Points for containing one of the query's collocations most frequent collocations
+    pub collocation: f64,
+    pub collocations: usize,
+    /// Encounters in watched episodes for a word to count as known
+    pub known_encounters: i64,
+}
+
+impl Default for LearnabilityWeights {
+    fn default() -> Self {
+        LearnabilityWeights {
+            short_line: 12,
+            known_word: 2.0,
+            common_word: 1.0,
+            common_words: COMMON_WORD_COUNT,
+            collocation: 0.5,
+            collocations: 10,
+            known_encounters: 5,
+        }
+    }
+}
+
+/// Scores lines by how easy they are to learn from: short lines made of
+/// words the user knows or the corpus uses a lot, that use the query word the
+/// way it's most often used. Ties keep the hits' order.
+#[derive(Debug, Clone, Default)]
+pub struct LearnabilityScorer {
+    weights: LearnabilityWeights,
+    known: HashSet<String>,
+    collocations: Vec<String>,
+}
+
+impl LearnabilityScorer {
+    /// A scorer counting `known` words as known, and giving points to lines
+    /// containing one of `collocations` (see [`crate::collocations`]).
+    pub fn new(
+        weights: LearnabilityWeights,
+        known: HashSet<String>,
+        collocations: Vec<String>,
+    ) -> Self {
+        LearnabilityScorer {
+            weights,
+            known,
+            collocations,
+        }
+    }
+
+    /// The score of a line with the indexed `words`, of which `common` are among
+    /// the corpus's most frequent; higher is easier.
+    pub fn score(&self, text: &str, words: &[String], common: usize) -> f64 {
+        let weights = &self.weights;
+        let length = text.chars().filter(|c| !c.is_whitespace()).count();
+        let mut score = if length > weights.short_line {
+            weights.short_line as f64 / length as f64
+        } else {
+            1.0
+        };
+        if !words.is_empty() {
+            let known = words
+                .iter()
+                .filter(|word| self.known.contains(*word))
+                .count();
+            score += weights.known_word * known as f64 / words.len() as f64;
+            score += weights.common_word * common.min(words.len()) as f64 / words.len() as f64;
+        }
+        if self
+            .collocations
+            .iter()
+            .any(|collocation| text.contains(collocation.as_str()))
+        {
+            score += weights.collocation;
+        }
+        score
+    }
+
+    /// `hits` ordered easiest first.
+    pub fn sort(&self, db: &DbHandler, hits: &mut [SearchHit]) -> rusqlite::Result<()> {
+        let ids: Vec<TranscriptId> = hits.iter().map(|hit| hit.transcript_id).collect();
+        let words = db.line_words(&ids)?;
+        let common = db.common_words(self.weights.common_words)?;
+        let scores: HashMap<TranscriptId, f64> = hits
+            .iter()
+            .map(|hit| {
+                let words = words.get(&hit.transcript_id).map_or(&[][..], Vec::as_slice);
+                let common = words.iter().filter(|word| common.contains(*word)).count();
+                (hit.transcript_id, self.score(&hit.text, words, common))
+            })
+            .collect();
+        hits.sort_by(|a, b| scores[&b.transcript_id].total_cmp(&scores[&a.transcript_id]));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_utils::test_db_with_lines;
+    use crate::llm::test_utils::FakeProvider;
+    use crate::tokenizer::test_utils::test_tokenizer;
+
+    #[test]
+    fn test_heuristic_ranker() {
+        let (db, ids) = test_db_with_lines(&[
+            "猫",
+            "猫が走ったけど",
+            "なんで猫がこんなところにいるんだよ！",
+            "猫が走った…？",
+            "次回予告！猫が走った！",
+        ]);
+        let hits = db.find_hits_by_ids(&ids).unwrap();
+        let ranker = HeuristicRanker::default();
+        let ranked = ranker.rank(&db, &hits, 4).unwrap();
+        let ranked: Vec<TranscriptId> = ranked.iter().map(|hit| hit.transcript_id).collect();
+        assert_eq!(ranked, [ids[2], ids[3], ids[1], ids[0]]);
+        assert!(ranker.score("猫が走った", 2) > ranker.score("猫が走った", 0));
+
+        // Without the boilerplate penalty, the preview's exclamations win
+        let ranker = HeuristicRanker::new(HeuristicWeights {
+            boilerplate: 0.0,
+            ..HeuristicWeights::default()
+        });
+        assert_eq!(ranker.rank(&db, &hits, 1).unwrap()[0].transcript_id, ids[4]);
+    }
+
+    #[test]
+    fn test_learnability_scorer() {
+        let (mut db, ids) = test_db_with_lines(&[
+            "あの猫はとても珍しい種類の猫で、名前はまだ決まっていない",
+            "猫が走った",
+            "猫が好き",
+        ]);
+        db.index_transcripts(&test_tokenizer(), &ids).unwrap();
+        let mut hits = db.find_hits_by_ids(&ids).unwrap();
+
+        // With nothing known and no collocations, the short lines come first, in order
+        let scorer = LearnabilityScorer::default();
+        scorer.sort(&db, &mut hits).unwrap();
+        let sorted: Vec<TranscriptId> = hits.iter().map(|hit| hit.transcript_id).collect();
+        assert_eq!(sorted, [ids[1], ids[2], ids[0]]);
+
+        let known = HashSet::from(["猫".to_string(), "好き".to_string()]);
+        let scorer = LearnabilityScorer::new(LearnabilityWeights::default(), known, Vec::new());
+        scorer.sort(&db, &mut hits).unwrap();
+        assert_eq!(hits[0].transcript_id, ids[2]);
+
+        let scorer = LearnabilityScorer::new(
+            LearnabilityWeights::default(),
+            HashSet::new(),
+            vec!["猫が走".to_string()],
+        );
+        assert!(scorer.score("猫が走った", &[], 0) > scorer.score("猫が好き", &[], 0));
+        assert_eq!("learnable".parse(), Ok(SortOrder::Learnable));
+        assert!("random".parse::<SortOrder>().is_err());
+    }
+
+    #[test]
+    fn test_is_complete_sentence() {
+        assert!(is_complete_sentence("猫が走った"));
+        assert!(is_complete_sentence("走って！"));
+        assert!(!is_complete_sentence("猫が走って"));
+        assert!(!is_complete_sentence("猫だけど、"));
+        assert!(!is_complete_sentence(""));
+    }
+
+    #[test]
+    fn test_open_ranker() {
+        let path = Path::new(":memory:");
+        let config = RankingConfig::default();
+        let ranker = open_ranker(&config, &LlmConfig::default(), path).unwrap();
+        assert_eq!(ranker.name(), "heuristic");
+        let config = RankingConfig {
+            ranker: RankerKind::Llm,
+            ..RankingConfig::default()
+        };
+        assert!(open_ranker(&config, &LlmConfig::default(), path).is_err());
+
+        let config: RankingConfig =
+            toml::from_str("ranker = \"heuristic\"\n[heuristic]\nrare_word = 2.0").unwrap();
+        assert_eq!(config.ranker, RankerKind::Heuristic);
+        assert_eq!(config.heuristic.rare_word, 2.0);
+        assert_eq!(config.heuristic.long_line, 30);
+
+        let (db, ids) = test_db_with_lines(&["猫", "犬"]);
+        let hits = db.find_hits_by_ids(&ids).unwrap();
+        let provider = FakeProvider::new(&ids[1].to_string());
+        let ranker = LlmRanker::new(&provider, 8000);
+        assert_eq!(ranker.name(), "fake:model");
+        assert_eq!(ranker.rank(&db, &hits, 1).unwrap()[0].transcript_id, ids[1]);
+    }
+}
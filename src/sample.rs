@@ -0,0 +1,113 @@
+//! Random example sentences for a word, for variety instead of the same top hits.
+
+use crate::db::{DbHandler, SearchHit};
+use crate::search::{search, Result};
+use crate::tokenizer::JapaneseTokenizer;
+use rand::Rng;
+
+// How much more likely a line from a preferred show is to be picked
+const PREFERRED_SHOW_WEIGHT: f64 = 5.0;
+
+/// How [`sample`] picks its lines.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SampleOptions {
+    /// Number of lines to return
+    pub count: usize,
+    /// Make shorter lines more likely, in proportion to 1 / length
+    pub prefer_short: bool,
+    /// Make lines from these shows more likely
+    pub preferred_shows: Vec<String>,
+}
+
+impl Default for SampleOptions {
+    fn default() -> Self {
+        SampleOptions {
+            count: 10,
+            prefer_short: false,
+            preferred_shows: Vec::new(),
+        }
+    }
+}
+
+/// Returns up to `options.count` random lines containing every word of `query`,
+/// without repeating a line.
+pub fn sample(
+    db: &DbHandler,
+    tokenizer: &JapaneseTokenizer,
+    query: &str,
+    options: &SampleOptions,
+    rng: &mut impl Rng,
+) -> Result<Vec<SearchHit>> {
+    Ok(sample_hits(search(db, tokenizer, query)?, options, rng))
+}
+
+/// Picks up to `options.count` of `hits` at random, weighted according to `options`.
+pub fn sample_hits(
+    hits: Vec<SearchHit>,
+    options: &SampleOptions,
+    rng: &mut impl Rng,
+) -> Vec<SearchHit> {
+    // Weighted sampling without replacement (Efraimidis-Spirakis):
+    // each hit gets the key u^(1/weight) and the largest keys win
+    let mut keyed: Vec<(f64, SearchHit)> = hits
+        .into_iter()
+        .map(|hit| {
+            let key = rng.gen::<f64>().powf(1.0 / weight(&hit, options));
+            (key, hit)
+        })
+        .collect();
+    keyed.sort_by(|a, b| b.0.total_cmp(&a.0));
+    keyed.truncate(options.count);
+    keyed.into_iter().map(|(_, hit)| hit).collect()
+}
+
+fn weight(hit: &SearchHit, options: &SampleOptions) -> f64 {
+    let mut weight = 1.0;
+    if options.prefer_short {
+        weight /= hit.text.chars().count().max(1) as f64;
+    }
+    if options.preferred_shows.contains(&hit.show_name) {
+        weight *= PREFERRED_SHOW_WEIGHT;
+    }
+    weight
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_utils::test_db_with_lines;
+    use crate::tokenizer::test_utils::test_tokenizer;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_sample_returns_distinct_matching_lines() {
+        let (mut db, ids) = test_db_with_lines(&["猫", "猫が走った", "犬", "猫が好きです"]);
+        db.index_transcripts(&test_tokenizer(), &ids).unwrap();
+        let options = SampleOptions {
+            count: 2,
+            ..SampleOptions::default()
+        };
+        let mut rng = StdRng::seed_from_u64(7);
+        let hits = sample(&db, &test_tokenizer(), "猫", &options, &mut rng).unwrap();
+        assert_eq!(hits.len(), 2);
+        assert_ne!(hits[0].transcript_id, hits[1].transcript_id);
+        assert!(hits.iter().all(|hit| hit.text.contains('猫')));
+    }
+
+    #[test]
+    fn test_sample_prefers_short_lines() {
+        let (db, _) = test_db_with_lines(&["猫", &"猫".repeat(200)]);
+        let hits = db.find_lines_containing_any(&["猫".to_string()]).unwrap();
+        let options = SampleOptions {
+            count: 1,
+            prefer_short: true,
+            ..SampleOptions::default()
+        };
+        let mut rng = StdRng::seed_from_u64(1);
+        let short_wins = (0..100)
+            .filter(|_| sample_hits(hits.clone(), &options, &mut rng)[0].text == "猫")
+            .count();
+        assert!(short_wins > 90);
+    }
+}
@@ -0,0 +1,696 @@
+use crate::collocations::is_punctuation;
+use crate::db::{DbHandler, Proximity, QueryTerm, SearchHit, TranscriptId, WordQuery};
+use crate::grammar::GrammarPattern;
+use crate::tokenizer::{fold_case, normalize, JapaneseTokenizer, Tokenizer};
+use regex::Regex;
+use regex_syntax::hir::literal::{ExtractKind, Extractor};
+use std::collections::HashSet;
+use std::fmt;
+use std::ops::ControlFlow;
+
+#[derive(Debug)]
+pub enum SearchError {
+    InvalidRegex(regex::Error),
+    DbError(rusqlite::Error),
+}
+
+impl From<regex::Error> for SearchError {
+    fn from(error: regex::Error) -> Self {
+        SearchError::InvalidRegex(error)
+    }
+}
+
+impl From<rusqlite::Error> for SearchError {
+    fn from(error: rusqlite::Error) -> Self {
+        SearchError::DbError(error)
+    }
+}
+
+impl fmt::Display for SearchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SearchError::InvalidRegex(e) => write!(f, "Invalid regex: {}", e),
+            SearchError::DbError(e) => write!(f, "Database error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for SearchError {}
+
+pub type Result<T> = std::result::Result<T, SearchError>;
+
+/// Finds lines containing every word of `query`.
+///
+/// The query is tokenized the same way lines were indexed, so conjugated
+/// forms match their dictionary form and stopwords are ignored. A query made
+/// up only of stopwords matches nothing.
+///
+/// `猫 NEAR/3 好き` additionally requires the words on either side of the
+/// operator to occur within three tokens of each other.
+///
+/// `走る:動詞` matches the dictionary form 走る only where it was tagged as a
+/// verb. Words with a part-of-speech are used as written, without tokenizing.
+///
+/// Words match across scripts: おもしろい also finds 面白い and vice versa.
+/// Set [`WordQuery::exact_script`] on a [`parse_query`] result to turn that off.
+///
+/// `speaker:太郎` keeps only the lines said by 太郎, as named by the actor field
+/// of ASS subtitles or a bracketed name before the line. On its own it finds
+/// all of their lines.
+pub fn search(db: &DbHandler, tokenizer: &dyn Tokenizer, query: &str) -> Result<Vec<SearchHit>> {
+    Ok(db.find_lines(&parse_query(tokenizer, query))?)
+}
+
+/// Like [`search`], but hands each line to `f` as soon as it is read instead of
+/// collecting them, stopping early when `f` returns [`ControlFlow::Break`].
+pub fn search_each(
+    db: &DbHandler,
+    tokenizer: &dyn Tokenizer,
+    query: &str,
+    f: impl FnMut(SearchHit) -> ControlFlow<()>,
+) -> Result<()> {
+    Ok(db.for_each_line(&parse_query(tokenizer, query), f)?)
+}
+
+/// Turns a query string into a word query, handling `NEAR/N` operators, `word:POS` terms
+/// and a `speaker:NAME` filter.
+pub fn parse_query(tokenizer: &dyn Tokenizer, query: &str) -> WordQuery {
+    let near = Regex::new(r"\s+NEAR/(\d+)\s+").unwrap();
+
+    // The speaker filter isn't a word, so it's taken out before the words are parsed
+    let mut speaker = None;
+    let chunks: Vec<&str> = query
+        .split_whitespace()
+        .filter(|chunk| match speaker_filter(chunk) {
+            Some(name) => {
+                speaker = Some(name.to_string());
+                false
+            }
+            None => true,
+        })
+        .collect();
+    let query = &chunks.join(" ");
+
+    // Split the query into the parts between NEAR operators
+    let mut parts = Vec::new();
+    let mut distances = Vec::new();
+    let mut last_end = 0;
+    for cap in near.captures_iter(query) {
+        let operator = cap.get(0).unwrap();
+        parts.push(&query[last_end..operator.start()]);
+        distances.push(cap[1].parse().unwrap_or(u32::MAX));
+        last_end = operator.end();
+    }
+    parts.push(&query[last_end..]);
+
+    let part_terms: Vec<Vec<QueryTerm>> = parts
+        .iter()
+        .map(|part| parse_terms(tokenizer, part))
+        .collect();
+
+    let mut terms: Vec<QueryTerm> = part_terms.iter().flatten().cloned().collect();
+    terms.sort();
+    terms.dedup();
+
+    // Each operator links the last word before it with the first word after it
+    let proximity = distances
+        .iter()
+        .enumerate()
+        .filter_map(|(i, &max_distance)| {
+            Some(Proximity {
+                left: part_terms[i].last()?.word.clone(),
+                right: part_terms[i + 1].first()?.word.clone(),
+                max_distance,
+            })
+        })
+        .collect();
+
+    WordQuery {
+        terms,
+        proximity,
+        speaker,
+        ..WordQuery::default()
+    }
+}
+
+// The name of a `speaker:NAME` chunk
+fn speaker_filter(chunk: &str) -> Option<&str> {
+    let name = chunk
+        .strip_prefix("speaker:")
+        .or_else(|| chunk.strip_prefix("speaker："))?;
+    (!name.is_empty()).then_some(name)
+}
+
+// Parses whitespace-separated chunks, each either `word:POS` or free text to tokenize
+fn parse_terms(tokenizer: &dyn Tokenizer, part: &str) -> Vec<QueryTerm> {
+    part.split_whitespace()
+        .flat_map(|chunk| match chunk.split_once([':', '：']) {
+            Some((word, pos)) if !word.is_empty() && !pos.is_empty() => vec![QueryTerm {
+                word: fold_case(&normalize(word)),
+                pos: Some(pos.to_string()),
+            }],
+            _ => tokenizer
+                .index_terms(chunk)
+                .into_iter()
+                .map(QueryTerm::word)
+                .collect(),
+        })
+        .collect()
+}
+
+/// Finds lines containing `text` (e.g. a single kanji like 憂 or a compound like 憂鬱)
+/// at the character level, independently of how the tokenizer split the line.
+///
+/// With `within_token`, the text must also appear inside a single token, so
+/// 日本 would not match a line where 日 and 本 belong to different words.
+pub fn search_kanji(
+    db: &DbHandler,
+    tokenizer: &JapaneseTokenizer,
+    text: &str,
+    within_token: bool,
+) -> Result<Vec<SearchHit>> {
+    let hits = db.find_lines_containing(text)?;
+    if !within_token {
+        return Ok(hits);
+    }
+    // Token surfaces are normalized, so the text has to be too
+    let normalized = normalize(text);
+    Ok(hits
+        .into_iter()
+        .filter(|hit| {
+            tokenizer
+                .tokenize(&hit.text)
+                .iter()
+                .any(|token| token.surface.contains(&normalized))
+        })
+        .collect())
+}
+
+/// Finds lines whose text matches the regular expression `pattern`, e.g. `て(も|は)いい`.
+///
+/// Literal strings that every match must start with are extracted from the
+/// pattern and used to narrow the candidates in SQL before the regex runs.
+/// Patterns without such literals (e.g. `.+た`) scan every line.
+///
+/// The pattern and lines are matched normalized (see [`normalize`]), so `２０`
+/// finds 20. Full-width characters that normalize to regex syntax, like （,
+/// are matched literally.
+pub fn search_regex(db: &DbHandler, pattern: &str) -> Result<Vec<SearchHit>> {
+    let pattern = normalize_pattern(pattern);
+    let regex = Regex::new(&pattern)?;
+    let hits = db.find_lines_containing_any(&required_literals(&pattern))?;
+    Ok(hits
+        .into_iter()
+        .filter(|hit| regex.is_match(&normalize(&hit.text)))
+        .collect())
+}
+
+// Normalizes a regex pattern, escaping the characters normalization would
+// turn into regex syntax, so （笑） still matches （笑） rather than a group
+fn normalize_pattern(pattern: &str) -> String {
+    let mut escaped = String::new();
+    for c in pattern.chars() {
+        let normalized = normalize(&c.to_string());
+        let mut chars = normalized.chars();
+        match (chars.next(), chars.next()) {
+            (Some(meta), None) if !c.is_ascii() && regex_syntax::is_meta_character(meta) => {
+                escaped.push('\\');
+                escaped.push(meta);
+            }
+            _ => escaped.push(c),
+        }
+    }
+    normalize(&escaped)
+}
+
+// Literals one of which must appear in any line matching `pattern`
+// Returns an empty list when no such set can be determined
+fn required_literals(pattern: &str) -> Vec<String> {
+    let Ok(hir) = regex_syntax::parse(pattern) else {
+        return Vec::new();
+    };
+    let seq = Extractor::new().kind(ExtractKind::Prefix).extract(&hir);
+    let Some(literals) = seq.literals() else {
+        return Vec::new();
+    };
+    if literals.iter().any(|literal| literal.as_bytes().is_empty()) {
+        return Vec::new();
+    }
+    // Long literals are cut at a byte limit, which can fall inside a character;
+    // leaving such a literal out would miss the lines only it leads to
+    literals
+        .iter()
+        .map(|literal| String::from_utf8(literal.as_bytes().to_vec()).ok())
+        .collect::<Option<Vec<String>>>()
+        .unwrap_or_default()
+}
+
+/// Finds lines containing a grammar pattern.
+///
+/// Candidate lines are narrowed to those containing the pattern's literal,
+/// then re-tokenized so the token and part-of-speech sequence can be checked.
+/// This also works for patterns made of stopwords, which aren't in the word index.
+pub fn search_grammar(
+    db: &DbHandler,
+    tokenizer: &JapaneseTokenizer,
+    pattern: &GrammarPattern,
+) -> Result<Vec<SearchHit>> {
+    let hits = db.find_lines_containing_any(&[pattern.literal.to_string()])?;
+    Ok(hits
+        .into_iter()
+        .filter(|hit| pattern.matches(&tokenizer.tokenize(&hit.text)))
+        .collect())
+}
+
+/// Hits of one show, grouped by episode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShowGroup {
+    pub show_name: String,
+    pub episodes: Vec<EpisodeGroup>,
+}
+
+/// Hits of one episode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EpisodeGroup {
+    pub season: i32,
+    /// `None` for a movie
+    pub episode_number: Option<i32>,
+    pub hits: Vec<SearchHit>,
+}
+
+impl ShowGroup {
+    /// Number of hits across all of the show's episodes.
+    pub fn hit_count(&self) -> usize {
+        self.episodes.iter().map(|episode| episode.hits.len()).sum()
+    }
+}
+
+/// Groups hits by show and then episode.
+///
+/// Shows and episodes keep the order in which their first hit appears, so
+/// ranked results stay ranked; hits keep their order within an episode.
+pub fn group_hits(hits: Vec<SearchHit>) -> Vec<ShowGroup> {
+    let mut shows: Vec<ShowGroup> = Vec::new();
+    for hit in hits {
+        let show = match shows.iter().position(|s| s.show_name == hit.show_name) {
+            Some(i) => &mut shows[i],
+            None => {
+                shows.push(ShowGroup {
+                    show_name: hit.show_name.clone(),
+                    episodes: Vec::new(),
+                });
+                shows.last_mut().unwrap()
+            }
+        };
+        let key = (hit.season, hit.episode_number);
+        match show
+            .episodes
+            .iter_mut()
+            .find(|e| (e.season, e.episode_number) == key)
+        {
+            Some(episode) => episode.hits.push(hit),
+            None => show.episodes.push(EpisodeGroup {
+                season: hit.season,
+                episode_number: hit.episode_number,
+                hits: vec![hit],
+            }),
+        }
+    }
+    shows
+}
+
+/// Limits on how long and hard a hit's line may be, to keep results to
+/// card-sized sentences.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ComplexityFilter {
+    /// Most tokens, punctuation not counted
+    pub max_tokens: Option<usize>,
+    /// Most characters, whitespace not counted
+    pub max_chars: Option<usize>,
+    /// Most indexed words that aren't known
+    pub max_unknown: Option<usize>,
+}
+
+impl ComplexityFilter {
+    pub fn is_empty(&self) -> bool {
+        *self == ComplexityFilter::default()
+    }
+}
+
+/// Drops the hits whose lines are longer, or have more words outside
+/// `known`, than `filter` allows.
+pub fn filter_complexity(
+    db: &DbHandler,
+    tokenizer: &JapaneseTokenizer,
+    hits: &mut Vec<SearchHit>,
+    filter: &ComplexityFilter,
+    known: &HashSet<String>,
+) -> Result<()> {
+    let words = match filter.max_unknown {
+        Some(_) => {
+            let ids: Vec<TranscriptId> = hits.iter().map(|hit| hit.transcript_id).collect();
+            db.line_words(&ids)?
+        }
+        None => Default::default(),
+    };
+    hits.retain(|hit| {
+        let chars = || hit.text.chars().filter(|c| !c.is_whitespace()).count();
+        let tokens = || {
+            tokenizer
+                .tokenize(&hit.text)
+                .iter()
+                .filter(|token| !is_punctuation(token))
+                .count()
+        };
+        let unknown = || {
+            words.get(&hit.transcript_id).map_or(0, |words| {
+                words.iter().filter(|word| !known.contains(*word)).count()
+            })
+        };
+        filter.max_chars.is_none_or(|max| chars() <= max)
+            && filter.max_tokens.is_none_or(|max| tokens() <= max)
+            && filter.max_unknown.is_none_or(|max| unknown() <= max)
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_utils::test_db_with_lines;
+    use crate::tokenizer::test_utils::test_tokenizer;
+
+    fn test_db(lines: &[&str]) -> DbHandler {
+        let (mut db, ids) = test_db_with_lines(lines);
+        db.index_transcripts(&test_tokenizer(), &ids).unwrap();
+        db
+    }
+
+    #[test]
+    fn test_search_matches_all_words() {
+        let db = test_db(&["猫が走った", "猫が好きです", "犬が走る"]);
+        let hits = search(&db, &test_tokenizer(), "猫は走る").unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].text, "猫が走った");
+        assert_eq!(hits[0].show_name, "Show Name");
+    }
+
+    #[test]
+    fn test_search_ignores_stopwords_in_query() {
+        let db = test_db(&["猫が走った", "犬が走る"]);
+        assert_eq!(search(&db, &test_tokenizer(), "犬が").unwrap().len(), 1);
+        assert!(search(&db, &test_tokenizer(), "が").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_search_matches_across_scripts() {
+        let db = test_db(&["面白い", "おもしろい", "猫が走った"]);
+        let tokenizer = test_tokenizer();
+        assert_eq!(search(&db, &tokenizer, "おもしろい").unwrap().len(), 2);
+        assert_eq!(search(&db, &tokenizer, "面白い").unwrap().len(), 2);
+        // Kana never indexed as a word still finds the kanji spelling
+        assert_eq!(search(&db, &tokenizer, "ネコ:名詞").unwrap().len(), 1);
+
+        let mut query = parse_query(&tokenizer, "おもしろい");
+        query.exact_script = true;
+        let hits = db.find_lines(&query).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].text, "おもしろい");
+    }
+
+    #[test]
+    fn test_search_latin_words_in_japanese_lines() {
+        let db = test_db(&["LINE既読", "DVDを見る", "猫が走った"]);
+        let tokenizer = test_tokenizer();
+        let texts = |query: &str| -> Vec<String> {
+            search(&db, &tokenizer, query)
+                .unwrap()
+                .into_iter()
+                .map(|hit| hit.text)
+                .collect()
+        };
+        assert_eq!(texts("LINE 既読"), ["LINE既読"]);
+        assert_eq!(texts("line既読"), ["LINE既読"]);
+        assert_eq!(texts("既読"), ["LINE既読"]);
+        assert_eq!(texts("dvd"), ["DVDを見る"]);
+        assert_eq!(texts("ＤＶＤ"), ["DVDを見る"]);
+        assert_eq!(texts("Dvd:名詞"), ["DVDを見る"]);
+        assert!(texts("LINE 見る").is_empty());
+    }
+
+    #[test]
+    fn test_group_hits() {
+        let hit = |show: &str, episode: i32, id: i64| SearchHit {
+            transcript_id: crate::db::TranscriptId(id),
+            show_name: show.to_string(),
+            season: 1,
+            episode_number: Some(episode),
+            line_id: id as i32,
+            time_start: String::new(),
+            time_end: String::new(),
+            text: String::new(),
+            translation: None,
+            stable_id: String::new(),
+            source_url: None,
+            deep_link: None,
+        };
+        let groups = group_hits(vec![
+            hit("B", 2, 1),
+            hit("A", 1, 2),
+            hit("B", 1, 3),
+            hit("B", 2, 4),
+        ]);
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].show_name, "B");
+        assert_eq!(groups[0].hit_count(), 3);
+        let episodes: Vec<(Option<i32>, usize)> = groups[0]
+            .episodes
+            .iter()
+            .map(|e| (e.episode_number, e.hits.len()))
+            .collect();
+        assert_eq!(episodes, [(Some(2), 2), (Some(1), 1)]);
+        assert_eq!(groups[1].hit_count(), 1);
+    }
+
+    #[test]
+    fn test_search_kanji() {
+        let db = test_db(&["猫が走った", "子猫", "犬が走る"]);
+        let hits = search_kanji(&db, &test_tokenizer(), "猫", false).unwrap();
+        assert_eq!(hits.len(), 2);
+        let hits = search_kanji(&db, &test_tokenizer(), "走", false).unwrap();
+        assert_eq!(hits.len(), 2);
+    }
+
+    #[test]
+    fn test_search_kanji_normalizes() {
+        // ⽝ is the radical, which normalizes to the kanji 犬
+        let db = test_db(&["⽝が走る", "２０歳の猫"]);
+        let hits = search_kanji(&db, &test_tokenizer(), "犬", false).unwrap();
+        assert_eq!(hits.len(), 1);
+        let hits = search_kanji(&db, &test_tokenizer(), "20歳", false).unwrap();
+        assert_eq!(hits[0].text, "２０歳の猫");
+    }
+
+    #[test]
+    fn test_search_kanji_within_token() {
+        // 猫 and 好 are in different tokens, so the substring only matches across tokens
+        let db = test_db(&["猫好き", "猫が好きです"]);
+        assert_eq!(
+            search_kanji(&db, &test_tokenizer(), "猫好", false)
+                .unwrap()
+                .len(),
+            1
+        );
+        assert!(search_kanji(&db, &test_tokenizer(), "猫好", true)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_search_kanji_without_kanji_falls_back_to_substring() {
+        let db = test_db(&["猫が走った", "犬が走る"]);
+        assert_eq!(
+            search_kanji(&db, &test_tokenizer(), "った", false)
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_parse_query_near() {
+        let query = parse_query(&test_tokenizer(), "猫 NEAR/3 好き");
+        assert_eq!(
+            query.terms,
+            [QueryTerm::word("好き"), QueryTerm::word("猫")]
+        );
+        assert_eq!(
+            query.proximity,
+            [Proximity {
+                left: "猫".to_string(),
+                right: "好き".to_string(),
+                max_distance: 3,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_search_near() {
+        let db = test_db(&["猫が好きです", "猫が走った。犬が好きです"]);
+        assert_eq!(search(&db, &test_tokenizer(), "猫 好き").unwrap().len(), 2);
+        let hits = search(&db, &test_tokenizer(), "猫 NEAR/2 好き").unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].text, "猫が好きです");
+        assert!(search(&db, &test_tokenizer(), "猫 NEAR/1 好き")
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_search_near_repeated_word() {
+        // A word is not near itself, only another occurrence of it
+        let db = test_db(&["猫と猫", "猫が好きです"]);
+        let hits = search(&db, &test_tokenizer(), "猫 NEAR/2 猫").unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].text, "猫と猫");
+    }
+
+    #[test]
+    fn test_required_literals() {
+        let mut literals = required_literals("て(も|は)いい");
+        literals.sort();
+        assert_eq!(literals, ["てはいい", "てもいい"]);
+        assert!(required_literals(".+た").is_empty());
+        // The long alternative is cut inside a character, so no literal is required
+        let long = format!("x|{}", "あ".repeat(40));
+        assert!(required_literals(&long).is_empty());
+        let line = "あ".repeat(40);
+        let db = test_db(&[line.as_str(), "xyz", "猫"]);
+        assert_eq!(search_regex(&db, &long).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_search_regex() {
+        let db = test_db(&["食べてもいい", "見てはいい", "行ってもいいかな", "いいね"]);
+        let hits = search_regex(&db, "て(も|は)いい$").unwrap();
+        let texts: Vec<&str> = hits.iter().map(|hit| hit.text.as_str()).collect();
+        assert_eq!(texts, ["食べてもいい", "見てはいい"]);
+        assert!(matches!(
+            search_regex(&db, "(").unwrap_err(),
+            SearchError::InvalidRegex(_)
+        ));
+    }
+
+    #[test]
+    fn test_search_regex_normalizes() {
+        let db = test_db(&["２０歳（笑）", "20歳だ", "ｶﾞﾝﾊﾞﾚ"]);
+        let texts = |pattern: &str| -> Vec<String> {
+            let hits = search_regex(&db, pattern).unwrap();
+            hits.into_iter().map(|hit| hit.text).collect()
+        };
+        assert_eq!(texts("20歳"), ["２０歳（笑）", "20歳だ"]);
+        assert_eq!(texts("（笑）$"), ["２０歳（笑）"]);
+        assert_eq!(texts("ガンバ"), ["ｶﾞﾝﾊﾞﾚ"]);
+    }
+
+    #[test]
+    fn test_search_grammar() {
+        let db = test_db(&["犬を見たばかりに", "犬ばかり", "見たばかりにね"]);
+        let pattern = crate::grammar::find_grammar_pattern("N3:ばかりに").unwrap();
+        let hits = search_grammar(&db, &test_tokenizer(), pattern).unwrap();
+        assert_eq!(hits.len(), 2);
+    }
+
+    #[test]
+    fn test_parse_query_pos() {
+        let query = parse_query(&test_tokenizer(), "猫が 走る:動詞");
+        assert_eq!(
+            query.terms,
+            [
+                QueryTerm::word("猫"),
+                QueryTerm {
+                    word: "走る".to_string(),
+                    pos: Some("動詞".to_string()),
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_query_speaker() {
+        let query = parse_query(&test_tokenizer(), "speaker:太郎 猫 NEAR/2 好き");
+        assert_eq!(query.speaker.as_deref(), Some("太郎"));
+        assert_eq!(
+            query.terms,
+            [QueryTerm::word("好き"), QueryTerm::word("猫")]
+        );
+        assert_eq!(query.proximity.len(), 1);
+        let query = parse_query(&test_tokenizer(), "speaker：花子");
+        assert_eq!(query.speaker.as_deref(), Some("花子"));
+        assert!(query.terms.is_empty());
+    }
+
+    #[test]
+    fn test_search_pos_filter() {
+        let db = test_db(&["猫が走った", "好きです"]);
+        assert_eq!(
+            search(&db, &test_tokenizer(), "走る:動詞").unwrap().len(),
+            1
+        );
+        assert_eq!(
+            search(&db, &test_tokenizer(), "走る:動詞-自立")
+                .unwrap()
+                .len(),
+            1
+        );
+        assert!(search(&db, &test_tokenizer(), "走る:名詞")
+            .unwrap()
+            .is_empty());
+        assert!(search(&db, &test_tokenizer(), "走る:動")
+            .unwrap()
+            .is_empty());
+        // LIKE wildcards are taken literally
+        assert!(search(&db, &test_tokenizer(), "走る:%").unwrap().is_empty());
+        assert!(search(&db, &test_tokenizer(), "走る:__")
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_search_normalizes_width() {
+        let db = test_db(&["２０１０年", "ｶﾀｶﾅです"]);
+        assert_eq!(search(&db, &test_tokenizer(), "2010").unwrap().len(), 1);
+        assert_eq!(search(&db, &test_tokenizer(), "カタカナ").unwrap().len(), 1);
+        assert_eq!(search(&db, &test_tokenizer(), "ｶﾀｶﾅ").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_filter_complexity() {
+        let db = test_db(&["猫が走った。", "あの猫はとても珍しい種類の猫です", "猫と犬"]);
+        let all = search(&db, &test_tokenizer(), "猫").unwrap();
+        let texts = |filter: &ComplexityFilter, known: &[&str]| {
+            let known = known.iter().map(|word| word.to_string()).collect();
+            let mut hits = all.clone();
+            filter_complexity(&db, &test_tokenizer(), &mut hits, filter, &known).unwrap();
+            hits.into_iter().map(|hit| hit.text).collect::<Vec<_>>()
+        };
+
+        let filter = ComplexityFilter {
+            max_chars: Some(5),
+            ..ComplexityFilter::default()
+        };
+        assert_eq!(texts(&filter, &[]), ["猫と犬"]);
+        // The full stop isn't a token
+        let filter = ComplexityFilter {
+            max_tokens: Some(4),
+            ..ComplexityFilter::default()
+        };
+        assert_eq!(texts(&filter, &[]), ["猫が走った。", "猫と犬"]);
+        let filter = ComplexityFilter {
+            max_unknown: Some(0),
+            ..ComplexityFilter::default()
+        };
+        assert_eq!(texts(&filter, &["猫", "走る"]), ["猫が走った。"]);
+        assert_eq!(texts(&ComplexityFilter::default(), &[]).len(), 3);
+    }
+}
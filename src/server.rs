@@ -0,0 +1,639 @@
+//! An HTTP server for searching the corpus from a browser or other programs.
+//!
+//! Routes:
+//! - `GET /?q=...`: an HTML page with a search box and the matching lines;
+//!   with the `web` feature, an interactive front-end instead
+//! - `GET /search?q=...&limit=...&show=...`: the matching lines as JSON,
+//!   optionally only those of one show. With `Accept: application/x-ndjson`,
+//!   one line per JSON object, sent as each is found
+//! - `GET /context?id=...&before=...&after=...`: the lines around a line as JSON
+//! - `GET /shows`: every show as JSON
+//! - `GET /stats?top=...`: corpus-wide numbers and the most frequent words as JSON
+//! - `GET /stats/episodes?show=...`: dialogue density of every episode as JSON,
+//!   optionally only those of one show
+//! - `GET /suggest?q=...`: OpenSearch suggestions, `["q", ["word", ...]]`
+//! - `GET /opensearch.xml`: an OpenSearch description, so browsers can add
+//!   the server as a search engine for their address bar
+//! - `GET /yomitan/audio`, `/yomitan/sentences` and `/yomitan/clip`: audio
+//!   and example sentences for words looked up in Yomitan-style popup
+//!   dictionaries (see the `yomitan` module). Yomitan can't send API keys, so
+//!   these are served without one even with `AppState::require_api_key`
+//! - `POST /ingest` with `{"root_dir": "..."}`: ingests the subtitle files
+//!   under a directory inside [`AppState::ingest_root`]
+//!
+//! `/ingest` always needs an API key with the ingest scope (see [`auth`]).
+//! With [`AppState::require_api_key`] set, every other route but Yomitan's
+//! needs one too.
+//!
+//! Requires the `server` feature.
+
+pub mod auth;
+#[cfg(not(feature = "web"))]
+mod page;
+#[cfg(feature = "web")]
+mod web;
+mod yomitan;
+
+use crate::backend::BackendConfig;
+use crate::context::ContextWindow;
+use crate::db::{CorpusStats, DbPool, EpisodeStats, NewLoggedQuery, SearchHit, Show, TranscriptId};
+use crate::ingest::{self, IngestError, ShowTypeConfig};
+use crate::nonblocking;
+use crate::query_log::{latency_micros, QueryLogConfig};
+use crate::search::SearchError;
+use crate::tokenizer::JapaneseTokenizer;
+use axum::body::Body;
+use axum::extract::{Query, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::middleware::from_fn_with_state;
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use futures_util::{future, stream, StreamExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Instant;
+
+pub use auth::RateLimiter;
+
+// Hits returned by /search when the request doesn't set a limit
+const DEFAULT_LIMIT: usize = 100;
+
+const NDJSON: &str = "application/x-ndjson";
+
+// Completions returned by /suggest; browsers show about this many
+const SUGGESTION_LIMIT: usize = 8;
+
+/// What the request handlers share.
+#[derive(Clone)]
+pub struct AppState {
+    pub pool: Arc<DbPool>,
+    pub tokenizer: Arc<JapaneseTokenizer>,
+    /// The URL browsers reach the server at, e.g. `https://search.example.com`,
+    /// used in the OpenSearch description. Defaults to the request's Host header.
+    pub public_url: Option<String>,
+    /// Counts the requests of each API key against its rate limit
+    pub api_keys: Arc<RateLimiter>,
+    /// Require an API key on every request, not only on `/ingest`.
+    /// Without it, anyone who can reach the server can search.
+    pub require_api_key: bool,
+    /// The directory `/ingest` may read subtitle files from, itself or below.
+    /// Without it, `/ingest` is refused.
+    pub ingest_root: Option<PathBuf>,
+    /// Which searches to record in the query log
+    pub query_log: QueryLogConfig,
+    /// The search backend `/ingest` adds lines to, besides the word index
+    pub search_backend: BackendConfig,
+    /// Which type `/ingest` gives the shows it adds
+    pub show_types: ShowTypeConfig,
+}
+
+/// Builds the server's routes.
+pub fn router(state: AppState) -> Router {
+    let router = Router::new()
+        .route("/search", get(search))
+        .route("/context", get(context))
+        .route("/shows", get(shows))
+        .route("/stats", get(stats))
+        .route("/stats/episodes", get(episode_stats))
+        .route("/suggest", get(suggest))
+        .route("/opensearch.xml", get(opensearch));
+    #[cfg(feature = "web")]
+    let router = router.merge(web::routes());
+    #[cfg(not(feature = "web"))]
+    let router = router.merge(page::routes());
+    let router = router.route_layer(from_fn_with_state(state.clone(), auth::require_read));
+    // Merged after the read check, as Yomitan can't send API keys
+    let router = router.merge(yomitan::routes());
+    let ingest_routes = Router::new()
+        .route("/ingest", post(ingest))
+        .route_layer(from_fn_with_state(state.clone(), auth::require_ingest));
+    router.merge(ingest_routes).with_state(state)
+}
+
+/// Serves requests on `listener` until the process exits.
+pub async fn serve(listener: tokio::net::TcpListener, state: AppState) -> std::io::Result<()> {
+    axum::serve(listener, router(state)).await
+}
+
+/// Opens `url` in the default browser, without waiting for it.
+pub fn open_browser(url: &str) -> std::io::Result<()> {
+    let mut command = if cfg!(target_os = "windows") {
+        let mut command = std::process::Command::new("cmd");
+        command.args(["/C", "start", ""]);
+        command
+    } else if cfg!(target_os = "macos") {
+        std::process::Command::new("open")
+    } else {
+        std::process::Command::new("xdg-open")
+    };
+    command.arg(url).spawn()?;
+    Ok(())
+}
+
+struct ApiError(SearchError);
+
+impl From<SearchError> for ApiError {
+    fn from(error: SearchError) -> Self {
+        ApiError(error)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (StatusCode::INTERNAL_SERVER_ERROR, self.0.to_string()).into_response()
+    }
+}
+
+#[derive(Deserialize)]
+struct SearchParams {
+    #[serde(default)]
+    q: String,
+    limit: Option<usize>,
+    /// Only hits from the show with this name
+    show: Option<String>,
+}
+
+async fn search(
+    State(state): State<AppState>,
+    Query(params): Query<SearchParams>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let wants_ndjson = headers
+        .get(header::ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .is_some_and(|accept| accept.contains(NDJSON));
+    if wants_ndjson {
+        return search_ndjson(state, params).await;
+    }
+    let started = Instant::now();
+    let mut hits =
+        nonblocking::search(state.pool.clone(), state.tokenizer, params.q.clone()).await?;
+    let show = params.show.filter(|show| !show.is_empty());
+    if let Some(show) = &show {
+        hits.retain(|hit| hit.show_name == *show);
+    }
+    hits.truncate(params.limit.unwrap_or(DEFAULT_LIMIT));
+    if state.query_log.enabled {
+        let query = NewLoggedQuery {
+            query: params.q,
+            kind: "words".to_string(),
+            filters: show.map_or(String::new(), |show| format!("show={}", show)),
+            hits: hits.len(),
+            latency_micros: latency_micros(started.elapsed()),
+        };
+        // A search that worked shouldn't fail because it couldn't be logged
+        if let Err(e) = nonblocking::log_search(state.pool, state.query_log, query).await {
+            eprintln!("Failed to log search: {}", e);
+        }
+    }
+    Ok(Json(hits).into_response())
+}
+
+// Streams the hits as newline-delimited JSON while the search runs
+// Errors before the first hit still get an error status; a later one ends the body early
+async fn search_ndjson(state: AppState, params: SearchParams) -> Result<Response, ApiError> {
+    let mut receiver = nonblocking::search_streaming(state.pool, state.tokenizer, params.q);
+    let first = receiver.recv().await.transpose()?;
+    let rest = stream::unfold(receiver, |mut receiver| async move {
+        let hit = receiver.recv().await?;
+        Some((hit, receiver))
+    });
+    let show = params.show.filter(|show| !show.is_empty());
+    let lines = stream::iter(first.map(Ok))
+        .chain(rest)
+        .try_filter(move |hit| {
+            let keep = show.as_ref().is_none_or(|show| hit.show_name == *show);
+            future::ready(keep)
+        })
+        .take(params.limit.unwrap_or(DEFAULT_LIMIT))
+        .map_ok(|hit| {
+            let mut line = serde_json::to_vec(&hit).expect("hits serialize");
+            line.push(b'\n');
+            line
+        });
+    Ok(([(header::CONTENT_TYPE, NDJSON)], Body::from_stream(lines)).into_response())
+}
+
+#[derive(Deserialize)]
+struct StatsParams {
+    #[serde(default = "default_top")]
+    top: usize,
+}
+
+fn default_top() -> usize {
+    20
+}
+
+async fn stats(
+    State(state): State<AppState>,
+    Query(params): Query<StatsParams>,
+) -> Result<Json<CorpusStats>, ApiError> {
+    Ok(Json(
+        nonblocking::corpus_stats(state.pool, params.top).await?,
+    ))
+}
+
+#[derive(Deserialize)]
+struct EpisodeStatsParams {
+    show: Option<String>,
+}
+
+async fn episode_stats(
+    State(state): State<AppState>,
+    Query(params): Query<EpisodeStatsParams>,
+) -> Result<Json<Vec<EpisodeStats>>, ApiError> {
+    let show = params.show.filter(|show| !show.is_empty());
+    Ok(Json(nonblocking::episode_stats(state.pool, show).await?))
+}
+
+#[derive(Deserialize)]
+struct IngestParams {
+    root_dir: PathBuf,
+}
+
+#[derive(Serialize)]
+struct IngestResult {
+    files: usize,
+    lines: usize,
+}
+
+async fn ingest(
+    State(state): State<AppState>,
+    Json(params): Json<IngestParams>,
+) -> Result<Json<IngestResult>, Response> {
+    let Some(ingest_root) = state.ingest_root.as_deref() else {
+        let message = "Ingestion is disabled; start the server with --ingest-root";
+        return Err((StatusCode::FORBIDDEN, message).into_response());
+    };
+    let Some(root_dir) = ingest::directory_within(ingest_root, &params.root_dir) else {
+        let message = format!(
+            "{} is not a directory under {}",
+            params.root_dir.display(),
+            ingest_root.display()
+        );
+        return Err((StatusCode::BAD_REQUEST, message).into_response());
+    };
+    let ingested = nonblocking::ingest_directory(
+        state.pool,
+        state.tokenizer,
+        state.search_backend,
+        state.show_types,
+        root_dir,
+    )
+    .await
+    .map_err(|e: IngestError| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response())?;
+    Ok(Json(IngestResult {
+        files: ingested.episode_ids.len(),
+        lines: ingested.transcript_ids.len(),
+    }))
+}
+
+#[derive(Deserialize)]
+struct ContextParams {
+    id: i64,
+    #[serde(default = "default_before")]
+    before: u32,
+    #[serde(default = "default_after")]
+    after: u32,
+}
+
+fn default_before() -> u32 {
+    ContextWindow::default().before
+}
+
+fn default_after() -> u32 {
+    ContextWindow::default().after
+}
+
+async fn context(
+    State(state): State<AppState>,
+    Query(params): Query<ContextParams>,
+) -> Result<Json<Vec<SearchHit>>, ApiError> {
+    let lines = nonblocking::context(
+        state.pool,
+        TranscriptId(params.id),
+        params.before,
+        params.after,
+    )
+    .await?;
+    Ok(Json(lines))
+}
+
+async fn shows(State(state): State<AppState>) -> Result<Json<Vec<Show>>, ApiError> {
+    Ok(Json(nonblocking::list_shows(state.pool).await?))
+}
+
+async fn suggest(
+    State(state): State<AppState>,
+    Query(params): Query<SearchParams>,
+) -> Result<Response, ApiError> {
+    // Complete the last word, keeping the rest of the query as typed
+    let (head, last) = match params.q.rsplit_once(char::is_whitespace) {
+        Some((head, last)) => (format!("{} ", head), last.to_string()),
+        None => (String::new(), params.q.clone()),
+    };
+    let suggestions = nonblocking::suggest(state.pool, last, SUGGESTION_LIMIT).await?;
+    let completions: Vec<String> = suggestions
+        .into_iter()
+        .map(|suggestion| head.clone() + &suggestion.word)
+        .collect();
+    let body = serde_json::json!([params.q, completions]).to_string();
+    Ok((
+        [(header::CONTENT_TYPE, "application/x-suggestions+json")],
+        body,
+    )
+        .into_response())
+}
+
+async fn opensearch(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    let base = base_url(&state, &headers);
+    let body = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<OpenSearchDescription xmlns="http://a9.com/-/spec/opensearch/1.1/">
+  <ShortName>Subtitle search</ShortName>
+  <Description>Search Japanese subtitle lines</Description>
+  <InputEncoding>UTF-8</InputEncoding>
+  <Url type="text/html" method="get" template="{base}/?q={{searchTerms}}"/>
+  <Url type="application/x-suggestions+json" method="get" template="{base}/suggest?q={{searchTerms}}"/>
+</OpenSearchDescription>
+"#,
+        base = escape_html(&base)
+    );
+    (
+        [(
+            header::CONTENT_TYPE,
+            "application/opensearchdescription+xml",
+        )],
+        body,
+    )
+        .into_response()
+}
+
+fn base_url(state: &AppState, headers: &HeaderMap) -> String {
+    match &state.public_url {
+        Some(url) => url.trim_end_matches('/').to_string(),
+        None => {
+            let host = headers
+                .get(header::HOST)
+                .and_then(|host| host.to_str().ok())
+                .unwrap_or("localhost");
+            format!("http://{}", host)
+        }
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_utils::{insert_test_lines, TempDb};
+    use crate::db::ApiScope;
+    use crate::tokenizer::test_utils::test_tokenizer;
+    use axum::http::Request;
+    use http_body_util::BodyExt;
+    use tower::ServiceExt;
+
+    async fn get(router: &Router, uri: &str) -> (StatusCode, String, String) {
+        let request = Request::get(uri)
+            .header(header::HOST, "localhost:8080")
+            .body(Body::empty())
+            .unwrap();
+        let response = router.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let content_type = response.headers()[header::CONTENT_TYPE]
+            .to_str()
+            .unwrap()
+            .to_string();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        (
+            status,
+            content_type,
+            String::from_utf8(body.to_vec()).unwrap(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_search_suggest_and_opensearch() {
+        let path = TempDb::new("server");
+        let pool = Arc::new(DbPool::open(&path.0, 2).unwrap());
+        let tokenizer = Arc::new(test_tokenizer());
+        {
+            let mut db = pool.writer();
+            let ids = insert_test_lines(&mut db, &["走った", "走る", "猫が好き"]);
+            db.index_transcripts(&tokenizer, &ids).unwrap();
+        }
+        let router = router(AppState {
+            pool,
+            tokenizer,
+            public_url: None,
+            api_keys: Arc::new(RateLimiter::new()),
+            require_api_key: false,
+            ingest_root: None,
+            query_log: QueryLogConfig::default(),
+            search_backend: BackendConfig::default(),
+            show_types: ShowTypeConfig::default(),
+        });
+
+        let (status, _, body) = get(&router, "/search?q=%E8%B5%B0%E3%82%8B&limit=1").await;
+        assert_eq!(status, StatusCode::OK);
+        let hits: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(hits.as_array().unwrap().len(), 1);
+
+        let request = Request::get("/search?q=%E8%B5%B0%E3%82%8B")
+            .header(header::ACCEPT, NDJSON)
+            .body(Body::empty())
+            .unwrap();
+        let response = router.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.headers()[header::CONTENT_TYPE], NDJSON);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let texts: Vec<String> = String::from_utf8(body.to_vec())
+            .unwrap()
+            .lines()
+            .map(|line| {
+                serde_json::from_str::<serde_json::Value>(line).unwrap()["text"].to_string()
+            })
+            .collect();
+        assert_eq!(texts, [r#""走った""#, r#""走る""#]);
+
+        // 猫 走
+        let (_, content_type, body) = get(&router, "/suggest?q=%E7%8C%AB%20%E8%B5%B0").await;
+        assert_eq!(content_type, "application/x-suggestions+json");
+        assert_eq!(body, r#"["猫 走",["猫 走る"]]"#);
+
+        let (_, content_type, body) = get(&router, "/opensearch.xml").await;
+        assert_eq!(content_type, "application/opensearchdescription+xml");
+        assert!(body.contains(r#"template="http://localhost:8080/suggest?q={searchTerms}""#));
+
+        let (_, _, body) = get(&router, "/context?id=2&before=1&after=0").await;
+        let lines: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(lines[0]["text"], "走った");
+        assert_eq!(lines[1]["text"], "走る");
+
+        let (_, _, body) = get(&router, "/stats?top=1").await;
+        let stats: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(stats["lines"], 3);
+        assert_eq!(stats["top_words"].as_array().unwrap().len(), 1);
+
+        let (_, _, body) = get(&router, "/stats/episodes?show=Show%20Name").await;
+        let episodes: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(episodes[0]["lines"], 3);
+
+        let (_, _, body) = get(&router, "/?q=%E7%8C%AB").await;
+        #[cfg(not(feature = "web"))]
+        assert!(body.contains("猫が好き"));
+        #[cfg(feature = "web")]
+        assert!(body.contains(r#"<script src="/app.js">"#));
+
+        // Ingestion needs a key even when searching doesn't
+        let request = Request::post("/ingest")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(r#"{"root_dir": "/"}"#))
+            .unwrap();
+        let response = router.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_yomitan_sentences_and_audio() {
+        let path = TempDb::new("server_yomitan");
+        let pool = Arc::new(DbPool::open(&path.0, 2).unwrap());
+        let tokenizer = Arc::new(test_tokenizer());
+        let ids = {
+            let mut db = pool.writer();
+            let ids = insert_test_lines(&mut db, &["面白い", "猫が好き"]);
+            db.index_transcripts(&tokenizer, &ids).unwrap();
+            ids
+        };
+        let router = router(AppState {
+            pool: pool.clone(),
+            tokenizer,
+            public_url: None,
+            api_keys: Arc::new(RateLimiter::new()),
+            require_api_key: false,
+            ingest_root: None,
+            query_log: QueryLogConfig::default(),
+            search_backend: BackendConfig::default(),
+            show_types: ShowTypeConfig::default(),
+        });
+
+        // 猫
+        let (status, _, body) = get(&router, "/yomitan/sentences?term=%E7%8C%AB").await;
+        assert_eq!(status, StatusCode::OK);
+        let found: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(found["sentences"][0]["text"], "猫が好き");
+        assert_eq!(
+            found["sentences"][0]["source"],
+            "Show Name S01E01 00:00:01,000"
+        );
+        assert!(found["sentences"][0]["audio"].is_null());
+
+        // おもしろい, which the test dictionary only knows as a reading of 面白い
+        let (_, _, body) = get(
+            &router,
+            "/yomitan/audio?term=x&reading=%E3%81%8A%E3%82%82%E3%81%97%E3%82%8D%E3%81%84",
+        )
+        .await;
+        let sources: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(sources["type"], "audioSourceList");
+        assert_eq!(sources["audioSources"].as_array().unwrap().len(), 0);
+
+        let episode_id = pool
+            .writer()
+            .get_transcript(ids[0])
+            .unwrap()
+            .unwrap()
+            .episode_id;
+        pool.writer()
+            .record_media_files(&[crate::db::MediaFile {
+                episode_id,
+                path: "/nonexistent/Show 01.mkv".to_string(),
+            }])
+            .unwrap();
+        let (_, _, body) = get(
+            &router,
+            "/yomitan/audio?term=x&reading=%E3%81%8A%E3%82%82%E3%81%97%E3%82%8D%E3%81%84",
+        )
+        .await;
+        let sources: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(
+            sources["audioSources"][0]["url"],
+            format!("http://localhost:8080/yomitan/clip?id={}", ids[0])
+        );
+
+        let (status, _, _) = get(&router, "/yomitan/clip?id=999").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_api_keys_and_rate_limits() {
+        let path = TempDb::new("server_auth");
+        let pool = Arc::new(DbPool::open(&path.0, 2).unwrap());
+        let (read_key, ingest_key) = {
+            let db = pool.writer();
+            let (_, read_key) = db.create_api_key("reader", ApiScope::Read, 1).unwrap();
+            let (_, ingest_key) = db.create_api_key("admin", ApiScope::Ingest, 60).unwrap();
+            (read_key, ingest_key)
+        };
+        let router = router(AppState {
+            pool,
+            tokenizer: Arc::new(test_tokenizer()),
+            public_url: None,
+            api_keys: Arc::new(RateLimiter::new()),
+            require_api_key: true,
+            ingest_root: Some(std::env::temp_dir()),
+            query_log: QueryLogConfig::default(),
+            search_backend: BackendConfig::default(),
+            show_types: ShowTypeConfig::default(),
+        });
+        let status = |request: Request<Body>| {
+            let router = router.clone();
+            async move { router.oneshot(request).await.unwrap().status() }
+        };
+        let search = |key: &str| {
+            Request::get("/search?q=x")
+                .header(header::AUTHORIZATION, format!("Bearer {}", key))
+                .body(Body::empty())
+                .unwrap()
+        };
+        let ingest = |key: &str| {
+            Request::post("/ingest")
+                .header("x-api-key", key)
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(r#"{"root_dir": "/nonexistent"}"#))
+                .unwrap()
+        };
+
+        let anonymous = Request::get("/shows").body(Body::empty()).unwrap();
+        assert_eq!(status(anonymous).await, StatusCode::UNAUTHORIZED);
+        // Yomitan can't send keys, so its routes are served without one
+        let yomitan = Request::get("/yomitan/sentences?term=%E7%8C%AB")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(status(yomitan).await, StatusCode::OK);
+        assert_eq!(status(search("wrong")).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(status(search(&read_key)).await, StatusCode::OK);
+        assert_eq!(
+            status(search(&read_key)).await,
+            StatusCode::TOO_MANY_REQUESTS
+        );
+        assert_eq!(status(ingest(&read_key)).await, StatusCode::FORBIDDEN);
+        // Only directories under the ingest root can be ingested
+        assert_eq!(status(ingest(&ingest_key)).await, StatusCode::BAD_REQUEST);
+    }
+}
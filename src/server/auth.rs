@@ -0,0 +1,77 @@
+//! API keys and per-key rate limits, enforced as middleware: on `/ingest`
+//! always, and on every other route when the server is started with
+//! [`AppState::require_api_key`] set.
+//!
+//! Clients send their key as `Authorization: Bearer <key>` or `X-Api-Key: <key>`.
+
+use super::{ApiError, AppState};
+use crate::db::{ApiScope, KeyRejection};
+use crate::nonblocking;
+use axum::extract::{Request, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use std::time::Instant;
+
+pub use crate::db::RateLimiter;
+
+/// Middleware letting through requests with a valid key of at least read
+/// scope, or every request when keys aren't required.
+pub(super) async fn require_read(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if !state.require_api_key {
+        return next.run(request).await;
+    }
+    authorize(state, ApiScope::Read, request, next).await
+}
+
+/// Middleware letting through requests with a valid key of ingest scope.
+pub(super) async fn require_ingest(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    authorize(state, ApiScope::Ingest, request, next).await
+}
+
+async fn authorize(state: AppState, required: ApiScope, request: Request, next: Next) -> Response {
+    let Some(secret) = request_key(request.headers()) else {
+        return (StatusCode::UNAUTHORIZED, "An API key is required").into_response();
+    };
+    let key = match nonblocking::find_api_key(state.pool, secret.to_string()).await {
+        Ok(Some(key)) => key,
+        Ok(None) => return (StatusCode::UNAUTHORIZED, "Unknown API key").into_response(),
+        Err(e) => return ApiError(e).into_response(),
+    };
+    match state.api_keys.admit(&key, required, Instant::now()) {
+        Ok(()) => next.run(request).await,
+        Err(rejection @ KeyRejection::Scope { .. }) => {
+            (StatusCode::FORBIDDEN, rejection.to_string()).into_response()
+        }
+        Err(rejection @ KeyRejection::RateLimited(wait)) => {
+            let retry_after = wait.as_secs().saturating_add(1).to_string();
+            (
+                StatusCode::TOO_MANY_REQUESTS,
+                [(header::RETRY_AFTER, retry_after)],
+                rejection.to_string(),
+            )
+                .into_response()
+        }
+    }
+}
+
+fn request_key(headers: &HeaderMap) -> Option<&str> {
+    let bearer = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    let api_key = || {
+        headers
+            .get("x-api-key")
+            .and_then(|value| value.to_str().ok())
+    };
+    bearer.or_else(api_key).map(str::trim)
+}
@@ -0,0 +1,59 @@
+// The page served at / without the `web` feature: a plain form, rendered on the server
+
+use super::{escape_html, ApiError, AppState, SearchParams, DEFAULT_LIMIT};
+use crate::db::episode_label;
+use crate::nonblocking;
+use axum::extract::{Query, State};
+use axum::response::Html;
+use axum::routing::get;
+use axum::Router;
+use std::fmt::Write;
+
+pub(super) fn routes() -> Router<AppState> {
+    Router::new().route("/", get(index))
+}
+
+async fn index(
+    State(state): State<AppState>,
+    Query(params): Query<SearchParams>,
+) -> Result<Html<String>, ApiError> {
+    let query = params.q.trim().to_string();
+    let mut page = format!(
+        r#"<!DOCTYPE html>
+<html lang="ja">
+<head>
+<meta charset="utf-8">
+<title>Subtitle search</title>
+<link rel="search" type="application/opensearchdescription+xml" title="Subtitle search" href="/opensearch.xml">
+</head>
+<body>
+<form action="/" method="get"><input name="q" value="{}" autofocus> <button>Search</button></form>
+"#,
+        escape_html(&query)
+    );
+    if !query.is_empty() {
+        let hits = nonblocking::search(state.pool, state.tokenizer, query).await?;
+        let _ = writeln!(page, "<p>{} hits</p>\n<ul>", hits.len());
+        for hit in hits.iter().take(params.limit.unwrap_or(DEFAULT_LIMIT)) {
+            let _ = write!(
+                page,
+                "<li>{} {} {}<br>{}",
+                escape_html(&hit.show_name),
+                episode_label(hit.season, hit.episode_number),
+                hit.time_start,
+                escape_html(&hit.text).replace('\n', "<br>")
+            );
+            if let Some(translation) = &hit.translation {
+                let _ = write!(page, "<br><i>{}</i>", escape_html(translation));
+            }
+            // The link jumps to the line's moment in the video where it can
+            if let Some(url) = hit.deep_link.as_ref().or(hit.source_url.as_ref()) {
+                let _ = write!(page, "<br><a href=\"{}\">Source</a>", escape_html(url));
+            }
+            page.push_str("</li>\n");
+        }
+        page.push_str("</ul>\n");
+    }
+    page.push_str("</body>\n</html>\n");
+    Ok(Html(page))
+}
@@ -0,0 +1,125 @@
+"use strict";
+
+// How many lines each "More context" click adds on each side
+const CONTEXT_STEP = 2;
+
+const form = document.getElementById("search");
+const query = document.getElementById("query");
+const show = document.getElementById("show");
+const limit = document.getElementById("limit");
+const status = document.getElementById("status");
+const results = document.getElementById("results");
+const suggestions = document.getElementById("suggestions");
+const template = document.getElementById("hit");
+
+async function getJson(path, params) {
+  const response = await fetch(path + "?" + new URLSearchParams(params));
+  if (!response.ok) {
+    throw new Error(await response.text());
+  }
+  return response.json();
+}
+
+function source(hit) {
+  const season = String(hit.season).padStart(2, "0");
+  const episode = String(hit.episode_number).padStart(2, "0");
+  return `${hit.show_name} S${season}E${episode} ${hit.time_start}`;
+}
+
+function renderHit(hit) {
+  const item = template.content.firstElementChild.cloneNode(true);
+  item.querySelector(".source").textContent = source(hit);
+  item.querySelector(".text").textContent = hit.text;
+  item.querySelector(".translation").textContent = hit.translation || "";
+  // Opens the video at the line, where its source can be linked to at a time
+  if (hit.deep_link) {
+    const watch = item.querySelector(".watch");
+    watch.href = hit.deep_link;
+    watch.hidden = false;
+  }
+
+  let radius = 0;
+  item.querySelector(".more").addEventListener("click", async () => {
+    radius += CONTEXT_STEP;
+    const lines = await getJson("/context", { id: hit.transcript_id, before: radius, after: radius });
+    const container = item.querySelector(".lines");
+    container.replaceChildren(
+      ...lines.map((line) => {
+        const p = document.createElement("p");
+        p.textContent = line.text;
+        p.className = line.transcript_id === hit.transcript_id ? "text" : "context";
+        return p;
+      })
+    );
+  });
+
+  const anki = item.querySelector(".anki");
+  anki.addEventListener("click", async () => {
+    // Tab-separated so it pastes into Anki's import or fills fields in order
+    const note = [hit.text, hit.translation || "", source(hit)].join("\t");
+    await navigator.clipboard.writeText(note);
+    anki.textContent = "Copied";
+    setTimeout(() => (anki.textContent = "Copy for Anki"), 1500);
+  });
+  return item;
+}
+
+async function search() {
+  const params = { q: query.value.trim(), limit: limit.value };
+  if (show.value) {
+    params.show = show.value;
+  }
+  history.replaceState(null, "", "/?" + new URLSearchParams(params));
+  results.replaceChildren();
+  if (!params.q) {
+    status.textContent = "";
+    return;
+  }
+  status.textContent = "Searching…";
+  try {
+    const hits = await getJson("/search", params);
+    status.textContent = `${hits.length} hits`;
+    results.replaceChildren(...hits.map(renderHit));
+  } catch (error) {
+    status.textContent = error.message;
+  }
+}
+
+async function suggest() {
+  const words = query.value.split(/\s+/);
+  const last = words.pop();
+  if (!last) {
+    return;
+  }
+  const [, completions] = await getJson("/suggest", { q: query.value });
+  suggestions.replaceChildren(
+    ...completions.map((completion) => {
+      const option = document.createElement("option");
+      option.value = completion;
+      return option;
+    })
+  );
+}
+
+async function loadShows() {
+  const shows = await getJson("/shows", {});
+  for (const { name } of shows) {
+    const option = document.createElement("option");
+    option.value = option.textContent = name;
+    show.append(option);
+  }
+}
+
+form.addEventListener("submit", (event) => {
+  event.preventDefault();
+  search();
+});
+query.addEventListener("input", () => suggest().catch(() => {}));
+
+const initial = new URLSearchParams(location.search);
+query.value = initial.get("q") || "";
+limit.value = initial.get("limit") || limit.value;
+loadShows().then(() => {
+  show.value = initial.get("show") || "";
+  search();
+});
@@ -0,0 +1,184 @@
+// Endpoints for Yomitan (and Yomichan) popup dictionaries, so looking up a word
+// in the browser can show example sentences and play audio from your own corpus
+//
+// - GET /yomitan/audio?term=...&reading=...: a "Custom URL (JSON)" audio source
+//   list, with a clip for each hit whose episode has a media file (see `map-media`)
+// - GET /yomitan/clip?id=...: the audio of one line as MP3, cut with ffmpeg
+// - GET /yomitan/sentences?term=...&reading=...&limit=...: example sentences as JSON
+//
+// Terms without hits are looked up again by their reading
+
+use super::{base_url, ApiError, AppState};
+use crate::db::{SearchHit, TranscriptId};
+use crate::export::attribution;
+use crate::media::extract_audio_clip;
+use crate::nonblocking;
+use axum::extract::{Query, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+// Clips offered per lookup; Yomitan tries them in order
+const MAX_AUDIO_SOURCES: usize = 5;
+
+// Sentences returned when the request doesn't set a limit
+const DEFAULT_SENTENCES: usize = 10;
+
+pub(super) fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/yomitan/audio", get(audio))
+        .route("/yomitan/clip", get(clip))
+        .route("/yomitan/sentences", get(sentences))
+}
+
+#[derive(Deserialize)]
+struct LookupParams {
+    #[serde(default)]
+    term: String,
+    #[serde(default)]
+    reading: String,
+    limit: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct AudioSource {
+    name: String,
+    url: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct AudioSourceList {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    audio_sources: Vec<AudioSource>,
+}
+
+#[derive(Serialize)]
+struct Sentence {
+    id: TranscriptId,
+    text: String,
+    translation: Option<String>,
+    source: String,
+    /// Where to get the line's audio, if its episode has a media file
+    audio: Option<String>,
+}
+
+#[derive(Serialize)]
+struct SentenceList {
+    term: String,
+    sentences: Vec<Sentence>,
+}
+
+// The hits for the term, or for its reading if the term has none
+async fn lookup(state: &AppState, params: &LookupParams) -> Result<Vec<SearchHit>, ApiError> {
+    let term = params.term.trim();
+    let reading = params.reading.trim();
+    let mut hits = Vec::new();
+    for query in [term, reading] {
+        if hits.is_empty() && !query.is_empty() {
+            hits = nonblocking::search(
+                state.pool.clone(),
+                state.tokenizer.clone(),
+                query.to_string(),
+            )
+            .await?;
+        }
+    }
+    Ok(hits)
+}
+
+// The URL of a hit's clip, if its episode has a media file
+async fn clip_url(
+    state: &AppState,
+    base: &str,
+    hit: &SearchHit,
+) -> Result<Option<String>, ApiError> {
+    let media = nonblocking::line_media_file(state.pool.clone(), hit.transcript_id).await?;
+    Ok(media.map(|_| format!("{}/yomitan/clip?id={}", base, hit.transcript_id)))
+}
+
+async fn audio(
+    State(state): State<AppState>,
+    Query(params): Query<LookupParams>,
+    headers: HeaderMap,
+) -> Result<Json<AudioSourceList>, ApiError> {
+    let base = base_url(&state, &headers);
+    let mut audio_sources = Vec::new();
+    for hit in lookup(&state, &params).await? {
+        if audio_sources.len() == MAX_AUDIO_SOURCES {
+            break;
+        }
+        if let Some(url) = clip_url(&state, &base, &hit).await? {
+            audio_sources.push(AudioSource {
+                name: attribution(&hit),
+                url,
+            });
+        }
+    }
+    Ok(Json(AudioSourceList {
+        kind: "audioSourceList",
+        audio_sources,
+    }))
+}
+
+async fn sentences(
+    State(state): State<AppState>,
+    Query(params): Query<LookupParams>,
+    headers: HeaderMap,
+) -> Result<Json<SentenceList>, ApiError> {
+    let base = base_url(&state, &headers);
+    let limit = params.limit.unwrap_or(DEFAULT_SENTENCES);
+    let mut sentences = Vec::new();
+    for hit in lookup(&state, &params).await?.into_iter().take(limit) {
+        sentences.push(Sentence {
+            audio: clip_url(&state, &base, &hit).await?,
+            source: attribution(&hit),
+            id: hit.transcript_id,
+            text: hit.text,
+            translation: hit.translation,
+        });
+    }
+    Ok(Json(SentenceList {
+        term: params.term,
+        sentences,
+    }))
+}
+
+#[derive(Deserialize)]
+struct ClipParams {
+    id: i64,
+}
+
+async fn clip(
+    State(state): State<AppState>,
+    Query(params): Query<ClipParams>,
+) -> Result<Response, ApiError> {
+    let id = TranscriptId(params.id);
+    let not_found =
+        |message: &str| Ok((StatusCode::NOT_FOUND, message.to_string()).into_response());
+    let Some(line) = nonblocking::context(state.pool.clone(), id, 0, 0)
+        .await?
+        .into_iter()
+        .next()
+    else {
+        return not_found("No line with this id");
+    };
+    let Some(media) = nonblocking::line_media_file(state.pool, id).await? else {
+        return not_found("The line's episode has no media file");
+    };
+    let task = tokio::task::spawn_blocking(move || {
+        extract_audio_clip(&PathBuf::from(media), &line.time_start, &line.time_end)
+    });
+    let clip = match task.await {
+        Ok(result) => result,
+        Err(e) => std::panic::resume_unwind(e.into_panic()),
+    };
+    Ok(match clip {
+        Ok(mp3) => ([(header::CONTENT_TYPE, "audio/mpeg")], mp3).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    })
+}
@@ -1,8 +1,29 @@
+mod archive;
+mod ass;
+mod bilingual;
 mod episode_info;
 mod errors;
+mod language;
+mod lrc;
 mod parsing;
+mod stats;
+mod ttml;
 mod types;
+mod writer;
+mod youtube;
 
+pub use archive::{is_archive_file, process_archive};
+pub use bilingual::{
+    align_translations, find_original_file, find_translation_file, is_translation_file,
+};
 pub use episode_info::{EpisodeNameMethod, EpisodeNumberMethod};
-pub use parsing::{process_srt_directory, process_srt_file, SrtEntry};
+pub use errors::{Diagnostic, ParsingError, Position};
+pub use language::{detect_language, Language};
+pub use parsing::{
+    is_subtitle_file, process_srt_directory, process_srt_directory_filtered, process_srt_file,
+    SrtEntry, SubtitleFormat,
+};
+pub use stats::{CleaningStats, ParseStats};
 pub use types::{Subtitle, Subtitles, Timestamp};
+pub use writer::LineEnding;
+pub use youtube::{youtube_video, YoutubeVideo};
@@ -0,0 +1,318 @@
+use super::bilingual::{base_name, is_translation_file, JAPANESE_SUFFIXES, TRANSLATION_SUFFIXES};
+use super::episode_info::{
+    get_episode_name, get_episode_number, EpisodeNameMethod, EpisodeNumberMethod,
+};
+use super::errors::ParsingError;
+use super::language::{detect_language, Language};
+use super::parsing::{is_subtitle_file, SrtEntry, SubtitleFormat};
+use super::types::Subtitles;
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use walkdir::WalkDir;
+
+/// The archive formats subtitle files can be ingested from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ArchiveFormat {
+    /// Read in memory
+    Zip,
+    /// Extracted with the `7z` command
+    SevenZip,
+    /// Extracted with the `unrar` command
+    Rar,
+}
+
+impl ArchiveFormat {
+    fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "zip" => Some(ArchiveFormat::Zip),
+            "7z" => Some(ArchiveFormat::SevenZip),
+            "rar" => Some(ArchiveFormat::Rar),
+            _ => None,
+        }
+    }
+}
+
+/// Whether `path` has the extension of a supported archive format.
+pub fn is_archive_file(path: &Path) -> bool {
+    ArchiveFormat::from_path(path).is_some()
+}
+
+/// Reads the subtitle files in an archive (one show per archive, as downloaded
+/// from kitsunekko) as entries of a show named after the archive.
+///
+/// Zip archives are read in memory; `.7z` and `.rar` archives need the `7z`
+/// and `unrar` commands, and are extracted to a temporary directory. Episodes
+/// are numbered and named as in a show directory, with the archive's files in
+/// place of the directory's, and English files are paired with the Japanese
+/// ones they belong to. Each entry's path is the archive's joined with the
+/// file's path inside it.
+pub fn process_archive(
+    path: &Path,
+    number_method: &EpisodeNumberMethod,
+    name_method: &EpisodeNameMethod,
+) -> Result<Vec<SrtEntry>, ParsingError> {
+    let show_name = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("Unknown Show")
+        .to_string();
+    let mut files = match ArchiveFormat::from_path(path) {
+        Some(ArchiveFormat::Zip) => read_zip(path)?,
+        Some(ArchiveFormat::SevenZip) => extract_with_command(path, "7z", |dir| {
+            let output = format!("-o{}", dir.display());
+            vec!["x".into(), "-y".into(), output.into(), path.into()]
+        })?,
+        // unrar takes a destination ending in a separator as the directory to extract to
+        Some(ArchiveFormat::Rar) => extract_with_command(path, "unrar", |dir| {
+            let output = format!("{}{}", dir.display(), std::path::MAIN_SEPARATOR);
+            vec!["x".into(), "-y".into(), path.into(), output.into()]
+        })?,
+        None => return Err(ParsingError::ArchiveError("not an archive".to_string())),
+    };
+    files.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let episodes: Vec<&(PathBuf, String)> = files
+        .iter()
+        .filter(|(name, _)| !is_translation_file(name))
+        .collect();
+    let mut entries = Vec::new();
+    for (name, content) in &episodes {
+        let parsed = Subtitles::parse_content_with_stats(content, SubtitleFormat::from_path(name));
+        let (content, stats) = match parsed {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                eprintln!("Error processing file {:?} in {:?}: {}", name, path, e);
+                continue;
+            }
+        };
+        let episode_number = match number_method {
+            // Numbered among the files with the same language suffix, as in a directory
+            EpisodeNumberMethod::FromFileOrder => {
+                let language = Language::from_suffix(name);
+                episodes
+                    .iter()
+                    .filter(|(other, _)| Language::from_suffix(other) == language)
+                    .position(|(other, _)| other == name)
+                    .map_or(0, |position| position as i32 + 1)
+            }
+            method => get_episode_number(method, &show_name, name, Path::new("")),
+        };
+        let episode_name = get_episode_name(name_method, name, episode_number)
+            .unwrap_or_else(|| format!("Episode {}", episode_number));
+        let translation = match find_translation(&files, name) {
+            Some((translation, text)) => {
+                let format = SubtitleFormat::from_path(translation);
+                match Subtitles::parse_content_with_stats(text, format) {
+                    Ok((subtitles, _)) => Some(subtitles),
+                    // A broken translation shouldn't keep the Japanese lines out
+                    Err(e) => {
+                        eprintln!(
+                            "Error processing translation {:?} in {:?}: {}",
+                            translation, path, e
+                        );
+                        None
+                    }
+                }
+            }
+            None => None,
+        };
+        let language = detect_language(name, &content);
+        entries.push(SrtEntry {
+            path: path.join(name),
+            show_name: show_name.clone(),
+            episode_name,
+            episode_number: Some(episode_number),
+            content,
+            translation,
+            stats,
+            language,
+        });
+    }
+    Ok(entries)
+}
+
+// The English file among `files` that belongs to the Japanese file `name`
+fn find_translation<'a>(
+    files: &'a [(PathBuf, String)],
+    name: &Path,
+) -> Option<&'a (PathBuf, String)> {
+    let base = base_name(name, &JAPANESE_SUFFIXES)?;
+    files.iter().find(|(candidate, _)| {
+        is_translation_file(candidate)
+            && candidate.parent() == name.parent()
+            && base_name(candidate, &TRANSLATION_SUFFIXES).as_deref() == Some(base.as_str())
+    })
+}
+
+// The subtitle files in a zip archive, by path inside it
+fn read_zip(path: &Path) -> Result<Vec<(PathBuf, String)>, ParsingError> {
+    let mut archive = zip::ZipArchive::new(File::open(path)?)
+        .map_err(|e| ParsingError::ArchiveError(e.to_string()))?;
+    let mut files = Vec::new();
+    for i in 0..archive.len() {
+        let mut file = archive
+            .by_index(i)
+            .map_err(|e| ParsingError::ArchiveError(e.to_string()))?;
+        // Names that would escape the archive, like `../x.srt`, are skipped
+        let Some(name) = file.enclosed_name() else {
+            continue;
+        };
+        if !file.is_file() || !is_subtitle_file(&name) {
+            continue;
+        }
+        let mut content = String::new();
+        match file.read_to_string(&mut content) {
+            Ok(_) => files.push((name, content)),
+            Err(e) => eprintln!("Error reading {:?} in {:?}: {}", name, path, e),
+        }
+    }
+    Ok(files)
+}
+
+// The subtitle files in an archive extracted by running `program` with the arguments
+// `args` gives for the directory to extract to, by path inside the archive
+fn extract_with_command(
+    path: &Path,
+    program: &str,
+    args: impl FnOnce(&Path) -> Vec<OsString>,
+) -> Result<Vec<(PathBuf, String)>, ParsingError> {
+    let dir = std::env::temp_dir().join(format!(
+        "anime_search_archive_{}_{}",
+        std::process::id(),
+        path.file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("archive")
+    ));
+    std::fs::create_dir_all(&dir)?;
+    let files = run_extraction(program, args(&dir)).map(|()| read_extracted(path, &dir));
+    // Extracted files are only needed until they're read
+    let _ = std::fs::remove_dir_all(&dir);
+    files
+}
+
+fn run_extraction(program: &str, args: Vec<OsString>) -> Result<(), ParsingError> {
+    let output = Command::new(program).args(args).output().map_err(|e| {
+        ParsingError::ArchiveError(format!(
+            "couldn't run {} (is it installed?): {}",
+            program, e
+        ))
+    })?;
+    if !output.status.success() {
+        return Err(ParsingError::ArchiveError(format!(
+            "{} failed: {}",
+            program,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(())
+}
+
+// The subtitle files under `dir`, where `archive` was extracted, by path relative to it
+fn read_extracted(archive: &Path, dir: &Path) -> Vec<(PathBuf, String)> {
+    let mut files = Vec::new();
+    for entry in WalkDir::new(dir).into_iter().filter_map(|e| e.ok()) {
+        let file = entry.path();
+        if entry.file_type().is_file() && is_subtitle_file(file) {
+            let name = file.strip_prefix(dir).unwrap_or(file).to_path_buf();
+            match std::fs::read_to_string(file) {
+                Ok(content) => files.push((name, content)),
+                Err(e) => eprintln!("Error reading {:?} in {:?}: {}", name, archive, e),
+            }
+        }
+    }
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use zip::write::SimpleFileOptions;
+
+    #[test]
+    fn test_process_zip_archive() {
+        let path = std::env::temp_dir().join("anime_search_test_archive.zip");
+        {
+            let mut writer = zip::ZipWriter::new(File::create(&path).unwrap());
+            let files = [
+                (
+                    "Show/02.srt",
+                    "1\n00:00:01,000 --> 00:00:02,000\n二話目だよ\n",
+                ),
+                (
+                    "Show/01.srt",
+                    "1\n00:00:01,000 --> 00:00:02,000\n一話目だよ\n",
+                ),
+                (
+                    "Show/01.en.srt",
+                    "1\n00:00:01,000 --> 00:00:02,000\nEpisode one\n",
+                ),
+                ("Show/notes.txt", "not a subtitle"),
+            ];
+            for (name, content) in files {
+                writer
+                    .start_file(name, SimpleFileOptions::default())
+                    .unwrap();
+                writer.write_all(content.as_bytes()).unwrap();
+            }
+            writer.finish().unwrap();
+        }
+        let entries = process_archive(
+            &path,
+            &EpisodeNumberMethod::FromFileOrder,
+            &EpisodeNameMethod::FromEpisodeNumber,
+        )
+        .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].show_name, "anime_search_test_archive");
+        assert_eq!(entries[0].episode_number, Some(1));
+        assert_eq!(entries[0].content.0[0].text, "一話目だよ");
+        assert_eq!(entries[0].path, path.join("Show/01.srt"));
+        let translation = entries[0].translation.as_ref().unwrap();
+        assert_eq!(translation.0[0].text, "Episode one");
+        assert_eq!(entries[1].episode_number, Some(2));
+        assert!(entries[1].translation.is_none());
+    }
+
+    #[test]
+    fn test_process_archive_skips_broken_translation() {
+        let path = std::env::temp_dir().join(format!(
+            "anime_search_broken_translation-{}.zip",
+            std::process::id()
+        ));
+        {
+            let mut writer = zip::ZipWriter::new(File::create(&path).unwrap());
+            let files = [
+                (
+                    "Show/01.srt",
+                    "1\n00:00:01,000 --> 00:00:02,000\n一話目だよ\n",
+                ),
+                ("Show/01.en.srt", "not a subtitle"),
+            ];
+            for (name, content) in files {
+                writer
+                    .start_file(name, SimpleFileOptions::default())
+                    .unwrap();
+                writer.write_all(content.as_bytes()).unwrap();
+            }
+            writer.finish().unwrap();
+        }
+        let entries = process_archive(
+            &path,
+            &EpisodeNumberMethod::FromFileOrder,
+            &EpisodeNameMethod::FromEpisodeNumber,
+        );
+        std::fs::remove_file(&path).unwrap();
+        let entries = entries.unwrap();
+
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].content.0[0].text, "一話目だよ");
+        assert!(entries[0].translation.is_none());
+    }
+}
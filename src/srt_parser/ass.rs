@@ -0,0 +1,269 @@
+use super::errors::{Diagnostic, ParsingError, Position};
+use super::stats::{clean_input, ParseStats};
+use super::types::{Subtitle, Subtitles, Timestamp};
+use regex::Regex;
+use std::collections::HashMap;
+
+// The columns of events in files whose [Events] section has no Format line
+const DEFAULT_EVENT_FORMAT: &str =
+    "Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text";
+
+// Words in style names that mark songs and signs rather than dialogue, e.g. `OP Romaji`,
+// `Karaoke-JP` or `Sign_Top`; matched against whole words of the name, less any trailing
+// number, so character names like `Karen` or `Titania` aren't taken for them
+const SIGN_STYLE_WORDS: &[&str] = &[
+    "op", "ed", "opening", "ending", "insert", "song", "songs", "lyric", "lyrics", "kara",
+    "karaoke", "romaji", "kfx", "sign", "signs", "title", "titles", "note", "notes",
+];
+
+impl Subtitles {
+    /// Parses Advanced SubStation Alpha (`.ass`) and SubStation Alpha (`.ssa`)
+    /// subtitles.
+    ///
+    /// Each `Dialogue` event becomes one cue, in start order; override blocks
+    /// (`{\i1}`) are removed and `\N` becomes a newline. Events that are songs
+    /// or signs rather than dialogue are dropped, so they don't fill the index
+    /// with lyrics and on-screen text: those on styles named like `OP`,
+    /// `Karaoke` or `Sign`, karaoke-timed ones (`\k` tags), drawings (`\p1`),
+    /// and ones positioned at the top of the screen by their style or an `\an7`
+    /// to `\an9` tag. The `Name` (actor) field of each event, when set, is
+    /// kept as the speaker of its cue.
+    pub fn parse_ass(input: &str) -> Result<Self, ParsingError> {
+        Self::parse_ass_with_stats(input, &mut ParseStats::default())
+    }
+
+    pub(super) fn parse_ass_with_stats(
+        input: &str,
+        stats: &mut ParseStats,
+    ) -> Result<Self, ParsingError> {
+        let input = &clean_input(input, &mut stats.cleaning);
+        let regex =
+            |pattern: &str| Regex::new(pattern).map_err(|_| ParsingError::MalformedSubtitle);
+        let override_block = regex(r"\{[^}]*\}")?;
+        let karaoke = regex(r"\\[kK][fo]?\d")?;
+        let drawing = regex(r"\\p[1-9]")?;
+        let top_alignment = regex(r"\\an[789]")?;
+
+        // Styles by name, whether each is a sign style, from the sections before the events
+        let mut sign_styles: HashMap<String, bool> = HashMap::new();
+        let mut section = String::new();
+        let mut style_format: Vec<String> = Vec::new();
+        let mut event_format = columns(DEFAULT_EVENT_FORMAT);
+        let mut events = Vec::new();
+        let mut offset = 0;
+        for line in input.split('\n') {
+            let line_offset = offset;
+            offset += line.len() + 1;
+            let line = line.trim();
+            if line.starts_with('[') && line.ends_with(']') {
+                section = line[1..line.len() - 1].to_ascii_lowercase();
+                continue;
+            }
+            let Some((kind, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim_start();
+            match (section.as_str(), kind) {
+                (_, "Format") if section.ends_with("styles") => style_format = columns(value),
+                ("events", "Format") => event_format = columns(value),
+                (_, "Style") if section.ends_with("styles") => {
+                    let fields = fields(value, style_format.len());
+                    let field = |name: &str| {
+                        style_format
+                            .iter()
+                            .position(|column| column == name)
+                            .and_then(|i| fields.get(i))
+                            .map_or("", |field| field.trim())
+                    };
+                    // SSA numbers alignments 1-3 bottom, 5-7 top and 9-11 middle;
+                    // ASS like a numeric keypad, 7-9 being the top
+                    let top = match section.as_str() {
+                        "v4 styles" => matches!(field("alignment"), "5" | "6" | "7"),
+                        _ => matches!(field("alignment"), "7" | "8" | "9"),
+                    };
+                    let name = field("name");
+                    sign_styles.insert(name.to_string(), top || is_sign_style(name));
+                }
+                ("events", "Dialogue") => events.push((line_offset, value)),
+                _ => {}
+            }
+        }
+
+        let column = |name: &str| event_format.iter().position(|column| column == name);
+        let (Some(start_column), Some(end_column), Some(text_column)) =
+            (column("start"), column("end"), column("text"))
+        else {
+            return Err(ParsingError::MalformedSubtitle);
+        };
+        let style_column = column("style");
+        let name_column = column("name").or_else(|| column("actor"));
+
+        let mut cues = Vec::new();
+        for (i, (line_offset, value)) in events.into_iter().enumerate() {
+            let fields = fields(value, event_format.len());
+            let (Some(start), Some(end), Some(raw_text)) = (
+                fields.get(start_column).and_then(|time| parse_time(time)),
+                fields.get(end_column).and_then(|time| parse_time(time)),
+                fields.get(text_column),
+            ) else {
+                stats.diagnostics.push(Diagnostic::new(
+                    Position::locate(input, line_offset, i + 1),
+                    ParsingError::InvalidTimestamp,
+                ));
+                stats.cues_skipped += 1;
+                continue;
+            };
+
+            let style = style_column
+                .and_then(|column| fields.get(column))
+                .map_or("", |style| style.trim().trim_start_matches('*'));
+            let is_sign = sign_styles
+                .get(style)
+                .copied()
+                .unwrap_or_else(|| is_sign_style(style))
+                || karaoke.is_match(raw_text)
+                || drawing.is_match(raw_text)
+                || top_alignment.is_match(raw_text);
+            if is_sign {
+                stats.cleaning.sign_events_removed += 1;
+                continue;
+            }
+
+            stats.cleaning.tags_removed += override_block.find_iter(raw_text).count();
+            let text = override_block
+                .replace_all(raw_text, "")
+                .replace("\\N", "\n")
+                .replace("\\n", "\n")
+                .replace("\\h", " ");
+            let text = text
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .collect::<Vec<_>>()
+                .join("\n");
+            let speaker = name_column
+                .and_then(|column| fields.get(column))
+                .map(|name| name.trim())
+                .filter(|name| !name.is_empty())
+                .map(str::to_string);
+            if !text.is_empty() {
+                cues.push((start, end, text, raw_text.to_string(), speaker));
+            }
+        }
+        // Events are often grouped by style rather than in time order
+        cues.sort_by_key(|(start, end, ..)| (*start, *end));
+
+        let mut subtitles = Subtitles::new();
+        for (start, end, text, raw_text, speaker) in cues {
+            subtitles.push(
+                Subtitle::new(
+                    subtitles.len() + 1,
+                    Timestamp::from_millis(start),
+                    Timestamp::from_millis(end),
+                    text,
+                )
+                .with_raw_text(raw_text)
+                .with_speaker(speaker),
+            );
+        }
+
+        if subtitles.is_empty() {
+            Err(ParsingError::MalformedSubtitle)
+        } else {
+            Ok(subtitles)
+        }
+    }
+}
+
+// The lowercased column names of a Format line
+fn columns(format: &str) -> Vec<String> {
+    format
+        .split(',')
+        .map(|column| column.trim().to_ascii_lowercase())
+        .collect()
+}
+
+// The fields of a Style or Dialogue line with `count` columns; the last one, the
+// text of events, may itself contain commas
+fn fields(value: &str, count: usize) -> Vec<&str> {
+    value.splitn(count.max(1), ',').collect()
+}
+
+// An ASS time, `H:MM:SS.cc` in centiseconds, in milliseconds
+fn parse_time(time: &str) -> Option<u64> {
+    let (clock, fraction) = time.trim().split_once('.')?;
+    let mut parts = clock.split(':');
+    let (hours, minutes, seconds) = (parts.next()?, parts.next()?, parts.next()?);
+    if parts.next().is_some() {
+        return None;
+    }
+    let hours: u64 = hours.parse().ok()?;
+    let minutes: u64 = minutes.parse().ok()?;
+    let seconds: u64 = seconds.parse().ok()?;
+    // ".5" means 500ms and ".05" 50ms
+    let millis: u64 = format!("{:0<3}", fraction).get(..3)?.parse().ok()?;
+    Some(((hours * 60 + minutes) * 60 + seconds) * 1000 + millis)
+}
+
+// Whether a style's name marks songs or signs, e.g. `OP Romaji` or `Sign_Top`
+fn is_sign_style(name: &str) -> bool {
+    name.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .map(|word| word.trim_end_matches(|c: char| c.is_ascii_digit()))
+        .any(|word| SIGN_STYLE_WORDS.contains(&word))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ass() {
+        let input = "[Script Info]\nScriptType: v4.00+\n\n\
+            [V4+ Styles]\n\
+            Format: Name, Fontname, Fontsize, Alignment\n\
+            Style: Default,Arial,20,2\n\
+            Style: Top,Arial,20,8\n\
+            Style: OP Romaji,Arial,20,2\n\n\
+            [Events]\n\
+            Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n\
+            Dialogue: 0,0:00:05.00,0:00:07.50,Default,,0,0,0,,{\\i1}猫だ、{\\i0}可愛い\\N本当に\n\
+            Dialogue: 0,0:00:01.00,0:00:03.00,*Default,太郎,0,0,0,,おはよう\n\
+            Comment: 0,0:00:02.00,0:00:03.00,Default,,0,0,0,,訳注\n\
+            Dialogue: 0,0:00:01.00,0:00:03.00,OP Romaji,,0,0,0,,{\\k20}yume {\\k30}no\n\
+            Dialogue: 0,0:00:01.00,0:00:03.00,Default,,0,0,0,,{\\kf50}夢の\n\
+            Dialogue: 0,0:00:01.00,0:00:03.00,Top,,0,0,0,,第一話\n\
+            Dialogue: 0,0:00:01.00,0:00:03.00,Default,,0,0,0,,{\\an8}東京駅\n\
+            Dialogue: 0,0:00:01.00,0:00:03.00,Default,,0,0,0,,{\\p1}m 0 0 l 100 0\n\
+            Dialogue: 0,0:00:xx.00,0:00:03.00,Default,,0,0,0,,壊れた\n";
+        let mut stats = ParseStats::default();
+        let subtitles = Subtitles::parse_ass_with_stats(input, &mut stats).unwrap();
+        assert_eq!(subtitles.len(), 2);
+        assert_eq!(subtitles.0[0].text, "おはよう");
+        assert_eq!(subtitles.0[0].speaker.as_deref(), Some("太郎"));
+        assert_eq!(subtitles.0[1].speaker, None);
+        assert_eq!(subtitles.0[1].text, "猫だ、可愛い\n本当に");
+        assert_eq!(subtitles.0[1].start_time.to_string(), "00:00:05,000");
+        assert_eq!(subtitles.0[1].end_time.to_string(), "00:00:07,500");
+        assert_eq!(subtitles.0[1].number, 2);
+        assert_eq!(stats.cleaning.sign_events_removed, 5);
+        assert_eq!(stats.cleaning.tags_removed, 2);
+        assert_eq!(stats.cues_skipped, 1);
+    }
+
+    #[test]
+    fn test_is_sign_style() {
+        assert!(is_sign_style("OP Romaji"));
+        assert!(is_sign_style("Karaoke-JP"));
+        assert!(is_sign_style("sign_top"));
+        assert!(is_sign_style("ED"));
+        assert!(!is_sign_style("Default"));
+        assert!(!is_sign_style("Editorial"));
+        assert!(!is_sign_style("Flashback"));
+        assert!(is_sign_style("OP1"));
+        assert!(is_sign_style("Kara Title"));
+        assert!(!is_sign_style("Karen"));
+        assert!(!is_sign_style("Karasuma"));
+        assert!(!is_sign_style("Titania"));
+    }
+}
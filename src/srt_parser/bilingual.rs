@@ -0,0 +1,142 @@
+use super::parsing::is_subtitle_file;
+use super::types::Subtitles;
+use std::path::{Path, PathBuf};
+
+// Language suffixes before the extension, e.g. `Episode 01.en.srt`
+pub(super) const TRANSLATION_SUFFIXES: [&str; 3] = ["en", "eng", "english"];
+pub(super) const JAPANESE_SUFFIXES: [&str; 4] = ["ja", "jp", "jpn", "japanese"];
+
+/// Whether `path` is an English subtitle file meant to be paired with a Japanese one.
+pub fn is_translation_file(path: &Path) -> bool {
+    is_subtitle_file(path)
+        && language_suffix(path).is_some_and(|s| is_one_of(s, &TRANSLATION_SUFFIXES))
+}
+
+/// Finds the English subtitle file next to a Japanese one.
+///
+/// `Episode 01.srt` and `Episode 01.ja.srt` both pair with `Episode 01.en.srt`
+/// (or `.eng`/`.english`, in any supported subtitle format).
+pub fn find_translation_file(path: &Path) -> Option<PathBuf> {
+    let base = base_name(path, &JAPANESE_SUFFIXES)?;
+    let dir = path.parent()?;
+    sibling_subtitle_files(dir).into_iter().find(|candidate| {
+        is_translation_file(candidate)
+            && base_name(candidate, &TRANSLATION_SUFFIXES).as_deref() == Some(base.as_str())
+    })
+}
+
+/// Finds the Japanese subtitle file an English one belongs to.
+pub fn find_original_file(translation: &Path) -> Option<PathBuf> {
+    let base = base_name(translation, &TRANSLATION_SUFFIXES)?;
+    let dir = translation.parent()?;
+    sibling_subtitle_files(dir).into_iter().find(|candidate| {
+        !is_translation_file(candidate)
+            && base_name(candidate, &JAPANESE_SUFFIXES).as_deref() == Some(base.as_str())
+    })
+}
+
+/// Pairs each Japanese cue with the English cues that overlap it in time.
+///
+/// An English cue is attached when it overlaps the Japanese cue for at least
+/// half the duration of the shorter of the two, so a long English line can
+/// belong to several short Japanese ones and vice versa. Returns one entry per
+/// Japanese cue, with multiple English cues joined by a space.
+pub fn align_translations(japanese: &Subtitles, english: &Subtitles) -> Vec<Option<String>> {
+    japanese
+        .iter()
+        .map(|cue| {
+            let (start, end) = (cue.start_time.to_millis(), cue.end_time.to_millis());
+            let texts: Vec<String> = english
+                .iter()
+                .filter(|other| {
+                    let (other_start, other_end) =
+                        (other.start_time.to_millis(), other.end_time.to_millis());
+                    let overlap = end.min(other_end).saturating_sub(start.max(other_start));
+                    let shorter =
+                        (end.saturating_sub(start)).min(other_end.saturating_sub(other_start));
+                    overlap > 0 && overlap * 2 >= shorter
+                })
+                .map(|other| other.text.replace('\n', " "))
+                .collect();
+            if texts.is_empty() {
+                None
+            } else {
+                Some(texts.join(" "))
+            }
+        })
+        .collect()
+}
+
+fn language_suffix(path: &Path) -> Option<&str> {
+    Path::new(path.file_stem()?).extension()?.to_str()
+}
+
+fn is_one_of(suffix: &str, suffixes: &[&str]) -> bool {
+    suffixes.iter().any(|s| s.eq_ignore_ascii_case(suffix))
+}
+
+// The file name without its extension and, if it is one of `suffixes`, its language suffix
+pub(super) fn base_name(path: &Path, suffixes: &[&str]) -> Option<String> {
+    let stem = Path::new(path.file_stem()?);
+    let base = match language_suffix(path) {
+        Some(suffix) if is_one_of(suffix, suffixes) => stem.file_stem()?,
+        _ => stem.as_os_str(),
+    };
+    base.to_str().map(String::from)
+}
+
+fn sibling_subtitle_files(dir: &Path) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = std::fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| is_subtitle_file(path))
+        .collect();
+    files.sort();
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_align_translations() {
+        let japanese = Subtitles::parse_from_str(
+            "1\n00:00:01,000 --> 00:00:03,000\n行こう\n\n\
+             2\n00:00:03,000 --> 00:00:04,000\nうん\n\n\
+             3\n00:00:10,000 --> 00:00:11,000\n誰？\n",
+        )
+        .unwrap();
+        let english = Subtitles::parse_from_str(
+            "1\n00:00:01,200 --> 00:00:02,900\nLet's\ngo.\n\n\
+             2\n00:00:02,800 --> 00:00:04,100\nYeah.\n",
+        )
+        .unwrap();
+        assert_eq!(
+            align_translations(&japanese, &english),
+            vec![
+                Some("Let's go.".to_string()),
+                Some("Yeah.".to_string()),
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn test_base_name() {
+        let japanese = Path::new("Show/Episode 01.ja.srt");
+        let english = Path::new("Show/Episode 01.en.lrc");
+        assert_eq!(
+            base_name(japanese, &JAPANESE_SUFFIXES).unwrap(),
+            "Episode 01"
+        );
+        assert_eq!(
+            base_name(english, &TRANSLATION_SUFFIXES).unwrap(),
+            "Episode 01"
+        );
+        assert!(is_translation_file(english));
+        assert!(!is_translation_file(japanese));
+        assert!(!is_translation_file(Path::new("Show/Episode 01.srt")));
+    }
+}
@@ -1,7 +1,11 @@
+use super::bilingual::is_translation_file;
+use super::language::Language;
+use super::parsing::is_subtitle_file;
 use regex::Regex;
 use std::fs;
 use std::path::Path;
 
+#[allow(clippy::enum_variant_names)]
 pub enum EpisodeNumberMethod {
     FromFilename,
     FromFileOrder,
@@ -65,14 +69,20 @@ fn get_episode_number_from_filename(file_path: &Path) -> Option<i32> {
         .and_then(|m| m.as_str().parse().ok())
 }
 
+// Variants of an episode in other languages (`Episode 01.zh.srt` next to `Episode 01.srt`)
+// are numbered among the files with the same language suffix, so they share its number
 fn get_episode_number_from_file_order(show_name: &str, file_path: &Path, root: &Path) -> i32 {
     let show_dir = root.join(show_name);
+    let language = Language::from_suffix(file_path);
     let mut episode_files: Vec<_> = fs::read_dir(show_dir)
         .unwrap()
         .filter_map(|entry| {
             let entry = entry.ok()?;
             let path = entry.path();
-            if path.extension()? == "srt" {
+            if is_subtitle_file(&path)
+                && !is_translation_file(&path)
+                && Language::from_suffix(&path) == language
+            {
                 Some(path)
             } else {
                 None
@@ -1,3 +1,4 @@
+use serde::Serialize;
 use std::fmt;
 
 #[derive(Debug)]
@@ -6,6 +7,35 @@ pub enum ParsingError {
     InvalidTimestamp,
     InvalidNumber,
     IoError(std::io::Error),
+    /// An archive couldn't be opened or extracted
+    ArchiveError(String),
+    /// One of the other errors, at a known place in the file
+    At {
+        position: Position,
+        error: Box<ParsingError>,
+    },
+}
+
+impl ParsingError {
+    /// This error, located at `position`.
+    pub fn at(self, position: Position) -> Self {
+        match self {
+            // The innermost position is the most precise one
+            ParsingError::At { .. } => self,
+            error => ParsingError::At {
+                position,
+                error: Box::new(error),
+            },
+        }
+    }
+
+    /// Where in the file the error is, if that's known.
+    pub fn position(&self) -> Option<Position> {
+        match self {
+            ParsingError::At { position, .. } => Some(*position),
+            _ => None,
+        }
+    }
 }
 
 impl From<std::io::Error> for ParsingError {
@@ -21,6 +51,70 @@ impl fmt::Display for ParsingError {
             ParsingError::InvalidTimestamp => write!(f, "Invalid timestamp"),
             ParsingError::InvalidNumber => write!(f, "Invalid subtitle number"),
             ParsingError::IoError(e) => write!(f, "I/O error: {}", e),
+            ParsingError::ArchiveError(e) => write!(f, "Archive error: {}", e),
+            ParsingError::At { position, error } => write!(f, "{}: {}", position, error),
+        }
+    }
+}
+
+impl std::error::Error for ParsingError {}
+
+/// A place in a subtitle file.
+///
+/// Lines and columns count from 1, columns in characters. A byte order mark
+/// at the start of the file doesn't count.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Position {
+    /// Which cue (SRT block, TTML paragraph or LRC line) the place is in
+    pub cue: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Position {
+    // The position of byte `offset` of `text`, in cue `cue`
+    pub(super) fn locate(text: &str, offset: usize, cue: usize) -> Self {
+        let before = &text[..offset];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        Position {
+            cue,
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
         }
     }
 }
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {}, column {} (cue {})",
+            self.line, self.column, self.cue
+        )
+    }
+}
+
+/// A problem the parser skipped past, such as a cue it couldn't read.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Diagnostic {
+    pub position: Position,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub(super) fn new(position: Position, error: ParsingError) -> Self {
+        Diagnostic {
+            position: error.position().unwrap_or(position),
+            message: match error {
+                ParsingError::At { error, .. } => error.to_string(),
+                error => error.to_string(),
+            },
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.position, self.message)
+    }
+}
@@ -0,0 +1,161 @@
+use super::types::Subtitles;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+// Language suffixes before the extension, e.g. `Episode 01.zh-Hans.srt`, by language
+const SUFFIXES: [(Language, &[&str]); 4] = [
+    (Language::Japanese, &["ja", "jp", "jpn", "japanese"]),
+    (Language::English, &["en", "eng", "english"]),
+    (
+        Language::Chinese,
+        &[
+            "zh", "zho", "chi", "chs", "cht", "sc", "tc", "zh-hans", "zh-hant", "zh-cn", "zh-tw",
+            "chinese",
+        ],
+    ),
+    (Language::Korean, &["ko", "kor", "korean"]),
+];
+
+// Content with fewer letters than this is too short to tell its language by
+const MIN_DETECTION_LETTERS: usize = 20;
+
+// Share of the CJK characters that must be kana for text to count as Japanese;
+// Japanese dialogue is mostly kana, while Chinese has none
+const MIN_KANA_SHARE: f64 = 0.1;
+
+/// The language of a subtitle file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Language {
+    Japanese,
+    English,
+    Chinese,
+    Korean,
+}
+
+impl Language {
+    /// The language named by the file's suffix, as in `Episode 01.en.srt`.
+    pub fn from_suffix(path: &Path) -> Option<Self> {
+        let suffix = Path::new(path.file_stem()?).extension()?.to_str()?;
+        suffix.parse().ok()
+    }
+
+    /// Tells the language of `text` by its scripts: kana means Japanese, hangul
+    /// Korean, hanzi without kana Chinese, and Latin letters English. `None` if
+    /// the text has too few letters to tell.
+    pub fn detect(text: &str) -> Option<Self> {
+        let (mut kana, mut han, mut hangul, mut latin) = (0, 0, 0, 0);
+        for c in text.chars() {
+            match c {
+                '\u{3041}'..='\u{309f}' | '\u{30a0}'..='\u{30ff}' | '\u{ff66}'..='\u{ff9f}' => {
+                    kana += 1
+                }
+                '\u{4e00}'..='\u{9fff}' | '\u{3400}'..='\u{4dbf}' => han += 1,
+                '\u{ac00}'..='\u{d7af}' | '\u{1100}'..='\u{11ff}' | '\u{3130}'..='\u{318f}' => {
+                    hangul += 1
+                }
+                c if c.is_ascii_alphabetic() => latin += 1,
+                _ => {}
+            }
+        }
+        let cjk = kana + han + hangul;
+        if cjk + latin < MIN_DETECTION_LETTERS {
+            return None;
+        }
+        Some(if latin > cjk {
+            Language::English
+        } else if hangul > kana + han {
+            Language::Korean
+        } else if kana as f64 >= (kana + han) as f64 * MIN_KANA_SHARE {
+            Language::Japanese
+        } else {
+            Language::Chinese
+        })
+    }
+
+    /// The ISO 639-1 code, e.g. `ja`.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Language::Japanese => "ja",
+            Language::English => "en",
+            Language::Chinese => "zh",
+            Language::Korean => "ko",
+        }
+    }
+}
+
+impl fmt::Display for Language {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+impl FromStr for Language {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        SUFFIXES
+            .iter()
+            .find(|(_, suffixes)| suffixes.iter().any(|suffix| suffix.eq_ignore_ascii_case(s)))
+            .map(|&(language, _)| language)
+            .ok_or_else(|| format!("unknown language {:?} (expected ja, en, zh or ko)", s))
+    }
+}
+
+/// The language of a parsed subtitle file: what its content looks like, or
+/// where there's too little text to tell, what its file name suffix says.
+pub fn detect_language(path: &Path, subtitles: &Subtitles) -> Option<Language> {
+    let text: String = subtitles.iter().map(|s| s.text.as_str()).collect();
+    Language::detect(&text).or_else(|| Language::from_suffix(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        let japanese = "今日はいい天気ですね。散歩に行きましょうか。";
+        let chinese = "今天天气很好。我们一起去散步吧。你觉得怎么样呢？";
+        let korean = "오늘은 날씨가 정말 좋네요. 산책하러 갈까요? 좋아요.";
+        let english = "The weather is nice today. Shall we go for a walk?";
+        assert_eq!(Language::detect(japanese), Some(Language::Japanese));
+        assert_eq!(Language::detect(chinese), Some(Language::Chinese));
+        assert_eq!(Language::detect(korean), Some(Language::Korean));
+        assert_eq!(Language::detect(english), Some(Language::English));
+        assert_eq!(Language::detect("はい"), None);
+    }
+
+    #[test]
+    fn test_from_suffix() {
+        let language = |name: &str| Language::from_suffix(Path::new(name));
+        assert_eq!(
+            language("Show/Episode 01.zh-Hans.srt"),
+            Some(Language::Chinese)
+        );
+        assert_eq!(
+            language("Show/Episode 01.JPN.ass"),
+            Some(Language::Japanese)
+        );
+        assert_eq!(language("Show/Episode 01.srt"), None);
+        assert_eq!("ko".parse::<Language>(), Ok(Language::Korean));
+        assert!("xx".parse::<Language>().is_err());
+    }
+
+    #[test]
+    fn test_detect_language_prefers_content() {
+        let subtitles = Subtitles::parse_from_str(
+            "1\n00:00:01,000 --> 00:00:03,000\nThis is clearly English text.\n",
+        )
+        .unwrap();
+        assert_eq!(
+            detect_language(Path::new("Show/Episode 01.ja.srt"), &subtitles),
+            Some(Language::English)
+        );
+        let short = Subtitles::parse_from_str("1\n00:00:01,000 --> 00:00:03,000\nはい\n").unwrap();
+        assert_eq!(
+            detect_language(Path::new("Show/Episode 01.ja.srt"), &short),
+            Some(Language::Japanese)
+        );
+    }
+}
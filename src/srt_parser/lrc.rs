@@ -0,0 +1,151 @@
+use super::errors::{Diagnostic, ParsingError, Position};
+use super::stats::{clean_input, ParseStats};
+use super::types::{Subtitle, Subtitles, Timestamp};
+use regex::Regex;
+
+// How long the last line of an LRC file is shown, since LRC only has start times
+const LAST_LINE_DURATION_MS: u64 = 5000;
+
+impl Subtitles {
+    /// Parses LRC lyrics (`[mm:ss.xx]text`) into subtitles.
+    ///
+    /// A line may carry several timestamps (`[00:12.00][01:30.50]chorus`), and is
+    /// then repeated at each of them. Each cue ends where the next one starts;
+    /// the last one lasts five seconds. Metadata tags such as `[ar:...]` and
+    /// empty lines (instrumental breaks) are skipped.
+    pub fn parse_lrc(input: &str) -> Result<Self, ParsingError> {
+        Self::parse_lrc_with_stats(input, &mut ParseStats::default())
+    }
+
+    pub(super) fn parse_lrc_with_stats(
+        input: &str,
+        stats: &mut ParseStats,
+    ) -> Result<Self, ParsingError> {
+        let input = clean_input(input, &mut stats.cleaning);
+        let tag = Regex::new(r"^\[(\d+):(\d{1,2})(?:[.:](\d{1,3}))?\]")
+            .map_err(|_| ParsingError::MalformedSubtitle)?;
+        let metadata =
+            Regex::new(r"^\[[A-Za-z#]+:.*\]$").map_err(|_| ParsingError::MalformedSubtitle)?;
+
+        // Every (start, text, raw text) triple, including empty text, which still ends the
+        // previous line
+        let mut cues: Vec<(u64, String, &str)> = Vec::new();
+        for (i, line) in input.lines().enumerate() {
+            let mut rest = line.trim_start();
+            let mut starts = Vec::new();
+            // Each line is a cue, so the cue and line numbers are the same
+            let position = |rest: &str| Position {
+                cue: i + 1,
+                line: i + 1,
+                column: line[..line.len() - rest.len()].chars().count() + 1,
+            };
+            while let Some(cap) = tag.captures(rest) {
+                let invalid = |_| ParsingError::InvalidTimestamp.at(position(rest));
+                let minutes: u64 = cap[1].parse().map_err(invalid)?;
+                let seconds: u64 = cap[2].parse().map_err(invalid)?;
+                // ".5" means 500ms and ".05" 50ms
+                let fraction = cap.get(3).map_or(Ok(0), |m| {
+                    format!("{:0<3}", m.as_str())
+                        .parse::<u64>()
+                        .map_err(invalid)
+                })?;
+                starts.push((minutes * 60 + seconds) * 1000 + fraction);
+                rest = &rest[cap[0].len()..];
+            }
+            let text = rest.trim();
+            if starts.is_empty() && !text.is_empty() && !metadata.is_match(text) {
+                stats.diagnostics.push(Diagnostic::new(
+                    position(line),
+                    ParsingError::InvalidTimestamp,
+                ));
+                stats.cues_skipped += 1;
+            }
+            for start in starts {
+                cues.push((start, text.to_string(), rest));
+            }
+        }
+        cues.sort_by_key(|(start, _, _)| *start);
+
+        let mut subtitles = Subtitles::new();
+        for (i, (start, text, raw_text)) in cues.iter().enumerate() {
+            if text.is_empty() {
+                continue;
+            }
+            let end = cues
+                .get(i + 1)
+                .map_or(start + LAST_LINE_DURATION_MS, |(next, _, _)| *next);
+            subtitles.push(
+                Subtitle::new(
+                    subtitles.len() + 1,
+                    Timestamp::from_millis(*start),
+                    Timestamp::from_millis(end),
+                    text.clone(),
+                )
+                .with_raw_text(raw_text.to_string()),
+            );
+        }
+
+        if subtitles.is_empty() {
+            Err(ParsingError::MalformedSubtitle)
+        } else {
+            Ok(subtitles)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_lrc() {
+        let input =
+            "[ar:Artist]\n[ti:Title]\n[00:01.50]一行目\n[00:04.00][00:10.5]繰り返し\n[00:06.00]\n";
+        let subtitles = Subtitles::parse_lrc(input).unwrap();
+        let cues: Vec<(String, String, &str)> = subtitles
+            .iter()
+            .map(|s| {
+                (
+                    s.start_time.to_string(),
+                    s.end_time.to_string(),
+                    s.text.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            cues,
+            [
+                (
+                    "00:00:01,500".to_string(),
+                    "00:00:04,000".to_string(),
+                    "一行目"
+                ),
+                (
+                    "00:00:04,000".to_string(),
+                    "00:00:06,000".to_string(),
+                    "繰り返し"
+                ),
+                (
+                    "00:00:10,500".to_string(),
+                    "00:00:15,500".to_string(),
+                    "繰り返し"
+                ),
+            ]
+        );
+        assert_eq!(subtitles.0[2].number, 3);
+    }
+
+    #[test]
+    fn test_parse_lrc_without_timestamps() {
+        assert!(Subtitles::parse_lrc("[ar:Artist]\njust text\n").is_err());
+
+        let mut stats = ParseStats::default();
+        Subtitles::parse_lrc_with_stats("[ar:Artist]\n[00:01.00]一行目\n二行目\n", &mut stats)
+            .unwrap();
+        assert_eq!(stats.cues_skipped, 1);
+        assert_eq!(
+            stats.diagnostics[0].to_string(),
+            "line 3, column 1 (cue 3): Invalid timestamp"
+        );
+    }
+}
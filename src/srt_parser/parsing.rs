@@ -1,39 +1,123 @@
+use super::archive::{is_archive_file, process_archive};
+use super::bilingual::{find_translation_file, is_translation_file};
 use super::episode_info::{
     get_episode_name, get_episode_number, get_show_name, EpisodeNameMethod, EpisodeNumberMethod,
 };
-use super::errors::ParsingError;
+use super::errors::{Diagnostic, ParsingError, Position};
+use super::language::{detect_language, Language};
+use super::stats::{clean_input, ParseStats};
 use super::types::{Subtitle, Subtitles, Timestamp};
+use super::youtube::youtube_video;
 use regex::Regex;
 use std::collections::HashMap;
 use std::fs::File;
 use std::io::Read;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use walkdir::WalkDir;
 
+/// The subtitle file formats that can be ingested, chosen by file extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubtitleFormat {
+    Srt,
+    Lrc,
+    /// TTML / DFXP
+    Ttml,
+    /// YouTube's JSON timed text, as downloaded by yt-dlp
+    Json3,
+    /// YouTube's XML timed text, as downloaded by yt-dlp
+    Srv3,
+    /// (Advanced) SubStation Alpha
+    Ass,
+}
+
+impl SubtitleFormat {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "srt" => Some(SubtitleFormat::Srt),
+            "lrc" => Some(SubtitleFormat::Lrc),
+            "ttml" | "dfxp" => Some(SubtitleFormat::Ttml),
+            "json3" => Some(SubtitleFormat::Json3),
+            "srv3" => Some(SubtitleFormat::Srv3),
+            "ass" | "ssa" => Some(SubtitleFormat::Ass),
+            _ => None,
+        }
+    }
+}
+
+/// Whether `path` has the extension of a supported subtitle format.
+pub fn is_subtitle_file(path: &Path) -> bool {
+    SubtitleFormat::from_path(path).is_some()
+}
+
 pub struct SrtEntry {
+    /// The file the entry was parsed from
+    pub path: PathBuf,
     pub show_name: String,
     pub episode_name: String,
-    pub episode_number: i32,
+    /// `None` for a movie; files are parsed as numbered episodes, and become
+    /// movies by their show's type when ingested
+    pub episode_number: Option<i32>,
     pub content: Subtitles,
+    /// English subtitles from a paired `.en` file, if there was one
+    pub translation: Option<Subtitles>,
+    /// Statistics from parsing the (Japanese) file
+    pub stats: ParseStats,
+    /// The file's language, from its content or file name (see [`detect_language`])
+    pub language: Option<Language>,
 }
 
 pub fn process_srt_directory(
     root_dir: &Path,
     number_method: &EpisodeNumberMethod,
     name_method: &EpisodeNameMethod,
+) -> HashMap<String, Vec<SrtEntry>> {
+    process_srt_directory_filtered(root_dir, number_method, name_method, |_| true)
+}
+
+/// Like [`process_srt_directory`], but only parses the files for which `keep` returns true.
+pub fn process_srt_directory_filtered(
+    root_dir: &Path,
+    number_method: &EpisodeNumberMethod,
+    name_method: &EpisodeNameMethod,
+    mut keep: impl FnMut(&Path) -> bool,
 ) -> HashMap<String, Vec<SrtEntry>> {
     let mut show_entries: HashMap<String, Vec<SrtEntry>> = HashMap::new();
 
     for entry in WalkDir::new(root_dir).into_iter().filter_map(|e| e.ok()) {
         let path = entry.path();
-        if path.extension().map_or(false, |ext| ext == "srt") {
+        if is_archive_file(path) && keep(path) {
+            println!("Processing {:?}...", path.file_name().unwrap());
+            // An archive in a show folder, rather than at the top, holds more of that show's files
+            let folder_show = if path != root_dir && path.parent() != Some(root_dir) {
+                get_show_name(path)
+            } else {
+                None
+            };
+            match process_archive(path, number_method, name_method) {
+                Ok(entries) => {
+                    for mut srt_entry in entries {
+                        if let Some(show_name) = &folder_show {
+                            srt_entry.show_name = show_name.clone();
+                        }
+                        show_entries
+                            .entry(srt_entry.show_name.clone())
+                            .or_default()
+                            .push(srt_entry);
+                    }
+                }
+                Err(e) => eprintln!("Error processing archive {:?}: {}", path, e),
+            }
+        }
+        // English files are read together with the Japanese file they belong to
+        else if is_subtitle_file(path) && !is_translation_file(path) && keep(path) {
             println!("Processing {:?}...", path.file_name().unwrap());
             match process_srt_file(path, root_dir, number_method, name_method) {
                 Ok(srt_entry) => {
                     show_entries
                         .entry(srt_entry.show_name.clone())
-                        .or_insert_with(Vec::new)
+                        .or_default()
                         .push(srt_entry);
                 }
                 Err(e) => eprintln!("Error processing file {:?}: {}", path, e),
@@ -57,19 +141,75 @@ pub fn process_srt_file(
 ) -> Result<SrtEntry, ParsingError> {
     let show_name = get_show_name(file_path).unwrap_or_else(|| "Unknown Show".to_string());
     let episode_number = get_episode_number(number_method, &show_name, file_path, root);
-    let episode_name = get_episode_name(name_method, file_path, episode_number)
-        .unwrap_or_else(|| format!("Episode {}", episode_number));
+    // YouTube captions are named after their video's title instead
+    let episode_name = match youtube_video(file_path) {
+        Some(video) => video.title,
+        None => get_episode_name(name_method, file_path, episode_number)
+            .unwrap_or_else(|| format!("Episode {}", episode_number)),
+    };
 
-    let content = Subtitles::parse_from_file(file_path)?;
+    let (content, stats) = Subtitles::parse_file_with_stats(file_path)?;
+    let language = detect_language(file_path, &content);
+    // A broken translation shouldn't keep the Japanese lines out
+    let translation = match find_translation_file(file_path) {
+        Some(path) => match Subtitles::parse_from_file(&path) {
+            Ok(translation) => Some(translation),
+            Err(e) => {
+                eprintln!("Error processing translation {:?}: {}", path, e);
+                None
+            }
+        },
+        None => None,
+    };
 
     Ok(SrtEntry {
+        path: file_path.to_path_buf(),
         show_name,
         episode_name,
-        episode_number,
+        episode_number: Some(episode_number),
         content,
+        translation,
+        stats,
+        language,
     })
 }
 
+// Why an SRT block isn't a cue, and where in it: the line (counted from the
+// block's first) and column of the problem
+fn diagnose_srt_block(block: &str) -> (usize, usize, ParsingError) {
+    let mut lines = block.lines();
+    let number = lines.next().unwrap_or_default();
+    if number.trim().parse::<usize>().is_err() {
+        return (0, 1, ParsingError::InvalidNumber);
+    }
+    let Some(timing) = lines.next() else {
+        return (
+            0,
+            number.chars().count() + 1,
+            ParsingError::MalformedSubtitle,
+        );
+    };
+    let Some((start, end)) = timing.split_once("-->") else {
+        return (1, 1, ParsingError::InvalidTimestamp);
+    };
+    if let Err(error) = Timestamp::from_str(start.trim()) {
+        return (1, 1, error);
+    }
+    if let Err(error) = Timestamp::from_str(end.trim()) {
+        let spaces = end.len() - end.trim_start().len();
+        return (1, start.chars().count() + 4 + spaces, error);
+    }
+    if lines.next().is_none() {
+        return (
+            1,
+            timing.chars().count() + 1,
+            ParsingError::MalformedSubtitle,
+        );
+    }
+    // A timing line the pattern didn't take, e.g. with three-digit minutes
+    (1, 1, ParsingError::InvalidTimestamp)
+}
+
 impl Subtitles {
     /// Parses a string containing SRT formatted subtitles into a `Subtitles` struct.
     ///
@@ -81,40 +221,78 @@ impl Subtitles {
     ///
     /// * `Result<Self, ParsingError>` - Parsed subtitles or an error
     pub fn parse_from_str(input: &str) -> Result<Self, ParsingError> {
+        Self::parse_from_str_with_stats(input).map(|(subtitles, _)| subtitles)
+    }
+
+    /// Like [`Subtitles::parse_from_str`], also reporting what was parsed, skipped and cleaned up.
+    pub fn parse_from_str_with_stats(input: &str) -> Result<(Self, ParseStats), ParsingError> {
+        let mut stats = ParseStats::default();
+        let subtitles = Self::parse_srt(input, &mut stats)?;
+        stats.record_cues(&subtitles);
+        Ok((subtitles, stats))
+    }
+
+    fn parse_srt(input: &str, stats: &mut ParseStats) -> Result<Self, ParsingError> {
         // Remove BOM if present and normalize line endings
-        let input = input.trim_start_matches('\u{feff}').replace('\r', "");
+        let input = clean_input(input, &mut stats.cleaning);
 
         // Define regex pattern for parsing SRT format
         // Detailed explanation of the regex pattern:
         // r"(\d+)\n                     - Group 1: Matches the subtitle number (one or more digits) followed by a newline
-        //   (\d{2}:\d{2}:\d{2},\d{3})   - Group 2: Matches the start time (HH:MM:SS,mmm format)
-        //   -->                         - Matches the arrow separator between timestamps
-        //   (\d{2}:\d{2}:\d{2},\d{3})   - Group 3: Matches the end time (HH:MM:SS,mmm format)
-        //   \n                          - Matches the newline after the timestamp line
-        //   ((?s:.*?)                   - Group 4: Starts the subtitle text capture
+        //   (\d{1,2}:\d{2}:\d{2}        - Group 2: Matches the start time (H:MM:SS or HH:MM:SS, ...
+        //     (?:[,.]\d{1,3})?)          - ... with milliseconds after a comma or dot, or none)
+        //   \x20*-->\x20*                - Matches the arrow separator between timestamps, however spaced
+        //   (\d{1,2}:...)               - Group 3: Matches the end time, like the start time
+        //   ([ \t]+[^\n]*)?             - Group 4: Matches anything else on the timing line, like
+        //                                 `X1:100 X2:200 Y1:10 Y2:20` positioning, which is dropped
+        //   [ \t]*\n                    - Matches trailing spaces and the newline after the timestamp line
+        //   ((?s:.*?)                   - Group 5: Starts the subtitle text capture
         //     (?s:.*?)                    - Non-greedy match of any characters, including newlines (s flag)
-        //   (?:\n\n|$))                 - End of Group 4: Matches either two newlines or the end of the string
+        //   (?:\n\n|$))                 - End of Group 5: Matches either two newlines or the end of the string
         //                                 This allows for multi-line subtitles and handles the last subtitle"
+        // Timestamps are stored in the canonical HH:MM:SS,mmm form whichever variant was read
         let re = Regex::new(
-            r"(\d+)\n(\d{2}:\d{2}:\d{2},\d{3}) --> (\d{2}:\d{2}:\d{2},\d{3})\n((?s:.*?)(?:\n\n|$))",
+            r"(\d+)\n(\d{1,2}:\d{2}:\d{2}(?:[,.]\d{1,3})?) *--> *(\d{1,2}:\d{2}:\d{2}(?:[,.]\d{1,3})?)([ \t]+[^\n]*)?[ \t]*\n((?s:.*?)(?:\n\n|$))",
         )
         .map_err(|_| ParsingError::MalformedSubtitle)?;
 
         let mut subtitles = Vec::new();
+        // Where each accepted cue starts, in order, to tell which blocks none of them covers
+        let mut cue_starts = Vec::new();
+        let (mut cue, mut counted) = (1, 0);
 
         // Iterate over each regex match in the input
         for cap in re.captures_iter(&input) {
+            let start = cap.get(0).map_or(0, |m| m.start());
+            cue_starts.push(start);
+            cue += input[counted..start].matches("\n\n").count();
+            counted = start;
+            let position = |group: usize| {
+                Position::locate(&input, cap.get(group).map_or(start, |m| m.start()), cue)
+            };
+
             // Parse subtitle number (Group 1)
-            let number = cap[1].parse().map_err(|_| ParsingError::InvalidNumber)?;
+            let number = cap[1]
+                .parse()
+                .map_err(|_| ParsingError::InvalidNumber.at(position(1)))?;
 
             // Parse start timestamp (Group 2)
-            let start_time = Timestamp::from_str(&cap[2])?;
+            let start_time = Timestamp::from_str(&cap[2]).map_err(|e| e.at(position(2)))?;
 
             // Parse end timestamp (Group 3)
-            let end_time = Timestamp::from_str(&cap[3])?;
+            let end_time = Timestamp::from_str(&cap[3]).map_err(|e| e.at(position(3)))?;
 
-            // Extract and trim subtitle text (Group 4)
-            let text = cap[4].trim().to_string();
+            // Positioning and other extensions of the timing line (Group 4) aren't kept
+            if cap.get(4).is_some_and(|m| !m.as_str().trim().is_empty()) {
+                stats.cleaning.cue_settings_removed += 1;
+            }
+
+            // Extract and trim subtitle text (Group 5)
+            // The blank line ending the cue is part of the match, so it doesn't count as cleaning
+            let text = cap[5].trim().to_string();
+            if cap[5].trim_end_matches('\n') != text {
+                stats.cleaning.texts_trimmed += 1;
+            }
 
             // Debug output - consider removing in production
             // println!("Number: {}", number);
@@ -128,9 +306,28 @@ impl Subtitles {
                 start_time,
                 end_time,
                 text,
+                raw_text: cap[5].trim_end_matches('\n').to_string(),
+                speaker: None,
             });
         }
 
+        // Every other non-blank block is a cue the pattern didn't accept
+        let mut offset = 0;
+        for (i, block) in input.split("\n\n").enumerate() {
+            let end = offset + block.len();
+            let next_cue = cue_starts.partition_point(|&start| start < offset);
+            let accepted = cue_starts.get(next_cue).is_some_and(|&start| start < end);
+            if !block.trim().is_empty() && !accepted {
+                let (line, column, error) = diagnose_srt_block(block);
+                let mut position = Position::locate(&input, offset, i + 1);
+                position.line += line;
+                position.column = column;
+                stats.diagnostics.push(Diagnostic::new(position, error));
+                stats.cues_skipped += 1;
+            }
+            offset = end + 2;
+        }
+
         // Check if any subtitles were parsed
         if subtitles.is_empty() {
             Err(ParsingError::MalformedSubtitle)
@@ -139,19 +336,41 @@ impl Subtitles {
         }
     }
 
+    /// Reads a subtitle file, parsing it as SRT unless its extension names another format.
     pub fn parse_from_file(path: &Path) -> Result<Self, ParsingError> {
+        Self::parse_file_with_stats(path).map(|(subtitles, _)| subtitles)
+    }
+
+    /// Like [`Subtitles::parse_from_file`], also reporting what was parsed, skipped and cleaned up.
+    pub fn parse_file_with_stats(path: &Path) -> Result<(Self, ParseStats), ParsingError> {
         let mut file = File::open(path)?;
         let mut content = String::new();
         file.read_to_string(&mut content)?;
+        Self::parse_content_with_stats(&content, SubtitleFormat::from_path(path))
+    }
 
-        Self::parse_from_str(&content)
+    /// Parses the content of a subtitle file in the given format, or as SRT if it's unknown.
+    pub fn parse_content_with_stats(
+        content: &str,
+        format: Option<SubtitleFormat>,
+    ) -> Result<(Self, ParseStats), ParsingError> {
+        let mut stats = ParseStats::default();
+        let subtitles = match format {
+            Some(SubtitleFormat::Lrc) => Self::parse_lrc_with_stats(content, &mut stats),
+            Some(SubtitleFormat::Ttml) => Self::parse_ttml_with_stats(content, &mut stats),
+            Some(SubtitleFormat::Json3) => Self::parse_json3_with_stats(content, &mut stats),
+            Some(SubtitleFormat::Srv3) => Self::parse_srv3_with_stats(content, &mut stats),
+            Some(SubtitleFormat::Ass) => Self::parse_ass_with_stats(content, &mut stats),
+            Some(SubtitleFormat::Srt) | None => Self::parse_srt(content, &mut stats),
+        }?;
+        stats.record_cues(&subtitles);
+        Ok((subtitles, stats))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::path::PathBuf;
 
     #[test]
     fn test_parse_from_str() {
@@ -164,12 +383,76 @@ mod tests {
         assert_eq!(subtitles.0[1].text, "This is a test.");
     }
 
+    #[test]
+    fn test_parse_timestamp_variants() {
+        let input = "1\n0:00:01.000 --> 0:00:02.500\n一つ目\n\n\
+                     2\n00:00:03 --> 00:00:04  \n二つ目\n\n\
+                     3\n00:00:05,000-->00:00:06,000\n三つ目\n";
+        let subtitles = Subtitles::parse_from_str(input).unwrap();
+        let times: Vec<String> = subtitles
+            .iter()
+            .map(|s| format!("{} --> {}", s.start_time, s.end_time))
+            .collect();
+        assert_eq!(
+            times,
+            [
+                "00:00:01,000 --> 00:00:02,500",
+                "00:00:03,000 --> 00:00:04,000",
+                "00:00:05,000 --> 00:00:06,000"
+            ]
+        );
+        assert_eq!(subtitles.0[1].text, "二つ目");
+    }
+
+    #[test]
+    fn test_parse_drops_cue_settings() {
+        let input = "1\n00:00:01,000 --> 00:00:02,000 X1:100 X2:200 Y1:10 Y2:20\n猫だ\n\n\
+                     2\n00:00:03,000 --> 00:00:04,000\n犬だ\n";
+        let (subtitles, stats) = Subtitles::parse_from_str_with_stats(input).unwrap();
+        assert_eq!(subtitles.len(), 2);
+        assert_eq!(subtitles.0[0].text, "猫だ");
+        assert_eq!(subtitles.0[0].end_time.to_string(), "00:00:02,000");
+        assert_eq!(stats.cleaning.cue_settings_removed, 1);
+        assert_eq!(stats.cues_skipped, 0);
+    }
+
+    #[test]
+    fn test_parse_keeps_raw_text() {
+        let input = "1\n00:00:01,000 --> 00:00:04,000\n  <i>猫だ</i> \n\n";
+        let subtitles = Subtitles::parse_from_str(input).unwrap();
+        assert_eq!(subtitles.0[0].text, "<i>猫だ</i>");
+        assert_eq!(subtitles.0[0].raw_text, "  <i>猫だ</i> ");
+    }
+
     #[test]
     fn test_process_srt_file() {
         // This test would require a mock file system or test SRT files
         // Implement based on your testing strategy
     }
 
+    #[test]
+    fn test_process_srt_file_skips_broken_translation() {
+        let root =
+            std::env::temp_dir().join(format!("anime_search_translation-{}", std::process::id()));
+        let show = root.join("Show Name");
+        std::fs::create_dir_all(&show).unwrap();
+        let path = show.join("Episode 01.ja.srt");
+        std::fs::write(&path, "1\n00:00:01,000 --> 00:00:02,000\n猫だ\n").unwrap();
+        // Not UTF-8, so it can't be read
+        std::fs::write(show.join("Episode 01.en.srt"), [0xff, 0xfe, 0xff]).unwrap();
+
+        let entry = process_srt_file(
+            &path,
+            &root,
+            &EpisodeNumberMethod::FromFilename,
+            &EpisodeNameMethod::FromEpisodeNumber,
+        );
+        std::fs::remove_dir_all(&root).unwrap();
+        let entry = entry.unwrap();
+        assert_eq!(entry.content.len(), 1);
+        assert!(entry.translation.is_none());
+    }
+
     #[test]
     fn test_process_srt_directory() {
         // This test would require a mock file system or test SRT files
@@ -0,0 +1,158 @@
+use super::errors::Diagnostic;
+use super::types::{Subtitles, Timestamp};
+use serde::Serialize;
+
+/// What parsing one subtitle file produced, for ingestion reports.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ParseStats {
+    pub cues_parsed: usize,
+    /// Blocks or lines that looked like cues but couldn't be parsed, or had no timing
+    pub cues_skipped: usize,
+    pub cleaning: CleaningStats,
+    /// Earliest start time of any cue
+    pub first_start: Option<Timestamp>,
+    /// Latest end time of any cue
+    pub last_end: Option<Timestamp>,
+    /// Characters of cue text, not counting line breaks
+    pub characters: usize,
+    /// Where and why cues were skipped; only kept for single files, not merged
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// Fixes applied to the raw file while parsing it.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct CleaningStats {
+    pub byte_order_marks_removed: usize,
+    pub carriage_returns_removed: usize,
+    /// Cue texts with leading or trailing whitespace removed
+    pub texts_trimmed: usize,
+    /// Markup tags removed from cue texts (TTML, srv3, ASS override blocks)
+    pub tags_removed: usize,
+    /// Cues whose timing line had positioning (`X1:… Y1:…`) or other settings
+    /// after the end time, which were dropped (SRT)
+    pub cue_settings_removed: usize,
+    /// Events that were songs or signs rather than dialogue, which were dropped (ASS)
+    pub sign_events_removed: usize,
+}
+
+impl CleaningStats {
+    /// Number of cleaning operations applied in total.
+    pub fn total(&self) -> usize {
+        self.byte_order_marks_removed
+            + self.carriage_returns_removed
+            + self.texts_trimmed
+            + self.tags_removed
+            + self.cue_settings_removed
+            + self.sign_events_removed
+    }
+
+    fn merge(&mut self, other: &CleaningStats) {
+        self.byte_order_marks_removed += other.byte_order_marks_removed;
+        self.carriage_returns_removed += other.carriage_returns_removed;
+        self.texts_trimmed += other.texts_trimmed;
+        self.tags_removed += other.tags_removed;
+        self.cue_settings_removed += other.cue_settings_removed;
+        self.sign_events_removed += other.sign_events_removed;
+    }
+}
+
+impl ParseStats {
+    /// Adds up the statistics of several files; timestamps become the overall range.
+    /// Diagnostics are left out, as their positions only make sense per file.
+    pub fn merge(&mut self, other: &ParseStats) {
+        self.cues_parsed += other.cues_parsed;
+        self.cues_skipped += other.cues_skipped;
+        self.cleaning.merge(&other.cleaning);
+        self.first_start =
+            earliest_or_latest(self.first_start.take(), other.first_start.clone(), false);
+        self.last_end = earliest_or_latest(self.last_end.take(), other.last_end.clone(), true);
+        self.characters += other.characters;
+    }
+
+    // Fills in the cue count, time range and character count from the parsed cues
+    pub(super) fn record_cues(&mut self, subtitles: &Subtitles) {
+        self.cues_parsed = subtitles.len();
+        self.first_start = subtitles
+            .iter()
+            .map(|s| &s.start_time)
+            .min_by_key(|t| t.to_millis())
+            .cloned();
+        self.last_end = subtitles
+            .iter()
+            .map(|s| &s.end_time)
+            .max_by_key(|t| t.to_millis())
+            .cloned();
+        self.characters = subtitles
+            .iter()
+            .map(|s| s.text.chars().filter(|&c| c != '\n').count())
+            .sum();
+    }
+}
+
+// The earlier of two timestamps, or the later one with `latest`
+fn earliest_or_latest(
+    a: Option<Timestamp>,
+    b: Option<Timestamp>,
+    latest: bool,
+) -> Option<Timestamp> {
+    match (a, b) {
+        (Some(a), Some(b)) => {
+            let a_first = a.to_millis() <= b.to_millis();
+            Some(if a_first != latest { a } else { b })
+        }
+        (a, b) => a.or(b),
+    }
+}
+
+// Removes a byte order mark and carriage returns, counting what was removed
+pub(super) fn clean_input(input: &str, cleaning: &mut CleaningStats) -> String {
+    let without_bom = input.trim_start_matches('\u{feff}');
+    cleaning.byte_order_marks_removed += (input.len() - without_bom.len()) / '\u{feff}'.len_utf8();
+    let carriage_returns = without_bom.matches('\r').count();
+    cleaning.carriage_returns_removed += carriage_returns;
+    if carriage_returns == 0 {
+        without_bom.to_string()
+    } else {
+        without_bom.replace('\r', "")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_stats() {
+        let input = "\u{feff}1\r\n00:00:01,000 --> 00:00:02,000\r\n 猫 \r\n\r\n\
+                     2\r\n00:00:xx,000 --> 00:00:04,000\r\n壊れた\r\n\r\n\
+                     3\r\n00:00:05,000 --> 00:00:07,500\r\n犬\r\n走る\r\n";
+        let (subtitles, stats) = Subtitles::parse_from_str_with_stats(input).unwrap();
+        assert_eq!(subtitles.len(), 2);
+        assert_eq!(stats.cues_parsed, 2);
+        assert_eq!(stats.cues_skipped, 1);
+        assert_eq!(stats.cleaning.byte_order_marks_removed, 1);
+        assert_eq!(stats.cleaning.carriage_returns_removed, 12);
+        assert_eq!(stats.cleaning.texts_trimmed, 1);
+        assert_eq!(
+            stats.first_start.as_ref().unwrap().to_string(),
+            "00:00:01,000"
+        );
+        assert_eq!(stats.last_end.as_ref().unwrap().to_string(), "00:00:07,500");
+        assert_eq!(stats.characters, 4);
+        assert_eq!(stats.diagnostics.len(), 1);
+        assert_eq!(
+            stats.diagnostics[0].to_string(),
+            "line 6, column 1 (cue 2): Invalid timestamp"
+        );
+
+        let mut total = ParseStats {
+            first_start: Some(Timestamp::from_millis(500)),
+            last_end: Some(Timestamp::from_millis(1000)),
+            ..ParseStats::default()
+        };
+        total.merge(&stats);
+        assert_eq!(total.first_start, Some(Timestamp::from_millis(500)));
+        assert_eq!(total.last_end, stats.last_end);
+        assert_eq!(total.cleaning.total(), stats.cleaning.total());
+    }
+}
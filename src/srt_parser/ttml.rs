@@ -0,0 +1,239 @@
+use super::errors::{Diagnostic, ParsingError, Position};
+use super::stats::{clean_input, ParseStats};
+use super::types::{Subtitle, Subtitles, Timestamp};
+use regex::Regex;
+
+// TTML defaults when the document doesn't set ttp:frameRate / ttp:tickRate
+const DEFAULT_FRAME_RATE: f64 = 30.0;
+const DEFAULT_TICK_RATE: f64 = 1.0;
+
+impl Subtitles {
+    /// Parses TTML / DFXP (the XML format of Netflix rips) into subtitles.
+    ///
+    /// Each `<p>` with `begin` and `end` (or `dur`) becomes one cue; `<br/>`
+    /// becomes a newline and other markup is dropped, along with ruby readings
+    /// (`tts:ruby="text"` spans) so only the base text is kept. Times may be
+    /// clock times (`00:00:01.500`, `00:00:01:12` with frames) or offsets
+    /// (`1.5s`, `1500ms`, `15000000t` with `ttp:tickRate`).
+    pub fn parse_ttml(input: &str) -> Result<Self, ParsingError> {
+        Self::parse_ttml_with_stats(input, &mut ParseStats::default())
+    }
+
+    pub(super) fn parse_ttml_with_stats(
+        input: &str,
+        stats: &mut ParseStats,
+    ) -> Result<Self, ParsingError> {
+        let input = &clean_input(input, &mut stats.cleaning);
+        let regex =
+            |pattern: &str| Regex::new(pattern).map_err(|_| ParsingError::MalformedSubtitle);
+        let paragraph = regex(r"(?s)<p\b([^>]*)>(.*?)</p>")?;
+        let attribute = regex(r#"([\w:]+)\s*=\s*"([^"]*)""#)?;
+        let ruby_text = regex(r#"(?s)<span\b[^>]*tts:ruby="text"[^>]*>.*?</span>"#)?;
+        let line_break = regex(r"<br\s*/?>")?;
+        let tag = regex(r"<[^>]*>")?;
+        let whitespace = regex(r"\s+")?;
+
+        let rate = |name: &str, default: f64| {
+            Regex::new(&format!(r#"{}\s*=\s*"([\d.]+)""#, name))
+                .ok()
+                .and_then(|re| re.captures(input))
+                .and_then(|cap| cap[1].parse().ok())
+                .unwrap_or(default)
+        };
+        let rates = TimeRates {
+            frame_rate: rate("ttp:frameRate", DEFAULT_FRAME_RATE),
+            tick_rate: rate("ttp:tickRate", DEFAULT_TICK_RATE),
+        };
+
+        let mut subtitles = Subtitles::new();
+        for (i, cap) in paragraph.captures_iter(input).enumerate() {
+            let start = cap.get(0).map_or(0, |m| m.start());
+            let position = Position::locate(input, start, i + 1);
+            let (mut begin, mut end, mut dur) = (None, None, None);
+            for attr in attribute.captures_iter(&cap[1]) {
+                let time = || {
+                    let offset = cap.get(1).map_or(start, |m| m.start())
+                        + attr.get(2).map_or(0, |m| m.start());
+                    parse_time(&attr[2], &rates)
+                        .map_err(|e| e.at(Position::locate(input, offset, i + 1)))
+                };
+                match &attr[1] {
+                    "begin" => begin = Some(time()?),
+                    "end" => end = Some(time()?),
+                    "dur" => dur = Some(time()?),
+                    _ => {}
+                }
+            }
+            let (Some(begin), Some(end)) = (begin, end.or(begin.zip(dur).map(|(b, d)| b + d)))
+            else {
+                stats
+                    .diagnostics
+                    .push(Diagnostic::new(position, ParsingError::InvalidTimestamp));
+                stats.cues_skipped += 1;
+                continue;
+            };
+
+            let content = ruby_text.replace_all(&cap[2], "");
+            // Source line breaks are just XML formatting; only <br/> breaks a line
+            let content = whitespace.replace_all(&content, " ");
+            let content = line_break.replace_all(&content, "\n");
+            stats.cleaning.tags_removed += tag.find_iter(&content).count();
+            let content = decode_entities(&tag.replace_all(&content, ""));
+            let text = content
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .collect::<Vec<_>>()
+                .join("\n");
+            if text.is_empty() {
+                stats
+                    .diagnostics
+                    .push(Diagnostic::new(position, ParsingError::MalformedSubtitle));
+                stats.cues_skipped += 1;
+                continue;
+            }
+
+            subtitles.push(
+                Subtitle::new(
+                    subtitles.len() + 1,
+                    Timestamp::from_millis(begin),
+                    Timestamp::from_millis(end),
+                    text,
+                )
+                .with_raw_text(cap[2].trim().to_string()),
+            );
+        }
+
+        if subtitles.is_empty() {
+            Err(ParsingError::MalformedSubtitle)
+        } else {
+            Ok(subtitles)
+        }
+    }
+}
+
+struct TimeRates {
+    frame_rate: f64,
+    tick_rate: f64,
+}
+
+// Parses a TTML time expression into milliseconds
+fn parse_time(value: &str, rates: &TimeRates) -> Result<u64, ParsingError> {
+    let value = value.trim();
+    let number = |s: &str| s.parse::<f64>().map_err(|_| ParsingError::InvalidTimestamp);
+
+    let seconds = if value.contains(':') {
+        // Clock time: hours:minutes:seconds(.fraction) or hours:minutes:seconds:frames
+        let parts: Vec<&str> = value.split(':').collect();
+        match parts.as_slice() {
+            [h, m, s] => number(h)? * 3600.0 + number(m)? * 60.0 + number(s)?,
+            [h, m, s, f] => {
+                number(h)? * 3600.0 + number(m)? * 60.0 + number(s)? + number(f)? / rates.frame_rate
+            }
+            _ => return Err(ParsingError::InvalidTimestamp),
+        }
+    } else {
+        // Offset time: a number followed by a unit
+        let split = value
+            .find(|c: char| c.is_ascii_alphabetic())
+            .ok_or(ParsingError::InvalidTimestamp)?;
+        let (amount, unit) = value.split_at(split);
+        let amount = number(amount)?;
+        match unit {
+            "h" => amount * 3600.0,
+            "m" => amount * 60.0,
+            "s" => amount,
+            "ms" => amount / 1000.0,
+            "f" => amount / rates.frame_rate,
+            "t" => amount / rates.tick_rate,
+            _ => return Err(ParsingError::InvalidTimestamp),
+        }
+    };
+    if !seconds.is_finite() || seconds < 0.0 {
+        return Err(ParsingError::InvalidTimestamp);
+    }
+    Ok((seconds * 1000.0).round() as u64)
+}
+
+pub(super) fn decode_entities(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        output.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let decoded = rest.find(';').and_then(|end| {
+            let entity = &rest[1..end];
+            let c = match entity {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "nbsp" => Some('\u{a0}'),
+                _ => entity
+                    .strip_prefix("#x")
+                    .map(|hex| u32::from_str_radix(hex, 16))
+                    .or_else(|| entity.strip_prefix('#').map(|dec| dec.parse()))
+                    .and_then(|code| code.ok())
+                    .and_then(char::from_u32),
+            };
+            c.map(|c| (c, end))
+        });
+        match decoded {
+            Some((c, end)) => {
+                output.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                output.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    output.push_str(rest);
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ttml_ticks() {
+        let input = r#"<?xml version="1.0" encoding="utf-8"?>
+<tt xmlns="http://www.w3.org/ns/ttml" xmlns:ttp="http://www.w3.org/ns/ttml#parameter"
+    xmlns:tts="http://www.w3.org/ns/ttml#styling" ttp:tickRate="10000000">
+  <body><div>
+    <p begin="15000000t" end="30000000t" region="bottom">（太郎）<br/>
+      <span tts:ruby="container"><span tts:ruby="base">漢字</span><span tts:ruby="text">かんじ</span></span>だ</p>
+    <p begin="40000000t" dur="5000000t">A &amp; B &#x3042;</p>
+    <p>untimed</p>
+  </div></body>
+</tt>"#;
+        let mut stats = ParseStats::default();
+        let subtitles = Subtitles::parse_ttml_with_stats(input, &mut stats).unwrap();
+        assert_eq!(subtitles.len(), 2);
+        assert_eq!(
+            stats.diagnostics[0].to_string(),
+            "line 8, column 5 (cue 3): Invalid timestamp"
+        );
+        assert_eq!(subtitles.0[0].start_time.to_string(), "00:00:01,500");
+        assert_eq!(subtitles.0[0].end_time.to_string(), "00:00:03,000");
+        assert_eq!(subtitles.0[0].text, "（太郎）\n漢字だ");
+        assert_eq!(subtitles.0[1].end_time.to_string(), "00:00:04,500");
+        assert_eq!(subtitles.0[1].text, "A & B あ");
+    }
+
+    #[test]
+    fn test_parse_time() {
+        let rates = TimeRates {
+            frame_rate: 24.0,
+            tick_rate: 1.0,
+        };
+        assert_eq!(parse_time("00:01:02.500", &rates).unwrap(), 62_500);
+        assert_eq!(parse_time("00:00:01:12", &rates).unwrap(), 1500);
+        assert_eq!(parse_time("1.5s", &rates).unwrap(), 1500);
+        assert_eq!(parse_time("250ms", &rates).unwrap(), 250);
+        assert!(parse_time("soon", &rates).is_err());
+    }
+}
@@ -1,6 +1,21 @@
 use super::errors::ParsingError;
+use serde::{Serialize, Serializer};
+use std::collections::HashMap;
+use std::fmt;
 use std::str::FromStr;
 
+// The brackets subtitles put speaker names in, e.g. （太郎）こんにちは
+const SPEAKER_BRACKETS: &[(char, char)] = &[
+    ('（', '）'),
+    ('(', ')'),
+    ('【', '】'),
+    ('［', '］'),
+    ('[', ']'),
+];
+
+// Longer bracketed text is an aside or a sound description, not a name
+const MAX_SPEAKER_LENGTH: usize = 10;
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Timestamp {
     pub hours: u32,
@@ -15,6 +30,10 @@ pub struct Subtitle {
     pub start_time: Timestamp,
     pub end_time: Timestamp,
     pub text: String,
+    /// The cue's text as it appeared in the file, before cleaning
+    pub raw_text: String,
+    /// Who says the line, if the file names them (the actor field of ASS events)
+    pub speaker: Option<String>,
 }
 
 #[derive(Debug, Clone, Default)]
@@ -30,36 +49,65 @@ impl Timestamp {
         }
     }
 
-    pub fn to_string(&self) -> String {
-        format!(
+    pub fn from_millis(total: u64) -> Self {
+        Timestamp {
+            hours: (total / 3_600_000) as u32,
+            minutes: (total / 60_000 % 60) as u32,
+            seconds: (total / 1000 % 60) as u32,
+            milliseconds: (total % 1000) as u32,
+        }
+    }
+
+    pub fn to_millis(&self) -> u64 {
+        ((self.hours as u64 * 60 + self.minutes as u64) * 60 + self.seconds as u64) * 1000
+            + self.milliseconds as u64
+    }
+}
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
             "{:02}:{:02}:{:02},{:03}",
             self.hours, self.minutes, self.seconds, self.milliseconds
         )
     }
 }
 
+// Serialized in its SRT form, e.g. "00:01:02,500"
+impl Serialize for Timestamp {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+// Reads SRT timestamps (00:00:01,000) and the variants found in real files: a dot
+// before the milliseconds, one-digit hours, and fewer or no millisecond digits
+// (00:00:01.5 is 500 milliseconds)
 impl FromStr for Timestamp {
     type Err = ParsingError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let parts: Vec<&str> = s.split(&[':', ',']).collect();
-        if parts.len() != 4 {
+        let number = |part: &str| {
+            if part.is_empty() || !part.bytes().all(|b| b.is_ascii_digit()) {
+                return Err(ParsingError::InvalidTimestamp);
+            }
+            part.parse().map_err(|_| ParsingError::InvalidTimestamp)
+        };
+        let parts: Vec<&str> = s.split(':').collect();
+        let [hours, minutes, seconds] = parts[..] else {
+            return Err(ParsingError::InvalidTimestamp);
+        };
+        let (seconds, fraction) = seconds.split_once([',', '.']).unwrap_or((seconds, "0"));
+        if minutes.len() > 2 || seconds.len() > 2 || fraction.len() > 3 {
             return Err(ParsingError::InvalidTimestamp);
         }
 
         Ok(Timestamp {
-            hours: parts[0]
-                .parse()
-                .map_err(|_| ParsingError::InvalidTimestamp)?,
-            minutes: parts[1]
-                .parse()
-                .map_err(|_| ParsingError::InvalidTimestamp)?,
-            seconds: parts[2]
-                .parse()
-                .map_err(|_| ParsingError::InvalidTimestamp)?,
-            milliseconds: parts[3]
-                .parse()
-                .map_err(|_| ParsingError::InvalidTimestamp)?,
+            hours: number(hours)?,
+            minutes: number(minutes)?,
+            seconds: number(seconds)?,
+            milliseconds: number(&format!("{:0<3}", fraction))?,
         })
     }
 }
@@ -70,17 +118,54 @@ impl Subtitle {
             number,
             start_time,
             end_time,
+            raw_text: text.clone(),
             text,
+            speaker: None,
         }
     }
 
-    pub fn to_string(&self) -> String {
-        format!(
+    /// Sets the text the cue had in the file, when cleaning changed it.
+    pub fn with_raw_text(mut self, raw_text: String) -> Self {
+        self.raw_text = raw_text;
+        self
+    }
+
+    /// Sets who says the line.
+    pub fn with_speaker(mut self, speaker: Option<String>) -> Self {
+        self.speaker = speaker;
+        self
+    }
+
+    /// The name in brackets before the cue's text, e.g. 太郎 in `（太郎）おはよう`,
+    /// in its cleaned or raw text. Bracketed text with nothing after it, such
+    /// as `【効果音】`, is a sound description rather than a name.
+    pub fn bracketed_speaker(&self) -> Option<String> {
+        bracketed_name(&self.text)
+            .or_else(|| bracketed_name(&self.raw_text))
+            .map(str::to_string)
+    }
+}
+
+fn bracketed_name(text: &str) -> Option<&str> {
+    let text = text.trim_start();
+    let open = text.chars().next()?;
+    let &(_, close) = SPEAKER_BRACKETS
+        .iter()
+        .find(|(bracket, _)| *bracket == open)?;
+    let (name, rest) = text[open.len_utf8()..].split_once(close)?;
+    let name = name.trim();
+    let is_name = !name.is_empty()
+        && name.chars().count() <= MAX_SPEAKER_LENGTH
+        && !name.contains(|c: char| "\n、。！？!?…".contains(c));
+    (is_name && !rest.trim().is_empty()).then_some(name)
+}
+
+impl fmt::Display for Subtitle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
             "{}\n{} --> {}\n{}",
-            self.number,
-            self.start_time.to_string(),
-            self.end_time.to_string(),
-            self.text
+            self.number, self.start_time, self.end_time, self.text
         )
     }
 }
@@ -110,12 +195,44 @@ impl Subtitles {
         self.0.iter_mut()
     }
 
-    pub fn to_string(&self) -> String {
-        self.0
+    /// Merges cues shown at the same time, as some rips split top and bottom
+    /// text into, into the first of them, joining their texts with newlines.
+    /// Returns how many cues were merged away.
+    pub fn merge_simultaneous(&mut self) -> usize {
+        let before = self.0.len();
+        let mut merged: Vec<Subtitle> = Vec::with_capacity(before);
+        let mut first_at: HashMap<(u64, u64), usize> = HashMap::new();
+        for subtitle in self.0.drain(..) {
+            let key = (
+                subtitle.start_time.to_millis(),
+                subtitle.end_time.to_millis(),
+            );
+            match first_at.get(&key) {
+                Some(&i) => {
+                    let first = &mut merged[i];
+                    first.text = format!("{}\n{}", first.text, subtitle.text);
+                    first.raw_text = format!("{}\n{}", first.raw_text, subtitle.raw_text);
+                }
+                None => {
+                    first_at.insert(key, merged.len());
+                    merged.push(subtitle);
+                }
+            }
+        }
+        self.0 = merged;
+        before - self.0.len()
+    }
+}
+
+impl fmt::Display for Subtitles {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let joined = self
+            .0
             .iter()
             .map(|subtitle| subtitle.to_string())
             .collect::<Vec<String>>()
-            .join("\n\n")
+            .join("\n\n");
+        f.write_str(&joined)
     }
 }
 
@@ -145,3 +262,71 @@ impl<'a> IntoIterator for &'a mut Subtitles {
         self.0.iter_mut()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timestamp_variants() {
+        let parse = |s: &str| Timestamp::from_str(s).map(|t| t.to_string()).ok();
+        assert_eq!(parse("01:02:03,456").as_deref(), Some("01:02:03,456"));
+        assert_eq!(parse("01:02:03.456").as_deref(), Some("01:02:03,456"));
+        assert_eq!(parse("1:02:03,456").as_deref(), Some("01:02:03,456"));
+        assert_eq!(parse("00:00:01").as_deref(), Some("00:00:01,000"));
+        assert_eq!(parse("00:00:01.5").as_deref(), Some("00:00:01,500"));
+        assert_eq!(parse("00:00:xx,000"), None);
+        assert_eq!(parse("00:00:01,1234"), None);
+        assert_eq!(parse("00:01,000"), None);
+    }
+
+    #[test]
+    fn test_merge_simultaneous() {
+        let cue = |start: u64, text: &str| {
+            Subtitle::new(
+                1,
+                Timestamp::from_millis(start),
+                Timestamp::from_millis(start + 1000),
+                text.to_string(),
+            )
+        };
+        let mut subtitles = Subtitles(vec![
+            cue(0, "上の字幕"),
+            cue(0, "下の字幕"),
+            cue(2000, "次"),
+        ]);
+        assert_eq!(subtitles.merge_simultaneous(), 1);
+        assert_eq!(subtitles.len(), 2);
+        assert_eq!(subtitles.0[0].text, "上の字幕\n下の字幕");
+        assert_eq!(subtitles.0[1].text, "次");
+    }
+
+    #[test]
+    fn test_bracketed_speaker() {
+        let speaker = |text: &str| {
+            Subtitle::new(
+                1,
+                Timestamp::from_millis(0),
+                Timestamp::from_millis(1000),
+                text.to_string(),
+            )
+            .bracketed_speaker()
+        };
+        assert_eq!(speaker("（太郎）おはよう").as_deref(), Some("太郎"));
+        assert_eq!(speaker("【花子】\nまたね").as_deref(), Some("花子"));
+        assert_eq!(speaker("[Narrator] 昔々").as_deref(), Some("Narrator"));
+        assert_eq!(speaker("【効果音】"), None);
+        assert_eq!(speaker("（ドアが閉まる音、そして足音）誰？"), None);
+        assert_eq!(speaker("おはよう（笑）"), None);
+
+        // A name a cleaning hook stripped from the text is still in the raw text
+        let cue = Subtitle::new(
+            1,
+            Timestamp::from_millis(0),
+            Timestamp::from_millis(1000),
+            "次".to_string(),
+        )
+        .with_raw_text("（太郎）次".to_string());
+        assert_eq!(cue.bracketed_speaker().as_deref(), Some("太郎"));
+    }
+}
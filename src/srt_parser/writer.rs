@@ -0,0 +1,171 @@
+use super::errors::ParsingError;
+use super::types::{Subtitle, Subtitles, Timestamp};
+use std::fs;
+use std::path::Path;
+
+/// The line ending used when writing subtitle files.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    /// Windows line endings, which some older players expect
+    CrLf,
+}
+
+impl LineEnding {
+    fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+        }
+    }
+}
+
+impl Subtitles {
+    /// Numbers the cues 1, 2, 3... in their current order.
+    pub fn renumber(&mut self) {
+        for (i, subtitle) in self.iter_mut().enumerate() {
+            subtitle.number = i + 1;
+        }
+    }
+
+    /// Serializes the cues as SRT, numbered from 1 in order.
+    ///
+    /// Blank lines inside a cue's text are dropped, since SRT players read
+    /// them as the end of the cue. Cues without any text are left out.
+    pub fn to_srt(&self, line_ending: LineEnding) -> String {
+        let mut output = String::new();
+        for (i, (subtitle, text)) in self.written_cues(line_ending).enumerate() {
+            let lines = [
+                (i + 1).to_string(),
+                format!("{} --> {}", subtitle.start_time, subtitle.end_time),
+                text,
+                String::new(),
+            ];
+            for line in lines {
+                output.push_str(&line);
+                output.push_str(line_ending.as_str());
+            }
+        }
+        output
+    }
+
+    /// Serializes the cues as WebVTT.
+    ///
+    /// `&`, `<` and `>` are escaped, as VTT would read them as markup, and
+    /// blank lines inside a cue are dropped as with [`Subtitles::to_srt`].
+    pub fn to_vtt(&self, line_ending: LineEnding) -> String {
+        let mut output = format!("WEBVTT{0}{0}", line_ending.as_str());
+        for (subtitle, text) in self.written_cues(line_ending) {
+            let text = text
+                .replace('&', "&amp;")
+                .replace('<', "&lt;")
+                .replace('>', "&gt;");
+            let lines = [
+                format!(
+                    "{} --> {}",
+                    vtt_timestamp(&subtitle.start_time),
+                    vtt_timestamp(&subtitle.end_time)
+                ),
+                text,
+                String::new(),
+            ];
+            for line in lines {
+                output.push_str(&line);
+                output.push_str(line_ending.as_str());
+            }
+        }
+        output
+    }
+
+    /// Writes the cues to `path` as SRT; see [`Subtitles::to_srt`].
+    pub fn write_srt(&self, path: &Path, line_ending: LineEnding) -> Result<(), ParsingError> {
+        Ok(fs::write(path, self.to_srt(line_ending))?)
+    }
+
+    /// Writes the cues to `path` as WebVTT; see [`Subtitles::to_vtt`].
+    pub fn write_vtt(&self, path: &Path, line_ending: LineEnding) -> Result<(), ParsingError> {
+        Ok(fs::write(path, self.to_vtt(line_ending))?)
+    }
+
+    // Each cue with its text lines joined by `line_ending`, skipping cues with no text
+    fn written_cues(&self, line_ending: LineEnding) -> impl Iterator<Item = (&Subtitle, String)> {
+        self.iter().filter_map(move |subtitle| {
+            let lines: Vec<&str> = subtitle
+                .text
+                .lines()
+                .map(str::trim_end)
+                .filter(|line| !line.trim().is_empty())
+                .collect();
+            (!lines.is_empty()).then(|| (subtitle, lines.join(line_ending.as_str())))
+        })
+    }
+}
+
+fn vtt_timestamp(timestamp: &Timestamp) -> String {
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        timestamp.hours, timestamp.minutes, timestamp.seconds, timestamp.milliseconds
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn subtitles() -> Subtitles {
+        Subtitles(vec![
+            Subtitle::new(
+                7,
+                Timestamp::from_millis(1500),
+                Timestamp::from_millis(3000),
+                "一行目\n\n二行目".to_string(),
+            ),
+            Subtitle::new(
+                9,
+                Timestamp::from_millis(4000),
+                Timestamp::from_millis(5000),
+                " ".to_string(),
+            ),
+            Subtitle::new(
+                8,
+                Timestamp::from_millis(3_723_004),
+                Timestamp::from_millis(3_724_000),
+                "A & <B>".to_string(),
+            ),
+        ])
+    }
+
+    #[test]
+    fn test_to_srt_round_trips() {
+        let srt = subtitles().to_srt(LineEnding::Lf);
+        assert_eq!(
+            srt,
+            "1\n00:00:01,500 --> 00:00:03,000\n一行目\n二行目\n\n\
+             2\n01:02:03,004 --> 01:02:04,000\nA & <B>\n\n"
+        );
+        let parsed = Subtitles::parse_from_str(&srt).unwrap();
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed.0[1].text, "A & <B>");
+
+        let crlf = subtitles().to_srt(LineEnding::CrLf);
+        assert_eq!(crlf, srt.replace('\n', "\r\n"));
+    }
+
+    #[test]
+    fn test_to_vtt() {
+        assert_eq!(
+            subtitles().to_vtt(LineEnding::Lf),
+            "WEBVTT\n\n00:00:01.500 --> 00:00:03.000\n一行目\n二行目\n\n\
+             01:02:03.004 --> 01:02:04.000\nA &amp; &lt;B&gt;\n\n"
+        );
+    }
+
+    #[test]
+    fn test_renumber() {
+        let mut subtitles = subtitles();
+        subtitles.renumber();
+        let numbers: Vec<usize> = subtitles.iter().map(|subtitle| subtitle.number).collect();
+        assert_eq!(numbers, [1, 2, 3]);
+    }
+}
@@ -0,0 +1,261 @@
+use super::bilingual::{base_name, JAPANESE_SUFFIXES};
+use super::errors::{Diagnostic, ParsingError, Position};
+use super::parsing::SubtitleFormat;
+use super::stats::{clean_input, ParseStats};
+use super::ttml::decode_entities;
+use super::types::{Subtitle, Subtitles, Timestamp};
+use regex::Regex;
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+
+// yt-dlp's json3 captions: a list of events, each with its text split into segments
+#[derive(Debug, Deserialize)]
+struct Json3 {
+    #[serde(default)]
+    events: Vec<Json3Event>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Json3Event {
+    t_start_ms: u64,
+    #[serde(default)]
+    d_duration_ms: u64,
+    #[serde(default)]
+    segs: Vec<Json3Segment>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Json3Segment {
+    #[serde(default)]
+    utf8: String,
+}
+
+impl Subtitles {
+    /// Parses YouTube's json3 timed text (`yt-dlp --sub-format json3`) into subtitles.
+    ///
+    /// Each event with text becomes one cue, its segments (the words of
+    /// automatic captions) joined. Events without text, which only set up
+    /// caption windows or add line breaks, are skipped.
+    pub fn parse_json3(input: &str) -> Result<Self, ParsingError> {
+        Self::parse_json3_with_stats(input, &mut ParseStats::default())
+    }
+
+    pub(super) fn parse_json3_with_stats(
+        input: &str,
+        stats: &mut ParseStats,
+    ) -> Result<Self, ParsingError> {
+        let input = clean_input(input, &mut stats.cleaning);
+        let json3: Json3 =
+            serde_json::from_str(&input).map_err(|_| ParsingError::MalformedSubtitle)?;
+
+        let mut subtitles = Subtitles::new();
+        for event in json3.events {
+            let text: String = event.segs.iter().map(|seg| seg.utf8.as_str()).collect();
+            let Some(text) = caption_text(&text) else {
+                continue;
+            };
+            subtitles.push(
+                Subtitle::new(
+                    subtitles.len() + 1,
+                    Timestamp::from_millis(event.t_start_ms),
+                    Timestamp::from_millis(event.t_start_ms + event.d_duration_ms),
+                    text,
+                )
+                .with_raw_text(event.segs.into_iter().map(|seg| seg.utf8).collect()),
+            );
+        }
+
+        if subtitles.is_empty() {
+            Err(ParsingError::MalformedSubtitle)
+        } else {
+            Ok(subtitles)
+        }
+    }
+
+    /// Parses YouTube's srv3 timed text (`yt-dlp --sub-format srv3`) into subtitles.
+    ///
+    /// Each `<p t="start" d="duration">` with text becomes one cue, in
+    /// milliseconds; the `<s>` word segments of automatic captions are joined
+    /// and other markup is dropped.
+    pub fn parse_srv3(input: &str) -> Result<Self, ParsingError> {
+        Self::parse_srv3_with_stats(input, &mut ParseStats::default())
+    }
+
+    pub(super) fn parse_srv3_with_stats(
+        input: &str,
+        stats: &mut ParseStats,
+    ) -> Result<Self, ParsingError> {
+        let input = &clean_input(input, &mut stats.cleaning);
+        let regex =
+            |pattern: &str| Regex::new(pattern).map_err(|_| ParsingError::MalformedSubtitle);
+        let paragraph = regex(r"(?s)<p\b([^>]*)>(.*?)</p>")?;
+        let attribute = regex(r#"(\w+)\s*=\s*"([^"]*)""#)?;
+        let tag = regex(r"<[^>]*>")?;
+
+        let mut subtitles = Subtitles::new();
+        for (i, cap) in paragraph.captures_iter(input).enumerate() {
+            stats.cleaning.tags_removed += tag.find_iter(&cap[2]).count();
+            let Some(text) = caption_text(&decode_entities(&tag.replace_all(&cap[2], ""))) else {
+                continue;
+            };
+            let (mut start, mut duration) = (None, None);
+            for attr in attribute.captures_iter(&cap[1]) {
+                match &attr[1] {
+                    "t" => start = attr[2].parse::<u64>().ok(),
+                    "d" => duration = attr[2].parse::<u64>().ok(),
+                    _ => {}
+                }
+            }
+            let Some(start) = start else {
+                let offset = cap.get(0).map_or(0, |m| m.start());
+                stats.diagnostics.push(Diagnostic::new(
+                    Position::locate(input, offset, i + 1),
+                    ParsingError::InvalidTimestamp,
+                ));
+                stats.cues_skipped += 1;
+                continue;
+            };
+
+            subtitles.push(
+                Subtitle::new(
+                    subtitles.len() + 1,
+                    Timestamp::from_millis(start),
+                    Timestamp::from_millis(start + duration.unwrap_or(0)),
+                    text,
+                )
+                .with_raw_text(cap[2].to_string()),
+            );
+        }
+
+        if subtitles.is_empty() {
+            Err(ParsingError::MalformedSubtitle)
+        } else {
+            Ok(subtitles)
+        }
+    }
+}
+
+// The caption's lines, trimmed and without blank ones; `None` if no text is left
+fn caption_text(text: &str) -> Option<String> {
+    let text = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n");
+    (!text.is_empty()).then_some(text)
+}
+
+/// The YouTube video a captions file was downloaded for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct YoutubeVideo {
+    /// The video id, e.g. `dQw4w9WgXcQ`
+    pub id: String,
+    pub title: String,
+    /// The channel, if yt-dlp's `.info.json` was written next to the file
+    pub channel: Option<String>,
+}
+
+impl YoutubeVideo {
+    pub fn url(&self) -> String {
+        format!("https://www.youtube.com/watch?v={}", self.id)
+    }
+}
+
+// The parts of yt-dlp's `.info.json` that are read
+#[derive(Debug, Deserialize)]
+struct InfoJson {
+    id: String,
+    title: String,
+    channel: Option<String>,
+    uploader: Option<String>,
+}
+
+/// The video a YouTube captions file (json3 or srv3) downloaded with yt-dlp
+/// is for: from the `.info.json` next to it (`--write-info-json`), or else
+/// from yt-dlp's default file name, `Title [id].ja.json3`.
+pub fn youtube_video(path: &Path) -> Option<YoutubeVideo> {
+    if !matches!(
+        SubtitleFormat::from_path(path),
+        Some(SubtitleFormat::Json3 | SubtitleFormat::Srv3)
+    ) {
+        return None;
+    }
+    let base = base_name(path, &JAPANESE_SUFFIXES)?;
+    let info = path.with_file_name(format!("{}.info.json", base));
+    if let Some(info) = fs::read_to_string(info)
+        .ok()
+        .and_then(|json| serde_json::from_str::<InfoJson>(&json).ok())
+    {
+        return Some(YoutubeVideo {
+            id: info.id,
+            title: info.title,
+            channel: info.channel.or(info.uploader),
+        });
+    }
+
+    let name = Regex::new(r"^(.*?)\s*\[([A-Za-z0-9_-]{11})\]$").ok()?;
+    let cap = name.captures(&base)?;
+    Some(YoutubeVideo {
+        id: cap[2].to_string(),
+        title: cap[1].to_string(),
+        channel: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_parse_json3() {
+        let input = r#"{"wireMagic": "pb3", "events": [
+            {"tStartMs": 0, "dDurationMs": 6000, "id": 1, "wpWinPosId": 1},
+            {"tStartMs": 1200, "dDurationMs": 3000, "wWinId": 1,
+             "segs": [{"utf8": "こんにちは", "acAsrConf": 0}, {"utf8": " 皆さん", "tOffsetMs": 480}]},
+            {"tStartMs": 4200, "wWinId": 1, "aAppend": 1, "segs": [{"utf8": "\n"}]},
+            {"tStartMs": 4200, "dDurationMs": 1800, "segs": [{"utf8": "今日は晴れ"}]}
+        ]}"#;
+        let subtitles = Subtitles::parse_json3(input).unwrap();
+        assert_eq!(subtitles.len(), 2);
+        assert_eq!(subtitles.0[0].text, "こんにちは 皆さん");
+        assert_eq!(subtitles.0[0].start_time.to_string(), "00:00:01,200");
+        assert_eq!(subtitles.0[0].end_time.to_string(), "00:00:04,200");
+        assert_eq!(subtitles.0[1].number, 2);
+        assert!(Subtitles::parse_json3("not json").is_err());
+    }
+
+    #[test]
+    fn test_parse_srv3() {
+        let input = r#"<?xml version="1.0" encoding="utf-8" ?><timedtext format="3">
+<body>
+<p t="1200" d="3000" w="1"><s ac="0">こんにちは</s><s t="480"> 皆さん</s></p>
+<p t="4200" w="1" a="1">
+</p>
+<p t="4200" d="1800">A &amp; B</p>
+<p d="1000">いつ？</p>
+</body>
+</timedtext>"#;
+        let mut stats = ParseStats::default();
+        let subtitles = Subtitles::parse_srv3_with_stats(input, &mut stats).unwrap();
+        assert_eq!(subtitles.len(), 2);
+        assert_eq!(subtitles.0[0].text, "こんにちは 皆さん");
+        assert_eq!(subtitles.0[0].end_time.to_string(), "00:00:04,200");
+        assert_eq!(subtitles.0[1].text, "A & B");
+        assert_eq!(stats.cues_skipped, 1);
+        assert_eq!(stats.cleaning.tags_removed, 4);
+    }
+
+    #[test]
+    fn test_youtube_video() {
+        let video =
+            youtube_video(&PathBuf::from("Channel/猫の動画 [dQw4w9WgXcQ].ja.json3")).unwrap();
+        assert_eq!(video.id, "dQw4w9WgXcQ");
+        assert_eq!(video.title, "猫の動画");
+        assert_eq!(video.url(), "https://www.youtube.com/watch?v=dQw4w9WgXcQ");
+        assert_eq!(youtube_video(&PathBuf::from("Show/Episode 01.srv3")), None);
+    }
+}
@@ -0,0 +1,699 @@
+#[cfg(feature = "chinese")]
+mod chinese;
+#[cfg(feature = "korean")]
+mod korean;
+mod normalize;
+mod policy;
+mod stopwords;
+#[cfg(test)]
+pub(crate) mod test_utils;
+mod whitespace;
+
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+#[cfg(feature = "chinese")]
+pub use chinese::ChineseTokenizer;
+#[cfg(feature = "korean")]
+pub use korean::KoreanTokenizer;
+use normalize::normalize_pieces;
+pub use normalize::{fold_case, normalize};
+pub use policy::IndexPolicy;
+pub use stopwords::StopwordStrategy;
+pub use whitespace::WhitespaceTokenizer;
+
+/// The layout of the morphological dictionary's feature columns.
+///
+/// IPADIC and UniDic both use the MeCab CSV format, but store the base form
+/// and reading of a word in different columns.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DictionaryKind {
+    #[default]
+    Ipadic,
+    Unidic,
+}
+
+impl DictionaryKind {
+    // Column indices of the base form and reading within the feature string
+    fn base_form_index(&self) -> usize {
+        match self {
+            DictionaryKind::Ipadic => 6,
+            DictionaryKind::Unidic => 10,
+        }
+    }
+
+    fn reading_indices(&self) -> &'static [usize] {
+        match self {
+            DictionaryKind::Ipadic => &[7],
+            // unidic-cwj stores the kana reading in column 20, older releases only have pron
+            DictionaryKind::Unidic => &[20, 9],
+        }
+    }
+
+    /// A user dictionary CSV line (without newline) adding `surface` as a proper noun
+    /// with the given katakana reading, laid out so the feature columns match this kind.
+    pub fn proper_noun_entry(&self, surface: &str, reading: &str) -> String {
+        let columns = match self {
+            DictionaryKind::Ipadic => 9,
+            DictionaryKind::Unidic => 21,
+        };
+        let mut features = vec!["*"; columns];
+        features[..3].copy_from_slice(&["名詞", "固有名詞", "一般"]);
+        match self {
+            DictionaryKind::Ipadic => features[8] = reading,
+            // lemma reading, lemma, orthography, pronunciation and base reading
+            DictionaryKind::Unidic => {
+                features[6] = reading;
+                features[7] = surface;
+                features[8] = surface;
+                features[11] = reading;
+            }
+        }
+        features[self.base_form_index()] = surface;
+        for &index in self.reading_indices() {
+            features[index] = reading;
+        }
+        format!(
+            "{},0,0,{},{}",
+            surface,
+            PROPER_NOUN_COST,
+            features.join(",")
+        )
+    }
+}
+
+// Word cost of generated proper noun entries; low enough to beat splitting the name
+const PROPER_NOUN_COST: i16 = 100;
+
+/// Which tokenizer a corpus is indexed and searched with.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenizerKind {
+    /// The morphological analyzer ([`JapaneseTokenizer`])
+    #[default]
+    Japanese,
+    /// Words separated by spaces and punctuation ([`WhitespaceTokenizer`])
+    Whitespace,
+    /// jieba's Chinese word segmentation (`ChineseTokenizer`, needs the `chinese` feature)
+    Chinese,
+    /// lindera's Korean morphological analysis (`KoreanTokenizer`, needs the `korean` feature)
+    Korean,
+}
+
+/// `[tokenizer]` section of the config file.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TokenizerConfig {
+    /// Which dictionary format `dictionary_path` points to
+    pub dictionary: DictionaryKind,
+    /// A compiled vibrato system dictionary (`system.dic`, decompressed)
+    pub dictionary_path: Option<PathBuf>,
+    /// A MeCab-style CSV of extra words, such as character and place names
+    pub user_dictionary_path: Option<PathBuf>,
+    /// Where `glossary --extract` writes the proper nouns it finds, in the same CSV
+    /// format; loaded along with `user_dictionary_path` when it exists
+    pub glossary_dictionary_path: Option<PathBuf>,
+    /// Words left out of the index and ignored in queries
+    pub stopwords: StopwordStrategy,
+    /// The tokenizer of each corpus (see `corpus`) that isn't Japanese,
+    /// e.g. `english = "whitespace"`
+    pub corpora: HashMap<String, TokenizerKind>,
+    /// Which words the Japanese tokenizer indexes beyond the stopwords
+    pub index: IndexPolicy,
+}
+
+impl TokenizerConfig {
+    /// The tokenizer lines of `corpus` are indexed with; Japanese unless configured otherwise.
+    pub fn kind_for(&self, corpus: &str) -> TokenizerKind {
+        self.corpora.get(corpus).copied().unwrap_or_default()
+    }
+}
+
+#[derive(Debug)]
+pub enum TokenizerError {
+    MissingDictionary,
+    IoError(std::io::Error),
+    DictionaryError(vibrato::errors::VibratoError),
+    #[cfg(feature = "korean")]
+    KoreanDictionaryError(String),
+    /// The tokenizer was configured but the crate was built without its feature
+    NotCompiled(TokenizerKind),
+}
+
+impl From<std::io::Error> for TokenizerError {
+    fn from(error: std::io::Error) -> Self {
+        TokenizerError::IoError(error)
+    }
+}
+
+impl From<vibrato::errors::VibratoError> for TokenizerError {
+    fn from(error: vibrato::errors::VibratoError) -> Self {
+        TokenizerError::DictionaryError(error)
+    }
+}
+
+impl fmt::Display for TokenizerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenizerError::MissingDictionary => {
+                write!(f, "No dictionary_path configured for the tokenizer")
+            }
+            TokenizerError::IoError(e) => write!(f, "I/O error: {}", e),
+            TokenizerError::DictionaryError(e) => write!(f, "Dictionary error: {}", e),
+            #[cfg(feature = "korean")]
+            TokenizerError::KoreanDictionaryError(e) => write!(f, "Korean dictionary error: {}", e),
+            TokenizerError::NotCompiled(kind) => {
+                write!(f, "The {:?} tokenizer isn't available in this build", kind)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TokenizerError {}
+
+/// A single morpheme produced by the tokenizer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    pub surface: String,
+    /// Dictionary form, e.g. 走る for 走っ; falls back to the surface for unknown words.
+    /// Latin letters are lowercased (see [`fold_case`]), so DVD is indexed as dvd
+    pub base_form: String,
+    /// Katakana reading, if the dictionary has one
+    pub reading: Option<String>,
+    /// Part-of-speech hierarchy, most general first (e.g. ["動詞", "自立"])
+    pub pos: Vec<String>,
+}
+
+impl Token {
+    /// Hiragana reading of the dictionary form, e.g. はしる for 走っ.
+    ///
+    /// Inflected tokens only have the reading of their surface, so the base
+    /// form's reading is rebuilt by swapping the kana ending of the surface
+    /// for that of the base form. Returns None when that isn't possible.
+    pub fn base_reading(&self) -> Option<String> {
+        if self.base_form.chars().all(is_kana) {
+            return Some(katakana_to_hiragana(&self.base_form));
+        }
+        let reading = katakana_to_hiragana(self.reading.as_ref()?);
+        let stem_len = self
+            .surface
+            .chars()
+            .zip(self.base_form.chars())
+            .take_while(|(a, b)| a == b)
+            .count();
+        let surface_ending: String = self.surface.chars().skip(stem_len).collect();
+        let base_ending: String = self.base_form.chars().skip(stem_len).collect();
+        if !surface_ending.chars().all(is_kana) || !base_ending.chars().all(is_kana) {
+            return None;
+        }
+        let stem_reading = reading.strip_suffix(&katakana_to_hiragana(&surface_ending))?;
+        Some(format!(
+            "{}{}",
+            stem_reading,
+            katakana_to_hiragana(&base_ending)
+        ))
+    }
+}
+
+/// Splits text into the tokens stored in the word index.
+///
+/// Lines are indexed, and queries on them parsed, with the tokenizer of their
+/// corpus (see [`TokenizerConfig::corpora`]).
+pub trait Tokenizer: Send + Sync {
+    /// Names the tokenizer and its dictionary, e.g. `japanese/ipadic`, for the
+    /// index's metadata.
+    fn name(&self) -> String;
+
+    /// Splits `text` into tokens. The text is normalized first (see [`normalize`]),
+    /// so surfaces may differ from the original in width.
+    fn tokenize(&self, text: &str) -> Vec<Token>;
+
+    /// Which tokens are left out of the word index.
+    fn stopwords(&self) -> &StopwordStrategy;
+
+    /// Which of the tokens the stopwords let through are indexed.
+    fn policy(&self) -> &IndexPolicy {
+        &DEFAULT_POLICY
+    }
+
+    /// Identifies how this tokenizer picks index terms; see [`IndexPolicy::fingerprint`].
+    fn index_fingerprint(&self) -> String {
+        self.policy().fingerprint(self.stopwords())
+    }
+
+    /// The dictionary forms stored in the word index for `text`, with stopwords removed.
+    ///
+    /// Used both when indexing lines and when parsing search queries.
+    fn index_terms(&self, text: &str) -> Vec<String> {
+        self.index_tokens(text)
+            .into_iter()
+            .map(|(_, token)| token.base_form)
+            .collect()
+    }
+
+    /// The tokens of `text` that belong in the word index, with their position
+    /// among all tokens of `text` (stopwords still count towards positions).
+    fn index_tokens(&self, text: &str) -> Vec<(usize, Token)> {
+        self.tokenize(text)
+            .into_iter()
+            .enumerate()
+            .filter(|(_, token)| !self.stopwords().is_stopword(token))
+            .filter(|(_, token)| !token.base_form.trim().is_empty())
+            .filter(|(_, token)| self.policy().allows(token))
+            .collect()
+    }
+}
+
+// Let shared and borrowed tokenizers be passed wherever a `&dyn Tokenizer` is expected
+impl<T: Tokenizer + ?Sized> Tokenizer for &T {
+    fn name(&self) -> String {
+        (**self).name()
+    }
+
+    fn tokenize(&self, text: &str) -> Vec<Token> {
+        (**self).tokenize(text)
+    }
+
+    fn stopwords(&self) -> &StopwordStrategy {
+        (**self).stopwords()
+    }
+
+    fn policy(&self) -> &IndexPolicy {
+        (**self).policy()
+    }
+}
+
+impl<T: Tokenizer + ?Sized> Tokenizer for Arc<T> {
+    fn name(&self) -> String {
+        (**self).name()
+    }
+
+    fn tokenize(&self, text: &str) -> Vec<Token> {
+        (**self).tokenize(text)
+    }
+
+    fn stopwords(&self) -> &StopwordStrategy {
+        (**self).stopwords()
+    }
+
+    fn policy(&self) -> &IndexPolicy {
+        (**self).policy()
+    }
+}
+
+static DEFAULT_POLICY: IndexPolicy = IndexPolicy::DEFAULT;
+
+/// Japanese morphological analyzer backed by a MeCab-format dictionary.
+pub struct JapaneseTokenizer {
+    kind: DictionaryKind,
+    inner: vibrato::Tokenizer,
+    stopwords: StopwordStrategy,
+    policy: IndexPolicy,
+}
+
+impl JapaneseTokenizer {
+    pub fn from_config(config: &TokenizerConfig) -> Result<Self, TokenizerError> {
+        let path = config
+            .dictionary_path
+            .as_ref()
+            .ok_or(TokenizerError::MissingDictionary)?;
+        let mut dictionary = read_dictionary(path)?;
+        let mut user_lexicon = String::new();
+        if let Some(user_path) = &config.user_dictionary_path {
+            user_lexicon = std::fs::read_to_string(user_path)?;
+        }
+        // The glossary is generated, so it's fine for it not to exist yet
+        if let Some(glossary_path) = config
+            .glossary_dictionary_path
+            .as_ref()
+            .filter(|p| p.exists())
+        {
+            if !user_lexicon.is_empty() && !user_lexicon.ends_with('\n') {
+                user_lexicon.push('\n');
+            }
+            user_lexicon.push_str(&std::fs::read_to_string(glossary_path)?);
+        }
+        if !user_lexicon.is_empty() {
+            dictionary =
+                dictionary.reset_user_lexicon_from_reader(Some(user_lexicon.as_bytes()))?;
+        }
+        Ok(Self::new(dictionary, config.dictionary)
+            .with_stopwords(config.stopwords.clone())
+            .with_policy(config.index.clone()))
+    }
+
+    pub fn new(dictionary: vibrato::Dictionary, kind: DictionaryKind) -> Self {
+        JapaneseTokenizer {
+            kind,
+            inner: vibrato::Tokenizer::new(dictionary),
+            stopwords: StopwordStrategy::default(),
+            policy: IndexPolicy::default(),
+        }
+    }
+
+    pub fn with_stopwords(mut self, stopwords: StopwordStrategy) -> Self {
+        self.stopwords = stopwords;
+        self
+    }
+
+    pub fn with_policy(mut self, policy: IndexPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Splits `text` into tokens. The text is normalized first (see [`normalize`]),
+    /// so surfaces may differ from the original in width.
+    pub fn tokenize(&self, text: &str) -> Vec<Token> {
+        let mut worker = self.inner.new_worker();
+        worker.reset_sentence(normalize(text));
+        worker.tokenize();
+        worker
+            .token_iter()
+            .map(|token| self.to_token(token.surface(), token.feature()))
+            .collect()
+    }
+
+    /// Like [`tokenize`](Self::tokenize), but with the surfaces as written in
+    /// `text`, for showing the text as it was, e.g. with furigana. The other
+    /// fields come from the normalized text.
+    ///
+    /// Characters that normalize into several tokens (㍿ becomes 株式会社) go
+    /// to the first of them, leaving the others an empty surface.
+    pub fn tokenize_original(&self, text: &str) -> Vec<Token> {
+        let (normalized, pieces) = normalize_pieces(text);
+        let mut worker = self.inner.new_worker();
+        worker.reset_sentence(normalized);
+        worker.tokenize();
+        let mut pieces = pieces.into_iter().peekable();
+        let mut tokens: Vec<Token> = worker
+            .token_iter()
+            .map(|token| {
+                let end = token.range_byte().end;
+                let mut surface = String::new();
+                while let Some(piece) = pieces.next_if(|piece| piece.normalized.start < end) {
+                    surface.push_str(&text[piece.original]);
+                }
+                Token {
+                    surface,
+                    ..self.to_token(token.surface(), token.feature())
+                }
+            })
+            .collect();
+        if let Some(last) = tokens.last_mut() {
+            for piece in pieces {
+                last.surface.push_str(&text[piece.original]);
+            }
+        }
+        tokens
+    }
+
+    /// See [`Tokenizer::index_terms`].
+    pub fn index_terms(&self, text: &str) -> Vec<String> {
+        Tokenizer::index_terms(self, text)
+    }
+
+    /// See [`Tokenizer::index_tokens`].
+    pub fn index_tokens(&self, text: &str) -> Vec<(usize, Token)> {
+        Tokenizer::index_tokens(self, text)
+    }
+
+    fn to_token(&self, surface: &str, feature: &str) -> Token {
+        let fields: Vec<&str> = feature.split(',').collect();
+        let field = |index: usize| {
+            fields
+                .get(index)
+                .filter(|value| !value.is_empty() && **value != "*")
+                .map(|value| value.to_string())
+        };
+        Token {
+            surface: surface.to_string(),
+            base_form: fold_case(
+                &field(self.kind.base_form_index()).unwrap_or_else(|| surface.to_string()),
+            ),
+            reading: self.kind.reading_indices().iter().find_map(|&i| field(i)),
+            pos: (0..4).map_while(field).collect(),
+        }
+    }
+}
+
+impl Tokenizer for JapaneseTokenizer {
+    fn name(&self) -> String {
+        match self.kind {
+            DictionaryKind::Ipadic => "japanese/ipadic",
+            DictionaryKind::Unidic => "japanese/unidic",
+        }
+        .to_string()
+    }
+
+    fn tokenize(&self, text: &str) -> Vec<Token> {
+        JapaneseTokenizer::tokenize(self, text)
+    }
+
+    fn stopwords(&self) -> &StopwordStrategy {
+        &self.stopwords
+    }
+
+    fn policy(&self) -> &IndexPolicy {
+        &self.policy
+    }
+}
+
+/// Opens the tokenizer of `kind`, with the dictionary and stopwords of `config`
+/// for the Japanese one; the others use their own default stopwords.
+pub fn open_tokenizer(
+    config: &TokenizerConfig,
+    kind: TokenizerKind,
+) -> Result<Box<dyn Tokenizer>, TokenizerError> {
+    match kind {
+        TokenizerKind::Japanese => Ok(Box::new(JapaneseTokenizer::from_config(config)?)),
+        TokenizerKind::Whitespace => Ok(Box::new(WhitespaceTokenizer::default())),
+        #[cfg(feature = "chinese")]
+        TokenizerKind::Chinese => Ok(Box::new(ChineseTokenizer::default())),
+        #[cfg(not(feature = "chinese"))]
+        TokenizerKind::Chinese => Err(TokenizerError::NotCompiled(kind)),
+        #[cfg(feature = "korean")]
+        TokenizerKind::Korean => Ok(Box::new(KoreanTokenizer::new()?)),
+        #[cfg(not(feature = "korean"))]
+        TokenizerKind::Korean => Err(TokenizerError::NotCompiled(kind)),
+    }
+}
+
+/// Whether `c` is a CJK ideograph (kanji), including the extension A and compatibility blocks.
+pub fn is_kanji(c: char) -> bool {
+    matches!(c, '\u{4E00}'..='\u{9FFF}' | '\u{3400}'..='\u{4DBF}' | '\u{F900}'..='\u{FAFF}')
+}
+
+/// Whether `c` is hiragana, katakana or the prolonged sound mark ー.
+pub fn is_kana(c: char) -> bool {
+    matches!(c, '\u{3041}'..='\u{3096}' | '\u{30A1}'..='\u{30FA}' | 'ー')
+}
+
+/// Converts hiragana to katakana, leaving every other character as is.
+pub fn hiragana_to_katakana(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '\u{3041}'..='\u{3096}' => char::from_u32(c as u32 + 0x60).unwrap_or(c),
+            _ => c,
+        })
+        .collect()
+}
+
+/// Converts katakana to hiragana, leaving every other character (including ー) as is.
+pub fn katakana_to_hiragana(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '\u{30A1}'..='\u{30F6}' => char::from_u32(c as u32 - 0x60).unwrap_or(c),
+            _ => c,
+        })
+        .collect()
+}
+
+// Reads a compiled (uncompressed) vibrato system dictionary
+fn read_dictionary(path: &Path) -> Result<vibrato::Dictionary, TokenizerError> {
+    let reader = BufReader::new(File::open(path)?);
+    Ok(vibrato::Dictionary::read(reader)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_utils::{test_dictionary, test_tokenizer};
+    use super::*;
+
+    #[test]
+    fn test_tokenize_base_form_and_reading() {
+        let tokens = test_tokenizer().tokenize("猫が走った");
+        let surfaces: Vec<&str> = tokens.iter().map(|t| t.surface.as_str()).collect();
+        let base_forms: Vec<&str> = tokens.iter().map(|t| t.base_form.as_str()).collect();
+        assert_eq!(surfaces, ["猫", "が", "走っ", "た"]);
+        assert_eq!(base_forms, ["猫", "が", "走る", "た"]);
+        assert_eq!(tokens[2].reading.as_deref(), Some("ハシッ"));
+        assert_eq!(tokens[2].pos, ["動詞", "自立"]);
+    }
+
+    #[test]
+    fn test_latin_words_are_width_and_case_folded() {
+        let tokens = test_tokenizer().tokenize("ＤＶＤを見る");
+        let surfaces: Vec<&str> = tokens.iter().map(|t| t.surface.as_str()).collect();
+        let base_forms: Vec<&str> = tokens.iter().map(|t| t.base_form.as_str()).collect();
+        assert_eq!(surfaces, ["DVD", "を", "見る"]);
+        assert_eq!(base_forms, ["dvd", "を", "見る"]);
+        // The Latin and kanji parts of a mixed word are split at the script boundary
+        assert_eq!(test_tokenizer().index_terms("LINE既読"), ["line", "既読"]);
+        assert_eq!(test_tokenizer().index_terms("Line 既読"), ["line", "既読"]);
+    }
+
+    #[test]
+    fn test_base_reading() {
+        let tokens = test_tokenizer().tokenize("猫が走った");
+        let readings: Vec<Option<String>> = tokens.iter().map(Token::base_reading).collect();
+        assert_eq!(readings[0].as_deref(), Some("ねこ"));
+        assert_eq!(readings[2].as_deref(), Some("はしる"));
+        assert_eq!(readings[3].as_deref(), Some("た"));
+    }
+
+    #[test]
+    fn test_index_terms_skips_stopwords() {
+        let tokenizer = test_tokenizer();
+        assert_eq!(tokenizer.index_terms("猫が走った。"), ["猫", "走る"]);
+        let tokenizer = tokenizer.with_stopwords(StopwordStrategy::IndexAll);
+        assert_eq!(
+            tokenizer.index_terms("猫が走った。"),
+            ["猫", "が", "走る", "た", "。"]
+        );
+    }
+
+    #[test]
+    fn test_index_terms_follow_policy() {
+        let tokenizer = test_tokenizer().with_policy(IndexPolicy {
+            min_length: 2,
+            ..IndexPolicy::default()
+        });
+        assert_eq!(tokenizer.index_terms("猫が走った。"), ["走る"]);
+        let tokenizer = test_tokenizer().with_policy(IndexPolicy {
+            pos: vec!["動詞".to_string()],
+            numbers: false,
+            ..IndexPolicy::default()
+        });
+        assert_eq!(tokenizer.index_terms("猫が走った2010"), ["走る"]);
+        assert_ne!(
+            tokenizer.index_fingerprint(),
+            test_tokenizer().index_fingerprint()
+        );
+    }
+
+    #[test]
+    fn test_user_dictionary_keeps_names_together() {
+        assert_ne!(test_tokenizer().tokenize("魔王城").len(), 1);
+
+        let user_lexicon =
+            "魔王城,0,0,100,名詞,固有名詞,一般,*,*,*,魔王城,マオウジョウ,マオウジョウ\n";
+        let dictionary = test_dictionary()
+            .reset_user_lexicon_from_reader(Some(user_lexicon.as_bytes()))
+            .unwrap();
+        let tokens = JapaneseTokenizer::new(dictionary, DictionaryKind::Ipadic).tokenize("魔王城");
+        assert_eq!(tokens.len(), 1);
+        assert_eq!(tokens[0].reading.as_deref(), Some("マオウジョウ"));
+    }
+
+    #[test]
+    fn test_proper_noun_entry_columns() {
+        let entry = DictionaryKind::Ipadic.proper_noun_entry("魔王城", "マオウジョウ");
+        assert_eq!(
+            entry,
+            "魔王城,0,0,100,名詞,固有名詞,一般,*,*,*,魔王城,マオウジョウ,マオウジョウ"
+        );
+        let dictionary = test_dictionary()
+            .reset_user_lexicon_from_reader(Some(entry.as_bytes()))
+            .unwrap();
+        let tokens = JapaneseTokenizer::new(dictionary, DictionaryKind::Ipadic).tokenize("魔王城");
+        assert_eq!(tokens.len(), 1);
+
+        let tokenizer = JapaneseTokenizer::new(test_dictionary(), DictionaryKind::Unidic);
+        let entry = DictionaryKind::Unidic.proper_noun_entry("魔王城", "マオウジョウ");
+        let feature = entry.splitn(5, ',').nth(4).unwrap();
+        let token = tokenizer.to_token("魔王城", feature);
+        assert_eq!(token.base_form, "魔王城");
+        assert_eq!(token.reading.as_deref(), Some("マオウジョウ"));
+        assert_eq!(token.pos, ["名詞", "固有名詞", "一般"]);
+    }
+
+    #[test]
+    fn test_unidic_feature_columns() {
+        let tokenizer = JapaneseTokenizer::new(test_dictionary(), DictionaryKind::Unidic);
+        let feature = "動詞,一般,*,*,五段-ラ行,連用形-促音便,ハシル,走る,走っ,ハシッ,走る,ハシル,和,*,*,*,*,*,*,用,ハシッ,ハシル,ハシッ,ハシル,2,C1,*,8036,29";
+        let token = tokenizer.to_token("走っ", feature);
+        assert_eq!(token.base_form, "走る");
+        assert_eq!(token.reading.as_deref(), Some("ハシッ"));
+        assert_eq!(token.pos, ["動詞", "一般"]);
+    }
+
+    #[test]
+    fn test_config_dictionary_kind() {
+        let config: TokenizerConfig =
+            toml::from_str("dictionary = \"unidic\"\ndictionary_path = \"system.dic\"").unwrap();
+        assert_eq!(config.dictionary, DictionaryKind::Unidic);
+        assert_eq!(config.dictionary_path, Some(PathBuf::from("system.dic")));
+        assert_eq!(config.stopwords, StopwordStrategy::default());
+        assert_eq!(config.kind_for("anime"), TokenizerKind::Japanese);
+        assert_eq!(config.index, IndexPolicy::default());
+    }
+
+    #[test]
+    fn test_config_index_policy() {
+        let config: TokenizerConfig =
+            toml::from_str("[index]\nmin_length = 2\nnumbers = false\npos = [\"名詞\"]").unwrap();
+        assert_eq!(config.index.min_length, 2);
+        assert_eq!(config.index.max_length, None);
+        assert_eq!(config.index.pos, ["名詞"]);
+        assert!(!config.index.numbers);
+    }
+
+    #[test]
+    fn test_config_corpus_tokenizers() {
+        let config: TokenizerConfig =
+            toml::from_str("[corpora]\nenglish = \"whitespace\"\nanime = \"japanese\"").unwrap();
+        assert_eq!(config.kind_for("english"), TokenizerKind::Whitespace);
+        assert_eq!(config.kind_for("anime"), TokenizerKind::Japanese);
+        assert_eq!(config.kind_for("drama"), TokenizerKind::Japanese);
+    }
+
+    #[test]
+    fn test_open_tokenizer() {
+        let config = TokenizerConfig::default();
+        let tokenizer = open_tokenizer(&config, TokenizerKind::Whitespace).unwrap();
+        assert_eq!(tokenizer.index_terms("Hello, world"), ["hello", "world"]);
+        // No dictionary configured
+        assert!(open_tokenizer(&config, TokenizerKind::Japanese).is_err());
+        #[cfg(not(feature = "chinese"))]
+        assert!(matches!(
+            open_tokenizer(&config, TokenizerKind::Chinese),
+            Err(TokenizerError::NotCompiled(TokenizerKind::Chinese))
+        ));
+    }
+
+    #[test]
+    fn test_config_stopwords() {
+        let config: TokenizerConfig =
+            toml::from_str("[stopwords]\nstrategy = \"skip_words\"\nwords = [\"です\", \"ます\"]")
+                .unwrap();
+        assert_eq!(
+            config.stopwords,
+            StopwordStrategy::SkipWords {
+                words: vec!["です".to_string(), "ます".to_string()]
+            }
+        );
+    }
+
+    #[test]
+    fn test_katakana_to_hiragana() {
+        assert_eq!(katakana_to_hiragana("ハシッタ"), "はしった");
+        assert_eq!(katakana_to_hiragana("ラーメン屋"), "らーめん屋");
+        assert_eq!(hiragana_to_katakana("まおうじょう"), "マオウジョウ");
+    }
+}
@@ -0,0 +1,77 @@
+use super::{normalize, StopwordStrategy, Token, Tokenizer};
+use jieba_rs::Jieba;
+
+// jieba tags left out of the index by default: punctuation and other non-words (x),
+// structural particles such as 的, 了 and 着 (u*), and sentence-final particles (y)
+const SKIPPED_POS: [&str; 8] = ["x", "uj", "ul", "uz", "ug", "uv", "ud", "y"];
+
+/// Chinese word segmenter backed by jieba and its built-in dictionary.
+///
+/// Words are indexed as written, tagged with jieba's part-of-speech tags
+/// (`n`, `v`, `uj`, ...), so `word:POS` queries use those tags.
+pub struct ChineseTokenizer {
+    inner: Jieba,
+    stopwords: StopwordStrategy,
+}
+
+impl Default for ChineseTokenizer {
+    fn default() -> Self {
+        ChineseTokenizer {
+            inner: Jieba::new(),
+            stopwords: StopwordStrategy::SkipPos {
+                pos: SKIPPED_POS.into_iter().map(String::from).collect(),
+            },
+        }
+    }
+}
+
+impl ChineseTokenizer {
+    pub fn with_stopwords(mut self, stopwords: StopwordStrategy) -> Self {
+        self.stopwords = stopwords;
+        self
+    }
+}
+
+impl Tokenizer for ChineseTokenizer {
+    fn name(&self) -> String {
+        "jieba".to_string()
+    }
+
+    fn tokenize(&self, text: &str) -> Vec<Token> {
+        let text = normalize(text);
+        self.inner
+            .tag(&text, true)
+            .into_iter()
+            // Spaces between words don't count towards positions
+            .filter(|tag| !tag.word.trim().is_empty())
+            .map(|tag| Token {
+                surface: tag.word.to_string(),
+                base_form: tag.word.to_string(),
+                reading: None,
+                pos: vec![tag.tag.to_string()],
+            })
+            .collect()
+    }
+
+    fn stopwords(&self) -> &StopwordStrategy {
+        &self.stopwords
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokenize_chinese() {
+        let tokenizer = ChineseTokenizer::default();
+        let surfaces: Vec<String> = tokenizer
+            .tokenize("我喜欢猫")
+            .into_iter()
+            .map(|token| token.surface)
+            .collect();
+        assert_eq!(surfaces, ["我", "喜欢", "猫"]);
+        // 的 and the full stop are skipped
+        assert_eq!(tokenizer.index_terms("我的猫。"), ["我", "猫"]);
+    }
+}
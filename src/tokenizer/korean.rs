@@ -0,0 +1,145 @@
+use super::{normalize, StopwordStrategy, Token, Tokenizer, TokenizerError};
+use lindera::core::mode::Mode;
+use lindera::dictionary::{load_dictionary_from_config, DictionaryConfig, DictionaryKind};
+
+// ko-dic tags left out of the index by default: particles (J*), verb endings (E*)
+// and punctuation and symbols (S* other than foreign words, hanja and numbers)
+const SKIPPED_POS: [&str; 22] = [
+    "JKS", "JKC", "JKG", "JKO", "JKB", "JKV", "JKQ", "JX", "JC", "EP", "EF", "EC", "ETN", "ETM",
+    "SF", "SE", "SSO", "SSC", "SC", "SY", "SP", "SW",
+];
+
+// Index of the part-of-speech tag, the entry type and the morpheme analysis in ko-dic's details
+const POS_INDEX: usize = 0;
+const TYPE_INDEX: usize = 4;
+const EXPRESSION_INDEX: usize = 7;
+
+/// Korean morphological analyzer backed by lindera and the bundled ko-dic dictionary.
+///
+/// Tokens are tagged with ko-dic's (Sejong) part-of-speech tags. Conjugated
+/// verbs and adjectives are indexed by their dictionary form, e.g. 먹었다 and
+/// 먹는 both as 먹다, and particles such as 가 and 를 are left out of the index
+/// by default.
+pub struct KoreanTokenizer {
+    inner: lindera::tokenizer::Tokenizer,
+    stopwords: StopwordStrategy,
+}
+
+impl KoreanTokenizer {
+    pub fn new() -> Result<Self, TokenizerError> {
+        let dictionary = load_dictionary_from_config(DictionaryConfig {
+            kind: Some(DictionaryKind::KoDic),
+            path: None,
+        })
+        .map_err(|e| TokenizerError::KoreanDictionaryError(e.to_string()))?;
+        Ok(KoreanTokenizer {
+            inner: lindera::tokenizer::Tokenizer::new(Mode::Normal, dictionary, None),
+            stopwords: StopwordStrategy::SkipPos {
+                pos: SKIPPED_POS.into_iter().map(String::from).collect(),
+            },
+        })
+    }
+
+    pub fn with_stopwords(mut self, stopwords: StopwordStrategy) -> Self {
+        self.stopwords = stopwords;
+        self
+    }
+}
+
+impl Tokenizer for KoreanTokenizer {
+    fn name(&self) -> String {
+        "lindera/ko-dic".to_string()
+    }
+
+    fn tokenize(&self, text: &str) -> Vec<Token> {
+        let text = normalize(text);
+        // Analysis only fails on dictionary corruption, which loading already checked
+        let Ok(mut tokens) = self.inner.tokenize(&text) else {
+            return Vec::new();
+        };
+        tokens
+            .iter_mut()
+            .map(|token| {
+                let surface = token.text.to_string();
+                to_token(surface, &token.details())
+            })
+            .collect()
+    }
+
+    fn stopwords(&self) -> &StopwordStrategy {
+        &self.stopwords
+    }
+}
+
+// Builds a token from ko-dic details, e.g. `VV+EP,*,T,먹었,Inflect,VV,EP,먹/VV/*+었/EP/*`
+fn to_token(surface: String, details: &[&str]) -> Token {
+    let field = |index: usize| {
+        details
+            .get(index)
+            .copied()
+            .filter(|value| !value.is_empty() && *value != "*")
+    };
+    // Compound tags like VV+EP are tagged by their first morpheme
+    let pos: Vec<String> = field(POS_INDEX)
+        .and_then(|tag| tag.split('+').next())
+        .map(|tag| vec![tag.to_string()])
+        .unwrap_or_default();
+    let base_form = match (field(TYPE_INDEX), field(EXPRESSION_INDEX)) {
+        (Some("Inflect"), Some(expression)) => dictionary_form(expression),
+        _ => None,
+    }
+    .unwrap_or_else(|| surface.clone());
+    Token {
+        surface,
+        base_form,
+        reading: None,
+        pos,
+    }
+}
+
+// The dictionary form of a conjugated verb or adjective from its morpheme analysis,
+// e.g. 먹다 for `먹/VV/*+었/EP/*`
+fn dictionary_form(expression: &str) -> Option<String> {
+    let mut first = expression.split('+').next()?.split('/');
+    let stem = first.next()?;
+    let tag = first.next()?;
+    if stem.is_empty() || !matches!(tag, "VV" | "VA" | "VX" | "VCP" | "VCN") {
+        return None;
+    }
+    Some(format!("{}다", stem))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dictionary_form() {
+        assert_eq!(dictionary_form("먹/VV/*+었/EP/*").as_deref(), Some("먹다"));
+        assert_eq!(dictionary_form("학교/NNG/*+에/JKB/*"), None);
+        let token = to_token(
+            "먹었".to_string(),
+            &[
+                "VV+EP",
+                "*",
+                "T",
+                "먹었",
+                "Inflect",
+                "VV",
+                "EP",
+                "먹/VV/*+었/EP/*",
+            ],
+        );
+        assert_eq!(token.base_form, "먹다");
+        assert_eq!(token.pos, ["VV"]);
+    }
+
+    #[test]
+    fn test_index_terms_skip_particles() {
+        let tokenizer = KoreanTokenizer::new().unwrap();
+        let terms = tokenizer.index_terms("고양이가 좋아요.");
+        assert!(terms.contains(&"고양이".to_string()));
+        assert!(!terms.contains(&"가".to_string()));
+        assert!(!terms.contains(&".".to_string()));
+    }
+}
@@ -0,0 +1,106 @@
+use std::ops::Range;
+use unicode_normalization::UnicodeNormalization;
+
+/// Normalizes text before it is tokenized, so differently encoded forms of the
+/// same text index and search identically.
+///
+/// NFKC maps full-width ASCII to half-width (２０１０年 → 2010年), half-width
+/// katakana to full-width (ｶﾀｶﾅ → カタカナ, ｶﾞ → ガ), and folds other
+/// compatibility characters such as ㍻ and circled numbers.
+pub fn normalize(text: &str) -> String {
+    text.nfkc().collect()
+}
+
+/// A run of text and the part of its normalization it became; see [`normalize_pieces`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Piece {
+    /// Byte range in the original text
+    pub original: Range<usize>,
+    /// Byte range in the normalized text
+    pub normalized: Range<usize>,
+}
+
+/// Normalizes `text` like [`normalize`], also splitting it into the smallest
+/// pieces that normalize on their own, so positions in the normalized text
+/// can be mapped back to the original. ｶﾞ is one piece, as it becomes ガ.
+pub(crate) fn normalize_pieces(text: &str) -> (String, Vec<Piece>) {
+    let mut normalized = String::new();
+    let mut pieces = Vec::new();
+    let mut push = |original: Range<usize>, normalized: &mut String| {
+        let piece = normalize(&text[original.clone()]);
+        let start = normalized.len();
+        normalized.push_str(&piece);
+        pieces.push(Piece {
+            original,
+            normalized: start..normalized.len(),
+        });
+    };
+    let mut start = 0;
+    for (i, c) in text.char_indices().skip(1) {
+        // A character starts a new piece unless it changes how the piece so
+        // far normalizes, like a voiced sound mark after its kana
+        let joined = normalize(&text[start..i + c.len_utf8()]);
+        if joined == normalize(&text[start..i]) + &normalize(&c.to_string()) {
+            push(start..i, &mut normalized);
+            start = i;
+        }
+    }
+    if !text.is_empty() {
+        push(start..text.len(), &mut normalized);
+    }
+    // A mark can compose with a character before the previous piece; keep such text whole
+    if normalized != normalize(text) {
+        let normalized = normalize(text);
+        let piece = Piece {
+            original: 0..text.len(),
+            normalized: 0..normalized.len(),
+        };
+        return (normalized, vec![piece]);
+    }
+    (normalized, pieces)
+}
+
+/// Lowercases the Latin (and other cased) letters of a normalized index term,
+/// so acronyms and names inside Japanese lines (LINE, Line, ｌｉｎｅ) index and
+/// search alike. Kana and kanji have no case and are left as they are.
+pub fn fold_case(term: &str) -> String {
+    term.to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_widths() {
+        assert_eq!(normalize("２０１０年"), "2010年");
+        assert_eq!(normalize("ＡＢＣ！"), "ABC!");
+        assert_eq!(normalize("ｶﾀｶﾅ ｶﾞｯｺｳ"), "カタカナ ガッコウ");
+        assert_eq!(normalize("猫が好き"), "猫が好き");
+    }
+
+    #[test]
+    fn test_normalize_pieces() {
+        let (normalized, pieces) = normalize_pieces("ｶﾞ２猫");
+        assert_eq!(normalized, "ガ2猫");
+        let spans: Vec<(&str, &str)> = pieces
+            .iter()
+            .map(|piece| {
+                (
+                    &"ｶﾞ２猫"[piece.original.clone()],
+                    &normalized[piece.normalized.clone()],
+                )
+            })
+            .collect();
+        assert_eq!(spans, [("ｶﾞ", "ガ"), ("２", "2"), ("猫", "猫")]);
+        assert_eq!(normalize_pieces(""), (String::new(), Vec::new()));
+    }
+
+    #[test]
+    fn test_fold_case_across_scripts() {
+        assert_eq!(fold_case(&normalize("ＬＩＮＥ既読")), "line既読");
+        assert_eq!(fold_case("DVDを見る"), "dvdを見る");
+        assert_eq!(fold_case("Ｔシャツ"), "ｔシャツ");
+        assert_eq!(fold_case(&normalize("Ｔシャツ")), "tシャツ");
+    }
+}
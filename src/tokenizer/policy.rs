@@ -0,0 +1,137 @@
+use super::{StopwordStrategy, Token};
+use crate::grammar::pos_matches;
+use serde::Deserialize;
+
+// Part-of-speech tags of numbers: IPADIC, UniDic, and the whitespace tokenizer
+const NUMBER_POS: [&str; 3] = ["名詞-数", "名詞-数詞", "number"];
+
+/// Which tokens the stopword strategy lets through are indexed, from `[tokenizer.index]`.
+///
+/// Like stopwords, the policy applies both when indexing lines and when
+/// tokenizing search queries. Changing it only affects lines indexed
+/// afterwards, so the policy an index was built with is recorded in the
+/// database (see [`IndexPolicy::fingerprint`]) and searches warn when it differs.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct IndexPolicy {
+    /// Words shorter than this many characters are skipped
+    pub min_length: usize,
+    /// Words longer than this many characters are skipped; no limit if unset
+    pub max_length: Option<usize>,
+    /// Only words whose part-of-speech starts with one of these tags are indexed,
+    /// e.g. `["名詞", "動詞", "形容詞"]`; every part-of-speech if empty
+    pub pos: Vec<String>,
+    /// Index numbers, written in digits or tagged as numerals
+    pub numbers: bool,
+}
+
+impl Default for IndexPolicy {
+    fn default() -> Self {
+        IndexPolicy::DEFAULT
+    }
+}
+
+impl IndexPolicy {
+    /// Index every word the stopwords let through, as before the policy existed.
+    pub const DEFAULT: IndexPolicy = IndexPolicy {
+        min_length: 1,
+        max_length: None,
+        pos: Vec::new(),
+        numbers: true,
+    };
+
+    /// Whether `token` belongs in the word index under this policy.
+    pub fn allows(&self, token: &Token) -> bool {
+        let length = token.base_form.chars().count();
+        if length < self.min_length || self.max_length.is_some_and(|max| length > max) {
+            return false;
+        }
+        if !self.numbers && is_number(token) {
+            return false;
+        }
+        self.pos.is_empty() || self.pos.iter().any(|tag| pos_matches(tag, &token.pos))
+    }
+
+    /// Identifies this policy together with the stopword strategy, so an index
+    /// built under one can be told apart from queries tokenized under another.
+    pub fn fingerprint(&self, stopwords: &StopwordStrategy) -> String {
+        let stopwords = match stopwords {
+            StopwordStrategy::IndexAll => "index_all".to_string(),
+            StopwordStrategy::SkipPos { pos } => format!("skip_pos={}", pos.join(",")),
+            StopwordStrategy::SkipWords { words } => format!("skip_words={}", words.join(",")),
+        };
+        let max_length = self
+            .max_length
+            .map_or_else(|| "none".to_string(), |max| max.to_string());
+        format!(
+            "stopwords:{}; min_length:{}; max_length:{}; pos:{}; numbers:{}",
+            stopwords,
+            self.min_length,
+            max_length,
+            self.pos.join(","),
+            self.numbers
+        )
+    }
+}
+
+fn is_number(token: &Token) -> bool {
+    (!token.surface.is_empty() && token.surface.chars().all(|c| c.is_ascii_digit()))
+        || NUMBER_POS.iter().any(|tag| pos_matches(tag, &token.pos))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token(base_form: &str, pos: &[&str]) -> Token {
+        Token {
+            surface: base_form.to_string(),
+            base_form: base_form.to_string(),
+            reading: None,
+            pos: pos.iter().map(|p| p.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_default_allows_everything() {
+        let policy = IndexPolicy::default();
+        assert!(policy.allows(&token("猫", &["名詞", "一般"])));
+        assert!(policy.allows(&token("2010", &["名詞", "数"])));
+    }
+
+    #[test]
+    fn test_lengths_pos_and_numbers() {
+        let policy = IndexPolicy {
+            min_length: 2,
+            max_length: Some(4),
+            pos: vec!["名詞".to_string(), "動詞-自立".to_string()],
+            numbers: false,
+        };
+        assert!(!policy.allows(&token("猫", &["名詞", "一般"])));
+        assert!(policy.allows(&token("走る", &["動詞", "自立"])));
+        assert!(!policy.allows(&token("見える", &["動詞", "非自立"])));
+        assert!(!policy.allows(&token("面白い", &["形容詞", "自立"])));
+        assert!(!policy.allows(&token("ジャガイモ畑", &["名詞", "一般"])));
+        assert!(!policy.allows(&token("2010", &["名詞", "数"])));
+        assert!(!policy.allows(&token("三十", &["名詞", "数"])));
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let stopwords = StopwordStrategy::default();
+        let default = IndexPolicy::default().fingerprint(&stopwords);
+        assert_eq!(
+            default,
+            "stopwords:skip_pos=助詞,助動詞,記号,補助記号; min_length:1; max_length:none; pos:; numbers:true"
+        );
+        let policy = IndexPolicy {
+            numbers: false,
+            ..IndexPolicy::default()
+        };
+        assert_ne!(policy.fingerprint(&stopwords), default);
+        assert_ne!(
+            IndexPolicy::default().fingerprint(&StopwordStrategy::IndexAll),
+            default
+        );
+    }
+}
@@ -0,0 +1,75 @@
+use super::Token;
+use crate::grammar::pos_matches;
+use serde::Deserialize;
+
+/// Which tokens are left out of the word index.
+///
+/// The same strategy is applied when indexing lines and when tokenizing
+/// search queries, so a skipped word can never cause a query to miss.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "strategy", rename_all = "snake_case", deny_unknown_fields)]
+pub enum StopwordStrategy {
+    /// Index every token
+    IndexAll,
+    /// Skip tokens whose part-of-speech starts with one of these tags.
+    /// Tags can name a sub-category with `-`, e.g. `名詞-数`.
+    SkipPos { pos: Vec<String> },
+    /// Skip tokens whose surface or dictionary form is in this list
+    SkipWords { words: Vec<String> },
+}
+
+impl Default for StopwordStrategy {
+    fn default() -> Self {
+        // Particles, auxiliary verbs, and punctuation in both IPADIC and UniDic
+        StopwordStrategy::SkipPos {
+            pos: ["助詞", "助動詞", "記号", "補助記号"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        }
+    }
+}
+
+impl StopwordStrategy {
+    pub fn is_stopword(&self, token: &Token) -> bool {
+        match self {
+            StopwordStrategy::IndexAll => false,
+            StopwordStrategy::SkipPos { pos } => pos.iter().any(|tag| pos_matches(tag, &token.pos)),
+            StopwordStrategy::SkipWords { words } => words
+                .iter()
+                .any(|word| *word == token.surface || *word == token.base_form),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token(surface: &str, pos: &[&str]) -> Token {
+        Token {
+            surface: surface.to_string(),
+            base_form: surface.to_string(),
+            reading: None,
+            pos: pos.iter().map(|p| p.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_skip_pos_matches_sub_categories() {
+        let strategy = StopwordStrategy::SkipPos {
+            pos: vec!["名詞-数".to_string()],
+        };
+        assert!(strategy.is_stopword(&token("二", &["名詞", "数"])));
+        assert!(!strategy.is_stopword(&token("猫", &["名詞", "一般"])));
+    }
+
+    #[test]
+    fn test_skip_words() {
+        let strategy = StopwordStrategy::SkipWords {
+            words: vec!["です".to_string()],
+        };
+        assert!(strategy.is_stopword(&token("です", &["助動詞"])));
+        assert!(!StopwordStrategy::IndexAll.is_stopword(&token("です", &["助動詞"])));
+    }
+}
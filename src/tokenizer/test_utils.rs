@@ -0,0 +1,72 @@
+//! A tiny IPADIC-format dictionary for tests, so they don't depend on a real
+//! dictionary being installed.
+
+use super::{DictionaryKind, JapaneseTokenizer};
+
+const LEXICON: &str = "\
+猫,0,0,100,名詞,一般,*,*,*,*,猫,ネコ,ネコ
+犬,0,0,100,名詞,一般,*,*,*,*,犬,イヌ,イヌ
+が,0,0,100,助詞,格助詞,一般,*,*,*,が,ガ,ガ
+は,0,0,100,助詞,係助詞,*,*,*,*,は,ハ,ワ
+好き,0,0,100,名詞,形容動詞語幹,*,*,*,*,好き,スキ,スキ
+です,0,0,100,助動詞,*,*,*,特殊・デス,基本形,です,デス,デス
+走る,0,0,100,動詞,自立,*,*,五段・ラ行,基本形,走る,ハシル,ハシル
+走っ,0,0,100,動詞,自立,*,*,五段・ラ行,連用タ接続,走る,ハシッ,ハシッ
+た,0,0,100,助動詞,*,*,*,特殊・タ,基本形,た,タ,タ
+を,0,0,100,助詞,格助詞,一般,*,*,*,を,ヲ,ヲ
+に,0,0,100,助詞,格助詞,一般,*,*,*,に,ニ,ニ
+ばかり,0,0,100,助詞,副助詞,*,*,*,*,ばかり,バカリ,バカリ
+見,0,0,100,動詞,自立,*,*,一段,連用形,見る,ミ,ミ
+見る,0,0,100,動詞,自立,*,*,一段,基本形,見る,ミル,ミル
+面白い,0,0,100,形容詞,自立,*,*,形容詞・アウオ段,基本形,面白い,オモシロイ,オモシロイ
+おもしろい,0,0,100,形容詞,自立,*,*,形容詞・アウオ段,基本形,おもしろい,オモシロイ,オモシロイ
+。,0,0,100,記号,句点,*,*,*,*,。,。,。
+";
+
+const MATRIX: &str = "1 1\n0 0 0\n";
+
+const CHAR_DEF: &str = "\
+DEFAULT 0 1 0
+SPACE 0 1 0
+KANJI 0 0 2
+SYMBOL 1 1 0
+NUMERIC 1 1 0
+ALPHA 1 1 0
+HIRAGANA 0 1 2
+KATAKANA 1 1 2
+
+0x0020 SPACE
+0x0030..0x0039 NUMERIC
+0x0041..0x005A ALPHA
+0x0061..0x007A ALPHA
+0x3000..0x303F SYMBOL
+0x3041..0x309F HIRAGANA
+0x30A1..0x30FF KATAKANA
+0x4E00..0x9FFF KANJI
+0xFF01..0xFF0F SYMBOL
+";
+
+const UNK_DEF: &str = "\
+DEFAULT,0,0,5000,記号,一般,*,*,*,*,*
+SPACE,0,0,5000,記号,空白,*,*,*,*,*
+KANJI,0,0,5000,名詞,一般,*,*,*,*,*
+SYMBOL,0,0,5000,記号,一般,*,*,*,*,*
+NUMERIC,0,0,5000,名詞,数,*,*,*,*,*
+ALPHA,0,0,5000,名詞,固有名詞,組織,*,*,*,*
+HIRAGANA,0,0,5000,名詞,一般,*,*,*,*,*
+KATAKANA,0,0,5000,名詞,一般,*,*,*,*,*
+";
+
+pub(crate) fn test_dictionary() -> vibrato::Dictionary {
+    vibrato::SystemDictionaryBuilder::from_readers(
+        LEXICON.as_bytes(),
+        MATRIX.as_bytes(),
+        CHAR_DEF.as_bytes(),
+        UNK_DEF.as_bytes(),
+    )
+    .unwrap()
+}
+
+pub(crate) fn test_tokenizer() -> JapaneseTokenizer {
+    JapaneseTokenizer::new(test_dictionary(), DictionaryKind::Ipadic)
+}
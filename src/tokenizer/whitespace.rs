@@ -0,0 +1,133 @@
+use super::{fold_case, normalize, StopwordStrategy, Token, Tokenizer};
+
+// Part-of-speech tags of the tokens this tokenizer produces
+const WORD: &str = "word";
+const NUMBER: &str = "number";
+const PUNCTUATION: &str = "punct";
+
+/// Splits text into words at whitespace and punctuation, for languages that
+/// separate words with spaces, such as English or French.
+///
+/// Words are indexed lowercased, and an apostrophe or hyphen between letters
+/// keeps a word together (`don't`, `well-known`). Every other character that
+/// isn't a letter or digit is a token of its own, tagged `punct`, and left out
+/// of the index by default.
+#[derive(Debug, Clone)]
+pub struct WhitespaceTokenizer {
+    stopwords: StopwordStrategy,
+}
+
+impl Default for WhitespaceTokenizer {
+    fn default() -> Self {
+        WhitespaceTokenizer {
+            stopwords: StopwordStrategy::SkipPos {
+                pos: vec![PUNCTUATION.to_string()],
+            },
+        }
+    }
+}
+
+impl WhitespaceTokenizer {
+    pub fn with_stopwords(mut self, stopwords: StopwordStrategy) -> Self {
+        self.stopwords = stopwords;
+        self
+    }
+}
+
+impl Tokenizer for WhitespaceTokenizer {
+    fn name(&self) -> String {
+        "whitespace".to_string()
+    }
+
+    fn tokenize(&self, text: &str) -> Vec<Token> {
+        let chars: Vec<char> = normalize(text).chars().collect();
+        let mut tokens = Vec::new();
+        let mut start = 0;
+        while start < chars.len() {
+            let c = chars[start];
+            if c.is_whitespace() {
+                start += 1;
+                continue;
+            }
+            if !c.is_alphanumeric() {
+                tokens.push(token(c.to_string(), PUNCTUATION));
+                start += 1;
+                continue;
+            }
+            let mut end = start + 1;
+            while end < chars.len() {
+                if chars[end].is_alphanumeric() {
+                    end += 1;
+                } else if is_joiner(chars[end])
+                    && chars.get(end + 1).is_some_and(|c| c.is_alphanumeric())
+                {
+                    end += 2;
+                } else {
+                    break;
+                }
+            }
+            let surface: String = chars[start..end].iter().collect();
+            let pos = if surface.chars().all(char::is_numeric) {
+                NUMBER
+            } else {
+                WORD
+            };
+            tokens.push(token(surface, pos));
+            start = end;
+        }
+        tokens
+    }
+
+    fn stopwords(&self) -> &StopwordStrategy {
+        &self.stopwords
+    }
+}
+
+fn token(surface: String, pos: &str) -> Token {
+    Token {
+        base_form: fold_case(&surface),
+        surface,
+        reading: None,
+        pos: vec![pos.to_string()],
+    }
+}
+
+// Characters that join the letters on either side into one word
+fn is_joiner(c: char) -> bool {
+    matches!(c, '\'' | '’' | '-')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokenize_words_and_punctuation() {
+        let tokens = WhitespaceTokenizer::default().tokenize("Don't stop, well-known cat! 42");
+        let surfaces: Vec<&str> = tokens.iter().map(|t| t.surface.as_str()).collect();
+        assert_eq!(
+            surfaces,
+            ["Don't", "stop", ",", "well-known", "cat", "!", "42"]
+        );
+        assert_eq!(tokens[0].base_form, "don't");
+        assert_eq!(tokens[2].pos, ["punct"]);
+        assert_eq!(tokens[6].pos, ["number"]);
+    }
+
+    #[test]
+    fn test_index_terms_skip_punctuation() {
+        let tokenizer = WhitespaceTokenizer::default();
+        assert_eq!(
+            tokenizer.index_terms("The CAT -- sat 'here'."),
+            ["the", "cat", "sat", "here"]
+        );
+        // Positions still count the punctuation
+        let positions: Vec<usize> = tokenizer
+            .index_tokens("Hi, you")
+            .into_iter()
+            .map(|(position, _)| position)
+            .collect();
+        assert_eq!(positions, [0, 2]);
+        assert_eq!(tokenizer.index_terms("Ｃａｆé  crème"), ["café", "crème"]);
+    }
+}
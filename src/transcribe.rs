@@ -0,0 +1,524 @@
+//! Transcribing video and audio files that have no subtitles with Whisper,
+//! so podcasts, YouTube videos and drama CDs can be in the corpus too.
+//!
+//! Each file's audio is extracted with ffmpeg and transcribed by whisper.cpp,
+//! run locally, or by an OpenAI-compatible transcription API (which needs the
+//! `transcribe` feature). The segments are written as an SRT file into the
+//! show's folder in the subtitle directory, and ingested from there like any
+//! other subtitle file.
+
+use crate::fetch::sanitize_file_name;
+use crate::media::find_media_files;
+use crate::srt_parser::{
+    is_subtitle_file, is_translation_file, LineEnding, Subtitle, Subtitles, Timestamp,
+};
+use serde::Deserialize;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// The `[transcribe]` config section.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TranscribeConfig {
+    /// The whisper.cpp executable
+    pub whisper_path: PathBuf,
+    /// The whisper.cpp model file, e.g. `models/ggml-large-v3.bin`
+    pub model: Option<PathBuf>,
+    /// An OpenAI-compatible API to transcribe with instead of whisper.cpp,
+    /// e.g. `https://api.openai.com/v1`
+    pub api_url: Option<String>,
+    /// The environment variable holding the API's key
+    pub api_key_env: String,
+    /// The model the API transcribes with
+    pub api_model: String,
+    /// The spoken language, as an ISO 639-1 code
+    pub language: String,
+    /// Extra command-line arguments for whisper.cpp
+    pub extra_args: Vec<String>,
+}
+
+impl Default for TranscribeConfig {
+    fn default() -> Self {
+        TranscribeConfig {
+            whisper_path: PathBuf::from("whisper-cli"),
+            model: None,
+            api_url: None,
+            api_key_env: "OPENAI_API_KEY".to_string(),
+            api_model: "whisper-1".to_string(),
+            language: "ja".to_string(),
+            extra_args: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum TranscribeError {
+    /// An API is configured, but the crate was built without the `transcribe` feature
+    NotCompiled,
+    /// whisper.cpp needs `model` set in the `[transcribe]` config section
+    NoModel,
+    /// ffmpeg couldn't extract the audio of a file
+    Ffmpeg {
+        path: PathBuf,
+        message: String,
+    },
+    /// whisper.cpp couldn't be run or failed
+    Whisper(String),
+    /// The request didn't reach the API, or its reply couldn't be read
+    #[cfg(feature = "transcribe")]
+    Http(Box<ureq::Error>),
+    /// The API answered with an error status
+    Api {
+        status: u16,
+        message: String,
+    },
+    /// whisper.cpp's or the API's output didn't have the expected shape
+    InvalidOutput(String),
+    /// None of the files were video or audio files
+    NoFiles,
+    IoError(std::io::Error),
+}
+
+#[cfg(feature = "transcribe")]
+impl From<ureq::Error> for TranscribeError {
+    fn from(error: ureq::Error) -> Self {
+        match error {
+            ureq::Error::Status(status, response) => TranscribeError::Api {
+                status,
+                message: response.into_string().unwrap_or_default(),
+            },
+            error => TranscribeError::Http(Box::new(error)),
+        }
+    }
+}
+
+impl From<std::io::Error> for TranscribeError {
+    fn from(error: std::io::Error) -> Self {
+        TranscribeError::IoError(error)
+    }
+}
+
+impl fmt::Display for TranscribeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TranscribeError::NotCompiled => {
+                write!(f, "Transcribing with an API isn't available in this build")
+            }
+            TranscribeError::NoModel => write!(
+                f,
+                "Set the whisper.cpp model file in the [transcribe] config section"
+            ),
+            TranscribeError::Ffmpeg { path, message } => {
+                write!(f, "ffmpeg failed on {}: {}", path.display(), message)
+            }
+            TranscribeError::Whisper(message) => write!(f, "whisper.cpp failed: {}", message),
+            #[cfg(feature = "transcribe")]
+            TranscribeError::Http(e) => write!(f, "HTTP error: {}", e),
+            TranscribeError::Api { status, message } => {
+                write!(f, "The API answered {}: {}", status, message)
+            }
+            TranscribeError::InvalidOutput(message) => {
+                write!(f, "Unexpected transcription output: {}", message)
+            }
+            TranscribeError::NoFiles => write!(f, "No video or audio files to transcribe"),
+            TranscribeError::IoError(e) => write!(f, "I/O error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for TranscribeError {}
+
+pub type Result<T> = std::result::Result<T, TranscribeError>;
+
+/// A stretch of speech Whisper transcribed, with its times in milliseconds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    pub start: u64,
+    pub end: u64,
+    pub text: String,
+}
+
+/// What [`transcribe_show`] transcribed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TranscribedShow {
+    /// The show's folder in the subtitle directory
+    pub dir: PathBuf,
+    /// The SRT files written, in that folder
+    pub files: Vec<PathBuf>,
+    /// The video and audio files left out for having subtitles already
+    pub skipped: Vec<PathBuf>,
+}
+
+/// Transcribes the video and audio files in `paths` (files, or directories
+/// searched for them) into SRT files named after them, in a folder named after
+/// `show` under `root_dir`.
+///
+/// Files with a Japanese subtitle file next to them, or transcribed before,
+/// are skipped unless `force` is set, so an interrupted run can be resumed.
+pub fn transcribe_show(
+    config: &TranscribeConfig,
+    show: &str,
+    paths: &[PathBuf],
+    root_dir: &Path,
+    force: bool,
+) -> Result<TranscribedShow> {
+    let mut media = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            let mut found = find_media_files(path);
+            found.sort();
+            media.extend(found);
+        } else {
+            media.push(path.clone());
+        }
+    }
+    if media.is_empty() {
+        return Err(TranscribeError::NoFiles);
+    }
+
+    let dir = root_dir.join(sanitize_file_name(show));
+    fs::create_dir_all(&dir)?;
+    let mut files = Vec::new();
+    let mut skipped = Vec::new();
+    for path in media {
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let output = dir.join(format!("{}.srt", sanitize_file_name(&stem)));
+        if !force && (output.exists() || has_subtitles(&path)) {
+            skipped.push(path);
+            continue;
+        }
+        println!("Transcribing {}...", path.display());
+        let subtitles = transcribe_file(config, &path)?;
+        fs::write(&output, subtitles.to_srt(LineEnding::Lf))?;
+        files.push(output);
+    }
+    Ok(TranscribedShow {
+        dir,
+        files,
+        skipped,
+    })
+}
+
+/// Transcribes one video or audio file, with the API if one is configured and
+/// whisper.cpp otherwise. Needs ffmpeg on the PATH.
+pub fn transcribe_file(config: &TranscribeConfig, media: &Path) -> Result<Subtitles> {
+    let segments = match &config.api_url {
+        Some(url) => transcribe_with_api(config, url, media)?,
+        None => transcribe_with_whisper_cpp(config, media)?,
+    };
+    Ok(segments_to_subtitles(segments))
+}
+
+/// Whether a Japanese subtitle file named after `media` sits next to it, e.g.
+/// `Episode 01.srt` or `Episode 01.ja.ass` for `Episode 01.mkv`.
+pub fn has_subtitles(media: &Path) -> bool {
+    let (Some(dir), Some(stem)) = (media.parent(), media.file_stem()) else {
+        return false;
+    };
+    let stem = stem.to_string_lossy();
+    let Ok(siblings) = fs::read_dir(dir) else {
+        return false;
+    };
+    siblings.filter_map(|entry| entry.ok()).any(|entry| {
+        let path = entry.path();
+        let name_stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let matches = name_stem
+            .strip_prefix(stem.as_ref())
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('.'));
+        matches && is_subtitle_file(&path) && !is_translation_file(&path)
+    })
+}
+
+/// The segments as subtitle cues, numbered from 1, leaving out those without text.
+pub fn segments_to_subtitles(segments: Vec<Segment>) -> Subtitles {
+    let mut subtitles = Subtitles::new();
+    for segment in segments {
+        let text = segment.text.trim();
+        if text.is_empty() {
+            continue;
+        }
+        subtitles.push(Subtitle::new(
+            subtitles.len() + 1,
+            Timestamp::from_millis(segment.start),
+            Timestamp::from_millis(segment.end.max(segment.start)),
+            text.to_string(),
+        ));
+    }
+    subtitles
+}
+
+// whisper.cpp's JSON output (`-oj`), of which only the segments' offsets and text are read
+#[derive(Debug, Deserialize)]
+struct WhisperCppOutput {
+    transcription: Vec<WhisperCppSegment>,
+}
+
+#[derive(Debug, Deserialize)]
+struct WhisperCppSegment {
+    offsets: WhisperCppOffsets,
+    text: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct WhisperCppOffsets {
+    from: u64,
+    to: u64,
+}
+
+/// The segments of whisper.cpp's JSON output.
+pub fn parse_whisper_cpp_json(json: &str) -> Result<Vec<Segment>> {
+    let output: WhisperCppOutput =
+        serde_json::from_str(json).map_err(|e| TranscribeError::InvalidOutput(e.to_string()))?;
+    Ok(output
+        .transcription
+        .into_iter()
+        .map(|segment| Segment {
+            start: segment.offsets.from,
+            end: segment.offsets.to,
+            text: segment.text,
+        })
+        .collect())
+}
+
+// The `verbose_json` reply of an OpenAI-compatible transcription API, with times in seconds
+#[derive(Debug, Deserialize)]
+struct ApiOutput {
+    segments: Vec<ApiSegment>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiSegment {
+    start: f64,
+    end: f64,
+    text: String,
+}
+
+/// The segments of a transcription API's `verbose_json` reply.
+pub fn parse_api_json(json: &str) -> Result<Vec<Segment>> {
+    let output: ApiOutput =
+        serde_json::from_str(json).map_err(|e| TranscribeError::InvalidOutput(e.to_string()))?;
+    let millis = |seconds: f64| (seconds.max(0.0) * 1000.0).round() as u64;
+    Ok(output
+        .segments
+        .into_iter()
+        .map(|segment| Segment {
+            start: millis(segment.start),
+            end: millis(segment.end),
+            text: segment.text,
+        })
+        .collect())
+}
+
+// A file in the temporary directory, removed when dropped
+struct TempFile(PathBuf);
+
+impl TempFile {
+    fn new(extension: &str) -> Self {
+        TempFile(std::env::temp_dir().join(format!(
+            "anime_search_transcribe_{}.{}",
+            std::process::id(),
+            extension
+        )))
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+// Writes the audio of `media` to `output` as mono, in the format `codec_args` pick
+fn extract_audio(media: &Path, output: &Path, codec_args: &[&str]) -> Result<()> {
+    let result = Command::new("ffmpeg")
+        .args(["-v", "error", "-y", "-i"])
+        .arg(media)
+        .args(["-vn", "-ac", "1"])
+        .args(codec_args)
+        .arg(output)
+        .output()?;
+    if !result.status.success() {
+        return Err(TranscribeError::Ffmpeg {
+            path: media.to_path_buf(),
+            message: String::from_utf8_lossy(&result.stderr).trim().to_string(),
+        });
+    }
+    Ok(())
+}
+
+// whisper.cpp reads 16 kHz WAV files, and writes its JSON next to the prefix given with `-of`
+fn transcribe_with_whisper_cpp(config: &TranscribeConfig, media: &Path) -> Result<Vec<Segment>> {
+    let model = config.model.as_ref().ok_or(TranscribeError::NoModel)?;
+    let audio = TempFile::new("wav");
+    extract_audio(media, &audio.0, &["-ar", "16000", "-c:a", "pcm_s16le"])?;
+    let output = TempFile::new("json");
+    let prefix = output.0.with_extension("");
+
+    let result = Command::new(&config.whisper_path)
+        .arg("-m")
+        .arg(model)
+        .args(["-l", config.language.as_str(), "-oj", "-np", "-of"])
+        .arg(&prefix)
+        .args(&config.extra_args)
+        .arg("-f")
+        .arg(&audio.0)
+        .output()
+        .map_err(|e| TranscribeError::Whisper(e.to_string()))?;
+    if !result.status.success() {
+        let message = String::from_utf8_lossy(&result.stderr).trim().to_string();
+        return Err(TranscribeError::Whisper(message));
+    }
+    parse_whisper_cpp_json(&fs::read_to_string(&output.0)?)
+}
+
+// Uploads the audio as a small MP3, since transcription APIs limit the size of uploads
+#[cfg(feature = "transcribe")]
+fn transcribe_with_api(config: &TranscribeConfig, url: &str, media: &Path) -> Result<Vec<Segment>> {
+    let audio = TempFile::new("mp3");
+    extract_audio(media, &audio.0, &["-c:a", "libmp3lame", "-b:a", "32k"])?;
+    let boundary = format!("anime-search-{}", std::process::id());
+    let body = multipart_body(
+        &boundary,
+        &[
+            ("model", config.api_model.as_str()),
+            ("language", config.language.as_str()),
+            ("response_format", "verbose_json"),
+        ],
+        &fs::read(&audio.0)?,
+    );
+
+    let mut request = ureq::post(&format!(
+        "{}/audio/transcriptions",
+        url.trim_end_matches('/')
+    ))
+    .set(
+        "Content-Type",
+        &format!("multipart/form-data; boundary={}", boundary),
+    );
+    // Local OpenAI-compatible servers often don't need a key
+    if let Some(key) = std::env::var(&config.api_key_env)
+        .ok()
+        .filter(|key| !key.is_empty())
+    {
+        request = request.set("Authorization", &format!("Bearer {}", key));
+    }
+    let reply = request.send_bytes(&body)?.into_string()?;
+    parse_api_json(&reply)
+}
+
+#[cfg(not(feature = "transcribe"))]
+fn transcribe_with_api(
+    _config: &TranscribeConfig,
+    _url: &str,
+    _media: &Path,
+) -> Result<Vec<Segment>> {
+    Err(TranscribeError::NotCompiled)
+}
+
+// A multipart/form-data body with text fields and the audio as `file`
+#[cfg(feature = "transcribe")]
+fn multipart_body(boundary: &str, fields: &[(&str, &str)], audio: &[u8]) -> Vec<u8> {
+    let mut body = Vec::new();
+    for (name, value) in fields {
+        body.extend_from_slice(
+            format!(
+                "--{}\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n{}\r\n",
+                boundary, name, value
+            )
+            .as_bytes(),
+        );
+    }
+    body.extend_from_slice(
+        format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"audio.mp3\"\r\n\
+             Content-Type: audio/mpeg\r\n\r\n",
+            boundary
+        )
+        .as_bytes(),
+    );
+    body.extend_from_slice(audio);
+    body.extend_from_slice(format!("\r\n--{}--\r\n", boundary).as_bytes());
+    body
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_outputs() {
+        let whisper_cpp = r#"{
+            "result": {"language": "ja"},
+            "transcription": [
+                {"timestamps": {"from": "00:00:00,000", "to": "00:00:02,500"},
+                 "offsets": {"from": 0, "to": 2500}, "text": " おはようございます"},
+                {"timestamps": {"from": "00:00:02,500", "to": "00:00:03,000"},
+                 "offsets": {"from": 2500, "to": 3000}, "text": " "}
+            ]
+        }"#;
+        let segments = parse_whisper_cpp_json(whisper_cpp).unwrap();
+        assert_eq!(
+            segments[0],
+            Segment {
+                start: 0,
+                end: 2500,
+                text: " おはようございます".to_string(),
+            }
+        );
+
+        let api = r#"{"text": "...", "segments": [
+            {"id": 0, "start": 1.25, "end": 3.0, "text": "今日はいい天気ですね"}
+        ]}"#;
+        let segments = parse_api_json(api).unwrap();
+        assert_eq!((segments[0].start, segments[0].end), (1250, 3000));
+        assert!(parse_api_json("{}").is_err());
+    }
+
+    #[test]
+    fn test_segments_to_subtitles() {
+        let segments = vec![
+            Segment {
+                start: 0,
+                end: 2500,
+                text: " おはようございます".to_string(),
+            },
+            Segment {
+                start: 2500,
+                end: 3000,
+                text: " ".to_string(),
+            },
+            Segment {
+                start: 3000,
+                end: 2900,
+                text: "またね".to_string(),
+            },
+        ];
+        let subtitles = segments_to_subtitles(segments);
+        assert_eq!(subtitles.len(), 2);
+        assert_eq!(subtitles.0[0].text, "おはようございます");
+        assert_eq!(subtitles.0[1].number, 2);
+        assert_eq!(subtitles.0[1].end_time, Timestamp::from_millis(3000));
+    }
+
+    #[test]
+    fn test_has_subtitles() {
+        let dir = std::env::temp_dir().join(format!("transcribe-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for name in [
+            "01.mkv",
+            "01.ja.srt",
+            "02.mkv",
+            "02.en.srt",
+            "03.mp3",
+            "03 extra.srt",
+        ] {
+            fs::write(dir.join(name), "").unwrap();
+        }
+        assert!(has_subtitles(&dir.join("01.mkv")));
+        assert!(!has_subtitles(&dir.join("02.mkv")));
+        assert!(!has_subtitles(&dir.join("03.mp3")));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,303 @@
+//! Machine translation of subtitle lines, for hits (and their context) that
+//! came without an English subtitle.
+//!
+//! Lines are translated by a [`Translator`]: either the model set in `[llm]`,
+//! or DeepL's API (behind the `llm` feature, which brings the HTTP client).
+//! Translations are cached in the database per line and translator, so
+//! repeating a query doesn't pay for the same lines again.
+
+use crate::db::{DbHandler, TranscriptId};
+use crate::llm::{open_cached_provider, Completion, LlmConfig, LlmError, LlmProvider, Result};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+
+// Lines sent per request, to keep prompts and replies a manageable size
+const BATCH_SIZE: usize = 40;
+
+const TRANSLATION_SYSTEM: &str =
+    "You translate Japanese anime subtitles into natural English, keeping their tone.";
+
+/// Which [`Translator`] the `[translation]` section configures.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TranslatorKind {
+    /// The model set in `[llm]`
+    #[default]
+    Llm,
+    /// DeepL's translation API
+    DeepL,
+}
+
+/// The `[translation]` config section.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TranslationConfig {
+    pub provider: TranslatorKind,
+    /// The environment variable holding the DeepL API key
+    pub deepl_api_key_env: String,
+    /// DeepL's translate endpoint; Pro accounts use https://api.deepl.com/v2/translate
+    pub deepl_url: String,
+    /// DeepL's code for the language to translate into
+    pub target_lang: String,
+}
+
+impl Default for TranslationConfig {
+    fn default() -> Self {
+        TranslationConfig {
+            provider: TranslatorKind::Llm,
+            deepl_api_key_env: "DEEPL_API_KEY".to_string(),
+            deepl_url: "https://api-free.deepl.com/v2/translate".to_string(),
+            target_lang: "EN-US".to_string(),
+        }
+    }
+}
+
+/// Translates Japanese lines into English.
+pub trait Translator {
+    /// Identifies the translator, and the model if it has one; cached
+    /// translations are kept per name.
+    fn name(&self) -> String;
+
+    /// One translation per line, in the same order.
+    fn translate(&self, lines: &[String]) -> Result<Vec<String>>;
+}
+
+/// Opens the translator selected by `config`, using `llm` for the LLM one,
+/// with its replies cached in the database at `db_path`.
+pub fn open_translator(
+    config: &TranslationConfig,
+    llm: &LlmConfig,
+    db_path: &Path,
+) -> Result<Box<dyn Translator>> {
+    match config.provider {
+        TranslatorKind::Llm => Ok(Box::new(LlmTranslator::new(open_cached_provider(
+            llm, db_path,
+        )?))),
+        #[cfg(feature = "llm")]
+        TranslatorKind::DeepL => Ok(Box::new(DeepLTranslator::new(config)?)),
+        #[cfg(not(feature = "llm"))]
+        TranslatorKind::DeepL => Err(LlmError::NotCompiled),
+    }
+}
+
+/// Translates by asking an [`LlmProvider`], a batch of numbered lines per prompt.
+pub struct LlmTranslator<P> {
+    provider: P,
+}
+
+impl<P: LlmProvider> LlmTranslator<P> {
+    pub fn new(provider: P) -> Self {
+        LlmTranslator { provider }
+    }
+}
+
+impl<P: LlmProvider> Translator for LlmTranslator<P> {
+    fn name(&self) -> String {
+        self.provider.name()
+    }
+
+    fn translate(&self, lines: &[String]) -> Result<Vec<String>> {
+        let mut translations = Vec::new();
+        for batch in lines.chunks(BATCH_SIZE) {
+            let numbered: Vec<String> = batch
+                .iter()
+                .enumerate()
+                .map(|(i, line)| format!("{}. {}", i + 1, line.replace('\n', " ")))
+                .collect();
+            let prompt = format!(
+                "Translate each numbered subtitle line into English. The lines may come \
+                 from different scenes and shows, so translate each on its own. Reply with \
+                 one line per subtitle, in the same numbered format, and nothing else.\n\n{}",
+                numbered.join("\n")
+            );
+            let reply = self.provider.complete(&Completion {
+                system: Some(TRANSLATION_SYSTEM.to_string()),
+                prompt,
+            })?;
+            translations.extend(numbered_lines(&reply, batch.len())?);
+        }
+        Ok(translations)
+    }
+}
+
+// The `count` lines of a reply numbered 1. to count., in order
+fn numbered_lines(reply: &str, count: usize) -> Result<Vec<String>> {
+    let mut lines = vec![None; count];
+    for line in reply.lines() {
+        let line = line.trim();
+        let digits = line.len() - line.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        let Ok(number) = line[..digits].parse::<usize>() else {
+            continue;
+        };
+        let rest = line[digits..].trim_start_matches(['.', ')', ':']).trim();
+        if let Some(slot) = number.checked_sub(1).and_then(|i| lines.get_mut(i)) {
+            slot.get_or_insert_with(|| rest.to_string());
+        }
+    }
+    lines
+        .into_iter()
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| {
+            LlmError::InvalidResponse(format!("expected {} numbered lines in {:?}", count, reply))
+        })
+}
+
+/// Translates with DeepL's API.
+#[cfg(feature = "llm")]
+pub struct DeepLTranslator {
+    url: String,
+    api_key: String,
+    target_lang: String,
+}
+
+#[cfg(feature = "llm")]
+impl DeepLTranslator {
+    pub fn new(config: &TranslationConfig) -> Result<Self> {
+        let api_key = std::env::var(&config.deepl_api_key_env)
+            .ok()
+            .filter(|key| !key.is_empty())
+            .ok_or_else(|| LlmError::MissingApiKey(config.deepl_api_key_env.clone()))?;
+        Ok(DeepLTranslator {
+            url: config.deepl_url.clone(),
+            api_key,
+            target_lang: config.target_lang.clone(),
+        })
+    }
+
+    fn request_body(&self, lines: &[String]) -> serde_json::Value {
+        serde_json::json!({
+            "text": lines,
+            "source_lang": "JA",
+            "target_lang": self.target_lang,
+        })
+    }
+}
+
+#[cfg(feature = "llm")]
+fn parse_deepl_reply(reply: &serde_json::Value, count: usize) -> Result<Vec<String>> {
+    let translations: Option<Vec<String>> = reply["translations"].as_array().map(|items| {
+        items
+            .iter()
+            .filter_map(|item| item["text"].as_str().map(str::to_string))
+            .collect()
+    });
+    translations
+        .filter(|translations| translations.len() == count)
+        .ok_or_else(|| LlmError::InvalidResponse(reply.to_string()))
+}
+
+#[cfg(feature = "llm")]
+impl Translator for DeepLTranslator {
+    fn name(&self) -> String {
+        format!("deepl:{}", self.target_lang.to_lowercase())
+    }
+
+    fn translate(&self, lines: &[String]) -> Result<Vec<String>> {
+        let mut translations = Vec::new();
+        for batch in lines.chunks(BATCH_SIZE) {
+            let reply: serde_json::Value = ureq::post(&self.url)
+                .set("Authorization", &format!("DeepL-Auth-Key {}", self.api_key))
+                .send_json(self.request_body(batch))?
+                .into_json()
+                .map_err(|e| LlmError::InvalidResponse(e.to_string()))?;
+            translations.extend(parse_deepl_reply(&reply, batch.len())?);
+        }
+        Ok(translations)
+    }
+}
+
+/// Machine translations of the lines `ids`, from the cache where possible;
+/// the rest are translated together, in corpus order, and cached. They are
+/// usually search hits, so not consecutive lines of one episode.
+pub fn translate_lines(
+    db: &mut DbHandler,
+    translator: &dyn Translator,
+    ids: &[TranscriptId],
+) -> Result<HashMap<TranscriptId, String>> {
+    let name = translator.name();
+    let mut translations = db.machine_translations(&name, ids)?;
+    let mut missing: Vec<TranscriptId> = ids
+        .iter()
+        .filter(|id| !translations.contains_key(id))
+        .copied()
+        .collect();
+    missing.sort();
+    missing.dedup();
+    if missing.is_empty() {
+        return Ok(translations);
+    }
+    let lines = db.find_hits_by_ids(&missing)?;
+    let texts: Vec<String> = lines.iter().map(|line| line.text.clone()).collect();
+    let translated: Vec<(TranscriptId, String)> = lines
+        .iter()
+        .map(|line| line.transcript_id)
+        .zip(translator.translate(&texts)?)
+        .collect();
+    db.insert_machine_translations(&name, &translated)?;
+    translations.extend(translated);
+    Ok(translations)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_utils::test_db_with_lines;
+    use crate::llm::test_utils::FakeProvider;
+
+    #[test]
+    fn test_translations_are_cached() {
+        let (mut db, ids) = test_db_with_lines(&["猫", "犬", "走った"]);
+        let provider = FakeProvider::new("2. Dog\n1) The cat\nDone!");
+        let translator = LlmTranslator::new(&provider);
+
+        let translations = translate_lines(&mut db, &translator, &[ids[1], ids[0]]).unwrap();
+        assert_eq!(translations[&ids[0]], "The cat");
+        assert_eq!(translations[&ids[1]], "Dog");
+        assert!(provider.prompts.borrow()[0].contains("1. 猫\n2. 犬"));
+
+        // Only the new line is sent
+        let provider = FakeProvider::new("1. It ran");
+        let translator = LlmTranslator::new(&provider);
+        let translations = translate_lines(&mut db, &translator, &ids).unwrap();
+        assert_eq!(translations.len(), 3);
+        assert_eq!(translations[&ids[2]], "It ran");
+        let prompt = provider.prompts.borrow()[0].clone();
+        assert!(prompt.contains("1. 走った") && !prompt.contains("猫"));
+
+        translate_lines(&mut db, &translator, &ids).unwrap();
+        assert_eq!(provider.prompts.borrow().len(), 1);
+    }
+
+    #[test]
+    fn test_numbered_lines() {
+        assert_eq!(
+            numbered_lines("Sure:\n1. Hi. 2. no\n2: Bye\n1. Again", 2).unwrap(),
+            ["Hi. 2. no", "Bye"]
+        );
+        assert!(matches!(
+            numbered_lines("1. Hi", 2),
+            Err(LlmError::InvalidResponse(_))
+        ));
+    }
+
+    #[cfg(feature = "llm")]
+    #[test]
+    fn test_deepl_request_and_reply() {
+        let translator = DeepLTranslator {
+            url: TranslationConfig::default().deepl_url,
+            api_key: "key".to_string(),
+            target_lang: "EN-US".to_string(),
+        };
+        let body = translator.request_body(&["猫".to_string()]);
+        assert_eq!(body["text"][0], "猫");
+        assert_eq!(body["source_lang"], "JA");
+        assert_eq!(translator.name(), "deepl:en-us");
+
+        let reply = serde_json::json!({
+            "translations": [{ "detected_source_language": "JA", "text": "Cat" }]
+        });
+        assert_eq!(parse_deepl_reply(&reply, 1).unwrap(), ["Cat"]);
+        assert!(parse_deepl_reply(&reply, 2).is_err());
+    }
+}
@@ -0,0 +1,81 @@
+//! Watching the subtitle directory for new and changed files.
+
+use crate::srt_parser::is_subtitle_file;
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use std::collections::BTreeSet;
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::Duration;
+
+/// Watches `root` recursively and calls `on_change` with the subtitle files
+/// (`.srt`, `.ass`, `.lrc`, `.ttml`, `.json3`, ...) that were created or modified.
+///
+/// Events are batched until no new ones arrive for `quiet_period`, so a file
+/// that is still being written (or a whole season being copied in) is handled
+/// once. Runs until `on_change` returns [`ControlFlow::Break`].
+pub fn watch_srt_files<F>(
+    root: &Path,
+    quiet_period: Duration,
+    mut on_change: F,
+) -> notify::Result<()>
+where
+    F: FnMut(Vec<PathBuf>) -> ControlFlow<()>,
+{
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender)?;
+    watcher.watch(root, RecursiveMode::Recursive)?;
+
+    // Blocks for the first event of a batch, then drains until things go quiet
+    while let Ok(event) = receiver.recv() {
+        let mut changed = BTreeSet::new();
+        changed.extend(changed_srt_files(event?));
+        loop {
+            match receiver.recv_timeout(quiet_period) {
+                Ok(event) => changed.extend(changed_srt_files(event?)),
+                Err(mpsc::RecvTimeoutError::Timeout) => break,
+                Err(mpsc::RecvTimeoutError::Disconnected) => return Ok(()),
+            }
+        }
+        // Files can be deleted again before the batch is handled
+        let changed: Vec<PathBuf> = changed.into_iter().filter(|path| path.is_file()).collect();
+        if !changed.is_empty() && on_change(changed).is_break() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+// The subtitle files an event created or changed; renames count for their new name
+fn changed_srt_files(event: Event) -> Vec<PathBuf> {
+    match event.kind {
+        EventKind::Create(_) | EventKind::Modify(_) => event
+            .paths
+            .into_iter()
+            .filter(|path| is_subtitle_file(path))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use notify::event::{CreateKind, ModifyKind, RemoveKind};
+
+    #[test]
+    fn test_changed_srt_files() {
+        let event = Event::new(EventKind::Create(CreateKind::File))
+            .add_path(PathBuf::from("show/ep1.srt"))
+            .add_path(PathBuf::from("show/cover.jpg"));
+        assert_eq!(changed_srt_files(event), [PathBuf::from("show/ep1.srt")]);
+
+        let event =
+            Event::new(EventKind::Modify(ModifyKind::Any)).add_path(PathBuf::from("show/ep2.srt"));
+        assert_eq!(changed_srt_files(event), [PathBuf::from("show/ep2.srt")]);
+
+        let event =
+            Event::new(EventKind::Remove(RemoveKind::File)).add_path(PathBuf::from("show/ep1.srt"));
+        assert!(changed_srt_files(event).is_empty());
+    }
+}